@@ -0,0 +1,149 @@
+#![doc(html_root_url = "https://docs.rs/couchbase-flags/1.0.0-alpha.5")]
+#![no_std]
+
+//! Encoding and decoding of the flags and datatype Couchbase stores with every document.
+//!
+//! All Couchbase SDKs agree on the "common flags" to describe the format of a document, so a
+//! document written by one SDK can be read by another. This crate has no dependencies and does
+//! no IO, so tools which only look at documents (i.e. consumers of a DCP or Kafka stream) can
+//! interpret them without pulling in the whole SDK.
+//!
+//! ```
+//! use couchbase_flags::{decode_common_flags, encode_common_flags, Compression, DataFormat};
+//!
+//! let flags = encode_common_flags(DataFormat::Json, Compression::None);
+//! assert_eq!(flags, 0x0200_0000);
+//! assert_eq!(decode_common_flags(flags), (DataFormat::Json, Compression::None));
+//! ```
+
+/// The bits of the flags which are used by the common flags.
+pub const COMMON_FLAGS_MASK: u32 = 0xFF00_0000;
+
+const FORMAT_MASK: u32 = 0x0F00_0000;
+const COMPRESSION_MASK: u32 = 0xE000_0000;
+
+/// The common flags marking a document as JSON.
+pub const JSON_COMMON_FLAGS: u32 = 0x02 << 24;
+
+/// The common flags marking a document as raw binary.
+pub const BINARY_COMMON_FLAGS: u32 = 0x03 << 24;
+
+/// The common flags marking a document as an UTF-8 string.
+pub const STRING_COMMON_FLAGS: u32 = 0x04 << 24;
+
+/// The format of a document as described by its common flags.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DataFormat {
+    /// Private to the SDK which wrote the document.
+    Private,
+    /// A JSON document.
+    Json,
+    /// Raw binary content.
+    Binary,
+    /// An UTF-8 string.
+    String,
+    /// The flags do not carry a known format, i.e. because they were written without common
+    /// flags.
+    Unknown,
+}
+
+/// The compression of a document as described by its common flags.
+///
+/// No compression has been specified yet, the field is reserved for future use.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compression {
+    /// The content is not compressed.
+    None,
+    /// The flags do not carry a known compression.
+    Unknown,
+}
+
+/// Encodes the format and compression into common flags.
+///
+/// `DataFormat::Unknown` and `Compression::Unknown` can not be encoded and leave the flags
+/// empty.
+pub fn encode_common_flags(format: DataFormat, compression: Compression) -> u32 {
+    let format = match format {
+        DataFormat::Private => 0x01 << 24,
+        DataFormat::Json => JSON_COMMON_FLAGS,
+        DataFormat::Binary => BINARY_COMMON_FLAGS,
+        DataFormat::String => STRING_COMMON_FLAGS,
+        DataFormat::Unknown => return 0,
+    };
+    match compression {
+        Compression::None => format,
+        Compression::Unknown => 0,
+    }
+}
+
+/// Decodes the format and compression from the common flags.
+///
+/// Only the upper byte of the flags is looked at, the rest is free for the application.
+pub fn decode_common_flags(flags: u32) -> (DataFormat, Compression) {
+    if flags & COMMON_FLAGS_MASK == 0 {
+        return (DataFormat::Unknown, Compression::Unknown);
+    }
+    let format = match (flags & FORMAT_MASK) >> 24 {
+        0x01 => DataFormat::Private,
+        0x02 => DataFormat::Json,
+        0x03 => DataFormat::Binary,
+        0x04 => DataFormat::String,
+        _ => DataFormat::Unknown,
+    };
+    let compression = match flags & COMPRESSION_MASK {
+        0 => Compression::None,
+        _ => Compression::Unknown,
+    };
+    (format, compression)
+}
+
+/// The datatype the server keeps for every document, next to the flags.
+///
+/// Unlike the flags, the datatype is maintained by the server itself: it detects JSON
+/// content and tracks whether the content is compressed or carries extended attributes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Datatype(u8);
+
+impl Datatype {
+    /// The content is valid JSON.
+    pub const JSON: Datatype = Datatype(0x01);
+    /// The content is compressed with snappy.
+    pub const SNAPPY: Datatype = Datatype(0x02);
+    /// The content is prefixed with extended attributes.
+    pub const XATTR: Datatype = Datatype(0x04);
+
+    /// Wraps the datatype byte as found in the binary protocol.
+    pub fn from_bits(bits: u8) -> Self {
+        Datatype(bits)
+    }
+
+    /// The datatype byte as used in the binary protocol.
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Whether all the bits of `other` are set.
+    pub fn contains(self, other: Datatype) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_json(self) -> bool {
+        self.contains(Datatype::JSON)
+    }
+
+    pub fn is_snappy(self) -> bool {
+        self.contains(Datatype::SNAPPY)
+    }
+
+    pub fn has_xattrs(self) -> bool {
+        self.contains(Datatype::XATTR)
+    }
+}
+
+impl core::ops::BitOr for Datatype {
+    type Output = Datatype;
+
+    fn bitor(self, rhs: Datatype) -> Datatype {
+        Datatype(self.0 | rhs.0)
+    }
+}
@@ -53,6 +53,14 @@ fn main() {
         build_cfg.define("LIBCOUCHBASE_STATIC", "ON");
     }
 
+    if cfg!(feature = "packet-dump") {
+        build_cfg.define("LCB_DUMP_PACKETS", "ON");
+    }
+
+    if cfg!(feature = "http-compression") {
+        build_cfg.define("LCB_USE_ZLIB", "ON");
+    }
+
     if cfg!(target_os = "windows") {
         build_cfg.no_c_flags(true);
     }
@@ -98,6 +106,9 @@ fn main() {
             println!("cargo:rustc-link-lib=dylib=stdc++");
             println!("cargo:rustc-link-lib=dylib=gcc");
         }
+        if cfg!(feature = "http-compression") {
+            println!("cargo:rustc-link-lib=dylib=z");
+        }
         println!("cargo:rustc-link-lib=static=couchbase");
     } else {
         println!("cargo:rustc-link-lib=dylib=couchbase");
@@ -116,7 +127,11 @@ fn main() {
         .size_t_is_usize(true)
         .generate_comments(false)
         .whitelist_function("lcb_.*")
+        .whitelist_function("lcbauth_.*")
+        .whitelist_function("lcbvb_.*")
         .whitelist_type("lcb_.*")
+        .whitelist_type("lcbauth_.*")
+        .whitelist_type("lcbvb_.*")
         .whitelist_var("LCB_.*")
         .whitelist_type("__va_list_tag");
 
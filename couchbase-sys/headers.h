@@ -1 +1,3 @@
-#include "libcouchbase/include/libcouchbase/couchbase.h"
\ No newline at end of file
+#include "libcouchbase/include/libcouchbase/couchbase.h"
+#include "libcouchbase/include/libcouchbase/utils.h"
+#include "libcouchbase/include/libcouchbase/vbucket.h"
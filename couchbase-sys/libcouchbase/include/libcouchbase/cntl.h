@@ -1217,6 +1217,10 @@ typedef enum {
 /**
  * Select type of network (alternative addresses).
  *
+ * `auto` (the default) uses the alternate addresses if the bootstrap host is one of them,
+ * `default` always uses the primary addresses and any other value always uses the
+ * alternate addresses of the network with that name.
+ *
  * Use `network` in the connection string
  *
  * @cntl_arg_get_and_set{`const char**`, `const char*`}
@@ -1286,11 +1290,124 @@ typedef enum {
  */
 #define LCB_CNTL_SEARCH_TIMEOUT 0x63
 
+/**
+ * @brief HTTP response compression
+ * Ask the query, search and analytics services for gzip or deflate encoded responses and
+ * decompress them transparently. Only has an effect if the library was built with
+ * `LCB_USE_ZLIB`.
+ *
+ * @cntl_arg_both{int (as boolean)}
+ * @volatile
+ */
+#define LCB_CNTL_HTTP_COMPRESSION 0x64
+
+/**
+ * @brief Configuration push
+ * Ask the data nodes to notify the client about configuration changes (duplex clustermap
+ * change notifications), so topology changes are learned right away instead of only through
+ * polling. Servers which do not support it ignore the request.
+ *
+ * Use `enable_config_push` in the connection string
+ *
+ * @cntl_arg_both{int (as boolean)}
+ * @volatile
+ */
+#define LCB_CNTL_ENABLE_CONFIG_PUSH 0x65
+
+/**
+ * @brief Configuration refresh floor
+ * The minimum time in between two configuration refreshes, 50ms by default.
+ *
+ * Refreshes requested earlier (i.e. by many operations failing with `NOT_MY_VBUCKET` during
+ * a rebalance) are coalesced into a single one which runs once the floor has passed. The
+ * floor is also the lowest accepted value for @ref LCB_CNTL_CONFIG_POLL_INTERVAL, so it
+ * has to be set first when lowering both.
+ *
+ * Use `config_poll_floor` in the connection string
+ *
+ * @cntl_arg_both{lcb_U32*}
+ * @volatile
+ */
+#define LCB_CNTL_CONFIG_POLL_FLOOR 0x66
+
+/**
+ * @brief Circuit breakers for the data nodes
+ * Track the requests sent to every data node and stop dispatching to a node once too many of
+ * them time out. Requests which would go to the node are handed to the retry strategy with
+ * `LCB_RETRY_REASON_CIRCUIT_BREAKER_OPEN` instead, and fail with
+ * `LCB_ERR_CIRCUIT_BREAKER_OPEN` unless they are retried. Once the sleep window passed, a
+ * NOOP is sent to the node as canary and the breaker closes again if it is answered in time.
+ * Disabled by default.
+ *
+ * Use `circuit_breaker` in the connection string
+ *
+ * @cntl_arg_both{int (as boolean)}
+ * @volatile
+ */
+#define LCB_CNTL_CIRCUIT_BREAKER 0x67
+
+/**
+ * @brief Circuit breaker volume threshold
+ * The minimum number of requests within the rolling window before the breaker may open, 20
+ * by default.
+ *
+ * Use `circuit_breaker_volume_threshold` in the connection string
+ *
+ * @cntl_arg_both{lcb_U32*}
+ * @volatile
+ */
+#define LCB_CNTL_CIRCUIT_BREAKER_VOLUME_THRESHOLD 0x68
+
+/**
+ * @brief Circuit breaker error threshold
+ * The percentage of requests within the rolling window which have to time out for the
+ * breaker to open, 50 by default.
+ *
+ * Use `circuit_breaker_error_threshold` in the connection string
+ *
+ * @cntl_arg_both{lcb_U32*}
+ * @volatile
+ */
+#define LCB_CNTL_CIRCUIT_BREAKER_ERROR_THRESHOLD 0x69
+
+/**
+ * @brief Circuit breaker sleep window
+ * How long an open breaker waits before sending the canary, 5 seconds by default.
+ *
+ * Use `circuit_breaker_sleep_window` in the connection string
+ *
+ * @cntl_arg_both{lcb_U32*}
+ * @volatile
+ */
+#define LCB_CNTL_CIRCUIT_BREAKER_SLEEP_WINDOW 0x6a
+
+/**
+ * @brief Circuit breaker rolling window
+ * The window over which the requests are counted, 1 minute by default.
+ *
+ * Use `circuit_breaker_rolling_window` in the connection string
+ *
+ * @cntl_arg_both{lcb_U32*}
+ * @volatile
+ */
+#define LCB_CNTL_CIRCUIT_BREAKER_ROLLING_WINDOW 0x6b
+
+/**
+ * @brief Circuit breaker canary timeout
+ * How long to wait for the response to the canary NOOP, 5 seconds by default.
+ *
+ * Use `circuit_breaker_canary_timeout` in the connection string
+ *
+ * @cntl_arg_both{lcb_U32*}
+ * @volatile
+ */
+#define LCB_CNTL_CIRCUIT_BREAKER_CANARY_TIMEOUT 0x6c
+
 /**
  * This is not a command, but rather an indicator of the last item.
  * @internal
  */
-#define LCB_CNTL__MAX 0x64
+#define LCB_CNTL__MAX 0x6d
 /**@}*/
 
 #ifdef __cplusplus
@@ -699,6 +699,11 @@ LIBCOUCHBASE_API lcb_STATUS lcb_cmdget_destroy(lcb_CMDGET *cmd);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdget_parent_span(lcb_CMDGET *cmd, lcbtrace_SPAN *span);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdget_collection(lcb_CMDGET *cmd, const char *scope, size_t scope_len,
                                                   const char *collection, size_t collection_len);
+/**
+ * Uses the given collection id instead of resolving the scope and collection names.
+ * @volatile
+ */
+LIBCOUCHBASE_API lcb_STATUS lcb_cmdget_collection_id(lcb_CMDGET *cmd, uint32_t cid);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdget_key(lcb_CMDGET *cmd, const char *key, size_t key_len);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdget_expiry(lcb_CMDGET *cmd, uint32_t expiration);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdget_locktime(lcb_CMDGET *cmd, uint32_t duration);
@@ -963,6 +968,11 @@ LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_destroy(lcb_CMDSTORE *cmd);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_parent_span(lcb_CMDSTORE *cmd, lcbtrace_SPAN *span);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_collection(lcb_CMDSTORE *cmd, const char *scope, size_t scope_len,
                                                     const char *collection, size_t collection_len);
+/**
+ * Uses the given collection id instead of resolving the scope and collection names.
+ * @volatile
+ */
+LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_collection_id(lcb_CMDSTORE *cmd, uint32_t cid);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_key(lcb_CMDSTORE *cmd, const char *key, size_t key_len);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_value(lcb_CMDSTORE *cmd, const char *value, size_t value_len);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_value_iov(lcb_CMDSTORE *cmd, const lcb_IOV *value, size_t value_len);
@@ -971,9 +981,42 @@ LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_cas(lcb_CMDSTORE *cmd, uint64_t cas);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_flags(lcb_CMDSTORE *cmd, uint32_t flags);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_datatype(lcb_CMDSTORE *cmd, uint8_t datatype);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_durability(lcb_CMDSTORE *cmd, lcb_DURABILITY_LEVEL level);
+/**
+ * Keeps the current expiry of the document instead of resetting it, requires Couchbase Server 7.0.
+ * Only valid for ::LCB_STORE_UPSERT and ::LCB_STORE_REPLACE.
+ */
+LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_preserve_expiry(lcb_CMDSTORE *cmd, int should_preserve);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_durability_observe(lcb_CMDSTORE *cmd, int persist_to, int replicate_to);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_timeout(lcb_CMDSTORE *cmd, uint32_t timeout);
 LIBCOUCHBASE_API lcb_STATUS lcb_store(lcb_INSTANCE *instance, void *cookie, const lcb_CMDSTORE *cmd);
+
+/**
+ * @uncommitted
+ *
+ * Polls the nodes of a key until a completed mutation of it is persisted to and replicated to
+ * the given number of nodes, like lcb_cmdstore_durability_observe() does as part of a store.
+ * This covers removals, counters and sub-document mutations, which cannot poll as part of
+ * their command.
+ *
+ * The outcome is passed to the ::LCB_CALLBACK_ENDURE callback, see lcb_respendure_status() and
+ * lcb_respendure_cookie().
+ *
+ * @param instance
+ * @param cookie passed to the callback
+ * @param key the key of the mutated document
+ * @param key_len
+ * @param cas the CAS returned by the mutation
+ * @param token the mutation token returned by the mutation, the last known token of the
+ * partition is used if it is NULL
+ * @param persist_to the number of nodes, including the active one, to persist to
+ * @param replicate_to the number of replicas to replicate to
+ * @param check_delete non-zero if the mutation removed the document
+ */
+LIBCOUCHBASE_API lcb_STATUS lcb_endure_mutation(lcb_INSTANCE *instance, void *cookie, const char *key, size_t key_len,
+                                                uint64_t cas, const lcb_MUTATION_TOKEN *token, int persist_to,
+                                                int replicate_to, int check_delete);
+LIBCOUCHBASE_API lcb_STATUS lcb_respendure_status(const lcb_RESPBASE *resp);
+LIBCOUCHBASE_API lcb_STATUS lcb_respendure_cookie(const lcb_RESPBASE *resp, void **cookie);
 /**@}*/
 
 /**
@@ -1005,6 +1048,164 @@ LIBCOUCHBASE_API lcb_open_callback lcb_set_open_callback(lcb_INSTANCE *instance,
 LIBCOUCHBASE_API lcb_STATUS lcb_open(lcb_INSTANCE *instance, const char *bucket, size_t bucket_len);
 /**@}*/
 
+/**
+ * @ingroup lcb-kv-api
+ * @defgroup lcb-kvdispatch Dispatch Observer
+ * @brief Observe every key/value response as it is dispatched
+ * @addtogroup lcb-kvdispatch
+ * @{
+ */
+
+/**
+ * @volatile
+ * @brief Metadata of a key/value packet at the time its response is dispatched.
+ *
+ * Values are never exposed, only sizes. All pointers are only valid for the duration of the
+ * callback.
+ */
+typedef struct {
+    uint8_t opcode;
+    /** Status as received from the server, 0 if the packet was failed locally */
+    uint16_t status;
+    /** Error the packet was failed with locally (i.e. on timeout), LCB_SUCCESS otherwise */
+    lcb_STATUS rc;
+    uint32_t opaque;
+    uint16_t vbucket;
+    /** Document key, without the collection prefix */
+    const char *key;
+    size_t nkey;
+    uint64_t cas;
+    /** Size of the request packet in bytes, including its value */
+    uint32_t request_size;
+    /** Size of the response body in bytes, 0 if the packet was failed locally */
+    uint32_t response_size;
+    /** Time between scheduling the request and dispatching its response */
+    uint64_t latency_ns;
+    /** Time the server spent on the request, 0 if not reported */
+    uint64_t server_duration_us;
+    /** Node the request was sent to, NULL if it was never sent */
+    const char *host;
+    const char *port;
+} lcb_KVDISPATCH_INFO;
+
+typedef void (*lcb_kvdispatch_callback)(lcb_INSTANCE *instance, const lcb_KVDISPATCH_INFO *info);
+
+/**
+ * @volatile
+ * @brief Sets the callback invoked for every key/value response before the operation
+ * callback, i.e. to record per opcode latencies or sample slow operations.
+ *
+ * The callback runs on the hot path and should return quickly.
+ *
+ * @param instance
+ * @param callback the callback, or NULL to remove it
+ * @return the previous callback
+ */
+LIBCOUCHBASE_API lcb_kvdispatch_callback lcb_set_kvdispatch_callback(lcb_INSTANCE *instance,
+                                                                     lcb_kvdispatch_callback callback);
+/**@}*/
+
+/**
+ * @ingroup lcb-public-api
+ * @defgroup lcb-certificate Certificate Observer
+ * @brief Observe the certificates presented by the nodes
+ * @addtogroup lcb-certificate
+ * @{
+ */
+
+/**
+ * @volatile
+ * @brief The certificate a node presented when establishing a TLS connection.
+ *
+ * All pointers are only valid for the duration of the callback.
+ */
+typedef struct {
+    /** Node the connection goes to */
+    const char *host;
+    const char *port;
+    /** Subject of the certificate, in the OpenSSL one line format */
+    const char *subject;
+    /** Issuer of the certificate, in the OpenSSL one line format */
+    const char *issuer;
+    /** Hex encoded SHA-256 of the DER encoded SubjectPublicKeyInfo of the certificate */
+    const char *spki_sha256;
+} lcb_CERTIFICATE_INFO;
+
+/**
+ * @return 0 to accept the certificate, anything else to fail the connection with
+ * LCB_ERR_SSL_ERROR
+ */
+typedef int (*lcb_certificate_callback)(lcb_INSTANCE *instance, const lcb_CERTIFICATE_INFO *info);
+
+/**
+ * @volatile
+ * @brief Sets the callback invoked for the certificate of every TLS connection, i.e. to
+ * detect certificate rotations or to pin certificates.
+ *
+ * The callback is only invoked for certificates which passed verification, or for every
+ * certificate if verification is disabled.
+ *
+ * @param instance
+ * @param callback the callback, or NULL to remove it
+ * @return the previous callback
+ */
+LIBCOUCHBASE_API lcb_certificate_callback lcb_set_certificate_callback(lcb_INSTANCE *instance,
+                                                                       lcb_certificate_callback callback);
+/**@}*/
+
+/**
+ * @ingroup lcb-public-api
+ * @defgroup lcb-cluster-event Cluster Event Observer
+ * @brief Observe changes of the state of the instance
+ * @addtogroup lcb-cluster-event
+ * @{
+ */
+
+typedef enum {
+    /** A new cluster configuration has been applied */
+    LCB_CLUSTER_EVENT_CONFIG_UPDATED = 0,
+    /** A key/value connection has been established and negotiated */
+    LCB_CLUSTER_EVENT_ENDPOINT_CONNECTED,
+    /** A key/value connection has been closed because of an error */
+    LCB_CLUSTER_EVENT_ENDPOINT_DISCONNECTED,
+    /** The circuit breaker of a node opened, see LCB_CNTL_CIRCUIT_BREAKER */
+    LCB_CLUSTER_EVENT_CIRCUIT_OPENED,
+    /** The circuit breaker of a node closed again after a successful canary */
+    LCB_CLUSTER_EVENT_CIRCUIT_CLOSED
+} lcb_CLUSTER_EVENT_TYPE;
+
+/**
+ * @volatile
+ * @brief An event passed to the cluster event callback.
+ *
+ * All pointers are only valid for the duration of the callback.
+ */
+typedef struct {
+    lcb_CLUSTER_EVENT_TYPE type;
+    /** Node of the event, NULL for LCB_CLUSTER_EVENT_CONFIG_UPDATED */
+    const char *host;
+    const char *port;
+    /** Revision of the configuration, for LCB_CLUSTER_EVENT_CONFIG_UPDATED */
+    int64_t config_revision;
+    /** Why the connection was closed, for LCB_CLUSTER_EVENT_ENDPOINT_DISCONNECTED */
+    lcb_STATUS rc;
+} lcb_CLUSTER_EVENT;
+
+typedef void (*lcb_cluster_event_callback)(lcb_INSTANCE *instance, const lcb_CLUSTER_EVENT *event);
+
+/**
+ * @volatile
+ * @brief Sets the callback invoked when the configuration, the key/value connections or the
+ * circuit breakers of the instance change.
+ *
+ * @param instance
+ * @param callback the callback, or NULL to remove it
+ * @return the previous callback
+ */
+LIBCOUCHBASE_API lcb_cluster_event_callback lcb_set_cluster_event_callback(lcb_INSTANCE *instance,
+                                                                           lcb_cluster_event_callback callback);
+/**@}*/
+
 /**
  * @ingroup lcb-kv-api
  * @defgroup lcb-remove Remove
@@ -1074,6 +1275,11 @@ LIBCOUCHBASE_API lcb_STATUS lcb_cmdremove_destroy(lcb_CMDREMOVE *cmd);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdremove_parent_span(lcb_CMDREMOVE *cmd, lcbtrace_SPAN *span);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdremove_collection(lcb_CMDREMOVE *cmd, const char *scope, size_t scope_len,
                                                      const char *collection, size_t collection_len);
+/**
+ * Uses the given collection id instead of resolving the scope and collection names.
+ * @volatile
+ */
+LIBCOUCHBASE_API lcb_STATUS lcb_cmdremove_collection_id(lcb_CMDREMOVE *cmd, uint32_t cid);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdremove_key(lcb_CMDREMOVE *cmd, const char *key, size_t key_len);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdremove_cas(lcb_CMDREMOVE *cmd, uint64_t cas);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdremove_durability(lcb_CMDREMOVE *cmd, lcb_DURABILITY_LEVEL level);
@@ -1444,6 +1650,25 @@ LIBCOUCHBASE_API lcb_STATUS lcb_cmddiag_prettify(lcb_CMDDIAG *cmd, int enable);
  */
 LIBCOUCHBASE_API lcb_STATUS lcb_diag(lcb_INSTANCE *instance, void *cookie, const lcb_CMDDIAG *cmd);
 
+/**
+ * @volatile
+ * @brief Steers the requests of a HTTP service away from a node, i.e. after it failed its
+ * health checks.
+ *
+ * A quarantined node is only picked if no other node provides the service. Quarantines are
+ * kept across configuration updates until they are lifted.
+ *
+ * @param instance
+ * @param service the service, LCB_PING_SERVICE_KV is rejected since keys are bound to their
+ * nodes
+ * @param hostname the hostname of the node as in the cluster configuration, IPv6 addresses
+ * without brackets
+ * @param quarantined non-zero to quarantine the node, zero to lift the quarantine
+ * @return LCB_ERR_INVALID_ARGUMENT for the key/value service or a missing hostname
+ */
+LIBCOUCHBASE_API lcb_STATUS lcb_set_node_quarantine(lcb_INSTANCE *instance, lcb_PING_SERVICE service,
+                                                    const char *hostname, int quarantined);
+
 /**@} (Group: PING) */
 
 /* @ingroup lcb-public-api
@@ -1514,7 +1739,8 @@ typedef enum {
     LCB_HTTP_METHOD_POST = 1,
     LCB_HTTP_METHOD_PUT = 2,
     LCB_HTTP_METHOD_DELETE = 3,
-    LCB_HTTP_METHOD_MAX = 4
+    LCB_HTTP_METHOD_PATCH = 4,
+    LCB_HTTP_METHOD_MAX = 5
 } lcb_HTTP_METHOD;
 
 /**
@@ -1602,6 +1828,14 @@ LIBCOUCHBASE_API int lcb_resphttp_is_final(const lcb_RESPHTTP *resp);
  * is terminated by a `NULL` pointer to indicate no more headers.
  */
 LIBCOUCHBASE_API lcb_STATUS lcb_resphttp_headers(const lcb_RESPHTTP *resp, const char *const **headers);
+/**
+ * Size of the response body received so far, as received from the network and after
+ * decompression. Both are the same unless the response is compressed, see
+ * @ref LCB_CNTL_HTTP_COMPRESSION.
+ *
+ * @volatile
+ */
+LIBCOUCHBASE_API lcb_STATUS lcb_resphttp_body_sizes(const lcb_RESPHTTP *resp, lcb_U64 *wire_bytes, lcb_U64 *raw_bytes);
 
 typedef struct lcb_CMDHTTP_ lcb_CMDHTTP;
 
@@ -1795,6 +2029,17 @@ typedef enum {
 LIBCOUCHBASE_API
 lcb_STATUS lcb_wait(lcb_INSTANCE *instance, lcb_WAITFLAGS flags);
 
+/**
+ * @volatile
+ * @brief Wait for completion of scheduled operations, but no longer than the timeout.
+ * @param instance the instance
+ * @param timeout_us how long to wait at most, in microseconds
+ * @return LCB_SUCCESS if no operations are pending anymore, LCB_ERR_TIMEOUT if some still are
+ * once the timeout passed
+ */
+LIBCOUCHBASE_API
+lcb_STATUS lcb_wait_for(lcb_INSTANCE *instance, uint32_t timeout_us);
+
 /**
  * @brief Forcefully break from the event loop.
  *
@@ -2903,6 +3148,13 @@ LIBCOUCHBASE_API lcb_STATUS lcb_cmdquery_named_param(lcb_CMDQUERY *cmd, const ch
  */
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdquery_positional_param(lcb_CMDQUERY *cmd, const char *value, size_t value_len);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdquery_adhoc(lcb_CMDQUERY *cmd, int adhoc);
+/**
+ * Only prepares the statement and caches its plan for later queries with `adhoc` disabled,
+ * instead of executing it. The plan is returned as the only row.
+ *
+ * @volatile
+ */
+LIBCOUCHBASE_API lcb_STATUS lcb_cmdquery_prepare_only(lcb_CMDQUERY *cmd, int prepare_only);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdquery_client_context_id(lcb_CMDQUERY *cmd, const char *value, size_t value_len);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdquery_pretty(lcb_CMDQUERY *cmd, int pretty);
 /**
@@ -3207,6 +3459,15 @@ LIBCOUCHBASE_API lcb_STATUS lcb_cmdsubdoc_access_deleted(lcb_CMDSUBDOC *cmd, int
  * Any system or user XATTRs will be stored, but a document body will not be.
  */
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdsubdoc_create_as_deleted(lcb_CMDSUBDOC *cmd, int flag);
+
+/**
+ * Sends the lookup to the given replica (starting at 0) instead of the active node,
+ * using the replica read document flag. Pass -1 to read from the active node again.
+ *
+ * Only valid for lookups, and only against servers which negotiated the subdoc replica
+ * read feature, otherwise scheduling fails with ::LCB_ERR_UNSUPPORTED_OPERATION.
+ */
+LIBCOUCHBASE_API lcb_STATUS lcb_cmdsubdoc_replica_index(lcb_CMDSUBDOC *cmd, int index);
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdsubdoc_timeout(lcb_CMDSUBDOC *cmd, uint32_t timeout);
 
 LIBCOUCHBASE_API lcb_STATUS lcb_subdoc(lcb_INSTANCE *instance, void *cookie, const lcb_CMDSUBDOC *cmd);
@@ -202,7 +202,8 @@ X(LCB_ERR_CONNECT_ERROR,                    1051, LCB_ERROR_TYPE_SDK, LCB_ERROR_
 X(LCB_ERR_EMPTY_KEY,                        1052, LCB_ERROR_TYPE_SDK, LCB_ERROR_FLAG_INPUT, "An empty key was passed to an operation") \
 X(LCB_ERR_HTTP,                             1053, LCB_ERROR_TYPE_SDK, 0, "HTTP Operation failed. Inspect status code for details") \
 X(LCB_ERR_QUERY,                            1054, LCB_ERROR_TYPE_SDK, 0, "Query execution failed. Inspect raw response object for information") \
-X(LCB_ERR_TOPOLOGY_CHANGE,                  1055, LCB_ERROR_TYPE_SDK, 0, "Topology Change (internal)")
+X(LCB_ERR_TOPOLOGY_CHANGE,                  1055, LCB_ERROR_TYPE_SDK, 0, "Topology Change (internal)") \
+X(LCB_ERR_CIRCUIT_BREAKER_OPEN,             1056, LCB_ERROR_TYPE_SDK, LCB_ERROR_FLAG_TRANSIENT, "The circuit breaker of the node is open, the request was not sent")
 /* clang-format on */
 
 /** Error codes returned by the library. */
@@ -241,6 +242,12 @@ LIBCOUCHBASE_API lcb_STATUS lcb_errctx_kv_ref(const lcb_KEY_VALUE_ERROR_CONTEXT
                                               size_t *ref_len);
 LIBCOUCHBASE_API lcb_STATUS lcb_errctx_kv_endpoint(const lcb_KEY_VALUE_ERROR_CONTEXT *ctx, const char **endpoint,
                                                    size_t *endpoint_len);
+/** The local address of the connection the operation was sent over, if it was sent */
+LIBCOUCHBASE_API lcb_STATUS lcb_errctx_kv_local_endpoint(const lcb_KEY_VALUE_ERROR_CONTEXT *ctx, const char **endpoint,
+                                                         size_t *endpoint_len);
+/** The id the connection announced to the server in its HELLO, as logged by the server */
+LIBCOUCHBASE_API lcb_STATUS lcb_errctx_kv_connection_id(const lcb_KEY_VALUE_ERROR_CONTEXT *ctx, const char **id,
+                                                        size_t *id_len);
 
 typedef struct lcb_QUERY_ERROR_CONTEXT_ lcb_QUERY_ERROR_CONTEXT;
 LIBCOUCHBASE_API lcb_STATUS lcb_errctx_query_rc(const lcb_QUERY_ERROR_CONTEXT *ctx);
@@ -811,11 +811,22 @@ typedef enum {
     PROTOCOL_BINARY_FEATURE_ALT_REQUEST_SUPPORT = 0x10,
     PROTOCOL_BINARY_FEATURE_SYNC_REPLICATION = 0x11,
     PROTOCOL_BINARY_FEATURE_COLLECTIONS = 0x12,
-    PROTOCOL_BINARY_FEATURE_CREATE_AS_DELETED = 0x17
+    PROTOCOL_BINARY_FEATURE_PRESERVE_TTL = 0x14,
+    PROTOCOL_BINARY_FEATURE_CREATE_AS_DELETED = 0x17,
+    PROTOCOL_BINARY_FEATURE_SUBDOC_REPLICA_READ = 0x1c,
+    PROTOCOL_BINARY_FEATURE_CLUSTERMAP_CHANGE_NOTIFICATION_BRIEF = 0x1f
 } protocol_binary_hello_features;
 
+/**
+ * Opcodes of the requests the server sends to the client (PROTOCOL_BINARY_SREQ), once
+ * PROTOCOL_BINARY_FEATURE_DUPLEX has been negotiated.
+ */
+typedef enum {
+    PROTOCOL_BINARY_SCMD_CLUSTERMAP_CHANGE_NOTIFICATION = 0x01
+} protocol_binary_server_command;
+
 #define MEMCACHED_FIRST_HELLO_FEATURE 0x01
-#define MEMCACHED_TOTAL_HELLO_FEATURES 15
+#define MEMCACHED_TOTAL_HELLO_FEATURES 20
 
 // clang-format off
 #define protocol_feature_2_text(a) \
@@ -832,12 +843,15 @@ typedef enum {
     (a == PROTOCOL_BINARY_FEATURE_JSON) ? "JSON": \
     (a == PROTOCOL_BINARY_FEATURE_DUPLEX) ? "Duplex": \
     (a == PROTOCOL_BINARY_FEATURE_CLUSTERMAP_CHANGE_NOTIFICATION) ? "Clustermap change notification": \
+    (a == PROTOCOL_BINARY_FEATURE_CLUSTERMAP_CHANGE_NOTIFICATION_BRIEF) ? "Clustermap change notification (brief)": \
     (a == PROTOCOL_BINARY_FEATURE_UNORDERED_EXECUTION) ? "Unordered execution": \
     (a == PROTOCOL_BINARY_FEATURE_TRACING) ? "Tracing": \
     (a == PROTOCOL_BINARY_FEATURE_ALT_REQUEST_SUPPORT) ? "Alt request support": \
     (a == PROTOCOL_BINARY_FEATURE_SYNC_REPLICATION) ? "Synchronous Replication": \
     (a == PROTOCOL_BINARY_FEATURE_COLLECTIONS) ? "Collections": \
+    (a == PROTOCOL_BINARY_FEATURE_PRESERVE_TTL) ? "Preserve TTL": \
     (a == PROTOCOL_BINARY_FEATURE_CREATE_AS_DELETED) ? "Create as Deleted": \
+    (a == PROTOCOL_BINARY_FEATURE_SUBDOC_REPLICA_READ) ? "Subdoc replica read": \
     "Unknown"
 // clang-format on
 
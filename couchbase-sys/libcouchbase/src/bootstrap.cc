@@ -16,7 +16,11 @@
  */
 
 #define LCB_BOOTSTRAP_DEFINE_STRUCT 1
+#include "connspec.h"
 #include "internal.h"
+#include <atomic>
+#include <system_error>
+#include <thread>
 
 #define LOGARGS(instance, lvl) instance->settings, "bootstrap", LCB_LOG_##lvl, __FILE__, __LINE__
 
@@ -143,6 +147,33 @@ void Bootstrap::config_callback(EventType event, ConfigInfo *info)
 
 const char *provider_string(clconfig::Method type);
 
+/** How often the DNS SRV records are looked up again at most */
+static const hrtime_t DNSSRV_REFRESH_INTERVAL = LCB_S2NS(10);
+
+/** How often a running DNS SRV lookup is checked for its result */
+static const uint32_t DNSSRV_POLL_INTERVAL = LCB_MS2US(100);
+
+namespace lcb
+{
+/**
+ * A DNS SRV lookup running on its own thread, as the resolver blocks. The thread shares it,
+ * so it outlives the instance if that is destroyed while the lookup runs.
+ */
+struct DnsSrvLookup {
+    DnsSrvLookup(std::string name_, bool ssl_) : name(std::move(name_)), ssl(ssl_) {}
+    ~DnsSrvLookup()
+    {
+        delete hosts;
+    }
+
+    const std::string name;
+    const bool ssl;
+    lcb_STATUS rc{LCB_ERR_SDK_INTERNAL};
+    Hostlist *hosts{nullptr};
+    std::atomic< bool > done{false};
+};
+} // namespace lcb
+
 void Bootstrap::clconfig_lsn(EventType e, ConfigInfo *i)
 {
     if (state == S_INITIAL_PRE) {
@@ -151,6 +182,49 @@ void Bootstrap::clconfig_lsn(EventType e, ConfigInfo *i)
         lcb_log(LOGARGS(parent, INFO), "Got new config (source=%s, bucket=%.*s, rev=%d). Will refresh asynchronously",
                 provider_string(i->get_origin()), (int)i->vbc->bname_len, i->vbc->bname, i->vbc->revid);
         tm.signal();
+    } else if (e == clconfig::CLCONFIG_EVENT_PROVIDERS_CYCLED && parent->dnssrv_name != nullptr) {
+        /* None of the known nodes could be reached, the cluster might have moved to other hosts */
+        hrtime_t now = gethrtime();
+        if (last_rediscover == 0 || now - last_rediscover >= DNSSRV_REFRESH_INTERVAL) {
+            last_rediscover = now;
+            tmsrv.signal();
+        }
+    }
+}
+
+void Bootstrap::rediscover()
+{
+    if (!srvlookup) {
+        lcb_log(LOGARGS(parent, INFO), "No known node is reachable. Refreshing DNS SRV records");
+        srvlookup = std::make_shared< DnsSrvLookup >(*parent->dnssrv_name,
+                                                     LCBT_SETTING(parent, sslopts) & LCB_SSL_ENABLED);
+        std::shared_ptr< DnsSrvLookup > lookup = srvlookup;
+        try {
+            std::thread([lookup]() {
+                lookup->hosts = dnssrv_getbslist(lookup->name.c_str(), lookup->ssl, lookup->rc);
+                lookup->done.store(true, std::memory_order_release);
+            }).detach();
+        } catch (const std::system_error &e) {
+            lcb_log(LOGARGS(parent, WARN), "Could not start DNS SRV lookup: %s", e.what());
+            srvlookup.reset();
+            return;
+        }
+        tmsrv.rearm(DNSSRV_POLL_INTERVAL);
+        return;
+    }
+
+    if (!srvlookup->done.load(std::memory_order_acquire)) {
+        tmsrv.rearm(DNSSRV_POLL_INTERVAL);
+        return;
+    }
+
+    std::shared_ptr< DnsSrvLookup > lookup;
+    lookup.swap(srvlookup);
+    if (lookup->hosts == nullptr) {
+        lcb_log(LOGARGS(parent, WARN), "Failed to refresh DNS SRV records for %s: %s", lookup->name.c_str(),
+                lcb_strerror_short(lookup->rc));
+    } else if (parent->apply_dns_srv(*lookup->hosts)) {
+        bootstrap(BS_REFRESH_ALWAYS);
     }
 }
 
@@ -213,8 +287,8 @@ void Bootstrap::initial_error(lcb_STATUS err, const char *errinfo)
 }
 
 Bootstrap::Bootstrap(lcb_INSTANCE *instance)
-    : parent(instance), tm(parent->iotable, this), tmpoll(parent->iotable, this), last_refresh(0), errcounter(0),
-      state(S_INITIAL_PRE)
+    : parent(instance), tm(parent->iotable, this), tmpoll(parent->iotable, this), tmsrv(parent->iotable, this),
+      last_rediscover(0), last_refresh(0), errcounter(0), state(S_INITIAL_PRE)
 {
     parent->confmon->add_listener(this);
 }
@@ -263,6 +337,17 @@ lcb_STATUS Bootstrap::bootstrap(unsigned options)
         }
     }
 
+    if (options != BS_REFRESH_INITIAL && options != BS_REFRESH_OPEN_BUCKET && last_refresh != 0) {
+        /* Coalesce refreshes requested in quick succession into one, run once the floor has passed */
+        hrtime_t next_ts = last_refresh + LCB_US2NS(LCBT_SETTING(parent, config_poll_floor));
+        if (now < next_ts) {
+            lcb_log(LOGARGS(parent, TRACE), "Deferring config refresh by %" PRIu64 "us (config_poll_floor)",
+                    LCB_NS2US(next_ts - now));
+            tmpoll.rearm(static_cast<uint32_t>(LCB_NS2US(next_ts - now)));
+            return LCB_SUCCESS;
+        }
+    }
+
     if (options == BS_REFRESH_INITIAL) {
         if (LCBT_SETTING(parent, network)) {
             lcb_log(LOGARGS(parent, INFO), "Requested network configuration: \"%s\"", LCBT_SETTING(parent, network));
@@ -291,6 +376,7 @@ Bootstrap::~Bootstrap()
 {
     tm.release();
     tmpoll.release();
+    tmsrv.release();
     parent->confmon->remove_listener(this);
 }
 
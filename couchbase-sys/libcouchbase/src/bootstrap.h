@@ -29,9 +29,12 @@
 #ifdef __cplusplus
 #include "bucketconfig/clconfig.h"
 #include <lcbio/timer-cxx.h>
+#include <memory>
 
 namespace lcb
 {
+struct DnsSrvLookup;
+
 /**
  * Structure containing the bootstrap state for the instance.
  *
@@ -74,6 +77,7 @@ class Bootstrap : lcb::clconfig::Listener
     inline void initial_error(lcb_STATUS, const char *);
     void timer_dispatch();
     void bgpoll();
+    void rediscover();
 
     lcb_INSTANCE *parent;
 
@@ -86,6 +90,19 @@ class Bootstrap : lcb::clconfig::Listener
     /**Timer used for periodic polling of config */
     lcb::io::Timer< Bootstrap, &Bootstrap::bgpoll > tmpoll;
 
+    /**Timer used to re-resolve the DNS SRV records outside of the provider callbacks, and to
+     * check for the result of the lookup */
+    lcb::io::Timer< Bootstrap, &Bootstrap::rediscover > tmsrv;
+
+    /** The running DNS SRV lookup, if any */
+    std::shared_ptr< DnsSrvLookup > srvlookup;
+
+    /**
+     * Timestamp of the most recent DNS SRV re-resolution, which happens at most
+     * once per DNSSRV_REFRESH_INTERVAL.
+     */
+    hrtime_t last_rediscover;
+
     /**
      * Timestamp indicating the most recent configuration activity. This
      * timestamp is used to control throttling, such that the @ref
@@ -30,6 +30,8 @@
 #include <lcbio/ssl.h>
 #include "ctx-log-inl.h"
 
+#include <cinttypes>
+#include <cstdlib>
 #include <cstring>
 
 #define LOGFMT CTX_LOGFMT
@@ -58,6 +60,7 @@ struct CccpProvider : public Provider {
         mcio_error(LCB_ERR_TIMEOUT);
     }
     lcb_STATUS update(const char *host, const char *data);
+    bool has_revision(int64_t epoch, int64_t revision) const;
     void request_config();
     void on_io_read();
 
@@ -93,6 +96,8 @@ struct CccpProvider : public Provider {
     ConfigInfo *config;
     lcb::io::Timer<CccpProvider, &CccpProvider::on_timeout> timer;
     lcb_INSTANCE *instance;
+    /** The revision epoch of the config, which the vbucket config does not track */
+    int64_t config_epoch{-1};
     lcb::io::ConnectionRequest *creq{};
     lcbio_CTX *ioctx;
     CccpCookie *cmdcookie;
@@ -204,11 +209,61 @@ lcb_STATUS lcb::clconfig::cccp_update(Provider *provider, const char *host, cons
     return static_cast<CccpProvider *>(provider)->update(host, data);
 }
 
+/**
+ * Reads a top level integer field of the configuration without parsing it as a
+ * whole. The fields looked for (`rev` and `revEpoch`) do not appear in nested
+ * objects, so the first match is the one we want.
+ */
+static int64_t peek_int_field(const char *data, const char *field)
+{
+    std::string needle = std::string("\"") + field + "\"";
+    const char *pos = std::strstr(data, needle.c_str());
+    if (pos == nullptr) {
+        return -1;
+    }
+    pos += needle.size();
+    while (*pos == ' ' || *pos == '\t' || *pos == '\n' || *pos == '\r' || *pos == ':') {
+        pos++;
+    }
+    char *end = nullptr;
+    long long value = std::strtoll(pos, &end, 10);
+    if (end == pos) {
+        return -1;
+    }
+    return static_cast<int64_t>(value);
+}
+
+bool CccpProvider::has_revision(int64_t epoch, int64_t revision) const
+{
+    if (config == nullptr || revision < 0) {
+        return false;
+    }
+    if (epoch != config_epoch) {
+        return epoch < config_epoch;
+    }
+    return revision <= lcbvb_get_revision(config->vbc);
+}
+
+bool lcb::clconfig::cccp_has_revision(Provider *provider, int64_t epoch, int64_t revision)
+{
+    return static_cast<CccpProvider *>(provider)->has_revision(epoch, revision);
+}
+
 lcb_STATUS CccpProvider::update(const char *host, const char *data)
 {
     lcbvb_CONFIG *vbc;
     int rv;
     ConfigInfo *new_config;
+
+    int64_t epoch = peek_int_field(data, "revEpoch");
+    int64_t revision = peek_int_field(data, "rev");
+    if (has_revision(epoch, revision)) {
+        lcb_log(LOGARGS(this, TRACE), LOGFMT "Ignoring configuration (epoch=%" PRId64 ", rev=%" PRId64
+                ") received from %s, already have the same or a newer one",
+                LOGID(this), epoch, revision, host);
+        return LCB_SUCCESS;
+    }
+
     vbc = lcbvb_create();
 
     if (!vbc) {
@@ -235,8 +290,8 @@ lcb_STATUS CccpProvider::update(const char *host, const char *data)
         config->decref();
     }
 
-    /** TODO: Figure out the comparison vector */
     config = new_config;
+    config_epoch = epoch;
     parent->provider_got_config(this, new_config);
     return LCB_SUCCESS;
 }
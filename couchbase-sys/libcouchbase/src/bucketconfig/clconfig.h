@@ -705,6 +705,22 @@ static inline const lcb_host_t *http_get_host(Confmon *c)
  */
 lcb_STATUS cccp_update(Provider *provider, const char *host, const char *data);
 
+/**
+ * @brief Check whether the CCCP provider already has a configuration at least
+ * as new as the given revision
+ *
+ * During a rebalance many operations fail with `NOT_MY_VBUCKET` at once, and
+ * each of them carries the same configuration. Checking the revision first
+ * avoids parsing and comparing the same configuration over and over again.
+ *
+ * @param provider The CCCP provider
+ * @param epoch The revision epoch, or -1 if unknown
+ * @param revision The revision, or -1 if unknown
+ * @return true if a configuration with the same or a newer revision has
+ * already been received, false otherwise (or if the revision is unknown)
+ */
+bool cccp_has_revision(Provider *provider, int64_t epoch, int64_t revision);
+
 /**
  * @brief Notify the CCCP provider about a configuration received from a
  * `CMD_GET_CLUSTER_CONFIG` response.
@@ -94,6 +94,48 @@ CALLBACK_ACCESSOR(lcb_set_pktfwd_callback, lcb_pktfwd_callback, pktfwd)
 CALLBACK_ACCESSOR(lcb_set_pktflushed_callback, lcb_pktflushed_callback, pktflushed)
 CALLBACK_ACCESSOR(lcb_set_open_callback, lcb_open_callback, open)
 
+LIBCOUCHBASE_API
+lcb_kvdispatch_callback lcb_set_kvdispatch_callback(lcb_INSTANCE *instance, lcb_kvdispatch_callback cb)
+{
+    /* Unlike the other accessors NULL removes the callback, it is optional */
+    lcb_kvdispatch_callback ret = instance->callbacks.kvdispatch;
+    instance->callbacks.kvdispatch = cb;
+    return ret;
+}
+
+LIBCOUCHBASE_API
+lcb_cluster_event_callback lcb_set_cluster_event_callback(lcb_INSTANCE *instance, lcb_cluster_event_callback cb)
+{
+    lcb_cluster_event_callback ret = instance->callbacks.cluster_event;
+    instance->callbacks.cluster_event = cb;
+    return ret;
+}
+
+void lcb_emit_cluster_event(lcb_INSTANCE *instance, lcb_CLUSTER_EVENT_TYPE type, const char *host, const char *port,
+                            int64_t config_revision, lcb_STATUS rc)
+{
+    lcb_CLUSTER_EVENT event;
+    if (instance->callbacks.cluster_event == NULL) {
+        return;
+    }
+    event.type = type;
+    event.host = host;
+    event.port = port;
+    event.config_revision = config_revision;
+    event.rc = rc;
+    instance->callbacks.cluster_event(instance, &event);
+}
+
+LIBCOUCHBASE_API
+lcb_certificate_callback lcb_set_certificate_callback(lcb_INSTANCE *instance, lcb_certificate_callback cb)
+{
+    /* Lives in the settings since the TLS layer has no access to the instance */
+    lcb_certificate_callback ret = LCBT_SETTING(instance, certificate_callback);
+    LCBT_SETTING(instance, certificate_callback) = cb;
+    LCBT_SETTING(instance, certificate_instance) = instance;
+    return ret;
+}
+
 LIBCOUCHBASE_API
 lcb_RESPCALLBACK lcb_install_callback(lcb_INSTANCE *instance, int cbtype, lcb_RESPCALLBACK cb)
 {
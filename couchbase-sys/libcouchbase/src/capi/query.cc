@@ -189,6 +189,16 @@ LIBCOUCHBASE_API lcb_STATUS lcb_cmdquery_adhoc(lcb_CMDQUERY *cmd, int adhoc)
     return LCB_SUCCESS;
 }
 
+LIBCOUCHBASE_API lcb_STATUS lcb_cmdquery_prepare_only(lcb_CMDQUERY *cmd, int prepare_only)
+{
+    if (prepare_only) {
+        cmd->cmdflags |= LCB_CMDN1QL_F_PREPARE_ONLY;
+    } else {
+        cmd->cmdflags &= ~LCB_CMDN1QL_F_PREPARE_ONLY;
+    }
+    return LCB_SUCCESS;
+}
+
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdquery_client_context_id(lcb_CMDQUERY *cmd, const char *value, size_t value_len)
 {
     cmd->root["client_context_id"] = std::string(value, value_len);
@@ -60,6 +60,14 @@ struct lcb_QUERY_ERROR_CONTEXT_ {
  */
 #define LCB_CMDN1QL_F_ANALYTICSQUERY (1u << 18u)
 
+/**
+ * Only prepare the statement and cache its plan, without executing it. The
+ * prepared plan is returned as the only row.
+ *
+ * @volatile
+ */
+#define LCB_CMDN1QL_F_PREPARE_ONLY (1u << 19u)
+
 /**
  * Command structure for N1QL queries. Typically an application will use the
  * lcb_N1QLPARAMS structure to populate the #query and #content_type fields.
@@ -114,6 +114,14 @@ static std::uint32_t *get_timeout_field(lcb_INSTANCE *instance, int cmd)
             return &settings->retry_nmv_interval;
         case LCB_CNTL_CONFIG_POLL_INTERVAL:
             return &settings->config_poll_interval;
+        case LCB_CNTL_CONFIG_POLL_FLOOR:
+            return &settings->config_poll_floor;
+        case LCB_CNTL_CIRCUIT_BREAKER_SLEEP_WINDOW:
+            return &settings->circuit_breaker_sleep_window;
+        case LCB_CNTL_CIRCUIT_BREAKER_ROLLING_WINDOW:
+            return &settings->circuit_breaker_rolling_window;
+        case LCB_CNTL_CIRCUIT_BREAKER_CANARY_TIMEOUT:
+            return &settings->circuit_breaker_canary_timeout;
         case LCB_CNTL_TRACING_ORPHANED_QUEUE_FLUSH_INTERVAL:
             return &settings->tracer_orphaned_queue_flush_interval;
         case LCB_CNTL_TRACING_THRESHOLD_QUEUE_FLUSH_INTERVAL:
@@ -245,9 +253,9 @@ HANDLER(tracing_threshold_queue_size_handler){
 HANDLER(config_poll_interval_handler)
 {
     auto *user = reinterpret_cast<std::uint32_t *>(arg);
-    if (mode == LCB_CNTL_SET && *user > 0 && *user < LCB_CONFIG_POLL_INTERVAL_FLOOR) {
+    if (mode == LCB_CNTL_SET && *user > 0 && *user < LCBT_SETTING(instance, config_poll_floor)) {
         lcb_log(LOGARGS(instance, ERROR), "Interval for background poll is too low: %dus (min: %dus)", *user,
-                LCB_CONFIG_POLL_INTERVAL_FLOOR);
+                LCBT_SETTING(instance, config_poll_floor));
         return LCB_ERR_CONTROL_INVALID_ARGUMENT;
     }
     lcb_STATUS rv = timeout_common(mode, instance, cmd, arg);
@@ -700,7 +708,8 @@ HANDLER(network_handler)
         const char *val = reinterpret_cast<const char *>(arg);
         free(LCBT_SETTING(instance, network));
         LCBT_SETTING(instance, network) = nullptr;
-        if (val) {
+        /* "auto" leaves the choice to the heuristic on the first configuration */
+        if (val && strcmp(val, "auto") != 0) {
             LCBT_SETTING(instance, network) = strdup(val);
         }
     } else {
@@ -712,6 +721,26 @@ HANDLER(network_handler)
 
 HANDLER(durable_write_handler){RETURN_GET_SET(int, LCBT_SETTING(instance, enable_durable_write))}
 
+HANDLER(http_compression_handler){RETURN_GET_SET(int, LCBT_SETTING(instance, http_compression))}
+
+HANDLER(config_push_handler){RETURN_GET_SET(int, LCBT_SETTING(instance, enable_config_push))}
+
+HANDLER(circuit_breaker_handler){RETURN_GET_SET(int, LCBT_SETTING(instance, circuit_breaker))}
+
+HANDLER(circuit_breaker_volume_handler)
+{
+    RETURN_GET_SET(std::uint32_t, LCBT_SETTING(instance, circuit_breaker_volume_threshold))
+}
+
+HANDLER(circuit_breaker_error_handler)
+{
+    auto *user = reinterpret_cast<std::uint32_t *>(arg);
+    if (mode == LCB_CNTL_SET && *user > 100) {
+        return LCB_ERR_CONTROL_INVALID_ARGUMENT;
+    }
+    RETURN_GET_SET(std::uint32_t, LCBT_SETTING(instance, circuit_breaker_error_threshold))
+}
+
 HANDLER(unordered_execution_handler)
 {
     RETURN_GET_SET(int, LCBT_SETTING(instance, enable_unordered_execution))
@@ -819,6 +848,15 @@ static ctl_handler handlers[] = {
     timeout_common,                       /* LCB_CNTL_ANALYTICS_TIMEOUT */
     unordered_execution_handler,          /* LCB_CNTL_ENABLE_UNORDERED_EXECUTION */
     timeout_common,                       /* LCB_CNTL_SEARCH_TIMEOUT */
+    http_compression_handler,             /* LCB_CNTL_HTTP_COMPRESSION */
+    config_push_handler,                  /* LCB_CNTL_ENABLE_CONFIG_PUSH */
+    timeout_common,                       /* LCB_CNTL_CONFIG_POLL_FLOOR */
+    circuit_breaker_handler,              /* LCB_CNTL_CIRCUIT_BREAKER */
+    circuit_breaker_volume_handler,       /* LCB_CNTL_CIRCUIT_BREAKER_VOLUME_THRESHOLD */
+    circuit_breaker_error_handler,        /* LCB_CNTL_CIRCUIT_BREAKER_ERROR_THRESHOLD */
+    timeout_common,                       /* LCB_CNTL_CIRCUIT_BREAKER_SLEEP_WINDOW */
+    timeout_common,                       /* LCB_CNTL_CIRCUIT_BREAKER_ROLLING_WINDOW */
+    timeout_common,                       /* LCB_CNTL_CIRCUIT_BREAKER_CANARY_TIMEOUT */
     nullptr
 };
 /* clang-format on */
@@ -1051,6 +1089,15 @@ static cntl_OPCODESTRS stropcode_map[] = {
     {"analytics_timeout", LCB_CNTL_ANALYTICS_TIMEOUT, convert_timevalue},
     {"enable_unordered_execution", LCB_CNTL_ENABLE_UNORDERED_EXECUTION, convert_intbool},
     {"search_timeout", LCB_CNTL_SEARCH_TIMEOUT, convert_timevalue},
+    {"http_compression", LCB_CNTL_HTTP_COMPRESSION, convert_intbool},
+    {"enable_config_push", LCB_CNTL_ENABLE_CONFIG_PUSH, convert_intbool},
+    {"config_poll_floor", LCB_CNTL_CONFIG_POLL_FLOOR, convert_timevalue},
+    {"circuit_breaker", LCB_CNTL_CIRCUIT_BREAKER, convert_intbool},
+    {"circuit_breaker_volume_threshold", LCB_CNTL_CIRCUIT_BREAKER_VOLUME_THRESHOLD, convert_u32},
+    {"circuit_breaker_error_threshold", LCB_CNTL_CIRCUIT_BREAKER_ERROR_THRESHOLD, convert_u32},
+    {"circuit_breaker_sleep_window", LCB_CNTL_CIRCUIT_BREAKER_SLEEP_WINDOW, convert_timevalue},
+    {"circuit_breaker_rolling_window", LCB_CNTL_CIRCUIT_BREAKER_ROLLING_WINDOW, convert_timevalue},
+    {"circuit_breaker_canary_timeout", LCB_CNTL_CIRCUIT_BREAKER_CANARY_TIMEOUT, convert_timevalue},
     {nullptr, -1}};
 
 #define CNTL_NUM_HANDLERS (sizeof(handlers) / sizeof(handlers[0]))
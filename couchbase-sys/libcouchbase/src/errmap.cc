@@ -341,6 +341,22 @@ LIBCOUCHBASE_API lcb_STATUS lcb_errctx_kv_endpoint(const lcb_KEY_VALUE_ERROR_CON
     return LCB_SUCCESS;
 }
 
+LIBCOUCHBASE_API lcb_STATUS lcb_errctx_kv_local_endpoint(const lcb_KEY_VALUE_ERROR_CONTEXT *ctx, const char **endpoint,
+                                                         size_t *endpoint_len)
+{
+    *endpoint = ctx->local_endpoint;
+    *endpoint_len = ctx->local_endpoint_len;
+    return LCB_SUCCESS;
+}
+
+LIBCOUCHBASE_API lcb_STATUS lcb_errctx_kv_connection_id(const lcb_KEY_VALUE_ERROR_CONTEXT *ctx, const char **id,
+                                                        size_t *id_len)
+{
+    *id = ctx->connection_id;
+    *id_len = ctx->connection_id_len;
+    return LCB_SUCCESS;
+}
+
 LIBCOUCHBASE_API lcb_STATUS lcb_errctx_http_rc(const lcb_HTTP_ERROR_CONTEXT *ctx)
 {
     return ctx->rc;
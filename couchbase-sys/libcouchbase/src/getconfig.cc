@@ -37,6 +37,16 @@ static void ext_callback_proxy(mc_PIPELINE *pl, mc_PACKET *req, lcb_STATUS rc, c
             lcb::clconfig::cccp_update(rd->cookie, rc, res->value(), res->vallen(),
                                        server->has_valid_host() ? &server->get_host() : nullptr);
             break;
+        case PROTOCOL_BINARY_CMD_NOOP: {
+            bool was_half_open = server->breaker.get_state() == lcb::CircuitBreaker::HALF_OPEN;
+            server->breaker.canary_done(rc == LCB_SUCCESS, gethrtime());
+            if (was_half_open && server->breaker.get_state() == lcb::CircuitBreaker::CLOSED &&
+                server->has_valid_host()) {
+                lcb_emit_cluster_event(server->get_instance(), LCB_CLUSTER_EVENT_CIRCUIT_CLOSED,
+                                       server->get_host().host, server->get_host().port, 0, LCB_SUCCESS);
+            }
+            break;
+        }
     }
     free(rd);
     req->u_rdata.exdata = nullptr;
@@ -128,3 +138,42 @@ lcb_STATUS lcb_st::select_bucket(const void *cookie_, lcb::Server *server)
     mcreq_sched_leave(&cmdq, 0);
     return LCB_SUCCESS;
 }
+
+lcb_STATUS lcb_st::send_canary(lcb::Server *server)
+{
+    lcb_STATUS err;
+    mc_PACKET *packet;
+    mc_REQDATAEX *rd;
+
+    packet = mcreq_allocate_packet(server);
+    if (!packet) {
+        return LCB_ERR_NO_MEMORY;
+    }
+
+    err = mcreq_reserve_header(server, packet, 24);
+    if (err != LCB_SUCCESS) {
+        mcreq_release_packet(server, packet);
+        return err;
+    }
+
+    rd = reinterpret_cast<mc_REQDATAEX *>(calloc(1, sizeof(*rd)));
+    rd->procs = &procs;
+    rd->start = gethrtime();
+    rd->deadline = rd->start + LCB_US2NS(settings->circuit_breaker_canary_timeout);
+    packet->u_rdata.exdata = rd;
+    packet->flags |= MCREQ_F_REQEXT;
+
+    lcb::MemcachedRequest hdr(PROTOCOL_BINARY_CMD_NOOP, packet->opaque);
+    hdr.opaque(packet->opaque);
+    memcpy(SPAN_BUFFER(&packet->kh_span), hdr.data(), hdr.size());
+
+    if (cmdq.ctxenter) {
+        /* flushed along with the operations being scheduled */
+        mcreq_sched_add(server, packet);
+    } else {
+        mcreq_sched_enter(&cmdq);
+        mcreq_sched_add(server, packet);
+        mcreq_sched_leave(&cmdq, 1);
+    }
+    return LCB_SUCCESS;
+}
@@ -312,6 +312,19 @@ void init_resp(lcb_INSTANCE *instance, mc_PIPELINE *pipeline, const MemcachedRes
         ptr++;
         resp->ctx.endpoint_len = ptr - resp->ctx.endpoint - 1;
     }
+
+    const lcbio_SOCKET *sock = server->connctx ? server->connctx->sock : nullptr;
+    if (sock) {
+        /* the same id the connection announced to the server in its HELLO agent */
+        int written = snprintf(resp->ctx.connection_id, sizeof(resp->ctx.connection_id), "%016" PRIx64 "/%016" PRIx64,
+                               LCBT_SETTING(instance, iid), sock->id);
+        resp->ctx.connection_id_len = written > 0 ? strlen(resp->ctx.connection_id) : 0;
+        if (sock->info) {
+            strncpy(resp->ctx.local_endpoint, sock->info->ep_local, sizeof(resp->ctx.local_endpoint) - 1);
+            resp->ctx.local_endpoint[sizeof(resp->ctx.local_endpoint) - 1] = '\0';
+            resp->ctx.local_endpoint_len = strlen(resp->ctx.local_endpoint);
+        }
+    }
 }
 
 /**
@@ -1007,14 +1020,44 @@ static void H_select_bucket(mc_PIPELINE *pipeline, mc_PACKET *request, Memcached
     }
 }
 
-static void record_metrics(mc_PIPELINE *pipeline, mc_PACKET *req, MemcachedResponse *)
+static void notify_kvdispatch(mc_PIPELINE *pipeline, mc_PACKET *req, MemcachedResponse *res, lcb_STATUS immerr)
+{
+    lcb_INSTANCE *instance = get_instance(pipeline);
+    lcb_KVDISPATCH_INFO info{};
+    protocol_binary_request_header hdr;
+
+    /* Packets failed locally come with a synthesized response which has no magic */
+    bool received = res->magic() != 0;
+
+    mcreq_read_hdr(req, &hdr);
+    info.opcode = hdr.request.opcode;
+    info.status = received ? res->status() : 0;
+    info.rc = immerr;
+    info.opaque = req->opaque;
+    info.vbucket = ntohs(hdr.request.vbucket);
+    mcreq_get_key(instance, req, &info.key, &info.nkey);
+    info.cas = res->cas();
+    info.request_size = mcreq_get_size(req);
+    info.response_size = received ? res->bodylen() : 0;
+    info.latency_ns = MCREQ_PKT_RDATA(req)->dispatch - MCREQ_PKT_RDATA(req)->start;
+    info.server_duration_us = received ? res->duration() : 0;
+
+    auto *server = static_cast<lcb::Server *>(pipeline);
+    if (server->has_valid_host()) {
+        info.host = server->get_host().host;
+        info.port = server->get_host().port;
+    }
+    instance->callbacks.kvdispatch(instance, &info);
+}
+
+static void record_metrics(mc_PIPELINE *pipeline, mc_PACKET *req, MemcachedResponse *res, lcb_STATUS immerr)
 {
     lcb_INSTANCE *instance = get_instance(pipeline);
     if (
 #ifdef HAVE_DTRACE
         1
 #else
-        instance->kv_timings
+        instance->kv_timings || instance->callbacks.kvdispatch
 #endif
     ) {
         MCREQ_PKT_RDATA(req)->dispatch = gethrtime();
@@ -1022,6 +1065,9 @@ static void record_metrics(mc_PIPELINE *pipeline, mc_PACKET *req, MemcachedRespo
     if (instance->kv_timings) {
         lcb_histogram_record(instance->kv_timings, MCREQ_PKT_RDATA(req)->dispatch - MCREQ_PKT_RDATA(req)->start);
     }
+    if (instance->callbacks.kvdispatch) {
+        notify_kvdispatch(pipeline, req, res, immerr);
+    }
 }
 
 static void dispatch_ufwd_error(mc_PIPELINE *pipeline, mc_PACKET *req, lcb_STATUS immerr)
@@ -1035,7 +1081,7 @@ static void dispatch_ufwd_error(mc_PIPELINE *pipeline, mc_PACKET *req, lcb_STATU
 
 int mcreq_dispatch_response(mc_PIPELINE *pipeline, mc_PACKET *req, MemcachedResponse *res, lcb_STATUS immerr)
 {
-    record_metrics(pipeline, req, res);
+    record_metrics(pipeline, req, res, immerr);
 
     if (req->flags & MCREQ_F_UFWD) {
         dispatch_ufwd_error(pipeline, req, immerr);
@@ -28,6 +28,9 @@
 #include <string>
 #include <vector>
 #include <set>
+#ifdef LCB_USE_ZLIB
+#include <zlib.h>
+#endif
 
 namespace lcb
 {
@@ -179,6 +182,18 @@ struct Request {
     inline unsigned handle_parse_chunked(const char *buf, unsigned nbuf);
     inline void assign_response_headers(const lcb::htparse::Response &);
 
+    /**
+     * Prepares the decompression of the response body, if the response is gzip or deflate
+     * encoded. Returns false if the decompression could not be set up.
+     */
+    bool setup_inflate(const lcb::htparse::Response &);
+
+    /**
+     * Decompresses a chunk of the response body and appends it to `out`.
+     * Returns false if the chunk is corrupt.
+     */
+    bool inflate_body(const char *buf, unsigned nbuf, std::string &out);
+
     /**
      * Called when a redirect has happened. pending_redirect must not be empty.
      * This will transfer control to the redirect call. If there is an error
@@ -337,6 +352,16 @@ struct Request {
     const uint32_t user_timeout;
 
     hrtime_t start; /**< Start time */
+
+    /** Whether the response body is compressed and gets decompressed while parsing */
+    bool inflating;
+    /** Size of the response body as received from the network */
+    lcb_U64 body_wire_bytes;
+    /** Size of the response body after decompression */
+    lcb_U64 body_raw_bytes;
+#ifdef LCB_USE_ZLIB
+    z_stream zstream;
+#endif
     lcbio_SERVICE service;
 };
 
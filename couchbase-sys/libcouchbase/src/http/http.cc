@@ -60,6 +60,13 @@ LIBCOUCHBASE_API lcb_STATUS lcb_resphttp_headers(const lcb_RESPHTTP *resp, const
     return LCB_SUCCESS;
 }
 
+LIBCOUCHBASE_API lcb_STATUS lcb_resphttp_body_sizes(const lcb_RESPHTTP *resp, lcb_U64 *wire_bytes, lcb_U64 *raw_bytes)
+{
+    *wire_bytes = resp->body_wire_bytes;
+    *raw_bytes = resp->body_raw_bytes;
+    return LCB_SUCCESS;
+}
+
 LIBCOUCHBASE_API lcb_STATUS lcb_resphttp_handle(const lcb_RESPHTTP *resp, lcb_HTTP_HANDLE **handle)
 {
     *handle = resp->_htreq;
@@ -187,8 +194,9 @@ using namespace lcb::http;
 static const char *method_strings[] = {
     "GET ",   /* LCB_HTTP_METHOD_GET */
     "POST ",  /* LCB_HTTP_METHOD_POST */
-    "PUT ",   /* LCB_HTTP_METHOD_PUT */
-    "DELETE " /* LCB_HTTP_METHOD_DELETE */
+    "PUT ",    /* LCB_HTTP_METHOD_PUT */
+    "DELETE ", /* LCB_HTTP_METHOD_DELETE */
+    "PATCH "   /* LCB_HTTP_METHOD_PATCH */
 };
 
 void Request::decref()
@@ -302,6 +310,8 @@ void Request::init_resp(lcb_RESPHTTP *res)
     res->ctx.response_code = htres.status;
     res->ctx.endpoint = peer.c_str();
     res->ctx.endpoint_len = peer.size();
+    res->body_wire_bytes = body_wire_bytes;
+    res->body_raw_bytes = body_raw_bytes;
 }
 
 void Request::finish(lcb_STATUS error)
@@ -542,6 +552,58 @@ static lcbvb_SVCTYPE httype2svctype(unsigned httype)
     }
 }
 
+static std::string quarantine_key(lcbvb_SVCTYPE svc, const char *hostname)
+{
+    std::string host(hostname);
+    if (host.size() > 2 && host.front() == '[' && host.back() == ']') {
+        host = host.substr(1, host.size() - 2);
+    }
+    return std::to_string(svc) + "/" + host;
+}
+
+bool lcb_st::is_quarantined(lcbvb_SVCTYPE svc, const char *hostname) const
+{
+    if (quarantined_nodes == nullptr || quarantined_nodes->empty() || hostname == nullptr) {
+        return false;
+    }
+    return quarantined_nodes->count(quarantine_key(svc, hostname)) != 0;
+}
+
+LIBCOUCHBASE_API
+lcb_STATUS lcb_set_node_quarantine(lcb_INSTANCE *instance, lcb_PING_SERVICE service, const char *hostname,
+                                   int quarantined)
+{
+    lcbvb_SVCTYPE svc;
+    switch (service) {
+        case LCB_PING_SERVICE_VIEWS:
+            svc = LCBVB_SVCTYPE_VIEWS;
+            break;
+        case LCB_PING_SERVICE_QUERY:
+            svc = LCBVB_SVCTYPE_QUERY;
+            break;
+        case LCB_PING_SERVICE_SEARCH:
+            svc = LCBVB_SVCTYPE_SEARCH;
+            break;
+        case LCB_PING_SERVICE_ANALYTICS:
+            svc = LCBVB_SVCTYPE_ANALYTICS;
+            break;
+        default:
+            return LCB_ERR_INVALID_ARGUMENT;
+    }
+    if (hostname == nullptr || *hostname == '\0') {
+        return LCB_ERR_INVALID_ARGUMENT;
+    }
+    if (instance->quarantined_nodes == nullptr) {
+        instance->quarantined_nodes = new std::set<std::string>();
+    }
+    if (quarantined) {
+        instance->quarantined_nodes->insert(quarantine_key(svc, hostname));
+    } else {
+        instance->quarantined_nodes->erase(quarantine_key(svc, hostname));
+    }
+    return LCB_SUCCESS;
+}
+
 const char *Request::get_api_node(lcb_STATUS &rc)
 {
     if (!is_data_request()) {
@@ -564,7 +626,17 @@ const char *Request::get_api_node(lcb_STATUS &rc)
     }
     used_nodes.resize(LCBVB_NSERVERS(vbc));
 
-    int ix = lcbvb_get_randhost_ex(vbc, svc, mode, &used_nodes[0]);
+    /* Quarantined nodes are skipped like the ones already tried, unless there is no other */
+    std::vector<int> excluded(used_nodes);
+    for (size_t ii = 0; ii < excluded.size(); ii++) {
+        if (instance->is_quarantined(svc, lcbvb_get_hostname(vbc, ii))) {
+            excluded[ii] = 1;
+        }
+    }
+    int ix = lcbvb_get_randhost_ex(vbc, svc, mode, &excluded[0]);
+    if (ix < 0) {
+        ix = lcbvb_get_randhost_ex(vbc, svc, mode, &used_nodes[0]);
+    }
     if (ix < 0) {
         rc = LCB_ERR_UNSUPPORTED_OPERATION;
         return nullptr;
@@ -580,7 +652,7 @@ lcb_STATUS Request::setup_inputs(const lcb_CMDHTTP *cmd)
     size_t nbase = 0;
     lcb_STATUS rc = LCB_SUCCESS;
 
-    if (method > LCB_HTTP_METHOD_MAX) {
+    if (method >= LCB_HTTP_METHOD_MAX) {
         return LCB_ERR_INVALID_ARGUMENT;
     }
 
@@ -597,12 +669,8 @@ lcb_STATUS Request::setup_inputs(const lcb_CMDHTTP *cmd)
         }
     } else {
         if (cmd->host) {
-            if (reqtype == LCB_HTTP_TYPE_ANALYTICS || reqtype == LCB_HTTP_TYPE_PING) {
-                /* might be a deferred CBAS URL or PING */
-                base = cmd->host;
-            } else {
-                return LCB_ERR_INVALID_ARGUMENT;
-            }
+            /* an explicit node of the service, e.g. a deferred CBAS URL, PING or a targeted request */
+            base = cmd->host;
         }
         if (base == nullptr) {
             base = get_api_node(rc);
@@ -622,7 +690,8 @@ lcb_STATUS Request::setup_inputs(const lcb_CMDHTTP *cmd)
             password.clear();
         } else if (username.empty() && password.empty()) {
             const Authenticator &auth = *LCBT_SETTING(instance, auth);
-            if (reqtype == LCB_HTTP_TYPE_MANAGEMENT) {
+            /* dynamic authenticators may hand out different credentials per node and over time */
+            if (reqtype == LCB_HTTP_TYPE_MANAGEMENT && auth.mode() != LCBAUTH_MODE_DYNAMIC) {
                 username = auth.username();
                 password = auth.password();
             } else {
@@ -664,6 +733,12 @@ lcb_STATUS Request::setup_inputs(const lcb_CMDHTTP *cmd)
     }
 
     add_header("Accept", "application/json");
+#ifdef LCB_USE_ZLIB
+    if (LCBT_SETTING(instance, http_compression) &&
+        (reqtype == LCB_HTTP_TYPE_QUERY || reqtype == LCB_HTTP_TYPE_SEARCH || reqtype == LCB_HTTP_TYPE_ANALYTICS)) {
+        add_header("Accept-Encoding", "gzip, deflate");
+    }
+#endif
     if (!username.empty()) {
         char auth[256];
         std::string upassbuf;
@@ -691,7 +766,8 @@ Request::Request(lcb_INSTANCE *instance_, const void *cookie, const lcb_CMDHTTP
       chunked(cmd->cmdflags & LCB_CMDHTTP_F_STREAM), paused(false), command_cookie(cookie), refcount(1), redircount(0),
       passed_data(false), last_vbcrev(-1), reqtype(cmd->type), status(ONGOING),
       callback(lcb_find_callback(instance, LCB_CALLBACK_HTTP)), io(instance->iotable), ioctx(nullptr), timer(nullptr),
-      parser(nullptr), user_timeout(cmd->cmdflags & LCB_CMDHTTP_F_CASTMO ? cmd->cas : 0)
+      parser(nullptr), user_timeout(cmd->cmdflags & LCB_CMDHTTP_F_CASTMO ? cmd->cas : 0), inflating(false),
+      body_wire_bytes(0), body_raw_bytes(0)
 {
     memset(&creq, 0, sizeof creq);
 }
@@ -706,6 +782,63 @@ Request::~Request()
         lcbio_timer_destroy(timer);
         timer = nullptr;
     }
+
+#ifdef LCB_USE_ZLIB
+    if (inflating) {
+        inflateEnd(&zstream);
+    }
+#endif
+}
+
+bool Request::setup_inflate(const lcb::htparse::Response &res)
+{
+#ifdef LCB_USE_ZLIB
+    if (inflating) {
+        // A previous attempt of this request (e.g. before a redirect) was compressed
+        inflateEnd(&zstream);
+        inflating = false;
+    }
+    const char *encoding = res.get_header_value("Content-Encoding");
+    if (encoding == nullptr || (strcmp(encoding, "gzip") != 0 && strcmp(encoding, "deflate") != 0)) {
+        return true;
+    }
+    memset(&zstream, 0, sizeof zstream);
+    // 15 bits of window size, +32 to detect the gzip or zlib header automatically
+    if (inflateInit2(&zstream, 15 + 32) != Z_OK) {
+        return false;
+    }
+    inflating = true;
+#else
+    (void)res;
+#endif
+    return true;
+}
+
+bool Request::inflate_body(const char *buf, unsigned nbuf, std::string &out)
+{
+#ifdef LCB_USE_ZLIB
+    char chunk[16384];
+    zstream.next_in = reinterpret_cast<Bytef *>(const_cast<char *>(buf));
+    zstream.avail_in = nbuf;
+    do {
+        zstream.next_out = reinterpret_cast<Bytef *>(chunk);
+        zstream.avail_out = sizeof(chunk);
+        int rv = inflate(&zstream, Z_NO_FLUSH);
+        if (rv != Z_OK && rv != Z_STREAM_END && rv != Z_BUF_ERROR) {
+            return false;
+        }
+        out.append(chunk, sizeof(chunk) - zstream.avail_out);
+        if (rv == Z_STREAM_END) {
+            break;
+        }
+    } while (zstream.avail_out == 0);
+    return true;
+#else
+    (void)buf;
+    (void)nbuf;
+    (void)out;
+    return false;
+#endif
 }
 
 uint32_t Request::timeout() const
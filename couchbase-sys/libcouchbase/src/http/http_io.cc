@@ -58,6 +58,11 @@ unsigned Request::handle_parse_chunked(const char *buf, unsigned nbuf)
         /* Got headers now for the first time */
         if (diff & Parser::S_HEADER) {
             assign_response_headers(res);
+            if (!setup_inflate(res)) {
+                lcb_log(LOGARGS(this, ERR), LOGFMT "Could not set up the decompression of the HTTP response",
+                        LOGID(this));
+                return Parser::S_ERROR;
+            }
             if (res.status >= 300 && res.status <= 400) {
                 const char *redir = res.get_header_value("Location");
                 if (redir != nullptr) {
@@ -72,6 +77,20 @@ unsigned Request::handle_parse_chunked(const char *buf, unsigned nbuf)
             return parse_state;
         }
 
+        std::string inflated;
+        if (nbody) {
+            body_wire_bytes += nbody;
+            if (inflating) {
+                if (!inflate_body(rbody, nbody, inflated)) {
+                    lcb_log(LOGARGS(this, ERR), LOGFMT "Could not decompress the HTTP response body", LOGID(this));
+                    return Parser::S_ERROR;
+                }
+                rbody = inflated.c_str();
+                nbody = inflated.size();
+            }
+            body_raw_bytes += nbody;
+        }
+
         if (nbody) {
             if (chunked) {
                 lcb_RESPHTTP htresp{};
@@ -201,6 +201,7 @@ lcb_STATUS lcb_st::process_dns_srv(Connspec &spec)
         }
     }
 
+    dnssrv_name = new std::string(host.hostname);
     spec.clear_hosts();
     for (size_t ii = 0; ii < hl->size(); ++ii) {
         const lcb_host_t &src = (*hl)[ii];
@@ -225,6 +226,54 @@ lcb_STATUS lcb_st::process_dns_srv(Connspec &spec)
     return LCB_SUCCESS;
 }
 
+/**
+ * Hands the nodes found by looking up the DNS SRV records the instance was bootstrapped from
+ * again to the configuration providers. Used when none of the known nodes is reachable
+ * anymore, for example because the cluster moved to different hosts. The known nodes are
+ * kept if the records list no usable node.
+ */
+bool lcb_st::apply_dns_srv(const Hostlist &hosts)
+{
+    std::vector< std::pair< std::string, int > > found;
+    for (size_t ii = 0; ii < hosts.size(); ++ii) {
+        const lcb_host_t &src = hosts[ii];
+        char *end = nullptr;
+        errno = 0;
+        long val = std::strtol(src.port, &end, 10);
+        if (errno == ERANGE || end == src.port) {
+            continue;
+        }
+        found.emplace_back(src.host, static_cast<int>(val));
+    }
+    if (found.empty()) {
+        lcb_log(LOGARGS(this, WARN), "DNS SRV records for %s list no usable node, keeping the known nodes",
+                dnssrv_name->c_str());
+        return false;
+    }
+
+    /* The records only list the key/value ports, the HTTP nodes use the default port */
+    int defl_http = (settings->sslopts & LCB_SSL_ENABLED) ? LCB_CONFIG_HTTP_SSL_PORT : LCB_CONFIG_HTTP_PORT;
+    mc_nodes->clear();
+    ht_nodes->clear();
+    for (const auto &node : found) {
+        add_bs_host(node.first.c_str(), node.second, LCB_CONFIG_TRANSPORT_CCCP);
+        add_bs_host(node.first.c_str(), defl_http, LCB_CONFIG_TRANSPORT_HTTP);
+    }
+
+    bool configured = false;
+    lcb::clconfig::Provider *cccp = confmon->get_provider(lcb::clconfig::CLCONFIG_CCCP);
+    if (cccp != nullptr && cccp->enabled) {
+        cccp->configure_nodes(*mc_nodes);
+        configured = true;
+    }
+    lcb::clconfig::Provider *http = confmon->get_provider(lcb::clconfig::CLCONFIG_HTTP);
+    if (http != nullptr && http->enabled) {
+        http->configure_nodes(*ht_nodes);
+        configured = true;
+    }
+    return configured;
+}
+
 static lcb_STATUS init_providers(lcb_INSTANCE *obj, const Connspec &spec)
 {
     using namespace lcb::clconfig;
@@ -696,6 +745,10 @@ void lcb_destroy(lcb_INSTANCE *instance)
     }
     delete instance->crypto;
     instance->crypto = nullptr;
+    delete instance->dnssrv_name;
+    instance->dnssrv_name = nullptr;
+    delete instance->quarantined_nodes;
+    instance->quarantined_nodes = nullptr;
 
     delete[] instance->dcpinfo;
     memset(instance, 0xff, sizeof(*instance));
@@ -85,11 +85,14 @@ struct lcb_callback_st {
     lcb_pktfwd_callback pktfwd;
     lcb_pktflushed_callback pktflushed;
     lcb_open_callback open;
+    lcb_kvdispatch_callback kvdispatch;
+    lcb_cluster_event_callback cluster_event;
 };
 
 struct lcb_GUESSVB_st;
 
 #ifdef __cplusplus
+#include <set>
 #include <string>
 typedef std::string *lcb_pSCRATCHBUF;
 typedef lcb::RetryQueue lcb_RETRYQ;
@@ -133,6 +136,10 @@ struct lcb_st {
 #ifdef __cplusplus
     typedef std::map<std::string, lcbcrypto_PROVIDER *> lcb_ProviderMap;
     lcb_ProviderMap *crypto;
+    std::string *dnssrv_name; /**< The name the bootstrap nodes were found for via DNS SRV, if any */
+    /** Nodes HTTP requests avoid, as "<lcbvb_SVCTYPE>/<hostname>", see lcb_set_node_quarantine */
+    std::set<std::string> *quarantined_nodes;
+    bool is_quarantined(lcbvb_SVCTYPE svc, const char *hostname) const;
     lcb_settings *getSettings()
     {
         return settings;
@@ -145,6 +152,7 @@ struct lcb_st {
     inline void add_bs_host(const lcb::Spechost &host, int defl_http, int defl_cccp);
     inline lcb_STATUS process_dns_srv(lcb::Connspec &spec);
     inline void populate_nodes(const lcb::Connspec &);
+    bool apply_dns_srv(const lcb::Hostlist &hosts);
     lcb::Server *get_server(size_t index) const
     {
         return static_cast<lcb::Server *>(cmdq.pipelines[index]);
@@ -152,6 +160,8 @@ struct lcb_st {
     lcb::Server *find_server(const lcb_host_t &host) const;
     lcb_STATUS request_config(const void *cookie, lcb::Server *server);
     lcb_STATUS select_bucket(const void *cookie, lcb::Server *server);
+    /** Sends the NOOP probing the node once its circuit breaker is open */
+    lcb_STATUS send_canary(lcb::Server *server);
 
     /**
      * @brief Request that the handle update its configuration.
@@ -211,6 +221,9 @@ LCB_INTERNAL_API
 void lcb_maybe_breakout(lcb_INSTANCE *instance);
 
 void lcb_update_vbconfig(lcb_INSTANCE *instance, lcb_pCONFIGINFO config);
+/** Passes the event to the cluster event callback, if there is one */
+void lcb_emit_cluster_event(lcb_INSTANCE *instance, lcb_CLUSTER_EVENT_TYPE type, const char *host, const char *port,
+                            int64_t config_revision, lcb_STATUS rc);
 
 lcb_STATUS lcb_iops_cntl_handler(int mode, lcb_INSTANCE *instance, int cmd, void *arg);
 
@@ -254,8 +267,14 @@ LCB_INTERNAL_API void lcb_loop_unref(lcb_INSTANCE *instance);
         lcb_sched_leave(o);                                                                                            \
     }
 
+/**
+ * Like mcreq_sched_add(), but hands the packet over to the retry queue instead if the circuit
+ * breaker of the server is open
+ */
+void lcb_sched_add(lcb_INSTANCE *instance, mc_PIPELINE *pl, mc_PACKET *pkt);
+
 #define LCB_SCHED_ADD(instance, pl, pkt)                                                                               \
-    mcreq_sched_add(pl, pkt);                                                                                          \
+    lcb_sched_add(instance, pl, pkt);                                                                                  \
     MAYBE_SCHEDLEAVE(instance)
 
 void lcb_vbguess_newconfig(lcb_INSTANCE *instance, lcbvb_CONFIG *cfg, struct lcb_GUESSVB_st *guesses);
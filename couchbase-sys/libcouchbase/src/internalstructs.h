@@ -47,6 +47,10 @@ struct lcb_KEY_VALUE_ERROR_CONTEXT_ {
     size_t context_len;
     char endpoint[NI_MAXHOST + NI_MAXSERV + 4];
     size_t endpoint_len;
+    char local_endpoint[NI_MAXHOST + NI_MAXSERV + 2];
+    size_t local_endpoint_len;
+    char connection_id[34];
+    size_t connection_id_len;
 };
 
 /**
@@ -167,6 +171,12 @@ struct lcb_CMDBASE_ {
 
 #define LCB_CMD_F_CLONE (1u << 2u)
 
+/**
+ * If this flag is set, lcb_CMDBASE::cid has been set explicitly and is used as is, without
+ * resolving the scope and collection names through the collection cache.
+ */
+#define LCB_CMD_F_EXPLICIT_CID (1u << 3u)
+
 /**@}*/
 
 /**
@@ -906,6 +916,9 @@ struct lcb_CMDSTORE_ {
      */
     lcb_STORE_OPERATION operation;
 
+    /** Keep the current expiry of the document, only valid for upsert and replace */
+    int preserve_expiry;
+
     uint8_t durability_mode;
 
     union {
@@ -1140,6 +1153,10 @@ struct lcb_RESPHTTP_ {
 
     /**@internal*/
     lcb_HTTP_HANDLE *_htreq;
+
+    /** Size of the response body so far, as received and after decompression */
+    lcb_U64 body_wire_bytes;
+    lcb_U64 body_raw_bytes;
 };
 
 /**@ingroup lcb-public-api
@@ -1356,6 +1373,12 @@ typedef struct {
 
 #define LCB_CMDSUBDOC_F_CREATE_AS_DELETED (1u << 19u)
 
+/**
+ * Send the lookup to the replica selected by lcb_CMDSUBDOC::replica_index, with
+ * the replica read document flag set
+ */
+#define LCB_CMDSUBDOC_F_REPLICA_READ (1u << 20u)
+
 struct lcb_SUBDOCSPECS_ {
     uint32_t options;
 
@@ -1399,6 +1422,11 @@ struct lcb_CMDSUBDOC_ {
      * derived from the _first_ command issued.
      */
     lcb_U32 multimode;
+    /**
+     * Index of the replica to read from, starting at 0. Only used if
+     * ::LCB_CMDSUBDOC_F_REPLICA_READ is set.
+     */
+    int replica_index;
 
     LCB_CMD_DURABILITY;
 };
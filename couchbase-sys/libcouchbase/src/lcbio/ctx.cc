@@ -20,6 +20,7 @@
 #include "timer-ng.h"
 #include "ioutils.h"
 #include <lcbio/ssl.h>
+#include "strcodecs/strcodecs.h"
 
 #define CTX_FD(ctx) (ctx)->fd
 #define CTX_SD(ctx) (ctx)->sd
@@ -359,7 +360,7 @@ static void Cr_handler(lcb_sockdata_t *sd, lcb_ssize_t nr, void *arg)
                 {
                     char *b64 = nullptr;
                     lcb_SIZE nb64 = 0;
-                    char *buf = calloc(total, sizeof(char));
+                    char *buf = static_cast<char *>(calloc(total, sizeof(char)));
                     rdb_copyread(&ctx->ior, buf, total);
                     lcb_base64_encode2(buf, total, &b64, &nb64);
                     lcb_log(LOGARGS(ctx, TRACE), CTX_LOGFMT "pkt,rcv: size=%d, %.*s", CTX_LOGID(ctx), (int)nb64,
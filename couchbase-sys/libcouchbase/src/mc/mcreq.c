@@ -490,12 +490,37 @@ uint16_t mcreq_get_key_size(protocol_binary_request_header *hdr)
     }
 }
 
+static lcb_STATUS basic_packet_init(const lcb_CMDBASE *cmd, protocol_binary_request_header *req, lcb_uint8_t extlen,
+                                    lcb_uint8_t ffextlen, int vb, mc_PACKET **packet, mc_PIPELINE *pipeline)
+{
+    uint16_t nkey;
+
+    *packet = mcreq_allocate_packet(pipeline);
+    if (*packet == NULL) {
+        return LCB_ERR_NO_MEMORY;
+    }
+
+    mcreq_reserve_key(pipeline, *packet, sizeof(*req) + extlen + ffextlen, &cmd->key, cmd->cid);
+
+    nkey = (*packet)->kh_span.size - PKT_HDRSIZE(*packet);
+
+    if (ffextlen) {
+        req->request.magic = PROTOCOL_BINARY_AREQ;
+        req->request.keylen = ((0xff & nkey) << 8) | ffextlen;
+    } else {
+        req->request.magic = PROTOCOL_BINARY_REQ;
+        req->request.keylen = htons(nkey);
+    }
+    req->request.vbucket = htons(vb);
+    req->request.extlen = extlen;
+    return LCB_SUCCESS;
+}
+
 lcb_STATUS mcreq_basic_packet(mc_CMDQUEUE *queue, const lcb_CMDBASE *cmd, protocol_binary_request_header *req,
                               lcb_uint8_t extlen, lcb_uint8_t ffextlen, mc_PACKET **packet, mc_PIPELINE **pipeline,
                               int options)
 {
     int vb, srvix;
-    uint16_t nkey;
 
     if (!queue->config) {
         return LCB_ERR_NO_CONFIGURATION;
@@ -516,25 +541,29 @@ lcb_STATUS mcreq_basic_packet(mc_CMDQUEUE *queue, const lcb_CMDBASE *cmd, protoc
         }
     }
 
-    *packet = mcreq_allocate_packet(*pipeline);
-    if (*packet == NULL) {
-        return LCB_ERR_NO_MEMORY;
-    }
+    return basic_packet_init(cmd, req, extlen, ffextlen, vb, packet, *pipeline);
+}
 
-    mcreq_reserve_key(*pipeline, *packet, sizeof(*req) + extlen + ffextlen, &cmd->key, cmd->cid);
+lcb_STATUS mcreq_replica_packet(mc_CMDQUEUE *queue, const lcb_CMDBASE *cmd, protocol_binary_request_header *req,
+                                lcb_uint8_t extlen, int replica, mc_PACKET **packet, mc_PIPELINE **pipeline)
+{
+    int vb, srvix;
 
-    nkey = (*packet)->kh_span.size - PKT_HDRSIZE(*packet);
+    if (!queue->config) {
+        return LCB_ERR_NO_CONFIGURATION;
+    }
+    if (!cmd) {
+        return LCB_ERR_INVALID_ARGUMENT;
+    }
 
-    if (ffextlen) {
-        req->request.magic = PROTOCOL_BINARY_AREQ;
-        req->request.keylen = ((0xff & nkey) << 8) | ffextlen;
-    } else {
-        req->request.magic = PROTOCOL_BINARY_REQ;
-        req->request.keylen = htons(nkey);
+    mcreq_map_key(queue, &cmd->key, sizeof(*req) + extlen, &vb, &srvix);
+    srvix = lcbvb_vbreplica(queue->config, vb, replica);
+    if (srvix < 0 || srvix >= (int)queue->npipelines) {
+        return LCB_ERR_NO_MATCHING_SERVER;
     }
-    req->request.vbucket = htons(vb);
-    req->request.extlen = extlen;
-    return LCB_SUCCESS;
+    *pipeline = queue->pipelines[srvix];
+
+    return basic_packet_init(cmd, req, extlen, 0, vb, packet, *pipeline);
 }
 
 void mcreq_set_cid(mc_PIPELINE *pipeline, mc_PACKET *packet, uint32_t cid)
@@ -662,6 +662,17 @@ lcb_STATUS mcreq_basic_packet(mc_CMDQUEUE *queue, const lcb_CMDBASE *cmd, protoc
                               uint8_t extlen, uint8_t ffextlen, mc_PACKET **packet, mc_PIPELINE **pipeline,
                               int options);
 
+/**
+ * Like mcreq_basic_packet(), but targets one of the replicas of the key's vBucket
+ * instead of its master.
+ *
+ * @param replica the index of the replica, starting at 0
+ * @return LCB_ERR_NO_MATCHING_SERVER if the vBucket has no such replica, or it
+ * is not part of the current configuration
+ */
+lcb_STATUS mcreq_replica_packet(mc_CMDQUEUE *queue, const lcb_CMDBASE *cmd, protocol_binary_request_header *req,
+                                uint8_t extlen, int replica, mc_PACKET **packet, mc_PIPELINE **pipeline);
+
 /**
  * @brief Get the key from a packet
  * @param[in] packet The packet from which to retrieve the key
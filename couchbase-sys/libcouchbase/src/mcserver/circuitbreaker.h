@@ -0,0 +1,152 @@
+/* -*- Mode: C; tab-width: 4; c-basic-offset: 4; indent-tabs-mode: nil -*- */
+/*
+ *     Copyright 2020 Couchbase, Inc.
+ *
+ *   Licensed under the Apache License, Version 2.0 (the "License");
+ *   you may not use this file except in compliance with the License.
+ *   You may obtain a copy of the License at
+ *
+ *       http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *   Unless required by applicable law or agreed to in writing, software
+ *   distributed under the License is distributed on an "AS IS" BASIS,
+ *   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *   See the License for the specific language governing permissions and
+ *   limitations under the License.
+ */
+
+#ifndef LCB_CIRCUITBREAKER_H
+#define LCB_CIRCUITBREAKER_H
+
+#include "settings.h"
+
+#ifdef __cplusplus
+namespace lcb
+{
+
+/**
+ * Circuit breaker of a single data node, see LCB_CNTL_CIRCUIT_BREAKER.
+ *
+ * Only timeouts count as failures, since every other error is a response of the node and so
+ * proves that it is alive. Nothing is tracked while the breaker is disabled.
+ */
+class CircuitBreaker
+{
+  public:
+    enum State { CLOSED, OPEN, HALF_OPEN };
+
+    explicit CircuitBreaker(const lcb_settings *settings_) : settings(settings_) {}
+
+    bool enabled() const
+    {
+        return settings != nullptr && settings->circuit_breaker;
+    }
+
+    /** Whether requests may be dispatched to the node */
+    bool allows_request() const
+    {
+        return !enabled() || state == CLOSED;
+    }
+
+    /**
+     * Whether the canary has to be sent now. Moves an open breaker to half open once the
+     * sleep window passed, so only a single canary is in flight.
+     */
+    bool should_send_canary(hrtime_t now)
+    {
+        if (!enabled() || state != OPEN) {
+            return false;
+        }
+        if (now < opened_at + LCB_US2NS(settings->circuit_breaker_sleep_window)) {
+            return false;
+        }
+        state = HALF_OPEN;
+        return true;
+    }
+
+    void mark_success(hrtime_t now)
+    {
+        if (!enabled() || state != CLOSED) {
+            return;
+        }
+        roll(now);
+        total++;
+    }
+
+    void mark_failure(hrtime_t now)
+    {
+        if (!enabled() || state != CLOSED) {
+            return;
+        }
+        roll(now);
+        total++;
+        failed++;
+        if (total >= settings->circuit_breaker_volume_threshold &&
+            failed * 100 >= total * settings->circuit_breaker_error_threshold) {
+            open(now);
+        }
+    }
+
+    /** Completes the canary sent after should_send_canary() */
+    void canary_done(bool success, hrtime_t now)
+    {
+        if (state != HALF_OPEN) {
+            return;
+        }
+        if (success) {
+            state = CLOSED;
+            reset(now);
+        } else {
+            open(now);
+        }
+    }
+
+    State get_state() const
+    {
+        return state;
+    }
+
+    const char *state_name() const
+    {
+        switch (state) {
+            case OPEN:
+                return "open";
+            case HALF_OPEN:
+                return "half_open";
+            default:
+                return "closed";
+        }
+    }
+
+  private:
+    void open(hrtime_t now)
+    {
+        state = OPEN;
+        opened_at = now;
+    }
+
+    /** Starts counting from scratch once the rolling window passed */
+    void roll(hrtime_t now)
+    {
+        if (now >= window_start + LCB_US2NS(settings->circuit_breaker_rolling_window)) {
+            reset(now);
+        }
+    }
+
+    void reset(hrtime_t now)
+    {
+        window_start = now;
+        total = 0;
+        failed = 0;
+    }
+
+    const lcb_settings *settings;
+    State state{CLOSED};
+    hrtime_t opened_at{0};
+    hrtime_t window_start{0};
+    lcb_U32 total{0};
+    lcb_U32 failed{0};
+};
+} // namespace lcb
+#endif /* __cplusplus */
+#endif /* LCB_CIRCUITBREAKER_H */
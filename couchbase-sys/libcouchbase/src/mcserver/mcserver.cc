@@ -30,6 +30,7 @@
 
 #include "sllist.h"
 #include "sllist-inl.h"
+#include "strcodecs/strcodecs.h"
 
 #define LOGARGS(c, lvl) (c)->settings, "server", LCB_LOG_##lvl, __FILE__, __LINE__
 #define LOGARGS_T(lvl) LOGARGS(this, lvl)
@@ -122,6 +123,49 @@ void lcb_sched_flush(lcb_INSTANCE *instance)
     }
 }
 
+/**
+ * Invoked when the server pushes a request to us, which it only does after the DUPLEX
+ * feature has been negotiated. The only request we ask for is the clustermap change
+ * notification: it either carries the new configuration, or (in its brief form) only the
+ * epoch and revision of it, in which case the configuration is fetched.
+ */
+void Server::handle_server_request(const MemcachedResponse &req)
+{
+    if (req.opcode() != PROTOCOL_BINARY_SCMD_CLUSTERMAP_CHANGE_NOTIFICATION) {
+        lcb_log(LOGARGS_T(DEBUG), LOGFMT "Ignoring unknown server request (OP=0x%x)", LOGID_T(), req.opcode());
+        return;
+    }
+
+    lcb::clconfig::Provider *cccp = instance->confmon->get_provider(lcb::clconfig::CLCONFIG_CCCP);
+    if (req.vallen() && cccp->enabled) {
+        std::string s(req.value(), req.vallen());
+        lcb_log(LOGARGS_T(DEBUG), LOGFMT "Received configuration pushed by the server", LOGID_T());
+        if (lcb::clconfig::cccp_update(cccp, curhost->host, s.c_str()) == LCB_SUCCESS) {
+            return;
+        }
+    }
+
+    int64_t epoch = -1, revision = -1;
+    if (req.extlen() >= 16) {
+        uint64_t tmp;
+        memcpy(&tmp, req.ext(), sizeof(tmp));
+        epoch = static_cast<int64_t>(lcb_ntohll(tmp));
+        memcpy(&tmp, req.ext() + 8, sizeof(tmp));
+        revision = static_cast<int64_t>(lcb_ntohll(tmp));
+    } else if (req.extlen() >= 4) {
+        uint32_t tmp;
+        memcpy(&tmp, req.ext(), sizeof(tmp));
+        revision = ntohl(tmp);
+    }
+    lcb_log(LOGARGS_T(DEBUG), LOGFMT "Configuration changed (epoch=%" PRId64 ", rev=%" PRId64 ")", LOGID_T(), epoch,
+            revision);
+
+    if (lcb::clconfig::cccp_has_revision(cccp, epoch, revision)) {
+        return;
+    }
+    instance->bootstrap(BS_REFRESH_ALWAYS);
+}
+
 /**
  * Invoked when get a NOT_MY_VBUCKET response. If the response contains a JSON
  * payload then we refresh the configuration with it.
@@ -148,6 +192,7 @@ bool Server::handle_nmv(MemcachedResponse &resinfo, mc_PACKET *oldpkt)
     lcb_vbguess_remap(instance, vbid, index);
 
     if (resinfo.vallen() && cccp->enabled) {
+        /* Configs we already have (i.e. from the other operations failing with the same NMV) are skipped cheaply */
         std::string s(resinfo.value(), resinfo.vallen());
         err = lcb::clconfig::cccp_update(cccp, curhost->host, s.c_str());
     }
@@ -266,7 +311,7 @@ bool Server::handle_unknown_collection(MemcachedResponse &resp, mc_PACKET *oldpk
     if (req.request.opcode == PROTOCOL_BINARY_CMD_COLLECTIONS_GET_CID) {
         mc_PACKET *newpkt = mcreq_renew_packet(oldpkt);
         newpkt->flags &= ~MCREQ_STATE_FLAGS;
-        instance->retryq->ucadd((mc_EXPACKET *)newpkt, orig_err, orig_status);
+        instance->retryq->ucadd((mc_EXPACKET *)newpkt, orig_err, orig_status, retry.retry_after_ms);
         return true;
     }
 
@@ -492,6 +537,14 @@ Server::ReadState Server::try_read(lcbio_CTX *ctx, rdb_IOROPE *ior)
         RETURN_NEED_MORE(pktsize);
     }
 
+    /* Requests pushed by the server are not related to any of ours */
+    if (mcresp.magic() == PROTOCOL_BINARY_SREQ) {
+        DO_ASSIGN_PAYLOAD()
+        handle_server_request(mcresp);
+        DO_SWALLOW_PAYLOAD()
+        return PKT_READ_COMPLETE;
+    }
+
     /* Find the packet */
     if (mcresp.opcode() == PROTOCOL_BINARY_CMD_STAT && mcresp.keylen() != 0) {
         is_last = 0;
@@ -529,6 +582,7 @@ Server::ReadState Server::try_read(lcbio_CTX *ctx, rdb_IOROPE *ior)
         rdb_consumed(ior, pktsize);
         return PKT_READ_COMPLETE;
     }
+    breaker.mark_success(gethrtime());
 
     lcb_STATUS err_override = LCB_SUCCESS;
     ReadState rdstate = PKT_READ_COMPLETE;
@@ -654,7 +708,7 @@ bool Server::maybe_retry_packet(mc_PACKET *pkt, lcb_STATUS err, protocol_binary_
     mc_PACKET *newpkt = mcreq_renew_packet(pkt);
     newpkt->flags &= ~MCREQ_STATE_FLAGS;
     // TODO: Load the 4th argument from the error map
-    instance->retryq->add((mc_EXPACKET *)newpkt, err, status, nullptr);
+    instance->retryq->add((mc_EXPACKET *)newpkt, err, status, nullptr, retry.retry_after_ms);
     return true;
 }
 
@@ -759,6 +813,14 @@ static const char *opcode_name(uint8_t code)
 
 void Server::purge_single(mc_PACKET *pkt, lcb_STATUS err)
 {
+    if (err == LCB_ERR_TIMEOUT) {
+        bool was_closed = breaker.get_state() == CircuitBreaker::CLOSED;
+        breaker.mark_failure(gethrtime());
+        if (was_closed && breaker.get_state() == CircuitBreaker::OPEN && curhost) {
+            lcb_emit_cluster_event(instance, LCB_CLUSTER_EVENT_CIRCUIT_OPENED, curhost->host, curhost->port, 0,
+                                   LCB_SUCCESS);
+        }
+    }
     if (maybe_retry_packet(pkt, err, PROTOCOL_BINARY_RESPONSE_EINTERNAL)) {
         return;
     }
@@ -901,10 +963,44 @@ static void timeout_server(void *arg)
     reinterpret_cast<Server *>(arg)->io_timeout();
 }
 
+/**
+ * Checks whether a keepalive NOOP sent over the pipeline has not been answered in time.
+ * Keepalives are pings without a cookie, the NOOPs of user pings only fail on their own.
+ * @param pipeline The pipeline
+ * @param now The current time
+ * @return true if an expired keepalive NOOP is still pending
+ */
+static bool has_expired_noop(mc_PIPELINE *pipeline, hrtime_t now)
+{
+    sllist_node *nn;
+    SLLIST_FOREACH(&pipeline->requests, nn)
+    {
+        mc_PACKET *pkt = SLLIST_ITEM(nn, mc_PACKET, slnode);
+        if (MCREQ_PKT_RDATA(pkt)->deadline > now || MCREQ_PKT_COOKIE(pkt) != nullptr) {
+            continue;
+        }
+        protocol_binary_request_header hdr;
+        mcreq_read_hdr(pkt, &hdr);
+        if (hdr.request.opcode == PROTOCOL_BINARY_CMD_NOOP) {
+            return true;
+        }
+    }
+    return false;
+}
+
 void Server::io_timeout()
 {
     hrtime_t now = gethrtime();
 
+    /* A NOOP is answered right away by the server, so if a keepalive did not make it back the
+     * connection is most likely half-open. Reconnect instead of letting all following commands time out. */
+    if (state == S_CLEAN && connctx != nullptr && has_expired_noop(this, now)) {
+        lcb_log(LOGARGS_T(WARN), LOGFMT "NOOP timed out. Assuming the connection is dead", LOGID_T());
+        MC_INCR_METRIC(this, packets_timeout, 1);
+        socket_failed(LCB_ERR_TIMEOUT);
+        return;
+    }
+
     int npurged = purge(LCB_ERR_TIMEOUT, now, Server::REFRESH_ONFAILED);
     if (npurged) {
         MC_INCR_METRIC(this, packets_timeout, npurged);
@@ -987,6 +1083,9 @@ void Server::handle_connected(lcbio_SOCKET *sock, lcb_STATUS err, lcbio_OSERR sy
         mutation_tokens = sessinfo->has_feature(PROTOCOL_BINARY_FEATURE_MUTATION_SEQNO);
         new_durability = sessinfo->has_feature(PROTOCOL_BINARY_FEATURE_SYNC_REPLICATION) &&
                          sessinfo->has_feature(PROTOCOL_BINARY_FEATURE_ALT_REQUEST_SUPPORT);
+        preserve_ttl = sessinfo->has_feature(PROTOCOL_BINARY_FEATURE_PRESERVE_TTL) &&
+                       sessinfo->has_feature(PROTOCOL_BINARY_FEATURE_ALT_REQUEST_SUPPORT);
+        subdoc_replica_read = sessinfo->has_feature(PROTOCOL_BINARY_FEATURE_SUBDOC_REPLICA_READ);
         selected_bucket = sessinfo->selected_bucket();
         if (selected_bucket) {
             bucket = sessinfo->bucket_name();
@@ -1021,6 +1120,8 @@ void Server::handle_connected(lcbio_SOCKET *sock, lcb_STATUS err, lcbio_OSERR sy
     }
     uint32_t tmo = next_timeout();
     lcbio_timer_rearm(io_timer, tmo);
+    lcb_emit_cluster_event(instance, LCB_CLUSTER_EVENT_ENDPOINT_CONNECTED, curhost->host, curhost->port, 0,
+                           LCB_SUCCESS);
     flush();
 }
 
@@ -1040,7 +1141,8 @@ static void buf_done_cb(mc_PIPELINE *pl, const void *cookie, void *, void *)
 Server::Server(lcb_INSTANCE *instance_, int ix)
     : mc_PIPELINE(), state(S_CLEAN), io_timer(lcbio_timer_new(instance_->iotable, this, timeout_server)),
       instance(instance_), settings(lcb_settings_ref2(instance_->settings)), compsupport(0), jsonsupport(0),
-      mutation_tokens(0), new_durability(-1), selected_bucket(0), connctx(nullptr), curhost(new lcb_host_t())
+      mutation_tokens(0), new_durability(-1), selected_bucket(0), connctx(nullptr), curhost(new lcb_host_t()),
+      breaker(settings)
 {
     mcreq_pipeline_init(this);
     flush_start = (mcreq_flushstart_fn)server_connect;
@@ -1064,7 +1166,8 @@ Server::Server(lcb_INSTANCE *instance_, int ix)
 
 Server::Server()
     : mc_pipeline_st(), state(S_TEMPORARY), io_timer(nullptr), instance(nullptr), settings(nullptr), compsupport(0),
-      jsonsupport(0), mutation_tokens(0), new_durability(0), connctx(nullptr), connreq(nullptr), curhost(nullptr)
+      jsonsupport(0), mutation_tokens(0), new_durability(0), connctx(nullptr), connreq(nullptr), curhost(nullptr),
+      breaker(nullptr)
 {
 }
 
@@ -1121,6 +1224,11 @@ void Server::socket_failed(lcb_STATUS err)
         return;
     }
 
+    /* Failed connection attempts have no context yet, only established connections count */
+    if (connctx) {
+        lcb_emit_cluster_event(instance, LCB_CLUSTER_EVENT_ENDPOINT_DISCONNECTED, curhost->host, curhost->port, 0,
+                               err);
+    }
     purge(err, 0, REFRESH_ALWAYS);
     lcb_maybe_breakout(instance);
     start_errored_ctx(S_ERRDRAIN);
@@ -22,6 +22,7 @@
 #include <lcbio/timer-ng.h>
 #include <mc/mcreq.h>
 #include <netbuf/netbuf.h>
+#include "circuitbreaker.h"
 
 #ifdef __cplusplus
 namespace lcb
@@ -135,6 +136,16 @@ class Server : public mc_PIPELINE
         return new_durability;
     }
 
+    bool supports_preserve_ttl() const
+    {
+        return preserve_ttl;
+    }
+
+    bool supports_subdoc_replica_read() const
+    {
+        return subdoc_replica_read;
+    }
+
     bool is_connected() const
     {
         return connctx != nullptr;
@@ -199,6 +210,7 @@ class Server : public mc_PIPELINE
     ReadState try_read(lcbio_CTX *ctx, rdb_IOROPE *ior);
     int handle_unknown_error(const mc_PACKET *request, const MemcachedResponse &resinfo, lcb_STATUS &newerr);
     bool handle_nmv(MemcachedResponse &resinfo, mc_PACKET *oldpkt);
+    void handle_server_request(const MemcachedResponse &req);
     bool handle_unknown_collection(MemcachedResponse &resinfo, mc_PACKET *oldpkt);
 
     bool maybe_retry_packet(mc_PACKET *pkt, lcb_STATUS err, protocol_binary_response_status status);
@@ -229,6 +241,12 @@ class Server : public mc_PIPELINE
     /** Whether new durability is supported */
     short new_durability;
 
+    /** Whether mutations may keep the expiry of the document */
+    short preserve_ttl{};
+
+    /** Whether sub-document lookups may be sent to replicas */
+    short subdoc_replica_read{};
+
     /** Whether bucket has been selected */
     short selected_bucket{};
 
@@ -238,6 +256,9 @@ class Server : public mc_PIPELINE
     /** Request for current connection */
     lcb_host_t *curhost;
     std::string bucket{}; /** non-empty if bucket has been selected */
+
+    /** Short-circuits dispatching while the node keeps timing out */
+    CircuitBreaker breaker;
 };
 } // namespace lcb
 #endif /* __cplusplus */
@@ -437,6 +437,14 @@ bool SessionRequestImpl::send_hello()
         features[nfeatures++] = PROTOCOL_BINARY_FEATURE_UNORDERED_EXECUTION;
     }
     features[nfeatures++] = PROTOCOL_BINARY_FEATURE_CREATE_AS_DELETED;
+    features[nfeatures++] = PROTOCOL_BINARY_FEATURE_PRESERVE_TTL;
+    features[nfeatures++] = PROTOCOL_BINARY_FEATURE_SUBDOC_REPLICA_READ;
+    if (settings->enable_config_push) {
+        /* servers which know the brief notifications prefer them over the full ones */
+        features[nfeatures++] = PROTOCOL_BINARY_FEATURE_DUPLEX;
+        features[nfeatures++] = PROTOCOL_BINARY_FEATURE_CLUSTERMAP_CHANGE_NOTIFICATION;
+        features[nfeatures++] = PROTOCOL_BINARY_FEATURE_CLUSTERMAP_CHANGE_NOTIFICATION_BRIEF;
+    }
 
     std::string agent = generate_agent_json();
     lcb::MemcachedRequest hdr(PROTOCOL_BINARY_CMD_HELLO);
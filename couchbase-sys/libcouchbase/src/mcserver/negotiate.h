@@ -114,6 +114,15 @@ class SessionInfo : public lcbio_PROTOCTX
      * @return true if supported, false otherwise
      */
     bool has_feature(uint16_t feature) const;
+
+    /**
+     * @brief The features the server agreed to in its HELLO response
+     */
+    const std::vector<uint16_t> &features() const
+    {
+        return server_features;
+    }
+
     bool selected_bucket() const;
     const std::string &bucket_name() const;
 
@@ -199,6 +199,8 @@ typedef struct lcb_QUERY_HANDLE_ : lcb::jsparse::Parser::Actions {
 
     /** String of the original statement. Cached here to avoid jsoncpp lookups */
     std::string statement;
+    /** Key of the prepared plan: the same statement may refer to different keyspaces depending on its query context */
+    std::string plan_key;
     std::string client_context_id;
     std::string first_error_message;
     uint32_t first_error_code{};
@@ -266,6 +268,13 @@ typedef struct lcb_QUERY_HANDLE_ : lcb::jsparse::Parser::Actions {
         return flags & LCB_CMDN1QL_F_PREPCACHE;
     }
 
+    /**
+     * Insert the plan returned by a prepare-only request into the cache
+     * @param row The row holding the prepared statement
+     * @param nrow The length of the row
+     */
+    inline void cache_plan(const char *row, size_t nrow);
+
     /**
      * Pass a row back to the application
      * @param resp The response. This is populated with state information
@@ -291,6 +300,9 @@ typedef struct lcb_QUERY_HANDLE_ : lcb::jsparse::Parser::Actions {
         lcb_RESPQUERY resp{};
         resp.row = static_cast<const char *>(row.row.iov_base);
         resp.nrow = row.row.iov_len;
+        if (nrows == 0 && (flags & LCB_CMDN1QL_F_PREPARE_ONLY)) {
+            cache_plan(resp.row, resp.nrow);
+        }
         nrows++;
         invoke_row(&resp, false);
     }
@@ -310,6 +322,20 @@ static bool parse_json(const char *s, size_t n, Json::Value &res)
     return Json::Reader().parse(s, s + n, res);
 }
 
+void N1QLREQ::cache_plan(const char *row, size_t nrow)
+{
+    Json::Value prepared;
+    if (!parse_json(row, nrow, prepared)) {
+        lcb_log(LOGARGS(this, ERROR), LOGFMT "Invalid JSON returned from PREPARE", LOGID(this));
+        return;
+    }
+
+    bool eps = LCBVB_CCAPS(LCBT_VBCONFIG(instance)) & LCBVB_CCAP_N1QL_ENHANCED_PREPARED_STATEMENTS;
+    lcb_log(LOGARGS(this, DEBUG), LOGFMT "Got %sprepared statement. Inserting into cache", LOGID(this),
+            eps ? "(enhanced) " : "");
+    cache().add_entry(plan_key, prepared, !eps);
+}
+
 lcb_N1QLCACHE *lcb_n1qlcache_create(void)
 {
     return new lcb_N1QLCACHE;
@@ -427,7 +453,7 @@ bool N1QLREQ::maybe_retry()
 
     // Let's see if we can actually retry. First remove the existing prepared
     // entry:
-    cache().remove_entry(statement);
+    cache().remove_entry(plan_key);
 
     if ((lasterr = request_plan()) == LCB_SUCCESS) {
         // We'll be parsing more rows later on..
@@ -652,7 +678,7 @@ static void prepare_rowcb(lcb_INSTANCE *instance, int, const lcb_RESPQUERY *row)
         // Insert plan into cache
         lcb_log(LOGARGS(origreq, DEBUG), LOGFMT "Got %sprepared statement. Inserting into cache and reissuing",
                 LOGID(origreq), eps ? "(enhanced) " : "");
-        const Plan &ent = origreq->cache().add_entry(origreq->statement, prepared, !eps);
+        const Plan &ent = origreq->cache().add_entry(origreq->plan_key, prepared, !eps);
 
         // Issue the query with the newly prepared plan
         lcb_STATUS rc = origreq->apply_plan(ent);
@@ -784,7 +810,7 @@ lcb_QUERY_HANDLE_::lcb_QUERY_HANDLE_(lcb_INSTANCE *obj, const void *user_cookie,
     if (flags & LCB_CMDN1QL_F_ANALYTICSQUERY) {
         is_cbas = true;
     }
-    if (is_cbas && (flags & LCB_CMDN1QL_F_PREPCACHE)) {
+    if (is_cbas && (flags & (LCB_CMDN1QL_F_PREPCACHE | LCB_CMDN1QL_F_PREPARE_ONLY))) {
         lasterr = LCB_ERR_OPTIONS_CONFLICT;
         return;
     }
@@ -792,6 +818,11 @@ lcb_QUERY_HANDLE_::lcb_QUERY_HANDLE_(lcb_INSTANCE *obj, const void *user_cookie,
     const Json::Value &j_statement = json_const()["statement"];
     if (j_statement.isString()) {
         statement = j_statement.asString();
+        plan_key = statement;
+        const Json::Value &j_query_context = json_const()["query_context"];
+        if (j_query_context.isString()) {
+            plan_key = j_query_context.asString() + '\n' + statement;
+        }
     } else if (!j_statement.isNull()) {
         lasterr = LCB_ERR_INVALID_ARGUMENT;
         return;
@@ -868,13 +899,24 @@ lcb_STATUS lcb_query(lcb_INSTANCE *instance, void *cookie, const lcb_CMDQUERY *c
         goto GT_DESTROY;
     }
 
-    if (cmd->cmdflags & LCB_CMDN1QL_F_PREPCACHE) {
+    if (cmd->cmdflags & LCB_CMDN1QL_F_PREPARE_ONLY) {
+        if (req->statement.empty()) {
+            err = LCB_ERR_INVALID_ARGUMENT;
+            goto GT_DESTROY;
+        }
+
+        // Issue the PREPARE itself, its row is cached as the plan once it arrives
+        req->json["statement"] = "PREPARE " + req->statement;
+        if ((err = req->issue_htreq()) != LCB_SUCCESS) {
+            goto GT_DESTROY;
+        }
+    } else if (cmd->cmdflags & LCB_CMDN1QL_F_PREPCACHE) {
         if (req->statement.empty()) {
             err = LCB_ERR_INVALID_ARGUMENT;
             goto GT_DESTROY;
         }
 
-        const Plan *cached = req->cache().get_entry(req->statement);
+        const Plan *cached = req->cache().get_entry(req->plan_key);
         if (cached != nullptr) {
             if ((err = req->apply_plan(*cached)) != LCB_SUCCESS) {
                 goto GT_DESTROY;
@@ -351,5 +351,7 @@ void lcb_update_vbconfig(lcb_INSTANCE *instance, lcb_pCONFIGINFO config)
         }
     }
 
+    lcb_emit_cluster_event(instance, LCB_CLUSTER_EVENT_CONFIG_UPDATED, nullptr, nullptr,
+                           lcbvb_get_revision(config->vbc), LCB_SUCCESS);
     lcb_maybe_breakout(instance);
 }
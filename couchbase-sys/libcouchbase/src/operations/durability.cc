@@ -459,6 +459,53 @@ lcb_MULTICMD_CTX *lcb_endure3_ctxnew(lcb_INSTANCE *instance, const lcb_durabilit
     return dset;
 }
 
+LIBCOUCHBASE_API
+lcb_STATUS lcb_endure_mutation(lcb_INSTANCE *instance, void *cookie, const char *key, size_t key_len, uint64_t cas,
+                               const lcb_MUTATION_TOKEN *token, int persist_to, int replicate_to, int check_delete)
+{
+    lcb_STATUS err = LCB_SUCCESS;
+    lcb_durability_opts_t opts = {0};
+    opts.v.v0.persist_to = persist_to;
+    opts.v.v0.replicate_to = replicate_to;
+    opts.v.v0.check_delete = check_delete ? 1 : 0;
+
+    lcb_MULTICMD_CTX *mctx = lcb_endure3_ctxnew(instance, &opts, &err);
+    if (mctx == NULL) {
+        return err;
+    }
+
+    lcb_CMDENDURE cmd = {0};
+    LCB_CMD_SET_KEY(&cmd, key, key_len);
+    cmd.cas = cas;
+    if (token != NULL && LCB_MUTATION_TOKEN_ISVALID(token)) {
+        cmd.mutation_token = token;
+        cmd.cmdflags |= LCB_CMDENDURE_F_MUTATION_TOKEN;
+    }
+    err = mctx->addcmd(mctx, (lcb_CMDBASE *)&cmd);
+    if (err != LCB_SUCCESS) {
+        mctx->fail(mctx);
+        return err;
+    }
+
+    lcb_sched_enter(instance);
+    err = mctx->done(mctx, cookie);
+    lcb_sched_leave(instance);
+    return err;
+}
+
+LIBCOUCHBASE_API
+lcb_STATUS lcb_respendure_status(const lcb_RESPBASE *resp)
+{
+    return resp->ctx.rc;
+}
+
+LIBCOUCHBASE_API
+lcb_STATUS lcb_respendure_cookie(const lcb_RESPBASE *resp, void **cookie)
+{
+    *cookie = resp->cookie;
+    return LCB_SUCCESS;
+}
+
 /**
  * Actually free the resources allocated by the dset (and all its entries).
  * Called by some other functions in libcouchbase
@@ -119,6 +119,13 @@ LIBCOUCHBASE_API lcb_STATUS lcb_cmdget_collection(lcb_CMDGET *cmd, const char *s
     return LCB_SUCCESS;
 }
 
+LIBCOUCHBASE_API lcb_STATUS lcb_cmdget_collection_id(lcb_CMDGET *cmd, uint32_t cid)
+{
+    cmd->cid = cid;
+    cmd->cmdflags |= LCB_CMD_F_EXPLICIT_CID;
+    return LCB_SUCCESS;
+}
+
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdget_key(lcb_CMDGET *cmd, const char *key, size_t key_len)
 {
     LCB_CMD_SET_KEY(cmd, key, key_len);
@@ -227,8 +234,8 @@ lcb_STATUS lcb_get(lcb_INSTANCE *instance, void *cookie, const lcb_CMDGET *comma
         return LCB_SUCCESS;
     };
 
-    if (!LCBT_SETTING(instance, use_collections)) {
-        /* fast path if collections are not enabled */
+    if (!LCBT_SETTING(instance, use_collections) || (command->cmdflags & LCB_CMD_F_EXPLICIT_CID)) {
+        /* fast path if collections are not enabled, or the collection id is already known */
         return operation(nullptr, command);
     }
 
@@ -401,8 +408,8 @@ lcb_STATUS lcb_unlock(lcb_INSTANCE *instance, void *cookie, const lcb_CMDUNLOCK
         return LCB_SUCCESS;
     };
 
-    if (!LCBT_SETTING(instance, use_collections)) {
-        /* fast path if collections are not enabled */
+    if (!LCBT_SETTING(instance, use_collections) || (command->cmdflags & LCB_CMD_F_EXPLICIT_CID)) {
+        /* fast path if collections are not enabled, or the collection id is already known */
         return operation(nullptr, command);
     }
 
@@ -802,8 +809,8 @@ lcb_STATUS lcb_getreplica(lcb_INSTANCE *instance, void *cookie, const lcb_CMDGET
         return LCB_SUCCESS;
     };
 
-    if (!LCBT_SETTING(instance, use_collections)) {
-        /* fast path if collections are not enabled */
+    if (!LCBT_SETTING(instance, use_collections) || (command->cmdflags & LCB_CMD_F_EXPLICIT_CID)) {
+        /* fast path if collections are not enabled, or the collection id is already known */
         return operation(nullptr, command);
     }
 
@@ -18,6 +18,7 @@
 #include "internal.h"
 #include "http/http.h"
 #include "auth-priv.h"
+#include "mcserver/negotiate.h"
 
 LIBCOUCHBASE_API lcb_STATUS lcb_respping_status(const lcb_RESPPING *resp)
 {
@@ -679,6 +680,61 @@ LIBCOUCHBASE_API lcb_STATUS lcb_cmddiag_prettify(lcb_CMDDIAG *cmd, int enable)
     return LCB_SUCCESS;
 }
 
+/**
+ * Names the HELLO features in diagnostics reports. Unlike protocol_feature_2_text(),
+ * these names are meant to be matched by tools and must not change.
+ */
+static std::string diag_feature_name(uint16_t feature)
+{
+    switch (feature) {
+        case PROTOCOL_BINARY_FEATURE_TLS:
+            return "tls";
+        case PROTOCOL_BINARY_FEATURE_TCPNODELAY:
+            return "tcp_nodelay";
+        case PROTOCOL_BINARY_FEATURE_MUTATION_SEQNO:
+            return "mutation_seqno";
+        case PROTOCOL_BINARY_FEATURE_TCPDELAY:
+            return "tcp_delay";
+        case PROTOCOL_BINARY_FEATURE_XATTR:
+            return "xattr";
+        case PROTOCOL_BINARY_FEATURE_XERROR:
+            return "xerror";
+        case PROTOCOL_BINARY_FEATURE_SELECT_BUCKET:
+            return "select_bucket";
+        case PROTOCOL_BINARY_FEATURE_SNAPPY:
+            return "snappy";
+        case PROTOCOL_BINARY_FEATURE_JSON:
+            return "json";
+        case PROTOCOL_BINARY_FEATURE_DUPLEX:
+            return "duplex";
+        case PROTOCOL_BINARY_FEATURE_CLUSTERMAP_CHANGE_NOTIFICATION:
+            return "clustermap_change_notification";
+        case PROTOCOL_BINARY_FEATURE_CLUSTERMAP_CHANGE_NOTIFICATION_BRIEF:
+            return "clustermap_change_notification_brief";
+        case PROTOCOL_BINARY_FEATURE_UNORDERED_EXECUTION:
+            return "unordered_execution";
+        case PROTOCOL_BINARY_FEATURE_TRACING:
+            return "tracing";
+        case PROTOCOL_BINARY_FEATURE_ALT_REQUEST_SUPPORT:
+            return "alt_request";
+        case PROTOCOL_BINARY_FEATURE_SYNC_REPLICATION:
+            return "sync_replication";
+        case PROTOCOL_BINARY_FEATURE_COLLECTIONS:
+            return "collections";
+        case PROTOCOL_BINARY_FEATURE_PRESERVE_TTL:
+            return "preserve_ttl";
+        case PROTOCOL_BINARY_FEATURE_CREATE_AS_DELETED:
+            return "create_as_deleted";
+        case PROTOCOL_BINARY_FEATURE_SUBDOC_REPLICA_READ:
+            return "subdoc_replica_read";
+        default: {
+            char buf[8] = {0};
+            snprintf(buf, sizeof(buf), "0x%02x", feature);
+            return buf;
+        }
+    }
+}
+
 LIBCOUCHBASE_API
 lcb_STATUS lcb_diag(lcb_INSTANCE *instance, void *cookie, const lcb_CMDDIAG *cmd)
 {
@@ -721,6 +777,9 @@ lcb_STATUS lcb_diag(lcb_INSTANCE *instance, void *cookie, const lcb_CMDDIAG *cmd
             if (!server->bucket.empty()) {
                 endpoint["namespace"] = server->bucket;
             }
+            if (server->breaker.enabled()) {
+                endpoint["circuit_breaker"] = server->breaker.state_name();
+            }
             if (ctx->sock) {
                 if (ctx->sock->info) {
                     endpoint["local"] = ctx->sock->info->ep_local;
@@ -728,6 +787,22 @@ lcb_STATUS lcb_diag(lcb_INSTANCE *instance, void *cookie, const lcb_CMDDIAG *cmd
                 endpoint["last_activity_us"] =
                     (Json::Value::UInt64)(now > ctx->sock->atime ? now - ctx->sock->atime : 0);
                 endpoint["status"] = "connected";
+                /* the same id the connection announced to the server in its HELLO agent */
+                char connection_id[34] = {0};
+                snprintf(connection_id, sizeof(connection_id), "%016" PRIx64 "/%016" PRIx64,
+                         LCBT_SETTING(instance, iid), ctx->sock->id);
+                endpoint["connection_id"] = connection_id;
+                lcb::SessionInfo *info = lcb::SessionInfo::get(ctx->sock);
+                if (info) {
+                    Json::Value features(Json::arrayValue);
+                    for (uint16_t feature : info->features()) {
+                        features.append(diag_feature_name(feature));
+                    }
+                    endpoint["features"] = features;
+                    if (!info->get_mech().empty()) {
+                        endpoint["sasl_mechanism"] = info->get_mech();
+                    }
+                }
                 root[lcbio_svcstr(ctx->sock->service)].append(endpoint);
             }
         }
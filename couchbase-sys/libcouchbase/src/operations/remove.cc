@@ -110,6 +110,13 @@ LIBCOUCHBASE_API lcb_STATUS lcb_cmdremove_collection(lcb_CMDREMOVE *cmd, const c
     return LCB_SUCCESS;
 }
 
+LIBCOUCHBASE_API lcb_STATUS lcb_cmdremove_collection_id(lcb_CMDREMOVE *cmd, uint32_t cid)
+{
+    cmd->cid = cid;
+    cmd->cmdflags |= LCB_CMD_F_EXPLICIT_CID;
+    return LCB_SUCCESS;
+}
+
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdremove_key(lcb_CMDREMOVE *cmd, const char *key, size_t key_len)
 {
     LCB_CMD_SET_KEY(cmd, key, key_len);
@@ -211,8 +218,8 @@ lcb_STATUS lcb_remove(lcb_INSTANCE *instance, void *cookie, const lcb_CMDREMOVE
         return LCB_SUCCESS;
     };
 
-    if (!LCBT_SETTING(instance, use_collections)) {
-        /* fast path if collections are not enabled */
+    if (!LCBT_SETTING(instance, use_collections) || (command->cmdflags & LCB_CMD_F_EXPLICIT_CID)) {
+        /* fast path if collections are not enabled, or the collection id is already known */
         return operation(nullptr, command);
     }
 
@@ -182,6 +182,13 @@ LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_collection(lcb_CMDSTORE *cmd, const cha
     return LCB_SUCCESS;
 }
 
+LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_collection_id(lcb_CMDSTORE *cmd, uint32_t cid)
+{
+    cmd->cid = cid;
+    cmd->cmdflags |= LCB_CMD_F_EXPLICIT_CID;
+    return LCB_SUCCESS;
+}
+
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_key(lcb_CMDSTORE *cmd, const char *key, size_t key_len)
 {
     LCB_CMD_SET_KEY(cmd, key, key_len);
@@ -234,6 +241,12 @@ LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_durability(lcb_CMDSTORE *cmd, lcb_DURAB
     return LCB_SUCCESS;
 }
 
+LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_preserve_expiry(lcb_CMDSTORE *cmd, int should_preserve)
+{
+    cmd->preserve_expiry = should_preserve;
+    return LCB_SUCCESS;
+}
+
 LIBCOUCHBASE_API lcb_STATUS lcb_cmdstore_durability_observe(lcb_CMDSTORE *cmd, int persist_to, int replicate_to)
 {
     cmd->durability_mode = LCB_DURABILITY_POLL;
@@ -402,6 +415,14 @@ static lcb_STATUS store_validate(lcb_INSTANCE *instance, const lcb_CMDSTORE *cmd
             return LCB_ERR_UNSUPPORTED_OPERATION;
         }
     }
+    if (cmd->preserve_expiry) {
+        if (cmd->operation != LCB_STORE_UPSERT && cmd->operation != LCB_STORE_REPLACE) {
+            return LCB_ERR_OPTIONS_CONFLICT;
+        }
+        if (cmd->exptime) {
+            return LCB_ERR_OPTIONS_CONFLICT;
+        }
+    }
     switch (cmd->operation) {
         case LCB_STORE_APPEND:
         case LCB_STORE_PREPEND:
@@ -456,14 +477,22 @@ LIBCOUCHBASE_API lcb_STATUS lcb_store(lcb_INSTANCE *instance, void *cookie, cons
         hdr->request.magic = PROTOCOL_BINARY_REQ;
 
         lcb_U8 ffextlen = 0;
-        if (cmd->durability_mode == LCB_DURABILITY_SYNC && cmd->durability.sync.dur_level && new_durability_supported) {
-            hdr->request.magic = PROTOCOL_BINARY_AREQ;
+        bool sync_durability = cmd->durability_mode == LCB_DURABILITY_SYNC && cmd->durability.sync.dur_level &&
+                               new_durability_supported;
+        if (sync_durability) {
             /* 1 byte for id and size
              * 1 byte for level
              * 2 bytes for timeout
              */
             ffextlen = 4;
         }
+        if (cmd->preserve_expiry) {
+            /* 1 byte for id and size, the frame has no value */
+            ffextlen += 1;
+        }
+        if (ffextlen) {
+            hdr->request.magic = PROTOCOL_BINARY_AREQ;
+        }
 
         err = get_esize_and_opcode(cmd->operation, &hdr->request.opcode, &hdr->request.extlen);
         if (err != LCB_SUCCESS) {
@@ -475,6 +504,10 @@ LIBCOUCHBASE_API lcb_STATUS lcb_store(lcb_INSTANCE *instance, void *cookie, cons
         if (err != LCB_SUCCESS) {
             return err;
         }
+        if (cmd->preserve_expiry && !static_cast<const lcb::Server *>(pipeline)->supports_preserve_ttl()) {
+            mcreq_release_packet(pipeline, packet);
+            return LCB_ERR_UNSUPPORTED_OPERATION;
+        }
 
         should_compress = can_compress(instance, pipeline, cmd->datatype);
         if (should_compress) {
@@ -515,18 +548,25 @@ LIBCOUCHBASE_API lcb_STATUS lcb_store(lcb_INSTANCE *instance, void *cookie, cons
             rdata->start = gethrtime();
             rdata->deadline =
                 rdata->start + LCB_US2NS(cmd->timeout ? cmd->timeout : LCBT_SETTING(instance, operation_timeout));
-            if (cmd->durability.sync.dur_level && new_durability_supported) {
-                scmd.message.body.alt.expiration = htonl(cmd->exptime);
-                scmd.message.body.alt.flags = htonl(cmd->flags);
-                scmd.message.body.alt.meta = (1 << 4) | 3;
-                scmd.message.body.alt.level = cmd->durability.sync.dur_level;
-                scmd.message.body.alt.timeout = htons(lcb_durability_timeout(instance, cmd->timeout));
-            } else {
-                scmd.message.body.norm.expiration = htonl(cmd->exptime);
-                scmd.message.body.norm.flags = htonl(cmd->flags);
-            }
         }
 
+        /* framing extras come first, followed by the flags and expiration */
+        lcb_U8 *body = scmd.bytes + sizeof(*hdr);
+        if (sync_durability) {
+            lcb_U16 timeout = htons(lcb_durability_timeout(instance, cmd->timeout));
+            *body++ = (1 << 4) | 3;
+            *body++ = cmd->durability.sync.dur_level;
+            memcpy(body, &timeout, sizeof(timeout));
+            body += sizeof(timeout);
+        }
+        if (cmd->preserve_expiry) {
+            *body++ = 5 << 4;
+        }
+        lcb_U32 flags = htonl(cmd->flags);
+        lcb_U32 expiration = htonl(cmd->exptime);
+        memcpy(body, &flags, sizeof(flags));
+        memcpy(body + sizeof(flags), &expiration, sizeof(expiration));
+
         hdr->request.cas = lcb_htonll(cmd->cas);
         hdr->request.datatype = PROTOCOL_BINARY_RAW_BYTES;
 
@@ -553,8 +593,8 @@ LIBCOUCHBASE_API lcb_STATUS lcb_store(lcb_INSTANCE *instance, void *cookie, cons
         return LCB_SUCCESS;
     };
 
-    if (!LCBT_SETTING(instance, use_collections)) {
-        /* fast path if collections are not enabled */
+    if (!LCBT_SETTING(instance, use_collections) || (command->cmdflags & LCB_CMD_F_EXPLICIT_CID)) {
+        /* fast path if collections are not enabled, or the collection id is already known */
         return operation(nullptr, command);
     }
 
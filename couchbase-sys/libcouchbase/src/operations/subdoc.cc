@@ -409,6 +409,20 @@ LIBCOUCHBASE_API lcb_STATUS lcb_cmdsubdoc_create_as_deleted(lcb_CMDSUBDOC *cmd,
     return LCB_SUCCESS;
 }
 
+LIBCOUCHBASE_API lcb_STATUS lcb_cmdsubdoc_replica_index(lcb_CMDSUBDOC *cmd, int index)
+{
+    if (index < -1) {
+        return LCB_ERR_INVALID_ARGUMENT;
+    }
+    if (index < 0) {
+        cmd->cmdflags &= ~LCB_CMDSUBDOC_F_REPLICA_READ;
+    } else {
+        cmd->cmdflags |= LCB_CMDSUBDOC_F_REPLICA_READ;
+    }
+    cmd->replica_index = index;
+    return LCB_SUCCESS;
+}
+
 namespace SubdocCmdTraits
 {
 enum Options {
@@ -545,6 +559,7 @@ static const uint8_t MKDOC = 0x01;
 static const uint8_t ADDDOC = 0x02;
 static const uint8_t ACCESS_DELETED = 0x04;
 static const uint8_t CREATE_AS_DELETED = 0x08;
+static const uint8_t REPLICA_READ = 0x20;
 } // namespace SubdocDocFlags
 
 static size_t get_valbuf_size(const lcb_VALBUF &vb)
@@ -594,6 +609,9 @@ static uint8_t make_doc_flags(const uint32_t user)
     if (user & LCB_CMDSUBDOC_F_CREATE_AS_DELETED) {
         flags |= SubdocDocFlags::CREATE_AS_DELETED;
     }
+    if (user & LCB_CMDSUBDOC_F_REPLICA_READ) {
+        flags |= SubdocDocFlags::REPLICA_READ;
+    }
     return flags;
 }
 
@@ -807,6 +825,11 @@ lcb_STATUS lcb_subdoc(lcb_INSTANCE *instance, void *cookie, const lcb_CMDSUBDOC
             return LCB_ERR_OPTIONS_CONFLICT;
         }
 
+        bool replica_read = (docflags & SubdocDocFlags::REPLICA_READ) != 0;
+        if (replica_read && !ctx.is_lookup()) {
+            return LCB_ERR_OPTIONS_CONFLICT;
+        }
+
         for (size_t ii = 0; ii < cmd->nspecs; ++ii) {
             if (cmd->error_index) {
                 *cmd->error_index = ii;
@@ -845,12 +868,21 @@ lcb_STATUS lcb_subdoc(lcb_INSTANCE *instance, void *cookie, const lcb_CMDSUBDOC
             *cmd->error_index = -1;
         }
 
-        rc = mcreq_basic_packet(&instance->cmdq, reinterpret_cast<const lcb_CMDBASE *>(cmd), &hdr, extlen, ffextlen,
-                                &pkt, &pl, MCREQ_BASICPACKET_F_FALLBACKOK);
+        if (replica_read) {
+            rc = mcreq_replica_packet(&instance->cmdq, reinterpret_cast<const lcb_CMDBASE *>(cmd), &hdr, extlen,
+                                      cmd->replica_index, &pkt, &pl);
+        } else {
+            rc = mcreq_basic_packet(&instance->cmdq, reinterpret_cast<const lcb_CMDBASE *>(cmd), &hdr, extlen,
+                                    ffextlen, &pkt, &pl, MCREQ_BASICPACKET_F_FALLBACKOK);
+        }
 
         if (rc != LCB_SUCCESS) {
             return rc;
         }
+        if (replica_read && !static_cast<const lcb::Server *>(pl)->supports_subdoc_replica_read()) {
+            mcreq_release_packet(pl, pkt);
+            return LCB_ERR_UNSUPPORTED_OPERATION;
+        }
 
         lcb_VALBUF vb = {LCB_KV_IOVCOPY};
         vb.u_buf.multi.iov = &ctx.iovs[0];
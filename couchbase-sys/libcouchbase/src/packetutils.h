@@ -109,6 +109,15 @@ class MemcachedResponse
         release(&ctx->ior);
     }
 
+    /**
+     * Gets the magic of the packet, which tells responses apart from requests pushed by the
+     * server
+     */
+    uint8_t magic() const
+    {
+        return res.response.magic;
+    }
+
     /**
      * Gets the command for the packet
      */
@@ -47,6 +47,8 @@ static lcb_RETRY_REASON mc_code_to_reason(lcb_STATUS status)
         case LCB_ERR_CONNECT_ERROR:
         case LCB_ERR_UNKNOWN_HOST:
             return LCB_RETRY_REASON_NODE_NOT_AVAILABLE;
+        case LCB_ERR_CIRCUIT_BREAKER_OPEN:
+            return LCB_RETRY_REASON_CIRCUIT_BREAKER_OPEN;
         default:
             return LCB_RETRY_REASON_UNKNOWN;
     }
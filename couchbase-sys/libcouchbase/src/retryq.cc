@@ -266,6 +266,21 @@ void RetryQueue::flush(bool throttle)
             } else {
                 fail(op, LCB_ERR_NO_MATCHING_SERVER, now);
             }
+        } else if (!static_cast<lcb::Server *>(cq->pipelines[srvix])->breaker.allows_request()) {
+            auto *server = static_cast<lcb::Server *>(cq->pipelines[srvix]);
+            assign_error(op, LCB_ERR_CIRCUIT_BREAKER_OPEN);
+            if (server->breaker.should_send_canary(now)) {
+                get_instance()->send_canary(server);
+            }
+            if (lcb_kv_should_retry(settings, op->pkt, LCB_ERR_CIRCUIT_BREAKER_OPEN).should_retry) {
+                lcb_list_delete(static_cast<SchedNode *>(op));
+                lcb_list_delete(static_cast<TmoNode *>(op));
+                lcb_list_append(&resched_next, static_cast<SchedNode *>(op));
+                op->pkt->retries++;
+                update_trytime(op, now);
+            } else {
+                fail(op, LCB_ERR_CIRCUIT_BREAKER_OPEN, now);
+            }
         } else {
             lcb_log(LOGARGS(this, TRACE), "Flush PKT=%p to network. retries=%u, opaque=%u, IX=%d, time=%" PRIu64 "us",
                     (void *)op->pkt, op->pkt->retries, op->pkt->opaque, srvix, LCB_NS2US(now - op->start));
@@ -319,7 +334,7 @@ RetryOp::RetryOp(errmap::RetrySpec *spec_)
 }
 
 void RetryQueue::add(mc_EXPACKET *pkt, const lcb_STATUS err, protocol_binary_response_status status,
-                     errmap::RetrySpec *spec, int options)
+                     errmap::RetrySpec *spec, int options, uint32_t retry_after_ms)
 {
     RetryOp *op;
     mc_EPKTDATUM *d = mcreq_epkt_find(pkt, RETRY_PKT_KEY);
@@ -386,6 +401,8 @@ void RetryQueue::add(mc_EXPACKET *pkt, const lcb_STATUS err, protocol_binary_res
         op->trytime = now;
     } else if (err == LCB_ERR_NOT_MY_VBUCKET) {
         op->trytime = now + LCB_US2NS(settings->retry_nmv_interval);
+    } else if (retry_after_ms) {
+        op->trytime = now + LCB_US2NS(LCB_MS2US((hrtime_t)retry_after_ms));
     } else {
         update_trytime(op);
     }
@@ -432,12 +449,13 @@ void RetryQueue::nmvadd(mc_EXPACKET *detchpkt)
     if (settings->nmv_retry_imm) {
         flags = RETRY_SCHED_IMM;
     }
-    add(detchpkt, LCB_ERR_NOT_MY_VBUCKET, PROTOCOL_BINARY_RESPONSE_NOT_MY_VBUCKET, nullptr, flags);
+    add(detchpkt, LCB_ERR_NOT_MY_VBUCKET, PROTOCOL_BINARY_RESPONSE_NOT_MY_VBUCKET, nullptr, flags, 0);
 }
 
-void RetryQueue::ucadd(mc_EXPACKET *pkt, lcb_STATUS orig_err, protocol_binary_response_status status)
+void RetryQueue::ucadd(mc_EXPACKET *pkt, lcb_STATUS orig_err, protocol_binary_response_status status,
+                       uint32_t retry_after_ms)
 {
-    add(pkt, orig_err, status, nullptr, 0);
+    add(pkt, orig_err, status, nullptr, 0, retry_after_ms);
 }
 
 static void fallback_handler(mc_CMDQUEUE *cq, mc_PACKET *pkt)
@@ -449,7 +467,36 @@ static void fallback_handler(mc_CMDQUEUE *cq, mc_PACKET *pkt)
 void RetryQueue::add_fallback(mc_PACKET *pkt)
 {
     mc_PACKET *copy = mcreq_renew_packet(pkt);
-    add((mc_EXPACKET *)copy, LCB_ERR_NO_MATCHING_SERVER, PROTOCOL_BINARY_RESPONSE_EINTERNAL, nullptr, RETRY_SCHED_IMM);
+    add((mc_EXPACKET *)copy, LCB_ERR_NO_MATCHING_SERVER, PROTOCOL_BINARY_RESPONSE_EINTERNAL, nullptr, RETRY_SCHED_IMM, 0);
+}
+
+void RetryQueue::add_rejected(mc_PACKET *pkt, bool retry, uint32_t retry_after_ms)
+{
+    mc_PACKET *copy = mcreq_renew_packet(pkt);
+    if (!retry) {
+        /* fail asynchronously, the scheduling call must not invoke the callback */
+        MCREQ_PKT_RDATA(copy)->deadline = gethrtime();
+    }
+    add((mc_EXPACKET *)copy, LCB_ERR_CIRCUIT_BREAKER_OPEN, PROTOCOL_BINARY_RESPONSE_EINTERNAL, nullptr, 0,
+        retry_after_ms);
+}
+
+void lcb_sched_add(lcb_INSTANCE *instance, mc_PIPELINE *pl, mc_PACKET *pkt)
+{
+    if (pl->index < (int)instance->cmdq.npipelines) {
+        auto *server = static_cast<lcb::Server *>(pl);
+        if (!server->breaker.allows_request()) {
+            if (server->breaker.should_send_canary(gethrtime())) {
+                instance->send_canary(server);
+            }
+            lcb_RETRY_ACTION action = lcb_kv_should_retry(instance->settings, pkt, LCB_ERR_CIRCUIT_BREAKER_OPEN);
+            instance->retryq->add_rejected(pkt, action.should_retry, action.retry_after_ms);
+            mcreq_wipe_packet(pl, pkt);
+            mcreq_release_packet(pl, pkt);
+            return;
+        }
+    }
+    mcreq_sched_add(pl, pkt);
 }
 
 void RetryQueue::reset_timeouts(lcb_U64 now)
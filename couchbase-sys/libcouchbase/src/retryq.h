@@ -74,9 +74,10 @@ class RetryQueue
      * it may _not_ be used for memcached buckets (which is typically OK, as we only
      * map things here as a response for a not-my-vbucket).
      */
-    void add(mc_EXPACKET *detchpkt, lcb_STATUS err, protocol_binary_response_status status, errmap::RetrySpec *spec)
+    void add(mc_EXPACKET *detchpkt, lcb_STATUS err, protocol_binary_response_status status, errmap::RetrySpec *spec,
+             uint32_t retry_after_ms = 0)
     {
-        add(detchpkt, err, status, spec, 0);
+        add(detchpkt, err, status, spec, 0, retry_after_ms);
     }
 
     /**
@@ -87,7 +88,8 @@ class RetryQueue
      * @param detchpkt The new packet
      */
     void nmvadd(mc_EXPACKET *detchpkt);
-    void ucadd(mc_EXPACKET *pkt, lcb_STATUS orig_err, protocol_binary_response_status status);
+    void ucadd(mc_EXPACKET *pkt, lcb_STATUS orig_err, protocol_binary_response_status status,
+               uint32_t retry_after_ms = 0);
 
     /**
      * @brief Retry all queued operations
@@ -142,6 +144,12 @@ class RetryQueue
 
     inline void add_fallback(mc_PACKET *pkt);
 
+    /**
+     * Takes over a packet which was not dispatched because the circuit breaker of its server
+     * is open. If it must not be retried, it fails on the next tick.
+     */
+    void add_rejected(mc_PACKET *pkt, bool retry, uint32_t retry_after_ms = 0);
+
   private:
     static void erase(RetryOp *);
     void fail(RetryOp *, lcb_STATUS, hrtime_t);
@@ -155,7 +163,9 @@ class RetryQueue
     }
 
     enum AddOptions { RETRY_SCHED_IMM = 0x01 };
-    void add(mc_EXPACKET *pkt, lcb_STATUS, protocol_binary_response_status, errmap::RetrySpec *, int options);
+    /* retry_after_ms is the backoff picked by the retry strategy, 0 uses the retry interval */
+    void add(mc_EXPACKET *pkt, lcb_STATUS, protocol_binary_response_status, errmap::RetrySpec *, int options,
+             uint32_t retry_after_ms);
 
     /** List of operations in retry ordering. Sorted by 'crtime' */
     lcb_list_t schedops{};
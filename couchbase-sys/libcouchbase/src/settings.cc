@@ -63,6 +63,7 @@ void lcb_default_settings(lcb_settings *settings)
     settings->select_bucket = LCB_DEFAULT_SELECT_BUCKET;
     settings->tcp_keepalive = LCB_DEFAULT_TCP_KEEPALIVE;
     settings->config_poll_interval = LCB_DEFAULT_CONFIG_POLL_INTERVAL;
+    settings->config_poll_floor = LCB_CONFIG_POLL_INTERVAL_FLOOR;
     settings->use_collections = 1;
     settings->log_redaction = 0;
     settings->use_tracing = 1;
@@ -81,6 +82,14 @@ void lcb_default_settings(lcb_settings *settings)
     settings->enable_durable_write = 0;
     settings->retry_strategy = lcb_retry_strategy_best_effort;
     settings->enable_unordered_execution = 1;
+    settings->http_compression = 0;
+    settings->enable_config_push = 1;
+    settings->circuit_breaker = LCB_DEFAULT_CIRCUIT_BREAKER;
+    settings->circuit_breaker_volume_threshold = LCB_DEFAULT_CIRCUIT_BREAKER_VOLUME_THRESHOLD;
+    settings->circuit_breaker_error_threshold = LCB_DEFAULT_CIRCUIT_BREAKER_ERROR_THRESHOLD;
+    settings->circuit_breaker_sleep_window = LCB_DEFAULT_CIRCUIT_BREAKER_SLEEP_WINDOW;
+    settings->circuit_breaker_rolling_window = LCB_DEFAULT_CIRCUIT_BREAKER_ROLLING_WINDOW;
+    settings->circuit_breaker_canary_timeout = LCB_DEFAULT_CIRCUIT_BREAKER_CANARY_TIMEOUT;
 }
 
 LCB_INTERNAL_API
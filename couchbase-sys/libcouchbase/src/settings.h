@@ -97,6 +97,13 @@
 /* 50 ms */
 #define LCB_CONFIG_POLL_INTERVAL_FLOOR LCB_MS2US(50)
 
+#define LCB_DEFAULT_CIRCUIT_BREAKER 0
+#define LCB_DEFAULT_CIRCUIT_BREAKER_VOLUME_THRESHOLD 20
+#define LCB_DEFAULT_CIRCUIT_BREAKER_ERROR_THRESHOLD 50
+#define LCB_DEFAULT_CIRCUIT_BREAKER_SLEEP_WINDOW LCB_MS2US(5000)
+#define LCB_DEFAULT_CIRCUIT_BREAKER_ROLLING_WINDOW LCB_MS2US(60000)
+#define LCB_DEFAULT_CIRCUIT_BREAKER_CANARY_TIMEOUT LCB_MS2US(5000)
+
 #define LCBTRACE_DEFAULT_ORPHANED_QUEUE_FLUSH_INTERVAL LCB_MS2US(10000)
 #define LCBTRACE_DEFAULT_ORPHANED_QUEUE_SIZE 128
 #define LCBTRACE_DEFAULT_THRESHOLD_QUEUE_FLUSH_INTERVAL LCB_MS2US(10000)
@@ -161,6 +168,16 @@ typedef struct lcb_settings_st {
     /** Time to wait in between background config polls. 0 disables this */
     lcb_U32 config_poll_interval;
 
+    /** Minimum time in between two config refreshes, and so the lowest poll interval */
+    lcb_U32 config_poll_floor;
+
+    /** Circuit breakers of the data nodes, see LCB_CNTL_CIRCUIT_BREAKER */
+    lcb_U32 circuit_breaker_volume_threshold;
+    lcb_U32 circuit_breaker_error_threshold;
+    lcb_U32 circuit_breaker_sleep_window;
+    lcb_U32 circuit_breaker_rolling_window;
+    lcb_U32 circuit_breaker_canary_timeout;
+
     unsigned bc_http_urltype : 4;
 
     /** Don't guess next vbucket server. Mainly for testing */
@@ -193,6 +210,9 @@ typedef struct lcb_settings_st {
     unsigned wait_for_config : 1;
     unsigned enable_durable_write : 1;
     unsigned enable_unordered_execution : 1;
+    unsigned http_compression : 1;
+    unsigned enable_config_push : 1;
+    unsigned circuit_breaker : 1;
 
     lcb_RETRY_STRATEGY retry_strategy;
     short max_redir;
@@ -224,6 +244,9 @@ typedef struct lcb_settings_st {
     lcb_U32 compress_min_size;
     float compress_min_ratio;
     char *network; /** network resolution, AKA "Multi Network Configurations" */
+    /** Invoked for the certificate of every TLS connection, see lcb_set_certificate_callback */
+    lcb_certificate_callback certificate_callback;
+    lcb_INSTANCE *certificate_instance;
 } lcb_settings;
 
 LCB_INTERNAL_API
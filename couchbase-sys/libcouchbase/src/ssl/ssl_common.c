@@ -23,6 +23,7 @@
 #include "settings.h"
 #include "logging.h"
 #include <openssl/err.h>
+#include <openssl/evp.h>
 #include <openssl/opensslv.h>
 
 #if OPENSSL_VERSION_NUMBER >= 0x1010100fL
@@ -248,6 +249,84 @@ static void log_callback(const SSL *ssl, int where, int ret)
     }
 }
 
+/* Writes the hex encoded SHA-256 of the DER encoded SubjectPublicKeyInfo of the certificate */
+static int spki_sha256(X509 *cert, char *hex)
+{
+    EVP_PKEY *key;
+    unsigned char *der = NULL;
+    unsigned char md[EVP_MAX_MD_SIZE];
+    unsigned int nmd = 0, ii;
+    int nder, rv;
+
+    key = X509_get_pubkey(cert);
+    if (key == NULL) {
+        return 0;
+    }
+    nder = i2d_PUBKEY(key, &der);
+    EVP_PKEY_free(key);
+    if (nder <= 0) {
+        return 0;
+    }
+    rv = EVP_Digest(der, nder, md, &nmd, EVP_sha256(), NULL);
+    OPENSSL_free(der);
+    if (!rv) {
+        return 0;
+    }
+    for (ii = 0; ii < nmd; ii++) {
+        sprintf(hex + ii * 2, "%02x", md[ii]);
+    }
+    return 1;
+}
+
+/*
+ * Also installed when verification is disabled, so the certificate callback sees every
+ * certificate and may fail the handshake.
+ */
+static int verify_callback(int preverify_ok, X509_STORE_CTX *ctx)
+{
+    SSL *ssl = X509_STORE_CTX_get_ex_data(ctx, SSL_get_ex_data_X509_STORE_CTX_idx());
+    lcbio_SOCKET *sock = SSL_get_app_data(ssl);
+    lcb_settings *settings = sock->settings;
+    lcb_CERTIFICATE_INFO info;
+    X509 *cert;
+    char subject[256], issuer[256], spki[EVP_MAX_MD_SIZE * 2 + 1];
+
+    if (!preverify_ok && !(settings->sslopts & LCB_SSL_NOVERIFY)) {
+        return 0;
+    }
+    /* Called for every certificate of the chain, only the one of the node is of interest. With
+     * noverify the callback still sees it, so that certificates can be pinned without a CA */
+    if (X509_STORE_CTX_get_error_depth(ctx) != 0 || settings->certificate_callback == NULL) {
+        return 1;
+    }
+
+    cert = X509_STORE_CTX_get_current_cert(ctx);
+    if (!spki_sha256(cert, spki)) {
+        lcb_log(LOGARGS(ssl, LCB_LOG_ERROR), "sock=%p: Unable to hash the public key of the certificate",
+                (void *)sock);
+        X509_STORE_CTX_set_error(ctx, X509_V_ERR_APPLICATION_VERIFICATION);
+        return 0;
+    }
+    X509_NAME_oneline(X509_get_subject_name(cert), subject, sizeof(subject));
+    X509_NAME_oneline(X509_get_issuer_name(cert), issuer, sizeof(issuer));
+
+    memset(&info, 0, sizeof(info));
+    if (sock->info) {
+        info.host = sock->info->ep_remote.host;
+        info.port = sock->info->ep_remote.port;
+    }
+    info.subject = subject;
+    info.issuer = issuer;
+    info.spki_sha256 = spki;
+    if (settings->certificate_callback(settings->certificate_instance, &info) != 0) {
+        lcb_log(LOGARGS(ssl, LCB_LOG_ERROR), "sock=%p: Certificate rejected. Subject=%s, Issuer=%s, SPKI=%s",
+                (void *)sock, subject, issuer, spki);
+        X509_STORE_CTX_set_error(ctx, X509_V_ERR_APPLICATION_VERIFICATION);
+        return 0;
+    }
+    return 1;
+}
+
 #ifdef LCB_TLS_LOG_KEYS
 static void log_keys_callback(const SSL *ssl, const char *line)
 {
@@ -377,11 +456,9 @@ lcbio_pSSLCTX lcbio_ssl_new(const char *tsfile, const char *cafile, const char *
         }
     }
 
-    if (noverify) {
-        SSL_CTX_set_verify(ret->ctx, SSL_VERIFY_NONE, NULL);
-    } else {
-        SSL_CTX_set_verify(ret->ctx, SSL_VERIFY_PEER, NULL);
-    }
+    /* verify_callback accepts every certificate if noverify is set */
+    (void)noverify;
+    SSL_CTX_set_verify(ret->ctx, SSL_VERIFY_PEER, verify_callback);
 
     SSL_CTX_set_info_callback(ret->ctx, log_callback);
 #ifdef LCB_TLS_LOG_KEYS
@@ -114,6 +114,44 @@ lcb_STATUS lcb_wait(lcb_INSTANCE *instance, lcb_WAITFLAGS flags)
     return instance->last_error;
 }
 
+struct WaitDeadline {
+    lcb_INSTANCE *instance;
+    bool expired;
+};
+
+static void wait_deadline_cb(void *arg)
+{
+    auto *deadline = reinterpret_cast<WaitDeadline *>(arg);
+    deadline->expired = true;
+    lcb_breakout(deadline->instance);
+}
+
+LIBCOUCHBASE_API
+lcb_STATUS lcb_wait_for(lcb_INSTANCE *instance, uint32_t timeout_us)
+{
+    if (instance->wait != 0) {
+        return LCB_ERR_INVALID_ARGUMENT;
+    }
+    if (!has_pending(instance)) {
+        return LCB_SUCCESS;
+    }
+
+    WaitDeadline deadline{instance, false};
+    lcbio_pTIMER timer = lcbio_timer_new(instance->iotable, &deadline, wait_deadline_cb);
+    lcbio_timer_rearm(timer, timeout_us);
+
+    maybe_reset_timeouts(instance);
+    instance->wait = 1;
+    IOT_START(instance->iotable);
+    instance->wait = 0;
+    lcbio_timer_destroy(timer);
+
+    if (deadline.expired && has_pending(instance)) {
+        return LCB_ERR_TIMEOUT;
+    }
+    return LCB_SUCCESS;
+}
+
 /**
  * Stop event loop
  *
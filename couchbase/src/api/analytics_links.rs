@@ -0,0 +1,472 @@
+use crate::io::request::*;
+use crate::io::Core;
+use crate::{
+    CouchbaseError, CouchbaseResult, CreateAnalyticsLinkOptions, DropAnalyticsLinkOptions,
+    ErrorContext, GenericManagementResult, GetAnalyticsLinksOptions, ReplaceAnalyticsLinkOptions,
+    ServiceType,
+};
+use futures::channel::oneshot;
+use serde_derive::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How the analytics service connects to the remote cluster of a link.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalyticsEncryptionLevel {
+    /// Sends everything in plain text.
+    #[default]
+    None,
+    /// Only protects the credentials, the data is sent in plain text.
+    Half,
+    /// Encrypts everything with TLS, authenticating with either the credentials or a client
+    /// certificate.
+    Full,
+}
+
+impl AnalyticsEncryptionLevel {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AnalyticsEncryptionLevel::None => "none",
+            AnalyticsEncryptionLevel::Half => "half",
+            AnalyticsEncryptionLevel::Full => "full",
+        }
+    }
+}
+
+/// A link to a remote Couchbase cluster, whose collections the analytics service can then
+/// shadow in datasets.
+///
+/// Links returned by `AnalyticsLinkManager::get_all_links` never include the password or the
+/// client key.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CouchbaseRemoteAnalyticsLink {
+    #[serde(rename = "scope", alias = "dataverse")]
+    dataverse: String,
+    name: String,
+    #[serde(rename = "activeHostname", alias = "hostname")]
+    hostname: String,
+    #[serde(default)]
+    encryption: AnalyticsEncryptionLevel,
+    username: Option<String>,
+    #[serde(skip)]
+    password: Option<String>,
+    certificate: Option<String>,
+    #[serde(rename = "clientCertificate")]
+    client_certificate: Option<String>,
+    #[serde(skip)]
+    client_key: Option<String>,
+}
+
+impl CouchbaseRemoteAnalyticsLink {
+    /// A link to the cluster at `hostname`, which may include the port of the cluster
+    /// manager.
+    pub fn new<D: Into<String>, N: Into<String>, H: Into<String>>(
+        dataverse: D,
+        name: N,
+        hostname: H,
+    ) -> Self {
+        Self {
+            dataverse: dataverse.into(),
+            name: name.into(),
+            hostname: hostname.into(),
+            encryption: AnalyticsEncryptionLevel::None,
+            username: None,
+            password: None,
+            certificate: None,
+            client_certificate: None,
+            client_key: None,
+        }
+    }
+
+    pub fn credentials<U: Into<String>, P: Into<String>>(
+        mut self,
+        username: U,
+        password: P,
+    ) -> Self {
+        self.username = Some(username.into());
+        self.password = Some(password.into());
+        self
+    }
+
+    pub fn encryption(mut self, encryption: AnalyticsEncryptionLevel) -> Self {
+        self.encryption = encryption;
+        self
+    }
+
+    /// The certificate of the remote cluster in PEM format, needed for full encryption.
+    pub fn certificate<S: Into<String>>(mut self, certificate: S) -> Self {
+        self.certificate = Some(certificate.into());
+        self
+    }
+
+    /// Authenticates with a client certificate and key in PEM format instead of credentials,
+    /// which needs full encryption.
+    pub fn client_certificate<C: Into<String>, K: Into<String>>(
+        mut self,
+        certificate: C,
+        key: K,
+    ) -> Self {
+        self.client_certificate = Some(certificate.into());
+        self.client_key = Some(key.into());
+        self
+    }
+
+    pub fn dataverse(&self) -> &str {
+        &self.dataverse
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    pub fn encryption_level(&self) -> AnalyticsEncryptionLevel {
+        self.encryption
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    /// Checks the link is complete before it is sent to the server.
+    fn validate(&self) -> CouchbaseResult<()> {
+        let reason = if self.dataverse.is_empty() || self.name.is_empty() {
+            Some("the dataverse and name must not be empty")
+        } else if self.hostname.is_empty() {
+            Some("the hostname must not be empty")
+        } else if self.encryption == AnalyticsEncryptionLevel::Full {
+            if self.certificate.is_none() {
+                Some("full encryption needs the certificate of the remote cluster")
+            } else if self.client_certificate.is_none() && self.username.is_none() {
+                Some("full encryption needs credentials or a client certificate")
+            } else {
+                None
+            }
+        } else if self.client_certificate.is_some() {
+            Some("client certificates need full encryption")
+        } else if self.username.is_none() {
+            Some("the link needs credentials")
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => {
+                let mut ctx = ErrorContext::default();
+                ctx.insert("reason", Value::String(reason.into()));
+                Err(CouchbaseError::InvalidArgument { ctx })
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn form(&self) -> String {
+        let mut form = vec![
+            ("type", "couchbase"),
+            ("hostname", self.hostname.as_str()),
+            ("encryption", self.encryption.as_str()),
+        ];
+        let optional = [
+            ("username", &self.username),
+            ("password", &self.password),
+            ("certificate", &self.certificate),
+            ("clientCertificate", &self.client_certificate),
+            ("clientKey", &self.client_key),
+        ];
+        for (key, value) in optional.iter() {
+            if let Some(value) = value {
+                form.push((key, value.as_str()));
+            }
+        }
+        serde_urlencoded::to_string(&form).unwrap()
+    }
+}
+
+/// Manages the links of the analytics service to remote Couchbase clusters.
+///
+/// Needs Couchbase Server 7.0 or later.
+pub struct AnalyticsLinkManager {
+    core: Arc<Core>,
+}
+
+impl AnalyticsLinkManager {
+    pub(crate) fn new(core: Arc<Core>) -> Self {
+        Self { core }
+    }
+
+    /// Creates the link, failing with `LinkExists` if there already is one of the same name.
+    pub async fn create_link(
+        &self,
+        link: CouchbaseRemoteAnalyticsLink,
+        options: CreateAnalyticsLinkOptions,
+    ) -> CouchbaseResult<()> {
+        link.validate()?;
+        let path = link_path(Some(&link.dataverse), Some(&link.name));
+        self.request(path, "post", Some(link.form()), options.timeout)
+            .await?;
+        Ok(())
+    }
+
+    /// Replaces the settings of an existing link, failing with `LinkNotFound` if there is
+    /// none.
+    pub async fn replace_link(
+        &self,
+        link: CouchbaseRemoteAnalyticsLink,
+        options: ReplaceAnalyticsLinkOptions,
+    ) -> CouchbaseResult<()> {
+        link.validate()?;
+        let path = link_path(Some(&link.dataverse), Some(&link.name));
+        self.request(path, "put", Some(link.form()), options.timeout)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn drop_link<D: Into<String>, N: Into<String>>(
+        &self,
+        dataverse: D,
+        name: N,
+        options: DropAnalyticsLinkOptions,
+    ) -> CouchbaseResult<()> {
+        let path = link_path(Some(&dataverse.into()), Some(&name.into()));
+        self.request(path, "delete", None, options.timeout).await?;
+        Ok(())
+    }
+
+    /// Returns the links to remote Couchbase clusters, of all dataverses unless one is set on
+    /// the options.
+    pub async fn get_all_links(
+        &self,
+        options: GetAnalyticsLinksOptions,
+    ) -> CouchbaseResult<Vec<CouchbaseRemoteAnalyticsLink>> {
+        if options.name.is_some() && options.dataverse.is_none() {
+            let mut ctx = ErrorContext::default();
+            ctx.insert(
+                "reason",
+                Value::String("the name of a link needs its dataverse".into()),
+            );
+            return Err(CouchbaseError::InvalidArgument { ctx });
+        }
+        let path = format!(
+            "{}?type=couchbase",
+            link_path(options.dataverse.as_deref(), options.name.as_deref())
+        );
+        let result = self.request(path, "get", None, options.timeout).await?;
+        serde_json::from_slice(result.payload().map(Vec::as_slice).unwrap_or(b"[]")).map_err(|e| {
+            CouchbaseError::DecodingFailure {
+                ctx: ErrorContext::default(),
+                source: e.into(),
+            }
+        })
+    }
+
+    async fn request(
+        &self,
+        path: String,
+        method: &str,
+        payload: Option<String>,
+        timeout: Option<Duration>,
+    ) -> CouchbaseResult<GenericManagementResult> {
+        let (sender, receiver) = oneshot::channel();
+        let content_type = payload
+            .as_ref()
+            .map(|_| String::from("application/x-www-form-urlencoded"));
+        let timer = self.core.send(Request::GenericManagementRequest(
+            GenericManagementRequest {
+                sender,
+                path,
+                method: String::from(method),
+                payload,
+                content_type,
+                timeout,
+                service_type: Some(ServiceType::Analytics),
+                endpoint: None,
+            },
+        ));
+
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
+        match result.http_status() {
+            200 | 201 => Ok(result),
+            _ => Err(parse_error(&result)),
+        }
+    }
+}
+
+/// The path of the links of a dataverse, or of a single link.
+///
+/// Dataverse names with multiple parts (`bucket/scope`) are sent as a single path segment.
+fn link_path(dataverse: Option<&str>, name: Option<&str>) -> String {
+    let mut path = String::from("/analytics/link");
+    for segment in dataverse.iter().chain(name.iter()) {
+        path.push('/');
+        path.push_str(&encode_segment(segment));
+    }
+    path
+}
+
+fn encode_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Maps the error codes of the analytics service, which reports errors as
+/// `{"errors": [{"code": 24055, "msg": "..."}]}`.
+fn parse_error(result: &GenericManagementResult) -> CouchbaseError {
+    let payload = result.payload().map(Vec::as_slice).unwrap_or_default();
+    let message = String::from_utf8_lossy(payload).into_owned();
+    let code = serde_json::from_slice::<Value>(payload)
+        .ok()
+        .and_then(|body| body.pointer("/errors/0/code").and_then(Value::as_u64));
+
+    let mut ctx = ErrorContext::default();
+    if let Some(code) = code {
+        ctx.insert("code", Value::from(code));
+    }
+    ctx.insert("message", Value::String(message.clone()));
+    match code {
+        Some(24006) => CouchbaseError::LinkNotFound { ctx },
+        Some(24055) => CouchbaseError::LinkExists { ctx },
+        Some(24034) => CouchbaseError::DataverseNotFound { ctx },
+        _ => CouchbaseError::GenericHTTP {
+            ctx,
+            status: result.http_status(),
+            message,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link() -> CouchbaseRemoteAnalyticsLink {
+        CouchbaseRemoteAnalyticsLink::new("travel/inventory", "remote", "10.0.0.1:8091")
+    }
+
+    fn reason(link: &CouchbaseRemoteAnalyticsLink) -> Option<String> {
+        match link.validate() {
+            Ok(()) => None,
+            Err(CouchbaseError::InvalidArgument { ctx }) => {
+                Some(ctx.get("reason").unwrap().as_str().unwrap().to_string())
+            }
+            Err(e) => panic!("unexpected {:?}", e),
+        }
+    }
+
+    #[test]
+    fn validates_links() {
+        assert_eq!(reason(&link().credentials("user", "pass")), None);
+        assert_eq!(
+            reason(&CouchbaseRemoteAnalyticsLink::new("", "remote", "h").credentials("u", "p")),
+            Some("the dataverse and name must not be empty".into())
+        );
+        assert_eq!(
+            reason(&CouchbaseRemoteAnalyticsLink::new("d", "remote", "").credentials("u", "p")),
+            Some("the hostname must not be empty".into())
+        );
+        assert_eq!(reason(&link()), Some("the link needs credentials".into()));
+        assert_eq!(
+            reason(&link().client_certificate("cert", "key")),
+            Some("client certificates need full encryption".into())
+        );
+
+        let full = link().encryption(AnalyticsEncryptionLevel::Full);
+        assert_eq!(
+            reason(&full.clone().credentials("u", "p")),
+            Some("full encryption needs the certificate of the remote cluster".into())
+        );
+        assert_eq!(
+            reason(&full.clone().certificate("ca")),
+            Some("full encryption needs credentials or a client certificate".into())
+        );
+        assert_eq!(
+            reason(&full.clone().certificate("ca").credentials("u", "p")),
+            None
+        );
+        assert_eq!(
+            reason(&full.certificate("ca").client_certificate("cert", "key")),
+            None
+        );
+    }
+
+    #[test]
+    fn encodes_the_link_as_a_form() {
+        assert_eq!(
+            link().credentials("user", "p&ss word").form(),
+            "type=couchbase&hostname=10.0.0.1%3A8091&encryption=none&username=user&password=p%26ss+word"
+        );
+        assert_eq!(
+            link()
+                .encryption(AnalyticsEncryptionLevel::Full)
+                .certificate("ca")
+                .client_certificate("cert", "key")
+                .form(),
+            "type=couchbase&hostname=10.0.0.1%3A8091&encryption=full&certificate=ca&clientCertificate=cert&clientKey=key"
+        );
+    }
+
+    #[test]
+    fn builds_link_paths() {
+        assert_eq!(link_path(None, None), "/analytics/link");
+        assert_eq!(link_path(Some("Default"), None), "/analytics/link/Default");
+        // Multiple part dataverses are a single segment
+        assert_eq!(
+            link_path(Some("travel/inventory"), Some("my link")),
+            "/analytics/link/travel%2Finventory/my%20link"
+        );
+        assert_eq!(encode_segment("a-b_c.d~e"), "a-b_c.d~e");
+    }
+
+    fn error(status: u16, body: &str) -> CouchbaseError {
+        parse_error(&GenericManagementResult::new(
+            status,
+            Some(body.as_bytes().to_vec()),
+        ))
+    }
+
+    #[test]
+    fn maps_analytics_errors() {
+        match error(
+            404,
+            r#"{"errors": [{"code": 24006, "msg": "Link does not exist"}]}"#,
+        ) {
+            CouchbaseError::LinkNotFound { ctx } => {
+                assert_eq!(ctx.get("code"), Some(&Value::from(24006)))
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+        assert!(matches!(
+            error(
+                409,
+                r#"{"errors": [{"code": 24055, "msg": "Link already exists"}]}"#
+            ),
+            CouchbaseError::LinkExists { .. }
+        ));
+        assert!(matches!(
+            error(
+                404,
+                r#"{"errors": [{"code": 24034, "msg": "Cannot find dataverse"}]}"#
+            ),
+            CouchbaseError::DataverseNotFound { .. }
+        ));
+        match error(500, "Internal Server Error") {
+            CouchbaseError::GenericHTTP {
+                status, message, ..
+            } => {
+                assert_eq!(status, 500);
+                assert_eq!(message, "Internal Server Error");
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+}
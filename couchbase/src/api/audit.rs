@@ -0,0 +1,235 @@
+use crate::io::request::*;
+use crate::io::Core;
+use crate::{
+    CouchbaseError, CouchbaseResult, ErrorContext, GenericManagementResult,
+    GetAuditEventDescriptorsOptions, GetAuditSettingsOptions, UpdateAuditSettingsOptions,
+};
+use futures::channel::oneshot;
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The audit configuration of the cluster.
+///
+/// Settings which are not set keep their current value when updated, so the usual flow is
+/// to fetch the settings, change them and pass them to `AuditManager::update_settings`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuditSettings {
+    #[serde(rename = "auditdEnabled")]
+    enabled: bool,
+    #[serde(rename = "disabled", default)]
+    disabled_events: Option<Vec<u32>>,
+    #[serde(rename = "logPath")]
+    log_path: Option<String>,
+    #[serde(rename = "rotateInterval")]
+    rotate_interval: Option<u64>,
+    #[serde(rename = "rotateSize")]
+    rotate_size: Option<u64>,
+}
+
+impl AuditSettings {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            disabled_events: None,
+            log_path: None,
+            rotate_interval: None,
+            rotate_size: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled
+    }
+
+    /// The ids of the events which are not audited, see `AuditManager::get_event_descriptors`.
+    pub fn disabled_events(&self) -> Option<&Vec<u32>> {
+        self.disabled_events.as_ref()
+    }
+
+    pub fn set_disabled_events(&mut self, events: Vec<u32>) {
+        self.disabled_events = Some(events)
+    }
+
+    pub fn log_path(&self) -> Option<String> {
+        self.log_path.clone()
+    }
+
+    pub fn set_log_path(&mut self, log_path: String) {
+        self.log_path = Some(log_path)
+    }
+
+    /// How often the audit log is rotated.
+    pub fn rotate_interval(&self) -> Option<Duration> {
+        self.rotate_interval.map(Duration::from_secs)
+    }
+
+    pub fn set_rotate_interval(&mut self, interval: Duration) {
+        self.rotate_interval = Some(interval.as_secs())
+    }
+
+    /// The size in bytes after which the audit log is rotated.
+    pub fn rotate_size(&self) -> Option<u64> {
+        self.rotate_size
+    }
+
+    pub fn set_rotate_size(&mut self, size: u64) {
+        self.rotate_size = Some(size)
+    }
+}
+
+/// Describes an event which can be audited.
+#[derive(Debug, Deserialize)]
+pub struct AuditEventDescriptor {
+    id: u32,
+    name: String,
+    module: String,
+    #[serde(default)]
+    description: String,
+}
+
+impl AuditEventDescriptor {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+pub struct AuditManager {
+    core: Arc<Core>,
+}
+
+impl AuditManager {
+    pub(crate) fn new(core: Arc<Core>) -> Self {
+        Self { core }
+    }
+
+    pub async fn get_settings(
+        &self,
+        options: GetAuditSettingsOptions,
+    ) -> CouchbaseResult<AuditSettings> {
+        self.get(String::from("/settings/audit"), options.timeout)
+            .await
+    }
+
+    pub async fn update_settings(
+        &self,
+        settings: AuditSettings,
+        options: UpdateAuditSettingsOptions,
+    ) -> CouchbaseResult<()> {
+        // The server expects form data so we need to build that, serde expects each value to be an
+        // Option.
+        let form = &[
+            ("auditdEnabled", Some(settings.enabled.to_string())),
+            (
+                "disabled",
+                settings.disabled_events.map(|events| {
+                    events
+                        .iter()
+                        .map(|e| e.to_string())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                }),
+            ),
+            ("logPath", settings.log_path),
+            (
+                "rotateInterval",
+                settings.rotate_interval.map(|i| i.to_string()),
+            ),
+            ("rotateSize", settings.rotate_size.map(|s| s.to_string())),
+        ];
+
+        let form_encoded = serde_urlencoded::to_string(form).unwrap();
+        let content_type = String::from("application/x-www-form-urlencoded");
+        let (sender, receiver) = oneshot::channel();
+
+        let timer = self.core.send(Request::GenericManagementRequest(
+            GenericManagementRequest {
+                sender,
+                path: String::from("/settings/audit"),
+                method: String::from("post"),
+                payload: Some(form_encoded),
+                content_type: Some(content_type),
+                timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
+            },
+        ));
+
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
+
+        match result.http_status() {
+            200 => Ok(()),
+            _ => Err(self.parse_error(&result)),
+        }
+    }
+
+    /// Lists all events the cluster is able to audit.
+    pub async fn get_event_descriptors(
+        &self,
+        options: GetAuditEventDescriptorsOptions,
+    ) -> CouchbaseResult<Vec<AuditEventDescriptor>> {
+        self.get(String::from("/settings/audit/descriptors"), options.timeout)
+            .await
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: String,
+        timeout: Option<Duration>,
+    ) -> CouchbaseResult<T> {
+        let (sender, receiver) = oneshot::channel();
+
+        let timer = self.core.send(Request::GenericManagementRequest(
+            GenericManagementRequest {
+                sender,
+                path,
+                method: String::from("get"),
+                payload: None,
+                content_type: None,
+                timeout,
+                service_type: None,
+                endpoint: None,
+            },
+        ));
+
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
+
+        match result.http_status() {
+            200 => serde_json::from_slice(result.payload().unwrap()).map_err(|e| {
+                CouchbaseError::DecodingFailure {
+                    ctx: ErrorContext::default(),
+                    source: e.into(),
+                }
+            }),
+            _ => Err(self.parse_error(&result)),
+        }
+    }
+
+    fn parse_error(&self, result: &GenericManagementResult) -> CouchbaseError {
+        CouchbaseError::GenericHTTP {
+            ctx: ErrorContext::default(),
+            status: result.http_status(),
+            message: match result.payload() {
+                Some(p) => String::from_utf8_lossy(p).to_lowercase(),
+                None => String::new(),
+            },
+        }
+    }
+}
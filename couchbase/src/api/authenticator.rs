@@ -0,0 +1,140 @@
+use crate::api::results::ServiceType;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Authenticates against the cluster with a client certificate instead of a password.
+///
+/// The certificate is presented on all key/value and HTTP connections, so no SASL
+/// authentication takes place. This requires TLS, so the connection string has to use the
+/// `couchbases://` scheme.
+///
+/// ```no_run
+/// # use couchbase::*;
+/// # fn run() -> CouchbaseResult<()> {
+/// let authenticator = CertificateAuthenticator::new("/certs/client.pem", "/certs/client.key")
+///     .trust_store_path("/certs/ca.pem");
+/// let cluster = Cluster::connect_with_certificate(
+///     "couchbases://127.0.0.1",
+///     authenticator,
+///     ClusterOptions::default(),
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CertificateAuthenticator {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    trust_store_path: Option<PathBuf>,
+}
+
+impl CertificateAuthenticator {
+    /// Creates a new authenticator from the PEM encoded client certificate and private key.
+    pub fn new<P: Into<PathBuf>>(cert_path: P, key_path: P) -> Self {
+        Self {
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
+            trust_store_path: None,
+        }
+    }
+
+    /// The PEM encoded certificates used to verify the certificates of the cluster.
+    pub fn trust_store_path<P: Into<PathBuf>>(mut self, trust_store_path: P) -> Self {
+        self.trust_store_path = Some(trust_store_path.into());
+        self
+    }
+
+    pub fn cert_path(&self) -> &Path {
+        &self.cert_path
+    }
+
+    pub fn key_path(&self) -> &Path {
+        &self.key_path
+    }
+
+    /// Converts the authenticator into connection string parameters.
+    pub(crate) fn connection_string_params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![
+            ("certpath", encode_path(&self.cert_path)),
+            ("keypath", encode_path(&self.key_path)),
+        ];
+        if let Some(path) = &self.trust_store_path {
+            params.push(("truststorepath", encode_path(path)));
+        }
+        params
+    }
+}
+
+/// Percent-encodes a path so it can be used as a connection string value.
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Hands out the credentials whenever a connection authenticates.
+///
+/// Every new key/value connection and every HTTP request asks the provider again, so
+/// short-lived credentials can be rotated without reconnecting the cluster. Connections
+/// which are already authenticated keep using the credentials they were opened with.
+///
+/// The provider is called on the IO thread, so it should return quickly and must not panic.
+pub trait CredentialProvider: Send + Sync {
+    /// Returns the username and password for the service at the given `host:port` endpoint.
+    ///
+    /// Both are `None` when the SDK does not know yet which node it will talk to.
+    fn credentials(&self, service: Option<ServiceType>, endpoint: Option<&str>)
+        -> (String, String);
+}
+
+/// A `CredentialProvider` whose username and password can be swapped at any time.
+///
+/// ```no_run
+/// # use couchbase::*;
+/// # use std::sync::Arc;
+/// # fn run() -> CouchbaseResult<()> {
+/// let credentials = Arc::new(RotatingCredentials::new("user", "initial"));
+/// let cluster = Cluster::connect_with_credential_provider(
+///     "couchbase://127.0.0.1",
+///     credentials.clone(),
+///     ClusterOptions::default(),
+/// )?;
+/// // later on, once the secret got rotated
+/// credentials.rotate("user", "rotated");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RotatingCredentials {
+    current: RwLock<(String, String)>,
+}
+
+impl RotatingCredentials {
+    pub fn new<S: Into<String>>(username: S, password: S) -> Self {
+        Self {
+            current: RwLock::new((username.into(), password.into())),
+        }
+    }
+
+    /// Replaces the credentials used from now on.
+    pub fn rotate<S: Into<String>>(&self, username: S, password: S) {
+        *self.current.write().unwrap() = (username.into(), password.into());
+    }
+}
+
+impl CredentialProvider for RotatingCredentials {
+    fn credentials(
+        &self,
+        _service: Option<ServiceType>,
+        _endpoint: Option<&str>,
+    ) -> (String, String) {
+        self.current.read().unwrap().clone()
+    }
+}
@@ -7,9 +7,10 @@ use crate::CouchbaseError::{
 use crate::{
     CouchbaseError, CouchbaseResult, CreateBucketOptions, DropBucketOptions, ErrorContext,
     FlushBucketOptions, GenericManagementResult, GetAllBucketsOptions, GetBucketOptions,
-    UpdateBucketOptions,
+    InstallSampleBucketOptions, UpdateBucketOptions,
 };
 use futures::channel::oneshot;
+use futures_timer::Delay;
 use serde_derive::Deserialize;
 use serde_json::Value;
 use std::collections::HashMap;
@@ -17,7 +18,7 @@ use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, Copy)]
 pub enum BucketType {
@@ -319,7 +320,7 @@ impl BucketSettings {
         Ok(BucketSettings {
             name: settings.name,
             ram_quota_mb: settings.quota.raw_ram / 1024 / 1024,
-            flush_enabled: settings.controllers.flush != "",
+            flush_enabled: !settings.controllers.flush.is_empty(),
             num_replicas: settings.num_replicas,
             replica_indexes: settings.replica_indexes,
             bucket_type: BucketType::try_from(settings.bucket_type.as_str())?,
@@ -523,6 +524,100 @@ impl BucketSettings {
     }
 }
 
+/// How long `BucketManager::install_sample` waits for a sample to be loaded by default.
+const DEFAULT_SAMPLE_LOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+const SAMPLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the loading task of a sample may take to show up in the tasks of the cluster.
+///
+/// The task is only listed once the cluster picked up the install, and older servers remove it
+/// again once the sample has been loaded. If it never shows up, the sample is assumed to have
+/// been loaded before the first poll.
+const SAMPLE_LISTING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The progress of a sample bucket being installed, see `SampleLoadListener`.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum SampleLoadProgress {
+    /// The cluster accepted the sample, but has not started to load it yet.
+    Queued,
+    /// The documents are being loaded, `items` of them so far if the bucket statistics could be
+    /// read.
+    Loading { items: Option<u64> },
+    /// The sample has been loaded.
+    Loaded,
+}
+
+/// Follows the progress of `BucketManager::install_sample`, see
+/// `InstallSampleBucketOptions::progress`.
+///
+/// It is called once per poll of the cluster, which happens every second.
+pub trait SampleLoadListener: fmt::Debug + Send + Sync {
+    fn on_progress(&self, name: &str, progress: &SampleLoadProgress);
+}
+
+#[derive(Debug, Deserialize)]
+struct JSONTask {
+    #[serde(rename = "type")]
+    task_type: String,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    bucket: Option<String>,
+}
+
+/// The state of the task loading a sample bucket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SampleTask {
+    NotListed,
+    Queued,
+    Running,
+    Finished,
+}
+
+impl SampleTask {
+    fn find(tasks: &[JSONTask], name: &str) -> Self {
+        // Tasks of earlier installs of the same sample may still be listed as finished
+        tasks
+            .iter()
+            .filter(|t| t.task_type == "loadingSampleBucket" && t.bucket.as_deref() == Some(name))
+            .map(|t| match t.status.as_str() {
+                "queued" => SampleTask::Queued,
+                "running" => SampleTask::Running,
+                _ => SampleTask::Finished,
+            })
+            .min_by_key(|task| match task {
+                SampleTask::Running => 0,
+                SampleTask::Queued => 1,
+                _ => 2,
+            })
+            .unwrap_or(SampleTask::NotListed)
+    }
+
+    /// Whether the sample is still to be loaded, given whether its task has been listed before
+    /// and how long ago it has been installed.
+    fn pending(self, listed: bool, waited: Duration) -> bool {
+        match self {
+            SampleTask::Queued | SampleTask::Running => true,
+            SampleTask::Finished => false,
+            SampleTask::NotListed => !listed && waited < SAMPLE_LISTING_TIMEOUT,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JSONBucketStats {
+    #[serde(rename = "basicStats")]
+    basic_stats: JSONBasicStats,
+}
+
+#[derive(Debug, Deserialize)]
+struct JSONBasicStats {
+    #[serde(rename = "itemCount")]
+    item_count: u64,
+}
+
 pub struct BucketManager {
     core: Arc<Core>,
 }
@@ -545,18 +640,20 @@ impl BucketManager {
         let content_type = String::from("application/x-www-form-urlencoded");
         let (sender, receiver) = oneshot::channel();
 
-        self.core.send(Request::GenericManagementRequest(
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
-                path: format!("/pools/default/buckets/"),
+                path: "/pools/default/buckets/".to_string(),
                 method: String::from("post"),
                 payload: Some(form_encoded),
                 content_type: Some(content_type),
                 timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
 
         match result.http_status() {
             202 => Ok(()),
@@ -583,7 +680,7 @@ impl BucketManager {
         let content_type = String::from("application/x-www-form-urlencoded");
         let (sender, receiver) = oneshot::channel();
 
-        self.core.send(Request::GenericManagementRequest(
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
                 path: format!("/pools/default/buckets/{}", settings.name),
@@ -591,13 +688,19 @@ impl BucketManager {
                 payload: Some(form_encoded),
                 content_type: Some(content_type),
                 timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
 
         match result.http_status() {
-            200 => Ok(()),
+            200 => {
+                self.core
+                    .set_minimum_durability_level(settings.name, settings.durability_level);
+                Ok(())
+            }
             _ => Err(self.parse_error(
                 result.http_status(),
                 String::from_utf8(result.payload().unwrap().to_owned())
@@ -616,7 +719,7 @@ impl BucketManager {
         let (sender, receiver) = oneshot::channel();
 
         let bucket_name = name.into();
-        self.core.send(Request::GenericManagementRequest(
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
                 path: format!("/pools/default/buckets/{}", &bucket_name),
@@ -624,13 +727,19 @@ impl BucketManager {
                 payload: None,
                 content_type: None,
                 timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
 
         match result.http_status() {
-            200 => Ok(()),
+            200 => {
+                self.core
+                    .set_minimum_durability_level(bucket_name, DurabilityLevel::None);
+                Ok(())
+            }
             _ => Err(self.parse_error(
                 result.http_status(),
                 String::from_utf8(result.payload().unwrap().to_owned())
@@ -649,7 +758,7 @@ impl BucketManager {
         let (sender, receiver) = oneshot::channel();
 
         let bucket_name = name.into();
-        self.core.send(Request::GenericManagementRequest(
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
                 path: format!("/pools/default/buckets/{}", &bucket_name),
@@ -657,10 +766,12 @@ impl BucketManager {
                 payload: None,
                 content_type: None,
                 timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
 
         let bucket_data: JSONBucketSettings = match result.http_status() {
             200 => serde_json::from_slice(result.payload().unwrap()).map_err(|e| {
@@ -678,7 +789,10 @@ impl BucketManager {
             )),
         }?;
 
-        BucketSettings::from(bucket_data)
+        let settings = BucketSettings::from(bucket_data)?;
+        self.core
+            .set_minimum_durability_level(settings.name.clone(), settings.durability_level);
+        Ok(settings)
     }
 
     pub async fn get_all_buckets(
@@ -687,18 +801,20 @@ impl BucketManager {
     ) -> CouchbaseResult<HashMap<String, BucketSettings>> {
         let (sender, receiver) = oneshot::channel();
 
-        self.core.send(Request::GenericManagementRequest(
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
-                path: format!("/pools/default/buckets"),
+                path: "/pools/default/buckets".to_string(),
                 method: String::from("get"),
                 payload: None,
                 content_type: None,
                 timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
 
         let bucket_data: Vec<JSONBucketSettings> = match result.http_status() {
             200 => serde_json::from_slice(result.payload().unwrap()).map_err(|e| {
@@ -733,7 +849,7 @@ impl BucketManager {
         let (sender, receiver) = oneshot::channel();
 
         let bucket_name = name.into();
-        self.core.send(Request::GenericManagementRequest(
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
                 path: format!("/pools/default/buckets/{}/controller/doFlush", &bucket_name),
@@ -741,10 +857,12 @@ impl BucketManager {
                 payload: None,
                 content_type: None,
                 timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
 
         match result.http_status() {
             200 => Ok(()),
@@ -758,6 +876,151 @@ impl BucketManager {
         }
     }
 
+    /// Installs one of the sample buckets shipped with Couchbase Server, like `travel-sample` or
+    /// `beer-sample`, and waits until its documents have been loaded.
+    ///
+    /// Fails with `BucketExists` if the sample is already installed, with `InvalidArgument` if
+    /// there is no sample of that name and with `Timeout` if loading it takes longer than
+    /// `InstallSampleBucketOptions::load_timeout`. The sample keeps loading in that case.
+    ///
+    /// The cluster is polled for the loading task of the sample every second, the progress can
+    /// be followed through `InstallSampleBucketOptions::progress`.
+    pub async fn install_sample<S: Into<String>>(
+        &self,
+        name: S,
+        options: InstallSampleBucketOptions,
+    ) -> CouchbaseResult<()> {
+        let name = name.into();
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::GenericManagementRequest(
+            GenericManagementRequest {
+                sender,
+                path: String::from("/sampleBuckets/install"),
+                method: String::from("post"),
+                payload: Some(Value::from(vec![name.clone()]).to_string()),
+                content_type: Some(String::from("application/json")),
+                timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
+            },
+        ));
+
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
+        if result.http_status() != 202 {
+            return Err(self.parse_error(
+                result.http_status(),
+                String::from_utf8_lossy(result.payload().map_or(&[], |p| p.as_slice()))
+                    .to_lowercase(),
+                name,
+            ));
+        }
+
+        let installed = Instant::now();
+        let deadline = installed + options.load_timeout.unwrap_or(DEFAULT_SAMPLE_LOAD_TIMEOUT);
+        let mut listed = false;
+        loop {
+            let task = self.sample_task(&name, options.timeout).await?;
+            listed |= task != SampleTask::NotListed;
+            let pending = task.pending(listed, installed.elapsed());
+            if let Some(listener) = &options.progress {
+                let progress = match task {
+                    _ if !pending => SampleLoadProgress::Loaded,
+                    SampleTask::Running => SampleLoadProgress::Loading {
+                        items: self.item_count(&name, options.timeout).await.ok(),
+                    },
+                    _ => SampleLoadProgress::Queued,
+                };
+                listener.on_progress(&name, &progress);
+            }
+            if !pending {
+                return Ok(());
+            }
+            if Instant::now() + SAMPLE_POLL_INTERVAL >= deadline {
+                let mut ctx = ErrorContext::default();
+                ctx.insert("name", Value::String(name));
+                ctx.insert(
+                    "reason",
+                    Value::String("the sample is still being loaded".into()),
+                );
+                return Err(CouchbaseError::Timeout {
+                    ambiguous: false,
+                    ctx,
+                });
+            }
+            Delay::new(SAMPLE_POLL_INTERVAL).await;
+        }
+    }
+
+    /// The state of the task loading the documents of the sample bucket.
+    async fn sample_task(
+        &self,
+        name: &str,
+        timeout: Option<Duration>,
+    ) -> CouchbaseResult<SampleTask> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::GenericManagementRequest(
+            GenericManagementRequest {
+                sender,
+                path: String::from("/pools/default/tasks"),
+                method: String::from("get"),
+                payload: None,
+                content_type: None,
+                timeout,
+                service_type: None,
+                endpoint: None,
+            },
+        ));
+
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
+        let payload = result.payload().map_or(&[][..], |p| p.as_slice());
+        if result.http_status() != 200 {
+            return Err(self.parse_error(
+                result.http_status(),
+                String::from_utf8_lossy(payload).to_lowercase(),
+                name,
+            ));
+        }
+        let tasks: Vec<JSONTask> =
+            serde_json::from_slice(payload).map_err(|e| CouchbaseError::DecodingFailure {
+                ctx: ErrorContext::default(),
+                source: e.into(),
+            })?;
+        Ok(SampleTask::find(&tasks, name))
+    }
+
+    /// The number of documents in the bucket.
+    async fn item_count(&self, name: &str, timeout: Option<Duration>) -> CouchbaseResult<u64> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::GenericManagementRequest(
+            GenericManagementRequest {
+                sender,
+                path: format!("/pools/default/buckets/{}", name),
+                method: String::from("get"),
+                payload: None,
+                content_type: None,
+                timeout,
+                service_type: None,
+                endpoint: None,
+            },
+        ));
+
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
+        let payload = result.payload().map_or(&[][..], |p| p.as_slice());
+        if result.http_status() != 200 {
+            return Err(self.parse_error(
+                result.http_status(),
+                String::from_utf8_lossy(payload).to_lowercase(),
+                name,
+            ));
+        }
+        let stats: JSONBucketStats =
+            serde_json::from_slice(payload).map_err(|e| CouchbaseError::DecodingFailure {
+                ctx: ErrorContext::default(),
+                source: e.into(),
+            })?;
+        Ok(stats.basic_stats.item_count)
+    }
+
     fn parse_error<S: Into<String>>(
         &self,
         status: u16,
@@ -774,11 +1037,28 @@ impl BucketManager {
             ctx.insert("name", Value::String(bucket_name.into()));
             return BucketExists { ctx };
         }
+        if message.contains("already loaded") {
+            let mut ctx = ErrorContext::default();
+            ctx.insert("name", Value::String(bucket_name.into()));
+            return BucketExists { ctx };
+        }
+        if message.contains("not a valid sample") {
+            let mut ctx = ErrorContext::default();
+            ctx.insert("name", Value::String(bucket_name.into()));
+            ctx.insert("message", Value::String(message));
+            return InvalidArgument { ctx };
+        }
         if message.contains("flush is disabled") {
             let mut ctx = ErrorContext::default();
             ctx.insert("name", Value::String(bucket_name.into()));
             return BucketNotFlushable { ctx };
         }
+        if status == 409 {
+            let mut ctx = ErrorContext::default();
+            ctx.insert("name", Value::String(bucket_name.into()));
+            ctx.insert("message", Value::String(message));
+            return CouchbaseError::ConcurrentModification { ctx };
+        }
 
         CouchbaseError::GenericHTTP {
             ctx: Default::default(),
@@ -787,3 +1067,76 @@ impl BucketManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tasks(json: &str) -> Vec<JSONTask> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn finds_the_loading_task_of_the_sample() {
+        let listed = tasks(
+            r#"[
+                {"type": "rebalance", "status": "notRunning"},
+                {"type": "loadingSampleBucket", "status": "running", "bucket": "beer-sample"},
+                {"type": "loadingSampleBucket", "status": "queued", "bucket": "travel-sample"}
+            ]"#,
+        );
+        assert_eq!(
+            SampleTask::find(&listed, "travel-sample"),
+            SampleTask::Queued
+        );
+        assert_eq!(
+            SampleTask::find(&listed, "beer-sample"),
+            SampleTask::Running
+        );
+        assert_eq!(
+            SampleTask::find(&listed, "gamesim-sample"),
+            SampleTask::NotListed
+        );
+    }
+
+    #[test]
+    fn prefers_the_task_of_the_current_install() {
+        let listed = tasks(
+            r#"[
+                {"type": "loadingSampleBucket", "status": "completed", "bucket": "travel-sample"},
+                {"type": "loadingSampleBucket", "status": "running", "bucket": "travel-sample"}
+            ]"#,
+        );
+        assert_eq!(
+            SampleTask::find(&listed, "travel-sample"),
+            SampleTask::Running
+        );
+        assert_eq!(
+            SampleTask::find(&listed[..1], "travel-sample"),
+            SampleTask::Finished
+        );
+    }
+
+    #[test]
+    fn waits_for_queued_and_unlisted_samples() {
+        let early = Duration::from_secs(1);
+        assert!(SampleTask::Queued.pending(true, early));
+        assert!(SampleTask::Running.pending(true, early));
+        assert!(!SampleTask::Finished.pending(true, early));
+        // Not listed yet
+        assert!(SampleTask::NotListed.pending(false, early));
+        // Removed once loaded
+        assert!(!SampleTask::NotListed.pending(true, early));
+        // Never listed, so loaded before the first poll
+        assert!(!SampleTask::NotListed.pending(false, SAMPLE_LISTING_TIMEOUT));
+    }
+
+    #[test]
+    fn reads_the_item_count() {
+        let stats: JSONBucketStats = serde_json::from_str(
+            r#"{"name": "travel-sample", "basicStats": {"itemCount": 31591}}"#,
+        )
+        .unwrap();
+        assert_eq!(stats.basic_stats.item_count, 31591);
+    }
+}
@@ -0,0 +1,456 @@
+use crate::api::error::CouchbaseResult;
+use crate::api::metrics::Meter;
+use crate::api::options::*;
+use crate::api::results::{GetResult, MutationResult};
+use crate::api::Collection;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Configures the read-through cache of a `CachedCollection`.
+#[derive(Debug, Clone)]
+pub struct CacheOptions {
+    pub(crate) capacity: usize,
+    pub(crate) ttl: Duration,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            ttl: Duration::from_secs(1),
+        }
+    }
+}
+
+impl CacheOptions {
+    /// The maximum number of documents kept, the least recently used one is evicted first.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// How long a document is served from the cache before it is read from the server again.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+}
+
+/// Counters of the accesses to a read-through cache.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CacheStats {
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl CacheStats {
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// The number of documents evicted to make room for new ones.
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    /// The share of reads served from the cache, between 0 and 1.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+struct CacheEntry {
+    result: GetResult,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+// The reads of a document which are in flight
+struct PendingReads {
+    // Bumped on every invalidation of the document, so reads racing with a write do not
+    // cache stale content
+    generation: u64,
+    count: usize,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<String, CacheEntry>,
+    // The ids of the entries by their last use, the least recently used first
+    lru: BTreeMap<u64, String>,
+    // Bumped on every access, to order the entries by their last use
+    clock: u64,
+    reads: HashMap<String, PendingReads>,
+    stats: CacheStats,
+}
+
+impl CacheState {
+    fn lookup(&mut self, id: &str, now: Instant) -> Option<GetResult> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(id).filter(|e| e.expires_at > now)?;
+        self.lru.remove(&entry.last_used);
+        entry.last_used = self.clock;
+        self.lru.insert(self.clock, id.to_string());
+        Some(entry.result.clone())
+    }
+
+    /// Returns the generation of the document the read starts at.
+    fn begin_read(&mut self, id: &str) -> u64 {
+        let reads = self.reads.entry(id.to_string()).or_insert(PendingReads {
+            generation: 0,
+            count: 0,
+        });
+        reads.count += 1;
+        reads.generation
+    }
+
+    /// Caches the result of the read, unless the document was invalidated since it started.
+    fn end_read(
+        &mut self,
+        id: &str,
+        generation: u64,
+        result: Option<GetResult>,
+        options: &CacheOptions,
+        now: Instant,
+    ) {
+        let current = match self.reads.get_mut(id) {
+            Some(reads) => {
+                reads.count -= 1;
+                let current = reads.generation;
+                if reads.count == 0 {
+                    self.reads.remove(id);
+                }
+                current
+            }
+            None => return,
+        };
+        if let Some(result) = result {
+            if current == generation {
+                self.insert(id, result, options, now);
+            }
+        }
+    }
+
+    fn insert(&mut self, id: &str, result: GetResult, options: &CacheOptions, now: Instant) {
+        if options.capacity == 0 {
+            return;
+        }
+        match self.entries.get(id) {
+            // Never replace a newer version of the document stored by a concurrent read
+            Some(entry) if entry.result.cas() > result.cas() => return,
+            Some(_) => self.remove(id),
+            None if self.entries.len() >= options.capacity => {
+                if let Some(lru) = self.lru.values().next().cloned() {
+                    self.remove(&lru);
+                    self.stats.evictions += 1;
+                }
+            }
+            None => {}
+        }
+        self.clock += 1;
+        self.lru.insert(self.clock, id.to_string());
+        let entry = CacheEntry {
+            result,
+            expires_at: now + options.ttl,
+            last_used: self.clock,
+        };
+        self.entries.insert(id.to_string(), entry);
+    }
+
+    fn invalidate(&mut self, id: &str) {
+        if let Some(reads) = self.reads.get_mut(id) {
+            reads.generation += 1;
+        }
+        self.remove(id);
+    }
+
+    fn clear(&mut self) {
+        for reads in self.reads.values_mut() {
+            reads.generation += 1;
+        }
+        self.entries.clear();
+        self.lru.clear();
+    }
+
+    fn remove(&mut self, id: &str) {
+        if let Some(entry) = self.entries.remove(id) {
+            self.lru.remove(&entry.last_used);
+        }
+    }
+}
+
+/// A read of a document which missed the cache. If it is dropped without a result, i.e.
+/// because the read failed or its future was dropped, nothing is cached.
+struct PendingRead<'a> {
+    cache: &'a CachedCollection,
+    id: String,
+    generation: u64,
+    result: Option<GetResult>,
+}
+
+impl Drop for PendingRead<'_> {
+    fn drop(&mut self) {
+        let mut state = self.cache.state.lock().unwrap();
+        state.end_read(
+            &self.id,
+            self.generation,
+            self.result.take(),
+            &self.cache.options,
+            Instant::now(),
+        );
+    }
+}
+
+/// A `Collection` which serves repeated reads of hot documents from an in-process cache.
+///
+/// Documents are kept for a fixed time to live, so reads may return content up to that old
+/// when the document is changed by another client. Writes through this collection invalidate
+/// the document right away, and a read which raced with such a write never makes it into
+/// the cache.
+///
+/// Only `get` is cached, all other operations are available through `collection()`. Every
+/// read reports whether it hit the cache to the `Meter` of the cluster.
+///
+/// ```no_run
+/// # use couchbase::*;
+/// # use std::time::Duration;
+/// # async fn run(collection: Collection) -> CouchbaseResult<()> {
+/// let cached = CachedCollection::new(
+///     collection,
+///     CacheOptions::default().ttl(Duration::from_millis(500)),
+/// );
+/// let result = cached.get("hot-key", GetOptions::default()).await?;
+/// println!("hit rate: {}", cached.stats().hit_rate());
+/// # Ok(())
+/// # }
+/// ```
+pub struct CachedCollection {
+    collection: Collection,
+    options: CacheOptions,
+    state: Mutex<CacheState>,
+    meter: Arc<dyn Meter>,
+}
+
+impl CachedCollection {
+    pub fn new(collection: Collection, options: CacheOptions) -> Self {
+        let meter = collection.core.meter();
+        Self {
+            collection,
+            options,
+            state: Mutex::new(CacheState::default()),
+            meter,
+        }
+    }
+
+    /// The wrapped collection, to perform operations which bypass the cache.
+    pub fn collection(&self) -> &Collection {
+        &self.collection
+    }
+
+    /// Reads the document from the cache, or from the server if it is not cached or expired.
+    ///
+    /// Reads with projections, `with_expiry` or a transcoder always go to the server and are
+    /// not cached, so the cached results never decode through the transcoder of another read.
+    pub async fn get<S: Into<String>>(
+        &self,
+        id: S,
+        options: GetOptions,
+    ) -> CouchbaseResult<GetResult> {
+        let id = id.into();
+        if !options.project.is_empty() || options.with_expiry || options.transcoder.is_some() {
+            return self.collection.get(id, options).await;
+        }
+        let generation = {
+            let mut state = self.state.lock().unwrap();
+            let cached = state.lookup(&id, Instant::now());
+            self.meter.record_cache_access(cached.is_some());
+            if let Some(result) = cached {
+                state.stats.hits += 1;
+                return Ok(result);
+            }
+            state.stats.misses += 1;
+            state.begin_read(&id)
+        };
+
+        let mut read = PendingRead {
+            cache: self,
+            id: id.clone(),
+            generation,
+            result: None,
+        };
+        let result = self.collection.get(id, options).await?;
+        read.result = Some(result.clone());
+        Ok(result)
+    }
+
+    pub async fn upsert<S: Into<String>, T>(
+        &self,
+        id: S,
+        content: T,
+        options: UpsertOptions,
+    ) -> CouchbaseResult<MutationResult>
+    where
+        T: Serialize,
+    {
+        let id = id.into();
+        self.invalidate(&id);
+        let result = self.collection.upsert(id.clone(), content, options).await;
+        self.invalidate(&id);
+        result
+    }
+
+    pub async fn insert<S: Into<String>, T>(
+        &self,
+        id: S,
+        content: T,
+        options: InsertOptions,
+    ) -> CouchbaseResult<MutationResult>
+    where
+        T: Serialize,
+    {
+        let id = id.into();
+        self.invalidate(&id);
+        let result = self.collection.insert(id.clone(), content, options).await;
+        self.invalidate(&id);
+        result
+    }
+
+    pub async fn replace<S: Into<String>, T>(
+        &self,
+        id: S,
+        content: T,
+        options: ReplaceOptions,
+    ) -> CouchbaseResult<MutationResult>
+    where
+        T: Serialize,
+    {
+        let id = id.into();
+        self.invalidate(&id);
+        let result = self.collection.replace(id.clone(), content, options).await;
+        self.invalidate(&id);
+        result
+    }
+
+    pub async fn remove<S: Into<String>>(
+        &self,
+        id: S,
+        options: RemoveOptions,
+    ) -> CouchbaseResult<MutationResult> {
+        let id = id.into();
+        self.invalidate(&id);
+        let result = self.collection.remove(id.clone(), options).await;
+        self.invalidate(&id);
+        result
+    }
+
+    /// Drops the document from the cache, so the next read goes to the server.
+    ///
+    /// Use this after modifying the document through `collection()`.
+    pub fn invalidate(&self, id: &str) {
+        self.state.lock().unwrap().invalidate(id);
+    }
+
+    /// Drops all documents from the cache, i.e. after the cluster topology changed.
+    pub fn clear(&self) {
+        self.state.lock().unwrap().clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.state.lock().unwrap().stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(capacity: usize) -> CacheOptions {
+        CacheOptions::default()
+            .capacity(capacity)
+            .ttl(Duration::from_secs(10))
+    }
+
+    fn read(state: &mut CacheState, id: &str, cas: u64, options: &CacheOptions, now: Instant) {
+        let generation = state.begin_read(id);
+        let result = GetResult::new(b"{}".to_vec(), cas, 0);
+        state.end_read(id, generation, Some(result), options, now);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let options = options(2);
+        let now = Instant::now();
+        let mut state = CacheState::default();
+        read(&mut state, "a", 1, &options, now);
+        read(&mut state, "b", 1, &options, now);
+        assert!(state.lookup("a", now).is_some());
+        read(&mut state, "c", 1, &options, now);
+
+        assert!(state.lookup("a", now).is_some());
+        assert!(state.lookup("b", now).is_none());
+        assert!(state.lookup("c", now).is_some());
+        assert_eq!(state.stats.evictions(), 1);
+        assert_eq!(state.lru.len(), 2);
+    }
+
+    #[test]
+    fn expired_entries_are_missed() {
+        let options = options(2);
+        let now = Instant::now();
+        let mut state = CacheState::default();
+        read(&mut state, "a", 1, &options, now);
+
+        assert!(state.lookup("a", now + Duration::from_secs(11)).is_none());
+    }
+
+    #[test]
+    fn read_racing_with_invalidation_is_not_cached() {
+        let options = options(2);
+        let now = Instant::now();
+        let mut state = CacheState::default();
+        let generation = state.begin_read("a");
+        state.invalidate("a");
+        let result = GetResult::new(b"{}".to_vec(), 1, 0);
+        state.end_read("a", generation, Some(result), &options, now);
+
+        assert!(state.lookup("a", now).is_none());
+        assert!(state.reads.is_empty());
+    }
+
+    #[test]
+    fn invalidation_only_affects_its_document() {
+        let options = options(2);
+        let now = Instant::now();
+        let mut state = CacheState::default();
+        let generation = state.begin_read("a");
+        state.invalidate("b");
+        let result = GetResult::new(b"{}".to_vec(), 1, 0);
+        state.end_read("a", generation, Some(result), &options, now);
+
+        assert!(state.lookup("a", now).is_some());
+    }
+
+    #[test]
+    fn keeps_newer_version() {
+        let options = options(2);
+        let now = Instant::now();
+        let mut state = CacheState::default();
+        read(&mut state, "a", 2, &options, now);
+        read(&mut state, "a", 1, &options, now);
+
+        assert_eq!(state.lookup("a", now).unwrap().cas(), 2);
+        assert_eq!(state.lru.len(), 1);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::options::{GetOptions, RemoveOptions, UpsertOptions};
+use crate::api::Collection;
+use crate::io::request::MutateRequestType;
+use couchbase_flags::BINARY_COMMON_FLAGS;
+use futures::future::try_join_all;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+/// The largest possible chunk.
+///
+/// The server rejects items larger than 20 MiB, which includes the extended attributes of the
+/// document and the overhead of storing it, so a chunk leaves 1 MiB of headroom below that.
+pub const MAX_CHUNK_SIZE: usize = 19 * 1024 * 1024;
+
+const DEFAULT_CHUNK_SIZE: usize = 10 * 1024 * 1024;
+
+const _: () = assert!(MAX_CHUNK_SIZE < 20 * 1024 * 1024);
+const _: () = assert!(DEFAULT_CHUNK_SIZE <= MAX_CHUNK_SIZE);
+
+/// How often reading is retried when a concurrent write replaced the chunks being read.
+const MAX_READ_ATTEMPTS: usize = 3;
+
+/// Stores binary values larger than the server limit, split into chunks of many documents.
+///
+/// The document under the id itself only holds a manifest, which lists how many chunks the
+/// value has been split into and the CRC32 checksum of the whole value. Reading the value
+/// fetches the chunks and verifies the checksum before reassembling it.
+///
+/// Writes are not atomic, but every write stores its chunks under new keys before switching
+/// the manifest over, so readers either see the old or the new value and never a mix of both.
+/// Keep in mind that:
+///
+/// - a write which fails (or a client which crashes) half-way leaves orphaned chunks behind,
+///   which are not counted against the document but still take up space;
+/// - when writers race, the last manifest written wins and the chunks of the others are
+///   orphaned;
+/// - the chunks of a replaced value are removed after the switch, so a reader which is slower
+///   than the write retries a few times before it fails with `DocumentUnretrievable`;
+/// - the chunks are regular documents and can be seen (and changed) by other applications.
+///
+/// ```no_run
+/// # use couchbase::*;
+/// # async fn run(collection: Collection, payload: Vec<u8>) -> CouchbaseResult<()> {
+/// let document = collection.chunked("backup::2020-08-01");
+/// document.upsert(&payload).await?;
+/// let restored = document.get().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ChunkedDocument {
+    collection: Collection,
+    id: String,
+    chunk_size: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChunkManifest {
+    version: String,
+    chunks: usize,
+    size: usize,
+    crc32: u32,
+}
+
+impl ChunkedDocument {
+    pub(crate) fn new(collection: Collection, id: String) -> Self {
+        Self {
+            collection,
+            id,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// The size of the chunks the value is split into, 10 MiB by default.
+    ///
+    /// Must not be zero or larger than `MAX_CHUNK_SIZE`. Only applies to writes, values are
+    /// always read with the chunk size they have been written with.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// The id of the document holding the manifest.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Stores the value, replacing the previous one.
+    pub async fn upsert(&self, content: &[u8]) -> CouchbaseResult<()> {
+        if self.chunk_size == 0 || self.chunk_size > MAX_CHUNK_SIZE {
+            let mut ctx = ErrorContext::default();
+            ctx.insert("chunk_size", Value::from(self.chunk_size));
+            ctx.insert("max_chunk_size", Value::from(MAX_CHUNK_SIZE));
+            return Err(CouchbaseError::InvalidArgument { ctx });
+        }
+
+        let previous = self.manifest().await?;
+        let manifest = ChunkManifest {
+            version: Uuid::new_v4().to_simple().to_string(),
+            chunks: content.chunks(self.chunk_size).count(),
+            size: content.len(),
+            crc32: crc32(content),
+        };
+
+        let stored = try_join_all(content.chunks(self.chunk_size).enumerate().map(
+            |(index, chunk)| {
+                self.collection.send_mutation(
+                    self.chunk_id(&manifest.version, index),
+                    Ok(chunk.to_vec()),
+                    Some(BINARY_COMMON_FLAGS),
+                    MutateRequestType::Upsert {
+                        options: UpsertOptions::default(),
+                    },
+                )
+            },
+        ))
+        .await;
+        let switched = match stored {
+            Ok(_) => self
+                .collection
+                .upsert(&self.id, &manifest, UpsertOptions::default())
+                .await
+                .map(|_| ()),
+            Err(e) => Err(e),
+        };
+
+        // Either the new or the previous chunks are not referenced anymore
+        match switched {
+            Ok(_) => {
+                if let Some(previous) = previous {
+                    self.remove_chunks(&previous).await;
+                }
+                Ok(())
+            }
+            Err(e) => {
+                self.remove_chunks(&manifest).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Reads the value and verifies its checksum.
+    ///
+    /// Fails with `DocumentNotFound` if there is no value and with `DecodingFailure` if the
+    /// reassembled value does not match its checksum.
+    pub async fn get(&self) -> CouchbaseResult<Vec<u8>> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let manifest = match self.manifest().await? {
+                Some(manifest) => manifest,
+                None => {
+                    let mut ctx = ErrorContext::default();
+                    ctx.insert("id", Value::String(self.id.clone()));
+                    return Err(CouchbaseError::DocumentNotFound { ctx });
+                }
+            };
+
+            let chunks = try_join_all((0..manifest.chunks).map(|index| {
+                self.collection.get(
+                    self.chunk_id(&manifest.version, index),
+                    GetOptions::default(),
+                )
+            }))
+            .await;
+            let chunks = match chunks {
+                Ok(chunks) => chunks,
+                // A concurrent write replaced the value and removed the chunks
+                Err(CouchbaseError::DocumentNotFound { ctx }) => {
+                    if attempt < MAX_READ_ATTEMPTS {
+                        continue;
+                    }
+                    return Err(CouchbaseError::DocumentUnretrievable { ctx });
+                }
+                Err(e) => return Err(e),
+            };
+
+            let mut content = Vec::with_capacity(manifest.size);
+            for chunk in chunks {
+                content.extend_from_slice(&chunk.into_content());
+            }
+            if content.len() != manifest.size || crc32(&content) != manifest.crc32 {
+                let mut ctx = ErrorContext::default();
+                ctx.insert("id", Value::String(self.id.clone()));
+                ctx.insert("version", Value::String(manifest.version));
+                return Err(CouchbaseError::DecodingFailure {
+                    ctx,
+                    source: std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "the chunks do not match the checksum of the value",
+                    ),
+                });
+            }
+            return Ok(content);
+        }
+    }
+
+    /// Removes the value and all of its chunks.
+    pub async fn remove(&self) -> CouchbaseResult<()> {
+        let manifest = match self.manifest().await? {
+            Some(manifest) => manifest,
+            None => {
+                let mut ctx = ErrorContext::default();
+                ctx.insert("id", Value::String(self.id.clone()));
+                return Err(CouchbaseError::DocumentNotFound { ctx });
+            }
+        };
+        self.collection
+            .remove(&self.id, RemoveOptions::default())
+            .await?;
+        self.remove_chunks(&manifest).await;
+        Ok(())
+    }
+
+    async fn manifest(&self) -> CouchbaseResult<Option<ChunkManifest>> {
+        match self.collection.get(&self.id, GetOptions::default()).await {
+            Ok(result) => result.content().map(Some),
+            Err(CouchbaseError::DocumentNotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Removes the chunks of the manifest, ignoring failures since they are only orphaned.
+    async fn remove_chunks(&self, manifest: &ChunkManifest) {
+        let _ = futures::future::join_all((0..manifest.chunks).map(|index| {
+            self.collection.remove(
+                self.chunk_id(&manifest.version, index),
+                RemoveOptions::default(),
+            )
+        }))
+        .await;
+    }
+
+    fn chunk_id(&self, version: &str, index: usize) -> String {
+        format!("{}::chunk::{}::{}", self.id, version, index)
+    }
+}
+
+/// The lookup table of the CRC32 (IEEE) checksum, built at compile time.
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// The CRC32 (IEEE) checksum of the value.
+///
+/// Also the base of the hash which maps keys to partitions.
+pub(crate) fn crc32(content: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+    for b in content {
+        crc = (crc >> 8) ^ CRC32_TABLE[((crc ^ *b as u32) & 0xff) as usize];
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_the_crc32_checksum() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414F_A339
+        );
+    }
+
+    #[test]
+    fn round_trips_the_manifest() {
+        let manifest = ChunkManifest {
+            version: "abc".into(),
+            chunks: 3,
+            size: 25,
+            crc32: 0xCBF4_3926,
+        };
+        let encoded = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(
+            encoded,
+            serde_json::json!({"version": "abc", "chunks": 3, "size": 25, "crc32": 3421780262u32})
+        );
+        let decoded: ChunkManifest = serde_json::from_value(encoded).unwrap();
+        assert_eq!(decoded.chunks, 3);
+        assert_eq!(decoded.size, 25);
+        assert_eq!(decoded.crc32, 0xCBF4_3926);
+    }
+}
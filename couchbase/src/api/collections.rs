@@ -1,12 +1,17 @@
+use crate::api::redaction::MetaData;
 use crate::io::request::*;
 use crate::io::Core;
-use crate::CouchbaseError::{CollectionExists, CollectionNotFound, ScopeExists, ScopeNotFound};
+use crate::CouchbaseError::{
+    CollectionExists, CollectionNotFound, ConcurrentModification, ScopeExists, ScopeNotFound,
+};
 use crate::{
     CouchbaseError, CouchbaseResult, CreateCollectionOptions, CreateScopeOptions,
     DropCollectionOptions, DropScopeOptions, ErrorContext, GenericManagementResult,
-    GetAllScopesOptions,
+    GetAllScopesOptions, UpdateCollectionOptions,
 };
 use futures::channel::oneshot;
+use futures_timer::Delay;
+use log::debug;
 use serde_derive::Deserialize;
 use serde_json::Value;
 use std::sync::Arc;
@@ -37,17 +42,36 @@ pub struct CollectionSpec {
     name: String,
     scope_name: String,
     max_expiry: Duration,
+    no_expiry: bool,
+    history: Option<bool>,
 }
 
 impl CollectionSpec {
+    /// Creates a new collection spec, where a zero `max_expiry` inherits the bucket max expiry.
     pub fn new<S: Into<String>>(name: S, scope_name: S, max_expiry: Duration) -> Self {
         Self {
             name: name.into(),
             scope_name: scope_name.into(),
             max_expiry,
+            no_expiry: false,
+            history: None,
         }
     }
 
+    /// Documents in the collection never expire, even if the bucket has a max expiry.
+    pub fn no_expiry(mut self) -> Self {
+        self.max_expiry = Duration::from_secs(0);
+        self.no_expiry = true;
+        self
+    }
+
+    /// Enables or disables history retention, which requires a bucket on the magma storage
+    /// backend. The bucket setting is used if not set.
+    pub fn history_retention(mut self, enabled: bool) -> Self {
+        self.history = Some(enabled);
+        self
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -57,7 +81,29 @@ impl CollectionSpec {
     }
 
     pub fn max_expiry(&self) -> Duration {
-        self.max_expiry.clone()
+        self.max_expiry
+    }
+
+    pub fn never_expires(&self) -> bool {
+        self.no_expiry
+    }
+
+    pub fn history(&self) -> Option<bool> {
+        self.history
+    }
+
+    /// Encodes the settings the server accepts when creating or updating the collection.
+    fn settings_form(&self) -> Vec<(&'static str, String)> {
+        let mut form = vec![];
+        if self.no_expiry {
+            form.push(("maxTTL", String::from("-1")));
+        } else if self.max_expiry.as_secs() > 0 {
+            form.push(("maxTTL", self.max_expiry.as_secs().to_string()));
+        }
+        if let Some(history) = self.history {
+            form.push(("history", history.to_string()));
+        }
+        form
     }
 }
 
@@ -65,8 +111,10 @@ impl CollectionSpec {
 struct ManifestCollection {
     uid: String,
     name: String,
-    #[serde(rename = "maxTTL")]
-    max_expiry: u64,
+    // -1 means the documents never expire
+    #[serde(rename = "maxTTL", default)]
+    max_expiry: i64,
+    history: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -82,6 +130,11 @@ struct Manifest {
     scopes: Vec<ManifestScope>,
 }
 
+/// How often a request is sent when the manifest keeps changing concurrently.
+const MAX_CONFLICT_ATTEMPTS: u32 = 4;
+
+const CONFLICT_BACKOFF: Duration = Duration::from_millis(100);
+
 pub struct CollectionManager {
     core: Arc<Core>,
     bucket_name: String,
@@ -96,45 +149,22 @@ impl CollectionManager {
         &self,
         options: GetAllScopesOptions,
     ) -> CouchbaseResult<Vec<ScopeSpec>> {
-        let (sender, receiver) = oneshot::channel();
-
-        self.core.send(Request::GenericManagementRequest(
-            GenericManagementRequest {
-                sender,
-                path: format!("/pools/default/buckets/{}/scopes", self.bucket_name),
-                method: String::from("get"),
-                payload: None,
-                content_type: None,
-                timeout: options.timeout,
-            },
-        ));
-
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
-        let manifest: Manifest = match result.http_status() {
-            200 => serde_json::from_slice(result.payload().unwrap()).map_err(|e| {
-                CouchbaseError::DecodingFailure {
-                    ctx: ErrorContext::default(),
-                    source: e.into(),
-                }
-            }),
-            _ => Err(CouchbaseError::GenericHTTP {
-                ctx: Default::default(),
-                status: result.http_status(),
-                message: String::from_utf8(result.payload().unwrap().to_owned())
-                    .unwrap()
-                    .to_lowercase(),
-            }),
-        }?;
+        let manifest = self.manifest(options.timeout).await?;
 
         let mut scopes = vec![];
         for scope in manifest.scopes {
             let mut collections = vec![];
             for col in scope.collections {
-                collections.push(CollectionSpec::new(
+                let mut spec = CollectionSpec::new(
                     col.name,
                     scope.name.clone(),
-                    Duration::from_secs(col.max_expiry),
-                ))
+                    Duration::from_secs(col.max_expiry.max(0) as u64),
+                );
+                if col.max_expiry < 0 {
+                    spec = spec.no_expiry();
+                }
+                spec.history = col.history;
+                collections.push(spec)
             }
             scopes.push(ScopeSpec::new(scope.name, collections));
         }
@@ -142,150 +172,250 @@ impl CollectionManager {
         Ok(scopes)
     }
 
+    /// Creates the scope.
+    ///
+    /// The request is sent again if the manifest of the bucket was changed concurrently, and
+    /// only fails with `ConcurrentModification` if it keeps changing. Once the request ran into
+    /// such a change, finding the scope created counts as success.
     pub async fn create_scope<S: Into<String>>(
         &self,
         scope_name: S,
         options: CreateScopeOptions,
     ) -> CouchbaseResult<()> {
-        // The server expects form data so we need to build that, serde expects each value to be an
-        // Option.
         let scope = scope_name.into();
-
-        let form = &[("name", scope.clone())];
-
-        let form_encoded = serde_urlencoded::to_string(&form).unwrap();
-        let content_type = String::from("application/x-www-form-urlencoded");
-        let (sender, receiver) = oneshot::channel();
-
-        self.core.send(Request::GenericManagementRequest(
-            GenericManagementRequest {
-                sender,
-                path: format!("/pools/default/buckets/{}/scopes", self.bucket_name),
-                method: String::from("post"),
-                payload: Some(form_encoded),
-                content_type: Some(content_type),
-                timeout: options.timeout,
-            },
-        ));
-
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
-
-        match result.http_status() {
-            200 => Ok(()),
-            _ => Err(self.parse_error(
-                result.http_status(),
-                String::from_utf8(result.payload().unwrap().to_owned())
-                    .unwrap()
-                    .to_lowercase(),
-                scope,
-            )),
-        }
+        let form = vec![("name", scope.clone())];
+
+        let result = self
+            .send(
+                format!("/pools/default/buckets/{}/scopes", self.bucket_name),
+                "post",
+                Some(form),
+                options.timeout,
+                true,
+            )
+            .await?;
+        self.check_settled(result, scope, |e| matches!(e, ScopeExists { .. }))
     }
 
+    /// Creates the collection.
+    ///
+    /// The request is sent again if the manifest of the bucket was changed concurrently, and
+    /// only fails with `ConcurrentModification` if it keeps changing. Once the request ran into
+    /// such a change, finding the collection created counts as success.
     pub async fn create_collection(
         &self,
         collection: CollectionSpec,
         options: CreateCollectionOptions,
     ) -> CouchbaseResult<()> {
-        // The server expects form data so we need to build that, serde expects each value to be an
-        // Option.
         let mut form = vec![("name", collection.name.clone())];
-        if collection.max_expiry.as_secs() > 0 {
-            form.push(("maxTTL", collection.max_expiry.as_secs().to_string()));
-        }
+        form.extend(collection.settings_form());
 
-        let form_encoded = serde_urlencoded::to_string(&form).unwrap();
-        let content_type = String::from("application/x-www-form-urlencoded");
-        let (sender, receiver) = oneshot::channel();
-
-        self.core.send(Request::GenericManagementRequest(
-            GenericManagementRequest {
-                sender,
-                path: format!(
+        let result = self
+            .send(
+                format!(
                     "/pools/default/buckets/{}/scopes/{}/collections/",
                     self.bucket_name, collection.scope_name
                 ),
-                method: String::from("post"),
-                payload: Some(form_encoded),
-                content_type: Some(content_type),
-                timeout: options.timeout,
-            },
-        ));
-
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+                "post",
+                Some(form),
+                options.timeout,
+                true,
+            )
+            .await?;
+        self.check_settled(result, collection.name, |e| {
+            matches!(e, CollectionExists { .. })
+        })
+    }
 
-        match result.http_status() {
-            200 => Ok(()),
-            _ => Err(self.parse_error(
-                result.http_status(),
-                String::from_utf8(result.payload().unwrap().to_owned())
-                    .unwrap()
-                    .to_lowercase(),
-                collection.name,
-            )),
-        }
+    /// Updates the max expiry and history retention settings of an existing collection.
+    ///
+    /// This needs Couchbase Server 7.2 or later. Since applying the same settings twice does not
+    /// change the outcome, the update is retried if the manifest of the bucket was changed
+    /// concurrently.
+    pub async fn update_collection(
+        &self,
+        collection: CollectionSpec,
+        options: UpdateCollectionOptions,
+    ) -> CouchbaseResult<()> {
+        let result = self
+            .send(
+                format!(
+                    "/pools/default/buckets/{}/scopes/{}/collections/{}",
+                    self.bucket_name, collection.scope_name, collection.name
+                ),
+                "patch",
+                Some(collection.settings_form()),
+                options.timeout,
+                true,
+            )
+            .await?;
+        self.check(result.0, collection.name)
     }
 
+    /// Drops the scope and all of its collections.
+    ///
+    /// The request is sent again if the manifest of the bucket was changed concurrently, and
+    /// only fails with `ConcurrentModification` if it keeps changing. Once the request ran into
+    /// such a change, finding the scope dropped counts as success.
     pub async fn drop_scope<S: Into<String>>(
         &self,
         scope_name: S,
         options: DropScopeOptions,
     ) -> CouchbaseResult<()> {
-        let (sender, receiver) = oneshot::channel();
-
         let scope = scope_name.into();
 
-        self.core.send(Request::GenericManagementRequest(
-            GenericManagementRequest {
-                sender,
-                path: format!(
+        let result = self
+            .send(
+                format!(
                     "/pools/default/buckets/{}/scopes/{}",
-                    self.bucket_name, &scope,
+                    self.bucket_name, &scope
                 ),
-                method: String::from("delete"),
-                payload: None,
-                content_type: None,
-                timeout: options.timeout,
-            },
-        ));
+                "delete",
+                None,
+                options.timeout,
+                true,
+            )
+            .await?;
+        self.check_settled(result, scope, |e| matches!(e, ScopeNotFound { .. }))
+    }
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+    /// Drops the collection.
+    ///
+    /// The request is sent again if the manifest of the bucket was changed concurrently, and
+    /// only fails with `ConcurrentModification` if it keeps changing. Once the request ran into
+    /// such a change, finding the collection dropped counts as success.
+    pub async fn drop_collection(
+        &self,
+        collection: CollectionSpec,
+        options: DropCollectionOptions,
+    ) -> CouchbaseResult<()> {
+        let result = self
+            .send(
+                format!(
+                    "/pools/default/buckets/{}/scopes/{}/collections/{}",
+                    self.bucket_name, collection.scope_name, collection.name
+                ),
+                "delete",
+                None,
+                options.timeout,
+                true,
+            )
+            .await?;
+        self.check_settled(result, collection.name, |e| {
+            matches!(e, CollectionNotFound { .. })
+        })
+    }
 
+    async fn manifest(&self, timeout: Option<Duration>) -> CouchbaseResult<Manifest> {
+        let result = self
+            .send(
+                format!("/pools/default/buckets/{}/scopes", self.bucket_name),
+                "get",
+                None,
+                timeout,
+                true,
+            )
+            .await?
+            .0;
         match result.http_status() {
-            200 => Ok(()),
-            _ => Err(self.parse_error(
-                result.http_status(),
-                String::from_utf8(result.payload().unwrap().to_owned())
+            200 => serde_json::from_slice(result.payload().unwrap()).map_err(|e| {
+                CouchbaseError::DecodingFailure {
+                    ctx: ErrorContext::default(),
+                    source: e.into(),
+                }
+            }),
+            _ => Err(CouchbaseError::GenericHTTP {
+                ctx: Default::default(),
+                status: result.http_status(),
+                message: String::from_utf8(result.payload().unwrap().to_owned())
                     .unwrap()
                     .to_lowercase(),
-                scope,
-            )),
+            }),
         }
     }
 
-    pub async fn drop_collection(
+    /// Sends the request, which the server expects as form data.
+    ///
+    /// The server answers with `409 Conflict` if the manifest of the bucket was changed while
+    /// the request was processed. The request is then sent again once the manifest has been
+    /// refreshed if `retry_conflicts` is set, otherwise the conflict is left to `parse_error`.
+    /// Also returns whether the request ran into a conflict.
+    async fn send(
         &self,
-        collection: CollectionSpec,
-        options: DropCollectionOptions,
-    ) -> CouchbaseResult<()> {
-        let (sender, receiver) = oneshot::channel();
+        path: String,
+        method: &str,
+        form: Option<Vec<(&'static str, String)>>,
+        timeout: Option<Duration>,
+        retry_conflicts: bool,
+    ) -> CouchbaseResult<(GenericManagementResult, bool)> {
+        let payload = form.map(|f| serde_urlencoded::to_string(&f).unwrap());
+        let content_type = payload
+            .as_ref()
+            .map(|_| String::from("application/x-www-form-urlencoded"));
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let result = self
+                .dispatch(
+                    path.clone(),
+                    method,
+                    payload.clone(),
+                    content_type.clone(),
+                    timeout,
+                )
+                .await?;
+
+            if result.http_status() != 409 || !retry_conflicts || attempt >= MAX_CONFLICT_ATTEMPTS {
+                return Ok((result, attempt > 1));
+            }
+            debug!(
+                "Manifest of bucket {} changed concurrently, retrying {} {}",
+                MetaData(&self.bucket_name),
+                method,
+                MetaData(&path)
+            );
+            Delay::new(CONFLICT_BACKOFF * attempt).await;
+            if method != "get" {
+                // Only waits for the concurrent change to be visible, so failures do not matter
+                let _ = self
+                    .dispatch(
+                        format!("/pools/default/buckets/{}/scopes", self.bucket_name),
+                        "get",
+                        None,
+                        None,
+                        timeout,
+                    )
+                    .await;
+            }
+        }
+    }
 
-        self.core.send(Request::GenericManagementRequest(
+    async fn dispatch(
+        &self,
+        path: String,
+        method: &str,
+        payload: Option<String>,
+        content_type: Option<String>,
+        timeout: Option<Duration>,
+    ) -> CouchbaseResult<GenericManagementResult> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
-                path: format!(
-                    "/pools/default/buckets/{}/scopes/{}/collections/{}",
-                    self.bucket_name, collection.scope_name, collection.name
-                ),
-                method: String::from("delete"),
-                payload: None,
-                content_type: None,
-                timeout: options.timeout,
+                path,
+                method: String::from(method),
+                payload,
+                content_type,
+                timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
+        timer.finish(receiver.await.unwrap())
+    }
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
-
+    fn check(&self, result: GenericManagementResult, object_name: String) -> CouchbaseResult<()> {
         match result.http_status() {
             200 => Ok(()),
             _ => Err(self.parse_error(
@@ -293,11 +423,27 @@ impl CollectionManager {
                 String::from_utf8(result.payload().unwrap().to_owned())
                     .unwrap()
                     .to_lowercase(),
-                collection.name,
+                object_name,
             )),
         }
     }
 
+    /// Checks the outcome of a create or drop request. If the request ran into a concurrent
+    /// change of the manifest, the object may have been created or dropped by the attempt
+    /// which conflicted or by the concurrent change, so the error telling that it was counts
+    /// as success.
+    fn check_settled(
+        &self,
+        (result, conflicted): (GenericManagementResult, bool),
+        object_name: String,
+        settled: fn(&CouchbaseError) -> bool,
+    ) -> CouchbaseResult<()> {
+        match self.check(result, object_name) {
+            Err(e) if conflicted && settled(&e) => Ok(()),
+            outcome => outcome,
+        }
+    }
+
     fn parse_error(&self, status: u16, message: String, object_name: String) -> CouchbaseError {
         if message.contains("not_found") && message.contains("collection") {
             let mut ctx = ErrorContext::default();
@@ -319,6 +465,13 @@ impl CollectionManager {
             return ScopeExists { ctx };
         }
 
+        if status == 409 {
+            let mut ctx = ErrorContext::default();
+            ctx.insert("name", Value::String(object_name));
+            ctx.insert("message", Value::String(message));
+            return ConcurrentModification { ctx };
+        }
+
         CouchbaseError::GenericHTTP {
             ctx: Default::default(),
             status,
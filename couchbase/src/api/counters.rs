@@ -0,0 +1,178 @@
+use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::options::{GetOptions, MutateInOptions, StoreSemantics};
+use crate::api::subdoc_path::SubdocPath;
+use crate::api::{Collection, MutateInSpec};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// The maximum number of counters which can be changed in a single operation.
+///
+/// The server accepts at most 16 specs in a single sub-document mutation.
+pub const MAX_COUNTERS_PER_UPDATE: usize = 16;
+
+/// What happens when a counter would leave the range of a signed 64 bit integer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CounterOverflow {
+    /// The update fails with `NumberTooBig` or `DeltaInvalid`. This is the default.
+    Fail,
+    /// The counter starts over at the delta which made it overflow.
+    Reset,
+}
+
+/// Maintains many named counters inside a single JSON document.
+///
+/// Every counter is a top-level field of the document, updated through sub-document counter
+/// operations, so any number of counters only take up a single key. The document and the
+/// counters are created when they are first incremented.
+///
+/// ```no_run
+/// # use couchbase::*;
+/// # async fn run(collection: Collection) -> CouchbaseResult<()> {
+/// let counters = collection.counters("rate-counters");
+/// counters.increment("logins", 1).await?;
+/// let all = counters
+///     .update(vec![("logins".into(), 1), ("failures".into(), -1)])
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct CounterDocument {
+    collection: Collection,
+    id: String,
+    overflow: CounterOverflow,
+}
+
+impl CounterDocument {
+    pub(crate) fn new(collection: Collection, id: String) -> Self {
+        Self {
+            collection,
+            id,
+            overflow: CounterOverflow::Fail,
+        }
+    }
+
+    /// Sets what happens when a counter overflows, see `CounterOverflow`.
+    pub fn overflow(mut self, overflow: CounterOverflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// The id of the document holding the counters.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Adds the delta to the counter and returns its new value.
+    pub async fn increment<S: Into<String>>(&self, name: S, delta: i64) -> CouchbaseResult<i64> {
+        let name = name.into();
+        let mut values = self.update(vec![(name.clone(), delta)]).await?;
+        Ok(values.remove(&name).unwrap_or_default())
+    }
+
+    /// Subtracts the delta from the counter and returns its new value.
+    pub async fn decrement<S: Into<String>>(&self, name: S, delta: i64) -> CouchbaseResult<i64> {
+        self.increment(name, delta.saturating_neg()).await
+    }
+
+    /// Atomically adds the deltas to their counters and returns the new values.
+    ///
+    /// At most `MAX_COUNTERS_PER_UPDATE` counters can be changed at once and no delta may
+    /// be zero. When overflowing counters are reset, the counters are updated one by one
+    /// instead, so the update is not atomic anymore.
+    pub async fn update(
+        &self,
+        deltas: Vec<(String, i64)>,
+    ) -> CouchbaseResult<HashMap<String, i64>> {
+        if deltas.len() > MAX_COUNTERS_PER_UPDATE {
+            let mut ctx = ErrorContext::default();
+            ctx.insert("counters", Value::from(deltas.len()));
+            ctx.insert("max_counters", Value::from(MAX_COUNTERS_PER_UPDATE));
+            return Err(CouchbaseError::InvalidArgument { ctx });
+        }
+        if let Some((name, _)) = deltas.iter().find(|(_, delta)| *delta == 0) {
+            let mut ctx = ErrorContext::default();
+            ctx.insert("counter", Value::String(name.clone()));
+            ctx.insert("reason", Value::String("the delta must not be zero".into()));
+            return Err(CouchbaseError::InvalidArgument { ctx });
+        }
+
+        match self.mutate(&deltas).await {
+            Err(e) if self.overflow == CounterOverflow::Reset && is_overflow(&e) => {
+                let mut values = HashMap::new();
+                for (name, delta) in deltas {
+                    let value = match self.mutate(&[(name.clone(), delta)]).await {
+                        Err(e) if is_overflow(&e) => self.reset(name.clone(), delta).await?,
+                        result => result?.remove(&name).unwrap_or_default(),
+                    };
+                    values.insert(name, value);
+                }
+                Ok(values)
+            }
+            result => result,
+        }
+    }
+
+    /// Returns the current value of the counter, if it exists.
+    pub async fn get<S: Into<String>>(&self, name: S) -> CouchbaseResult<Option<i64>> {
+        Ok(self.get_all().await?.remove(&name.into()))
+    }
+
+    /// Returns the current values of all counters.
+    pub async fn get_all(&self) -> CouchbaseResult<HashMap<String, i64>> {
+        match self.collection.get(&self.id, GetOptions::default()).await {
+            Ok(result) => result.content(),
+            Err(CouchbaseError::DocumentNotFound { .. }) => Ok(HashMap::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sets the counter to the given value.
+    pub async fn set<S: Into<String>>(&self, name: S, value: i64) -> CouchbaseResult<()> {
+        self.reset(name.into(), value).await.map(|_| ())
+    }
+
+    async fn mutate(&self, deltas: &[(String, i64)]) -> CouchbaseResult<HashMap<String, i64>> {
+        let mut specs = Vec::with_capacity(deltas.len());
+        for (name, delta) in deltas {
+            specs.push(MutateInSpec::Counter {
+                path: SubdocPath::new().field(name.clone()).build()?,
+                delta: *delta,
+            });
+        }
+        let result = self
+            .collection
+            .mutate_in(
+                &self.id,
+                specs,
+                MutateInOptions::default().store_semantics(StoreSemantics::Upsert),
+            )
+            .await?;
+
+        let mut values = HashMap::new();
+        for (index, (name, _)) in deltas.iter().enumerate() {
+            values.insert(name.clone(), result.content(index)?);
+        }
+        Ok(values)
+    }
+
+    async fn reset(&self, name: String, value: i64) -> CouchbaseResult<i64> {
+        self.collection
+            .mutate_in(
+                &self.id,
+                vec![MutateInSpec::upsert(
+                    SubdocPath::new().field(name).build()?,
+                    value,
+                )],
+                MutateInOptions::default().store_semantics(StoreSemantics::Upsert),
+            )
+            .await?;
+        Ok(value)
+    }
+}
+
+fn is_overflow(e: &CouchbaseError) -> bool {
+    matches!(
+        e,
+        CouchbaseError::NumberTooBig { .. } | CouchbaseError::DeltaInvalid { .. }
+    )
+}
@@ -0,0 +1,488 @@
+use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::options::{
+    GetOptions, LookupInOptions, MutateInOptions, RemoveOptions, StoreSemantics,
+};
+use crate::api::subdoc_path::SubdocPath;
+use crate::api::{Collection, LookupInSpec, MutateInSpec};
+use futures::Stream;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// How often a read-modify-write of a data structure is attempted before the `CasMismatch`
+/// of the last attempt is returned.
+pub const MAX_CAS_ATTEMPTS: usize = 16;
+
+/// A list stored as a JSON array in a single document.
+///
+/// The document is created by the first write and removed by `clear`. Reads of a missing
+/// document behave as if the list was empty.
+///
+/// ```no_run
+/// # use couchbase::*;
+/// # use futures::StreamExt;
+/// # async fn run(collection: Collection) -> CouchbaseResult<()> {
+/// let list: CouchbaseList<String> = collection.list("shopping-list");
+/// list.append("milk".into()).await?;
+/// let mut items = list.iter().await?;
+/// while let Some(item) = items.next().await {
+///     println!("{}", item?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct CouchbaseList<T> {
+    collection: Collection,
+    id: String,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> CouchbaseList<T> {
+    pub(crate) fn new(collection: Collection, id: String) -> Self {
+        Self {
+            collection,
+            id,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The id of the document holding the list.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Streams the elements of the list as they were when it was read.
+    pub async fn iter(&self) -> CouchbaseResult<impl Stream<Item = CouchbaseResult<T>>> {
+        let values = read_array(&self.collection, &self.id).await?.0;
+        Ok(futures::stream::iter(values.into_iter().map(decode)))
+    }
+
+    /// Returns the element at the index, failing with `PathNotFound` if it is out of bounds
+    /// and with `DocumentNotFound` if the list has not been created yet.
+    pub async fn get(&self, index: usize) -> CouchbaseResult<T> {
+        let result = self
+            .collection
+            .lookup_in(
+                &self.id,
+                vec![LookupInSpec::get(SubdocPath::new().push(index).build()?)],
+                LookupInOptions::default(),
+            )
+            .await?;
+        // The lookup itself succeeds for a missing index, only the spec fails
+        if !result.exists(0) {
+            let mut ctx = ErrorContext::default();
+            ctx.insert("id", Value::String(self.id.clone()));
+            ctx.insert("index", Value::from(index));
+            return Err(CouchbaseError::PathNotFound { ctx });
+        }
+        result.content(0)
+    }
+
+    /// Adds the value to the end of the list.
+    pub async fn append(&self, value: T) -> CouchbaseResult<()> {
+        let spec = MutateInSpec::array_append("", vec![encode(&value)?]);
+        mutate_creating(&self.collection, &self.id, spec).await
+    }
+
+    /// Adds the value to the start of the list.
+    pub async fn prepend(&self, value: T) -> CouchbaseResult<()> {
+        let spec = MutateInSpec::array_prepend("", vec![encode(&value)?]);
+        mutate_creating(&self.collection, &self.id, spec).await
+    }
+
+    /// Replaces the element at the index, failing with `PathNotFound` if it is out of bounds.
+    pub async fn set(&self, index: usize, value: T) -> CouchbaseResult<()> {
+        self.collection
+            .mutate_in(
+                &self.id,
+                vec![MutateInSpec::replace(
+                    SubdocPath::new().push(index).build()?,
+                    encode(&value)?,
+                )],
+                MutateInOptions::default(),
+            )
+            .await
+            .map(|_| ())
+    }
+
+    /// Removes the element at the index, failing with `PathNotFound` if it is out of bounds.
+    pub async fn remove_at(&self, index: usize) -> CouchbaseResult<()> {
+        self.collection
+            .mutate_in(
+                &self.id,
+                vec![MutateInSpec::remove(SubdocPath::new().push(index).build()?)],
+                MutateInOptions::default(),
+            )
+            .await
+            .map(|_| ())
+    }
+
+    /// The index of the first element equal to the value.
+    pub async fn index_of(&self, value: &T) -> CouchbaseResult<Option<usize>> {
+        let value = encode(value)?;
+        let values = read_array(&self.collection, &self.id).await?.0;
+        Ok(values.iter().position(|v| *v == value))
+    }
+
+    /// The number of elements in the list.
+    pub async fn size(&self) -> CouchbaseResult<usize> {
+        size(&self.collection, &self.id).await
+    }
+
+    /// Removes the list, including its document.
+    pub async fn clear(&self) -> CouchbaseResult<()> {
+        clear(&self.collection, &self.id).await
+    }
+}
+
+/// A map from strings to values stored as a JSON object in a single document.
+///
+/// Every entry is a top-level field of the document, so the keys are escaped as needed and
+/// may contain any character. Reads of a missing document behave as if the map was empty.
+pub struct CouchbaseMap<T> {
+    collection: Collection,
+    id: String,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> CouchbaseMap<T> {
+    pub(crate) fn new(collection: Collection, id: String) -> Self {
+        Self {
+            collection,
+            id,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The id of the document holding the map.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the value of the key, if it exists.
+    pub async fn get<S: Into<String>>(&self, key: S) -> CouchbaseResult<Option<T>> {
+        let result = self
+            .collection
+            .lookup_in(
+                &self.id,
+                vec![LookupInSpec::get(SubdocPath::new().field(key).build()?)],
+                LookupInOptions::default(),
+            )
+            .await;
+        match result {
+            Ok(result) if result.exists(0) => result.content(0).map(Some),
+            Ok(_) | Err(CouchbaseError::DocumentNotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sets the value of the key, replacing any previous value.
+    pub async fn insert<S: Into<String>>(&self, key: S, value: T) -> CouchbaseResult<()> {
+        let spec = MutateInSpec::upsert(SubdocPath::new().field(key).build()?, encode(&value)?);
+        mutate_creating(&self.collection, &self.id, spec).await
+    }
+
+    /// Removes the key, which is a no-op if it does not exist.
+    pub async fn remove<S: Into<String>>(&self, key: S) -> CouchbaseResult<()> {
+        let result = self
+            .collection
+            .mutate_in(
+                &self.id,
+                vec![MutateInSpec::remove(SubdocPath::new().field(key).build()?)],
+                MutateInOptions::default(),
+            )
+            .await;
+        match result {
+            Ok(_)
+            | Err(CouchbaseError::DocumentNotFound { .. })
+            | Err(CouchbaseError::PathNotFound { .. }) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether the key exists.
+    pub async fn contains_key<S: Into<String>>(&self, key: S) -> CouchbaseResult<bool> {
+        let result = self
+            .collection
+            .lookup_in(
+                &self.id,
+                vec![LookupInSpec::exists(SubdocPath::new().field(key).build()?)],
+                LookupInOptions::default(),
+            )
+            .await;
+        match result {
+            Ok(result) => Ok(result.exists(0)),
+            Err(CouchbaseError::DocumentNotFound { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns all entries of the map.
+    pub async fn entries(&self) -> CouchbaseResult<HashMap<String, T>> {
+        let object = match self.collection.get(&self.id, GetOptions::default()).await {
+            Ok(result) => result.content::<Map<String, Value>>()?,
+            Err(CouchbaseError::DocumentNotFound { .. }) => return Ok(HashMap::new()),
+            Err(e) => return Err(e),
+        };
+        object
+            .into_iter()
+            .map(|(key, value)| decode(value).map(|value| (key, value)))
+            .collect()
+    }
+
+    /// Returns all keys of the map.
+    pub async fn keys(&self) -> CouchbaseResult<Vec<String>> {
+        Ok(self.entries().await?.into_keys().collect())
+    }
+
+    /// Returns all values of the map.
+    pub async fn values(&self) -> CouchbaseResult<Vec<T>> {
+        Ok(self.entries().await?.into_values().collect())
+    }
+
+    /// The number of entries in the map.
+    pub async fn size(&self) -> CouchbaseResult<usize> {
+        size(&self.collection, &self.id).await
+    }
+
+    /// Removes the map, including its document.
+    pub async fn clear(&self) -> CouchbaseResult<()> {
+        clear(&self.collection, &self.id).await
+    }
+}
+
+/// A set of unique values stored as a JSON array in a single document.
+///
+/// Uniqueness is enforced by the server, which only supports it for primitive values, so
+/// adding objects or arrays fails. Reads of a missing document behave as if the set was empty.
+pub struct CouchbaseSet<T> {
+    collection: Collection,
+    id: String,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> CouchbaseSet<T> {
+    pub(crate) fn new(collection: Collection, id: String) -> Self {
+        Self {
+            collection,
+            id,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The id of the document holding the set.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Adds the value and returns whether it was not part of the set yet.
+    pub async fn add(&self, value: T) -> CouchbaseResult<bool> {
+        let spec = MutateInSpec::array_add_unique("", encode(&value)?);
+        match mutate_creating(&self.collection, &self.id, spec).await {
+            Ok(()) => Ok(true),
+            Err(CouchbaseError::PathExists { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether the value is part of the set.
+    pub async fn contains(&self, value: &T) -> CouchbaseResult<bool> {
+        let value = encode(value)?;
+        let values = read_array(&self.collection, &self.id).await?.0;
+        Ok(values.contains(&value))
+    }
+
+    /// Removes the value and returns whether it was part of the set.
+    ///
+    /// The position of the value is looked up first, so the removal is retried if the set
+    /// changes in between, up to `MAX_CAS_ATTEMPTS` times.
+    pub async fn remove(&self, value: &T) -> CouchbaseResult<bool> {
+        let value = encode(value)?;
+        retry_on_cas_mismatch(|| async {
+            let (values, cas) = read_array(&self.collection, &self.id).await?;
+            let index = match values.iter().position(|v| *v == value) {
+                Some(index) => index,
+                None => return Ok(false),
+            };
+            remove_with_cas(&self.collection, &self.id, index as i64, cas).await?;
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Returns all values of the set.
+    pub async fn values(&self) -> CouchbaseResult<Vec<T>> {
+        let values = read_array(&self.collection, &self.id).await?.0;
+        values.into_iter().map(decode).collect()
+    }
+
+    /// The number of values in the set.
+    pub async fn size(&self) -> CouchbaseResult<usize> {
+        size(&self.collection, &self.id).await
+    }
+
+    /// Removes the set, including its document.
+    pub async fn clear(&self) -> CouchbaseResult<()> {
+        clear(&self.collection, &self.id).await
+    }
+}
+
+/// A first in, first out queue stored as a JSON array in a single document.
+///
+/// Values are pushed to the start of the array and popped from its end. Reads of a missing
+/// document behave as if the queue was empty.
+pub struct CouchbaseQueue<T> {
+    collection: Collection,
+    id: String,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> CouchbaseQueue<T> {
+    pub(crate) fn new(collection: Collection, id: String) -> Self {
+        Self {
+            collection,
+            id,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The id of the document holding the queue.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Adds the value to the queue.
+    pub async fn push(&self, value: T) -> CouchbaseResult<()> {
+        let spec = MutateInSpec::array_prepend("", vec![encode(&value)?]);
+        mutate_creating(&self.collection, &self.id, spec).await
+    }
+
+    /// Removes and returns the oldest value of the queue, if there is any.
+    ///
+    /// The value is read and removed under its CAS, so concurrent pops never return the same
+    /// value. The pop is retried up to `MAX_CAS_ATTEMPTS` times if the queue changes in between.
+    pub async fn pop(&self) -> CouchbaseResult<Option<T>> {
+        retry_on_cas_mismatch(|| async {
+            let (mut values, cas) = read_array(&self.collection, &self.id).await?;
+            let value = match values.pop() {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+            remove_with_cas(&self.collection, &self.id, -1, cas).await?;
+            decode(value).map(Some)
+        })
+        .await
+    }
+
+    /// The number of values in the queue.
+    pub async fn size(&self) -> CouchbaseResult<usize> {
+        size(&self.collection, &self.id).await
+    }
+
+    /// Removes the queue, including its document.
+    pub async fn clear(&self) -> CouchbaseResult<()> {
+        clear(&self.collection, &self.id).await
+    }
+}
+
+fn encode<T: Serialize>(value: &T) -> CouchbaseResult<Value> {
+    serde_json::to_value(value).map_err(|e| CouchbaseError::EncodingFailure {
+        ctx: ErrorContext::default(),
+        source: e.into(),
+    })
+}
+
+fn decode<T: DeserializeOwned>(value: Value) -> CouchbaseResult<T> {
+    serde_json::from_value(value).map_err(|e| CouchbaseError::DecodingFailure {
+        ctx: ErrorContext::default(),
+        source: e.into(),
+    })
+}
+
+/// Applies the spec, creating the document if it does not exist yet.
+async fn mutate_creating(
+    collection: &Collection,
+    id: &str,
+    spec: MutateInSpec,
+) -> CouchbaseResult<()> {
+    collection
+        .mutate_in(
+            id,
+            vec![spec],
+            MutateInOptions::default().store_semantics(StoreSemantics::Upsert),
+        )
+        .await
+        .map(|_| ())
+}
+
+/// Reads the array held by the document together with its CAS, which is zero if the
+/// document does not exist.
+async fn read_array(collection: &Collection, id: &str) -> CouchbaseResult<(Vec<Value>, u64)> {
+    match collection.get(id, GetOptions::default()).await {
+        Ok(result) => Ok((result.content()?, result.cas())),
+        Err(CouchbaseError::DocumentNotFound { .. }) => Ok((vec![], 0)),
+        Err(e) => Err(e),
+    }
+}
+
+async fn remove_with_cas(
+    collection: &Collection,
+    id: &str,
+    index: i64,
+    cas: u64,
+) -> CouchbaseResult<()> {
+    collection
+        .mutate_in(
+            id,
+            vec![MutateInSpec::remove(
+                SubdocPath::new().index(index).build()?,
+            )],
+            MutateInOptions::default().cas(cas),
+        )
+        .await
+        .map(|_| ())
+}
+
+async fn size(collection: &Collection, id: &str) -> CouchbaseResult<usize> {
+    let result = collection
+        .lookup_in(
+            id,
+            vec![LookupInSpec::count("")],
+            LookupInOptions::default(),
+        )
+        .await;
+    match result {
+        Ok(result) => result.content(0),
+        Err(CouchbaseError::DocumentNotFound { .. }) => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+async fn clear(collection: &Collection, id: &str) -> CouchbaseResult<()> {
+    match collection.remove(id, RemoveOptions::default()).await {
+        Ok(_) | Err(CouchbaseError::DocumentNotFound { .. }) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+async fn retry_on_cas_mismatch<F, Fut, R>(mut attempt: F) -> CouchbaseResult<R>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = CouchbaseResult<R>>,
+{
+    let mut attempts = 1;
+    loop {
+        match attempt().await {
+            Err(CouchbaseError::CasMismatch { .. }) if attempts < MAX_CAS_ATTEMPTS => {
+                attempts += 1;
+            }
+            // Another writer removed the document between the read and the removal
+            Err(CouchbaseError::DocumentNotFound { .. }) if attempts < MAX_CAS_ATTEMPTS => {
+                attempts += 1;
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::results::{
+    AnalyticsMetaData, AnalyticsResult, GenericManagementResult, ServiceType,
+};
+use crate::io::request::{GenericManagementRequest, Request};
+use crate::io::Core;
+use futures::channel::{mpsc, oneshot};
+use futures_timer::Delay;
+use serde_derive::Deserialize;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+/// How long `analytics_result` waits for a deferred query by default, like a regular query.
+const DEFAULT_WAIT: Duration = Duration::from_secs(75);
+
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Points to a deferred analytics query, see `AnalyticsOptions::deferred`.
+///
+/// The handle is the URI the analytics service returned for the query, so it can be stored and
+/// used to fetch the results from another process (or after a restart) as long as the
+/// analytics service still keeps them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnalyticsHandle {
+    uri: String,
+}
+
+impl AnalyticsHandle {
+    /// Recreates the handle from its `uri`.
+    pub fn new<S: Into<String>>(uri: S) -> Self {
+        Self { uri: uri.into() }
+    }
+
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// Splits the URI into the `host:port` of the analytics node and the path, relative handles
+    /// are fetched from any analytics node.
+    fn endpoint_and_path(&self) -> (Option<String>, String) {
+        let without_scheme = match self.uri.find("://") {
+            Some(i) => &self.uri[i + 3..],
+            None => return (None, self.uri.clone()),
+        };
+        match without_scheme.find('/') {
+            Some(i) => (
+                Some(without_scheme[..i].to_string()),
+                without_scheme[i..].to_string(),
+            ),
+            None => (Some(without_scheme.to_string()), String::from("/")),
+        }
+    }
+}
+
+/// The state of a deferred analytics query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnalyticsStatus {
+    Queued,
+    Running,
+    /// The query completed and its results can be fetched.
+    Success,
+    Failed,
+    /// Any other state reported by the analytics service.
+    Other(String),
+}
+
+impl From<&str> for AnalyticsStatus {
+    fn from(status: &str) -> Self {
+        match status {
+            "queued" => Self::Queued,
+            "running" => Self::Running,
+            "success" => Self::Success,
+            "failed" | "fatal" => Self::Failed,
+            _ => Self::Other(status.into()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct StatusResponse {
+    status: String,
+    handle: Option<String>,
+    #[serde(default)]
+    errors: Vec<Value>,
+}
+
+pub(crate) async fn status(
+    core: &Core,
+    handle: &AnalyticsHandle,
+    timeout: Option<Duration>,
+) -> CouchbaseResult<AnalyticsStatus> {
+    poll(core, handle, timeout)
+        .await
+        .map(|s| AnalyticsStatus::from(s.status.as_str()))
+}
+
+/// Waits for the deferred query to complete and fetches its results.
+pub(crate) async fn fetch(
+    core: &Core,
+    handle: &AnalyticsHandle,
+    timeout: Option<Duration>,
+) -> CouchbaseResult<AnalyticsResult> {
+    let deadline = Instant::now() + timeout.unwrap_or(DEFAULT_WAIT);
+    let mut interval = Duration::from_millis(100);
+    let result_handle = loop {
+        let response = poll(core, handle, timeout).await?;
+        match AnalyticsStatus::from(response.status.as_str()) {
+            AnalyticsStatus::Success if response.handle.is_some() => {
+                break AnalyticsHandle::new(response.handle.unwrap());
+            }
+            AnalyticsStatus::Queued | AnalyticsStatus::Running => {}
+            _ => {
+                let mut ctx = ErrorContext::default();
+                ctx.insert("handle", Value::String(handle.uri.clone()));
+                ctx.insert("status", Value::String(response.status));
+                ctx.insert("errors", Value::Array(response.errors));
+                return Err(CouchbaseError::Generic { ctx });
+            }
+        }
+
+        let now = Instant::now();
+        if now + interval >= deadline {
+            let mut ctx = ErrorContext::default();
+            ctx.insert("handle", Value::String(handle.uri.clone()));
+            ctx.insert("status", Value::String(response.status));
+            return Err(CouchbaseError::Timeout {
+                ambiguous: false,
+                ctx,
+            });
+        }
+        Delay::new(interval).await;
+        interval = (interval * 2).min(MAX_POLL_INTERVAL);
+    };
+
+    let result = get(core, &result_handle, timeout).await?;
+    let rows: Vec<Value> =
+        serde_json::from_slice(body(&result)).map_err(|e| CouchbaseError::DecodingFailure {
+            ctx: ErrorContext::default(),
+            source: e.into(),
+        })?;
+
+    let (rows_sender, rows_receiver) = mpsc::unbounded();
+    let (meta_sender, meta_receiver) = oneshot::channel();
+    let meta = AnalyticsMetaData::deferred(rows.len(), body(&result).len());
+    for row in rows {
+        let _ = rows_sender.unbounded_send(serde_json::to_vec(&row).unwrap());
+    }
+    let _ = meta_sender.send(meta);
+    Ok(AnalyticsResult::new(rows_receiver, meta_receiver))
+}
+
+async fn poll(
+    core: &Core,
+    handle: &AnalyticsHandle,
+    timeout: Option<Duration>,
+) -> CouchbaseResult<StatusResponse> {
+    let result = get(core, handle, timeout).await?;
+    serde_json::from_slice(body(&result)).map_err(|e| CouchbaseError::DecodingFailure {
+        ctx: ErrorContext::default(),
+        source: e.into(),
+    })
+}
+
+async fn get(
+    core: &Core,
+    handle: &AnalyticsHandle,
+    timeout: Option<Duration>,
+) -> CouchbaseResult<GenericManagementResult> {
+    let (endpoint, path) = handle.endpoint_and_path();
+    let (sender, receiver) = oneshot::channel();
+    let timer = core.send(Request::GenericManagementRequest(
+        GenericManagementRequest {
+            sender,
+            path,
+            method: String::from("get"),
+            payload: None,
+            content_type: None,
+            timeout,
+            service_type: Some(ServiceType::Analytics),
+            endpoint,
+        },
+    ));
+
+    let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
+    match result.http_status() {
+        200 => Ok(result),
+        status => {
+            let mut ctx = ErrorContext::default();
+            ctx.insert("handle", Value::String(handle.uri.clone()));
+            Err(CouchbaseError::GenericHTTP {
+                ctx,
+                status,
+                message: String::from_utf8_lossy(body(&result)).into_owned(),
+            })
+        }
+    }
+}
+
+fn body(result: &GenericManagementResult) -> &[u8] {
+    result.payload().map_or(&[], |p| p.as_slice())
+}
@@ -1,6 +1,6 @@
 use serde_json::Value;
 use snafu::Snafu;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::fmt::{Debug, Error, Formatter};
 
@@ -16,6 +16,15 @@ pub enum CouchbaseError {
         ctx: ErrorContext,
         source: std::io::Error,
     },
+    /// A row of a query, analytics or search result could not be decoded into the requested
+    /// type. `index` counts the rows of the result from 0, `row` holds it as received.
+    #[snafu(display("Decoding row {} of the result failed: {} {}", index, ctx, source))]
+    RowDecodingFailure {
+        ctx: ErrorContext,
+        index: usize,
+        row: Vec<u8>,
+        source: std::io::Error,
+    },
     #[snafu(display("Encoding the document content failed: {} {}", ctx, source))]
     EncodingFailure {
         ctx: ErrorContext,
@@ -38,6 +47,8 @@ pub enum CouchbaseError {
     InternalServerFailure { ctx: ErrorContext },
     #[snafu(display("Authentication was not successful: {}", ctx))]
     AuthenticationFailure { ctx: ErrorContext },
+    #[snafu(display("The user lacks the permissions for this request: {}", ctx))]
+    PermissionDenied { ctx: ErrorContext },
     #[snafu(display("A temporary failure occurred: {}", ctx))]
     TemporaryFailure { ctx: ErrorContext },
     #[snafu(display("Server-side parsing of the request failed: {}", ctx))]
@@ -76,6 +87,8 @@ pub enum CouchbaseError {
     DurableWriteInProgress { ctx: ErrorContext },
     #[snafu(display("Another durable write re-commit is currently in progress: {}", ctx))]
     DurableWriteReCommitInProgress { ctx: ErrorContext },
+    #[snafu(display("The durability level is below the minimum of the bucket: {}", ctx))]
+    DurabilityBelowMinimum { ctx: ErrorContext },
     #[snafu(display("The mutation has been lost: {}", ctx))]
     MutationLost { ctx: ErrorContext },
     #[snafu(display("The used path is not found: {}", ctx))]
@@ -120,6 +133,8 @@ pub enum CouchbaseError {
     PreparedStatementFailure { ctx: ErrorContext },
     #[snafu(display("The query could not be compilated: {}", ctx))]
     CompilationFailure { ctx: ErrorContext },
+    #[snafu(display("The query transaction failed: {}", ctx))]
+    QueryTransactionFailure { ctx: ErrorContext },
     #[snafu(display("The server job queue for this service is full: {}", ctx))]
     JobQueueFull { ctx: ErrorContext },
     #[snafu(display("The dataset is not found: {}", ctx))]
@@ -132,10 +147,16 @@ pub enum CouchbaseError {
     DataverseExists { ctx: ErrorContext },
     #[snafu(display("The link is not found: {}", ctx))]
     LinkNotFound { ctx: ErrorContext },
+    #[snafu(display("The link already exists: {}", ctx))]
+    LinkExists { ctx: ErrorContext },
     #[snafu(display("The view is not found: {}", ctx))]
     ViewNotFound { ctx: ErrorContext },
     #[snafu(display("The design document is not found: {}", ctx))]
     DesignDocumentNotFound { ctx: ErrorContext },
+    #[snafu(display("The query function is not found: {}", ctx))]
+    QueryFunctionNotFound { ctx: ErrorContext },
+    #[snafu(display("The query library is not found: {}", ctx))]
+    QueryLibraryNotFound { ctx: ErrorContext },
     #[snafu(display("The collection already exists: {}", ctx))]
     CollectionExists { ctx: ErrorContext },
     #[snafu(display("The scope already exists: {}", ctx))]
@@ -150,6 +171,17 @@ pub enum CouchbaseError {
     UserExists { ctx: ErrorContext },
     #[snafu(display("The bucket does not have flush enabled: {}", ctx))]
     BucketNotFlushable { ctx: ErrorContext },
+    #[snafu(display("The query returned no rows, but exactly one was expected: {}", ctx))]
+    NoRowsReturned { ctx: ErrorContext },
+    #[snafu(display(
+        "The query returned more than one row, but exactly one was expected: {}",
+        ctx
+    ))]
+    MultipleRowsReturned { ctx: ErrorContext },
+    #[snafu(display("The resource was modified concurrently: {}", ctx))]
+    ConcurrentModification { ctx: ErrorContext },
+    #[snafu(display("Some partitions of the search index failed: {}", ctx))]
+    PartialSearchFailure { ctx: ErrorContext },
     #[snafu(display("An error occurred: {} {} {}", ctx, status, message))]
     GenericHTTP {
         ctx: ErrorContext,
@@ -160,30 +192,123 @@ pub enum CouchbaseError {
 
 pub type CouchbaseResult<T, E = CouchbaseError> = std::result::Result<T, E>;
 
+#[derive(Default)]
 pub struct ErrorContext {
-    inner: HashMap<String, Value>,
+    // Sorted so the context is printed the same way every time
+    inner: BTreeMap<String, Value>,
 }
 
+/// The context fields which may carry user data, tagged so logs can be redacted.
+const USER_DATA_FIELDS: &[&str] = &["id", "key", "query", "row", "statement"];
+
 impl ErrorContext {
     pub fn insert<S: Into<String>>(&mut self, key: S, value: Value) {
         self.inner.insert(key.into(), value);
     }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        self.inner.get(key)
+    }
 }
 
-impl Default for ErrorContext {
-    fn default() -> Self {
-        ErrorContext {
-            inner: HashMap::new(),
+/// Roles which grant well-known permissions, matched by the end of the permission so they
+/// apply at every level (cluster, bucket, scope or collection).
+const ROLE_HINTS: &[(&str, &str)] = &[
+    ("cluster.eventing.functions!manage", "eventing_admin"),
+    (".eventing.function!manage", "eventing_manage_functions"),
+    (".analytics!manage", "analytics_manager"),
+    (".analytics!select", "analytics_select"),
+    (".fts!manage", "fts_admin"),
+    (".fts!read", "fts_searcher"),
+    (".n1ql.index!create", "query_manage_index"),
+    (".n1ql.index!drop", "query_manage_index"),
+    (".collections!write", "scope_admin"),
+];
+
+/// Builds a `PermissionDenied` from the body of a 403 response of a management endpoint.
+///
+/// The cluster manager and the search service list the missing permissions in `permissions`,
+/// eventing in `runtime_info.info`. Both are added to the context together with the roles
+/// which grant them, where known.
+pub(crate) fn permission_denied(
+    payload: Option<&[u8]>,
+    endpoint: Option<String>,
+) -> CouchbaseError {
+    let mut ctx = ErrorContext::default();
+    if let Some(endpoint) = endpoint {
+        ctx.insert("endpoint", Value::String(endpoint));
+    }
+    let body: Value = match payload.map(serde_json::from_slice) {
+        Some(Ok(body)) => body,
+        Some(Err(_)) => {
+            let raw = String::from_utf8_lossy(payload.unwrap()).into_owned();
+            ctx.insert("message", Value::String(raw));
+            return CouchbaseError::PermissionDenied { ctx };
+        }
+        None => return CouchbaseError::PermissionDenied { ctx },
+    };
+
+    if let Some(message) = body
+        .get("message")
+        .or_else(|| body.get("description"))
+        .and_then(Value::as_str)
+    {
+        ctx.insert("message", Value::String(message.into()));
+    }
+    let permissions: Vec<String> = body
+        .get("permissions")
+        .or_else(|| body.pointer("/runtime_info/info"))
+        .and_then(Value::as_array)
+        .map(|p| {
+            p.iter()
+                .filter_map(|p| p.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default();
+    if !permissions.is_empty() {
+        let mut roles: Vec<&str> = permissions
+            .iter()
+            .filter_map(|p| {
+                ROLE_HINTS
+                    .iter()
+                    .find(|(suffix, _)| p.ends_with(suffix))
+                    .map(|(_, role)| *role)
+            })
+            .collect();
+        roles.sort_unstable();
+        roles.dedup();
+        if !roles.is_empty() {
+            ctx.insert("roles", Value::from(roles));
         }
+        ctx.insert("permissions", Value::from(permissions));
     }
+    CouchbaseError::PermissionDenied { ctx }
 }
 
+/// Prints the context as JSON, with user data wrapped in `<ud>` tags like in the logs of the
+/// other Couchbase SDKs and the server.
 impl Display for ErrorContext {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        let tagged: BTreeMap<&str, Value> = self
+            .inner
+            .iter()
+            .map(|(k, v)| {
+                let v = if USER_DATA_FIELDS.contains(&k.as_str()) {
+                    let raw = match v {
+                        Value::String(s) => s.clone(),
+                        _ => v.to_string(),
+                    };
+                    Value::String(format!("<ud>{}</ud>", raw))
+                } else {
+                    v.clone()
+                };
+                (k.as_str(), v)
+            })
+            .collect();
         write!(
             f,
             "{}",
-            serde_json::to_string(&self.inner).unwrap_or_else(|_| "".into())
+            serde_json::to_string(&tagged).unwrap_or_else(|_| "".into())
         )
     }
 }
@@ -197,3 +322,74 @@ impl Debug for ErrorContext {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(payload: &str) -> ErrorContext {
+        match permission_denied(Some(payload.as_bytes()), Some("10.0.0.1:8091".into())) {
+            CouchbaseError::PermissionDenied { ctx } => ctx,
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+
+    #[test]
+    fn lists_the_missing_permissions_and_their_roles() {
+        let ctx = context(
+            r#"{
+                "message": "Forbidden. User needs one of the following permissions",
+                "permissions": [
+                    "cluster.bucket[travel].n1ql.index!create",
+                    "cluster.bucket[travel].fts!manage",
+                    "cluster.bucket[travel].n1ql.index!drop",
+                    "cluster.bucket[travel].fts!manage",
+                    "cluster.settings!write"
+                ]
+            }"#,
+        );
+        assert_eq!(
+            ctx.get("roles"),
+            Some(&Value::from(vec!["fts_admin", "query_manage_index"]))
+        );
+        assert_eq!(
+            ctx.get("permissions")
+                .and_then(Value::as_array)
+                .map(Vec::len),
+            Some(5)
+        );
+        assert_eq!(
+            ctx.get("message").and_then(Value::as_str),
+            Some("Forbidden. User needs one of the following permissions")
+        );
+        assert_eq!(
+            ctx.get("endpoint").and_then(Value::as_str),
+            Some("10.0.0.1:8091")
+        );
+    }
+
+    #[test]
+    fn reads_the_permissions_of_eventing() {
+        let ctx = context(
+            r#"{
+                "description": "Forbidden",
+                "runtime_info": {"info": ["cluster.eventing.functions!manage"]}
+            }"#,
+        );
+        assert_eq!(ctx.get("roles"), Some(&Value::from(vec!["eventing_admin"])));
+        assert_eq!(
+            ctx.get("message").and_then(Value::as_str),
+            Some("Forbidden")
+        );
+    }
+
+    #[test]
+    fn keeps_bodies_which_are_not_json() {
+        let ctx = context("Forbidden");
+        assert_eq!(
+            ctx.get("message").and_then(Value::as_str),
+            Some("Forbidden")
+        );
+        assert_eq!(ctx.get("roles"), None);
+    }
+}
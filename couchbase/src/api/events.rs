@@ -0,0 +1,106 @@
+use crate::api::results::ServiceType;
+use crate::api::retry::RetryReason;
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Receives the events of a cluster, i.e. to follow topology changes or to report the state of
+/// the SDK in health checks without scraping its logs.
+///
+/// Pass it to the cluster through `ClusterOptions::cluster_events`, or use
+/// `cluster_event_stream` to consume the events as a stream. Events are delivered on the IO
+/// thread, so the listener should return quickly.
+pub trait ClusterEventListener: Debug + Send + Sync {
+    fn on_event(&self, event: &ClusterEvent);
+}
+
+/// An event passed to the `ClusterEventListener`.
+///
+/// `bucket` is the bucket whose connections the event is about, `None` for the connections of
+/// the cluster itself. Remotes are given as `host:port`.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ClusterEvent {
+    /// A new cluster configuration has been applied.
+    ConfigUpdated {
+        bucket: Option<String>,
+        revision: i64,
+    },
+    /// A key/value connection has been established and negotiated.
+    EndpointConnected {
+        bucket: Option<String>,
+        remote: String,
+    },
+    /// A key/value connection has been closed because of an error, it is reconnected
+    /// afterwards.
+    EndpointDisconnected {
+        bucket: Option<String>,
+        remote: String,
+        reason: String,
+    },
+    /// An operation is going to be retried.
+    ///
+    /// `retry_after` is `None` if libcouchbase picks the backoff. Retries which are always
+    /// required, like `RetryReason::KvNotMyVbucket`, are not reported.
+    RetryScheduled {
+        reason: RetryReason,
+        attempts: u32,
+        retry_after: Option<Duration>,
+    },
+    /// The cluster or a bucket could not be bootstrapped.
+    BootstrapFailed {
+        bucket: Option<String>,
+        reason: String,
+    },
+    /// The circuit breaker of a key/value node opened, see `ClusterOptions::kv_circuit_breaker`.
+    CircuitOpened {
+        bucket: Option<String>,
+        remote: String,
+    },
+    /// The circuit breaker of a key/value node closed again after a successful canary.
+    CircuitClosed {
+        bucket: Option<String>,
+        remote: String,
+    },
+    /// Background pings of the endpoint failed repeatedly, see `HealthProbe`.
+    EndpointDegraded {
+        service: ServiceType,
+        remote: String,
+        reason: String,
+    },
+    /// The clock of a node is further off from the local clock than the threshold given to
+    /// `Collection::clock_skew`, which makes absolute expiries off by as much.
+    ClockSkewDetected {
+        bucket: Option<String>,
+        skew: Duration,
+        server_ahead: bool,
+    },
+    /// A degraded endpoint answered a background ping again.
+    EndpointRecovered {
+        service: ServiceType,
+        remote: String,
+    },
+}
+
+/// Returns a listener to pass to `ClusterOptions::cluster_events` and the stream it forwards
+/// the events into.
+///
+/// The stream is unbounded, so it should be consumed for as long as the cluster is in use.
+pub fn cluster_event_stream() -> (
+    Arc<dyn ClusterEventListener>,
+    UnboundedReceiver<ClusterEvent>,
+) {
+    let (sender, receiver) = mpsc::unbounded();
+    (Arc::new(StreamListener(sender)), receiver)
+}
+
+#[derive(Debug)]
+struct StreamListener(UnboundedSender<ClusterEvent>);
+
+impl ClusterEventListener for StreamListener {
+    fn on_event(&self, event: &ClusterEvent) {
+        // Fails once the stream has been dropped, which only means nobody listens anymore
+        let _ = self.0.unbounded_send(event.clone());
+    }
+}
@@ -0,0 +1,255 @@
+use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use serde_json::Value;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Expiries up to 30 days are sent as seconds, the server reads larger values as a unix
+/// timestamp.
+const RELATIVE_EXPIRY_CUTOFF: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// When the server removes a document.
+///
+/// Options which take an expiry accept a `Duration` or a `SystemTime` as well, so
+/// `UpsertOptions::default().expiry(Duration::from_secs(10))` keeps working. Durations over 30
+/// days are converted into a point in time by the SDK before they are sent to the server.
+///
+/// Note that mutations which do not set an expiry remove the expiry of the document, unless
+/// `preserve_expiry` is set on the options.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Expiry {
+    /// The document does not expire.
+    #[default]
+    Never,
+    /// The document expires after the duration, counted from the time of the mutation. Has to
+    /// be zero (which does not expire) or at least one second.
+    After(Duration),
+    /// The document expires at the given point in time, which has to be more than 30 days
+    /// after the unix epoch.
+    At(SystemTime),
+}
+
+impl Expiry {
+    /// Expires the document after the given number of seconds.
+    pub const fn seconds(seconds: u64) -> Self {
+        Expiry::After(Duration::from_secs(seconds))
+    }
+
+    /// Expires the document after the given number of minutes.
+    ///
+    /// Like the other constructors, this saturates instead of overflowing, the expiry is
+    /// rejected as too far in the future when it is used.
+    pub const fn minutes(minutes: u64) -> Self {
+        Expiry::After(Duration::from_secs(minutes.saturating_mul(60)))
+    }
+
+    /// Expires the document after the given number of hours.
+    pub const fn hours(hours: u64) -> Self {
+        Expiry::After(Duration::from_secs(hours.saturating_mul(60 * 60)))
+    }
+
+    /// Expires the document after the given number of days, which is sent as a point in time
+    /// if it is more than 30 days.
+    pub const fn days(days: u64) -> Self {
+        Expiry::After(Duration::from_secs(days.saturating_mul(24 * 60 * 60)))
+    }
+
+    /// Checks that the expiry can be represented by the server.
+    pub(crate) fn validate(&self) -> CouchbaseResult<()> {
+        let reason = match self {
+            Expiry::Never => return Ok(()),
+            Expiry::After(duration) => {
+                if *duration > Duration::from_secs(0) && *duration < Duration::from_secs(1) {
+                    "the expiry must be zero or at least one second"
+                } else if seconds_since_epoch(SystemTime::now() + *duration) > u32::MAX as u64 {
+                    "the expiry is too far in the future"
+                } else {
+                    return Ok(());
+                }
+            }
+            Expiry::At(time) => match time.duration_since(UNIX_EPOCH) {
+                Ok(since) if since < RELATIVE_EXPIRY_CUTOFF => {
+                    "the expiry must be more than 30 days after the unix epoch"
+                }
+                Ok(since) if since.as_secs() > u32::MAX as u64 => {
+                    "the expiry is too far in the future"
+                }
+                Ok(_) => return Ok(()),
+                Err(_) => "the expiry must be after the unix epoch",
+            },
+        };
+        let mut ctx = ErrorContext::default();
+        ctx.insert("expiry", Value::String(format!("{:?}", self)));
+        ctx.insert("reason", Value::String(reason.into()));
+        Err(CouchbaseError::InvalidArgument { ctx })
+    }
+
+    /// The expiry as sent to the server: zero for no expiry, seconds for up to 30 days and a
+    /// unix timestamp otherwise.
+    pub(crate) fn to_server(self) -> u32 {
+        let seconds = match self {
+            Expiry::Never => 0,
+            Expiry::After(duration) if duration < RELATIVE_EXPIRY_CUTOFF => duration.as_secs(),
+            Expiry::After(duration) => seconds_since_epoch(SystemTime::now() + duration),
+            Expiry::At(time) => seconds_since_epoch(time),
+        };
+        seconds.min(u32::MAX as u64) as u32
+    }
+}
+
+impl From<Duration> for Expiry {
+    fn from(duration: Duration) -> Self {
+        Expiry::After(duration)
+    }
+}
+
+impl From<SystemTime> for Expiry {
+    fn from(time: SystemTime) -> Self {
+        Expiry::At(time)
+    }
+}
+
+/// The difference between the clock of a node and the local clock, see
+/// `Collection::clock_skew`.
+///
+/// The server expires documents by its own clock, so an expiry given as a point in time (or a
+/// duration of more than 30 days) is off by the skew.
+#[derive(Debug, Copy, Clone)]
+pub struct ClockSkew {
+    server_time: SystemTime,
+    local_time: SystemTime,
+}
+
+impl ClockSkew {
+    pub(crate) fn new(server_time: SystemTime, local_time: SystemTime) -> Self {
+        Self {
+            server_time,
+            local_time,
+        }
+    }
+
+    /// The time of the node, with a resolution of one second.
+    pub fn server_time(&self) -> SystemTime {
+        self.server_time
+    }
+
+    /// The local time at which the node read its clock, assumed to be halfway through the
+    /// request.
+    pub fn local_time(&self) -> SystemTime {
+        self.local_time
+    }
+
+    /// How far the clocks are apart, in either direction.
+    pub fn skew(&self) -> Duration {
+        match self.server_time.duration_since(self.local_time) {
+            Ok(ahead) => ahead,
+            Err(behind) => behind.duration(),
+        }
+    }
+
+    /// Whether the clock of the node is ahead of the local clock, which makes documents expire
+    /// early.
+    pub fn server_ahead(&self) -> bool {
+        self.server_time > self.local_time
+    }
+}
+
+fn seconds_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Parses the `Date` header of an HTTP response, like `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(crate) fn parse_http_date(date: &str) -> Option<SystemTime> {
+    let mut parts = date.split_whitespace().skip(1);
+    let day: u64 = parts.next()?.parse().ok()?;
+    let month_name = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month_name)? as u64 + 1;
+    let year: u64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':').map(|p| p.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+    if parts.next() != Some("GMT") || year < 1970 || day == 0 || day > 31 {
+        return None;
+    }
+
+    // Days since the epoch of the civil date, counting years from March so that the leap day
+    // is the last day of the year
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let leap_days = year / 4 - year / 100 + year / 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let days = year * 365 + leap_days + day_of_year - 719_468;
+    Some(UNIX_EPOCH + Duration::from_secs(days * 86_400 + hours * 3_600 + minutes * 60 + seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_relative_expiries() {
+        assert_eq!(Expiry::seconds(90), Expiry::After(Duration::from_secs(90)));
+        assert_eq!(Expiry::minutes(2), Expiry::After(Duration::from_secs(120)));
+        assert_eq!(
+            Expiry::hours(3),
+            Expiry::After(Duration::from_secs(3 * 3_600))
+        );
+        assert_eq!(
+            Expiry::days(2),
+            Expiry::After(Duration::from_secs(2 * 86_400))
+        );
+    }
+
+    #[test]
+    fn saturates_huge_expiries() {
+        for expiry in [
+            Expiry::minutes(u64::MAX),
+            Expiry::hours(u64::MAX),
+            Expiry::days(u64::MAX),
+        ]
+        .iter()
+        {
+            assert_eq!(*expiry, Expiry::After(Duration::from_secs(u64::MAX)));
+        }
+    }
+
+    #[test]
+    fn measures_the_skew_in_both_directions() {
+        let local = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let ahead = ClockSkew::new(local + Duration::from_secs(7), local);
+        assert_eq!(ahead.skew(), Duration::from_secs(7));
+        assert!(ahead.server_ahead());
+
+        let behind = ClockSkew::new(local - Duration::from_secs(3), local);
+        assert_eq!(behind.skew(), Duration::from_secs(3));
+        assert!(!behind.server_ahead());
+
+        let synced = ClockSkew::new(local, local);
+        assert_eq!(synced.skew(), Duration::from_secs(0));
+        assert!(!synced.server_ahead());
+    }
+
+    #[test]
+    fn parses_http_dates() {
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(UNIX_EPOCH + Duration::from_secs(784_111_777))
+        );
+        assert_eq!(
+            parse_http_date("Thu, 29 Feb 2024 23:59:59 GMT"),
+            Some(UNIX_EPOCH + Duration::from_secs(1_709_251_199))
+        );
+        assert_eq!(
+            parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"),
+            Some(UNIX_EPOCH)
+        );
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 CET"), None);
+        assert_eq!(parse_http_date("yesterday"), None);
+    }
+}
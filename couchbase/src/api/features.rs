@@ -0,0 +1,194 @@
+use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::results::GenericManagementResult;
+use crate::io::request::{GenericManagementRequest, Request};
+use crate::io::Core;
+use futures::channel::oneshot;
+use log::debug;
+use serde_derive::Deserialize;
+use serde_json::Value;
+use std::fmt;
+
+/// A feature of Couchbase Server which is only available from a certain version on.
+///
+/// The SDK checks the features it relies on before sending a request, so using them against an
+/// older cluster fails with `FeatureNotAvailable` instead of an obscure server error. Use
+/// `Cluster::supports` to check a feature up front.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ServerFeature {
+    /// Scopes and collections.
+    Collections,
+    /// Keeping the expiry of a document when it is modified.
+    PreserveExpiry,
+    /// Search indexes which belong to a scope, see `Scope::search_query`.
+    ScopedSearchIndexes,
+    /// Vector queries, see `SearchOptions::vector_search`.
+    VectorSearch,
+    /// Scanning the documents of a collection by key range or sample.
+    RangeScan,
+    /// Sub-document lookups against replicas, see `Collection::lookup_in_any_replica`.
+    SubdocReplicaRead,
+}
+
+impl ServerFeature {
+    /// The first server version which supports the feature.
+    pub fn min_version(self) -> ServerVersion {
+        match self {
+            ServerFeature::Collections | ServerFeature::PreserveExpiry => {
+                ServerVersion::new(7, 0, 0)
+            }
+            ServerFeature::ScopedSearchIndexes
+            | ServerFeature::VectorSearch
+            | ServerFeature::RangeScan
+            | ServerFeature::SubdocReplicaRead => ServerVersion::new(7, 6, 0),
+        }
+    }
+}
+
+/// The version of Couchbase Server, without its build number and edition.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ServerVersion {
+    major: u32,
+    minor: u32,
+    patch: u32,
+}
+
+impl ServerVersion {
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parses the version reported by a node, like `7.2.4-7070-enterprise`.
+    pub fn parse(version: &str) -> Option<Self> {
+        let release = version.split('-').next()?;
+        let mut parts = release.split('.').map(|p| p.parse::<u32>());
+        let major = parts.next()?.ok()?;
+        let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+        let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+        Some(Self::new(major, minor, patch))
+    }
+
+    pub fn major(&self) -> u32 {
+        self.major
+    }
+
+    pub fn minor(&self) -> u32 {
+        self.minor
+    }
+
+    pub fn patch(&self) -> u32 {
+        self.patch
+    }
+
+    /// Whether servers of this version support the feature.
+    pub fn supports(&self, feature: ServerFeature) -> bool {
+        *self >= feature.min_version()
+    }
+}
+
+impl fmt::Display for ServerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PoolsDefault {
+    nodes: Vec<PoolsNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PoolsNode {
+    version: String,
+}
+
+/// The version of the cluster, which is the lowest version of its nodes so that features are
+/// only used once every node supports them.
+///
+/// It is read once and cached until the cluster configuration changes, i.e. because a node
+/// has been upgraded.
+pub(crate) async fn cluster_version(core: &Core) -> CouchbaseResult<ServerVersion> {
+    if let Some(version) = core.server_version() {
+        return Ok(version);
+    }
+    // A configuration applied while the version is read may already be about other nodes
+    let generation = core.config_generation();
+
+    let (sender, receiver) = oneshot::channel();
+    core.send(Request::GenericManagementRequest(
+        GenericManagementRequest {
+            sender,
+            path: String::from("/pools/default"),
+            method: String::from("get"),
+            payload: None,
+            content_type: None,
+            timeout: None,
+            service_type: None,
+            endpoint: None,
+        },
+    ));
+    let result: GenericManagementResult = receiver.await.unwrap()?;
+    let payload = result.payload().map_or(&[][..], |p| p.as_slice());
+    if result.http_status() != 200 {
+        return Err(CouchbaseError::GenericHTTP {
+            ctx: ErrorContext::default(),
+            status: result.http_status(),
+            message: String::from_utf8_lossy(payload).into_owned(),
+        });
+    }
+
+    let pools: PoolsDefault =
+        serde_json::from_slice(payload).map_err(|e| CouchbaseError::DecodingFailure {
+            ctx: ErrorContext::default(),
+            source: e.into(),
+        })?;
+    let version = pools
+        .nodes
+        .iter()
+        .filter_map(|n| ServerVersion::parse(&n.version))
+        .min()
+        .ok_or_else(|| {
+            let mut ctx = ErrorContext::default();
+            ctx.insert(
+                "reason",
+                Value::String("no node reported a valid version".into()),
+            );
+            CouchbaseError::DecodingFailure {
+                ctx,
+                source: std::io::ErrorKind::InvalidData.into(),
+            }
+        })?;
+    core.set_server_version(version, generation);
+    Ok(version)
+}
+
+/// Fails with `FeatureNotAvailable` if the cluster is known to not support the feature.
+///
+/// If the version of the cluster cannot be read, i.e. because the user may not read the
+/// cluster configuration, the request is sent anyway and left to the server.
+pub(crate) async fn require(core: &Core, feature: ServerFeature) -> CouchbaseResult<()> {
+    let version = match cluster_version(core).await {
+        Ok(version) => version,
+        Err(e) => {
+            debug!(
+                "Could not read the cluster version to check for {:?}: {}",
+                feature, e
+            );
+            return Ok(());
+        }
+    };
+    if version.supports(feature) {
+        return Ok(());
+    }
+    let mut ctx = ErrorContext::default();
+    ctx.insert("feature", Value::String(format!("{:?}", feature)));
+    ctx.insert(
+        "required_version",
+        Value::String(feature.min_version().to_string()),
+    );
+    ctx.insert("server_version", Value::String(version.to_string()));
+    Err(CouchbaseError::FeatureNotAvailable { ctx })
+}
@@ -0,0 +1,256 @@
+use crate::api::redaction::{SystemData, UserData};
+use crate::{CouchbaseError, ServiceType};
+use std::fmt::{Debug, Formatter};
+use std::time::Duration;
+
+/// Records the latency of every operation performed through the SDK.
+///
+/// Implement this trait to feed operation latencies into a metrics system of your choice and
+/// pass it to the cluster through `ClusterOptions::meter`.
+pub trait Meter: Debug + Send + Sync {
+    /// Called once an operation completed, successfully or not.
+    ///
+    /// # Arguments
+    ///
+    /// * `service` - the service the operation was dispatched to
+    /// * `operation` - the name of the operation, i.e. `get` or `query`
+    /// * `error` - the error the operation failed with, `None` on success
+    /// * `latency` - the time between dispatching the operation and receiving its result
+    fn record_latency(
+        &self,
+        service: ServiceType,
+        operation: &str,
+        error: Option<&CouchbaseError>,
+        latency: Duration,
+    );
+
+    /// Called whenever a retry was denied because the `RetryBudget` was exhausted.
+    fn record_retry_budget_exhausted(&self, _service: ServiceType) {}
+
+    /// Called once a query, search or analytics request finished, with the size of its response
+    /// body as received from the network and after decompression.
+    ///
+    /// Both sizes are the same unless `ClusterOptions::http_compression` is enabled and the
+    /// service compressed the response.
+    fn record_http_body_size(&self, _service: ServiceType, _wire_bytes: u64, _raw_bytes: u64) {}
+
+    /// Called on every read of a `CachedCollection`, with whether it was served from the cache.
+    #[cfg(feature = "read-cache")]
+    fn record_cache_access(&self, _hit: bool) {}
+
+    /// Called for every key/value response, with the name of the opcode (i.e. `get` or
+    /// `subdoc_multi_lookup`) and the time between scheduling the packet and receiving its
+    /// response.
+    ///
+    /// Unlike `record_latency`, which covers a whole operation, this is recorded per packet,
+    /// so retries and the packets of internal operations show up individually.
+    fn record_kv_latency(&self, _opcode: &'static str, _latency: Duration) {}
+
+    /// Called with the metadata of the slowest key/value packets, see
+    /// `ClusterOptions::slow_kv_sampling`.
+    fn record_slow_kv(&self, _packet: &KvPacketInfo) {}
+}
+
+/// The metadata of a key/value packet, captured when its response is received.
+///
+/// Values are never captured, only their sizes.
+#[derive(Clone)]
+pub struct KvPacketInfo {
+    pub(crate) opcode: u8,
+    pub(crate) opcode_name: &'static str,
+    pub(crate) status: Option<u16>,
+    pub(crate) opaque: u32,
+    pub(crate) partition: u16,
+    pub(crate) key: String,
+    pub(crate) cas: u64,
+    pub(crate) request_bytes: u32,
+    pub(crate) response_bytes: u32,
+    pub(crate) latency: Duration,
+    pub(crate) server_duration: Option<Duration>,
+    pub(crate) remote: Option<String>,
+}
+
+impl KvPacketInfo {
+    pub fn opcode(&self) -> u8 {
+        self.opcode
+    }
+
+    pub fn opcode_name(&self) -> &'static str {
+        self.opcode_name
+    }
+
+    /// The status of the response, `None` if the packet was failed locally (i.e. timed out).
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    pub fn opaque(&self) -> u32 {
+        self.opaque
+    }
+
+    pub fn partition(&self) -> u16 {
+        self.partition
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn cas(&self) -> u64 {
+        self.cas
+    }
+
+    /// The size of the request packet, including its value.
+    pub fn request_bytes(&self) -> u32 {
+        self.request_bytes
+    }
+
+    /// The size of the response body, including its value.
+    pub fn response_bytes(&self) -> u32 {
+        self.response_bytes
+    }
+
+    /// The time between scheduling the packet and receiving its response.
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// The time the server spent on the request, if reported.
+    pub fn server_duration(&self) -> Option<Duration> {
+        self.server_duration
+    }
+
+    /// The `host:port` of the node the packet was sent to, `None` if it never was.
+    pub fn remote(&self) -> Option<&str> {
+        self.remote.as_deref()
+    }
+}
+
+/// Tags the key and the remote, since slow packets are logged.
+impl Debug for KvPacketInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KvPacketInfo")
+            .field("opcode", &self.opcode)
+            .field("opcode_name", &self.opcode_name)
+            .field("status", &self.status)
+            .field("opaque", &self.opaque)
+            .field("partition", &self.partition)
+            .field("key", &UserData(&self.key))
+            .field("cas", &self.cas)
+            .field("request_bytes", &self.request_bytes)
+            .field("response_bytes", &self.response_bytes)
+            .field("latency", &self.latency)
+            .field("server_duration", &self.server_duration)
+            .field("remote", &SystemData(&self.remote))
+            .finish()
+    }
+}
+
+/// The default `Meter`, which does not record anything.
+#[derive(Debug, Default)]
+pub struct NoopMeter;
+
+impl Meter for NoopMeter {
+    fn record_latency(
+        &self,
+        _service: ServiceType,
+        _operation: &str,
+        _error: Option<&CouchbaseError>,
+        _latency: Duration,
+    ) {
+    }
+}
+
+/// A `Meter` which records through the `metrics` facade, into whichever recorder is installed
+/// (i.e. a Prometheus exporter).
+///
+/// Latencies are recorded in seconds into the `db.couchbase.operations` histogram, labeled with
+/// the `service`, the `operation` and its `outcome`, which is either `Success` or the kind of
+/// error the operation failed with.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+pub struct MetricsMeter;
+
+#[cfg(feature = "metrics")]
+impl Meter for MetricsMeter {
+    fn record_latency(
+        &self,
+        service: ServiceType,
+        operation: &str,
+        error: Option<&CouchbaseError>,
+        latency: Duration,
+    ) {
+        metrics::histogram!(
+            "db.couchbase.operations",
+            "service" => service.report_name(),
+            "operation" => operation.to_string(),
+            "outcome" => outcome(error),
+        )
+        .record(latency.as_secs_f64());
+    }
+
+    fn record_retry_budget_exhausted(&self, service: ServiceType) {
+        metrics::counter!(
+            "db.couchbase.retry_budget_exhausted",
+            "service" => service.report_name(),
+        )
+        .increment(1);
+    }
+
+    fn record_http_body_size(&self, service: ServiceType, wire_bytes: u64, raw_bytes: u64) {
+        metrics::histogram!(
+            "db.couchbase.http_body_size",
+            "service" => service.report_name(),
+            "encoding" => "wire",
+        )
+        .record(wire_bytes as f64);
+        metrics::histogram!(
+            "db.couchbase.http_body_size",
+            "service" => service.report_name(),
+            "encoding" => "raw",
+        )
+        .record(raw_bytes as f64);
+    }
+
+    #[cfg(feature = "read-cache")]
+    fn record_cache_access(&self, hit: bool) {
+        metrics::counter!(
+            "db.couchbase.cache_accesses",
+            "hit" => if hit { "true" } else { "false" },
+        )
+        .increment(1);
+    }
+
+    fn record_kv_latency(&self, opcode: &'static str, latency: Duration) {
+        metrics::histogram!("db.couchbase.kv_packets", "opcode" => opcode)
+            .record(latency.as_secs_f64());
+    }
+}
+
+/// The name of the error variant, which keeps the number of distinct labels bounded.
+#[cfg(feature = "metrics")]
+fn outcome(error: Option<&CouchbaseError>) -> String {
+    match error {
+        Some(e) => format!("{:?}", e)
+            .split(|c: char| !c.is_alphanumeric())
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        None => "Success".to_string(),
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use crate::ErrorContext;
+
+    #[test]
+    fn labels_outcomes_with_the_error_kind() {
+        assert_eq!(outcome(None), "Success");
+        let error = CouchbaseError::DocumentNotFound {
+            ctx: ErrorContext::default(),
+        };
+        assert_eq!(outcome(Some(&error)), "DocumentNotFound");
+    }
+}
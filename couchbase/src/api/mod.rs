@@ -1,27 +1,87 @@
+#[cfg(feature = "volatile")]
+pub mod analytics_links;
+pub mod audit;
+pub mod authenticator;
 pub mod buckets;
+#[cfg(feature = "read-cache")]
+pub mod cache;
+pub mod chunked;
 pub mod collections;
+pub mod counters;
+pub mod datastructures;
+pub mod deferred;
 pub mod error;
+pub mod events;
+pub mod expiry;
+pub mod features;
+pub mod metrics;
 pub mod options;
+#[cfg(feature = "volatile")]
+pub mod provisioning;
+pub mod query_args;
+pub mod query_functions;
+pub mod query_indexes;
+pub mod query_session;
+pub mod query_transaction;
+pub mod redaction;
 pub mod results;
+pub mod retry;
+pub mod sdk_info;
 pub mod search;
+pub mod search_indexes;
+pub mod security;
+#[cfg(feature = "volatile")]
+pub mod sharding;
+pub mod subdoc_path;
+pub mod tracing;
+pub mod transcoding;
 pub mod users;
 
+#[cfg(feature = "volatile")]
+use crate::api::analytics_links::AnalyticsLinkManager;
+use crate::api::audit::AuditManager;
+use crate::api::authenticator::{CertificateAuthenticator, CredentialProvider};
 use crate::api::buckets::BucketManager;
+use crate::api::chunked::ChunkedDocument;
+use crate::api::counters::CounterDocument;
+use crate::api::datastructures::{CouchbaseList, CouchbaseMap, CouchbaseQueue, CouchbaseSet};
+use crate::api::deferred::{AnalyticsHandle, AnalyticsStatus};
 use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::events::ClusterEvent;
+use crate::api::expiry::{parse_http_date, ClockSkew, Expiry};
+use crate::api::features::{ServerFeature, ServerVersion};
 use crate::api::options::*;
+#[cfg(feature = "volatile")]
+use crate::api::provisioning::ProvisioningManager;
+use crate::api::query_functions::QueryFunctionManager;
+use crate::api::query_indexes::QueryIndexManager;
+use crate::api::query_session::QuerySession;
+use crate::api::query_transaction::{execute, QueryTransaction};
+use crate::api::redaction::UserData;
 use crate::api::results::*;
+use crate::api::search_indexes::SearchIndexManager;
+#[cfg(feature = "volatile")]
+use crate::api::sharding::{ShardTarget, TenantRouter};
+use crate::api::subdoc_path::{insert, parse_path, resolve};
+use crate::api::transcoding::{TaggedTranscoder, JSON_COMMON_FLAGS};
 use crate::io::request::*;
-use crate::io::Core;
+use crate::io::{spawn_detached, Core, Credentials};
 use crate::CouchbaseError::Generic;
-use crate::{CollectionManager, SearchQuery, UserManager};
-use futures::channel::oneshot;
+use crate::{CollectionManager, MatchNoneQuery, PartialSearchResults, SearchQuery, UserManager};
+use futures::channel::{mpsc, oneshot};
+use futures::{FutureExt, StreamExt};
+use futures_timer::Delay;
+use log::warn;
+use serde::de::DeserializeOwned;
 use serde::Serialize;
+use serde_derive::Deserialize;
 use serde_json::{to_vec, Value};
 use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::future::Future;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Connect to a Couchbase cluster and perform cluster-level operations
 ///
@@ -60,6 +120,174 @@ impl Cluster {
         }
     }
 
+    /// Connect to a couchbase cluster with custom `ClusterOptions`
+    ///
+    /// # Arguments
+    ///
+    /// * `connection_string` - the connection string containing the bootstrap hosts
+    /// * `username` - the name of the user, used for authentication
+    /// * `password` - the password of the user
+    /// * `options` - cluster-wide options which are applied when connecting
+    ///
+    /// # Examples
+    ///
+    /// Connecting to localhost with tracing enabled:
+    /// ```no_run
+    /// let options = ClusterOptions::default().tracing(true);
+    /// let cluster = Cluster::connect_with_options("127.0.0.1", "username", "password", options);
+    /// ```
+    pub fn connect_with_options<S: Into<String>>(
+        connection_string: S,
+        username: S,
+        password: S,
+        options: ClusterOptions,
+    ) -> Self {
+        let mut credentials = Credentials::new(username.into(), password.into());
+        for (service, (username, password)) in options.service_credentials.clone() {
+            credentials.set_override(service, username, password);
+        }
+        Self::connect_with_credentials(connection_string.into(), credentials, options)
+    }
+
+    /// Connect to a couchbase cluster, asking the provider for credentials whenever a
+    /// connection authenticates
+    ///
+    /// # Arguments
+    ///
+    /// * `connection_string` - the connection string containing the bootstrap hosts
+    /// * `provider` - hands out the username and password, see `CredentialProvider`
+    /// * `options` - cluster-wide options which are applied when connecting
+    ///
+    /// The provider is told which service it is asked for, so this fails with
+    /// `InvalidArgument` if per-service credentials are set through
+    /// `ClusterOptions::service_credentials`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use couchbase::*;
+    /// # use std::sync::Arc;
+    /// # fn run() -> CouchbaseResult<()> {
+    /// let credentials = Arc::new(RotatingCredentials::new("username", "password"));
+    /// let cluster = Cluster::connect_with_credential_provider(
+    ///     "couchbase://127.0.0.1",
+    ///     credentials.clone(),
+    ///     ClusterOptions::default(),
+    /// )?;
+    /// credentials.rotate("username", "new-password");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn connect_with_credential_provider<S: Into<String>>(
+        connection_string: S,
+        provider: Arc<dyn CredentialProvider>,
+        options: ClusterOptions,
+    ) -> CouchbaseResult<Self> {
+        reject_service_credentials(&options, "credential provider")?;
+        Ok(Self::connect_with_credentials(
+            connection_string.into(),
+            Credentials::with_provider(provider),
+            options,
+        ))
+    }
+
+    fn connect_with_credentials(
+        mut connection_string: String,
+        credentials: Credentials,
+        options: ClusterOptions,
+    ) -> Self {
+        append_connection_string_params(&mut connection_string, options.connection_string_params());
+        let mut core = Core::with_credentials(connection_string, credentials);
+        if let Some(meter) = options.meter {
+            core.set_meter(meter);
+        }
+        if let Some(tracer) = options.tracer {
+            core.set_tracer(tracer);
+        }
+        if let Some(retry_budget) = options.retry_budget {
+            core.set_retry_budget(retry_budget);
+        }
+        if let Some(max_retries) = options.max_retries {
+            core.set_max_retries(max_retries);
+        }
+        if let Some(kv_keepalive) = options.kv_keepalive {
+            core.set_kv_keepalive(kv_keepalive);
+        }
+        if let Some(percent) = options.slow_kv_sampling {
+            core.set_slow_kv_sampling(percent);
+        }
+        if let Some(listener) = options.security_events {
+            core.set_security_event_listener(listener);
+        }
+        if let Some(pinning) = options.certificate_pinning {
+            core.set_certificate_pinning(pinning);
+        }
+        if let Some(level) = options.log_redaction {
+            redaction::raise_level(level);
+        }
+        if let Some(listener) = options.cluster_events {
+            core.set_cluster_event_listener(listener);
+        }
+        if let Some(health_probe) = options.health_probe {
+            core.set_health_probe(health_probe);
+        }
+        #[cfg(feature = "packet-dump")]
+        {
+            if let Some(every) = options.packet_dump_sampling {
+                core.set_packet_dump_sampling(every);
+            }
+        }
+        Cluster {
+            core: Arc::new(core),
+        }
+    }
+
+    /// Connect to a couchbase cluster, authenticating with a client certificate
+    ///
+    /// # Arguments
+    ///
+    /// * `connection_string` - the connection string containing the bootstrap hosts, which
+    ///   needs to use the `couchbases://` scheme
+    /// * `authenticator` - the client certificate and key to present
+    /// * `options` - cluster-wide options which are applied when connecting
+    ///
+    /// The certificate replaces all password based authentication, so this fails with
+    /// `InvalidArgument` if per-service credentials are set through
+    /// `ClusterOptions::service_credentials`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use couchbase::*;
+    /// # fn run() -> CouchbaseResult<()> {
+    /// let authenticator = CertificateAuthenticator::new("client.pem", "client.key");
+    /// let cluster = Cluster::connect_with_certificate(
+    ///     "couchbases://127.0.0.1",
+    ///     authenticator,
+    ///     ClusterOptions::default(),
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn connect_with_certificate<S: Into<String>>(
+        connection_string: S,
+        authenticator: CertificateAuthenticator,
+        options: ClusterOptions,
+    ) -> CouchbaseResult<Self> {
+        reject_service_credentials(&options, "certificate")?;
+        let mut connection_string = connection_string.into();
+        append_connection_string_params(
+            &mut connection_string,
+            authenticator.connection_string_params(),
+        );
+        Ok(Self::connect_with_options(
+            connection_string,
+            String::new(),
+            String::new(),
+            options,
+        ))
+    }
+
     /// Open and connect to a couchbase `Bucket`
     ///
     /// # Arguments
@@ -112,14 +340,222 @@ impl Cluster {
         statement: S,
         options: QueryOptions,
     ) -> CouchbaseResult<QueryResult> {
+        if options.endpoint.is_some() {
+            let endpoint = options.endpoint.clone();
+            return Ok(execute(&self.core, statement, options, endpoint).await?.0);
+        }
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::Query(QueryRequest {
+        let timer = self.core.send(Request::Query(QueryRequest {
             statement: statement.into(),
             options,
             sender,
             scope: None,
         }));
-        receiver.await.unwrap()
+        timer.finish(receiver.await.unwrap())
+    }
+
+    /// Opens a session which sends all of its queries to the same query node, see
+    /// `QuerySession`.
+    pub fn query_session(&self) -> QuerySession {
+        QuerySession::new(self.core.clone(), None)
+    }
+
+    /// Pings the services of the cluster and reports their latency
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - allows to pass in custom options
+    ///
+    /// # Examples
+    ///
+    /// Ping the query and search services and print the report.
+    /// ```no_run
+    /// # let cluster = Cluster::connect("127.0.0.1", "username", "password");
+    /// let options = PingOptions::default()
+    ///     .service_types(vec![ServiceType::Query, ServiceType::Search]);
+    /// let report = cluster.ping(options).await?;
+    /// println!("{}", report.to_json());
+    /// ```
+    ///
+    /// Key/value endpoints are only pinged once a bucket has been opened.
+    pub async fn ping(&self, options: PingOptions) -> CouchbaseResult<PingResult> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::Ping(PingRequest {
+            options,
+            sender,
+            bucket: None,
+        }));
+        timer.finish(receiver.await.unwrap())
+    }
+
+    /// Reports the state of the connections to the cluster, without performing any IO
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - allows to pass in custom options
+    ///
+    /// Use `ping` to actively check whether the services are reachable.
+    pub async fn diagnostics(
+        &self,
+        options: DiagnosticsOptions,
+    ) -> CouchbaseResult<DiagnosticsResult> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::Diagnostics(DiagnosticsRequest {
+            options,
+            sender,
+            bucket: None,
+        }));
+        timer.finish(receiver.await.unwrap())
+    }
+
+    /// Waits until the services of the cluster can be reached
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - how long to wait at most before failing with a `Timeout`
+    /// * `options` - allows to pass in custom options
+    ///
+    /// The cluster is pinged until the desired state is reached, so the first operations do
+    /// not need their own retry loop while the connections are still being established.
+    ///
+    /// Key/value connections belong to the buckets, so waiting for `ServiceType::KeyValue`
+    /// fails with `InvalidArgument`, use `Bucket::wait_until_ready` for those instead.
+    ///
+    /// ```no_run
+    /// # let cluster = Cluster::connect("127.0.0.1", "username", "password");
+    /// cluster
+    ///     .wait_until_ready(
+    ///         Duration::from_secs(10),
+    ///         WaitUntilReadyOptions::default().service_types(vec![ServiceType::Query]),
+    ///     )
+    ///     .await?;
+    /// ```
+    pub async fn wait_until_ready(
+        &self,
+        timeout: Duration,
+        options: WaitUntilReadyOptions,
+    ) -> CouchbaseResult<()> {
+        let service_types = options.service_types.as_deref().unwrap_or_default();
+        if service_types.contains(&ServiceType::KeyValue) {
+            let mut ctx = ErrorContext::default();
+            ctx.insert(
+                "msg",
+                Value::String(
+                    "the cluster has no key/value connections, wait on a bucket instead".into(),
+                ),
+            );
+            return Err(CouchbaseError::InvalidArgument { ctx });
+        }
+        wait_until_ready(&self.core, None, timeout, options).await
+    }
+
+    /// Opens connections and prepares statements ahead of the first requests
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - what to warm up
+    ///
+    /// The HTTP connections are otherwise only opened by the first request against each
+    /// node, which adds to its latency. Prepared statements are cached by the SDK, so later
+    /// queries with `adhoc` disabled skip the prepare round trip.
+    ///
+    /// ```no_run
+    /// # let cluster = Cluster::connect("127.0.0.1", "username", "password");
+    /// let options = PrewarmOptions::default()
+    ///     .query_nodes(true)
+    ///     .statements(vec!["SELECT * FROM `travel-sample` WHERE type = $1".into()])
+    ///     .search_indexes(vec!["travel-index".into()]);
+    /// cluster.prewarm(options).await?;
+    /// ```
+    pub async fn prewarm(&self, options: PrewarmOptions) -> CouchbaseResult<()> {
+        let mut service_types = vec![];
+        if options.query_nodes || !options.statements.is_empty() {
+            service_types.push(ServiceType::Query);
+        }
+        if !options.search_indexes.is_empty() {
+            service_types.push(ServiceType::Search);
+        }
+        if service_types.is_empty() {
+            return Ok(());
+        }
+
+        let mut ping_options = PingOptions::default().service_types(service_types);
+        ping_options.timeout = options.timeout;
+        self.ping(ping_options).await?;
+
+        for statement in options.statements {
+            let query_options = QueryOptions {
+                prepare_only: true,
+                timeout: options.timeout,
+                ..Default::default()
+            };
+            let mut result = self.query(statement, query_options).await?;
+            let rows: Vec<CouchbaseResult<Value>> = result.rows().collect().await;
+            for row in rows {
+                row?;
+            }
+            result.meta_data().await?;
+        }
+
+        for index in options.search_indexes {
+            let mut search_options = SearchOptions::default().limit(0);
+            search_options.timeout = options.timeout;
+            let mut result = self
+                .search_query(index, MatchNoneQuery, search_options)
+                .await?;
+            let rows: Vec<CouchbaseResult<SearchRow>> = result.rows().collect().await;
+            for row in rows {
+                row?;
+            }
+            result.meta_data().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts a N1QL transaction spanning multiple statements
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - allows to pass in custom options
+    ///
+    /// All statements executed through the returned `QueryTransaction` are sent to the query
+    /// node which started it, until it is committed or rolled back. A single statement can be
+    /// run in its own transaction through `QueryOptions::tx_implicit` instead.
+    pub async fn begin_query_transaction(
+        &self,
+        options: BeginQueryTransactionOptions,
+    ) -> CouchbaseResult<QueryTransaction> {
+        QueryTransaction::begin(self.core.clone(), options).await
+    }
+
+    /// Executes a N1QL query which is expected to return exactly one row
+    ///
+    /// # Arguments
+    ///
+    /// * `statement` - the N1QL statement to execute
+    /// * `options` - allows to pass in custom options
+    ///
+    /// # Examples
+    ///
+    /// Count the documents in a bucket.
+    /// ```no_run
+    /// # let cluster = Cluster::connect("127.0.0.1", "username", "password");
+    /// let count: u64 = cluster
+    ///     .query_one("select raw count(*) from `travel-sample`", QueryOptions::default())
+    ///     .await?;
+    /// ```
+    ///
+    /// Fails with `NoRowsReturned` if the query returned no rows and with `MultipleRowsReturned`
+    /// if it returned more than one.
+    pub async fn query_one<S: Into<String>, T: DeserializeOwned>(
+        &self,
+        statement: S,
+        options: QueryOptions,
+    ) -> CouchbaseResult<T> {
+        let statement = statement.into();
+        let mut result = self.query(statement.clone(), options).await?;
+        result.single_row(statement).await
     }
 
     /// Executes an analytics query
@@ -156,13 +592,53 @@ impl Cluster {
         options: AnalyticsOptions,
     ) -> CouchbaseResult<AnalyticsResult> {
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::Analytics(AnalyticsRequest {
+        let timer = self.core.send(Request::Analytics(AnalyticsRequest {
             statement: statement.into(),
             options,
             sender,
             scope: None,
         }));
-        receiver.await.unwrap()
+        timer.finish(receiver.await.unwrap())
+    }
+
+    /// Returns the state of a deferred analytics query.
+    ///
+    /// See `AnalyticsOptions::deferred` for how to run one.
+    pub async fn analytics_status(
+        &self,
+        handle: &AnalyticsHandle,
+        options: AnalyticsStatusOptions,
+    ) -> CouchbaseResult<AnalyticsStatus> {
+        deferred::status(&self.core, handle, options.timeout).await
+    }
+
+    /// Waits for a deferred analytics query to complete and fetches its results.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use couchbase::*;
+    /// # async fn run(cluster: Cluster) -> CouchbaseResult<()> {
+    /// let mut result = cluster
+    ///     .analytics_query("select * from dataset", AnalyticsOptions::default().deferred(true))
+    ///     .await?;
+    /// let handle = result.handle().await?.expect("deferred queries have a handle");
+    /// // ... possibly much later
+    /// let mut result = cluster
+    ///     .analytics_result(&handle, AnalyticsResultOptions::default())
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Fails with `Timeout` if the query is still running once the timeout passed, in which
+    /// case it keeps running and the call can be repeated.
+    pub async fn analytics_result(
+        &self,
+        handle: &AnalyticsHandle,
+        options: AnalyticsResultOptions,
+    ) -> CouchbaseResult<AnalyticsResult> {
+        deferred::fetch(&self.core, handle, options.timeout).await
     }
 
     /// Executes a search query
@@ -208,14 +684,35 @@ impl Cluster {
         query: T,
         options: SearchOptions,
     ) -> CouchbaseResult<SearchResult> {
+        if options.knn.is_some() {
+            features::require(&self.core, ServerFeature::VectorSearch).await?;
+        }
+        let partial_results = options.partial_results;
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::Search(SearchRequest {
+        let timer = self.core.send(Request::Search(SearchRequest {
             index: index.into(),
             query: query.to_json(),
             options,
             sender,
         }));
-        receiver.await.unwrap()
+        let result = timer.finish(receiver.await.unwrap())?;
+        match partial_results {
+            PartialSearchResults::Allow => Ok(result),
+            PartialSearchResults::Fail => result.fail_on_partial().await,
+        }
+    }
+
+    /// The version of the cluster, which is the lowest version of its nodes.
+    ///
+    /// It is read from the cluster configuration once and then cached until the configuration
+    /// changes, so upgraded nodes are picked up.
+    pub async fn server_version(&self) -> CouchbaseResult<ServerVersion> {
+        features::cluster_version(&self.core).await
+    }
+
+    /// Whether every node of the cluster supports the feature.
+    pub async fn supports(&self, feature: ServerFeature) -> CouchbaseResult<bool> {
+        Ok(self.server_version().await?.supports(feature))
     }
 
     /// Returns a new `UserManager`
@@ -248,6 +745,60 @@ impl Cluster {
         BucketManager::new(self.core.clone())
     }
 
+    /// Returns a new `SearchIndexManager`
+    ///
+    /// # Examples
+    ///
+    /// Point the `travel` alias to a freshly built index.
+    /// ```no_run
+    /// let cluster = Cluster::connect("127.0.0.1", "username", "password");
+    /// let alias = SearchIndex::alias("travel", vec![String::from("travel-v2")]);
+    /// cluster.search_indexes().upsert_index(alias, UpsertSearchIndexOptions::default());
+    /// ```
+    pub fn search_indexes(&self) -> SearchIndexManager {
+        SearchIndexManager::new(self.core.clone())
+    }
+
+    /// Returns a new `QueryIndexManager` to manage the indexes of the query service.
+    pub fn query_indexes(&self) -> QueryIndexManager {
+        QueryIndexManager::new(self.core.clone())
+    }
+
+    /// Returns a new `AnalyticsLinkManager` to manage the links of the analytics service to
+    /// remote clusters.
+    #[cfg(feature = "volatile")]
+    pub fn analytics_links(&self) -> AnalyticsLinkManager {
+        AnalyticsLinkManager::new(self.core.clone())
+    }
+
+    /// Returns a new `QueryFunctionManager` to manage the global user-defined functions of
+    /// the query service.
+    pub fn query_functions(&self) -> QueryFunctionManager {
+        QueryFunctionManager::new(self.core.clone())
+    }
+
+    /// Returns a new `AuditManager` to configure the audit log of the cluster.
+    pub fn audit(&self) -> AuditManager {
+        AuditManager::new(self.core.clone())
+    }
+
+    /// Returns a new `ProvisioningManager`
+    ///
+    /// Note that this API is unsupported and not stable, so you need to opt in via the
+    /// `volatile` feature to access it.
+    #[cfg(feature = "volatile")]
+    pub fn provisioning(&self) -> ProvisioningManager {
+        ProvisioningManager::new(self.core.clone())
+    }
+
+    /// Returns the capabilities supported by the IO backend this cluster is connected through.
+    ///
+    /// Operations which need a capability missing from this list fail right away with
+    /// `FeatureNotAvailable` instead of being dispatched.
+    pub fn capabilities(&self) -> Vec<Capability> {
+        self.core.capabilities().to_vec()
+    }
+
     /// Returns a reference to the underlying core.
     ///
     /// Note that this API is unsupported and not stable, so you need to opt in via the
@@ -282,6 +833,20 @@ impl Bucket {
         self.name.as_str()
     }
 
+    /// Reads the minimum durability level the bucket enforces from its settings.
+    ///
+    /// Once read, writes through the collections of the bucket which ask for a lower durability
+    /// level fail right away with `DurabilityBelowMinimum`, instead of being sent to the server.
+    pub async fn minimum_durability_level(
+        &self,
+        options: GetBucketOptions,
+    ) -> CouchbaseResult<DurabilityLevel> {
+        BucketManager::new(self.core.clone())
+            .get_bucket(self.name.clone(), options)
+            .await
+            .map(|settings| settings.minimum_durability_level())
+    }
+
     /// Opens a custom collection inside the `default` scope
     ///
     /// # Arguments
@@ -302,6 +867,20 @@ impl Bucket {
         Scope::new(self.core.clone(), name.into(), self.name.clone())
     }
 
+    /// Opens a `TenantRouter` which places tenants in the given collections.
+    ///
+    /// The layout is shared through the given document in the default collection. If it does
+    /// not exist yet, it is created with the given targets, otherwise the stored layout is used
+    /// and the targets are ignored.
+    #[cfg(feature = "volatile")]
+    pub async fn tenant_router<S: Into<String>>(
+        &self,
+        id: S,
+        targets: Vec<ShardTarget>,
+    ) -> CouchbaseResult<TenantRouter> {
+        TenantRouter::open(self.core.clone(), self.name.clone(), id.into(), targets).await
+    }
+
     /// Executes a ping request
     ///
     /// # Arguments
@@ -331,28 +910,295 @@ impl Bucket {
     /// See the [PingResult](struct.PingResult.html) for more information on what and how it can be consumed.
     pub async fn ping(&self, options: PingOptions) -> CouchbaseResult<PingResult> {
         let (sender, receiver) = oneshot::channel();
-        self.core
-            .send(Request::Ping(PingRequest { options, sender }));
-        receiver.await.unwrap()
+        let timer = self.core.send(Request::Ping(PingRequest {
+            options,
+            sender,
+            bucket: Some(self.name.clone()),
+        }));
+        timer.finish(receiver.await.unwrap())
     }
 
-    /// Returns a new `CollectionsManager`
+    /// Reports the state of the connections used by this bucket, without performing any IO.
+    pub async fn diagnostics(
+        &self,
+        options: DiagnosticsOptions,
+    ) -> CouchbaseResult<DiagnosticsResult> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::Diagnostics(DiagnosticsRequest {
+            options,
+            sender,
+            bucket: Some(self.name.clone()),
+        }));
+        timer.finish(receiver.await.unwrap())
+    }
+
+    /// Waits until the bucket is opened and its services can be reached
     ///
     /// # Arguments
     ///
-    /// # Examples
+    /// * `timeout` - how long to wait at most before failing with a `Timeout`
+    /// * `options` - allows to pass in custom options
+    pub async fn wait_until_ready(
+        &self,
+        timeout: Duration,
+        options: WaitUntilReadyOptions,
+    ) -> CouchbaseResult<()> {
+        wait_until_ready(&self.core, Some(self.name.clone()), timeout, options).await
+    }
+
+    /// Resolves the id the server assigned to a collection
+    ///
+    /// # Arguments
+    ///
+    /// * `scope` - the scope name, `_default` for the default scope
+    /// * `collection` - the collection name, `_default` for the default collection
+    /// * `options` - allows to pass in custom options
+    #[cfg(feature = "volatile")]
+    pub async fn collection_id<S: Into<String>>(
+        &self,
+        scope: S,
+        collection: S,
+        options: GetCollectionIdOptions,
+    ) -> CouchbaseResult<CollectionIdResult> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self
+            .core
+            .send(Request::GetCollectionId(GetCollectionIdRequest {
+                bucket: self.name.clone(),
+                scope: scope.into(),
+                collection: collection.into(),
+                sender,
+                options,
+            }));
+        timer.finish(receiver.await.unwrap())
+    }
+
+    /// Returns which node currently owns which partition of the bucket
+    ///
+    /// The map is built from the configuration the client already has, so no request is
+    /// sent to the cluster. It fails with `ServiceNotAvailable` before the bucket has been
+    /// bootstrapped and for memcached buckets, which are not partitioned.
+    #[cfg(feature = "volatile")]
+    pub async fn partition_map(&self) -> CouchbaseResult<PartitionMap> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self
+            .core
+            .send(Request::GetPartitionMap(GetPartitionMapRequest {
+                bucket: self.name.clone(),
+                sender,
+            }));
+        timer.finish(receiver.await.unwrap())
+    }
+
+    /// Streams the partition map every time it changes, starting with the current one
+    ///
+    /// A new map is emitted whenever the client receives a new configuration revision, i.e.
+    /// while partitions move during a rebalance or after a failover. The stream ends when
+    /// the cluster is shut down.
     ///
-    /// Connect and open the `travel-sample` bucket.
     /// ```no_run
-    /// let cluster = Cluster::connect("127.0.0.1", "username", "password");
+    /// # let cluster = Cluster::connect("127.0.0.1", "username", "password");
     /// let bucket = cluster.bucket("travel-sample");
-    /// let manager = bucket.collections();
+    /// let mut changes = bucket.partition_map_changes();
+    /// while let Some(map) = changes.next().await {
+    ///     println!("airline_10 is now owned by {:?}", map.owner_of("airline_10"));
+    /// }
     /// ```
-    pub fn collections(&self) -> CollectionManager {
-        CollectionManager::new(self.core.clone(), self.name.clone())
+    #[cfg(feature = "volatile")]
+    pub fn partition_map_changes(&self) -> impl futures::Stream<Item = PartitionMap> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.core
+            .send(Request::WatchPartitionMap(WatchPartitionMapRequest {
+                bucket: self.name.clone(),
+                sender,
+            }));
+        receiver
+    }
+
+    /// Opens a collection by its id, see `collection_id`
+    ///
+    /// The operations skip the name resolution, so the id is sent to the server as it is.
+    /// If the collection has been dropped in the meantime, they fail with
+    /// `CollectionNotFound`.
+    #[cfg(feature = "volatile")]
+    pub fn raw_collection(&self, collection_id: u32) -> RawCollection {
+        RawCollection {
+            core: self.core.clone(),
+            bucket_name: self.name.clone(),
+            collection_id,
+        }
+    }
+
+    /// Returns a new `CollectionsManager`
+    ///
+    /// # Arguments
+    ///
+    /// # Examples
+    ///
+    /// Connect and open the `travel-sample` bucket.
+    /// ```no_run
+    /// let cluster = Cluster::connect("127.0.0.1", "username", "password");
+    /// let bucket = cluster.bucket("travel-sample");
+    /// let manager = bucket.collections();
+    /// ```
+    pub fn collections(&self) -> CollectionManager {
+        CollectionManager::new(self.core.clone(), self.name.clone())
+    }
+}
+
+/// Appends the parameters to the query part of the connection string.
+/// Per-service credentials only make sense next to a username and password, so they are
+/// refused instead of silently dropped for the other ways of authenticating.
+fn reject_service_credentials(
+    options: &ClusterOptions,
+    authentication: &str,
+) -> CouchbaseResult<()> {
+    if options.service_credentials.is_empty() {
+        return Ok(());
+    }
+    let mut ctx = ErrorContext::default();
+    ctx.insert(
+        "reason",
+        Value::String(format!(
+            "service_credentials cannot be combined with {} authentication",
+            authentication
+        )),
+    );
+    Err(CouchbaseError::InvalidArgument { ctx })
+}
+
+fn append_connection_string_params(
+    connection_string: &mut String,
+    params: Vec<(&'static str, String)>,
+) {
+    for (key, value) in params {
+        let separator = if connection_string.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        connection_string.push_str(&format!("{}{}={}", separator, key, value));
+    }
+}
+
+/// How long to wait between two pings while waiting for the cluster to become ready.
+const WAIT_UNTIL_READY_INTERVAL: Duration = Duration::from_millis(50);
+const MAX_WAIT_UNTIL_READY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Pings the services until they reach the desired state or the timeout elapses.
+///
+/// Failed pings, like the ones sent before the bucket is opened, are retried as well.
+async fn wait_until_ready(
+    core: &Core,
+    bucket: Option<String>,
+    timeout: Duration,
+    options: WaitUntilReadyOptions,
+) -> CouchbaseResult<()> {
+    let deadline = Instant::now() + timeout;
+    let desired_state = options.desired_state.unwrap_or(ClusterState::Online);
+    let mut interval = WAIT_UNTIL_READY_INTERVAL;
+    loop {
+        let mut ping_options =
+            PingOptions::default().timeout(deadline.saturating_duration_since(Instant::now()));
+        if let Some(service_types) = &options.service_types {
+            ping_options = ping_options.service_types(service_types.clone());
+        }
+        let (sender, receiver) = oneshot::channel();
+        let timer = core.send(Request::Ping(PingRequest {
+            options: ping_options,
+            sender,
+            bucket: bucket.clone(),
+        }));
+        let mut ctx = ErrorContext::default();
+        match timer.finish(receiver.await.unwrap()) {
+            Ok(result) => {
+                let state = cluster_state(&result, options.service_types.as_deref());
+                if state_satisfies(state, desired_state) {
+                    return Ok(());
+                }
+                ctx.insert("state", Value::String(format!("{:?}", state)));
+            }
+            Err(e) => {
+                ctx.insert("last_error", Value::String(e.to_string()));
+            }
+        }
+
+        if Instant::now() + interval >= deadline {
+            ctx.insert(
+                "desired_state",
+                Value::String(format!("{:?}", desired_state)),
+            );
+            return Err(CouchbaseError::Timeout {
+                ambiguous: false,
+                ctx,
+            });
+        }
+        Delay::new(interval).await;
+        interval = (interval * 2).min(MAX_WAIT_UNTIL_READY_INTERVAL);
     }
 }
 
+/// Derives the state of the awaited services from a ping report.
+///
+/// Without explicit service types all services present in the report are awaited.
+fn cluster_state(result: &PingResult, service_types: Option<&[ServiceType]>) -> ClusterState {
+    let services: Vec<ServiceType> = match service_types {
+        Some(types) => types
+            .iter()
+            .copied()
+            .filter(|t| *t != ServiceType::Management)
+            .collect(),
+        None => result.endpoints().keys().copied().collect(),
+    };
+
+    let mut reachable = 0;
+    let mut unreachable = 0;
+    for service in services {
+        match result.endpoints().get(&service) {
+            Some(endpoints) if !endpoints.is_empty() => {
+                for endpoint in endpoints {
+                    match endpoint.state() {
+                        PingState::OK => reachable += 1,
+                        _ => unreachable += 1,
+                    }
+                }
+            }
+            _ => unreachable += 1,
+        }
+    }
+
+    match (reachable, unreachable) {
+        (0, _) => ClusterState::Offline,
+        (_, 0) => ClusterState::Online,
+        _ => ClusterState::Degraded,
+    }
+}
+
+fn state_satisfies(state: ClusterState, desired: ClusterState) -> bool {
+    match desired {
+        ClusterState::Online => state == ClusterState::Online,
+        ClusterState::Degraded => state != ClusterState::Offline,
+        ClusterState::Offline => true,
+    }
+}
+
+// Fails to compile if one of the futures stops being `Send`, or a write starts to hold on to
+// its content, which is borrowed from a value that is not `Sync` here.
+#[allow(dead_code)]
+fn assert_send_futures(cluster: &Cluster, collection: &Collection) {
+    fn is_send<T: Send>(_: T) {}
+    let content = std::cell::RefCell::new(0);
+    is_send(collection.get("id", GetOptions::default()));
+    is_send(collection.upsert("id", &content, UpsertOptions::default()));
+    is_send(collection.insert("id", &content, InsertOptions::default()));
+    is_send(collection.replace("id", &content, ReplaceOptions::default()));
+    is_send(collection.remove("id", RemoveOptions::default()));
+    is_send(collection.lookup_in("id", vec![], LookupInOptions::default()));
+    is_send(collection.mutate_in("id", vec![], MutateInOptions::default()));
+    is_send(collection.get_any_replica("id", GetAnyReplicaOptions::default()));
+    is_send(cluster.query("select 1", QueryOptions::default()));
+}
+
 /// Scopes provide access to a group of collections
 #[cfg(feature = "volatile")]
 pub struct Scope {
@@ -423,16 +1269,138 @@ impl Scope {
     pub async fn query<S: Into<String>>(
         &self,
         statement: S,
-        options: QueryOptions,
+        mut options: QueryOptions,
     ) -> CouchbaseResult<QueryResult> {
+        if options.endpoint.is_some() {
+            let endpoint = options.endpoint.clone();
+            options.query_context =
+                Some(query_session::query_context(&self.bucket_name, &self.name));
+            return Ok(execute(&self.core, statement, options, endpoint).await?.0);
+        }
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::Query(QueryRequest {
+        let timer = self.core.send(Request::Query(QueryRequest {
             statement: statement.into(),
             options,
             sender,
             scope: Some(self.name.clone()),
         }));
-        receiver.await.unwrap()
+        timer.finish(receiver.await.unwrap())
+    }
+
+    /// Opens a session which sends all of its queries to the same query node, with the scope as
+    /// their query context. See `QuerySession`.
+    pub fn query_session(&self) -> QuerySession {
+        QuerySession::new(
+            self.core.clone(),
+            Some(query_session::query_context(&self.bucket_name, &self.name)),
+        )
+    }
+
+    /// Executes a N1QL query which is expected to return exactly one row
+    ///
+    /// # Arguments
+    ///
+    /// * `statement` - the N1QL statement to execute
+    /// * `options` - allows to pass in custom options
+    ///
+    /// # Examples
+    ///
+    /// Count the documents in a bucket.
+    /// ```no_run
+    /// # let cluster = Cluster::connect("127.0.0.1", "username", "password");
+    /// # let bucket = cluster.bucket("travel-sample");
+    /// # let scope = bucket.scope("inventory");
+    /// let count: u64 = scope
+    ///     .query_one("select raw count(*) from `travel-sample`", QueryOptions::default())
+    ///     .await?;
+    /// ```
+    ///
+    /// Fails with `NoRowsReturned` if the query returned no rows and with `MultipleRowsReturned`
+    /// if it returned more than one.
+    pub async fn query_one<S: Into<String>, T: DeserializeOwned>(
+        &self,
+        statement: S,
+        options: QueryOptions,
+    ) -> CouchbaseResult<T> {
+        let statement = statement.into();
+        let mut result = self.query(statement.clone(), options).await?;
+        result.single_row(statement).await
+    }
+
+    /// Executes a search query against an index which belongs to this scope
+    ///
+    /// Scoped search indexes require Couchbase Server 7.6 or later. The hits are only
+    /// streamed once the full response has been received.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the name of the search index inside the scope
+    /// * `query` - the search query to perform
+    /// * `options` - allows to pass in custom options
+    pub async fn search_query<S: Into<String>, T: SearchQuery>(
+        &self,
+        index: S,
+        query: T,
+        mut options: SearchOptions,
+    ) -> CouchbaseResult<SearchResult> {
+        features::require(&self.core, ServerFeature::ScopedSearchIndexes).await?;
+        if options.knn.is_some() {
+            features::require(&self.core, ServerFeature::VectorSearch).await?;
+        }
+        let index = index.into();
+        options.consistency_ctl(&index);
+        options.index = Some(index.clone());
+        options.query = Some(query.to_json());
+        let payload =
+            serde_json::to_string(&options).map_err(|e| CouchbaseError::EncodingFailure {
+                ctx: ErrorContext::default(),
+                source: e.into(),
+            })?;
+
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::GenericManagementRequest(
+            GenericManagementRequest {
+                sender,
+                path: format!(
+                    "/api/bucket/{}/scope/{}/index/{}/query",
+                    self.bucket_name, self.name, index
+                ),
+                method: String::from("post"),
+                payload: Some(payload),
+                content_type: Some(String::from("application/json")),
+                timeout: options.timeout,
+                service_type: Some(ServiceType::Search),
+                endpoint: None,
+            },
+        ));
+        let result = timer.finish(receiver.await.unwrap())?;
+
+        let result = match result.http_status() {
+            200 => SearchResult::from_payload(result.payload().unwrap())?,
+            _ => return Err(search_indexes::parse_error(&result, index)),
+        };
+        match options.partial_results {
+            PartialSearchResults::Allow => Ok(result),
+            PartialSearchResults::Fail => result.fail_on_partial().await,
+        }
+    }
+
+    /// Returns a new `QueryFunctionManager` for the user-defined functions of this scope
+    pub fn query_functions(&self) -> QueryFunctionManager {
+        QueryFunctionManager::for_scope(
+            self.core.clone(),
+            self.bucket_name.clone(),
+            self.name.clone(),
+        )
+    }
+
+    /// Returns a new `SearchIndexManager` for the search indexes of this scope
+    pub fn search_indexes(&self) -> SearchIndexManager {
+        SearchIndexManager::for_scope(
+            self.core.clone(),
+            self.bucket_name.clone(),
+            self.name.clone(),
+        )
     }
 
     /// Executes an analytics query
@@ -469,17 +1437,38 @@ impl Scope {
         options: AnalyticsOptions,
     ) -> CouchbaseResult<AnalyticsResult> {
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::Analytics(AnalyticsRequest {
+        let timer = self.core.send(Request::Analytics(AnalyticsRequest {
             statement: statement.into(),
             options,
             sender,
             scope: Some(self.name.clone()),
         }));
-        receiver.await.unwrap()
+        timer.finish(receiver.await.unwrap())
     }
 }
 
+/// The longest time `Collection::get_and_lock` can lock a document for, the server rejects
+/// (or caps) longer lock times.
+pub const MAX_LOCK_TIME: Duration = Duration::from_secs(30);
+
+/// The clock skew above which `Collection::clock_skew` logs a warning by default.
+const DEFAULT_CLOCK_SKEW_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// The hybrid logical clock of a vbucket, as returned by the `$vbucket.HLC` virtual xattr.
+#[derive(Debug, Deserialize)]
+struct VbucketHlc {
+    /// The current time of the node in seconds since the epoch.
+    now: String,
+}
+
+/// The maximum number of specs the server accepts in a single sub-document lookup.
+const MAX_LOOKUP_SPECS: usize = 16;
+
 /// Primary API to access Key/Value operations
+///
+/// All futures returned are `Send`, so they can be spawned onto multi-threaded executors.
+/// Write operations encode their content before the future is returned, so it may be
+/// borrowed and does not need to be `Send` or `Sync` itself.
 pub struct Collection {
     core: Arc<Core>,
     name: String,
@@ -512,52 +1501,488 @@ impl Collection {
         id: S,
         options: GetOptions,
     ) -> CouchbaseResult<GetResult> {
+        let transcoder = options.transcoder.clone();
+        if !options.project.is_empty() || options.with_expiry {
+            return self
+                .get_projected(id.into(), options)
+                .await
+                .map(|r| r.with_transcoder(transcoder));
+        }
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::Get(GetRequest {
+        let timer = self.core.send(Request::Get(GetRequest {
             id: id.into(),
             ty: GetRequestType::Get { options },
             bucket: self.bucket_name.clone(),
             sender,
             scope: self.scope_name.clone(),
             collection: self.name.clone(),
+            collection_id: None,
         }));
-        receiver.await.unwrap()
+        timer
+            .finish(receiver.await.unwrap())
+            .map(|r| r.with_transcoder(transcoder))
+    }
+
+    /// Serves a get with projections or the expiry through a sub-document lookup.
+    async fn get_projected(&self, id: String, options: GetOptions) -> CouchbaseResult<GetResult> {
+        let paths = options
+            .project
+            .iter()
+            .map(|p| parse_path(p))
+            .collect::<CouchbaseResult<Vec<_>>>()?;
+
+        let mut specs = vec![LookupInSpec::get("$document.flags").xattr()];
+        if options.with_expiry {
+            specs.push(LookupInSpec::get("$document.exptime").xattr());
+        }
+        let first_path = specs.len();
+        // With too many paths the whole document is fetched and projected here instead
+        let lookup_paths = !paths.is_empty() && first_path + paths.len() <= MAX_LOOKUP_SPECS;
+        if lookup_paths {
+            specs.extend(
+                options
+                    .project
+                    .iter()
+                    .map(|p| LookupInSpec::get(p.as_str())),
+            );
+        } else {
+            specs.push(LookupInSpec::get(""));
+        }
+
+        let mut result = self
+            .lookup_in(
+                id,
+                specs,
+                LookupInOptions {
+                    timeout: options.timeout,
+                    retry_strategy: options.retry_strategy,
+                    max_retries: options.max_retries,
+                    access_deleted: None,
+                },
+            )
+            .await?;
+
+        let flags: u32 = result.content(0)?;
+        let expiry_time = if options.with_expiry {
+            match result.content::<u64>(1)? {
+                0 => None,
+                expiry => Some(UNIX_EPOCH + Duration::from_secs(expiry)),
+            }
+        } else {
+            None
+        };
+
+        let content = if paths.is_empty() {
+            result.take_raw_content(first_path)
+        } else {
+            let mut projected = Value::Object(Default::default());
+            if lookup_paths {
+                for (i, components) in paths.iter().enumerate() {
+                    if result.exists(first_path + i) {
+                        let value: Value = result.content(first_path + i)?;
+                        insert(&mut projected, components, value);
+                    }
+                }
+            } else {
+                let document: Value = result.content(first_path)?;
+                for components in &paths {
+                    if let Some(value) = resolve(&document, components) {
+                        insert(&mut projected, components, value.clone());
+                    }
+                }
+            }
+            to_vec(&projected).map_err(|e| CouchbaseError::EncodingFailure {
+                ctx: ErrorContext::default(),
+                source: e.into(),
+            })?
+        };
+        Ok(GetResult::new(content, result.cas(), flags).with_expiry_time(expiry_time))
     }
 
+    /// Fetches the document and locks it for the given time, which is rounded down to whole
+    /// seconds and has to be between one second and `MAX_LOCK_TIME`.
+    ///
+    /// Until it is unlocked (or the lock time passes), mutations of the document fail unless
+    /// they pass the CAS of the returned result.
     pub async fn get_and_lock<S: Into<String>>(
         &self,
         id: S,
         lock_time: Duration,
         options: GetAndLockOptions,
     ) -> CouchbaseResult<GetResult> {
+        if lock_time < Duration::from_secs(1) || lock_time > MAX_LOCK_TIME {
+            let mut ctx = ErrorContext::default();
+            ctx.insert("lock_time", Value::from(lock_time.as_secs_f64()));
+            ctx.insert(
+                "reason",
+                Value::String(format!(
+                    "the lock time must be between 1s and {}s",
+                    MAX_LOCK_TIME.as_secs()
+                )),
+            );
+            return Err(CouchbaseError::InvalidArgument { ctx });
+        }
+        let transcoder = options.transcoder.clone();
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::Get(GetRequest {
+        let timer = self.core.send(Request::Get(GetRequest {
             id: id.into(),
             ty: GetRequestType::GetAndLock { options, lock_time },
             bucket: self.bucket_name.clone(),
             sender,
             scope: self.scope_name.clone(),
             collection: self.name.clone(),
+            collection_id: None,
         }));
-        receiver.await.unwrap()
+        timer
+            .finish(receiver.await.unwrap())
+            .map(|r| r.with_transcoder(transcoder))
     }
 
-    pub async fn get_and_touch<S: Into<String>>(
+    /// Fetches the document and sets its expiry, see `Expiry`.
+    pub async fn get_and_touch<S: Into<String>, E: Into<Expiry>>(
         &self,
         id: S,
-        expiry: Duration,
+        expiry: E,
         options: GetAndTouchOptions,
     ) -> CouchbaseResult<GetResult> {
+        let transcoder = options.transcoder.clone();
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::Get(GetRequest {
+        let timer = self.core.send(Request::Get(GetRequest {
             id: id.into(),
-            ty: GetRequestType::GetAndTouch { options, expiry },
+            ty: GetRequestType::GetAndTouch {
+                options,
+                expiry: expiry.into(),
+            },
+            bucket: self.bucket_name.clone(),
+            sender,
+            scope: self.scope_name.clone(),
+            collection: self.name.clone(),
+            collection_id: None,
+        }));
+        timer
+            .finish(receiver.await.unwrap())
+            .map(|r| r.with_transcoder(transcoder))
+    }
+
+    /// Sets the expiry of the document without fetching it, see `Expiry`.
+    pub async fn touch<S: Into<String>, E: Into<Expiry>>(
+        &self,
+        id: S,
+        expiry: E,
+        options: TouchOptions,
+    ) -> CouchbaseResult<MutationResult> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::Touch(TouchRequest {
+            id: id.into(),
+            expiry: expiry.into(),
+            options,
+            bucket: self.bucket_name.clone(),
+            sender,
+            scope: self.scope_name.clone(),
+            collection: self.name.clone(),
+        }));
+        timer.finish(receiver.await.unwrap())
+    }
+
+    /// Measures how far the clock of the node holding the document is off from the local clock.
+    ///
+    /// Absolute expiries are evaluated by the clock of the server, so a skewed clock makes
+    /// documents expire early or late. The server time is read from the `$vbucket` virtual
+    /// extended attribute of the document (deleted documents work as well). If the document
+    /// does not exist, the time is taken from the `Date` header of a response of the cluster
+    /// manager instead, which may be served by another node than the one holding the document.
+    ///
+    /// If the clocks are further apart than `ClockSkewOptions::threshold`, a warning is logged
+    /// and `ClusterEvent::ClockSkewDetected` is sent to the cluster event listener.
+    pub async fn clock_skew<S: Into<String>>(
+        &self,
+        id: S,
+        options: ClockSkewOptions,
+    ) -> CouchbaseResult<ClockSkew> {
+        let id = id.into();
+        let skew = match self.vbucket_clock_skew(&id, options.timeout).await {
+            Err(CouchbaseError::DocumentNotFound { .. }) => {
+                cluster_manager_clock_skew(&self.core, options.timeout).await?
+            }
+            result => result?,
+        };
+
+        let threshold = options.threshold.unwrap_or(DEFAULT_CLOCK_SKEW_THRESHOLD);
+        if skew.skew() > threshold {
+            warn!(
+                "The clock of the node holding {} is {}s {} the local clock, absolute expiries will be off by as much",
+                UserData(&id),
+                skew.skew().as_secs(),
+                if skew.server_ahead() { "ahead of" } else { "behind" }
+            );
+            self.core
+                .emit_cluster_event(ClusterEvent::ClockSkewDetected {
+                    bucket: Some(self.bucket_name.clone()),
+                    skew: skew.skew(),
+                    server_ahead: skew.server_ahead(),
+                });
+        }
+        Ok(skew)
+    }
+
+    /// Reads the time of the node holding the document from its `$vbucket` virtual xattr.
+    async fn vbucket_clock_skew(
+        &self,
+        id: &str,
+        timeout: Option<Duration>,
+    ) -> CouchbaseResult<ClockSkew> {
+        let lookup_options = LookupInOptions {
+            timeout,
+            ..Default::default()
+        }
+        .access_deleted(true);
+        let sent = SystemTime::now();
+        let result = self
+            .lookup_in(
+                id,
+                vec![LookupInSpec::get("$vbucket.HLC").xattr()],
+                lookup_options,
+            )
+            .await?;
+        let received = SystemTime::now();
+
+        let hlc: VbucketHlc = result.content(0)?;
+        let server_seconds = hlc.now.parse::<u64>().map_err(|e| {
+            let mut ctx = ErrorContext::default();
+            ctx.insert("hlc", Value::String(hlc.now.clone()));
+            CouchbaseError::DecodingFailure {
+                ctx,
+                source: std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+            }
+        })?;
+        let round_trip = received.duration_since(sent).unwrap_or_default();
+        Ok(ClockSkew::new(
+            UNIX_EPOCH + Duration::from_secs(server_seconds),
+            sent + round_trip / 2,
+        ))
+    }
+
+    /// Releases the lock of a document locked through `get_and_lock`, given the CAS it
+    /// returned.
+    pub async fn unlock<S: Into<String>>(
+        &self,
+        id: S,
+        cas: u64,
+        options: UnlockOptions,
+    ) -> CouchbaseResult<()> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::Unlock(UnlockRequest {
+            id: id.into(),
+            cas,
+            options,
             bucket: self.bucket_name.clone(),
             sender,
             scope: self.scope_name.clone(),
             collection: self.name.clone(),
         }));
-        receiver.await.unwrap()
+        timer.finish(receiver.await.unwrap())
+    }
+
+    /// Fetches the document from the active node and all replicas, returning whichever
+    /// copy arrives first.
+    ///
+    /// Use `GetReplicaResult::is_replica` to find out where the document was read from. If no
+    /// copy could be read, `DocumentUnretrievable` is returned.
+    pub async fn get_any_replica<S: Into<String>>(
+        &self,
+        id: S,
+        options: GetAnyReplicaOptions,
+    ) -> CouchbaseResult<GetReplicaResult> {
+        let id = id.into();
+        let transcoder = options.transcoder;
+        let active = self.get_from_active(id.clone(), options.timeout);
+        let (sender, receiver) = mpsc::unbounded();
+        let timer = self.core.send(Request::GetReplica(GetReplicaRequest {
+            id: id.clone(),
+            bucket: self.bucket_name.clone(),
+            scope: self.scope_name.clone(),
+            collection: self.name.clone(),
+            sender,
+            mode: ReplicaMode::Any,
+            timeout: options.timeout,
+        }));
+        let replica = receiver.into_future().map(|(first, _)| match first {
+            Some(result) => result,
+            None => Err(CouchbaseError::RequestCanceled {
+                ctx: ErrorContext::default(),
+            }),
+        });
+
+        let result = match futures::future::select_ok(vec![active.boxed(), replica.boxed()]).await {
+            Ok((result, _)) => Ok(result.with_transcoder(transcoder)),
+            Err(_) => {
+                let mut ctx = ErrorContext::default();
+                ctx.insert("id", Value::String(id));
+                Err(CouchbaseError::DocumentUnretrievable { ctx })
+            }
+        };
+        timer.finish(result)
+    }
+
+    /// Fetches the document from the active node and all replicas.
+    ///
+    /// The copies are streamed as they arrive, so a slow or unavailable replica does not hold
+    /// back the others.
+    pub async fn get_all_replicas<S: Into<String>>(
+        &self,
+        id: S,
+        options: GetAllReplicasOptions,
+    ) -> CouchbaseResult<GetAllReplicasResult> {
+        let id = id.into();
+        let transcoder = options.transcoder;
+        let active = self.get_from_active(id.clone(), options.timeout);
+        let (sender, receiver) = mpsc::unbounded();
+        let timer = self.core.send(Request::GetReplica(GetReplicaRequest {
+            id: id.clone(),
+            bucket: self.bucket_name.clone(),
+            scope: self.scope_name.clone(),
+            collection: self.name.clone(),
+            sender,
+            mode: ReplicaMode::All,
+            timeout: options.timeout,
+        }));
+
+        // The read is finished once all copies arrived, and failed if none could be read
+        let copies = futures::stream::select(active.into_stream(), receiver);
+        let results =
+            futures::stream::unfold((copies, timer, false), move |(mut copies, timer, read)| {
+                let id = id.clone();
+                let transcoder = transcoder.clone();
+                async move {
+                    match copies.next().await {
+                        Some(result) => {
+                            let read = read || result.is_ok();
+                            let result = result.map(|r| r.with_transcoder(transcoder));
+                            Some((result, (copies, timer, read)))
+                        }
+                        None => {
+                            let outcome = if read {
+                                Ok(())
+                            } else {
+                                let mut ctx = ErrorContext::default();
+                                ctx.insert("id", Value::String(id));
+                                Err(CouchbaseError::DocumentUnretrievable { ctx })
+                            };
+                            let _ = timer.finish(outcome);
+                            None
+                        }
+                    }
+                }
+            });
+        Ok(GetAllReplicasResult::new(results.boxed()))
+    }
+
+    /// Reads the document from the active node as part of a replica read.
+    fn get_from_active(
+        &self,
+        id: String,
+        timeout: Option<Duration>,
+    ) -> impl futures::Future<Output = CouchbaseResult<GetReplicaResult>> {
+        let (sender, receiver) = oneshot::channel();
+        self.core.send(Request::Get(GetRequest {
+            id,
+            ty: GetRequestType::Get {
+                options: GetOptions {
+                    timeout,
+                    ..Default::default()
+                },
+            },
+            bucket: self.bucket_name.clone(),
+            sender,
+            scope: self.scope_name.clone(),
+            collection: self.name.clone(),
+            collection_id: None,
+        }));
+        receiver.map(|r| r.unwrap().map(GetReplicaResult::from_active))
+    }
+
+    /// Fetches a document and slides its expiry forward if it is about to expire.
+    ///
+    /// This implements the sliding expiry pattern used by session stores. The expiry is only
+    /// reset to `window` once the remaining time to live drops below the threshold, half of the
+    /// window by default, so most reads do not write to the document. The touch is dispatched
+    /// in the background and not awaited. Documents without an expiry are left untouched.
+    pub async fn get_sliding<S: Into<String>>(
+        &self,
+        id: S,
+        window: Duration,
+        options: GetSlidingOptions,
+    ) -> CouchbaseResult<GetResult> {
+        let id = id.into();
+        let mut result = self
+            .lookup_in(
+                id.clone(),
+                vec![
+                    LookupInSpec::get("$document.exptime").xattr(),
+                    LookupInSpec::get("$document.flags").xattr(),
+                    LookupInSpec::get(""),
+                ],
+                LookupInOptions {
+                    timeout: options.timeout,
+                    retry_strategy: None,
+                    max_retries: None,
+                    access_deleted: None,
+                },
+            )
+            .await?;
+
+        let expiry: u64 = result.content(0)?;
+        let flags: u32 = result.content(1)?;
+        let threshold = options.threshold.unwrap_or(window / 2);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let remaining = Duration::from_secs(expiry)
+            .checked_sub(now)
+            .unwrap_or_default();
+        if expiry != 0 && remaining < threshold {
+            // Nobody waits for the outcome, the touch is only there to keep the document alive.
+            let (sender, _) = oneshot::channel();
+            self.core.send(Request::Touch(TouchRequest {
+                id,
+                expiry: Expiry::After(window),
+                options: TouchOptions::default(),
+                bucket: self.bucket_name.clone(),
+                sender,
+                scope: self.scope_name.clone(),
+                collection: self.name.clone(),
+            }));
+        }
+
+        let cas = result.cas();
+        Ok(GetResult::new(result.take_raw_content(2), cas, flags))
+    }
+
+    /// Returns the key/value address (`host:port`) of the node which currently owns the document
+    ///
+    /// Only the partition map of the bucket is looked at, see `Bucket::partition_map`, so the
+    /// document does not need to exist. Fails with `ServiceNotAvailable` if no node owns the
+    /// partition of the document at the moment, i.e. during a failover.
+    #[cfg(feature = "volatile")]
+    pub async fn owner_of<S: Into<String>>(&self, id: S) -> CouchbaseResult<String> {
+        let id = id.into();
+        let (sender, receiver) = oneshot::channel();
+        let timer = self
+            .core
+            .send(Request::GetPartitionMap(GetPartitionMapRequest {
+                bucket: self.bucket_name.clone(),
+                sender,
+            }));
+        let map = timer.finish(receiver.await.unwrap())?;
+        match map.owner_of(&id) {
+            Some(owner) => Ok(owner.to_string()),
+            None => {
+                let mut ctx = ErrorContext::default();
+                ctx.insert("partition", Value::from(map.partition_for(&id)));
+                ctx.insert("id", Value::String(id));
+                Err(CouchbaseError::ServiceNotAvailable { ctx })
+            }
+        }
     }
 
     pub async fn exists<S: Into<String>>(
@@ -566,7 +1991,7 @@ impl Collection {
         options: ExistsOptions,
     ) -> CouchbaseResult<ExistsResult> {
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::Exists(ExistsRequest {
+        let timer = self.core.send(Request::Exists(ExistsRequest {
             id: id.into(),
             options,
             bucket: self.bucket_name.clone(),
@@ -574,78 +1999,167 @@ impl Collection {
             scope: self.scope_name.clone(),
             collection: self.name.clone(),
         }));
-        receiver.await.unwrap()
+        timer.finish(receiver.await.unwrap())
     }
 
-    pub async fn upsert<S: Into<String>, T>(
+    pub fn upsert<S: Into<String>, T>(
         &self,
         id: S,
         content: T,
         options: UpsertOptions,
-    ) -> CouchbaseResult<MutationResult>
+    ) -> impl Future<Output = CouchbaseResult<MutationResult>> + '_
     where
         T: Serialize,
     {
         self.mutate(id, content, MutateRequestType::Upsert { options })
-            .await
     }
 
-    pub async fn insert<S: Into<String>, T>(
+    /// Upserts an enum as a flat JSON document tagged with its variant.
+    ///
+    /// The document is flagged as JSON, read it back through `GetResult::content_tagged`
+    /// with the same transcoder.
+    pub fn upsert_tagged<S: Into<String>, T>(
+        &self,
+        id: S,
+        content: T,
+        transcoder: &TaggedTranscoder,
+        options: UpsertOptions,
+    ) -> impl Future<Output = CouchbaseResult<MutationResult>> + '_
+    where
+        T: Serialize,
+    {
+        let serialized = transcoder.encode(&content);
+        self.send_mutation(
+            id.into(),
+            serialized,
+            Some(JSON_COMMON_FLAGS),
+            MutateRequestType::Upsert { options },
+        )
+    }
+
+    /// Upserts a document and runs a search which is guaranteed to see the write.
+    ///
+    /// The search is consistent with the mutation token of the upsert, so the search service
+    /// waits until the index has caught up with the write, up to the search timeout, before
+    /// it runs the query. Combined with `SearchOptions::vector_search`, this indexes a freshly
+    /// written embedding and queries its neighbours without ever reading a stale index.
+    ///
+    /// Fails with `FeatureNotAvailable` if the upsert returned no mutation token, since the
+    /// search could not be made consistent with it.
+    pub fn upsert_and_search<S: Into<String>, T, I: Into<String>, Q: SearchQuery>(
+        &self,
+        id: S,
+        content: T,
+        options: UpsertOptions,
+        index: I,
+        query: Q,
+        search_options: SearchOptions,
+    ) -> impl Future<Output = CouchbaseResult<(MutationResult, SearchResult)>> + '_
+    where
+        T: Serialize,
+    {
+        let upsert = self.upsert(id, content, options);
+        self.search_after(upsert, index.into(), query.to_json(), search_options)
+    }
+
+    async fn search_after(
+        &self,
+        upsert: impl Future<Output = CouchbaseResult<MutationResult>>,
+        index: String,
+        query: Value,
+        search_options: SearchOptions,
+    ) -> CouchbaseResult<(MutationResult, SearchResult)> {
+        let mutation = upsert.await?;
+        if mutation.mutation_token().is_none() {
+            let mut ctx = ErrorContext::default();
+            ctx.insert(
+                "msg",
+                Value::String("The upsert did not return a mutation token".into()),
+            );
+            return Err(CouchbaseError::FeatureNotAvailable { ctx });
+        }
+
+        let mut state = MutationState::new();
+        state.add(&mutation);
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::Search(SearchRequest {
+            index,
+            query,
+            options: search_options.consistent_with(state),
+            sender,
+        }));
+        let result = timer.finish(receiver.await.unwrap())?;
+        Ok((mutation, result))
+    }
+
+    pub fn insert<S: Into<String>, T>(
         &self,
         id: S,
         content: T,
         options: InsertOptions,
-    ) -> CouchbaseResult<MutationResult>
+    ) -> impl Future<Output = CouchbaseResult<MutationResult>> + '_
     where
         T: Serialize,
     {
         self.mutate(id, content, MutateRequestType::Insert { options })
-            .await
     }
 
-    pub async fn replace<S: Into<String>, T>(
+    pub fn replace<S: Into<String>, T>(
         &self,
         id: S,
         content: T,
         options: ReplaceOptions,
-    ) -> CouchbaseResult<MutationResult>
+    ) -> impl Future<Output = CouchbaseResult<MutationResult>> + '_
     where
         T: Serialize,
     {
         self.mutate(id, content, MutateRequestType::Replace { options })
-            .await
     }
 
-    async fn mutate<S: Into<String>, T>(
+    /// Encodes the content right away, so the returned future neither borrows nor owns it.
+    ///
+    /// This keeps the future `Send` and free of the lifetime of the content, which allows
+    /// passing borrowed values that are not `Sync`.
+    fn mutate<S: Into<String>, T>(
         &self,
         id: S,
         content: T,
         ty: MutateRequestType,
-    ) -> CouchbaseResult<MutationResult>
+    ) -> impl Future<Output = CouchbaseResult<MutationResult>> + '_
     where
         T: Serialize,
     {
-        let serialized = match to_vec(&content) {
-            Ok(v) => v,
-            Err(e) => {
-                return Err(CouchbaseError::EncodingFailure {
-                    ctx: ErrorContext::default(),
-                    source: e.into(),
-                })
-            }
+        let (serialized, flags) = match encode_content(&content, &ty) {
+            Ok((encoded, flags)) => (Ok(encoded), flags),
+            Err(e) => (Err(e), None),
         };
+        self.send_mutation(id.into(), serialized, flags, ty)
+    }
 
+    async fn send_mutation(
+        &self,
+        id: String,
+        serialized: CouchbaseResult<Vec<u8>>,
+        flags: Option<u32>,
+        ty: MutateRequestType,
+    ) -> CouchbaseResult<MutationResult> {
+        let content = serialized?;
+        if preserves_expiry(&ty) {
+            features::require(&self.core, ServerFeature::PreserveExpiry).await?;
+        }
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::Mutate(MutateRequest {
-            id: id.into(),
-            content: serialized,
+        let timer = self.core.send(Request::Mutate(MutateRequest {
+            id,
+            content,
+            flags,
             sender,
             bucket: self.bucket_name.clone(),
             ty,
             scope: self.scope_name.clone(),
             collection: self.name.clone(),
+            collection_id: None,
         }));
-        receiver.await.unwrap()
+        timer.finish(receiver.await.unwrap())
     }
 
     pub async fn remove<S: Into<String>>(
@@ -654,15 +2168,16 @@ impl Collection {
         options: RemoveOptions,
     ) -> CouchbaseResult<MutationResult> {
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::Remove(RemoveRequest {
+        let timer = self.core.send(Request::Remove(RemoveRequest {
             id: id.into(),
             sender,
             bucket: self.bucket_name.clone(),
             options,
             scope: self.scope_name.clone(),
             collection: self.name.clone(),
+            collection_id: None,
         }));
-        receiver.await.unwrap()
+        timer.finish(receiver.await.unwrap())
     }
 
     pub async fn lookup_in<S: Into<String>>(
@@ -671,8 +2186,157 @@ impl Collection {
         specs: Vec<LookupInSpec>,
         options: LookupInOptions,
     ) -> CouchbaseResult<LookupInResult> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::LookupIn(LookupInRequest {
+            id: id.into(),
+            specs,
+            sender,
+            bucket: self.bucket_name.clone(),
+            options,
+            scope: self.scope_name.clone(),
+            collection: self.name.clone(),
+        }));
+        timer.finish(receiver.await.unwrap())
+    }
+
+    /// Performs a sub-document lookup against the active node and all replicas, returning
+    /// whichever answers first.
+    ///
+    /// The replicas are read with the replica read document flag, so this needs Couchbase Server
+    /// 7.6 or later and fails with `FeatureNotAvailable` on older clusters.
+    ///
+    /// Use `LookupInReplicaResult::is_replica` to find out where the document was read from. If
+    /// no copy could be read, `DocumentUnretrievable` is returned.
+    pub async fn lookup_in_any_replica<S: Into<String>>(
+        &self,
+        id: S,
+        specs: Vec<LookupInSpec>,
+        options: LookupInAnyReplicaOptions,
+    ) -> CouchbaseResult<LookupInReplicaResult> {
+        features::require(&self.core, ServerFeature::SubdocReplicaRead).await?;
+        let id = id.into();
+        let active = self.lookup_in_active(id.clone(), specs.clone(), options.timeout);
+        let (sender, receiver) = mpsc::unbounded();
+        let timer = self
+            .core
+            .send(Request::LookupInReplica(LookupInReplicaRequest {
+                id: id.clone(),
+                bucket: self.bucket_name.clone(),
+                scope: self.scope_name.clone(),
+                collection: self.name.clone(),
+                sender,
+                specs,
+                mode: ReplicaMode::Any,
+                timeout: options.timeout,
+            }));
+        // Unlike `get_any_replica`, every replica answers on its own, so skip the failed ones
+        let replica = receiver
+            .filter_map(|r| futures::future::ready(r.ok()))
+            .into_future()
+            .map(|(first, _)| {
+                first.ok_or(CouchbaseError::RequestCanceled {
+                    ctx: ErrorContext::default(),
+                })
+            });
+
+        let result = match futures::future::select_ok(vec![active.boxed(), replica.boxed()]).await {
+            Ok((result, _)) => Ok(result),
+            Err(_) => {
+                let mut ctx = ErrorContext::default();
+                ctx.insert("id", Value::String(id));
+                Err(CouchbaseError::DocumentUnretrievable { ctx })
+            }
+        };
+        timer.finish(result)
+    }
+
+    /// Performs a sub-document lookup against the active node and all replicas.
+    ///
+    /// Like `lookup_in_any_replica`, the replicas are read with the replica read document flag.
+    /// The results are streamed as they arrive, so a slow or unavailable replica does not hold
+    /// back the others.
+    pub async fn lookup_in_all_replicas<S: Into<String>>(
+        &self,
+        id: S,
+        specs: Vec<LookupInSpec>,
+        options: LookupInAllReplicasOptions,
+    ) -> CouchbaseResult<LookupInAllReplicasResult> {
+        features::require(&self.core, ServerFeature::SubdocReplicaRead).await?;
+        let id = id.into();
+        let active = self.lookup_in_active(id.clone(), specs.clone(), options.timeout);
+        let (sender, receiver) = mpsc::unbounded();
+        let timer = self
+            .core
+            .send(Request::LookupInReplica(LookupInReplicaRequest {
+                id: id.clone(),
+                bucket: self.bucket_name.clone(),
+                scope: self.scope_name.clone(),
+                collection: self.name.clone(),
+                sender,
+                specs,
+                mode: ReplicaMode::All,
+                timeout: options.timeout,
+            }));
+
+        // The lookup is finished once all copies answered, and failed if none could be read
+        let copies = futures::stream::select(active.into_stream(), receiver);
+        let results =
+            futures::stream::unfold((copies, timer, false), move |(mut copies, timer, read)| {
+                let id = id.clone();
+                async move {
+                    match copies.next().await {
+                        Some(result) => {
+                            let read = read || result.is_ok();
+                            Some((result, (copies, timer, read)))
+                        }
+                        None => {
+                            let outcome = if read {
+                                Ok(())
+                            } else {
+                                let mut ctx = ErrorContext::default();
+                                ctx.insert("id", Value::String(id));
+                                Err(CouchbaseError::DocumentUnretrievable { ctx })
+                            };
+                            let _ = timer.finish(outcome);
+                            None
+                        }
+                    }
+                }
+            });
+        Ok(LookupInAllReplicasResult::new(results.boxed()))
+    }
+
+    /// Performs the lookup against the active node as part of a replica lookup.
+    fn lookup_in_active(
+        &self,
+        id: String,
+        specs: Vec<LookupInSpec>,
+        timeout: Option<Duration>,
+    ) -> impl futures::Future<Output = CouchbaseResult<LookupInReplicaResult>> {
         let (sender, receiver) = oneshot::channel();
         self.core.send(Request::LookupIn(LookupInRequest {
+            id,
+            specs,
+            sender,
+            bucket: self.bucket_name.clone(),
+            options: LookupInOptions {
+                timeout,
+                ..Default::default()
+            },
+            scope: self.scope_name.clone(),
+            collection: self.name.clone(),
+        }));
+        receiver.map(|r| r.unwrap().map(|r| LookupInReplicaResult::new(r, false)))
+    }
+
+    pub async fn mutate_in<S: Into<String>>(
+        &self,
+        id: S,
+        specs: Vec<MutateInSpec>,
+        options: MutateInOptions,
+    ) -> CouchbaseResult<MutateInResult> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::MutateIn(MutateInRequest {
             id: id.into(),
             specs,
             sender,
@@ -681,30 +2345,74 @@ impl Collection {
             scope: self.scope_name.clone(),
             collection: self.name.clone(),
         }));
-        receiver.await.unwrap()
+        timer.finish(receiver.await.unwrap())
+    }
+
+    pub fn binary(&self) -> BinaryCollection {
+        BinaryCollection::new(
+            self.core.clone(),
+            self.name.clone(),
+            self.scope_name.clone(),
+            self.bucket_name.clone(),
+        )
+    }
+
+    /// Returns a `DetachedCollection` for fire-and-forget mutations, which are handed to the
+    /// IO layer right away and whose outcome is not awaited.
+    pub fn detached(&self) -> DetachedCollection {
+        DetachedCollection::new(
+            self.core.clone(),
+            self.name.clone(),
+            self.scope_name.clone(),
+            self.bucket_name.clone(),
+        )
+    }
+
+    /// Returns a `ChunkedDocument` which stores values larger than the server limit under the
+    /// given id.
+    pub fn chunked<S: Into<String>>(&self, id: S) -> ChunkedDocument {
+        ChunkedDocument::new(self.clone_handle(), id.into())
+    }
+
+    /// Returns a `CounterDocument` which maintains named counters inside the given document.
+    pub fn counters<S: Into<String>>(&self, id: S) -> CounterDocument {
+        CounterDocument::new(self.clone_handle(), id.into())
+    }
+
+    /// Returns a `CouchbaseList` which stores a list in the given document.
+    pub fn list<T, S: Into<String>>(&self, id: S) -> CouchbaseList<T>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        CouchbaseList::new(self.clone_handle(), id.into())
+    }
+
+    /// Returns a `CouchbaseMap` which stores a map in the given document.
+    pub fn map<T, S: Into<String>>(&self, id: S) -> CouchbaseMap<T>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        CouchbaseMap::new(self.clone_handle(), id.into())
+    }
+
+    /// Returns a `CouchbaseSet` which stores a set in the given document.
+    pub fn set<T, S: Into<String>>(&self, id: S) -> CouchbaseSet<T>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        CouchbaseSet::new(self.clone_handle(), id.into())
     }
 
-    pub async fn mutate_in<S: Into<String>>(
-        &self,
-        id: S,
-        specs: Vec<MutateInSpec>,
-        options: MutateInOptions,
-    ) -> CouchbaseResult<MutateInResult> {
-        let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::MutateIn(MutateInRequest {
-            id: id.into(),
-            specs,
-            sender,
-            bucket: self.bucket_name.clone(),
-            options,
-            scope: self.scope_name.clone(),
-            collection: self.name.clone(),
-        }));
-        receiver.await.unwrap()
+    /// Returns a `CouchbaseQueue` which stores a queue in the given document.
+    pub fn queue<T, S: Into<String>>(&self, id: S) -> CouchbaseQueue<T>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        CouchbaseQueue::new(self.clone_handle(), id.into())
     }
 
-    pub fn binary(&self) -> BinaryCollection {
-        BinaryCollection::new(
+    fn clone_handle(&self) -> Collection {
+        Collection::new(
             self.core.clone(),
             self.name.clone(),
             self.scope_name.clone(),
@@ -713,12 +2421,101 @@ impl Collection {
     }
 }
 
-#[derive(Debug)]
+/// A set of mutation tokens which queries and searches can be made consistent with.
+///
+/// Collect the results of the mutations a query or search must observe, to read your own
+/// writes without waiting for the index to catch up with all mutations (`RequestPlus`).
+///
+/// ```no_run
+/// # use couchbase::*;
+/// # async fn run(cluster: Cluster, collection: Collection) -> CouchbaseResult<()> {
+/// let mut state = MutationState::new();
+/// state.add(&collection.upsert("airline_1", "{}", UpsertOptions::default()).await?);
+/// state.add(&collection.upsert("airline_2", "{}", UpsertOptions::default()).await?);
+///
+/// let result = cluster
+///     .query(
+///         "SELECT * FROM `travel-sample` WHERE type = 'airline'",
+///         QueryOptions::default().consistent_with(state),
+///     )
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default, Clone)]
 pub struct MutationState {
     tokens: Vec<MutationToken>,
 }
 
-#[derive(Debug)]
+impl MutationState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the token of the given mutation, mutations without a token are ignored.
+    pub fn add(&mut self, result: &MutationResult) {
+        if let Some(token) = result.mutation_token() {
+            self.tokens.push(token.clone());
+        }
+    }
+
+    /// Adds a single mutation token, like the one of a `CounterResult` or `MutateInResult`.
+    pub fn add_token(&mut self, token: &MutationToken) {
+        self.tokens.push(token.clone());
+    }
+
+    /// Adds all tokens of another state, i.e. one collected by a concurrent task.
+    pub fn merge(&mut self, other: MutationState) {
+        self.tokens.extend(other.tokens);
+    }
+
+    pub fn tokens(&self) -> &[MutationToken] {
+        self.tokens.as_slice()
+    }
+
+    /// Renders the tokens in the scan vector format of the query service, per bucket.
+    pub(crate) fn query_vectors(&self) -> serde_json::Map<String, Value> {
+        let mut vectors = serde_json::Map::new();
+        for token in self.latest_tokens() {
+            let bucket = vectors
+                .entry(token.bucket_name.clone())
+                .or_insert_with(|| Value::Object(serde_json::Map::new()));
+            bucket[token.partition_id.to_string()] =
+                serde_json::json!([token.sequence_number, token.partition_uuid.to_string()]);
+        }
+        vectors
+    }
+
+    /// Renders the tokens in the consistency vector format of the search service.
+    pub(crate) fn search_vectors(&self) -> serde_json::Map<String, Value> {
+        self.latest_tokens()
+            .into_iter()
+            .map(|t| {
+                (
+                    format!("{}/{}", t.partition_id, t.partition_uuid),
+                    Value::from(t.sequence_number),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the token with the highest sequence number for every partition.
+    fn latest_tokens(&self) -> Vec<&MutationToken> {
+        let mut latest: Vec<&MutationToken> = vec![];
+        for token in &self.tokens {
+            match latest.iter_mut().find(|t| {
+                t.bucket_name == token.bucket_name && t.partition_id == token.partition_id
+            }) {
+                Some(t) if t.sequence_number < token.sequence_number => *t = token,
+                Some(_) => {}
+                None => latest.push(token),
+            }
+        }
+        latest
+    }
+}
+
+#[derive(Clone)]
 pub struct MutationToken {
     partition_uuid: u64,
     sequence_number: u64,
@@ -758,6 +2555,16 @@ impl MutationToken {
     }
 }
 
+impl fmt::Debug for MutationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "MutationToken {{ bucket_name: {}, partition_id: {}, partition_uuid: 0x{:x}, sequence_number: {} }}",
+            self.bucket_name, self.partition_id, self.partition_uuid, self.sequence_number
+        )
+    }
+}
+
 #[derive(Debug)]
 pub enum MutateInSpec {
     Replace { path: String, value: Vec<u8> },
@@ -834,7 +2641,7 @@ impl MutateInSpec {
     {
         let mut value = content
             .into_iter()
-            .map(|v| {
+            .flat_map(|v| {
                 let mut encoded = match to_vec(&v.into()) {
                     Ok(v) => v,
                     Err(_e) => panic!("Could not encode the value :-("),
@@ -842,7 +2649,6 @@ impl MutateInSpec {
                 encoded.push(b',');
                 encoded
             })
-            .flatten()
             .collect::<Vec<_>>();
         value.pop().unwrap();
 
@@ -858,7 +2664,7 @@ impl MutateInSpec {
     {
         let mut value = content
             .into_iter()
-            .map(|v| {
+            .flat_map(|v| {
                 let mut encoded = match to_vec(&v.into()) {
                     Ok(v) => v,
                     Err(_e) => panic!("Could not encode the value :-("),
@@ -866,7 +2672,6 @@ impl MutateInSpec {
                 encoded.push(b',');
                 encoded
             })
-            .flatten()
             .collect::<Vec<_>>();
         value.pop().unwrap();
 
@@ -882,7 +2687,7 @@ impl MutateInSpec {
     {
         let mut value = content
             .into_iter()
-            .map(|v| {
+            .flat_map(|v| {
                 let mut encoded = match to_vec(&v.into()) {
                     Ok(v) => v,
                     Err(_e) => panic!("Could not encode the value :-("),
@@ -890,7 +2695,6 @@ impl MutateInSpec {
                 encoded.push(b',');
                 encoded
             })
-            .flatten()
             .collect::<Vec<_>>();
         value.pop().unwrap();
 
@@ -905,24 +2709,56 @@ impl MutateInSpec {
     }
 }
 
-#[derive(Debug)]
-pub enum LookupInSpec {
-    Get { path: String },
-    Exists { path: String },
-    Count { path: String },
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum LookupInOperation {
+    Get,
+    Exists,
+    Count,
+}
+
+#[derive(Debug, Clone)]
+pub struct LookupInSpec {
+    pub(crate) operation: LookupInOperation,
+    pub(crate) path: String,
+    pub(crate) xattr: bool,
 }
 
 impl LookupInSpec {
+    fn new(operation: LookupInOperation, path: String) -> Self {
+        LookupInSpec {
+            operation,
+            path,
+            xattr: false,
+        }
+    }
+
     pub fn get<S: Into<String>>(path: S) -> Self {
-        LookupInSpec::Get { path: path.into() }
+        Self::new(LookupInOperation::Get, path.into())
     }
 
     pub fn exists<S: Into<String>>(path: S) -> Self {
-        LookupInSpec::Exists { path: path.into() }
+        Self::new(LookupInOperation::Exists, path.into())
     }
 
     pub fn count<S: Into<String>>(path: S) -> Self {
-        LookupInSpec::Count { path: path.into() }
+        Self::new(LookupInOperation::Count, path.into())
+    }
+
+    /// Looks up the path in the extended attributes instead of the document body.
+    ///
+    /// Note that the server requires all extended attribute specs to come first.
+    pub fn xattr(mut self) -> Self {
+        self.xattr = true;
+        self
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Whether the path is looked up in the extended attributes.
+    pub fn is_xattr(&self) -> bool {
+        self.xattr
     }
 }
 
@@ -955,16 +2791,18 @@ impl BinaryCollection {
         options: AppendOptions,
     ) -> CouchbaseResult<MutationResult> {
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::Mutate(MutateRequest {
+        let timer = self.core.send(Request::Mutate(MutateRequest {
             id: id.into(),
             content,
+            flags: None,
             sender,
             bucket: self.bucket_name.clone(),
             ty: MutateRequestType::Append { options },
             scope: self.scope_name.clone(),
             collection: self.name.clone(),
+            collection_id: None,
         }));
-        receiver.await.unwrap()
+        timer.finish(receiver.await.unwrap())
     }
 
     pub async fn prepend<S: Into<String>>(
@@ -974,16 +2812,18 @@ impl BinaryCollection {
         options: PrependOptions,
     ) -> CouchbaseResult<MutationResult> {
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::Mutate(MutateRequest {
+        let timer = self.core.send(Request::Mutate(MutateRequest {
             id: id.into(),
             content,
+            flags: None,
             sender,
             bucket: self.bucket_name.clone(),
             ty: MutateRequestType::Prepend { options },
             scope: self.scope_name.clone(),
             collection: self.name.clone(),
+            collection_id: None,
         }));
-        receiver.await.unwrap()
+        timer.finish(receiver.await.unwrap())
     }
 
     pub async fn increment<S: Into<String>>(
@@ -991,76 +2831,408 @@ impl BinaryCollection {
         id: S,
         options: IncrementOptions,
     ) -> CouchbaseResult<CounterResult> {
-        let delta = match options.delta {
-            Some(d) => i64::try_from(d).map_err(|_e| CouchbaseError::Generic {
+        let options = increment_options(options)?;
+        self.counter(id.into(), options).await
+    }
+
+    pub async fn decrement<S: Into<String>>(
+        &self,
+        id: S,
+        options: DecrementOptions,
+    ) -> CouchbaseResult<CounterResult> {
+        let options = decrement_options(options)?;
+        self.counter(id.into(), options).await
+    }
+
+    async fn counter(&self, id: String, options: CounterOptions) -> CouchbaseResult<CounterResult> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::Counter(CounterRequest {
+            id,
+            sender,
+            bucket: self.bucket_name.clone(),
+            options,
+            scope: self.scope_name.clone(),
+            collection: self.name.clone(),
+        }));
+        timer.finish(receiver.await.unwrap())
+    }
+}
+
+/// Reads the time of the cluster manager from the `Date` header of its response.
+async fn cluster_manager_clock_skew(
+    core: &Core,
+    timeout: Option<Duration>,
+) -> CouchbaseResult<ClockSkew> {
+    let (sender, receiver) = oneshot::channel();
+    let mut request = GenericManagementRequest::new(sender, "/pools".into(), "get".into(), None);
+    if let Some(timeout) = timeout {
+        request.timeout(timeout);
+    }
+    let sent = SystemTime::now();
+    core.send(Request::GenericManagementRequest(request));
+    let result = receiver.await.unwrap()?;
+    let received = SystemTime::now();
+
+    let server_time = result.date().and_then(parse_http_date).ok_or_else(|| {
+        let mut ctx = ErrorContext::default();
+        ctx.insert(
+            "date",
+            result
+                .date()
+                .map_or(Value::Null, |d| Value::String(d.into())),
+        );
+        CouchbaseError::DecodingFailure {
+            ctx,
+            source: std::io::ErrorKind::InvalidData.into(),
+        }
+    })?;
+    let round_trip = received.duration_since(sent).unwrap_or_default();
+    Ok(ClockSkew::new(server_time, sent + round_trip / 2))
+}
+
+/// Encodes the content of a mutation, through the transcoder given in its options if there is
+/// one and as JSON otherwise, together with the flags to store it with.
+fn encode_content<T: Serialize>(
+    content: &T,
+    ty: &MutateRequestType,
+) -> CouchbaseResult<(Vec<u8>, Option<u32>)> {
+    let transcoder = match ty {
+        MutateRequestType::Insert { options } => options.transcoder.as_ref(),
+        MutateRequestType::Upsert { options } => options.transcoder.as_ref(),
+        MutateRequestType::Replace { options } => options.transcoder.as_ref(),
+        _ => None,
+    };
+    let encoding_failure = |e: serde_json::Error| CouchbaseError::EncodingFailure {
+        ctx: ErrorContext::default(),
+        source: e.into(),
+    };
+    match transcoder {
+        Some(transcoder) => {
+            let value = serde_json::to_value(content).map_err(encoding_failure)?;
+            let (encoded, flags) = transcoder.encode(value)?;
+            Ok((encoded, Some(flags)))
+        }
+        None => Ok((to_vec(content).map_err(encoding_failure)?, None)),
+    }
+}
+
+fn preserves_expiry(ty: &MutateRequestType) -> bool {
+    match ty {
+        MutateRequestType::Upsert { options } => options.preserve_expiry,
+        MutateRequestType::Replace { options } => options.preserve_expiry,
+        _ => false,
+    }
+}
+
+fn increment_options(options: IncrementOptions) -> CouchbaseResult<CounterOptions> {
+    let delta = match options.delta {
+        Some(d) => i64::try_from(d).map_err(|_e| CouchbaseError::Generic {
+            // TODO: we shouldn't swallow the error detail.
+            ctx: ErrorContext::default(),
+        })?,
+        None => 1,
+    };
+    Ok(CounterOptions {
+        timeout: options.timeout,
+        retry_strategy: options.retry_strategy,
+        max_retries: options.max_retries,
+        cas: options.cas,
+        expiry: options.expiry,
+        delta,
+        initial: options.initial,
+        durability_level: options.durability_level,
+        client_durability: options.client_durability,
+    })
+}
+
+fn decrement_options(options: DecrementOptions) -> CouchbaseResult<CounterOptions> {
+    let delta = match options.delta {
+        Some(d) => {
+            -(i64::try_from(d).map_err(|_e| CouchbaseError::Generic {
                 // TODO: we shouldn't swallow the error detail.
                 ctx: ErrorContext::default(),
-            })?,
-            None => 1,
-        };
+            })?)
+        }
+        None => -1,
+    };
+    Ok(CounterOptions {
+        timeout: options.timeout,
+        retry_strategy: options.retry_strategy,
+        max_retries: options.max_retries,
+        cas: options.cas,
+        expiry: options.expiry,
+        delta,
+        initial: options.initial,
+        durability_level: options.durability_level,
+        client_durability: options.client_durability,
+    })
+}
+
+/// Fire-and-forget mutations, see `Collection::detached`.
+///
+/// Every method encodes the operation and hands it to the IO layer before it returns, so there
+/// is no future which could be dropped (or cancelled) halfway through. Only errors which happen
+/// before that, like a value which cannot be encoded, are returned.
+///
+/// Once handed over, the operation is dispatched, retried and timed out exactly like its
+/// awaited counterpart, but its outcome is not reported back, so a detached mutation is
+/// delivered at most once and may fail silently. Operations still in flight when the
+/// `Cluster` is dropped may or may not be applied. Use the regular methods of the
+/// `Collection` whenever the outcome matters.
+pub struct DetachedCollection {
+    core: Arc<Core>,
+    name: String,
+    scope_name: String,
+    bucket_name: String,
+}
+
+impl DetachedCollection {
+    pub(crate) fn new(
+        core: Arc<Core>,
+        name: String,
+        scope_name: String,
+        bucket_name: String,
+    ) -> Self {
+        Self {
+            core,
+            name,
+            scope_name,
+            bucket_name,
+        }
+    }
+
+    pub fn upsert<S: Into<String>, T>(
+        &self,
+        id: S,
+        content: T,
+        options: UpsertOptions,
+    ) -> CouchbaseResult<()>
+    where
+        T: Serialize,
+    {
+        self.mutate(id.into(), &content, MutateRequestType::Upsert { options })
+    }
+
+    pub fn insert<S: Into<String>, T>(
+        &self,
+        id: S,
+        content: T,
+        options: InsertOptions,
+    ) -> CouchbaseResult<()>
+    where
+        T: Serialize,
+    {
+        self.mutate(id.into(), &content, MutateRequestType::Insert { options })
+    }
+
+    pub fn replace<S: Into<String>, T>(
+        &self,
+        id: S,
+        content: T,
+        options: ReplaceOptions,
+    ) -> CouchbaseResult<()>
+    where
+        T: Serialize,
+    {
+        self.mutate(id.into(), &content, MutateRequestType::Replace { options })
+    }
+
+    pub fn remove<S: Into<String>>(&self, id: S, options: RemoveOptions) -> CouchbaseResult<()> {
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::Counter(CounterRequest {
+        let timer = self.core.send(Request::Remove(RemoveRequest {
             id: id.into(),
-            sender,
+            options,
             bucket: self.bucket_name.clone(),
-            options: CounterOptions {
-                timeout: options.timeout,
-                cas: options.cas,
-                expiry: options.expiry,
-                delta,
-            },
+            sender,
             scope: self.scope_name.clone(),
             collection: self.name.clone(),
+            collection_id: None,
         }));
-        receiver.await.unwrap()
+        timer.finish_detached(receiver);
+        Ok(())
     }
 
-    pub async fn decrement<S: Into<String>>(
+    pub fn increment<S: Into<String>>(
+        &self,
+        id: S,
+        options: IncrementOptions,
+    ) -> CouchbaseResult<()> {
+        let options = increment_options(options)?;
+        self.counter(id.into(), options)
+    }
+
+    pub fn decrement<S: Into<String>>(
         &self,
         id: S,
         options: DecrementOptions,
-    ) -> CouchbaseResult<CounterResult> {
-        let delta = match options.delta {
-            Some(d) => {
-                -(i64::try_from(d).map_err(|_e| CouchbaseError::Generic {
-                    // TODO: we shouldn't swallow the error detail.
-                    ctx: ErrorContext::default(),
-                })?)
-            }
-            None => -1,
-        };
+    ) -> CouchbaseResult<()> {
+        let options = decrement_options(options)?;
+        self.counter(id.into(), options)
+    }
+
+    fn mutate<T: Serialize>(
+        &self,
+        id: String,
+        content: &T,
+        ty: MutateRequestType,
+    ) -> CouchbaseResult<()> {
+        let (content, flags) = encode_content(content, &ty)?;
+        let preserve_expiry = preserves_expiry(&ty);
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::Counter(CounterRequest {
-            id: id.into(),
+        let request = Request::Mutate(MutateRequest {
+            id,
+            content,
+            flags,
             sender,
             bucket: self.bucket_name.clone(),
-            options: CounterOptions {
-                timeout: options.timeout,
-                cas: options.cas,
-                expiry: options.expiry,
-                delta,
-            },
+            scope: self.scope_name.clone(),
+            collection: self.name.clone(),
+            collection_id: None,
+            ty,
+        });
+        if preserve_expiry {
+            // The server version may have to be read first, which happens off the caller
+            let core = self.core.clone();
+            spawn_detached(async move {
+                match features::require(&core, ServerFeature::PreserveExpiry).await {
+                    Ok(()) => core.send(request).finish_detached(receiver),
+                    Err(e) => warn!("Dropping a detached mutation: {}", e),
+                }
+            });
+            return Ok(());
+        }
+        self.core.send(request).finish_detached(receiver);
+        Ok(())
+    }
+
+    fn counter(&self, id: String, options: CounterOptions) -> CouchbaseResult<()> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::Counter(CounterRequest {
+            id,
+            sender,
+            bucket: self.bucket_name.clone(),
+            options,
             scope: self.scope_name.clone(),
             collection: self.name.clone(),
         }));
-        receiver.await.unwrap()
+        timer.finish_detached(receiver);
+        Ok(())
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Raw key/value operations against a collection addressed by its id.
+///
+/// The content is sent and returned as it is, together with its flags, so no transcoding
+/// takes place.
+#[cfg(feature = "volatile")]
+pub struct RawCollection {
+    core: Arc<Core>,
+    bucket_name: String,
+    collection_id: u32,
+}
+
+#[cfg(feature = "volatile")]
+impl RawCollection {
+    /// The id of the collection.
+    pub fn collection_id(&self) -> u32 {
+        self.collection_id
+    }
+
+    /// Fetches the whole document, projections and the expiry are not supported.
+    pub async fn get<S: Into<String>>(
+        &self,
+        id: S,
+        options: GetOptions,
+    ) -> CouchbaseResult<GetResult> {
+        if !options.project.is_empty() || options.with_expiry {
+            let mut ctx = ErrorContext::default();
+            ctx.insert(
+                "reason",
+                Value::String("projections and with_expiry need a Collection".into()),
+            );
+            return Err(CouchbaseError::InvalidArgument { ctx });
+        }
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::Get(GetRequest {
+            id: id.into(),
+            ty: GetRequestType::Get { options },
+            bucket: self.bucket_name.clone(),
+            sender,
+            scope: "".into(),
+            collection: "".into(),
+            collection_id: Some(self.collection_id),
+        }));
+        timer.finish(receiver.await.unwrap())
+    }
+
+    pub async fn upsert<S: Into<String>>(
+        &self,
+        id: S,
+        content: Vec<u8>,
+        flags: u32,
+        options: UpsertOptions,
+    ) -> CouchbaseResult<MutationResult> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::Mutate(MutateRequest {
+            id: id.into(),
+            content,
+            flags: Some(flags),
+            sender,
+            bucket: self.bucket_name.clone(),
+            ty: MutateRequestType::Upsert { options },
+            scope: "".into(),
+            collection: "".into(),
+            collection_id: Some(self.collection_id),
+        }));
+        timer.finish(receiver.await.unwrap())
+    }
+
+    pub async fn remove<S: Into<String>>(
+        &self,
+        id: S,
+        options: RemoveOptions,
+    ) -> CouchbaseResult<MutationResult> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::Remove(RemoveRequest {
+            id: id.into(),
+            sender,
+            bucket: self.bucket_name.clone(),
+            options,
+            scope: "".into(),
+            collection: "".into(),
+            collection_id: Some(self.collection_id),
+        }));
+        timer.finish(receiver.await.unwrap())
+    }
+}
+
+/// A feature an IO backend may or may not support.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Capability {
+    KeyValue,
+    SubDocument,
+    ReplicaReads,
+    Query,
+    Analytics,
+    Search,
+    Management,
+    Diagnostics,
+    KvStats,
+    /// Sending management requests with the PATCH method.
+    HttpPatch,
+    /// Sub-document lookups against replicas, which need the replica read document flag.
+    ReplicaSubDocument,
+}
+
+#[derive(Debug, Default, Copy, Clone)]
 pub enum DurabilityLevel {
+    #[default]
     None = 0x00,
     Majority = 0x01,
     MajorityAndPersistOnMaster = 0x02,
     PersistToMajority = 0x03,
 }
 
-impl Default for DurabilityLevel {
-    fn default() -> Self {
-        DurabilityLevel::None
-    }
-}
-
 impl Display for DurabilityLevel {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let alias = match *self {
@@ -1074,6 +3246,50 @@ impl Display for DurabilityLevel {
     }
 }
 
+/// The number of nodes a mutation has to be persisted to for client verified durability.
+#[derive(Debug, Copy, Clone)]
+pub enum PersistTo {
+    None,
+    Active,
+    One,
+    Two,
+    Three,
+    Four,
+}
+
+impl PersistTo {
+    /// The number of nodes, including the active one.
+    pub(crate) fn nodes(self) -> i32 {
+        match self {
+            PersistTo::None => 0,
+            PersistTo::Active | PersistTo::One => 1,
+            PersistTo::Two => 2,
+            PersistTo::Three => 3,
+            PersistTo::Four => 4,
+        }
+    }
+}
+
+/// The number of replicas a mutation has to be replicated to for client verified durability.
+#[derive(Debug, Copy, Clone)]
+pub enum ReplicateTo {
+    None,
+    One,
+    Two,
+    Three,
+}
+
+impl ReplicateTo {
+    pub(crate) fn nodes(self) -> i32 {
+        match self {
+            ReplicateTo::None => 0,
+            ReplicateTo::One => 1,
+            ReplicateTo::Two => 2,
+            ReplicateTo::Three => 3,
+        }
+    }
+}
+
 impl TryFrom<&str> for DurabilityLevel {
     type Error = CouchbaseError;
 
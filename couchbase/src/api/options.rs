@@ -1,11 +1,289 @@
-use crate::api::MutationState;
+use crate::api::buckets::SampleLoadListener;
+use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::events::ClusterEventListener;
+use crate::api::expiry::Expiry;
+use crate::api::metrics::Meter;
+use crate::api::redaction::RedactionLevel;
+use crate::api::results::ServiceType;
+use crate::api::retry::RetryStrategy;
+use crate::api::search::{
+    HighlightStyle, PartialSearchResults, SearchFacet, SearchQuery, SearchSort,
+    VectorQueryCombination, VectorSearch,
+};
+use crate::api::security::SecurityEventListener;
+use crate::api::tracing::RequestTracer;
+use crate::api::transcoding::Transcoder;
+use crate::api::{DurabilityLevel, MutationState, PersistTo, ReplicateTo};
 use serde::Serializer;
 use serde_derive::Serialize;
 use serde_json::Value;
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
 use uuid::Uuid;
 
+/// Limits retries to a share of the operations performed, so they cannot amplify an outage.
+///
+/// Every successful operation deposits `percent_can_retry` tokens into the budget and every
+/// retry withdraws one token, up to `max_tokens` which the budget also starts with. Once the
+/// budget is empty, operations fail instead of being retried and their error context carries
+/// `RetryBudgetExhausted` as the `retry_reason`.
+///
+/// The budget is shared by all buckets of a cluster and only applies to key/value operations.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryBudget {
+    pub(crate) percent_can_retry: f64,
+    pub(crate) max_tokens: u32,
+}
+
+impl RetryBudget {
+    /// Creates a budget which allows retrying the given share (i.e. `0.1` for 10%) of operations.
+    pub fn new(percent_can_retry: f64) -> Self {
+        Self {
+            percent_can_retry,
+            max_tokens: 100,
+        }
+    }
+
+    /// The maximum number of retries which can be saved up, 100 by default.
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+}
+
+/// Sends a NOOP over every key/value connection which has been idle for a while.
+///
+/// A connection whose NOOP is not answered within the timeout is considered dead and
+/// reconnected, so half-open connections (i.e. after a node silently dropped off the network)
+/// are detected before the next operation sent over them times out.
+#[derive(Debug, Clone, Copy)]
+pub struct KvKeepAlive {
+    pub(crate) interval: Duration,
+    pub(crate) timeout: Duration,
+}
+
+impl KvKeepAlive {
+    /// Sends a NOOP once a connection has been idle for the given interval.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            timeout: Duration::from_millis(2500),
+        }
+    }
+
+    /// How long to wait for the NOOP response, 2.5 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// Compresses document values with Snappy on their way to and from the data nodes.
+///
+/// Compression is negotiated with every node and values are decompressed transparently, so
+/// this only changes what goes over the wire. A value is only sent compressed if it is at
+/// least `min_size` bytes large and compresses to at most `min_ratio` of its original size.
+#[derive(Debug, Clone, Copy)]
+pub struct KvCompression {
+    pub(crate) enabled: bool,
+    pub(crate) min_size: u32,
+    pub(crate) min_ratio: f32,
+}
+
+impl KvCompression {
+    /// Enables or disables compression in both directions.
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            min_size: 32,
+            min_ratio: 0.83,
+        }
+    }
+
+    /// The minimum size of a value in bytes to try compressing it, 32 by default.
+    pub fn min_size(mut self, min_size: u32) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// The maximum ratio of the compressed to the original size to send the value compressed,
+    /// 0.83 by default.
+    pub fn min_ratio(mut self, min_ratio: f32) -> Self {
+        self.min_ratio = min_ratio;
+        self
+    }
+}
+
+impl Default for KvCompression {
+    fn default() -> Self {
+        Self::new(true)
+    }
+}
+
+/// Stops dispatching to a key/value node once too many requests to it time out.
+///
+/// While the breaker of a node is open, operations which would go to it are handed to their
+/// retry strategy with `RetryReason::CircuitBreakerOpen` instead and fail with
+/// `RequestCanceled` unless they are retried. Once the `sleep_window` passed, a NOOP is sent
+/// to the node as canary and the breaker closes again if it is answered within the
+/// `canary_timeout`.
+#[derive(Debug, Clone, Copy)]
+pub struct KvCircuitBreaker {
+    pub(crate) enabled: bool,
+    pub(crate) volume_threshold: u32,
+    pub(crate) error_threshold_percentage: u32,
+    pub(crate) sleep_window: Duration,
+    pub(crate) rolling_window: Duration,
+    pub(crate) canary_timeout: Duration,
+}
+
+impl KvCircuitBreaker {
+    /// Enables or disables the circuit breakers of all key/value nodes.
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            volume_threshold: 20,
+            error_threshold_percentage: 50,
+            sleep_window: Duration::from_secs(5),
+            rolling_window: Duration::from_secs(60),
+            canary_timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// The minimum number of requests within the rolling window before the breaker may open,
+    /// 20 by default.
+    pub fn volume_threshold(mut self, volume_threshold: u32) -> Self {
+        self.volume_threshold = volume_threshold;
+        self
+    }
+
+    /// The percentage of requests within the rolling window which have to time out for the
+    /// breaker to open, 50 by default. Must not be above 100.
+    pub fn error_threshold_percentage(mut self, percentage: u32) -> Self {
+        self.error_threshold_percentage = percentage;
+        self
+    }
+
+    /// How long an open breaker waits before sending the canary, 5 seconds by default.
+    pub fn sleep_window(mut self, sleep_window: Duration) -> Self {
+        self.sleep_window = sleep_window;
+        self
+    }
+
+    /// The window over which requests are counted, 1 minute by default.
+    pub fn rolling_window(mut self, rolling_window: Duration) -> Self {
+        self.rolling_window = rolling_window;
+        self
+    }
+
+    /// How long to wait for the response to the canary, 5 seconds by default.
+    pub fn canary_timeout(mut self, canary_timeout: Duration) -> Self {
+        self.canary_timeout = canary_timeout;
+        self
+    }
+}
+
+impl Default for KvCircuitBreaker {
+    fn default() -> Self {
+        Self::new(true)
+    }
+}
+
+/// Pings the key/value connections and the HTTP services of idle nodes in the background, to
+/// find unhealthy endpoints before requests run into them.
+///
+/// An endpoint is marked degraded once `degraded_after` pings in a row failed and healthy
+/// again with the next successful one, which is reported as `ClusterEvent` and in the
+/// diagnostics. Degraded query, search, analytics and views nodes are only sent requests if
+/// no other node provides the service. Partitions are bound to their key/value node, so
+/// degraded key/value endpoints are reconnected instead, see `KvCircuitBreaker` to fail fast
+/// against them.
+#[derive(Debug, Clone, Copy)]
+pub struct HealthProbe {
+    pub(crate) interval: Duration,
+    pub(crate) timeout: Duration,
+    pub(crate) degraded_after: u32,
+}
+
+impl HealthProbe {
+    /// Pings the endpoints once per interval, skipping connections which are busy with
+    /// requests.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            timeout: Duration::from_millis(2500),
+            degraded_after: 2,
+        }
+    }
+
+    /// How long to wait for the response to a ping, 2.5 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many pings in a row have to fail for the endpoint to be marked degraded, 2 by
+    /// default.
+    pub fn degraded_after(mut self, failures: u32) -> Self {
+        self.degraded_after = failures.max(1);
+        self
+    }
+}
+
+/// Which addresses of the nodes are used to connect to them.
+///
+/// Nodes can advertise alternate addresses (and ports) for every service, i.e. to be
+/// reachable from outside a container network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkResolution {
+    /// Uses the alternate addresses if the node used to bootstrap is one of them.
+    Auto,
+    /// Always uses the addresses the nodes know themselves by.
+    Default,
+    /// Always uses the `external` alternate addresses.
+    External,
+    /// Always uses the alternate addresses of the given network.
+    Custom(String),
+}
+
+impl NetworkResolution {
+    fn as_str(&self) -> &str {
+        match self {
+            NetworkResolution::Auto => "auto",
+            NetworkResolution::Default => "default",
+            NetworkResolution::External => "external",
+            NetworkResolution::Custom(name) => name,
+        }
+    }
+}
+
+/// Where the cluster configuration is fetched from.
+///
+/// Over key/value the configuration is requested from the data nodes (CCCP). Over HTTP it is
+/// streamed from the terse `/pools/default/bs/{bucket}` endpoint of the cluster manager, which
+/// pushes every new revision on a single long lived connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigTransport {
+    /// Uses key/value and falls back to HTTP if the data nodes do not provide a configuration,
+    /// i.e. for memcached buckets or if the key/value port is not reachable.
+    All,
+    /// Only uses key/value.
+    KeyValue,
+    /// Only uses HTTP streaming.
+    Http,
+}
+
+impl ConfigTransport {
+    fn as_str(&self) -> &str {
+        match self {
+            ConfigTransport::All => "all",
+            ConfigTransport::KeyValue => "cccp",
+            ConfigTransport::Http => "http",
+        }
+    }
+}
+
 /// Macro to DRY up the repetitive timeout setter.
 macro_rules! timeout {
     () => {
@@ -16,21 +294,549 @@ macro_rules! timeout {
     };
 }
 
+macro_rules! retry_strategy {
+    () => {
+        /// Decides whether and when the operation is retried, see `RetryStrategy`.
+        pub fn retry_strategy(mut self, retry_strategy: Arc<dyn RetryStrategy>) -> Self {
+            self.retry_strategy = Some(retry_strategy);
+            self
+        }
+
+        /// Fails the operation once it has been retried the given number of times, whatever
+        /// the retry strategy decides. Overrides `ClusterOptions::max_retries`.
+        pub fn max_retries(mut self, max_retries: u32) -> Self {
+            self.max_retries = Some(max_retries);
+            self
+        }
+    };
+}
+
+macro_rules! collection {
+    () => {
+        /// Limits the operation to the indexes of the given collection.
+        pub fn collection<S: Into<String>>(mut self, scope_name: S, collection_name: S) -> Self {
+            self.scope_name = Some(scope_name.into());
+            self.collection_name = Some(collection_name.into());
+            self
+        }
+    };
+}
+
 macro_rules! expiry {
     () => {
-        pub fn expiry(mut self, expiry: Duration) -> Self {
-            self.expiry = Some(expiry);
+        /// Sets when the document expires, see `Expiry`.
+        pub fn expiry<E: Into<Expiry>>(mut self, expiry: E) -> Self {
+            self.expiry = Some(expiry.into());
+            self
+        }
+    };
+}
+
+macro_rules! preserve_expiry {
+    () => {
+        /// Keeps the current expiry of the document instead of removing it, which cannot be
+        /// combined with `expiry`.
+        ///
+        /// This needs Couchbase Server 7.0 or later and fails with `FeatureNotAvailable` on
+        /// older clusters.
+        pub fn preserve_expiry(mut self, preserve_expiry: bool) -> Self {
+            self.preserve_expiry = preserve_expiry;
+            self
+        }
+    };
+}
+
+macro_rules! transcoder {
+    () => {
+        /// Stores or reads the document through the given transcoder instead of as plain JSON,
+        /// see `Transcoder`.
+        pub fn transcoder(mut self, transcoder: Arc<dyn Transcoder>) -> Self {
+            self.transcoder = Some(transcoder);
             self
         }
     };
 }
 
+macro_rules! durability {
+    () => {
+        /// Requires the mutation to meet the given durability level before it completes.
+        ///
+        /// The level is enforced by the server and needs Couchbase Server 6.5 or later.
+        pub fn durability(mut self, durability_level: DurabilityLevel) -> Self {
+            self.durability_level = Some(durability_level);
+            self
+        }
+    };
+}
+
+macro_rules! client_durability {
+    () => {
+        /// Requires the mutation to be persisted and replicated to the given number of nodes
+        /// before it completes.
+        ///
+        /// This is verified by the client through polling the nodes, so it also works on
+        /// clusters which do not support `durability`. The two cannot be combined, the
+        /// operation fails with `InvalidArgument` if both are set.
+        pub fn client_durability(
+            mut self,
+            persist_to: PersistTo,
+            replicate_to: ReplicateTo,
+        ) -> Self {
+            self.client_durability = Some((persist_to, replicate_to));
+            self
+        }
+    };
+}
+
+/// Options which apply to the whole `Cluster` and are passed down when connecting.
+///
+/// Every setting which is not explicitly set keeps the default of the underlying IO layer.
+#[derive(Debug, Default, Clone)]
+pub struct ClusterOptions {
+    pub(crate) tracing: Option<bool>,
+    pub(crate) tracing_threshold_kv: Option<Duration>,
+    pub(crate) tracing_threshold_query: Option<Duration>,
+    pub(crate) tracing_threshold_views: Option<Duration>,
+    pub(crate) tracing_threshold_search: Option<Duration>,
+    pub(crate) tracing_threshold_analytics: Option<Duration>,
+    pub(crate) tracing_threshold_queue_size: Option<u32>,
+    pub(crate) tracing_threshold_queue_flush_interval: Option<Duration>,
+    pub(crate) orphan_reporting_queue_size: Option<u32>,
+    pub(crate) orphan_reporting_flush_interval: Option<Duration>,
+    pub(crate) meter: Option<Arc<dyn Meter>>,
+    pub(crate) tracer: Option<Arc<dyn RequestTracer>>,
+    pub(crate) service_credentials: HashMap<ServiceType, (String, String)>,
+    pub(crate) retry_budget: Option<RetryBudget>,
+    pub(crate) max_retries: Option<u32>,
+    pub(crate) kv_keepalive: Option<KvKeepAlive>,
+    pub(crate) slow_kv_sampling: Option<f64>,
+    pub(crate) network: Option<NetworkResolution>,
+    pub(crate) config_push: Option<bool>,
+    pub(crate) http_pool_size: Option<usize>,
+    pub(crate) unordered_execution: Option<bool>,
+    pub(crate) kv_compression: Option<KvCompression>,
+    pub(crate) config_poll_interval: Option<Duration>,
+    pub(crate) config_poll_floor: Option<Duration>,
+    pub(crate) config_transport: Option<ConfigTransport>,
+    pub(crate) security_events: Option<Arc<dyn SecurityEventListener>>,
+    pub(crate) certificate_pinning: Option<bool>,
+    pub(crate) kv_circuit_breaker: Option<KvCircuitBreaker>,
+    pub(crate) log_redaction: Option<RedactionLevel>,
+    pub(crate) cluster_events: Option<Arc<dyn ClusterEventListener>>,
+    pub(crate) health_probe: Option<HealthProbe>,
+    #[cfg(feature = "packet-dump")]
+    pub(crate) packet_dump_sampling: Option<usize>,
+    #[cfg(feature = "http-compression")]
+    pub(crate) http_compression: Option<bool>,
+}
+
+impl ClusterOptions {
+    /// Enables or disables request tracing.
+    ///
+    /// When enabled, operations which take longer than their service threshold are
+    /// collected and periodically logged as a JSON report.
+    pub fn tracing(mut self, enabled: bool) -> Self {
+        self.tracing = Some(enabled);
+        self
+    }
+
+    pub fn tracing_threshold_kv(mut self, threshold: Duration) -> Self {
+        self.tracing_threshold_kv = Some(threshold);
+        self
+    }
+
+    pub fn tracing_threshold_query(mut self, threshold: Duration) -> Self {
+        self.tracing_threshold_query = Some(threshold);
+        self
+    }
+
+    pub fn tracing_threshold_views(mut self, threshold: Duration) -> Self {
+        self.tracing_threshold_views = Some(threshold);
+        self
+    }
+
+    pub fn tracing_threshold_search(mut self, threshold: Duration) -> Self {
+        self.tracing_threshold_search = Some(threshold);
+        self
+    }
+
+    pub fn tracing_threshold_analytics(mut self, threshold: Duration) -> Self {
+        self.tracing_threshold_analytics = Some(threshold);
+        self
+    }
+
+    /// The maximum number of slow operations kept per service between two reports.
+    pub fn tracing_threshold_queue_size(mut self, size: u32) -> Self {
+        self.tracing_threshold_queue_size = Some(size);
+        self
+    }
+
+    /// How often the slow operations report is logged.
+    pub fn tracing_threshold_queue_flush_interval(mut self, interval: Duration) -> Self {
+        self.tracing_threshold_queue_flush_interval = Some(interval);
+        self
+    }
+
+    /// The maximum number of orphaned responses kept between two reports.
+    ///
+    /// Orphaned responses are responses which arrive after their request already timed out or
+    /// got canceled. They are collected (with their operation id, server and duration) and
+    /// periodically logged as a JSON report while tracing is enabled.
+    pub fn orphan_reporting_queue_size(mut self, size: u32) -> Self {
+        self.orphan_reporting_queue_size = Some(size);
+        self
+    }
+
+    /// How often the orphaned responses report is logged.
+    pub fn orphan_reporting_flush_interval(mut self, interval: Duration) -> Self {
+        self.orphan_reporting_flush_interval = Some(interval);
+        self
+    }
+
+    /// Sets the `Meter` which records the latency of every operation.
+    pub fn meter(mut self, meter: Arc<dyn Meter>) -> Self {
+        self.meter = Some(meter);
+        self
+    }
+
+    /// Sets the `RequestTracer` which starts a span for every operation.
+    ///
+    /// Unlike `tracing`, which only logs slow operations, this hands every operation to the
+    /// tracer, i.e. the `OpenTelemetryTracer` of the `opentelemetry` feature.
+    pub fn tracer(mut self, tracer: Arc<dyn RequestTracer>) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// Uses a different username and password for the given service.
+    ///
+    /// All other services keep using the credentials passed when connecting. Services are
+    /// identified through their default ports, so overrides do not apply to services which
+    /// are exposed on custom ports.
+    pub fn service_credentials<S: Into<String>>(
+        mut self,
+        service: ServiceType,
+        username: S,
+        password: S,
+    ) -> Self {
+        self.service_credentials
+            .insert(service, (username.into(), password.into()));
+        self
+    }
+
+    /// Limits how many key/value operations can be retried, see `RetryBudget`.
+    pub fn retry_budget(mut self, retry_budget: RetryBudget) -> Self {
+        self.retry_budget = Some(retry_budget);
+        self
+    }
+
+    /// Fails key/value operations once they have been retried the given number of times,
+    /// unless their options set `max_retries` themselves.
+    ///
+    /// This bounds the attempts on top of the timeout of the operation. Operations which ran
+    /// out of retries carry `MaxRetriesReached` as the `retry_reason` in their error context.
+    /// Retries which are always required, like after a partition moved, are not counted.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
+    /// Detects dead key/value connections while they are idle, see `KvKeepAlive`.
+    pub fn kv_keepalive(mut self, kv_keepalive: KvKeepAlive) -> Self {
+        self.kv_keepalive = Some(kv_keepalive);
+        self
+    }
+
+    /// Passes the metadata of the slowest key/value packets to `Meter::record_slow_kv`, i.e.
+    /// `1.0` for the slowest 1% of every opcode. Disabled by default.
+    ///
+    /// Needs a `meter`. The latency distribution is tracked per opcode, so slow gets are
+    /// sampled even if mutations are slower in general. Values are never captured.
+    pub fn slow_kv_sampling(mut self, percent: f64) -> Self {
+        self.slow_kv_sampling = Some(percent);
+        self
+    }
+
+    /// Forces the addresses used to connect to the nodes, `NetworkResolution::Auto` by default.
+    ///
+    /// Takes precedence over the `network` connection string parameter.
+    pub fn network(mut self, network: NetworkResolution) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Enables or disables configuration push, enabled by default.
+    ///
+    /// The data nodes notify the client as soon as the cluster topology changes (i.e. during a
+    /// rebalance), instead of the client only learning about it through polling or failed
+    /// operations. Servers which do not support notifications are polled as before.
+    pub fn config_push(mut self, enabled: bool) -> Self {
+        self.config_push = Some(enabled);
+        self
+    }
+
+    /// Sets how many idle HTTP connections are kept per node, 1 by default.
+    ///
+    /// The pool is shared by the query, view, search, analytics and eventing services (and
+    /// pings of them), so raise it when running many of those requests concurrently, or set it
+    /// to 0 to disable pooling. Management requests always open a new connection.
+    /// Key/value operations are not pooled either: they are pipelined over a single connection
+    /// per data node, which has no limit on the number of operations in flight.
+    pub fn http_pool_size(mut self, size: usize) -> Self {
+        self.http_pool_size = Some(size);
+        self
+    }
+
+    /// Enables or disables out of order execution of key/value operations, enabled by default.
+    ///
+    /// The data nodes may answer the operations sent over a connection in any order, so a slow
+    /// operation (i.e. one waiting for durability) does not hold back the ones sent after it.
+    /// Disabling it makes every connection answer in order, which can help when debugging.
+    pub fn unordered_execution(mut self, enabled: bool) -> Self {
+        self.unordered_execution = Some(enabled);
+        self
+    }
+
+    /// Controls the Snappy compression of document values, enabled by default.
+    pub fn kv_compression(mut self, kv_compression: KvCompression) -> Self {
+        self.kv_compression = Some(kv_compression);
+        self
+    }
+
+    /// How often the cluster configuration is polled in the background, 2.5 seconds by
+    /// default.
+    ///
+    /// A lower interval picks up topology changes (i.e. during a rebalance) sooner at the cost
+    /// of more requests to the cluster. `Duration::from_secs(0)` disables polling, must
+    /// otherwise not be below the `config_poll_floor`.
+    pub fn config_poll_interval(mut self, interval: Duration) -> Self {
+        self.config_poll_interval = Some(interval);
+        self
+    }
+
+    /// The minimum time in between two configuration refreshes, 50 milliseconds by default.
+    ///
+    /// Refreshes requested in quick succession, i.e. by many operations failing with "not my
+    /// vbucket" at once during a rebalance, are coalesced into one which runs once the floor
+    /// has passed.
+    pub fn config_poll_floor(mut self, floor: Duration) -> Self {
+        self.config_poll_floor = Some(floor);
+        self
+    }
+
+    /// Where the cluster configuration is fetched from, `ConfigTransport::All` by default.
+    ///
+    /// Whichever transport provides it, a configuration only replaces the current one if its
+    /// revision is newer.
+    pub fn config_transport(mut self, transport: ConfigTransport) -> Self {
+        self.config_transport = Some(transport);
+        self
+    }
+
+    /// Receives security relevant events, i.e. when a node presents a different certificate
+    /// than on a previous connection.
+    pub fn security_events(mut self, listener: Arc<dyn SecurityEventListener>) -> Self {
+        self.security_events = Some(listener);
+        self
+    }
+
+    /// Fails connections to nodes which present a different certificate than the first one
+    /// seen from them, disabled by default.
+    ///
+    /// Certificates are compared by issuer and public key, so renewals which keep both are
+    /// still accepted. Since a certificate is pinned on first use (and only for the lifetime of
+    /// the cluster), rotating the certificates of the cluster requires a reconnect. Only
+    /// applies to TLS (`couchbases://`) connections, including those which skip certificate
+    /// verification.
+    pub fn certificate_pinning(mut self, enabled: bool) -> Self {
+        self.certificate_pinning = Some(enabled);
+        self
+    }
+
+    /// Enables circuit breakers for the key/value nodes, which are disabled by default.
+    pub fn kv_circuit_breaker(mut self, kv_circuit_breaker: KvCircuitBreaker) -> Self {
+        self.kv_circuit_breaker = Some(kv_circuit_breaker);
+        self
+    }
+
+    /// Tags user data (and at `RedactionLevel::Full` also meta and system data) in the log
+    /// messages of the SDK, `RedactionLevel::None` by default.
+    pub fn log_redaction(mut self, level: RedactionLevel) -> Self {
+        self.log_redaction = Some(level);
+        self
+    }
+
+    /// Receives the configuration, connection, retry and circuit breaker events of the
+    /// cluster and its buckets, see `cluster_event_stream` to consume them as a stream.
+    pub fn cluster_events(mut self, listener: Arc<dyn ClusterEventListener>) -> Self {
+        self.cluster_events = Some(listener);
+        self
+    }
+
+    /// Pings idle endpoints in the background and routes around the degraded ones, see
+    /// `HealthProbe`. Disabled by default.
+    pub fn health_probe(mut self, health_probe: HealthProbe) -> Self {
+        self.health_probe = Some(health_probe);
+        self
+    }
+
+    /// Only dumps every nth packet, which keeps the log volume manageable under load.
+    ///
+    /// Packet dumps are logged at trace level to the `couchbase::packets` target and need
+    /// the `packet-dump` feature.
+    #[cfg(feature = "packet-dump")]
+    pub fn packet_dump_sampling(mut self, every: usize) -> Self {
+        self.packet_dump_sampling = Some(every);
+        self
+    }
+
+    /// Asks the query, search and analytics services for gzip or deflate compressed responses,
+    /// which are decompressed transparently.
+    ///
+    /// This reduces the transfer size of large results, especially across WAN links, at the
+    /// cost of CPU time on both ends. The sizes of every response body before and after
+    /// decompression are reported through `Meter::record_http_body_size`.
+    #[cfg(feature = "http-compression")]
+    pub fn http_compression(mut self, enabled: bool) -> Self {
+        self.http_compression = Some(enabled);
+        self
+    }
+
+    /// Converts the options into connection string parameters.
+    pub(crate) fn connection_string_params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![];
+        if let Some(v) = self.tracing {
+            params.push(("enable_tracing", v.to_string()));
+        }
+        push_duration(
+            &mut params,
+            "tracing_threshold_kv",
+            self.tracing_threshold_kv,
+        );
+        push_duration(
+            &mut params,
+            "tracing_threshold_query",
+            self.tracing_threshold_query,
+        );
+        push_duration(
+            &mut params,
+            "tracing_threshold_view",
+            self.tracing_threshold_views,
+        );
+        push_duration(
+            &mut params,
+            "tracing_threshold_search",
+            self.tracing_threshold_search,
+        );
+        push_duration(
+            &mut params,
+            "tracing_threshold_analytics",
+            self.tracing_threshold_analytics,
+        );
+        if let Some(v) = self.tracing_threshold_queue_size {
+            params.push(("tracing_threshold_queue_size", v.to_string()));
+        }
+        push_duration(
+            &mut params,
+            "tracing_threshold_queue_flush_interval",
+            self.tracing_threshold_queue_flush_interval,
+        );
+        if let Some(v) = self.orphan_reporting_queue_size {
+            params.push(("tracing_orphaned_queue_size", v.to_string()));
+        }
+        push_duration(
+            &mut params,
+            "tracing_orphaned_queue_flush_interval",
+            self.orphan_reporting_flush_interval,
+        );
+        if let Some(n) = &self.network {
+            params.push(("network", n.as_str().to_string()));
+        }
+        if let Some(v) = self.config_push {
+            params.push(("enable_config_push", v.to_string()));
+        }
+        if let Some(v) = self.http_pool_size {
+            params.push(("http_poolsize", v.to_string()));
+        }
+        if let Some(v) = self.unordered_execution {
+            params.push(("enable_unordered_execution", v.to_string()));
+        }
+        if let Some(c) = &self.kv_compression {
+            params.push(("compression", if c.enabled { "on" } else { "off" }.into()));
+            params.push(("compression_min_size", c.min_size.to_string()));
+            params.push(("compression_min_ratio", c.min_ratio.to_string()));
+        }
+        // The floor bounds the interval, so it has to be applied first
+        push_duration(&mut params, "config_poll_floor", self.config_poll_floor);
+        push_duration(
+            &mut params,
+            "config_poll_interval",
+            self.config_poll_interval,
+        );
+        if let Some(t) = &self.config_transport {
+            params.push(("bootstrap_on", t.as_str().to_string()));
+        }
+        if let Some(l) = self.log_redaction {
+            params.push(("log_redaction", (l != RedactionLevel::None).to_string()));
+        }
+        if let Some(b) = &self.kv_circuit_breaker {
+            params.push(("circuit_breaker", b.enabled.to_string()));
+            params.push((
+                "circuit_breaker_volume_threshold",
+                b.volume_threshold.to_string(),
+            ));
+            params.push((
+                "circuit_breaker_error_threshold",
+                b.error_threshold_percentage.to_string(),
+            ));
+            push_duration(
+                &mut params,
+                "circuit_breaker_sleep_window",
+                Some(b.sleep_window),
+            );
+            push_duration(
+                &mut params,
+                "circuit_breaker_rolling_window",
+                Some(b.rolling_window),
+            );
+            push_duration(
+                &mut params,
+                "circuit_breaker_canary_timeout",
+                Some(b.canary_timeout),
+            );
+        }
+        #[cfg(feature = "http-compression")]
+        {
+            if let Some(v) = self.http_compression {
+                params.push(("http_compression", v.to_string()));
+            }
+        }
+        params
+    }
+}
+
+/// Connection string durations are expressed in (fractional) seconds.
+fn push_duration(
+    params: &mut Vec<(&'static str, String)>,
+    key: &'static str,
+    value: Option<Duration>,
+) {
+    if let Some(d) = value {
+        params.push((key, d.as_secs_f64().to_string()));
+    }
+}
+
 #[derive(Debug, Default, Serialize)]
 pub struct QueryOptions {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) scan_consistency: Option<QueryScanConsistency>,
     #[serde(skip)]
     pub(crate) adhoc: Option<bool>,
+    // Only set by `Cluster::prewarm`, which prepares the statement without executing it
+    #[serde(skip)]
+    pub(crate) prepare_only: bool,
+    #[serde(skip)]
+    pub(crate) max_buffered_bytes: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(serialize_with = "convert_duration_for_golang")]
     pub(crate) timeout: Option<Duration>,
@@ -54,6 +860,21 @@ pub struct QueryOptions {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) profile: Option<QueryProfile>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "tximplicit")]
+    pub(crate) tx_implicit: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "txtimeout")]
+    #[serde(serialize_with = "convert_duration_for_golang")]
+    pub(crate) tx_timeout: Option<Duration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(serialize_with = "convert_durability_level")]
+    pub(crate) durability_level: Option<DurabilityLevel>,
+    // Only set by `QueryTransaction`, which also routes the statement to the right node
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "txid")]
+    pub(crate) tx_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(flatten)]
     #[serde(serialize_with = "convert_mutation_state")]
     pub(crate) consistent_with: Option<MutationState>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -63,9 +884,17 @@ pub struct QueryOptions {
     #[serde(flatten)]
     #[serde(serialize_with = "convert_named_params")]
     pub(crate) named_parameters: Option<serde_json::Map<String, Value>>,
+    #[serde(skip)]
+    pub(crate) parameters_error: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(flatten)]
     pub(crate) raw: Option<serde_json::Map<String, Value>>,
+    #[serde(skip)]
+    pub(crate) endpoint: Option<String>,
+    // Only set for scoped statements sent through the HTTP interface of the query service,
+    // libcouchbase sets it on its own otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) query_context: Option<String>,
     // The statement is not part of the public API, but added here
     // as a convenience so we can conver the whole block into the
     // JSON payload the query engine expects. DO NOT ADD A PUBLIC
@@ -73,11 +902,26 @@ pub struct QueryOptions {
     pub(crate) statement: Option<String>,
 }
 
-fn convert_mutation_state<S>(_x: &Option<MutationState>, _s: S) -> Result<S::Ok, S::Error>
+fn convert_mutation_state<S>(x: &Option<MutationState>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match x {
+        Some(state) => {
+            let mut conv = serde_json::Map::new();
+            conv.insert("scan_consistency".into(), Value::String("at_plus".into()));
+            conv.insert("scan_vectors".into(), Value::Object(state.query_vectors()));
+            s.serialize_some(&conv)
+        }
+        None => s.serialize_none(),
+    }
+}
+
+fn convert_durability_level<S>(x: &Option<DurabilityLevel>, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    todo!()
+    s.serialize_str(&x.expect("Expected a durability level!").to_string())
 }
 
 fn convert_duration_for_golang<S>(x: &Option<Duration>, s: S) -> Result<S::Ok, S::Error>
@@ -118,26 +962,90 @@ where
     }
 }
 
+fn encode_parameters<T: serde::Serialize>(parameters: T, kind: &str) -> Result<Value, String> {
+    serde_json::to_value(parameters)
+        .map_err(|e| format!("could not encode {} parameters: {}", kind, e))
+}
+
+/// Fails with `InvalidArgument` if the parameters of a query could not be encoded.
+pub(crate) fn check_parameters(error: &Option<String>) -> CouchbaseResult<()> {
+    match error {
+        Some(msg) => {
+            let mut ctx = ErrorContext::default();
+            ctx.insert("msg", Value::String(msg.clone()));
+            Err(CouchbaseError::InvalidArgument { ctx })
+        }
+        None => Ok(()),
+    }
+}
+
 impl QueryOptions {
     timeout!();
 
+    /// Sets the scan consistency, replacing any previously set `consistent_with`.
     pub fn scan_consistency(mut self, scan_consistency: QueryScanConsistency) -> Self {
         self.scan_consistency = Some(scan_consistency);
+        self.consistent_with = None;
         self
     }
 
+    /// Set to false to execute the statement as a prepared statement.
+    ///
+    /// The statement is prepared on first use and the plan is cached by statement and scope,
+    /// so later executions skip the planning phase. When the plan becomes stale, like after
+    /// an index it relies on has been dropped, it is prepared again transparently.
     pub fn adhoc(mut self, adhoc: bool) -> Self {
         self.adhoc = Some(adhoc);
         self
     }
 
-    pub fn client_context_id(mut self, client_context_id: String) -> Self {
-        self.client_context_id = Some(client_context_id);
+    /// Sends the query to the given query node (`host:port`), like the one reported by
+    /// `QueryMetaData::endpoint`.
+    ///
+    /// Pinned queries are sent through the HTTP interface of the query service, so their rows
+    /// are only streamed once the full response has been received, and they cannot be combined
+    /// with `adhoc(false)`. Use a `QuerySession` to send a sequence of queries to one node.
+    pub fn endpoint<S: Into<String>>(mut self, endpoint: S) -> Self {
+        self.endpoint = Some(endpoint.into());
         self
     }
 
-    pub fn max_parallelism(mut self, max_parallelism: u32) -> Self {
-        self.max_parallelism = Some(max_parallelism);
+    /// Runs the statement in a transaction of its own, which is rolled back if it fails.
+    ///
+    /// Use a `QueryTransaction` to run several statements in one transaction.
+    pub fn tx_implicit(mut self, tx_implicit: bool) -> Self {
+        self.tx_implicit = Some(tx_implicit);
+        self
+    }
+
+    /// How long the transaction of an implicit transaction statement may run.
+    pub fn tx_timeout(mut self, tx_timeout: Duration) -> Self {
+        self.tx_timeout = Some(tx_timeout);
+        self
+    }
+
+    /// The durability level of the writes performed by an implicit transaction statement.
+    pub fn durability_level(mut self, durability_level: DurabilityLevel) -> Self {
+        self.durability_level = Some(durability_level);
+        self
+    }
+
+    /// Cancels the query once the rows received but not consumed yet exceed this size.
+    ///
+    /// Rows are streamed as they arrive, but the query service cannot be slowed down. Without
+    /// this limit, a consumer which is slower than the query buffers rows without bound.
+    pub fn max_buffered_bytes(mut self, max_buffered_bytes: usize) -> Self {
+        self.max_buffered_bytes = Some(max_buffered_bytes);
+        self
+    }
+
+    pub fn client_context_id(mut self, client_context_id: String) -> Self {
+        self.client_context_id = Some(client_context_id);
+        self
+    }
+
+    pub fn max_parallelism(mut self, max_parallelism: u32) -> Self {
+        self.max_parallelism = Some(max_parallelism);
         self
     }
 
@@ -176,34 +1084,45 @@ impl QueryOptions {
         self
     }
 
+    /// Makes the query consistent with the given mutations, replacing any previously set
+    /// scan consistency.
     pub fn consistent_with(mut self, consistent_with: MutationState) -> Self {
         self.consistent_with = Some(consistent_with);
+        self.scan_consistency = None;
         self
     }
 
+    /// Sets the values of `$1`, `$2` and so on, anything which serializes into an array (see
+    /// `query_args!`).
+    ///
+    /// If the parameters can not be encoded into an array, the query fails with
+    /// `InvalidArgument` without being sent.
     pub fn positional_parameters<T>(mut self, positional_parameters: T) -> Self
     where
         T: serde::Serialize,
     {
-        let positional_parameters = match serde_json::to_value(positional_parameters) {
-            Ok(Value::Array(a)) => a,
-            Ok(_) => panic!("Only arrays are allowed"),
-            _ => panic!("Could not encode positional parameters"),
-        };
-        self.positional_parameters = Some(positional_parameters);
+        match encode_parameters(positional_parameters, "positional") {
+            Ok(Value::Array(a)) => self.positional_parameters = Some(a),
+            Ok(_) => self.parameters_error = Some("positional parameters must be an array".into()),
+            Err(e) => self.parameters_error = Some(e),
+        }
         self
     }
 
+    /// Sets the values of `$name` parameters, anything which serializes into an object (see
+    /// `query_args!`).
+    ///
+    /// If the parameters can not be encoded into an object, the query fails with
+    /// `InvalidArgument` without being sent.
     pub fn named_parameters<T>(mut self, named_parameters: T) -> Self
     where
         T: serde::Serialize,
     {
-        let named_parameters = match serde_json::to_value(named_parameters) {
-            Ok(Value::Object(a)) => a,
-            Ok(_) => panic!("Only objects are allowed"),
-            _ => panic!("Could not encode positional parameters"),
-        };
-        self.named_parameters = Some(named_parameters);
+        match encode_parameters(named_parameters, "named") {
+            Ok(Value::Object(a)) => self.named_parameters = Some(a),
+            Ok(_) => self.parameters_error = Some("named parameters must be an object".into()),
+            Err(e) => self.parameters_error = Some(e),
+        }
         self
     }
 
@@ -255,11 +1174,15 @@ pub struct AnalyticsOptions {
     #[serde(flatten)]
     #[serde(serialize_with = "convert_named_params")]
     pub(crate) named_parameters: Option<serde_json::Map<String, Value>>,
+    #[serde(skip)]
+    pub(crate) parameters_error: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) readonly: Option<bool>,
     #[serde(skip)]
     pub(crate) priority: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(flatten)]
     pub(crate) raw: Option<serde_json::Map<String, Value>>,
     // The statement is not part of the public API, but added here
@@ -287,29 +1210,37 @@ impl AnalyticsOptions {
         self
     }
 
+    /// Sets the values of `$1`, `$2` and so on, anything which serializes into an array (see
+    /// `query_args!`).
+    ///
+    /// If the parameters can not be encoded into an array, the query fails with
+    /// `InvalidArgument` without being sent.
     pub fn positional_parameters<T>(mut self, positional_parameters: T) -> Self
     where
         T: serde::Serialize,
     {
-        let positional_parameters = match serde_json::to_value(positional_parameters) {
-            Ok(Value::Array(a)) => a,
-            Ok(_) => panic!("Only arrays are allowed"),
-            _ => panic!("Could not encode positional parameters"),
-        };
-        self.positional_parameters = Some(positional_parameters);
+        match encode_parameters(positional_parameters, "positional") {
+            Ok(Value::Array(a)) => self.positional_parameters = Some(a),
+            Ok(_) => self.parameters_error = Some("positional parameters must be an array".into()),
+            Err(e) => self.parameters_error = Some(e),
+        }
         self
     }
 
+    /// Sets the values of `$name` parameters, anything which serializes into an object (see
+    /// `query_args!`).
+    ///
+    /// If the parameters can not be encoded into an object, the query fails with
+    /// `InvalidArgument` without being sent.
     pub fn named_parameters<T>(mut self, named_parameters: T) -> Self
     where
         T: serde::Serialize,
     {
-        let named_parameters = match serde_json::to_value(named_parameters) {
-            Ok(Value::Object(a)) => a,
-            Ok(_) => panic!("Only objects are allowed"),
-            _ => panic!("Could not encode positional parameters"),
-        };
-        self.named_parameters = Some(named_parameters);
+        match encode_parameters(named_parameters, "named") {
+            Ok(Value::Object(a)) => self.named_parameters = Some(a),
+            Ok(_) => self.parameters_error = Some("named parameters must be an object".into()),
+            Err(e) => self.parameters_error = Some(e),
+        }
         self
     }
 
@@ -318,6 +1249,21 @@ impl AnalyticsOptions {
         self
     }
 
+    /// Runs the query in the background instead of waiting for its results.
+    ///
+    /// The result of a deferred query has no rows, but a handle (see `AnalyticsResult::handle`)
+    /// which is passed to `Cluster::analytics_status` and `Cluster::analytics_result` to follow
+    /// the query and fetch its results later, so they can take longer than the timeout of a
+    /// request.
+    pub fn deferred(mut self, deferred: bool) -> Self {
+        self.mode = if deferred {
+            Some(String::from("async"))
+        } else {
+            None
+        };
+        self
+    }
+
     pub fn raw<T>(mut self, raw: T) -> Self
     where
         T: serde::Serialize,
@@ -332,6 +1278,29 @@ impl AnalyticsOptions {
     }
 }
 
+#[derive(Debug, Default)]
+pub struct AnalyticsStatusOptions {
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl AnalyticsStatusOptions {
+    timeout!();
+}
+
+#[derive(Debug, Default)]
+pub struct AnalyticsResultOptions {
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl AnalyticsResultOptions {
+    /// How long to wait for the deferred query to complete and its results to be fetched,
+    /// 75 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub enum AnalyticsScanConsistency {
     #[serde(rename = "not_bounded")]
@@ -354,6 +1323,25 @@ pub struct SearchOptions {
     #[serde(serialize_with = "convert_duration_for_golang")]
     pub(crate) timeout: Option<Duration>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) knn: Option<Vec<Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) knn_operator: Option<VectorQueryCombination>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) sort: Option<Vec<Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) highlight: Option<SearchHighlight>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) facets: Option<serde_json::Map<String, Value>>,
+    #[serde(rename = "includeLocations")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) include_locations: Option<bool>,
+    #[serde(skip)]
+    pub(crate) consistent_with: Option<MutationState>,
+    #[serde(skip)]
+    pub(crate) partial_results: PartialSearchResults,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) ctl: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(flatten)]
     pub(crate) raw: Option<serde_json::Map<String, Value>>,
     // The query and index are not part of the public API, but added here
@@ -383,6 +1371,84 @@ impl SearchOptions {
         self
     }
 
+    /// Adds a sort order, applied after the ones added before.
+    ///
+    /// Without any sort order the hits are sorted by descending score.
+    pub fn sort<T: SearchSort>(mut self, sort: T) -> Self {
+        self.sort.get_or_insert_with(Vec::new).push(sort.to_json());
+        self
+    }
+
+    /// Highlights the matches in the returned fragments with the given style.
+    pub fn highlight_style(mut self, style: HighlightStyle) -> Self {
+        self.highlight.get_or_insert_with(Default::default).style = Some(style);
+        self
+    }
+
+    /// Adds a facet, whose result is returned under its name by `SearchMetaData::facets`.
+    pub fn facet<S: Into<String>, T: SearchFacet>(mut self, name: S, facet: T) -> Self {
+        self.facets
+            .get_or_insert_with(Default::default)
+            .insert(name.into(), facet.to_json());
+        self
+    }
+
+    /// Returns where the terms of the query matched in every hit, see `SearchRow::locations`.
+    pub fn include_locations(mut self, include_locations: bool) -> Self {
+        self.include_locations = Some(include_locations);
+        self
+    }
+
+    /// Only returns fragments for the given fields, instead of all fields stored in the index.
+    pub fn highlight_fields(mut self, fields: Vec<String>) -> Self {
+        self.highlight.get_or_insert_with(Default::default).fields = Some(fields);
+        self
+    }
+
+    /// Adds a vector search, whose results are combined with the ones of the search query.
+    ///
+    /// Takes a single `VectorQuery` or a `VectorSearch` made up of several ones. Use a
+    /// `MatchNoneQuery` as search query to run a pure vector search.
+    pub fn vector_search<T: Into<VectorSearch>>(mut self, search: T) -> Self {
+        let search = search.into();
+        let knn = self.knn.get_or_insert_with(Vec::new);
+        knn.extend(search.queries.iter().map(|q| q.to_json()));
+        if search.combination.is_some() {
+            self.knn_operator = search.combination;
+        }
+        self
+    }
+
+    /// Makes the search wait until the index contains the given mutations.
+    pub fn consistent_with(mut self, consistent_with: MutationState) -> Self {
+        self.consistent_with = Some(consistent_with);
+        self
+    }
+
+    /// Decides what happens when some partitions of the index fail, which the search service
+    /// reports alongside the hits of the other partitions.
+    ///
+    /// With `PartialSearchResults::Fail`, the search only returns once the full response has
+    /// been received, so the rows are not streamed.
+    pub fn partial_results(mut self, partial_results: PartialSearchResults) -> Self {
+        self.partial_results = partial_results;
+        self
+    }
+
+    /// Builds the consistency control block, which is scoped to the index being searched.
+    pub(crate) fn consistency_ctl(&mut self, index: &str) {
+        if let Some(state) = self.consistent_with.take() {
+            let mut vectors = serde_json::Map::new();
+            vectors.insert(index.into(), Value::Object(state.search_vectors()));
+            self.ctl = Some(serde_json::json!({
+                "consistency": {
+                    "level": "at_plus",
+                    "vectors": vectors,
+                }
+            }));
+        }
+    }
+
     pub fn raw<T>(mut self, raw: T) -> Self
     where
         T: serde::Serialize,
@@ -397,452 +1463,1102 @@ impl SearchOptions {
     }
 }
 
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct SearchHighlight {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    style: Option<HighlightStyle>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fields: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default)]
+pub struct GetOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+    pub(crate) project: Vec<String>,
+    pub(crate) with_expiry: bool,
+    pub(crate) transcoder: Option<Arc<dyn Transcoder>>,
+}
+
+impl GetOptions {
+    timeout!();
+    retry_strategy!();
+    transcoder!();
+
+    /// Only fetches the given paths of the document, which is returned as a partial document
+    /// holding just those paths. Paths which do not exist in the document are left out.
+    ///
+    /// The paths are fetched through a sub-document lookup, so this only works for JSON
+    /// documents. If there are too many paths for a single lookup, the whole document is
+    /// fetched and projected on the client.
+    pub fn project<I, S>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.project = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Fetches the expiry of the document alongside it, see `GetResult::expiry_time`.
+    pub fn with_expiry(mut self, with_expiry: bool) -> Self {
+        self.with_expiry = with_expiry;
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GetAnyReplicaOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) transcoder: Option<Arc<dyn Transcoder>>,
+}
+
+impl GetAnyReplicaOptions {
+    timeout!();
+    transcoder!();
+}
+
+#[derive(Debug, Default)]
+pub struct GetAllReplicasOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) transcoder: Option<Arc<dyn Transcoder>>,
+}
+
+impl GetAllReplicasOptions {
+    timeout!();
+    transcoder!();
+}
+
+#[derive(Debug, Default)]
+pub struct LookupInAnyReplicaOptions {
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl LookupInAnyReplicaOptions {
+    timeout!();
+}
+
+#[derive(Debug, Default)]
+pub struct LookupInAllReplicasOptions {
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl LookupInAllReplicasOptions {
+    timeout!();
+}
+
+#[derive(Debug, Default)]
+pub struct GetSlidingOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) threshold: Option<Duration>,
+}
+
+impl GetSlidingOptions {
+    timeout!();
+
+    /// The remaining time to live below which the expiry is reset, half the window by default.
+    pub fn threshold(mut self, threshold: Duration) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GetAndTouchOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+    pub(crate) transcoder: Option<Arc<dyn Transcoder>>,
+}
+
+impl GetAndTouchOptions {
+    timeout!();
+    retry_strategy!();
+    transcoder!();
+}
+
+#[derive(Debug, Default)]
+pub struct TouchOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+}
+
+impl TouchOptions {
+    timeout!();
+    retry_strategy!();
+}
+
+#[derive(Debug, Default)]
+pub struct ClockSkewOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) threshold: Option<Duration>,
+}
+
+impl ClockSkewOptions {
+    timeout!();
+
+    /// Logs a warning if the clocks are further apart than this, five seconds by default.
+    pub fn threshold(mut self, threshold: Duration) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GetAndLockOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+    pub(crate) transcoder: Option<Arc<dyn Transcoder>>,
+}
+
+impl GetAndLockOptions {
+    timeout!();
+    retry_strategy!();
+    transcoder!();
+}
+
+#[derive(Debug, Default)]
+pub struct UnlockOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+}
+
+impl UnlockOptions {
+    timeout!();
+    retry_strategy!();
+}
+
+#[derive(Debug, Default)]
+pub struct UpsertOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+    pub(crate) expiry: Option<Expiry>,
+    pub(crate) preserve_expiry: bool,
+    pub(crate) durability_level: Option<DurabilityLevel>,
+    pub(crate) client_durability: Option<(PersistTo, ReplicateTo)>,
+    pub(crate) transcoder: Option<Arc<dyn Transcoder>>,
+}
+
+impl UpsertOptions {
+    timeout!();
+    retry_strategy!();
+    expiry!();
+    preserve_expiry!();
+    durability!();
+    client_durability!();
+    transcoder!();
+}
+
+#[derive(Debug, Default)]
+pub struct InsertOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+    pub(crate) expiry: Option<Expiry>,
+    pub(crate) durability_level: Option<DurabilityLevel>,
+    pub(crate) client_durability: Option<(PersistTo, ReplicateTo)>,
+    pub(crate) transcoder: Option<Arc<dyn Transcoder>>,
+}
+
+impl InsertOptions {
+    timeout!();
+    retry_strategy!();
+    expiry!();
+    durability!();
+    client_durability!();
+    transcoder!();
+}
+
+#[derive(Debug, Default)]
+pub struct ReplaceOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+    pub(crate) cas: Option<u64>,
+    pub(crate) expiry: Option<Expiry>,
+    pub(crate) preserve_expiry: bool,
+    pub(crate) durability_level: Option<DurabilityLevel>,
+    pub(crate) client_durability: Option<(PersistTo, ReplicateTo)>,
+    pub(crate) transcoder: Option<Arc<dyn Transcoder>>,
+}
+
+impl ReplaceOptions {
+    timeout!();
+    retry_strategy!();
+    expiry!();
+    preserve_expiry!();
+    durability!();
+    client_durability!();
+    transcoder!();
+
+    pub fn cas(mut self, cas: u64) -> Self {
+        self.cas = Some(cas);
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RemoveOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+    pub(crate) cas: Option<u64>,
+    pub(crate) durability_level: Option<DurabilityLevel>,
+    pub(crate) client_durability: Option<(PersistTo, ReplicateTo)>,
+}
+
+impl RemoveOptions {
+    timeout!();
+    retry_strategy!();
+    durability!();
+    client_durability!();
+
+    pub fn cas(mut self, cas: u64) -> Self {
+        self.cas = Some(cas);
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ExistsOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+}
+
+impl ExistsOptions {
+    timeout!();
+    retry_strategy!();
+}
+
+#[derive(Debug, Default)]
+pub struct AppendOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+    pub(crate) cas: Option<u64>,
+    pub(crate) durability_level: Option<DurabilityLevel>,
+    pub(crate) client_durability: Option<(PersistTo, ReplicateTo)>,
+}
+
+impl AppendOptions {
+    timeout!();
+    retry_strategy!();
+    durability!();
+    client_durability!();
+
+    pub fn cas(mut self, cas: u64) -> Self {
+        self.cas = Some(cas);
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PrependOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+    pub(crate) cas: Option<u64>,
+    pub(crate) durability_level: Option<DurabilityLevel>,
+    pub(crate) client_durability: Option<(PersistTo, ReplicateTo)>,
+}
+
+impl PrependOptions {
+    timeout!();
+    retry_strategy!();
+    durability!();
+    client_durability!();
+
+    pub fn cas(mut self, cas: u64) -> Self {
+        self.cas = Some(cas);
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct IncrementOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+    pub(crate) cas: Option<u64>,
+    pub(crate) expiry: Option<Expiry>,
+    pub(crate) delta: Option<u64>,
+    pub(crate) initial: Option<u64>,
+    pub(crate) durability_level: Option<DurabilityLevel>,
+    pub(crate) client_durability: Option<(PersistTo, ReplicateTo)>,
+}
+
+impl IncrementOptions {
+    timeout!();
+    retry_strategy!();
+    expiry!();
+    durability!();
+    client_durability!();
+
+    pub fn delta(mut self, delta: u64) -> Self {
+        self.delta = Some(delta);
+        self
+    }
+
+    /// Creates the document with this value if it does not exist, instead of failing with
+    /// `DocumentNotFound`. The expiry applies to the created document as well.
+    pub fn initial(mut self, initial: u64) -> Self {
+        self.initial = Some(initial);
+        self
+    }
+
+    pub fn cas(mut self, cas: u64) -> Self {
+        self.cas = Some(cas);
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DecrementOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+    pub(crate) cas: Option<u64>,
+    pub(crate) expiry: Option<Expiry>,
+    pub(crate) delta: Option<u64>,
+    pub(crate) initial: Option<u64>,
+    pub(crate) durability_level: Option<DurabilityLevel>,
+    pub(crate) client_durability: Option<(PersistTo, ReplicateTo)>,
+}
+
+impl DecrementOptions {
+    timeout!();
+    retry_strategy!();
+    expiry!();
+    durability!();
+    client_durability!();
+
+    pub fn delta(mut self, delta: u64) -> Self {
+        self.delta = Some(delta);
+        self
+    }
+
+    /// Creates the document with this value if it does not exist, instead of failing with
+    /// `DocumentNotFound`. The expiry applies to the created document as well.
+    pub fn initial(mut self, initial: u64) -> Self {
+        self.initial = Some(initial);
+        self
+    }
+
+    pub fn cas(mut self, cas: u64) -> Self {
+        self.cas = Some(cas);
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct CounterOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+    pub(crate) cas: Option<u64>,
+    pub(crate) expiry: Option<Expiry>,
+    pub(crate) delta: i64,
+    pub(crate) initial: Option<u64>,
+    pub(crate) durability_level: Option<DurabilityLevel>,
+    pub(crate) client_durability: Option<(PersistTo, ReplicateTo)>,
+}
+
+#[derive(Debug, Default)]
+pub struct MutateInOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+    pub(crate) cas: Option<u64>,
+    pub(crate) store_semantics: Option<StoreSemantics>,
+    pub(crate) expiry: Option<Expiry>,
+    pub(crate) access_deleted: Option<bool>,
+    pub(crate) durability_level: Option<DurabilityLevel>,
+    pub(crate) client_durability: Option<(PersistTo, ReplicateTo)>,
+}
+
+impl MutateInOptions {
+    timeout!();
+    retry_strategy!();
+    expiry!();
+    durability!();
+    client_durability!();
+
+    pub fn cas(mut self, cas: u64) -> Self {
+        self.cas = Some(cas);
+        self
+    }
+
+    pub fn store_semantics(mut self, store_semantics: StoreSemantics) -> Self {
+        self.store_semantics = Some(store_semantics);
+        self
+    }
+
+    pub fn access_deleted(mut self, access_deleted: bool) -> Self {
+        self.access_deleted = Some(access_deleted);
+        self
+    }
+}
+
+/// Describes how the outer document store semantics on subdoc should act.
+#[derive(Debug)]
+pub enum StoreSemantics {
+    /// Create the document, fail if it exists.
+    Insert,
+    /// Replace the document or create it if it does not exist.
+    Upsert,
+    /// Replace the document, fail if it does not exist. This is the default.
+    Replace,
+}
+
+#[derive(Debug, Default)]
+pub struct LookupInOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_strategy: Option<Arc<dyn RetryStrategy>>,
+    pub(crate) max_retries: Option<u32>,
+    pub(crate) access_deleted: Option<bool>,
+}
+
+impl LookupInOptions {
+    timeout!();
+    retry_strategy!();
+
+    pub fn access_deleted(mut self, access_deleted: bool) -> Self {
+        self.access_deleted = Some(access_deleted);
+        self
+    }
+}
+
+macro_rules! domain_name {
+    () => {
+        pub fn domain_name(mut self, domain_name: String) -> Self {
+            self.domain_name = Some(domain_name);
+            self
+        }
+    };
+}
+
+#[derive(Debug, Default)]
+pub struct GetUserOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) domain_name: Option<String>,
+}
+
+impl GetUserOptions {
+    timeout!();
+    domain_name!();
+}
+
+#[derive(Debug, Default)]
+pub struct GetAllUsersOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) domain_name: Option<String>,
+}
+
+impl GetAllUsersOptions {
+    timeout!();
+    domain_name!();
+}
+
+#[derive(Debug, Default)]
+pub struct UpsertUserOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) domain_name: Option<String>,
+}
+
+impl UpsertUserOptions {
+    timeout!();
+    domain_name!();
+}
+
+#[derive(Debug, Default)]
+pub struct DropUserOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) domain_name: Option<String>,
+}
+
+impl DropUserOptions {
+    timeout!();
+    domain_name!();
+}
+
+#[derive(Debug, Default)]
+pub struct ChangePasswordOptions {
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl ChangePasswordOptions {
+    timeout!();
+}
+
+#[derive(Debug, Default)]
+pub struct GetRolesOptions {
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl GetRolesOptions {
+    timeout!();
+}
+
+#[derive(Debug, Default)]
+pub struct GetGroupOptions {
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl GetGroupOptions {
+    timeout!();
+}
+
+#[derive(Debug, Default)]
+pub struct GetAllGroupsOptions {
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl GetAllGroupsOptions {
+    timeout!();
+}
+
+#[derive(Debug, Default)]
+pub struct UpsertGroupOptions {
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl UpsertGroupOptions {
+    timeout!();
+}
+
+#[derive(Debug, Default)]
+pub struct DropGroupOptions {
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl DropGroupOptions {
+    timeout!();
+}
+
+#[derive(Debug, Default)]
+#[cfg(feature = "volatile")]
+pub struct KvStatsOptions {
+    pub(crate) timeout: Option<Duration>,
+}
+
+#[cfg(feature = "volatile")]
+impl KvStatsOptions {
+    timeout!();
+}
+
+#[derive(Debug, Default)]
+#[cfg(feature = "volatile")]
+pub struct GetCollectionIdOptions {
+    pub(crate) timeout: Option<Duration>,
+}
+
+#[cfg(feature = "volatile")]
+impl GetCollectionIdOptions {
+    timeout!();
+}
+
+#[derive(Debug, Default)]
+#[cfg(feature = "volatile")]
+pub struct SetMemoryQuotasOptions {
+    pub(crate) timeout: Option<Duration>,
+}
+
+#[cfg(feature = "volatile")]
+impl SetMemoryQuotasOptions {
+    timeout!();
+}
+
+#[derive(Debug, Default)]
+#[cfg(feature = "volatile")]
+pub struct LoadSampleBucketsOptions {
+    pub(crate) timeout: Option<Duration>,
+}
+
+#[cfg(feature = "volatile")]
+impl LoadSampleBucketsOptions {
+    timeout!();
+}
+
 #[derive(Debug, Default)]
-pub struct GetOptions {
+pub struct BeginQueryTransactionOptions {
     pub(crate) timeout: Option<Duration>,
+    pub(crate) tx_timeout: Option<Duration>,
+    pub(crate) durability_level: Option<DurabilityLevel>,
 }
 
-impl GetOptions {
+impl BeginQueryTransactionOptions {
     timeout!();
+
+    /// How long the transaction may run before it is rolled back by the query service.
+    pub fn tx_timeout(mut self, tx_timeout: Duration) -> Self {
+        self.tx_timeout = Some(tx_timeout);
+        self
+    }
+
+    /// The durability level of all writes performed in the transaction.
+    pub fn durability_level(mut self, durability_level: DurabilityLevel) -> Self {
+        self.durability_level = Some(durability_level);
+        self
+    }
 }
 
 #[derive(Debug, Default)]
-pub struct GetAndTouchOptions {
+pub struct GetAllQueryIndexesOptions {
     pub(crate) timeout: Option<Duration>,
+    pub(crate) scope_name: Option<String>,
+    pub(crate) collection_name: Option<String>,
 }
 
-impl GetAndTouchOptions {
+impl GetAllQueryIndexesOptions {
     timeout!();
+    collection!();
 }
 
 #[derive(Debug, Default)]
-pub struct GetAndLockOptions {
+pub struct BuildDeferredQueryIndexesOptions {
     pub(crate) timeout: Option<Duration>,
+    pub(crate) scope_name: Option<String>,
+    pub(crate) collection_name: Option<String>,
 }
 
-impl GetAndLockOptions {
+impl BuildDeferredQueryIndexesOptions {
     timeout!();
+    collection!();
 }
 
 #[derive(Debug, Default)]
-pub struct UpsertOptions {
-    pub(crate) timeout: Option<Duration>,
-    pub(crate) expiry: Option<Duration>,
+pub struct WatchQueryIndexesOptions {
+    pub(crate) watch_primary: Option<bool>,
+    pub(crate) scope_name: Option<String>,
+    pub(crate) collection_name: Option<String>,
 }
 
-impl UpsertOptions {
-    timeout!();
-    expiry!();
+impl WatchQueryIndexesOptions {
+    collection!();
+
+    /// Also waits for the primary index to come online.
+    pub fn watch_primary(mut self, watch_primary: bool) -> Self {
+        self.watch_primary = Some(watch_primary);
+        self
+    }
 }
 
 #[derive(Debug, Default)]
-pub struct InsertOptions {
+pub struct GetAllQueryFunctionsOptions {
     pub(crate) timeout: Option<Duration>,
-    pub(crate) expiry: Option<Duration>,
 }
 
-impl InsertOptions {
+impl GetAllQueryFunctionsOptions {
     timeout!();
-    expiry!();
 }
 
 #[derive(Debug, Default)]
-pub struct ReplaceOptions {
+pub struct CreateQueryFunctionOptions {
     pub(crate) timeout: Option<Duration>,
-    pub(crate) cas: Option<u64>,
-    pub(crate) expiry: Option<Duration>,
+    pub(crate) replace: bool,
 }
 
-impl ReplaceOptions {
+impl CreateQueryFunctionOptions {
     timeout!();
-    expiry!();
 
-    pub fn cas(mut self, cas: u64) -> Self {
-        self.cas = Some(cas);
+    /// Replaces an existing function of the same name instead of failing.
+    pub fn replace(mut self, replace: bool) -> Self {
+        self.replace = replace;
         self
     }
 }
 
 #[derive(Debug, Default)]
-pub struct RemoveOptions {
+pub struct DropQueryFunctionOptions {
     pub(crate) timeout: Option<Duration>,
-    pub(crate) cas: Option<u64>,
 }
 
-impl RemoveOptions {
+impl DropQueryFunctionOptions {
     timeout!();
-
-    pub fn cas(mut self, cas: u64) -> Self {
-        self.cas = Some(cas);
-        self
-    }
 }
 
 #[derive(Debug, Default)]
-pub struct ExistsOptions {
+pub struct GetAllQueryLibrariesOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-impl ExistsOptions {
+impl GetAllQueryLibrariesOptions {
     timeout!();
 }
 
 #[derive(Debug, Default)]
-pub struct AppendOptions {
+pub struct UpsertQueryLibraryOptions {
     pub(crate) timeout: Option<Duration>,
-    pub(crate) cas: Option<u64>,
 }
 
-impl AppendOptions {
+impl UpsertQueryLibraryOptions {
     timeout!();
-
-    pub fn cas(mut self, cas: u64) -> Self {
-        self.cas = Some(cas);
-        self
-    }
 }
 
 #[derive(Debug, Default)]
-pub struct PrependOptions {
+pub struct DropQueryLibraryOptions {
     pub(crate) timeout: Option<Duration>,
-    pub(crate) cas: Option<u64>,
-    pub(crate) expiry: Option<Duration>,
 }
 
-impl PrependOptions {
+impl DropQueryLibraryOptions {
     timeout!();
-
-    pub fn cas(mut self, cas: u64) -> Self {
-        self.cas = Some(cas);
-        self
-    }
 }
 
 #[derive(Debug, Default)]
-pub struct IncrementOptions {
+pub struct GetAuditSettingsOptions {
     pub(crate) timeout: Option<Duration>,
-    pub(crate) cas: Option<u64>,
-    pub(crate) expiry: Option<Duration>,
-    pub(crate) delta: Option<u64>,
 }
 
-impl IncrementOptions {
+impl GetAuditSettingsOptions {
     timeout!();
-    expiry!();
-
-    pub fn delta(mut self, delta: u64) -> Self {
-        self.delta = Some(delta);
-        self
-    }
-
-    pub fn cas(mut self, cas: u64) -> Self {
-        self.cas = Some(cas);
-        self
-    }
 }
 
 #[derive(Debug, Default)]
-pub struct DecrementOptions {
+pub struct UpdateAuditSettingsOptions {
     pub(crate) timeout: Option<Duration>,
-    pub(crate) cas: Option<u64>,
-    pub(crate) expiry: Option<Duration>,
-    pub(crate) delta: Option<u64>,
 }
 
-impl DecrementOptions {
+impl UpdateAuditSettingsOptions {
     timeout!();
-    expiry!();
-
-    pub fn delta(mut self, delta: u64) -> Self {
-        self.delta = Some(delta);
-        self
-    }
-
-    pub fn cas(mut self, cas: u64) -> Self {
-        self.cas = Some(cas);
-        self
-    }
 }
 
 #[derive(Debug, Default)]
-pub(crate) struct CounterOptions {
+pub struct GetAuditEventDescriptorsOptions {
     pub(crate) timeout: Option<Duration>,
-    pub(crate) cas: Option<u64>,
-    pub(crate) expiry: Option<Duration>,
-    pub(crate) delta: i64,
+}
+
+impl GetAuditEventDescriptorsOptions {
+    timeout!();
 }
 
 #[derive(Debug, Default)]
-pub struct MutateInOptions {
+pub struct PingOptions {
+    pub(crate) report_id: Option<String>,
+    pub(crate) service_types: Option<Vec<ServiceType>>,
     pub(crate) timeout: Option<Duration>,
-    pub(crate) cas: Option<u64>,
-    pub(crate) store_semantics: Option<StoreSemantics>,
-    pub(crate) expiry: Option<Duration>,
-    pub(crate) access_deleted: Option<bool>,
 }
 
-impl MutateInOptions {
+impl PingOptions {
     timeout!();
-    expiry!();
 
-    pub fn cas(mut self, cas: u64) -> Self {
-        self.cas = Some(cas);
+    pub fn report_id(mut self, report_id: String) -> Self {
+        self.report_id = Some(report_id);
         self
     }
 
-    pub fn store_semantics(mut self, store_semantics: StoreSemantics) -> Self {
-        self.store_semantics = Some(store_semantics);
+    /// Only pings the given services instead of all of them.
+    ///
+    /// Management endpoints can not be pinged, so `ServiceType::Management` is ignored.
+    pub fn service_types(mut self, service_types: Vec<ServiceType>) -> Self {
+        self.service_types = Some(service_types);
         self
     }
+}
 
-    pub fn access_deleted(mut self, access_deleted: bool) -> Self {
-        self.access_deleted = Some(access_deleted);
+#[derive(Debug, Default)]
+pub struct DiagnosticsOptions {
+    pub(crate) report_id: Option<String>,
+}
+
+impl DiagnosticsOptions {
+    pub fn report_id(mut self, report_id: String) -> Self {
+        self.report_id = Some(report_id);
         self
     }
 }
 
-/// Describes how the outer document store semantics on subdoc should act.
-#[derive(Debug)]
-pub enum StoreSemantics {
-    /// Create the document, fail if it exists.
-    Insert,
-    /// Replace the document or create it if it does not exist.
-    Upsert,
-    /// Replace the document, fail if it does not exist. This is the default.
-    Replace,
+/// The state of the cluster as seen by `wait_until_ready`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ClusterState {
+    /// All endpoints of the awaited services are reachable.
+    Online,
+    /// Some, but not all, endpoints of the awaited services are reachable.
+    Degraded,
+    /// None of the endpoints of the awaited services are reachable.
+    Offline,
 }
 
 #[derive(Debug, Default)]
-pub struct LookupInOptions {
-    pub(crate) timeout: Option<Duration>,
-    pub(crate) access_deleted: Option<bool>,
+pub struct WaitUntilReadyOptions {
+    pub(crate) service_types: Option<Vec<ServiceType>>,
+    pub(crate) desired_state: Option<ClusterState>,
 }
 
-impl LookupInOptions {
-    timeout!();
-
-    pub fn access_deleted(mut self, access_deleted: bool) -> Self {
-        self.access_deleted = Some(access_deleted);
+impl WaitUntilReadyOptions {
+    /// Only waits for the given services instead of all of them.
+    ///
+    /// Management endpoints can not be pinged, so `ServiceType::Management` is ignored.
+    pub fn service_types(mut self, service_types: Vec<ServiceType>) -> Self {
+        self.service_types = Some(service_types);
         self
     }
-}
 
-macro_rules! domain_name {
-    () => {
-        pub fn domain_name(mut self, domain_name: String) -> Self {
-            self.domain_name = Some(domain_name);
-            self
-        }
-    };
+    /// The state the cluster needs to reach at least, `ClusterState::Online` by default.
+    pub fn desired_state(mut self, desired_state: ClusterState) -> Self {
+        self.desired_state = Some(desired_state);
+        self
+    }
 }
 
 #[derive(Debug, Default)]
-pub struct GetUserOptions {
+pub struct PrewarmOptions {
+    pub(crate) query_nodes: bool,
+    pub(crate) statements: Vec<String>,
+    pub(crate) search_indexes: Vec<String>,
     pub(crate) timeout: Option<Duration>,
-    pub(crate) domain_name: Option<String>,
 }
 
-impl GetUserOptions {
+impl PrewarmOptions {
     timeout!();
-    domain_name!();
+
+    /// Opens the connections to all query nodes.
+    pub fn query_nodes(mut self, query_nodes: bool) -> Self {
+        self.query_nodes = query_nodes;
+        self
+    }
+
+    /// Prepares the given statements, so later queries with `adhoc` disabled can use the
+    /// cached plans right away.
+    pub fn statements(mut self, statements: Vec<String>) -> Self {
+        self.statements = statements;
+        self
+    }
+
+    /// Opens the connections to the search nodes and runs an empty query against each of
+    /// the given indexes.
+    pub fn search_indexes(mut self, search_indexes: Vec<String>) -> Self {
+        self.search_indexes = search_indexes;
+        self
+    }
 }
 
 #[derive(Debug, Default)]
-pub struct GetAllUsersOptions {
+pub struct GetAllScopesOptions {
     pub(crate) timeout: Option<Duration>,
-    pub(crate) domain_name: Option<String>,
 }
 
-impl GetAllUsersOptions {
+impl GetAllScopesOptions {
     timeout!();
-    domain_name!();
 }
 
 #[derive(Debug, Default)]
-pub struct UpsertUserOptions {
+pub struct CreateScopeOptions {
     pub(crate) timeout: Option<Duration>,
-    pub(crate) domain_name: Option<String>,
 }
 
-impl UpsertUserOptions {
+impl CreateScopeOptions {
     timeout!();
-    domain_name!();
 }
 
 #[derive(Debug, Default)]
-pub struct DropUserOptions {
+pub struct CreateCollectionOptions {
     pub(crate) timeout: Option<Duration>,
-    pub(crate) domain_name: Option<String>,
 }
 
-impl DropUserOptions {
+impl CreateCollectionOptions {
     timeout!();
-    domain_name!();
 }
 
 #[derive(Debug, Default)]
-pub struct GetRolesOptions {
+pub struct UpdateCollectionOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-impl GetRolesOptions {
+impl UpdateCollectionOptions {
     timeout!();
 }
 
 #[derive(Debug, Default)]
-pub struct GetGroupOptions {
+pub struct DropScopeOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-impl GetGroupOptions {
+impl DropScopeOptions {
     timeout!();
 }
 
 #[derive(Debug, Default)]
-pub struct GetAllGroupsOptions {
+pub struct DropCollectionOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-impl GetAllGroupsOptions {
+impl DropCollectionOptions {
     timeout!();
 }
 
 #[derive(Debug, Default)]
-pub struct UpsertGroupOptions {
+pub struct CreateBucketOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-impl UpsertGroupOptions {
+impl CreateBucketOptions {
     timeout!();
 }
 
 #[derive(Debug, Default)]
-pub struct DropGroupOptions {
+pub struct DropBucketOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-impl DropGroupOptions {
+impl DropBucketOptions {
     timeout!();
 }
 
 #[derive(Debug, Default)]
-#[cfg(feature = "volatile")]
-pub struct KvStatsOptions {
+pub struct UpdateBucketOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-#[cfg(feature = "volatile")]
-impl KvStatsOptions {
+impl UpdateBucketOptions {
     timeout!();
 }
 
 #[derive(Debug, Default)]
-pub struct PingOptions {
-    pub(crate) report_id: Option<String>,
+pub struct GetBucketOptions {
+    pub(crate) timeout: Option<Duration>,
 }
 
-impl PingOptions {
-    pub fn report_id(mut self, report_id: String) -> Self {
-        self.report_id = Some(report_id);
-        self
-    }
+impl GetBucketOptions {
+    timeout!();
 }
 
 #[derive(Debug, Default)]
-pub struct GetAllScopesOptions {
+pub struct GetAllBucketsOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-impl GetAllScopesOptions {
+impl GetAllBucketsOptions {
     timeout!();
 }
 
 #[derive(Debug, Default)]
-pub struct CreateScopeOptions {
+pub struct FlushBucketOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-impl CreateScopeOptions {
+impl FlushBucketOptions {
     timeout!();
 }
 
 #[derive(Debug, Default)]
-pub struct CreateCollectionOptions {
+pub struct InstallSampleBucketOptions {
     pub(crate) timeout: Option<Duration>,
+    pub(crate) load_timeout: Option<Duration>,
+    pub(crate) progress: Option<Arc<dyn SampleLoadListener>>,
 }
 
-impl CreateCollectionOptions {
+impl InstallSampleBucketOptions {
     timeout!();
+
+    /// How long to wait for the documents of the sample to be loaded, five minutes by default.
+    pub fn load_timeout(mut self, load_timeout: Duration) -> Self {
+        self.load_timeout = Some(load_timeout);
+        self
+    }
+
+    /// Reports the progress of loading the sample to the listener while waiting for it.
+    pub fn progress(mut self, listener: Arc<dyn SampleLoadListener>) -> Self {
+        self.progress = Some(listener);
+        self
+    }
 }
 
 #[derive(Debug, Default)]
-pub struct DropScopeOptions {
+pub struct GetSearchIndexOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-impl DropScopeOptions {
+impl GetSearchIndexOptions {
     timeout!();
 }
 
 #[derive(Debug, Default)]
-pub struct DropCollectionOptions {
+pub struct GetAllSearchIndexesOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-impl DropCollectionOptions {
+impl GetAllSearchIndexesOptions {
     timeout!();
 }
 
 #[derive(Debug, Default)]
-pub struct CreateBucketOptions {
+pub struct UpsertSearchIndexOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-impl CreateBucketOptions {
+impl UpsertSearchIndexOptions {
     timeout!();
 }
 
 #[derive(Debug, Default)]
-pub struct DropBucketOptions {
+pub struct DropSearchIndexOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-impl DropBucketOptions {
+impl DropSearchIndexOptions {
     timeout!();
 }
 
+#[cfg(feature = "volatile")]
 #[derive(Debug, Default)]
-pub struct UpdateBucketOptions {
+pub struct CreateAnalyticsLinkOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-impl UpdateBucketOptions {
+#[cfg(feature = "volatile")]
+impl CreateAnalyticsLinkOptions {
     timeout!();
 }
 
+#[cfg(feature = "volatile")]
 #[derive(Debug, Default)]
-pub struct GetBucketOptions {
+pub struct ReplaceAnalyticsLinkOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-impl GetBucketOptions {
+#[cfg(feature = "volatile")]
+impl ReplaceAnalyticsLinkOptions {
     timeout!();
 }
 
+#[cfg(feature = "volatile")]
 #[derive(Debug, Default)]
-pub struct GetAllBucketsOptions {
+pub struct DropAnalyticsLinkOptions {
     pub(crate) timeout: Option<Duration>,
 }
 
-impl GetAllBucketsOptions {
+#[cfg(feature = "volatile")]
+impl DropAnalyticsLinkOptions {
     timeout!();
 }
 
+#[cfg(feature = "volatile")]
 #[derive(Debug, Default)]
-pub struct FlushBucketOptions {
+pub struct GetAnalyticsLinksOptions {
     pub(crate) timeout: Option<Duration>,
+    pub(crate) dataverse: Option<String>,
+    pub(crate) name: Option<String>,
 }
 
-impl FlushBucketOptions {
+#[cfg(feature = "volatile")]
+impl GetAnalyticsLinksOptions {
     timeout!();
+
+    /// Only returns the links of the given dataverse.
+    pub fn dataverse<S: Into<String>>(mut self, dataverse: S) -> Self {
+        self.dataverse = Some(dataverse.into());
+        self
+    }
+
+    /// Only returns the link of the given name, needs the dataverse to be set as well.
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
 }
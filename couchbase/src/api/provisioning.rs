@@ -0,0 +1,134 @@
+use crate::api::buckets::BucketManager;
+use crate::io::request::*;
+use crate::io::Core;
+use crate::{
+    CouchbaseError, CouchbaseResult, ErrorContext, GenericManagementResult,
+    InstallSampleBucketOptions, LoadSampleBucketsOptions, SetMemoryQuotasOptions,
+};
+use futures::channel::oneshot;
+use std::sync::Arc;
+
+/// Per-service memory quotas in megabytes.
+///
+/// Services which are not set keep their current quota.
+#[derive(Debug, Default)]
+pub struct MemoryQuotas {
+    kv: Option<u64>,
+    index: Option<u64>,
+    search: Option<u64>,
+    analytics: Option<u64>,
+    eventing: Option<u64>,
+}
+
+impl MemoryQuotas {
+    pub fn kv(mut self, quota_mb: u64) -> Self {
+        self.kv = Some(quota_mb);
+        self
+    }
+
+    pub fn index(mut self, quota_mb: u64) -> Self {
+        self.index = Some(quota_mb);
+        self
+    }
+
+    pub fn search(mut self, quota_mb: u64) -> Self {
+        self.search = Some(quota_mb);
+        self
+    }
+
+    pub fn analytics(mut self, quota_mb: u64) -> Self {
+        self.analytics = Some(quota_mb);
+        self
+    }
+
+    pub fn eventing(mut self, quota_mb: u64) -> Self {
+        self.eventing = Some(quota_mb);
+        self
+    }
+}
+
+/// Helpers to provision a freshly started development cluster.
+///
+/// Since the SDK needs a cluster configuration to connect, the node must have been initialized
+/// (services and administrator credentials set) before these helpers can be used.
+///
+/// Note that this API is unsupported and not stable, so you need to opt in via the
+/// `volatile` feature to access it.
+pub struct ProvisioningManager {
+    core: Arc<Core>,
+}
+
+impl ProvisioningManager {
+    pub(crate) fn new(core: Arc<Core>) -> Self {
+        Self { core }
+    }
+
+    pub async fn set_memory_quotas(
+        &self,
+        quotas: MemoryQuotas,
+        options: SetMemoryQuotasOptions,
+    ) -> CouchbaseResult<()> {
+        let form = &[
+            ("memoryQuota", quotas.kv),
+            ("indexMemoryQuota", quotas.index),
+            ("ftsMemoryQuota", quotas.search),
+            ("cbasMemoryQuota", quotas.analytics),
+            ("eventingMemoryQuota", quotas.eventing),
+        ];
+
+        let form_encoded = serde_urlencoded::to_string(form).unwrap();
+        let content_type = String::from("application/x-www-form-urlencoded");
+        let (sender, receiver) = oneshot::channel();
+
+        let timer = self.core.send(Request::GenericManagementRequest(
+            GenericManagementRequest {
+                sender,
+                path: String::from("/pools/default"),
+                method: String::from("post"),
+                payload: Some(form_encoded),
+                content_type: Some(content_type),
+                timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
+            },
+        ));
+
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
+
+        match result.http_status() {
+            200 => Ok(()),
+            _ => Err(self.parse_error(&result)),
+        }
+    }
+
+    /// Loads the given sample buckets, e.g. `travel-sample`, one after the other.
+    ///
+    /// Each sample is installed through `BucketManager::install_sample`, so this waits until
+    /// their documents have been loaded and fails the same way.
+    pub async fn load_sample_buckets(
+        &self,
+        names: Vec<String>,
+        options: LoadSampleBucketsOptions,
+    ) -> CouchbaseResult<()> {
+        let buckets = BucketManager::new(self.core.clone());
+        for name in names {
+            let mut install_options = InstallSampleBucketOptions::default();
+            if let Some(timeout) = options.timeout {
+                install_options = install_options.timeout(timeout);
+            }
+            buckets.install_sample(name, install_options).await?;
+        }
+        Ok(())
+    }
+
+    fn parse_error(&self, result: &GenericManagementResult) -> CouchbaseError {
+        CouchbaseError::GenericHTTP {
+            ctx: ErrorContext::default(),
+            status: result.http_status(),
+            message: match result.payload() {
+                Some(p) => String::from_utf8_lossy(p).to_lowercase(),
+                None => String::new(),
+            },
+        }
+    }
+}
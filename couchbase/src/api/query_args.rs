@@ -0,0 +1,102 @@
+use serde::ser::{Error, Serialize, Serializer};
+use serde_json::Value;
+
+/// The parameters of a query or analytics statement, usually built with `query_args!`.
+///
+/// Pass them to `named_parameters` or `positional_parameters` of `QueryOptions` and
+/// `AnalyticsOptions`. Every value is encoded as it is added, so one which can not be encoded
+/// (i.e. a map with keys which are not strings) fails the query with `InvalidArgument` before
+/// it is sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryArgs {
+    args: Result<Value, String>,
+}
+
+impl QueryArgs {
+    /// Starts positional parameters, referenced as `$1`, `$2` and so on in the statement.
+    pub fn positional() -> Self {
+        Self {
+            args: Ok(Value::Array(vec![])),
+        }
+    }
+
+    /// Starts named parameters, referenced as `$name` in the statement.
+    pub fn named() -> Self {
+        Self {
+            args: Ok(Value::Object(Default::default())),
+        }
+    }
+
+    /// Appends a positional parameter.
+    pub fn push<T: Serialize>(mut self, value: T) -> Self {
+        let position = match &self.args {
+            Ok(Value::Array(args)) => args.len() + 1,
+            _ => 0,
+        };
+        self.args = match (self.args, encode(value)) {
+            (Ok(Value::Array(mut args)), Ok(value)) => {
+                args.push(value);
+                Ok(Value::Array(args))
+            }
+            (Ok(_), Ok(_)) => Err("positional parameter added to named parameters".into()),
+            (Ok(_), Err(e)) => Err(format!("parameter ${} {}", position, e)),
+            (Err(e), _) => Err(e),
+        };
+        self
+    }
+
+    /// Sets a named parameter, the name may or may not start with `$`.
+    pub fn insert<N: Into<String>, T: Serialize>(mut self, name: N, value: T) -> Self {
+        let name = name.into();
+        let name = name.strip_prefix('$').map(String::from).unwrap_or(name);
+        self.args = match (self.args, encode(value)) {
+            (Ok(Value::Object(mut args)), Ok(value)) => {
+                args.insert(name, value);
+                Ok(Value::Object(args))
+            }
+            (Ok(_), Ok(_)) => Err(format!(
+                "named parameter ${} added to positional parameters",
+                name
+            )),
+            (Ok(_), Err(e)) => Err(format!("parameter ${} {}", name, e)),
+            (Err(e), _) => Err(e),
+        };
+        self
+    }
+}
+
+impl Serialize for QueryArgs {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.args {
+            Ok(args) => args.serialize(serializer),
+            Err(e) => Err(S::Error::custom(e)),
+        }
+    }
+}
+
+fn encode<T: Serialize>(value: T) -> Result<Value, String> {
+    serde_json::to_value(value).map_err(|e| format!("could not be encoded: {}", e))
+}
+
+/// Builds the parameters of a query or analytics statement.
+///
+/// `name => value` pairs build named parameters, a list of values positional ones. Values
+/// only have to implement `Serialize` and are taken by reference.
+///
+/// ```no_run
+/// # use couchbase::*;
+/// let options = QueryOptions::default().named_parameters(query_args! {
+///     "country" => "France",
+///     "limit" => 10,
+/// });
+/// let options = QueryOptions::default().positional_parameters(query_args!["France", 10]);
+/// ```
+#[macro_export]
+macro_rules! query_args {
+    ($($name:expr => $value:expr),+ $(,)?) => {
+        $crate::QueryArgs::named()$(.insert($name, &$value))+
+    };
+    ($($value:expr),+ $(,)?) => {
+        $crate::QueryArgs::positional()$(.push(&$value))+
+    };
+}
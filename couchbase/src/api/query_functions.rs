@@ -0,0 +1,526 @@
+use crate::io::request::*;
+use crate::io::Core;
+use crate::{
+    CouchbaseError, CouchbaseResult, CreateQueryFunctionOptions, DropQueryFunctionOptions,
+    DropQueryLibraryOptions, ErrorContext, GenericManagementResult, GetAllQueryFunctionsOptions,
+    GetAllQueryLibrariesOptions, QueryOptions, ServiceType, UpsertQueryLibraryOptions,
+};
+use futures::channel::oneshot;
+use futures::StreamExt;
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The code the query service fails with if the function does not exist.
+const FUNCTION_NOT_FOUND: u64 = 10101;
+
+/// What a user-defined function runs when it is called.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryFunctionBody {
+    /// An N1QL expression over the parameters.
+    Inline { expression: String },
+    /// A function exported by a JavaScript library, see `QueryFunctionManager::upsert_library`.
+    JavaScript { library: String, object: String },
+}
+
+/// A user-defined function of the query service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryFunction {
+    name: String,
+    parameters: Vec<String>,
+    body: QueryFunctionBody,
+}
+
+impl QueryFunction {
+    /// A function which evaluates an N1QL expression, i.e. `(celsius * 9 / 5) + 32`.
+    ///
+    /// Use `...` as the only parameter to accept any number of arguments, available as `args`
+    /// in the expression.
+    pub fn inline<S: Into<String>, E: Into<String>>(
+        name: S,
+        parameters: Vec<String>,
+        expression: E,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            parameters,
+            body: QueryFunctionBody::Inline {
+                expression: expression.into(),
+            },
+        }
+    }
+
+    /// A function which calls `object` of a JavaScript library.
+    ///
+    /// The libraries of a scope are referred to as `bucket/scope/library`.
+    pub fn javascript<S: Into<String>, L: Into<String>, O: Into<String>>(
+        name: S,
+        parameters: Vec<String>,
+        library: L,
+        object: O,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            parameters,
+            body: QueryFunctionBody::JavaScript {
+                library: library.into(),
+                object: object.into(),
+            },
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn parameters(&self) -> &[String] {
+        &self.parameters
+    }
+
+    pub fn body(&self) -> &QueryFunctionBody {
+        &self.body
+    }
+}
+
+/// A function as listed in `system:functions`.
+#[derive(Debug, Deserialize)]
+struct FunctionRow {
+    definition: FunctionDefinition,
+    identity: FunctionIdentity,
+}
+
+#[derive(Debug, Deserialize)]
+struct FunctionDefinition {
+    #[serde(rename = "#language")]
+    language: String,
+    #[serde(default)]
+    parameters: Vec<String>,
+    expression: Option<String>,
+    library: Option<String>,
+    object: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FunctionIdentity {
+    name: String,
+}
+
+impl From<FunctionRow> for QueryFunction {
+    fn from(row: FunctionRow) -> Self {
+        let definition = row.definition;
+        let body = match definition.language.as_str() {
+            "javascript" => QueryFunctionBody::JavaScript {
+                library: definition.library.unwrap_or_default(),
+                object: definition.object.unwrap_or_default(),
+            },
+            _ => QueryFunctionBody::Inline {
+                expression: definition.expression.unwrap_or_default(),
+            },
+        };
+        Self {
+            name: row.identity.name,
+            parameters: definition.parameters,
+            body,
+        }
+    }
+}
+
+/// A JavaScript library of the query service, whose functions are called by JavaScript
+/// user-defined functions.
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueryLibrary {
+    name: String,
+    #[serde(default)]
+    bucket: String,
+    #[serde(default)]
+    scope: String,
+    code: String,
+}
+
+impl QueryLibrary {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+}
+
+/// Manages the user-defined functions of the query service and the JavaScript libraries they
+/// call, either the global ones of the cluster or the ones of a single scope.
+///
+/// Functions are managed through N1QL statements, libraries through the
+/// `/evaluator/v1/libraries` endpoint of the query service, which needs Couchbase Server 7.1
+/// or later.
+pub struct QueryFunctionManager {
+    core: Arc<Core>,
+    scope: Option<(String, String)>,
+}
+
+impl QueryFunctionManager {
+    pub(crate) fn new(core: Arc<Core>) -> Self {
+        Self { core, scope: None }
+    }
+
+    #[cfg(feature = "volatile")]
+    pub(crate) fn for_scope(core: Arc<Core>, bucket_name: String, scope_name: String) -> Self {
+        Self {
+            core,
+            scope: Some((bucket_name, scope_name)),
+        }
+    }
+
+    pub async fn get_all_functions(
+        &self,
+        options: GetAllQueryFunctionsOptions,
+    ) -> CouchbaseResult<Vec<QueryFunction>> {
+        let (filter, params) = match &self.scope {
+            Some((bucket, scope)) => (
+                "f.identity.type = \"scope\" AND f.identity.`bucket` = $bucket \
+                 AND f.identity.`scope` = $scope",
+                json!({ "bucket": bucket, "scope": scope }),
+            ),
+            None => ("f.identity.type = \"global\"", json!({})),
+        };
+        let statement = format!(
+            "SELECT f.definition, f.identity FROM system:functions AS f WHERE {} \
+             ORDER BY f.identity.name",
+            filter
+        );
+
+        let mut result = self
+            .query(
+                statement,
+                QueryOptions {
+                    timeout: options.timeout,
+                    ..Default::default()
+                }
+                .named_parameters(params),
+            )
+            .await?;
+        let mut functions = vec![];
+        let mut rows = result.rows::<FunctionRow>();
+        while let Some(row) = rows.next().await {
+            functions.push(row?.into());
+        }
+        Ok(functions)
+    }
+
+    /// Creates the function, or replaces an existing one if `replace` is set on the options.
+    pub async fn create_function(
+        &self,
+        function: QueryFunction,
+        options: CreateQueryFunctionOptions,
+    ) -> CouchbaseResult<()> {
+        let statement = create_statement(&self.scope, &function, options.replace);
+        self.query(
+            statement,
+            QueryOptions {
+                timeout: options.timeout,
+                ..Default::default()
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Drops the function, failing with `QueryFunctionNotFound` if it does not exist.
+    pub async fn drop_function<S: Into<String>>(
+        &self,
+        name: S,
+        options: DropQueryFunctionOptions,
+    ) -> CouchbaseResult<()> {
+        let statement = format!("DROP FUNCTION {}", qualify(&self.scope, &name.into()));
+        self.query(
+            statement,
+            QueryOptions {
+                timeout: options.timeout,
+                ..Default::default()
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_all_libraries(
+        &self,
+        options: GetAllQueryLibrariesOptions,
+    ) -> CouchbaseResult<Vec<QueryLibrary>> {
+        let result = self
+            .libraries_request(
+                "/evaluator/v1/libraries".into(),
+                "get",
+                None,
+                None,
+                options.timeout,
+            )
+            .await?;
+        let libraries: Vec<QueryLibrary> = match result.payload() {
+            Some(payload) => serde_json::from_slice::<Option<Vec<QueryLibrary>>>(payload)
+                .map_err(|e| CouchbaseError::DecodingFailure {
+                    ctx: ErrorContext::default(),
+                    source: e.into(),
+                })?
+                .unwrap_or_default(),
+            None => vec![],
+        };
+        // The endpoint lists the libraries of all scopes
+        Ok(libraries
+            .into_iter()
+            .filter(|l| match &self.scope {
+                Some((bucket, scope)) => &l.bucket == bucket && &l.scope == scope,
+                None => l.bucket.is_empty(),
+            })
+            .collect())
+    }
+
+    /// Creates or replaces a JavaScript library with the given code.
+    pub async fn upsert_library<S: Into<String>, C: Into<String>>(
+        &self,
+        name: S,
+        code: C,
+        options: UpsertQueryLibraryOptions,
+    ) -> CouchbaseResult<()> {
+        let path = library_path(&self.scope, &name.into());
+        self.libraries_request(path, "post", Some(code.into()), None, options.timeout)
+            .await?;
+        Ok(())
+    }
+
+    /// Drops the library, failing with `QueryLibraryNotFound` if it does not exist.
+    pub async fn drop_library<S: Into<String>>(
+        &self,
+        name: S,
+        options: DropQueryLibraryOptions,
+    ) -> CouchbaseResult<()> {
+        let name = name.into();
+        let path = library_path(&self.scope, &name);
+        self.libraries_request(path, "delete", None, Some(&name), options.timeout)
+            .await?;
+        Ok(())
+    }
+
+    /// Sends a request to the libraries endpoint.
+    ///
+    /// A 404 means the library does not exist if the request is about an existing `library`,
+    /// otherwise that the endpoint does not, which is the case before Couchbase Server 7.1.
+    async fn libraries_request(
+        &self,
+        path: String,
+        method: &str,
+        payload: Option<String>,
+        library: Option<&str>,
+        timeout: Option<Duration>,
+    ) -> CouchbaseResult<GenericManagementResult> {
+        let (sender, receiver) = oneshot::channel();
+        let content_type = payload.as_ref().map(|_| String::from("application/json"));
+        let timer = self.core.send(Request::GenericManagementRequest(
+            GenericManagementRequest {
+                sender,
+                path,
+                method: String::from(method),
+                payload,
+                content_type,
+                timeout,
+                service_type: Some(ServiceType::Query),
+                endpoint: None,
+            },
+        ));
+
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
+        if result.http_status() == 200 {
+            return Ok(result);
+        }
+        Err(libraries_error(&result, library))
+    }
+
+    async fn query(
+        &self,
+        statement: String,
+        options: QueryOptions,
+    ) -> CouchbaseResult<crate::QueryResult> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::Query(QueryRequest {
+            statement,
+            options,
+            sender,
+            scope: None,
+        }));
+        timer
+            .finish(receiver.await.unwrap())
+            .map_err(function_error)
+    }
+}
+
+fn escape(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+/// The name of the function, qualified with its scope for the manager of a scope.
+fn qualify(scope: &Option<(String, String)>, name: &str) -> String {
+    match scope {
+        Some((bucket, scope)) => format!(
+            "default:{}.{}.{}",
+            escape(bucket),
+            escape(scope),
+            escape(name)
+        ),
+        None => escape(name),
+    }
+}
+
+fn create_statement(
+    scope: &Option<(String, String)>,
+    function: &QueryFunction,
+    replace: bool,
+) -> String {
+    let parameters: Vec<String> = function
+        .parameters
+        .iter()
+        .map(|p| if p == "..." { p.clone() } else { escape(p) })
+        .collect();
+    let head = format!(
+        "CREATE {}FUNCTION {}({})",
+        if replace { "OR REPLACE " } else { "" },
+        qualify(scope, &function.name),
+        parameters.join(", ")
+    );
+    match &function.body {
+        QueryFunctionBody::Inline { expression } => format!("{} {{ {} }}", head, expression),
+        QueryFunctionBody::JavaScript { library, object } => format!(
+            "{} LANGUAGE JAVASCRIPT AS {} AT {}",
+            head,
+            Value::from(object.as_str()),
+            Value::from(library.as_str())
+        ),
+    }
+}
+
+fn library_path(scope: &Option<(String, String)>, name: &str) -> String {
+    // Encodes the name as the value of an empty key, the `=` in front is dropped below
+    let name = serde_urlencoded::to_string([("", name)]).unwrap();
+    let mut path = format!("/evaluator/v1/libraries/{}", &name[1..]);
+    if let Some((bucket, scope)) = scope {
+        path.push('?');
+        path.push_str(
+            &serde_urlencoded::to_string([("bucket", bucket), ("scope", scope)]).unwrap(),
+        );
+    }
+    path
+}
+
+fn libraries_error(result: &GenericManagementResult, library: Option<&str>) -> CouchbaseError {
+    let message = match result.payload() {
+        Some(p) => String::from_utf8_lossy(p).into_owned(),
+        None => String::new(),
+    };
+    let mut ctx = ErrorContext::default();
+    match (result.http_status(), library) {
+        (404, Some(library)) => {
+            ctx.insert("library", Value::String(library.into()));
+            ctx.insert("message", Value::String(message));
+            CouchbaseError::QueryLibraryNotFound { ctx }
+        }
+        (404, None) => {
+            ctx.insert("message", Value::String(message));
+            CouchbaseError::FeatureNotAvailable { ctx }
+        }
+        (status, _) => CouchbaseError::GenericHTTP {
+            ctx,
+            status,
+            message,
+        },
+    }
+}
+
+/// Libcouchbase reports all query errors from 10000 to 10999 as authentication failures,
+/// including the one of a missing function.
+fn function_error(e: CouchbaseError) -> CouchbaseError {
+    match e {
+        CouchbaseError::AuthenticationFailure { ctx }
+            if ctx.get("first_error_code").and_then(Value::as_u64) == Some(FUNCTION_NOT_FOUND) =>
+        {
+            CouchbaseError::QueryFunctionNotFound { ctx }
+        }
+        e => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope() -> Option<(String, String)> {
+        Some(("travel-sample".into(), "in`ventory".into()))
+    }
+
+    #[test]
+    fn qualifies_function_names() {
+        assert_eq!(qualify(&None, "celsius"), "`celsius`");
+        assert_eq!(
+            qualify(&scope(), "to`celsius"),
+            "default:`travel-sample`.`in``ventory`.`to``celsius`"
+        );
+    }
+
+    #[test]
+    fn builds_create_statements() {
+        let inline =
+            QueryFunction::inline("celsius", vec!["degrees".into()], "(degrees - 32) * 5 / 9");
+        assert_eq!(
+            create_statement(&None, &inline, false),
+            "CREATE FUNCTION `celsius`(`degrees`) { (degrees - 32) * 5 / 9 }"
+        );
+
+        let javascript = QueryFunction::javascript("sum", vec!["...".into()], "math", "add\"All");
+        assert_eq!(
+            create_statement(&scope(), &javascript, true),
+            "CREATE OR REPLACE FUNCTION default:`travel-sample`.`in``ventory`.`sum`(...) \
+             LANGUAGE JAVASCRIPT AS \"add\\\"All\" AT \"math\""
+        );
+    }
+
+    #[test]
+    fn encodes_library_paths() {
+        assert_eq!(
+            library_path(&None, "my lib/v1"),
+            "/evaluator/v1/libraries/my+lib%2Fv1"
+        );
+        assert_eq!(
+            library_path(&scope(), "math"),
+            "/evaluator/v1/libraries/math?bucket=travel-sample&scope=in%60ventory"
+        );
+    }
+
+    #[test]
+    fn maps_missing_libraries_and_functions() {
+        let missing = GenericManagementResult::new(404, Some(b"Library not found".to_vec()));
+        assert!(matches!(
+            libraries_error(&missing, Some("math")),
+            CouchbaseError::QueryLibraryNotFound { .. }
+        ));
+        assert!(matches!(
+            libraries_error(&missing, None),
+            CouchbaseError::FeatureNotAvailable { .. }
+        ));
+        match libraries_error(&GenericManagementResult::new(500, None), Some("math")) {
+            CouchbaseError::GenericHTTP { status, .. } => assert_eq!(status, 500),
+            e => panic!("unexpected {:?}", e),
+        }
+
+        let mut ctx = ErrorContext::default();
+        ctx.insert("first_error_code", Value::from(FUNCTION_NOT_FOUND));
+        assert!(matches!(
+            function_error(CouchbaseError::AuthenticationFailure { ctx }),
+            CouchbaseError::QueryFunctionNotFound { .. }
+        ));
+        let mut ctx = ErrorContext::default();
+        ctx.insert("first_error_code", Value::from(10000));
+        assert!(matches!(
+            function_error(CouchbaseError::AuthenticationFailure { ctx }),
+            CouchbaseError::AuthenticationFailure { .. }
+        ));
+    }
+}
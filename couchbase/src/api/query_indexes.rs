@@ -0,0 +1,256 @@
+use crate::io::request::*;
+use crate::io::Core;
+use crate::{
+    BuildDeferredQueryIndexesOptions, CouchbaseError, CouchbaseResult, ErrorContext,
+    GetAllQueryIndexesOptions, QueryOptions, WatchQueryIndexesOptions,
+};
+use futures::channel::oneshot;
+use futures::StreamExt;
+use futures_timer::Delay;
+use serde_derive::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long to wait between two polls while watching indexes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(50);
+const MAX_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Deserialize)]
+pub struct QueryIndex {
+    name: String,
+    #[serde(default)]
+    is_primary: bool,
+    state: String,
+    keyspace_id: String,
+    bucket_id: Option<String>,
+    scope_id: Option<String>,
+    #[serde(default)]
+    index_key: Vec<String>,
+    condition: Option<String>,
+}
+
+impl QueryIndex {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_primary(&self) -> bool {
+        self.is_primary
+    }
+
+    /// The state of the index, like `online`, `deferred` or `building`.
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+
+    pub fn bucket_name(&self) -> &str {
+        self.bucket_id.as_deref().unwrap_or(&self.keyspace_id)
+    }
+
+    pub fn scope_name(&self) -> Option<&str> {
+        self.scope_id.as_deref()
+    }
+
+    /// The collection the index is built on, `None` for indexes on the bucket itself.
+    pub fn collection_name(&self) -> Option<&str> {
+        self.bucket_id.as_ref().map(|_| self.keyspace_id.as_str())
+    }
+
+    pub fn index_key(&self) -> &[String] {
+        self.index_key.as_slice()
+    }
+
+    pub fn condition(&self) -> Option<&str> {
+        self.condition.as_deref()
+    }
+}
+
+/// Manages the GSI indexes of the query service.
+///
+/// Every method works on the indexes of a bucket, or of a collection if the scope and
+/// collection name are set on its options.
+pub struct QueryIndexManager {
+    core: Arc<Core>,
+}
+
+impl QueryIndexManager {
+    pub(crate) fn new(core: Arc<Core>) -> Self {
+        Self { core }
+    }
+
+    pub async fn get_all_indexes<S: Into<String>>(
+        &self,
+        bucket_name: S,
+        options: GetAllQueryIndexesOptions,
+    ) -> CouchbaseResult<Vec<QueryIndex>> {
+        let bucket_name = bucket_name.into();
+        let (filter, params) = match (options.scope_name, options.collection_name) {
+            (Some(scope), Some(collection)) => (
+                "bucket_id = $bucket AND scope_id = $scope AND keyspace_id = $collection",
+                json!({ "bucket": bucket_name, "scope": scope, "collection": collection }),
+            ),
+            (Some(scope), None) => (
+                "bucket_id = $bucket AND scope_id = $scope",
+                json!({ "bucket": bucket_name, "scope": scope }),
+            ),
+            _ => (
+                "((bucket_id IS MISSING AND keyspace_id = $bucket) OR bucket_id = $bucket)",
+                json!({ "bucket": bucket_name }),
+            ),
+        };
+        let statement = format!(
+            "SELECT idx.* FROM system:indexes AS idx WHERE {} AND `using` = \"gsi\" \
+             ORDER BY is_primary DESC, name ASC",
+            filter
+        );
+
+        let mut result = self
+            .query(
+                statement,
+                QueryOptions {
+                    timeout: options.timeout,
+                    ..Default::default()
+                }
+                .named_parameters(params),
+            )
+            .await?;
+        let mut indexes = vec![];
+        let mut rows = result.rows::<QueryIndex>();
+        while let Some(index) = rows.next().await {
+            indexes.push(index?);
+        }
+        Ok(indexes)
+    }
+
+    /// Builds all indexes which have been created with the `defer_build` option.
+    ///
+    /// Returns the names of the indexes whose build has been started. The build runs in the
+    /// background, use `watch_indexes` to wait for the indexes to come online.
+    pub async fn build_deferred_indexes<S: Into<String>>(
+        &self,
+        bucket_name: S,
+        options: BuildDeferredQueryIndexesOptions,
+    ) -> CouchbaseResult<Vec<String>> {
+        let deferred = self
+            .get_all_indexes(
+                bucket_name,
+                GetAllQueryIndexesOptions {
+                    timeout: options.timeout,
+                    scope_name: options.scope_name,
+                    collection_name: options.collection_name,
+                },
+            )
+            .await?
+            .into_iter()
+            .filter(|i| i.state == "deferred");
+
+        // A single statement can only build the indexes of one keyspace
+        let mut keyspaces: Vec<(String, Vec<String>)> = vec![];
+        for index in deferred {
+            let keyspace = keyspace(
+                index.bucket_name(),
+                index.scope_name(),
+                index.collection_name(),
+            );
+            match keyspaces.iter_mut().find(|(k, _)| *k == keyspace) {
+                Some((_, names)) => names.push(index.name),
+                None => keyspaces.push((keyspace, vec![index.name])),
+            }
+        }
+
+        let mut built = vec![];
+        for (keyspace, names) in keyspaces {
+            let escaped: Vec<String> = names.iter().map(|n| escape(n)).collect();
+            let statement = format!("BUILD INDEX ON {}({})", keyspace, escaped.join(","));
+            self.query(
+                statement,
+                QueryOptions {
+                    timeout: options.timeout,
+                    ..Default::default()
+                },
+            )
+            .await?;
+            built.extend(names);
+        }
+        Ok(built)
+    }
+
+    /// Polls the indexes until all of them are online, or fails with `Timeout` once the given
+    /// timeout has passed.
+    pub async fn watch_indexes<S: Into<String>>(
+        &self,
+        bucket_name: S,
+        index_names: Vec<String>,
+        timeout: Duration,
+        options: WatchQueryIndexesOptions,
+    ) -> CouchbaseResult<()> {
+        let bucket_name = bucket_name.into();
+        let deadline = Instant::now() + timeout;
+        let mut interval = WATCH_INTERVAL;
+        loop {
+            let indexes = self
+                .get_all_indexes(
+                    bucket_name.clone(),
+                    GetAllQueryIndexesOptions {
+                        timeout: Some(deadline.saturating_duration_since(Instant::now())),
+                        scope_name: options.scope_name.clone(),
+                        collection_name: options.collection_name.clone(),
+                    },
+                )
+                .await?;
+            let pending: Vec<&String> = index_names
+                .iter()
+                .filter(|name| {
+                    !indexes
+                        .iter()
+                        .any(|i| &i.name == *name && i.state == "online")
+                })
+                .collect();
+            let primary_pending = options.watch_primary.unwrap_or(false)
+                && !indexes.iter().any(|i| i.is_primary && i.state == "online");
+            if pending.is_empty() && !primary_pending {
+                return Ok(());
+            }
+
+            let now = Instant::now();
+            if now + interval >= deadline {
+                let mut ctx = ErrorContext::default();
+                ctx.insert("pending", json!(pending));
+                return Err(CouchbaseError::Timeout {
+                    ambiguous: false,
+                    ctx,
+                });
+            }
+            Delay::new(interval).await;
+            interval = (interval * 2).min(MAX_WATCH_INTERVAL);
+        }
+    }
+
+    async fn query(
+        &self,
+        statement: String,
+        options: QueryOptions,
+    ) -> CouchbaseResult<crate::QueryResult> {
+        let (sender, receiver) = oneshot::channel();
+        let timer = self.core.send(Request::Query(QueryRequest {
+            statement,
+            options,
+            sender,
+            scope: None,
+        }));
+        timer.finish(receiver.await.unwrap())
+    }
+}
+
+fn escape(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+/// The keyspace of the bucket, or of the collection if scope and collection are given.
+fn keyspace(bucket: &str, scope: Option<&str>, collection: Option<&str>) -> String {
+    match (scope, collection) {
+        (Some(s), Some(c)) => format!("{}.{}.{}", escape(bucket), escape(s), escape(c)),
+        _ => escape(bucket),
+    }
+}
@@ -0,0 +1,84 @@
+use crate::api::error::CouchbaseResult;
+use crate::api::options::QueryOptions;
+use crate::api::query_transaction::execute;
+use crate::api::results::QueryResult;
+use crate::io::Core;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Sends a sequence of N1QL queries to the same query node.
+///
+/// The first query of the session picks a query node like any other query (or the one set
+/// through `QueryOptions::endpoint`), all following queries are sent to the node which served
+/// it. This keeps flows whose state lives on a single query node together, like a statement
+/// prepared with `PREPARE` on clusters without enhanced prepared statements, which can then be
+/// run with `EXECUTE` through the same session.
+///
+/// Like queries pinned through `QueryOptions::endpoint`, the rows are only streamed once the
+/// full response has been received.
+///
+/// ```no_run
+/// # use couchbase::*;
+/// # async fn run(cluster: Cluster) -> CouchbaseResult<()> {
+/// let session = cluster.query_session();
+/// session
+///     .query("PREPARE airports FROM SELECT * FROM `travel-sample` WHERE type = 'airport'", QueryOptions::default())
+///     .await?;
+/// let mut result = session.query("EXECUTE airports", QueryOptions::default()).await?;
+/// println!("served by {:?}", result.meta_data().await?.endpoint());
+/// # Ok(())
+/// # }
+/// ```
+pub struct QuerySession {
+    core: Arc<Core>,
+    query_context: Option<String>,
+    endpoint: Mutex<Option<String>>,
+}
+
+impl fmt::Debug for QuerySession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QuerySession")
+            .field("query_context", &self.query_context)
+            .field("endpoint", &self.endpoint())
+            .finish()
+    }
+}
+
+impl QuerySession {
+    pub(crate) fn new(core: Arc<Core>, query_context: Option<String>) -> Self {
+        Self {
+            core,
+            query_context,
+            endpoint: Mutex::new(None),
+        }
+    }
+
+    /// The query node (`host:port`) the session is pinned to, once its first query completed.
+    pub fn endpoint(&self) -> Option<String> {
+        self.endpoint.lock().unwrap().clone()
+    }
+
+    /// Executes a statement on the query node of the session.
+    pub async fn query<S: Into<String>>(
+        &self,
+        statement: S,
+        mut options: QueryOptions,
+    ) -> CouchbaseResult<QueryResult> {
+        if options.query_context.is_none() {
+            options.query_context = self.query_context.clone();
+        }
+        let endpoint = self.endpoint().or_else(|| options.endpoint.take());
+        let (result, served_by) = execute(&self.core, statement, options, endpoint).await?;
+        let mut pinned = self.endpoint.lock().unwrap();
+        if pinned.is_none() {
+            *pinned = served_by;
+        }
+        Ok(result)
+    }
+}
+
+/// The query context of statements run against a scope.
+#[cfg(feature = "volatile")]
+pub(crate) fn query_context(bucket: &str, scope: &str) -> String {
+    format!("default:`{}`.`{}`", bucket, scope)
+}
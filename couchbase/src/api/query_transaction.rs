@@ -0,0 +1,240 @@
+use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::options::{check_parameters, BeginQueryTransactionOptions, QueryOptions};
+use crate::api::results::{GenericManagementResult, QueryResult, ServiceType};
+use crate::io::request::{GenericManagementRequest, Request};
+use crate::io::Core;
+use futures::channel::oneshot;
+use serde_derive::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The error codes the query service reports for transaction failures.
+pub(crate) const TRANSACTION_ERROR_CODES: std::ops::Range<u64> = 17000..18000;
+
+/// A N1QL transaction, whose statements are committed or rolled back together.
+///
+/// Query nodes do not share transaction state, so all statements of the transaction are sent
+/// to the query node which started it. For a transaction made up of a single statement, use
+/// `QueryOptions::tx_implicit` instead.
+///
+/// ```no_run
+/// # use couchbase::*;
+/// # async fn run(cluster: Cluster) -> CouchbaseResult<()> {
+/// let transaction = cluster
+///     .begin_query_transaction(BeginQueryTransactionOptions::default())
+///     .await?;
+/// transaction
+///     .query("UPDATE `bank` SET balance = balance - 10 WHERE META().id = 'alice'", QueryOptions::default())
+///     .await?;
+/// transaction
+///     .query("UPDATE `bank` SET balance = balance + 10 WHERE META().id = 'bob'", QueryOptions::default())
+///     .await?;
+/// transaction.commit().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct QueryTransaction {
+    core: Arc<Core>,
+    tx_id: String,
+    endpoint: String,
+    timeout: Option<Duration>,
+}
+
+impl fmt::Debug for QueryTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueryTransaction")
+            .field("tx_id", &self.tx_id)
+            .field("endpoint", &self.endpoint)
+            .finish()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BeginWorkRow {
+    txid: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryErrorResponse {
+    #[serde(default)]
+    errors: Vec<QueryError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryError {
+    code: u64,
+    #[serde(default)]
+    msg: String,
+}
+
+impl QueryTransaction {
+    pub(crate) async fn begin(
+        core: Arc<Core>,
+        options: BeginQueryTransactionOptions,
+    ) -> CouchbaseResult<Self> {
+        let query_options = QueryOptions {
+            timeout: options.timeout,
+            tx_timeout: options.tx_timeout,
+            durability_level: options.durability_level,
+            ..Default::default()
+        };
+        let (mut result, endpoint) = execute(&core, "BEGIN WORK", query_options, None).await?;
+        let row: BeginWorkRow = result.single_row(String::from("BEGIN WORK")).await?;
+        let endpoint = match endpoint {
+            Some(e) => e,
+            None => {
+                let mut ctx = ErrorContext::default();
+                ctx.insert("statement", Value::String("BEGIN WORK".into()));
+                ctx.insert("msg", Value::String("the query node is unknown".into()));
+                return Err(CouchbaseError::QueryTransactionFailure { ctx });
+            }
+        };
+
+        Ok(Self {
+            core,
+            tx_id: row.txid,
+            endpoint,
+            timeout: options.timeout,
+        })
+    }
+
+    /// The id the query service assigned to the transaction.
+    pub fn tx_id(&self) -> &str {
+        &self.tx_id
+    }
+
+    /// The query node (`host:port`) all statements of the transaction are sent to.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Executes a statement as part of the transaction.
+    ///
+    /// The rows are only streamed once the full response has been received.
+    pub async fn query<S: Into<String>>(
+        &self,
+        statement: S,
+        mut options: QueryOptions,
+    ) -> CouchbaseResult<QueryResult> {
+        options.tx_id = Some(self.tx_id.clone());
+        let (result, _) =
+            execute(&self.core, statement, options, Some(self.endpoint.clone())).await?;
+        Ok(result)
+    }
+
+    /// Commits all statements of the transaction.
+    pub async fn commit(self) -> CouchbaseResult<()> {
+        self.finish("COMMIT WORK").await
+    }
+
+    /// Rolls back all statements of the transaction.
+    pub async fn rollback(self) -> CouchbaseResult<()> {
+        self.finish("ROLLBACK WORK").await
+    }
+
+    async fn finish(self, statement: &str) -> CouchbaseResult<()> {
+        let options = QueryOptions {
+            timeout: self.timeout,
+            ..Default::default()
+        };
+        self.query(statement, options).await.map(|_| ())
+    }
+}
+
+/// Sends the statement to the query service through the HTTP interface, optionally pinned to a
+/// single query node, and returns the result with the node which served it.
+pub(crate) async fn execute<S: Into<String>>(
+    core: &Core,
+    statement: S,
+    mut options: QueryOptions,
+    endpoint: Option<String>,
+) -> CouchbaseResult<(QueryResult, Option<String>)> {
+    let statement = statement.into();
+    if options.adhoc == Some(false) {
+        let mut ctx = ErrorContext::default();
+        ctx.insert("statement", Value::String(statement));
+        ctx.insert(
+            "reason",
+            Value::String("pinned queries cannot be prepared, use PREPARE and EXECUTE".into()),
+        );
+        return Err(CouchbaseError::InvalidArgument { ctx });
+    }
+    check_parameters(&options.parameters_error)?;
+    options.statement = Some(statement.clone());
+    let payload = serde_json::to_string(&options).map_err(|e| CouchbaseError::EncodingFailure {
+        ctx: ErrorContext::default(),
+        source: e.into(),
+    })?;
+
+    let (sender, receiver) = oneshot::channel();
+    let timer = core.send(Request::GenericManagementRequest(
+        GenericManagementRequest {
+            sender,
+            path: String::from("/query/service"),
+            method: String::from("post"),
+            payload: Some(payload),
+            content_type: Some(String::from("application/json")),
+            timeout: options.timeout,
+            service_type: Some(ServiceType::Query),
+            endpoint,
+        },
+    ));
+    let result = timer.finish(receiver.await.unwrap())?;
+
+    if let Some(error) = parse_error(&result, statement) {
+        return Err(error);
+    }
+    let endpoint = result.endpoint().map(String::from);
+    let payload = result.payload().map(Vec::as_slice).unwrap_or_default();
+    Ok((
+        QueryResult::from_payload(payload, endpoint.clone())?,
+        endpoint,
+    ))
+}
+
+/// Returns the error the query service reported, if any.
+///
+/// Transaction failures are reported as `QueryTransactionFailure`.
+fn parse_error(result: &GenericManagementResult, statement: String) -> Option<CouchbaseError> {
+    let errors = result
+        .payload()
+        .and_then(|p| serde_json::from_slice::<QueryErrorResponse>(p).ok())
+        .map(|r| r.errors)
+        .unwrap_or_default();
+    if result.http_status() == 200 && errors.is_empty() {
+        return None;
+    }
+
+    let mut ctx = ErrorContext::default();
+    ctx.insert("statement", Value::String(statement));
+    if let Some(endpoint) = result.endpoint() {
+        ctx.insert("remote", Value::String(endpoint.into()));
+    }
+    let first = match errors.into_iter().next() {
+        Some(first) => first,
+        None => {
+            let message = result
+                .payload()
+                .map(|p| String::from_utf8_lossy(p).into_owned())
+                .unwrap_or_default();
+            return Some(CouchbaseError::GenericHTTP {
+                ctx,
+                status: result.http_status(),
+                message,
+            });
+        }
+    };
+    ctx.insert("first_error_code", Value::from(first.code));
+    ctx.insert("first_error_message", Value::String(first.msg.clone()));
+
+    if TRANSACTION_ERROR_CODES.contains(&first.code) {
+        return Some(CouchbaseError::QueryTransactionFailure { ctx });
+    }
+    Some(CouchbaseError::GenericHTTP {
+        ctx,
+        status: result.http_status(),
+        message: first.msg,
+    })
+}
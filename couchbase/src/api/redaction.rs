@@ -0,0 +1,185 @@
+use std::fmt::{Debug, Display, Formatter, Result};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Which data is tagged in the log messages of the SDK, so that it can be redacted before the
+/// logs are shared, i.e. with Couchbase support.
+///
+/// Tagged data is wrapped in `<ud>` (user data, like document ids and statements), `<md>` (meta
+/// data, like bucket names) or `<sd>` (system data, like hostnames) tags, which the tools of
+/// Couchbase strip or hash. Libcouchbase tags all three kinds in its own messages at both
+/// `Partial` and `Full`.
+///
+/// The contexts of errors always tag their user data.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RedactionLevel {
+    /// Nothing is tagged.
+    None,
+    /// Only user data is tagged.
+    Partial,
+    /// User, meta and system data are tagged.
+    Full,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(RedactionLevel::None as u8);
+
+/// Raises the redaction level of the process.
+///
+/// Logging is global to the process, so with several clusters the highest level any of them
+/// asked for applies to all.
+pub(crate) fn raise_level(level: RedactionLevel) {
+    raise(&LEVEL, level);
+}
+
+fn level() -> RedactionLevel {
+    load(&LEVEL)
+}
+
+fn raise(current: &AtomicU8, level: RedactionLevel) {
+    current.fetch_max(level as u8, Ordering::Relaxed);
+}
+
+fn load(current: &AtomicU8) -> RedactionLevel {
+    match current.load(Ordering::Relaxed) {
+        0 => RedactionLevel::None,
+        1 => RedactionLevel::Partial,
+        _ => RedactionLevel::Full,
+    }
+}
+
+/// Defines a wrapper which tags its content in log messages from the given level on.
+macro_rules! tagged {
+    ($name:ident, $tag:literal, $from:expr) => {
+        pub(crate) struct $name<T>(pub T);
+
+        impl<T> $name<T> {
+            fn display_at(&self, level: RedactionLevel, f: &mut Formatter<'_>) -> Result
+            where
+                T: Display,
+            {
+                if level >= $from {
+                    write!(f, concat!("<", $tag, ">{}</", $tag, ">"), self.0)
+                } else {
+                    self.0.fmt(f)
+                }
+            }
+
+            fn debug_at(&self, level: RedactionLevel, f: &mut Formatter<'_>) -> Result
+            where
+                T: Debug,
+            {
+                if level >= $from {
+                    write!(f, concat!("<", $tag, ">{:?}</", $tag, ">"), self.0)
+                } else {
+                    self.0.fmt(f)
+                }
+            }
+        }
+
+        impl<T: Display> Display for $name<T> {
+            fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+                self.display_at(level(), f)
+            }
+        }
+
+        impl<T: Debug> Debug for $name<T> {
+            fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+                self.debug_at(level(), f)
+            }
+        }
+    };
+}
+
+tagged!(UserData, "ud", RedactionLevel::Partial);
+tagged!(MetaData, "md", RedactionLevel::Full);
+tagged!(SystemData, "sd", RedactionLevel::Full);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Formats through the given function, to render a wrapper at any level
+    struct Render<F>(F);
+
+    impl<F: Fn(&mut Formatter<'_>) -> Result> Display for Render<F> {
+        fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+            (self.0)(f)
+        }
+    }
+
+    fn render(format: impl Fn(&mut Formatter<'_>) -> Result) -> String {
+        Render(format).to_string()
+    }
+
+    #[test]
+    fn tags_user_data_from_partial() {
+        let data = UserData("doc");
+        assert_eq!(render(|f| data.display_at(RedactionLevel::None, f)), "doc");
+        assert_eq!(
+            render(|f| data.display_at(RedactionLevel::Partial, f)),
+            "<ud>doc</ud>"
+        );
+        assert_eq!(
+            render(|f| data.display_at(RedactionLevel::Full, f)),
+            "<ud>doc</ud>"
+        );
+        assert_eq!(
+            render(|f| data.debug_at(RedactionLevel::Partial, f)),
+            "<ud>\"doc\"</ud>"
+        );
+    }
+
+    #[test]
+    fn tags_meta_data_at_full() {
+        let data = MetaData("bucket");
+        assert_eq!(
+            render(|f| data.display_at(RedactionLevel::None, f)),
+            "bucket"
+        );
+        assert_eq!(
+            render(|f| data.display_at(RedactionLevel::Partial, f)),
+            "bucket"
+        );
+        assert_eq!(
+            render(|f| data.display_at(RedactionLevel::Full, f)),
+            "<md>bucket</md>"
+        );
+        assert_eq!(
+            render(|f| data.debug_at(RedactionLevel::Full, f)),
+            "<md>\"bucket\"</md>"
+        );
+    }
+
+    #[test]
+    fn tags_system_data_at_full() {
+        let data = SystemData("10.0.0.1:11210");
+        assert_eq!(
+            render(|f| data.display_at(RedactionLevel::None, f)),
+            "10.0.0.1:11210"
+        );
+        assert_eq!(
+            render(|f| data.display_at(RedactionLevel::Partial, f)),
+            "10.0.0.1:11210"
+        );
+        assert_eq!(
+            render(|f| data.display_at(RedactionLevel::Full, f)),
+            "<sd>10.0.0.1:11210</sd>"
+        );
+        assert_eq!(
+            render(|f| data.debug_at(RedactionLevel::None, f)),
+            "\"10.0.0.1:11210\""
+        );
+    }
+
+    #[test]
+    fn raising_the_level_never_lowers_it() {
+        let current = AtomicU8::new(RedactionLevel::None as u8);
+        raise(&current, RedactionLevel::Partial);
+        assert_eq!(load(&current), RedactionLevel::Partial);
+        raise(&current, RedactionLevel::Full);
+        assert_eq!(load(&current), RedactionLevel::Full);
+        raise(&current, RedactionLevel::Partial);
+        assert_eq!(load(&current), RedactionLevel::Full);
+        raise(&current, RedactionLevel::None);
+        assert_eq!(load(&current), RedactionLevel::Full);
+    }
+}
@@ -1,18 +1,99 @@
+use crate::api::deferred::AnalyticsHandle;
 use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::transcoding::{verify_json_flags, TaggedTranscoder, Transcoder};
 use crate::api::MutationToken;
 use futures::channel::mpsc::UnboundedReceiver;
-use futures::channel::oneshot::Receiver;
+use futures::channel::oneshot::{Canceled, Receiver};
+use futures::stream::BoxStream;
 use futures::{Stream, StreamExt};
 use serde::de::DeserializeOwned;
 use serde_derive::Deserialize;
+use serde_json::Value;
 use std::collections::HashMap;
 use std::fmt;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// The meta data of a streaming request is not sent if the request is cancelled or fails
+/// once its rows are streaming.
+fn received_meta<T>(meta: Result<T, Canceled>) -> CouchbaseResult<T> {
+    meta.map_err(|_| {
+        let mut ctx = ErrorContext::default();
+        ctx.insert(
+            "msg",
+            Value::String("the request ended without meta data".into()),
+        );
+        CouchbaseError::RequestCanceled { ctx }
+    })
+}
+
+/// Tracks the size of the rows which have been received, but not consumed yet.
+#[derive(Debug)]
+pub(crate) struct RowBuffer {
+    bytes: AtomicUsize,
+    limit: usize,
+    overflowed: AtomicBool,
+}
+
+impl RowBuffer {
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            bytes: AtomicUsize::new(0),
+            limit: limit.unwrap_or(usize::MAX),
+            overflowed: AtomicBool::new(false),
+        }
+    }
+
+    /// Adds a received row, returns false if the buffer is over its limit.
+    pub fn push(&self, len: usize) -> bool {
+        let bytes = self
+            .bytes
+            .fetch_add(len, Ordering::SeqCst)
+            .saturating_add(len);
+        if bytes > self.limit {
+            self.overflowed.store(true, Ordering::SeqCst);
+            return false;
+        }
+        true
+    }
+
+    pub fn pop(&self, len: usize) {
+        self.bytes.fetch_sub(len, Ordering::SeqCst);
+    }
+
+    pub fn overflowed(&self) -> bool {
+        self.overflowed.load(Ordering::SeqCst)
+    }
+}
 
+/// Decodes a row of a query, analytics or search result, failing with the row and its index.
+fn decode_row<T: DeserializeOwned>(index: usize, row: Vec<u8>) -> CouchbaseResult<T> {
+    serde_json::from_slice(&row).map_err(|e| {
+        let mut ctx = ErrorContext::default();
+        ctx.insert(
+            "row",
+            Value::String(String::from_utf8_lossy(&row).into_owned()),
+        );
+        CouchbaseError::RowDecodingFailure {
+            ctx,
+            index,
+            row,
+            source: e.into(),
+        }
+    })
+}
+
+/// The result of a N1QL query.
+///
+/// Rows are handed out as soon as they have been received from the query service, so the
+/// full result set is never held in memory unless the rows are consumed slower than they
+/// arrive. Use `QueryOptions::max_buffered_bytes` to bound the memory used in that case.
 #[derive(Debug)]
 pub struct QueryResult {
     rows: Option<UnboundedReceiver<Vec<u8>>>,
     meta: Option<Receiver<QueryMetaData>>,
+    buffer: Option<Arc<RowBuffer>>,
 }
 
 impl QueryResult {
@@ -20,26 +101,106 @@ impl QueryResult {
         Self {
             rows: Some(rows),
             meta: Some(meta),
+            buffer: None,
+        }
+    }
+
+    pub(crate) fn with_buffer(mut self, buffer: Arc<RowBuffer>) -> Self {
+        self.buffer = Some(buffer);
+        self
+    }
+
+    /// Builds the result from a complete query response instead of a stream.
+    pub(crate) fn from_payload(payload: &[u8], endpoint: Option<String>) -> CouchbaseResult<Self> {
+        let mut response: serde_json::Map<String, Value> = serde_json::from_slice(payload)
+            .map_err(|e| CouchbaseError::DecodingFailure {
+                ctx: ErrorContext::default(),
+                source: e.into(),
+            })?;
+
+        let (rows_sender, rows_receiver) = futures::channel::mpsc::unbounded();
+        if let Some(Value::Array(rows)) = response.remove("results") {
+            for row in rows {
+                let _ = rows_sender.unbounded_send(serde_json::to_vec(&row).unwrap());
+            }
+        }
+        let (meta_sender, meta_receiver) = futures::channel::oneshot::channel();
+        if let Ok(meta) = serde_json::from_value::<QueryMetaData>(Value::Object(response)) {
+            let _ = meta_sender.send(meta.with_endpoint(endpoint));
         }
+
+        Ok(Self::new(rows_receiver, meta_receiver))
     }
 
+    /// Streams the rows as they arrive, decoded into `T`.
+    ///
+    /// If the rows buffered by a slow consumer exceeded `QueryOptions::max_buffered_bytes`,
+    /// the query is cancelled and the stream ends with a `RequestCanceled` error.
     pub fn rows<T>(&mut self) -> impl Stream<Item = CouchbaseResult<T>>
     where
         T: DeserializeOwned,
     {
-        self.rows.take().expect("Can not consume rows twice!").map(
-            |v| match serde_json::from_slice(v.as_slice()) {
-                Ok(decoded) => Ok(decoded),
-                Err(e) => Err(CouchbaseError::DecodingFailure {
-                    ctx: ErrorContext::default(),
-                    source: e.into(),
-                }),
-            },
+        let buffer = self.buffer.clone();
+        let rows = self
+            .rows
+            .take()
+            .expect("Can not consume rows twice!")
+            .enumerate()
+            .map(move |(index, v)| {
+                if let Some(buffer) = &buffer {
+                    buffer.pop(v.len());
+                }
+                decode_row(index, v)
+            });
+
+        let buffer = self.buffer.clone();
+        let overflow = futures::stream::once(futures::future::lazy(move |_| match buffer {
+            Some(buffer) if buffer.overflowed() => {
+                let mut ctx = ErrorContext::default();
+                ctx.insert(
+                    "msg",
+                    Value::String("the rows were not consumed fast enough".into()),
+                );
+                ctx.insert("max_buffered_bytes", Value::from(buffer.limit));
+                Some(Err(CouchbaseError::RequestCanceled { ctx }))
+            }
+            _ => None,
+        }))
+        .filter_map(futures::future::ready);
+
+        rows.chain(overflow)
+    }
+
+    /// Waits for the meta data, which is received after the last row.
+    ///
+    /// Fails with `RequestCanceled` if the query was cancelled, or failed after its rows
+    /// started to stream, before the meta data was received.
+    pub async fn meta_data(&mut self) -> CouchbaseResult<QueryMetaData> {
+        received_meta(
+            self.meta
+                .take()
+                .expect("Can not consume meta data twice!")
+                .await,
         )
     }
 
-    pub async fn meta_data(&mut self) -> QueryMetaData {
-        self.meta.take().unwrap().await.unwrap()
+    /// Consumes the rows and returns the only one, failing if there are none or more than one.
+    pub(crate) async fn single_row<T>(&mut self, statement: String) -> CouchbaseResult<T>
+    where
+        T: DeserializeOwned,
+    {
+        let mut rows = self.rows::<T>();
+        let mut ctx = ErrorContext::default();
+        ctx.insert("statement", Value::String(statement));
+
+        let row = match rows.next().await {
+            Some(row) => row?,
+            None => return Err(CouchbaseError::NoRowsReturned { ctx }),
+        };
+        match rows.next().await {
+            Some(_) => Err(CouchbaseError::MultipleRowsReturned { ctx }),
+            None => Ok(row),
+        }
     }
 }
 
@@ -52,9 +213,21 @@ pub struct QueryMetaData {
     #[serde(rename = "clientContextID")]
     client_context_id: String,
     metrics: QueryMetrics,
+    #[serde(skip)]
+    endpoint: Option<String>,
 }
 
 impl QueryMetaData {
+    pub(crate) fn with_endpoint(mut self, endpoint: Option<String>) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    /// The query node (`host:port`) which served the query, see `QueryOptions::endpoint`.
+    pub fn endpoint(&self) -> Option<&str> {
+        self.endpoint.as_deref()
+    }
+
     pub fn metrics(&self) -> &QueryMetrics {
         &self.metrics
     }
@@ -132,6 +305,10 @@ impl QueryMetrics {
 pub struct AnalyticsResult {
     rows: Option<UnboundedReceiver<Vec<u8>>>,
     meta: Option<Receiver<AnalyticsMetaData>>,
+    // Received early by handle()
+    received_meta: Option<AnalyticsMetaData>,
+    // Kept once the meta data has been received, so handle() works after meta_data()
+    handle: Option<String>,
 }
 
 impl AnalyticsResult {
@@ -139,26 +316,53 @@ impl AnalyticsResult {
         Self {
             rows: Some(rows),
             meta: Some(meta),
+            received_meta: None,
+            handle: None,
         }
     }
 
+    /// The handle of a deferred query, see `AnalyticsOptions::deferred`, or `None` for a
+    /// regular query.
+    ///
+    /// Like `meta_data` it waits for the response to complete, which for a deferred query
+    /// happens as soon as the query has been accepted, and fails the same way if the query
+    /// was cancelled before that. It can be called before or after `meta_data`.
+    pub async fn handle(&mut self) -> CouchbaseResult<Option<AnalyticsHandle>> {
+        if let Some(meta) = self.meta.take() {
+            let meta = received_meta(meta.await)?;
+            self.handle = meta.handle.clone();
+            self.received_meta = Some(meta);
+        }
+        Ok(self.handle.clone().map(AnalyticsHandle::new))
+    }
+
     pub fn rows<T>(&mut self) -> impl Stream<Item = CouchbaseResult<T>>
     where
         T: DeserializeOwned,
     {
-        self.rows.take().expect("Can not consume rows twice!").map(
-            |v| match serde_json::from_slice(v.as_slice()) {
-                Ok(decoded) => Ok(decoded),
-                Err(e) => Err(CouchbaseError::DecodingFailure {
-                    ctx: ErrorContext::default(),
-                    source: e.into(),
-                }),
-            },
-        )
-    }
-
-    pub async fn meta_data(&mut self) -> AnalyticsMetaData {
-        self.meta.take().unwrap().await.unwrap()
+        self.rows
+            .take()
+            .expect("Can not consume rows twice!")
+            .enumerate()
+            .map(|(index, v)| decode_row(index, v))
+    }
+
+    /// Waits for the meta data, which is received after the last row.
+    ///
+    /// Fails with `RequestCanceled` if the query was cancelled, for example because its rows
+    /// were dropped, before the meta data was received.
+    pub async fn meta_data(&mut self) -> CouchbaseResult<AnalyticsMetaData> {
+        if let Some(meta) = self.received_meta.take() {
+            return Ok(meta);
+        }
+        let meta = received_meta(
+            self.meta
+                .take()
+                .expect("Can not consume meta data twice!")
+                .await,
+        )?;
+        self.handle = meta.handle.clone();
+        Ok(meta)
     }
 }
 
@@ -168,11 +372,304 @@ pub struct AnalyticsMetaData {
     request_id: String,
     #[serde(rename = "clientContextID")]
     client_context_id: String,
+    #[serde(default)]
+    status: String,
+    metrics: AnalyticsMetrics,
+    #[serde(default)]
+    handle: Option<String>,
+}
+
+impl AnalyticsMetaData {
+    /// The meta data of the results of a deferred query, which are fetched without the ids
+    /// and timings of the query.
+    pub(crate) fn deferred(result_count: usize, result_size: usize) -> Self {
+        Self {
+            request_id: String::new(),
+            client_context_id: String::new(),
+            status: String::from("success"),
+            metrics: AnalyticsMetrics {
+                elapsed_time: String::from("0s"),
+                execution_time: String::from("0s"),
+                result_count,
+                result_size,
+                error_count: 0,
+                warning_count: 0,
+                processed_objects: 0,
+            },
+            handle: None,
+        }
+    }
+
+    pub fn metrics(&self) -> &AnalyticsMetrics {
+        &self.metrics
+    }
+
+    pub fn request_id(&self) -> &str {
+        self.request_id.as_ref()
+    }
+
+    pub fn client_context_id(&self) -> &str {
+        self.client_context_id.as_ref()
+    }
+
+    pub fn status(&self) -> &str {
+        self.status.as_ref()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnalyticsMetrics {
+    #[serde(rename = "elapsedTime")]
+    elapsed_time: String,
+    #[serde(rename = "executionTime")]
+    execution_time: String,
+    #[serde(rename = "resultCount")]
+    result_count: usize,
+    #[serde(rename = "resultSize")]
+    result_size: usize,
+    #[serde(rename = "errorCount", default)]
+    error_count: usize,
+    #[serde(rename = "warningCount", default)]
+    warning_count: usize,
+    #[serde(rename = "processedObjects", default)]
+    processed_objects: usize,
+}
+
+impl AnalyticsMetrics {
+    pub fn elapsed_time(&self) -> Duration {
+        match parse_duration::parse(&self.elapsed_time) {
+            Ok(d) => d,
+            Err(_e) => Duration::from_secs(0),
+        }
+    }
+
+    pub fn execution_time(&self) -> Duration {
+        match parse_duration::parse(&self.execution_time) {
+            Ok(d) => d,
+            Err(_e) => Duration::from_secs(0),
+        }
+    }
+
+    pub fn result_count(&self) -> usize {
+        self.result_count
+    }
+
+    pub fn result_size(&self) -> usize {
+        self.result_size
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.error_count
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.warning_count
+    }
+
+    pub fn processed_objects(&self) -> usize {
+        self.processed_objects
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct SearchMetaData {
-    errors: Option<HashMap<String, String>>,
+    #[serde(default)]
+    status: SearchStatus,
+    #[serde(default, deserialize_with = "deserialize_null_default")]
+    facets: HashMap<String, SearchFacetResult>,
+}
+
+fn deserialize_null_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + serde::Deserialize<'de>,
+{
+    let value: Option<T> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(value.unwrap_or_default())
+}
+
+/// The result of a facet, see `SearchOptions::facet`.
+///
+/// Depending on the kind of facet, either the terms, the numeric ranges or the date ranges
+/// are filled in.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchFacetResult {
+    #[serde(default)]
+    field: String,
+    #[serde(default)]
+    total: u64,
+    #[serde(default)]
+    missing: u64,
+    #[serde(default)]
+    other: u64,
+    #[serde(default, deserialize_with = "deserialize_null_default")]
+    terms: Vec<TermFacetResult>,
+    #[serde(default, deserialize_with = "deserialize_null_default")]
+    numeric_ranges: Vec<NumericRangeFacetResult>,
+    #[serde(default, deserialize_with = "deserialize_null_default")]
+    date_ranges: Vec<DateRangeFacetResult>,
+}
+
+impl SearchFacetResult {
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    /// The number of values of the field in all hits.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// The number of hits without a value for the field.
+    pub fn missing(&self) -> u64 {
+        self.missing
+    }
+
+    /// The number of values which are not counted in any term or range returned.
+    pub fn other(&self) -> u64 {
+        self.other
+    }
+
+    pub fn terms(&self) -> &[TermFacetResult] {
+        &self.terms
+    }
+
+    pub fn numeric_ranges(&self) -> &[NumericRangeFacetResult] {
+        &self.numeric_ranges
+    }
+
+    pub fn date_ranges(&self) -> &[DateRangeFacetResult] {
+        &self.date_ranges
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TermFacetResult {
+    term: String,
+    count: u64,
+}
+
+impl TermFacetResult {
+    pub fn term(&self) -> &str {
+        &self.term
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NumericRangeFacetResult {
+    name: String,
+    min: Option<f64>,
+    max: Option<f64>,
+    count: u64,
+}
+
+impl NumericRangeFacetResult {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        self.min
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        self.max
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DateRangeFacetResult {
+    name: String,
+    start: Option<String>,
+    end: Option<String>,
+    count: u64,
+}
+
+impl DateRangeFacetResult {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn start(&self) -> Option<&str> {
+        self.start.as_deref()
+    }
+
+    pub fn end(&self) -> Option<&str> {
+        self.end.as_deref()
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/// How many partitions of the index took part in the search, and why some of them failed.
+#[derive(Debug, Default, Deserialize)]
+struct SearchStatus {
+    #[serde(default)]
+    total: usize,
+    #[serde(default)]
+    failed: usize,
+    #[serde(default)]
+    successful: usize,
+    #[serde(default, deserialize_with = "deserialize_partition_errors")]
+    errors: HashMap<String, String>,
+}
+
+/// Reads the errors by partition, tolerating errors which are not reported as plain strings.
+fn deserialize_partition_errors<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let errors: Option<HashMap<String, Value>> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(errors
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(partition, error)| match error {
+            Value::String(error) => (partition, error),
+            error => (partition, error.to_string()),
+        })
+        .collect())
+}
+
+impl SearchMetaData {
+    /// The errors of the partitions of the index which failed, by partition.
+    pub fn errors(&self) -> &HashMap<String, String> {
+        &self.status.errors
+    }
+
+    /// Whether some partitions of the index failed, in which case the rows only hold the hits
+    /// of the other partitions.
+    pub fn is_partial(&self) -> bool {
+        self.status.failed > 0 || !self.status.errors.is_empty()
+    }
+
+    pub fn total_partition_count(&self) -> usize {
+        self.status.total
+    }
+
+    pub fn success_partition_count(&self) -> usize {
+        self.status.successful
+    }
+
+    pub fn error_partition_count(&self) -> usize {
+        self.status.failed
+    }
+
+    /// The results of the facets of the search, by the name they were added with.
+    pub fn facets(&self) -> &HashMap<String, SearchFacetResult> {
+        &self.facets
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -180,6 +677,14 @@ pub struct SearchRow {
     index: String,
     id: String,
     score: f32,
+    #[serde(default)]
+    fragments: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    sort: Vec<Value>,
+    #[serde(default, deserialize_with = "deserialize_null_default")]
+    locations: SearchRowLocations,
+    #[serde(default)]
+    explanation: Option<Value>,
 }
 
 impl SearchRow {
@@ -194,6 +699,90 @@ impl SearchRow {
     pub fn score(&self) -> f32 {
         self.score
     }
+
+    /// The highlighted fragments per field, if highlighting was requested.
+    pub fn fragments(&self) -> &HashMap<String, Vec<String>> {
+        &self.fragments
+    }
+
+    /// The values the hit has been sorted by, one per sort order.
+    pub fn sort(&self) -> &[Value] {
+        &self.sort
+    }
+
+    /// Where the terms of the query matched, if `SearchOptions::include_locations` was set.
+    pub fn locations(&self) -> &SearchRowLocations {
+        &self.locations
+    }
+
+    /// How the score has been computed, if `SearchOptions::explain` was set.
+    pub fn explanation(&self) -> Option<&Value> {
+        self.explanation.as_ref()
+    }
+}
+
+/// The locations of the matched terms in a hit, by field and term.
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct SearchRowLocations {
+    fields: HashMap<String, HashMap<String, Vec<SearchRowLocation>>>,
+}
+
+impl SearchRowLocations {
+    /// The fields in which terms matched.
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.fields.keys().map(String::as_str)
+    }
+
+    /// The terms which matched in the field.
+    pub fn terms(&self, field: &str) -> impl Iterator<Item = &str> {
+        self.fields
+            .get(field)
+            .into_iter()
+            .flat_map(|terms| terms.keys().map(String::as_str))
+    }
+
+    /// Where the term matched in the field.
+    pub fn get(&self, field: &str, term: &str) -> &[SearchRowLocation] {
+        self.fields
+            .get(field)
+            .and_then(|terms| terms.get(term))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+/// Where a term matched in a field.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SearchRowLocation {
+    #[serde(rename = "pos")]
+    position: u64,
+    start: u64,
+    end: u64,
+    #[serde(default)]
+    array_positions: Option<Vec<u64>>,
+}
+
+impl SearchRowLocation {
+    /// The position of the term in the field, counting terms from 1.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// The byte offset the term starts at.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// The byte offset the term ends at, exclusive.
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    /// The indexes into the arrays the field is nested in, if any.
+    pub fn array_positions(&self) -> Option<&[u64]> {
+        self.array_positions.as_deref()
+    }
 }
 
 #[derive(Debug)]
@@ -210,27 +799,77 @@ impl SearchResult {
         }
     }
 
+    /// Builds the result from a complete search response instead of a stream.
+    #[cfg(feature = "volatile")]
+    pub(crate) fn from_payload(payload: &[u8]) -> CouchbaseResult<Self> {
+        let decoding_failure = |e: serde_json::Error| CouchbaseError::DecodingFailure {
+            ctx: ErrorContext::default(),
+            source: e.into(),
+        };
+        let mut response: serde_json::Map<String, Value> =
+            serde_json::from_slice(payload).map_err(decoding_failure)?;
+
+        let (rows_sender, rows_receiver) = futures::channel::mpsc::unbounded();
+        if let Some(Value::Array(hits)) = response.remove("hits") {
+            for hit in hits {
+                let _ = rows_sender.unbounded_send(serde_json::to_vec(&hit).unwrap());
+            }
+        }
+        let (meta_sender, meta_receiver) = futures::channel::oneshot::channel();
+        let meta = serde_json::from_value(Value::Object(response)).map_err(decoding_failure)?;
+        let _ = meta_sender.send(meta);
+
+        Ok(Self::new(rows_receiver, meta_receiver))
+    }
+
     pub fn rows(&mut self) -> impl Stream<Item = CouchbaseResult<SearchRow>> {
-        self.rows.take().expect("Can not consume rows twice!").map(
-            |v| match serde_json::from_slice(v.as_slice()) {
-                Ok(decoded) => Ok(decoded),
-                Err(e) => Err(CouchbaseError::DecodingFailure {
-                    ctx: ErrorContext::default(),
-                    source: e.into(),
-                }),
-            },
+        self.rows
+            .take()
+            .expect("Can not consume rows twice!")
+            .enumerate()
+            .map(|(index, v)| decode_row(index, v))
+    }
+
+    /// Waits for the meta data, which is received after the last row.
+    ///
+    /// Fails with `RequestCanceled` if the search was cancelled, for example because its rows
+    /// were dropped, before the meta data was received.
+    pub async fn meta_data(&mut self) -> CouchbaseResult<SearchMetaData> {
+        received_meta(
+            self.meta
+                .take()
+                .expect("Can not consume meta data twice!")
+                .await,
         )
     }
 
-    pub async fn meta_data(&mut self) -> SearchMetaData {
-        self.meta.take().unwrap().await.unwrap()
+    /// Waits for the full response and fails if some partitions of the index failed.
+    pub(crate) async fn fail_on_partial(mut self) -> CouchbaseResult<Self> {
+        let meta = self.meta_data().await?;
+        if meta.is_partial() {
+            let mut ctx = ErrorContext::default();
+            ctx.insert("failed", Value::from(meta.error_partition_count()));
+            ctx.insert("total", Value::from(meta.total_partition_count()));
+            ctx.insert(
+                "errors",
+                serde_json::to_value(meta.errors()).unwrap_or(Value::Null),
+            );
+            return Err(CouchbaseError::PartialSearchFailure { ctx });
+        }
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        let _ = sender.send(meta);
+        self.meta = Some(receiver);
+        Ok(self)
     }
 }
 
+#[derive(Clone)]
 pub struct GetResult {
     content: Vec<u8>,
     cas: u64,
     flags: u32,
+    expiry_time: Option<SystemTime>,
+    transcoder: Option<Arc<dyn Transcoder>>,
 }
 
 impl GetResult {
@@ -239,37 +878,255 @@ impl GetResult {
             content,
             cas,
             flags,
+            expiry_time: None,
+            transcoder: None,
         }
     }
 
+    pub(crate) fn with_expiry_time(mut self, expiry_time: Option<SystemTime>) -> Self {
+        self.expiry_time = expiry_time;
+        self
+    }
+
+    pub(crate) fn with_transcoder(mut self, transcoder: Option<Arc<dyn Transcoder>>) -> Self {
+        self.transcoder = transcoder;
+        self
+    }
+
     pub fn cas(&self) -> u64 {
         self.cas
     }
 
-    pub fn content<'a, T>(&'a self) -> CouchbaseResult<T>
-    where
-        T: serde::Deserialize<'a>,
-    {
-        match serde_json::from_slice(&self.content.as_slice()) {
-            Ok(v) => Ok(v),
-            Err(e) => Err(CouchbaseError::DecodingFailure {
-                ctx: ErrorContext::default(),
-                source: e.into(),
-            }),
+    /// When the document expires, or `None` if it does not expire.
+    ///
+    /// The expiry is only fetched if `GetOptions::with_expiry` is set, otherwise this is
+    /// always `None`.
+    pub fn expiry_time(&self) -> Option<SystemTime> {
+        self.expiry_time
+    }
+
+    /// Decodes the document, through the transcoder given in the options of the get if there
+    /// is one and as JSON otherwise.
+    pub fn content<'a, T>(&'a self) -> CouchbaseResult<T>
+    where
+        T: serde::Deserialize<'a>,
+    {
+        let decoded = match &self.transcoder {
+            Some(transcoder) => T::deserialize(transcoder.decode(&self.content, self.flags)?),
+            None => serde_json::from_slice(&self.content),
+        };
+        match decoded {
+            Ok(v) => Ok(v),
+            Err(e) => Err(CouchbaseError::DecodingFailure {
+                ctx: ErrorContext::default(),
+                source: e.into(),
+            }),
+        }
+    }
+
+    /// Decodes the document as JSON, borrowing strings and bytes from the content of the
+    /// result instead of copying them.
+    ///
+    /// Unlike `content`, this never goes through the transcoder of the get, so it fails for
+    /// documents which are flagged as another format, like binary or string. Documents
+    /// without common flags are assumed to be JSON, like in `content`. Fields have to be
+    /// marked with `#[serde(borrow)]` to be borrowed when they are not a plain `&str` or
+    /// `&[u8]`, and strings containing escape sequences cannot be borrowed as a `&str`, use a
+    /// `Cow<str>` for those.
+    ///
+    /// ```no_run
+    /// # use couchbase::*;
+    /// # use serde_derive::Deserialize;
+    /// # use std::borrow::Cow;
+    /// #[derive(Deserialize)]
+    /// struct Airline<'a> {
+    ///     #[serde(borrow)]
+    ///     name: Cow<'a, str>,
+    ///     callsign: &'a str,
+    /// }
+    ///
+    /// # async fn run(collection: Collection) -> CouchbaseResult<()> {
+    /// let result = collection.get("airline_10", GetOptions::default()).await?;
+    /// let airline: Airline = result.content_as_borrowed()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn content_as_borrowed<'a, T>(&'a self) -> CouchbaseResult<T>
+    where
+        T: serde::Deserialize<'a>,
+    {
+        verify_json_flags(self.flags)?;
+        serde_json::from_slice(&self.content).map_err(|e| CouchbaseError::DecodingFailure {
+            ctx: ErrorContext::default(),
+            source: e.into(),
+        })
+    }
+
+    /// Decodes a document written by `Collection::upsert_tagged` into its enum variant.
+    pub fn content_tagged<T>(&self, transcoder: &TaggedTranscoder) -> CouchbaseResult<T>
+    where
+        T: DeserializeOwned,
+    {
+        verify_json_flags(self.flags)?;
+        transcoder.decode(&self.content)
+    }
+
+    pub(crate) fn into_content(self) -> Vec<u8> {
+        self.content
+    }
+
+    /// The document as stored on the server, without decoding or copying it.
+    pub fn raw_content(&self) -> &[u8] {
+        &self.content
+    }
+
+    /// The flags stored alongside the document.
+    #[cfg(feature = "volatile")]
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+}
+
+impl fmt::Debug for GetResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "GetResult {{ cas: 0x{:x}, flags: 0x{:x}, content: {:?} }}",
+            self.cas,
+            self.flags,
+            RedactedContent::new(&self.content, Some(self.flags))
+        )
+    }
+}
+
+/// Describes document content in debug output by its data type and length, so documents do
+/// not end up in logs.
+struct RedactedContent<'a> {
+    content: &'a [u8],
+    flags: Option<u32>,
+}
+
+impl<'a> RedactedContent<'a> {
+    fn new(content: &'a [u8], flags: Option<u32>) -> Self {
+        Self { content, flags }
+    }
+}
+
+impl fmt::Debug for RedactedContent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The upper byte of the common flags carries the data type
+        let data_type = match self.flags.map(|flags| flags >> 24) {
+            Some(0) => "legacy, ",
+            Some(1) => "private, ",
+            Some(2) => "json, ",
+            Some(3) => "binary, ",
+            Some(4) => "string, ",
+            Some(_) => "unknown, ",
+            None => "",
+        };
+        write!(f, "<{}{} bytes>", data_type, self.content.len())
+    }
+}
+
+/// The result of a replica read, which may have been served by the active or a replica node.
+pub struct GetReplicaResult {
+    content: Vec<u8>,
+    cas: u64,
+    flags: u32,
+    is_replica: bool,
+    transcoder: Option<Arc<dyn Transcoder>>,
+}
+
+impl GetReplicaResult {
+    pub fn new(content: Vec<u8>, cas: u64, flags: u32, is_replica: bool) -> Self {
+        Self {
+            content,
+            cas,
+            flags,
+            is_replica,
+            transcoder: None,
+        }
+    }
+
+    pub(crate) fn with_transcoder(mut self, transcoder: Option<Arc<dyn Transcoder>>) -> Self {
+        self.transcoder = transcoder;
+        self
+    }
+
+    pub(crate) fn from_active(result: GetResult) -> Self {
+        Self::new(result.content, result.cas, result.flags, false)
+    }
+
+    pub fn cas(&self) -> u64 {
+        self.cas
+    }
+
+    /// Returns true if the document was read from a replica rather than the active node.
+    pub fn is_replica(&self) -> bool {
+        self.is_replica
+    }
+
+    /// The document as stored on the server, without decoding or copying it.
+    pub fn raw_content(&self) -> &[u8] {
+        &self.content
+    }
+
+    /// Decodes the document, through the transcoder given in the options of the replica read
+    /// if there is one and as JSON otherwise.
+    pub fn content<'a, T>(&'a self) -> CouchbaseResult<T>
+    where
+        T: serde::Deserialize<'a>,
+    {
+        let decoded = match &self.transcoder {
+            Some(transcoder) => T::deserialize(transcoder.decode(&self.content, self.flags)?),
+            None => serde_json::from_slice(&self.content),
+        };
+        match decoded {
+            Ok(v) => Ok(v),
+            Err(e) => Err(CouchbaseError::DecodingFailure {
+                ctx: ErrorContext::default(),
+                source: e.into(),
+            }),
+        }
+    }
+}
+
+impl fmt::Debug for GetReplicaResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "GetReplicaResult {{ cas: 0x{:x}, flags: 0x{:x}, is_replica: {}, content: {:?} }}",
+            self.cas,
+            self.flags,
+            self.is_replica,
+            RedactedContent::new(&self.content, Some(self.flags))
+        )
+    }
+}
+
+/// Streams the copies of a document from the active and all replica nodes as they arrive.
+pub struct GetAllReplicasResult {
+    results: Option<BoxStream<'static, CouchbaseResult<GetReplicaResult>>>,
+}
+
+impl GetAllReplicasResult {
+    pub(crate) fn new(results: BoxStream<'static, CouchbaseResult<GetReplicaResult>>) -> Self {
+        Self {
+            results: Some(results),
         }
     }
+
+    pub fn results(&mut self) -> impl Stream<Item = CouchbaseResult<GetReplicaResult>> {
+        self.results.take().expect("Can not consume results twice!")
+    }
 }
 
-impl fmt::Debug for GetResult {
+impl fmt::Debug for GetAllReplicasResult {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let content = match std::str::from_utf8(&self.content) {
-            Ok(c) => c,
-            Err(_e) => "<Not Valid/Printable UTF-8>",
-        };
         write!(
             f,
-            "GetResult {{ cas: 0x{:x}, flags: 0x{:x}, content: {} }}",
-            self.cas, self.flags, content
+            "GetAllReplicasResult {{ consumed: {} }}",
+            self.results.is_none()
         )
     }
 }
@@ -368,40 +1225,101 @@ impl fmt::Debug for CounterResult {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "CounterResult {{ cas: 0x{:x}, mutation_token: {:?},  content: {:?}}}",
+            "CounterResult {{ cas: 0x{:x}, mutation_token: {:?}, content: {:?} }}",
             self.cas, self.mutation_token, self.content
         )
     }
 }
 
-#[derive(Debug)]
 pub(crate) struct SubDocField {
     pub status: u32,
     pub value: Vec<u8>,
 }
 
-#[derive(Debug)]
+impl fmt::Debug for SubDocField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SubDocField {{ status: {}, value: {:?} }}",
+            self.status,
+            RedactedContent::new(&self.value, None)
+        )
+    }
+}
+
 pub struct MutateInResult {
     content: Vec<SubDocField>,
     cas: u64,
+    mutation_token: Option<MutationToken>,
+}
+
+impl fmt::Debug for MutateInResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "MutateInResult {{ cas: 0x{:x}, content: {:?} }}",
+            self.cas, self.content
+        )
+    }
 }
 
 impl MutateInResult {
-    pub(crate) fn new(content: Vec<SubDocField>, cas: u64) -> Self {
-        Self { content, cas }
+    pub(crate) fn new(
+        content: Vec<SubDocField>,
+        cas: u64,
+        mutation_token: Option<MutationToken>,
+    ) -> Self {
+        Self {
+            content,
+            cas,
+            mutation_token,
+        }
     }
 
     pub fn cas(&self) -> u64 {
         self.cas
     }
+
+    pub fn mutation_token(&self) -> Option<&MutationToken> {
+        self.mutation_token.as_ref()
+    }
+
+    /// The value returned by the spec at the given index, like the new value of a counter.
+    pub fn content<'a, T>(&'a self, index: usize) -> CouchbaseResult<T>
+    where
+        T: serde::Deserialize<'a>,
+    {
+        match serde_json::from_slice(
+            self.content
+                .get(index)
+                .expect("index not found")
+                .value
+                .as_slice(),
+        ) {
+            Ok(v) => Ok(v),
+            Err(e) => Err(CouchbaseError::DecodingFailure {
+                ctx: ErrorContext::default(),
+                source: e.into(),
+            }),
+        }
+    }
 }
 
-#[derive(Debug)]
 pub struct LookupInResult {
     content: Vec<SubDocField>,
     cas: u64,
 }
 
+impl fmt::Debug for LookupInResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "LookupInResult {{ cas: 0x{:x}, content: {:?} }}",
+            self.cas, self.content
+        )
+    }
+}
+
 impl LookupInResult {
     pub(crate) fn new(content: Vec<SubDocField>, cas: u64) -> Self {
         Self { content, cas }
@@ -416,8 +1334,7 @@ impl LookupInResult {
         T: serde::Deserialize<'a>,
     {
         match serde_json::from_slice(
-            &self
-                .content
+            self.content
                 .get(index)
                 .expect("index not found")
                 .value
@@ -434,17 +1351,119 @@ impl LookupInResult {
     pub fn exists(&self, index: usize) -> bool {
         self.content.get(index).expect("index not found").status == 0
     }
+
+    /// Moves the value out of the result instead of copying it, leaving it empty.
+    pub(crate) fn take_raw_content(&mut self, index: usize) -> Vec<u8> {
+        std::mem::take(&mut self.content.get_mut(index).expect("index not found").value)
+    }
 }
 
+/// The result of a sub-document lookup against the active node or a replica.
 #[derive(Debug)]
+pub struct LookupInReplicaResult {
+    result: LookupInResult,
+    is_replica: bool,
+}
+
+impl LookupInReplicaResult {
+    pub(crate) fn new(result: LookupInResult, is_replica: bool) -> Self {
+        Self { result, is_replica }
+    }
+
+    pub fn cas(&self) -> u64 {
+        self.result.cas()
+    }
+
+    pub fn content<'a, T>(&'a self, index: usize) -> CouchbaseResult<T>
+    where
+        T: serde::Deserialize<'a>,
+    {
+        self.result.content(index)
+    }
+
+    pub fn exists(&self, index: usize) -> bool {
+        self.result.exists(index)
+    }
+
+    /// Returns true if the lookup was served by a replica rather than the active node.
+    pub fn is_replica(&self) -> bool {
+        self.is_replica
+    }
+}
+
+/// Streams the lookup results from the active and all replica nodes as they arrive.
+pub struct LookupInAllReplicasResult {
+    results: Option<BoxStream<'static, CouchbaseResult<LookupInReplicaResult>>>,
+}
+
+impl LookupInAllReplicasResult {
+    pub(crate) fn new(results: BoxStream<'static, CouchbaseResult<LookupInReplicaResult>>) -> Self {
+        Self {
+            results: Some(results),
+        }
+    }
+
+    pub fn results(&mut self) -> impl Stream<Item = CouchbaseResult<LookupInReplicaResult>> {
+        self.results.take().expect("Can not consume results twice!")
+    }
+}
+
+impl fmt::Debug for LookupInAllReplicasResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "LookupInAllReplicasResult {{ consumed: {} }}",
+            self.results.is_none()
+        )
+    }
+}
+
 pub struct GenericManagementResult {
     status: u16,
     payload: Option<Vec<u8>>,
+    endpoint: Option<String>,
+    date: Option<String>,
+}
+
+impl fmt::Debug for GenericManagementResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "GenericManagementResult {{ status: {}, payload: {:?} }}",
+            self.status,
+            self.payload.as_deref().map(String::from_utf8_lossy)
+        )
+    }
 }
 
 impl GenericManagementResult {
     pub fn new(status: u16, payload: Option<Vec<u8>>) -> Self {
-        Self { status, payload }
+        Self {
+            status,
+            payload,
+            endpoint: None,
+            date: None,
+        }
+    }
+
+    pub(crate) fn with_endpoint(mut self, endpoint: Option<String>) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    pub(crate) fn with_date(mut self, date: Option<String>) -> Self {
+        self.date = date;
+        self
+    }
+
+    /// The `Date` header of the response, the time of the node which served it.
+    pub(crate) fn date(&self) -> Option<&str> {
+        self.date.as_deref()
+    }
+
+    /// The node (`host:port`) which served the request, if known.
+    pub fn endpoint(&self) -> Option<&str> {
+        self.endpoint.as_deref()
     }
 
     pub fn payload(&self) -> Option<&Vec<u8>> {
@@ -471,6 +1490,97 @@ impl KvStatsResult {
     }
 }
 
+/// The id of a collection, as assigned by the server.
+#[derive(Debug)]
+#[cfg(feature = "volatile")]
+pub struct CollectionIdResult {
+    collection_id: u32,
+    manifest_id: u64,
+}
+
+#[cfg(feature = "volatile")]
+impl CollectionIdResult {
+    pub(crate) fn new(collection_id: u32, manifest_id: u64) -> Self {
+        Self {
+            collection_id,
+            manifest_id,
+        }
+    }
+
+    pub fn collection_id(&self) -> u32 {
+        self.collection_id
+    }
+
+    /// The id of the collection manifest the collection id was resolved from.
+    pub fn manifest_id(&self) -> u64 {
+        self.manifest_id
+    }
+}
+
+/// Which node owns which partition (vbucket) of a bucket, as of a configuration revision.
+///
+/// Every key maps to one partition, so the map tells which node serves a key without asking
+/// the cluster. Partitions move between nodes during rebalance or failover, see
+/// `Bucket::partition_map_changes` to follow them.
+#[derive(Debug, Clone)]
+#[cfg(feature = "volatile")]
+pub struct PartitionMap {
+    revision: i64,
+    nodes: Vec<String>,
+    owners: Vec<Option<usize>>,
+}
+
+#[cfg(feature = "volatile")]
+impl PartitionMap {
+    pub(crate) fn new(revision: i64, nodes: Vec<String>, owners: Vec<Option<usize>>) -> Self {
+        Self {
+            revision,
+            nodes,
+            owners,
+        }
+    }
+
+    /// The revision of the configuration the map has been built from.
+    pub fn revision(&self) -> i64 {
+        self.revision
+    }
+
+    /// The key/value addresses (`host:port`) of the nodes.
+    pub fn nodes(&self) -> &[String] {
+        &self.nodes
+    }
+
+    pub fn num_partitions(&self) -> usize {
+        self.owners.len()
+    }
+
+    /// The partition the given key belongs to.
+    pub fn partition_for(&self, key: &str) -> u16 {
+        (partition_hash(key.as_bytes()) as usize % self.owners.len().max(1)) as u16
+    }
+
+    /// The key/value address of the node owning the partition, if it is owned by any.
+    pub fn owner_of_partition(&self, partition: u16) -> Option<&str> {
+        self.owners
+            .get(partition as usize)
+            .copied()
+            .flatten()
+            .and_then(|ix| self.nodes.get(ix))
+            .map(String::as_str)
+    }
+
+    /// The key/value address of the node owning the given key, if it is owned by any.
+    pub fn owner_of(&self, key: &str) -> Option<&str> {
+        self.owner_of_partition(self.partition_for(key))
+    }
+}
+
+/// The CRC32 based hash all Couchbase SDKs use to map keys to partitions.
+#[cfg(feature = "volatile")]
+fn partition_hash(key: &[u8]) -> u32 {
+    (crate::api::chunked::crc32(key) >> 16) & 0x7fff
+}
+
 #[derive(Debug)]
 pub struct KvStat {
     server: String,
@@ -514,6 +1624,247 @@ impl PingResult {
     pub fn endpoints(&self) -> &HashMap<ServiceType, Vec<EndpointPingReport>> {
         &self.services
     }
+
+    /// The SDK which produced the report.
+    pub fn sdk(&self) -> &str {
+        SDK_IDENTIFIER
+    }
+
+    /// Renders the report in the JSON format shared by all Couchbase SDKs.
+    pub fn to_json(&self) -> Value {
+        let mut services = serde_json::Map::new();
+        for (service, endpoints) in &self.services {
+            let endpoints = endpoints
+                .iter()
+                .map(|e| {
+                    let mut endpoint = serde_json::Map::new();
+                    endpoint.insert("id".into(), Value::String(e.id.clone()));
+                    endpoint.insert(
+                        "latency_us".into(),
+                        Value::from(e.latency.as_micros() as u64),
+                    );
+                    insert_optional(&mut endpoint, "remote", &e.remote);
+                    insert_optional(&mut endpoint, "local", &e.local);
+                    endpoint.insert("state".into(), Value::String(e.status.report_name().into()));
+                    insert_optional(&mut endpoint, "namespace", &e.scope);
+                    insert_optional(&mut endpoint, "error", &e.error);
+                    Value::Object(endpoint)
+                })
+                .collect();
+            services.insert(service.report_name().into(), Value::Array(endpoints));
+        }
+        serde_json::json!({
+            "version": 2,
+            "id": self.id,
+            "sdk": SDK_IDENTIFIER,
+            "services": services,
+        })
+    }
+}
+
+/// Identifies this SDK in ping and diagnostics reports.
+const SDK_IDENTIFIER: &str = concat!("couchbase-rs/", env!("CARGO_PKG_VERSION"));
+
+fn insert_optional(map: &mut serde_json::Map<String, Value>, key: &str, value: &Option<String>) {
+    if let Some(v) = value {
+        map.insert(key.into(), Value::String(v.clone()));
+    }
+}
+
+/// The state of the connections of the SDK, without performing any IO.
+#[derive(Debug)]
+pub struct DiagnosticsResult {
+    id: String,
+    services: HashMap<ServiceType, Vec<EndpointDiagnostics>>,
+}
+
+impl DiagnosticsResult {
+    pub(crate) fn new(
+        id: String,
+        services: HashMap<ServiceType, Vec<EndpointDiagnostics>>,
+    ) -> Self {
+        Self { id, services }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The SDK which produced the report.
+    pub fn sdk(&self) -> &str {
+        SDK_IDENTIFIER
+    }
+
+    pub fn endpoints(&self) -> &HashMap<ServiceType, Vec<EndpointDiagnostics>> {
+        &self.services
+    }
+
+    /// Renders the report in the JSON format shared by all Couchbase SDKs.
+    pub fn to_json(&self) -> Value {
+        let mut services = serde_json::Map::new();
+        for (service, endpoints) in &self.services {
+            let endpoints = endpoints
+                .iter()
+                .map(|e| {
+                    let mut endpoint = serde_json::Map::new();
+                    endpoint.insert("id".into(), Value::String(e.id.clone()));
+                    if let Some(last_activity) = e.last_activity {
+                        endpoint.insert(
+                            "last_activity_us".into(),
+                            Value::from(last_activity.as_micros() as u64),
+                        );
+                    }
+                    insert_optional(&mut endpoint, "remote", &e.remote);
+                    insert_optional(&mut endpoint, "local", &e.local);
+                    endpoint.insert("state".into(), Value::String(e.state.report_name().into()));
+                    insert_optional(&mut endpoint, "namespace", &e.namespace);
+                    if let Some(breaker) = e.circuit_breaker {
+                        endpoint.insert(
+                            "circuit_breaker".into(),
+                            Value::String(breaker.report_name().into()),
+                        );
+                    }
+                    insert_optional(&mut endpoint, "connection_id", &e.connection_id);
+                    if !e.features.is_empty() {
+                        endpoint.insert("features".into(), Value::from(e.features.clone()));
+                    }
+                    insert_optional(&mut endpoint, "sasl_mechanism", &e.sasl_mechanism);
+                    if e.degraded {
+                        endpoint.insert("degraded".into(), Value::Bool(true));
+                    }
+                    Value::Object(endpoint)
+                })
+                .collect();
+            services.insert(service.report_name().into(), Value::Array(endpoints));
+        }
+        serde_json::json!({
+            "version": 2,
+            "id": self.id,
+            "sdk": SDK_IDENTIFIER,
+            "services": services,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct EndpointDiagnostics {
+    pub(crate) typ: ServiceType,
+    pub(crate) id: String,
+    pub(crate) local: Option<String>,
+    pub(crate) remote: Option<String>,
+    pub(crate) last_activity: Option<Duration>,
+    pub(crate) state: EndpointState,
+    pub(crate) namespace: Option<String>,
+    pub(crate) circuit_breaker: Option<CircuitBreakerState>,
+    pub(crate) connection_id: Option<String>,
+    pub(crate) features: Vec<String>,
+    pub(crate) sasl_mechanism: Option<String>,
+    pub(crate) degraded: bool,
+}
+
+impl EndpointDiagnostics {
+    pub fn service_type(&self) -> ServiceType {
+        self.typ
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn local(&self) -> Option<&str> {
+        self.local.as_deref()
+    }
+
+    pub fn remote(&self) -> Option<&str> {
+        self.remote.as_deref()
+    }
+
+    /// How long ago the connection was last used.
+    pub fn last_activity(&self) -> Option<Duration> {
+        self.last_activity
+    }
+
+    pub fn state(&self) -> EndpointState {
+        self.state
+    }
+
+    /// The bucket the connection is bound to, if any.
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// The state of the circuit breaker of the node, if circuit breakers are enabled.
+    pub fn circuit_breaker(&self) -> Option<CircuitBreakerState> {
+        self.circuit_breaker
+    }
+
+    /// The id the key/value connection announced to the server, which also shows up in the
+    /// server logs.
+    pub fn connection_id(&self) -> Option<&str> {
+        self.connection_id.as_deref()
+    }
+
+    /// The features the server agreed to when the key/value connection was negotiated, like
+    /// `snappy`, `sync_replication`, `unordered_execution` or `collections`.
+    pub fn features(&self) -> &[String] {
+        &self.features
+    }
+
+    /// Whether the server agreed to the feature when the connection was negotiated.
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// The SASL mechanism the key/value connection authenticated with, like `SCRAM-SHA512`.
+    pub fn sasl_mechanism(&self) -> Option<&str> {
+        self.sasl_mechanism.as_deref()
+    }
+
+    /// Whether the background pings of `ClusterOptions::health_probe` marked the endpoint as
+    /// degraded.
+    pub fn degraded(&self) -> bool {
+        self.degraded
+    }
+}
+
+/// The state of the circuit breaker of a key/value node, see `ClusterOptions::kv_circuit_breaker`.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
+pub enum CircuitBreakerState {
+    /// Requests are dispatched to the node.
+    Closed,
+    /// Too many requests timed out, so none are dispatched to the node.
+    Open,
+    /// The canary is in flight, which closes the breaker again once it is answered.
+    HalfOpen,
+}
+
+impl CircuitBreakerState {
+    fn report_name(self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
+pub enum EndpointState {
+    Disconnected,
+    Connecting,
+    Connected,
+    Disconnecting,
+}
+
+impl EndpointState {
+    fn report_name(self) -> &'static str {
+        match self {
+            Self::Disconnected => "disconnected",
+            Self::Connecting => "connecting",
+            Self::Connected => "connected",
+            Self::Disconnecting => "disconnecting",
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -529,6 +1880,7 @@ pub struct EndpointPingReport {
 }
 
 impl EndpointPingReport {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         local: Option<String>,
         remote: Option<String>,
@@ -552,7 +1904,7 @@ impl EndpointPingReport {
     }
 
     pub fn service_type(&self) -> ServiceType {
-        self.typ.clone()
+        self.typ
     }
 
     pub fn id(&self) -> String {
@@ -568,7 +1920,7 @@ impl EndpointPingReport {
     }
 
     pub fn state(&self) -> PingState {
-        self.status.clone()
+        self.status
     }
 
     pub fn error(&self) -> Option<String> {
@@ -594,6 +1946,20 @@ pub enum ServiceType {
     Analytics,
 }
 
+impl ServiceType {
+    /// The name of the service in ping and diagnostics reports.
+    pub(crate) fn report_name(self) -> &'static str {
+        match self {
+            Self::Management => "mgmt",
+            Self::KeyValue => "kv",
+            Self::Views => "views",
+            Self::Query => "query",
+            Self::Search => "search",
+            Self::Analytics => "analytics",
+        }
+    }
+}
+
 impl fmt::Display for ServiceType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
@@ -608,8 +1974,93 @@ pub enum PingState {
     Invalid,
 }
 
+impl PingState {
+    fn report_name(self) -> &'static str {
+        match self {
+            Self::OK => "ok",
+            Self::Timeout => "timeout",
+            Self::Error | Self::Invalid => "error",
+        }
+    }
+}
+
 impl fmt::Display for PingState {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use couchbase_flags::{BINARY_COMMON_FLAGS, JSON_COMMON_FLAGS};
+    use futures::channel::{mpsc, oneshot};
+    use futures::executor::block_on;
+
+    fn analytics_result(handle: Option<&str>) -> AnalyticsResult {
+        let (_rows_sender, rows) = mpsc::unbounded();
+        let (meta_sender, meta) = oneshot::channel();
+        let mut deferred = AnalyticsMetaData::deferred(0, 0);
+        deferred.handle = handle.map(String::from);
+        meta_sender.send(deferred).unwrap();
+        AnalyticsResult::new(rows, meta)
+    }
+
+    #[test]
+    fn reads_the_analytics_handle_before_and_after_the_meta_data() {
+        let mut result = analytics_result(Some("/status/1"));
+        let handle = block_on(result.handle()).unwrap().unwrap();
+        assert_eq!(handle.uri(), "/status/1");
+        assert!(block_on(result.meta_data()).is_ok());
+        assert!(block_on(result.handle()).unwrap().is_some());
+
+        let mut result = analytics_result(Some("/status/2"));
+        assert!(block_on(result.meta_data()).is_ok());
+        let handle = block_on(result.handle()).unwrap().unwrap();
+        assert_eq!(handle.uri(), "/status/2");
+
+        let mut result = analytics_result(None);
+        assert!(block_on(result.handle()).unwrap().is_none());
+    }
+
+    #[derive(Debug, serde_derive::Deserialize, PartialEq)]
+    struct Airline<'a> {
+        name: &'a str,
+        code: &'a [u8],
+    }
+
+    #[test]
+    fn borrows_json_content_with_and_without_flags() {
+        let content = br#"{"name":"Couch Air","code":"CA"}"#.to_vec();
+        let expected = Airline {
+            name: "Couch Air",
+            code: b"CA",
+        };
+
+        let flagged = GetResult::new(content.clone(), 1, JSON_COMMON_FLAGS);
+        assert_eq!(flagged.content_as_borrowed::<Airline>().unwrap(), expected);
+        // Documents written without common flags, like by legacy clients
+        let unflagged = GetResult::new(content.clone(), 1, 0);
+        assert_eq!(
+            unflagged.content_as_borrowed::<Airline>().unwrap(),
+            expected
+        );
+
+        let binary = GetResult::new(content, 1, BINARY_COMMON_FLAGS);
+        match binary.content_as_borrowed::<Airline>() {
+            Err(CouchbaseError::DecodingFailure { .. }) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fails_the_analytics_handle_of_cancelled_queries() {
+        let (_rows_sender, rows) = mpsc::unbounded();
+        let (_, meta) = oneshot::channel::<AnalyticsMetaData>();
+        let mut result = AnalyticsResult::new(rows, meta);
+        match block_on(result.handle()) {
+            Err(CouchbaseError::RequestCanceled { .. }) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
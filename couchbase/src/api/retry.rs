@@ -0,0 +1,137 @@
+use std::fmt::Debug;
+use std::time::Duration;
+
+/// Why an operation is about to be retried.
+///
+/// Reasons which always require a retry, like `KvNotMyVbucket` during a rebalance, are retried
+/// regardless of the strategy and only listed for completeness.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RetryReason {
+    Unknown,
+    /// No connection to the node is available (yet).
+    SocketNotAvailable,
+    /// The service is not available on any node.
+    ServiceNotAvailable,
+    /// The node the operation has to go to is not part of the cluster map (yet).
+    NodeNotAvailable,
+    /// The partition moved to another node.
+    KvNotMyVbucket,
+    /// The collection id is not known to the node, i.e. because the collection was recreated.
+    KvCollectionOutdated,
+    /// The error map of the server marks the status as retryable.
+    KvErrorMapRetryIndicated,
+    /// The document is locked.
+    KvLocked,
+    /// The server is temporarily unable to process the operation, i.e. out of memory.
+    KvTemporaryFailure,
+    /// A synchronous write on the document is in progress.
+    KvSyncWriteInProgress,
+    /// A synchronous write on the document is being re-committed.
+    KvSyncWriteReCommitInProgress,
+    /// The status returned by a service marks the request as retryable.
+    ServiceResponseCodeIndicated,
+    /// The connection was closed while the operation was in flight, so it may or may not
+    /// have been applied.
+    SocketClosedWhileInFlight,
+    /// The circuit breaker for the endpoint is open.
+    CircuitBreakerOpen,
+    /// The prepared statement of a query has to be prepared again.
+    QueryPreparedStatementFailure,
+    /// The analytics service is temporarily unable to process the request.
+    AnalyticsTemporaryFailure,
+    /// The search service rejected the request because of rate limiting.
+    SearchTooManyRequests,
+}
+
+impl RetryReason {
+    /// Whether the reason guarantees that the operation has not been applied, so even
+    /// non-idempotent operations (i.e. mutations) can be retried safely.
+    pub fn allows_non_idempotent_retry(self) -> bool {
+        !matches!(
+            self,
+            RetryReason::Unknown | RetryReason::SocketClosedWhileInFlight
+        )
+    }
+
+    /// Whether the operation is retried without asking the strategy.
+    pub fn always_retry(self) -> bool {
+        matches!(
+            self,
+            RetryReason::KvNotMyVbucket | RetryReason::KvCollectionOutdated
+        )
+    }
+}
+
+/// The operation a `RetryStrategy` decides about.
+#[derive(Debug, Clone)]
+pub struct RetryRequest {
+    pub(crate) attempts: u32,
+    pub(crate) idempotent: bool,
+}
+
+impl RetryRequest {
+    /// How often the operation has been retried already.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Whether the operation can be applied more than once without changing the outcome,
+    /// which is the case for reads.
+    pub fn idempotent(&self) -> bool {
+        self.idempotent
+    }
+}
+
+/// The decision of a `RetryStrategy`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RetryAction {
+    pub(crate) retry_after: Option<Duration>,
+}
+
+impl RetryAction {
+    /// Retries the operation after the given delay.
+    pub fn retry_after(delay: Duration) -> Self {
+        Self {
+            retry_after: Some(delay),
+        }
+    }
+
+    /// Fails the operation with the error which caused the retry.
+    pub fn no_retry() -> Self {
+        Self { retry_after: None }
+    }
+}
+
+/// Decides whether and when a failed operation is retried.
+///
+/// A strategy is passed per operation through its options, i.e. `GetOptions::retry_strategy`.
+/// Retries still count against the `RetryBudget` and the operation still fails with a timeout
+/// once its timeout passed, whatever the strategy decides.
+pub trait RetryStrategy: Debug + Send + Sync {
+    fn retry_after(&self, request: &RetryRequest, reason: RetryReason) -> RetryAction;
+}
+
+/// Retries idempotent operations for every reason and the others whenever it is safe to do
+/// so, backing off exponentially up to 500 milliseconds.
+#[derive(Debug, Default)]
+pub struct BestEffortRetryStrategy;
+
+impl RetryStrategy for BestEffortRetryStrategy {
+    fn retry_after(&self, request: &RetryRequest, reason: RetryReason) -> RetryAction {
+        if !request.idempotent && !reason.allows_non_idempotent_retry() {
+            return RetryAction::no_retry();
+        }
+        let delay = 2u64.saturating_pow(request.attempts.min(10)).min(500);
+        RetryAction::retry_after(Duration::from_millis(delay))
+    }
+}
+
+/// Never retries, so operations fail with the first error they run into.
+#[derive(Debug, Default)]
+pub struct FailFastRetryStrategy;
+
+impl RetryStrategy for FailFastRetryStrategy {
+    fn retry_after(&self, _request: &RetryRequest, _reason: RetryReason) -> RetryAction {
+        RetryAction::no_retry()
+    }
+}
@@ -0,0 +1,134 @@
+use crate::api::results::ServiceType;
+use crate::io::{backend_version, BACKEND, DEFAULT_TIMEOUTS};
+use serde_json::{json, Map, Value};
+use std::time::Duration;
+
+/// The cargo features this crate has been compiled with.
+const FEATURES: &[(&str, bool)] = &[
+    ("libcouchbase", cfg!(feature = "libcouchbase")),
+    ("libcouchbase-static", cfg!(feature = "libcouchbase-static")),
+    ("uncomitted", cfg!(feature = "uncomitted")),
+    ("volatile", cfg!(feature = "volatile")),
+    ("packet-dump", cfg!(feature = "packet-dump")),
+    ("read-cache", cfg!(feature = "read-cache")),
+    ("http-compression", cfg!(feature = "http-compression")),
+    ("msgpack", cfg!(feature = "msgpack")),
+    ("cbor", cfg!(feature = "cbor")),
+    ("smoke", cfg!(feature = "smoke")),
+    ("opentelemetry", cfg!(feature = "opentelemetry")),
+    ("metrics", cfg!(feature = "metrics")),
+];
+
+/// Describes the SDK compiled into the running binary.
+///
+/// All information is static, so no cluster needs to be connected. Use `to_json` to get a
+/// machine-readable report whose format is kept stable across releases.
+#[derive(Debug, Clone)]
+pub struct SdkInfo {
+    backend_version: String,
+}
+
+/// Returns information about the SDK compiled into the running binary.
+///
+/// ```no_run
+/// let info = couchbase::sdk_info();
+/// println!("{}", info.to_json());
+/// ```
+pub fn sdk_info() -> SdkInfo {
+    SdkInfo {
+        backend_version: backend_version(),
+    }
+}
+
+impl SdkInfo {
+    /// The version of this crate.
+    pub fn version(&self) -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    /// The cargo features enabled at compile time.
+    pub fn features(&self) -> Vec<&'static str> {
+        FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    /// The name of the IO backend, like `libcouchbase`.
+    pub fn backend(&self) -> &'static str {
+        BACKEND
+    }
+
+    /// The version of the IO backend.
+    pub fn backend_version(&self) -> &str {
+        &self.backend_version
+    }
+
+    /// The timeout applied to requests of the given service which do not set their own.
+    pub fn default_timeout(&self, service: ServiceType) -> Option<Duration> {
+        DEFAULT_TIMEOUTS
+            .iter()
+            .find(|(s, _)| *s == service)
+            .map(|(_, timeout)| *timeout)
+    }
+
+    /// Renders the information as JSON.
+    ///
+    /// ```json
+    /// {
+    ///   "version": 1,
+    ///   "sdk": "couchbase-rs",
+    ///   "sdk_version": "1.0.0-alpha.5",
+    ///   "features": ["libcouchbase"],
+    ///   "backend": "libcouchbase",
+    ///   "backend_version": "3.0.5",
+    ///   "default_timeouts_ms": {"kv": 2500, "query": 75000}
+    /// }
+    /// ```
+    pub fn to_json(&self) -> Value {
+        let timeouts: Map<String, Value> = DEFAULT_TIMEOUTS
+            .iter()
+            .map(|(service, timeout)| {
+                (
+                    service.report_name().into(),
+                    Value::from(timeout.as_millis() as u64),
+                )
+            })
+            .collect();
+        json!({
+            "version": 1,
+            "sdk": "couchbase-rs",
+            "sdk_version": self.version(),
+            "features": self.features(),
+            "backend": self.backend(),
+            "backend_version": self.backend_version,
+            "default_timeouts_ms": timeouts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_every_feature_of_the_crate() {
+        let manifest = include_str!("../../Cargo.toml");
+        let features = manifest
+            .split("[features]")
+            .nth(1)
+            .unwrap()
+            .lines()
+            .take_while(|line| !line.starts_with('['))
+            .filter_map(|line| line.split_once(" = ").map(|(name, _)| name.trim()))
+            .filter(|name| !name.starts_with('#') && *name != "default");
+        for feature in features {
+            assert!(
+                FEATURES.iter().any(|(name, _)| *name == feature),
+                "the {} feature is not listed",
+                feature
+            );
+        }
+    }
+}
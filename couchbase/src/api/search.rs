@@ -1,3 +1,4 @@
+use serde_derive::Serialize;
 use serde_json::json;
 
 pub trait SearchQuery {
@@ -21,3 +22,986 @@ impl SearchQuery for QueryStringQuery {
         })
     }
 }
+
+/// Matches no documents, used to run a search made up of vector searches only.
+pub struct MatchNoneQuery;
+
+impl SearchQuery for MatchNoneQuery {
+    fn to_json(&self) -> serde_json::Value {
+        json!({ "match_none": {} })
+    }
+}
+
+/// Matches all documents.
+pub struct MatchAllQuery;
+
+impl SearchQuery for MatchAllQuery {
+    fn to_json(&self) -> serde_json::Value {
+        json!({ "match_all": {} })
+    }
+}
+
+/// Macro to DRY up the setters shared by the queries which search a single field.
+macro_rules! field_and_boost {
+    () => {
+        /// The field to search, the default field of the index if not set.
+        pub fn field<S: Into<String>>(mut self, field: S) -> Self {
+            self.field = Some(field.into());
+            self
+        }
+
+        /// Weighs the score of this query against the other queries of a compound query.
+        pub fn boost(mut self, boost: f32) -> Self {
+            self.boost = Some(boost);
+            self
+        }
+    };
+}
+
+/// Adds the field and boost to the JSON of a query, if set.
+fn with_field_and_boost(
+    mut json: serde_json::Value,
+    field: &Option<String>,
+    boost: Option<f32>,
+) -> serde_json::Value {
+    if let Some(field) = field {
+        json["field"] = json!(field);
+    }
+    if let Some(boost) = boost {
+        json["boost"] = json!(boost);
+    }
+    json
+}
+
+/// Matches the documents with the given ids.
+#[derive(Debug, Default)]
+pub struct DocIdQuery {
+    ids: Vec<String>,
+    boost: Option<f32>,
+}
+
+impl DocIdQuery {
+    pub fn new<I, S>(ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            ids: ids.into_iter().map(Into::into).collect(),
+            boost: None,
+        }
+    }
+
+    /// Weighs the score of this query against the other queries of a compound query.
+    pub fn boost(mut self, boost: f32) -> Self {
+        self.boost = Some(boost);
+        self
+    }
+}
+
+impl SearchQuery for DocIdQuery {
+    fn to_json(&self) -> serde_json::Value {
+        with_field_and_boost(json!({ "ids": &self.ids }), &None, self.boost)
+    }
+}
+
+/// Matches the documents whose boolean field has the given value.
+#[derive(Debug)]
+pub struct BooleanFieldQuery {
+    value: bool,
+    field: Option<String>,
+    boost: Option<f32>,
+}
+
+impl BooleanFieldQuery {
+    pub fn new(value: bool) -> Self {
+        Self {
+            value,
+            field: None,
+            boost: None,
+        }
+    }
+
+    field_and_boost!();
+}
+
+impl SearchQuery for BooleanFieldQuery {
+    fn to_json(&self) -> serde_json::Value {
+        with_field_and_boost(json!({ "bool": self.value }), &self.field, self.boost)
+    }
+}
+
+/// Matches the terms against a pattern, where `*` matches any number of characters and `?`
+/// exactly one.
+#[derive(Debug)]
+pub struct WildcardQuery {
+    wildcard: String,
+    field: Option<String>,
+    boost: Option<f32>,
+}
+
+impl WildcardQuery {
+    pub fn new<S: Into<String>>(wildcard: S) -> Self {
+        Self {
+            wildcard: wildcard.into(),
+            field: None,
+            boost: None,
+        }
+    }
+
+    field_and_boost!();
+}
+
+impl SearchQuery for WildcardQuery {
+    fn to_json(&self) -> serde_json::Value {
+        with_field_and_boost(
+            json!({ "wildcard": &self.wildcard }),
+            &self.field,
+            self.boost,
+        )
+    }
+}
+
+/// Matches the terms against a regular expression, which has to match the whole term.
+#[derive(Debug)]
+pub struct RegexpQuery {
+    regexp: String,
+    field: Option<String>,
+    boost: Option<f32>,
+}
+
+impl RegexpQuery {
+    pub fn new<S: Into<String>>(regexp: S) -> Self {
+        Self {
+            regexp: regexp.into(),
+            field: None,
+            boost: None,
+        }
+    }
+
+    field_and_boost!();
+}
+
+impl SearchQuery for RegexpQuery {
+    fn to_json(&self) -> serde_json::Value {
+        with_field_and_boost(json!({ "regexp": &self.regexp }), &self.field, self.boost)
+    }
+}
+
+/// Matches the documents whose numeric field is in a range.
+///
+/// At least one of the bounds has to be set. The minimum is inclusive and the maximum
+/// exclusive by default.
+#[derive(Debug, Default)]
+pub struct NumericRangeQuery {
+    min: Option<(f64, bool)>,
+    max: Option<(f64, bool)>,
+    field: Option<String>,
+    boost: Option<f32>,
+}
+
+impl NumericRangeQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn min(mut self, min: f64, inclusive: bool) -> Self {
+        self.min = Some((min, inclusive));
+        self
+    }
+
+    pub fn max(mut self, max: f64, inclusive: bool) -> Self {
+        self.max = Some((max, inclusive));
+        self
+    }
+
+    field_and_boost!();
+}
+
+impl SearchQuery for NumericRangeQuery {
+    fn to_json(&self) -> serde_json::Value {
+        let mut json = json!({});
+        if let Some((min, inclusive)) = self.min {
+            json["min"] = json!(min);
+            json["inclusive_min"] = json!(inclusive);
+        }
+        if let Some((max, inclusive)) = self.max {
+            json["max"] = json!(max);
+            json["inclusive_max"] = json!(inclusive);
+        }
+        with_field_and_boost(json, &self.field, self.boost)
+    }
+}
+
+/// Matches the documents whose date field is in a range, with the dates as RFC 3339 strings
+/// unless a custom date parser is set.
+///
+/// At least one of the bounds has to be set. The start is inclusive and the end exclusive by
+/// default.
+#[derive(Debug, Default)]
+pub struct DateRangeQuery {
+    start: Option<(String, bool)>,
+    end: Option<(String, bool)>,
+    datetime_parser: Option<String>,
+    field: Option<String>,
+    boost: Option<f32>,
+}
+
+impl DateRangeQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start<S: Into<String>>(mut self, start: S, inclusive: bool) -> Self {
+        self.start = Some((start.into(), inclusive));
+        self
+    }
+
+    pub fn end<S: Into<String>>(mut self, end: S, inclusive: bool) -> Self {
+        self.end = Some((end.into(), inclusive));
+        self
+    }
+
+    /// The name of the date time parser of the index to parse the bounds with.
+    pub fn datetime_parser<S: Into<String>>(mut self, parser: S) -> Self {
+        self.datetime_parser = Some(parser.into());
+        self
+    }
+
+    field_and_boost!();
+}
+
+impl SearchQuery for DateRangeQuery {
+    fn to_json(&self) -> serde_json::Value {
+        let mut json = json!({});
+        if let Some((start, inclusive)) = &self.start {
+            json["start"] = json!(start);
+            json["inclusive_start"] = json!(inclusive);
+        }
+        if let Some((end, inclusive)) = &self.end {
+            json["end"] = json!(end);
+            json["inclusive_end"] = json!(inclusive);
+        }
+        if let Some(parser) = &self.datetime_parser {
+            json["datetime_parser"] = json!(parser);
+        }
+        with_field_and_boost(json, &self.field, self.boost)
+    }
+}
+
+/// Matches the documents with a term in a lexicographic range.
+///
+/// At least one of the bounds has to be set. The minimum is inclusive and the maximum
+/// exclusive by default.
+#[derive(Debug, Default)]
+pub struct TermRangeQuery {
+    min: Option<(String, bool)>,
+    max: Option<(String, bool)>,
+    field: Option<String>,
+    boost: Option<f32>,
+}
+
+impl TermRangeQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn min<S: Into<String>>(mut self, min: S, inclusive: bool) -> Self {
+        self.min = Some((min.into(), inclusive));
+        self
+    }
+
+    pub fn max<S: Into<String>>(mut self, max: S, inclusive: bool) -> Self {
+        self.max = Some((max.into(), inclusive));
+        self
+    }
+
+    field_and_boost!();
+}
+
+impl SearchQuery for TermRangeQuery {
+    fn to_json(&self) -> serde_json::Value {
+        let mut json = json!({});
+        if let Some((min, inclusive)) = &self.min {
+            json["min"] = json!(min);
+            json["inclusive_min"] = json!(inclusive);
+        }
+        if let Some((max, inclusive)) = &self.max {
+            json["max"] = json!(max);
+            json["inclusive_max"] = json!(inclusive);
+        }
+        with_field_and_boost(json, &self.field, self.boost)
+    }
+}
+
+/// Matches the documents whose geo point field is within a distance of a location.
+///
+/// The distance includes its unit, i.e. `"10mi"` or `"5km"`.
+#[derive(Debug)]
+pub struct GeoDistanceQuery {
+    longitude: f64,
+    latitude: f64,
+    distance: String,
+    field: Option<String>,
+    boost: Option<f32>,
+}
+
+impl GeoDistanceQuery {
+    pub fn new<S: Into<String>>(longitude: f64, latitude: f64, distance: S) -> Self {
+        Self {
+            longitude,
+            latitude,
+            distance: distance.into(),
+            field: None,
+            boost: None,
+        }
+    }
+
+    field_and_boost!();
+}
+
+impl SearchQuery for GeoDistanceQuery {
+    fn to_json(&self) -> serde_json::Value {
+        let json = json!({
+            "location": [self.longitude, self.latitude],
+            "distance": &self.distance,
+        });
+        with_field_and_boost(json, &self.field, self.boost)
+    }
+}
+
+/// Matches the documents whose geo point field is within a rectangle, given by its top left
+/// and bottom right corners as `(longitude, latitude)`.
+#[derive(Debug)]
+pub struct GeoBoundingBoxQuery {
+    top_left: (f64, f64),
+    bottom_right: (f64, f64),
+    field: Option<String>,
+    boost: Option<f32>,
+}
+
+impl GeoBoundingBoxQuery {
+    pub fn new(top_left: (f64, f64), bottom_right: (f64, f64)) -> Self {
+        Self {
+            top_left,
+            bottom_right,
+            field: None,
+            boost: None,
+        }
+    }
+
+    field_and_boost!();
+}
+
+impl SearchQuery for GeoBoundingBoxQuery {
+    fn to_json(&self) -> serde_json::Value {
+        let json = json!({
+            "top_left": [self.top_left.0, self.top_left.1],
+            "bottom_right": [self.bottom_right.0, self.bottom_right.1],
+        });
+        with_field_and_boost(json, &self.field, self.boost)
+    }
+}
+
+/// Matches the documents whose geo point field is within a polygon, given by its corners as
+/// `(longitude, latitude)`.
+#[derive(Debug)]
+pub struct GeoPolygonQuery {
+    points: Vec<(f64, f64)>,
+    field: Option<String>,
+    boost: Option<f32>,
+}
+
+impl GeoPolygonQuery {
+    pub fn new(points: Vec<(f64, f64)>) -> Self {
+        Self {
+            points,
+            field: None,
+            boost: None,
+        }
+    }
+
+    field_and_boost!();
+}
+
+impl SearchQuery for GeoPolygonQuery {
+    fn to_json(&self) -> serde_json::Value {
+        let points: Vec<[f64; 2]> = self.points.iter().map(|(lon, lat)| [*lon, *lat]).collect();
+        with_field_and_boost(json!({ "polygon_points": points }), &self.field, self.boost)
+    }
+}
+
+/// Matches the documents which match all of the queries.
+#[derive(Debug, Default)]
+pub struct ConjunctionQuery {
+    queries: Vec<serde_json::Value>,
+    boost: Option<f32>,
+}
+
+impl ConjunctionQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn and<T: SearchQuery>(mut self, query: T) -> Self {
+        self.queries.push(query.to_json());
+        self
+    }
+
+    /// Weighs the score of this query against the other queries of a compound query.
+    pub fn boost(mut self, boost: f32) -> Self {
+        self.boost = Some(boost);
+        self
+    }
+}
+
+impl SearchQuery for ConjunctionQuery {
+    fn to_json(&self) -> serde_json::Value {
+        with_field_and_boost(json!({ "conjuncts": &self.queries }), &None, self.boost)
+    }
+}
+
+/// Matches the documents which match at least `min` of the queries, one by default.
+#[derive(Debug, Default)]
+pub struct DisjunctionQuery {
+    queries: Vec<serde_json::Value>,
+    min: Option<u32>,
+    boost: Option<f32>,
+}
+
+impl DisjunctionQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn or<T: SearchQuery>(mut self, query: T) -> Self {
+        self.queries.push(query.to_json());
+        self
+    }
+
+    pub fn min(mut self, min: u32) -> Self {
+        self.min = Some(min);
+        self
+    }
+
+    /// Weighs the score of this query against the other queries of a compound query.
+    pub fn boost(mut self, boost: f32) -> Self {
+        self.boost = Some(boost);
+        self
+    }
+}
+
+impl SearchQuery for DisjunctionQuery {
+    fn to_json(&self) -> serde_json::Value {
+        let mut json = json!({ "disjuncts": &self.queries });
+        if let Some(min) = self.min {
+            json["min"] = json!(min);
+        }
+        with_field_and_boost(json, &None, self.boost)
+    }
+}
+
+/// Combines queries the documents must, should and must not match.
+///
+/// Documents have to match all `must` queries, none of the `must_not` queries and at least
+/// `should_min` of the `should` queries, where matching more of them raises the score.
+#[derive(Debug, Default)]
+pub struct BooleanQuery {
+    must: ConjunctionQuery,
+    should: DisjunctionQuery,
+    must_not: DisjunctionQuery,
+    boost: Option<f32>,
+}
+
+impl BooleanQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn must<T: SearchQuery>(mut self, query: T) -> Self {
+        self.must = self.must.and(query);
+        self
+    }
+
+    pub fn should<T: SearchQuery>(mut self, query: T) -> Self {
+        self.should = self.should.or(query);
+        self
+    }
+
+    pub fn must_not<T: SearchQuery>(mut self, query: T) -> Self {
+        self.must_not = self.must_not.or(query);
+        self
+    }
+
+    /// How many of the `should` queries a document has to match, one by default.
+    pub fn should_min(mut self, min: u32) -> Self {
+        self.should = self.should.min(min);
+        self
+    }
+
+    /// Weighs the score of this query against the other queries of a compound query.
+    pub fn boost(mut self, boost: f32) -> Self {
+        self.boost = Some(boost);
+        self
+    }
+}
+
+impl SearchQuery for BooleanQuery {
+    fn to_json(&self) -> serde_json::Value {
+        let mut json = json!({});
+        if !self.must.queries.is_empty() {
+            json["must"] = self.must.to_json();
+        }
+        if !self.should.queries.is_empty() {
+            json["should"] = self.should.to_json();
+        }
+        if !self.must_not.queries.is_empty() {
+            json["must_not"] = self.must_not.to_json();
+        }
+        with_field_and_boost(json, &None, self.boost)
+    }
+}
+
+/// Finds the documents whose vector field is nearest to the given vector.
+///
+/// Vector queries are passed through `SearchOptions::vector_search`, on their own or combined
+/// into a `VectorSearch`.
+#[derive(Debug)]
+pub struct VectorQuery {
+    field: String,
+    vector: Vec<f32>,
+    num_candidates: u32,
+    boost: Option<f32>,
+    prefilter: Option<serde_json::Value>,
+}
+
+impl VectorQuery {
+    pub fn new<S: Into<String>>(field: S, vector: Vec<f32>) -> Self {
+        Self {
+            field: field.into(),
+            vector,
+            num_candidates: 3,
+            boost: None,
+            prefilter: None,
+        }
+    }
+
+    /// The number of nearest neighbours to return, 3 by default.
+    pub fn num_candidates(mut self, num_candidates: u32) -> Self {
+        self.num_candidates = num_candidates;
+        self
+    }
+
+    /// Weighs the score of this vector query against the other queries of the search.
+    pub fn boost(mut self, boost: f32) -> Self {
+        self.boost = Some(boost);
+        self
+    }
+
+    /// Only considers the documents matching the given query as neighbours.
+    pub fn prefilter<T: SearchQuery>(mut self, query: T) -> Self {
+        self.prefilter = Some(query.to_json());
+        self
+    }
+}
+
+impl SearchQuery for VectorQuery {
+    fn to_json(&self) -> serde_json::Value {
+        let mut json = json!({
+            "field": &self.field,
+            "vector": &self.vector,
+            "k": self.num_candidates,
+        });
+        if let Some(boost) = self.boost {
+            json["boost"] = json!(boost);
+        }
+        if let Some(prefilter) = &self.prefilter {
+            json["filter"] = prefilter.clone();
+        }
+        json
+    }
+}
+
+/// How the results of multiple vector queries are combined.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VectorQueryCombination {
+    /// Only returns documents which match all vector queries.
+    And,
+    /// Returns documents which match any vector query, the default.
+    Or,
+}
+
+/// What to do when some partitions of the search index fail, see
+/// `SearchOptions::partial_results`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum PartialSearchResults {
+    /// Returns the hits of the partitions which succeeded, the failures are reported through
+    /// `SearchMetaData::errors`. The default.
+    #[default]
+    Allow,
+    /// Fails the search with `PartialSearchFailure`.
+    Fail,
+}
+
+/// One or more vector queries, passed through `SearchOptions::vector_search`.
+///
+/// Vector searches run alongside the regular search query and their results are combined.
+/// Use a `MatchNoneQuery` as search query to run a pure vector search.
+#[derive(Debug)]
+pub struct VectorSearch {
+    pub(crate) queries: Vec<VectorQuery>,
+    pub(crate) combination: Option<VectorQueryCombination>,
+}
+
+impl VectorSearch {
+    pub fn new(queries: Vec<VectorQuery>) -> Self {
+        Self {
+            queries,
+            combination: None,
+        }
+    }
+
+    pub fn combination(mut self, combination: VectorQueryCombination) -> Self {
+        self.combination = Some(combination);
+        self
+    }
+}
+
+impl From<VectorQuery> for VectorSearch {
+    fn from(query: VectorQuery) -> Self {
+        Self::new(vec![query])
+    }
+}
+
+/// Defines how the search results are sorted, passed through `SearchOptions::sort`.
+pub trait SearchSort {
+    fn to_json(&self) -> serde_json::Value;
+}
+
+/// Sorts by the score of the hits, highest score first unless descending is turned off.
+#[derive(Debug, Default)]
+pub struct SearchSortScore {
+    descending: Option<bool>,
+}
+
+impl SearchSortScore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn descending(mut self, descending: bool) -> Self {
+        self.descending = Some(descending);
+        self
+    }
+}
+
+impl SearchSort for SearchSortScore {
+    fn to_json(&self) -> serde_json::Value {
+        let mut json = json!({ "by": "score" });
+        if let Some(descending) = self.descending {
+            json["desc"] = json!(descending);
+        }
+        json
+    }
+}
+
+/// Sorts by the document id of the hits.
+#[derive(Debug, Default)]
+pub struct SearchSortId {
+    descending: Option<bool>,
+}
+
+impl SearchSortId {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn descending(mut self, descending: bool) -> Self {
+        self.descending = Some(descending);
+        self
+    }
+}
+
+impl SearchSort for SearchSortId {
+    fn to_json(&self) -> serde_json::Value {
+        let mut json = json!({ "by": "id" });
+        if let Some(descending) = self.descending {
+            json["desc"] = json!(descending);
+        }
+        json
+    }
+}
+
+/// How the values of a field are interpreted when sorting.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchSortFieldType {
+    Auto,
+    String,
+    Number,
+    Date,
+}
+
+/// Which value is used when sorting by a field with multiple values.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchSortFieldMode {
+    Default,
+    Min,
+    Max,
+}
+
+/// Where hits without a value for the field end up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchSortFieldMissing {
+    First,
+    Last,
+}
+
+/// Sorts by the value of a field of the hits.
+#[derive(Debug)]
+pub struct SearchSortField {
+    field: String,
+    descending: Option<bool>,
+    typ: Option<SearchSortFieldType>,
+    mode: Option<SearchSortFieldMode>,
+    missing: Option<SearchSortFieldMissing>,
+}
+
+impl SearchSortField {
+    pub fn new<S: Into<String>>(field: S) -> Self {
+        Self {
+            field: field.into(),
+            descending: None,
+            typ: None,
+            mode: None,
+            missing: None,
+        }
+    }
+
+    pub fn descending(mut self, descending: bool) -> Self {
+        self.descending = Some(descending);
+        self
+    }
+
+    pub fn typ(mut self, typ: SearchSortFieldType) -> Self {
+        self.typ = Some(typ);
+        self
+    }
+
+    pub fn mode(mut self, mode: SearchSortFieldMode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    pub fn missing(mut self, missing: SearchSortFieldMissing) -> Self {
+        self.missing = Some(missing);
+        self
+    }
+}
+
+impl SearchSort for SearchSortField {
+    fn to_json(&self) -> serde_json::Value {
+        let mut json = json!({ "by": "field", "field": &self.field });
+        if let Some(descending) = self.descending {
+            json["desc"] = json!(descending);
+        }
+        if let Some(typ) = self.typ {
+            json["type"] = json!(typ);
+        }
+        if let Some(mode) = self.mode {
+            json["mode"] = json!(mode);
+        }
+        if let Some(missing) = self.missing {
+            json["missing"] = json!(missing);
+        }
+        json
+    }
+}
+
+/// The unit of the distances used by geo distance sorting.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchGeoDistanceUnits {
+    Meters,
+    Miles,
+    Centimeters,
+    Millimeters,
+    NauticalMiles,
+    Kilometers,
+    Feet,
+    Yards,
+    Inch,
+}
+
+/// Sorts by the distance between a geo point field of the hits and a location.
+#[derive(Debug)]
+pub struct SearchSortGeoDistance {
+    field: String,
+    longitude: f64,
+    latitude: f64,
+    descending: Option<bool>,
+    unit: Option<SearchGeoDistanceUnits>,
+}
+
+impl SearchSortGeoDistance {
+    pub fn new<S: Into<String>>(field: S, longitude: f64, latitude: f64) -> Self {
+        Self {
+            field: field.into(),
+            longitude,
+            latitude,
+            descending: None,
+            unit: None,
+        }
+    }
+
+    pub fn descending(mut self, descending: bool) -> Self {
+        self.descending = Some(descending);
+        self
+    }
+
+    pub fn unit(mut self, unit: SearchGeoDistanceUnits) -> Self {
+        self.unit = Some(unit);
+        self
+    }
+}
+
+impl SearchSort for SearchSortGeoDistance {
+    fn to_json(&self) -> serde_json::Value {
+        let mut json = json!({
+            "by": "geo_distance",
+            "field": &self.field,
+            "location": [self.longitude, self.latitude],
+        });
+        if let Some(descending) = self.descending {
+            json["desc"] = json!(descending);
+        }
+        if let Some(unit) = self.unit {
+            json["unit"] = json!(unit);
+        }
+        json
+    }
+}
+
+/// The markup used to highlight the matches in the returned fragments.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HighlightStyle {
+    Html,
+    Ansi,
+}
+
+/// Counts the hits by the values of a field, passed through `SearchOptions::facet`.
+pub trait SearchFacet {
+    fn to_json(&self) -> serde_json::Value;
+}
+
+/// Counts the hits by the terms of a field, returning the `size` most frequent terms.
+#[derive(Debug)]
+pub struct TermFacet {
+    field: String,
+    size: u32,
+}
+
+impl TermFacet {
+    pub fn new<S: Into<String>>(field: S, size: u32) -> Self {
+        Self {
+            field: field.into(),
+            size,
+        }
+    }
+}
+
+impl SearchFacet for TermFacet {
+    fn to_json(&self) -> serde_json::Value {
+        json!({ "field": &self.field, "size": self.size })
+    }
+}
+
+/// Counts the hits by the range a numeric field falls into.
+///
+/// A range without a minimum or maximum is open on that side.
+#[derive(Debug)]
+pub struct NumericRangeFacet {
+    field: String,
+    size: u32,
+    ranges: Vec<serde_json::Value>,
+}
+
+impl NumericRangeFacet {
+    pub fn new<S: Into<String>>(field: S, size: u32) -> Self {
+        Self {
+            field: field.into(),
+            size,
+            ranges: vec![],
+        }
+    }
+
+    pub fn add_range<S: Into<String>>(
+        mut self,
+        name: S,
+        min: Option<f64>,
+        max: Option<f64>,
+    ) -> Self {
+        let mut range = json!({ "name": name.into() });
+        if let Some(min) = min {
+            range["min"] = json!(min);
+        }
+        if let Some(max) = max {
+            range["max"] = json!(max);
+        }
+        self.ranges.push(range);
+        self
+    }
+}
+
+impl SearchFacet for NumericRangeFacet {
+    fn to_json(&self) -> serde_json::Value {
+        json!({ "field": &self.field, "size": self.size, "numeric_ranges": &self.ranges })
+    }
+}
+
+/// Counts the hits by the range a date field falls into, with the dates as RFC 3339 strings.
+///
+/// A range without a start or end is open on that side.
+#[derive(Debug)]
+pub struct DateRangeFacet {
+    field: String,
+    size: u32,
+    ranges: Vec<serde_json::Value>,
+}
+
+impl DateRangeFacet {
+    pub fn new<S: Into<String>>(field: S, size: u32) -> Self {
+        Self {
+            field: field.into(),
+            size,
+            ranges: vec![],
+        }
+    }
+
+    pub fn add_range<S: Into<String>>(
+        mut self,
+        name: S,
+        start: Option<String>,
+        end: Option<String>,
+    ) -> Self {
+        let mut range = json!({ "name": name.into() });
+        if let Some(start) = start {
+            range["start"] = json!(start);
+        }
+        if let Some(end) = end {
+            range["end"] = json!(end);
+        }
+        self.ranges.push(range);
+        self
+    }
+}
+
+impl SearchFacet for DateRangeFacet {
+    fn to_json(&self) -> serde_json::Value {
+        json!({ "field": &self.field, "size": self.size, "date_ranges": &self.ranges })
+    }
+}
@@ -0,0 +1,394 @@
+use crate::api::features::{self, ServerFeature};
+use crate::io::request::*;
+use crate::io::Core;
+use crate::CouchbaseError::{IndexExists, IndexNotFound};
+use crate::{
+    CouchbaseError, CouchbaseResult, DropSearchIndexOptions, ErrorContext, GenericManagementResult,
+    GetAllSearchIndexesOptions, GetSearchIndexOptions, ServiceType, UpsertSearchIndexOptions,
+};
+use futures::channel::oneshot;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const ALIAS_INDEX_TYPE: &str = "fulltext-alias";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchIndex {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    uuid: String,
+    name: String,
+    #[serde(rename = "type")]
+    index_type: String,
+    #[serde(default)]
+    params: Value,
+    #[serde(rename = "sourceType", default)]
+    source_type: String,
+    #[serde(rename = "sourceName", default)]
+    source_name: String,
+    #[serde(
+        rename = "sourceUUID",
+        default,
+        skip_serializing_if = "String::is_empty"
+    )]
+    source_uuid: String,
+    #[serde(rename = "sourceParams", default)]
+    source_params: Value,
+    #[serde(rename = "planParams", default)]
+    plan_params: Value,
+}
+
+impl SearchIndex {
+    /// Creates a new full text index definition on top of the given bucket.
+    pub fn new<S: Into<String>>(name: S, source_name: S) -> Self {
+        Self {
+            uuid: String::new(),
+            name: name.into(),
+            index_type: String::from("fulltext-index"),
+            params: json!({}),
+            source_type: String::from("couchbase"),
+            source_name: source_name.into(),
+            source_uuid: String::new(),
+            source_params: json!({}),
+            plan_params: json!({}),
+        }
+    }
+
+    /// Creates a new index alias definition which points to the given target indexes.
+    ///
+    /// Searching an alias works exactly like searching an index, so the targets can be swapped
+    /// by upserting the alias again without changing the application.
+    pub fn alias<S: Into<String>>(name: S, targets: Vec<String>) -> Self {
+        let targets: HashMap<String, Value> = targets.into_iter().map(|t| (t, json!({}))).collect();
+        Self {
+            uuid: String::new(),
+            name: name.into(),
+            index_type: String::from(ALIAS_INDEX_TYPE),
+            params: json!({ "targets": targets }),
+            source_type: String::from("nil"),
+            source_name: String::new(),
+            source_uuid: String::new(),
+            source_params: json!({}),
+            plan_params: json!({}),
+        }
+    }
+
+    pub fn params(mut self, params: Value) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn source_params(mut self, source_params: Value) -> Self {
+        self.source_params = source_params;
+        self
+    }
+
+    pub fn plan_params(mut self, plan_params: Value) -> Self {
+        self.plan_params = plan_params;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn uuid(&self) -> &str {
+        &self.uuid
+    }
+
+    pub fn index_type(&self) -> &str {
+        &self.index_type
+    }
+
+    pub fn source_name(&self) -> &str {
+        &self.source_name
+    }
+
+    pub fn is_alias(&self) -> bool {
+        self.index_type == ALIAS_INDEX_TYPE
+    }
+
+    /// Returns the names of the indexes this alias points to, empty if this is not an alias.
+    pub fn alias_targets(&self) -> Vec<String> {
+        match self.params.get("targets") {
+            Some(Value::Object(targets)) if self.is_alias() => targets.keys().cloned().collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Drops everything which ties the definition to the cluster it was read from.
+    ///
+    /// The UUIDs of the index and of its bucket differ between clusters, and the node plan
+    /// parameters refer to nodes of the source cluster.
+    fn into_portable(mut self) -> Self {
+        self.uuid = String::new();
+        self.source_uuid = String::new();
+        if let Value::Object(plan_params) = &mut self.plan_params {
+            plan_params.remove("nodePlanParams");
+        }
+        self
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchIndexResponse {
+    #[serde(rename = "indexDef")]
+    index_def: SearchIndex,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchIndexesResponse {
+    #[serde(rename = "indexDefs")]
+    index_defs: Option<SearchIndexDefs>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchIndexDefs {
+    #[serde(rename = "indexDefs", default)]
+    index_defs: HashMap<String, SearchIndex>,
+}
+
+/// Manages the search indexes of the cluster, or the ones of a single scope.
+///
+/// Indexes which belong to a scope are only visible through the manager of that scope, which
+/// requires Couchbase Server 7.6 or later.
+pub struct SearchIndexManager {
+    core: Arc<Core>,
+    scope: Option<(String, String)>,
+}
+
+impl SearchIndexManager {
+    pub(crate) fn new(core: Arc<Core>) -> Self {
+        Self { core, scope: None }
+    }
+
+    #[cfg(feature = "volatile")]
+    pub(crate) fn for_scope(core: Arc<Core>, bucket_name: String, scope_name: String) -> Self {
+        Self {
+            core,
+            scope: Some((bucket_name, scope_name)),
+        }
+    }
+
+    /// Scoped indexes need a server which supports them, the ones of the cluster do not.
+    async fn check_scope_support(&self) -> CouchbaseResult<()> {
+        match self.scope {
+            Some(_) => features::require(&self.core, ServerFeature::ScopedSearchIndexes).await,
+            None => Ok(()),
+        }
+    }
+
+    fn indexes_path(&self) -> String {
+        match &self.scope {
+            Some((bucket, scope)) => format!("/api/bucket/{}/scope/{}/index", bucket, scope),
+            None => String::from("/api/index"),
+        }
+    }
+
+    pub async fn get_index<S: Into<String>>(
+        &self,
+        index_name: S,
+        options: GetSearchIndexOptions,
+    ) -> CouchbaseResult<SearchIndex> {
+        self.check_scope_support().await?;
+        let index_name = index_name.into();
+        let (sender, receiver) = oneshot::channel();
+
+        let timer = self.core.send(Request::GenericManagementRequest(
+            GenericManagementRequest {
+                sender,
+                path: format!("{}/{}", self.indexes_path(), index_name),
+                method: String::from("get"),
+                payload: None,
+                content_type: None,
+                timeout: options.timeout,
+                service_type: Some(ServiceType::Search),
+                endpoint: None,
+            },
+        ));
+
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
+
+        match result.http_status() {
+            200 => serde_json::from_slice::<SearchIndexResponse>(result.payload().unwrap())
+                .map(|r| r.index_def)
+                .map_err(|e| CouchbaseError::DecodingFailure {
+                    ctx: ErrorContext::default(),
+                    source: e.into(),
+                }),
+            _ => Err(parse_error(&result, index_name)),
+        }
+    }
+
+    pub async fn get_all_indexes(
+        &self,
+        options: GetAllSearchIndexesOptions,
+    ) -> CouchbaseResult<Vec<SearchIndex>> {
+        self.check_scope_support().await?;
+        let (sender, receiver) = oneshot::channel();
+
+        let timer = self.core.send(Request::GenericManagementRequest(
+            GenericManagementRequest {
+                sender,
+                path: self.indexes_path(),
+                method: String::from("get"),
+                payload: None,
+                content_type: None,
+                timeout: options.timeout,
+                service_type: Some(ServiceType::Search),
+                endpoint: None,
+            },
+        ));
+
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
+
+        match result.http_status() {
+            200 => serde_json::from_slice::<SearchIndexesResponse>(result.payload().unwrap())
+                .map(|r| match r.index_defs {
+                    Some(defs) => defs.index_defs.into_values().collect(),
+                    None => vec![],
+                })
+                .map_err(|e| CouchbaseError::DecodingFailure {
+                    ctx: ErrorContext::default(),
+                    source: e.into(),
+                }),
+            _ => Err(parse_error(&result, String::new())),
+        }
+    }
+
+    /// Creates or updates an index or an index alias.
+    pub async fn upsert_index(
+        &self,
+        index: SearchIndex,
+        options: UpsertSearchIndexOptions,
+    ) -> CouchbaseResult<()> {
+        self.check_scope_support().await?;
+        let payload = match serde_json::to_string(&index) {
+            Ok(p) => p,
+            Err(e) => {
+                return Err(CouchbaseError::EncodingFailure {
+                    ctx: ErrorContext::default(),
+                    source: e.into(),
+                })
+            }
+        };
+        let (sender, receiver) = oneshot::channel();
+
+        let timer = self.core.send(Request::GenericManagementRequest(
+            GenericManagementRequest {
+                sender,
+                path: format!("{}/{}", self.indexes_path(), index.name),
+                method: String::from("put"),
+                payload: Some(payload),
+                content_type: Some(String::from("application/json")),
+                timeout: options.timeout,
+                service_type: Some(ServiceType::Search),
+                endpoint: None,
+            },
+        ));
+
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
+
+        match result.http_status() {
+            200 => Ok(()),
+            _ => Err(parse_error(&result, index.name)),
+        }
+    }
+
+    /// Returns the definition of an index as JSON, which can be stored or upserted into
+    /// another cluster through `upsert_from_json`.
+    ///
+    /// The UUIDs which tie the definition to this cluster are stripped.
+    pub async fn get_index_definition_json<S: Into<String>>(
+        &self,
+        index_name: S,
+        options: GetSearchIndexOptions,
+    ) -> CouchbaseResult<String> {
+        let index = self.get_index(index_name, options).await?.into_portable();
+        serde_json::to_string_pretty(&index).map_err(|e| CouchbaseError::EncodingFailure {
+            ctx: ErrorContext::default(),
+            source: e.into(),
+        })
+    }
+
+    /// Creates or updates an index from a JSON definition, i.e. one exported from another
+    /// cluster through `get_index_definition_json` or the web console.
+    ///
+    /// UUIDs contained in the definition are ignored. If the index already exists, it is
+    /// updated in place with the UUID of the existing index.
+    pub async fn upsert_from_json<S: AsRef<str>>(
+        &self,
+        definition: S,
+        options: UpsertSearchIndexOptions,
+    ) -> CouchbaseResult<()> {
+        let mut index = serde_json::from_str::<SearchIndex>(definition.as_ref())
+            .map_err(|e| CouchbaseError::DecodingFailure {
+                ctx: ErrorContext::default(),
+                source: e.into(),
+            })?
+            .into_portable();
+
+        let get_options = GetSearchIndexOptions {
+            timeout: options.timeout,
+        };
+        match self.get_index(index.name.clone(), get_options).await {
+            Ok(existing) => index.uuid = existing.uuid,
+            Err(IndexNotFound { .. }) => {}
+            Err(e) => return Err(e),
+        }
+
+        self.upsert_index(index, options).await
+    }
+
+    pub async fn drop_index<S: Into<String>>(
+        &self,
+        index_name: S,
+        options: DropSearchIndexOptions,
+    ) -> CouchbaseResult<()> {
+        self.check_scope_support().await?;
+        let index_name = index_name.into();
+        let (sender, receiver) = oneshot::channel();
+
+        let timer = self.core.send(Request::GenericManagementRequest(
+            GenericManagementRequest {
+                sender,
+                path: format!("{}/{}", self.indexes_path(), index_name),
+                method: String::from("delete"),
+                payload: None,
+                content_type: None,
+                timeout: options.timeout,
+                service_type: Some(ServiceType::Search),
+                endpoint: None,
+            },
+        ));
+
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
+
+        match result.http_status() {
+            200 => Ok(()),
+            _ => Err(parse_error(&result, index_name)),
+        }
+    }
+}
+
+pub(crate) fn parse_error(result: &GenericManagementResult, index_name: String) -> CouchbaseError {
+    let message = match result.payload() {
+        Some(p) => String::from_utf8_lossy(p).to_lowercase(),
+        None => String::new(),
+    };
+
+    let mut ctx = ErrorContext::default();
+    ctx.insert("name", Value::String(index_name));
+    if message.contains("index not found") {
+        return IndexNotFound { ctx };
+    } else if message.contains("index with the same name already exists") {
+        return IndexExists { ctx };
+    }
+
+    CouchbaseError::GenericHTTP {
+        ctx,
+        status: result.http_status(),
+        message,
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt::Debug;
+
+/// Receives the security relevant events of a cluster.
+///
+/// Pass it to the cluster through `ClusterOptions::security_events`. Events are delivered on
+/// the IO thread, so the listener should return quickly.
+pub trait SecurityEventListener: Debug + Send + Sync {
+    fn on_event(&self, event: &SecurityEvent);
+}
+
+/// An event passed to the `SecurityEventListener`.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum SecurityEvent {
+    /// A node presented a certificate with a different issuer or public key than it did on a
+    /// previous connection.
+    ///
+    /// This is expected when the certificates of the cluster are rotated, but may also hint at
+    /// a man-in-the-middle.
+    CertificateChanged(CertificateChange),
+}
+
+/// A change of the certificate of a node, see `SecurityEvent::CertificateChanged`.
+#[derive(Debug, Clone)]
+pub struct CertificateChange {
+    pub(crate) remote: String,
+    pub(crate) previous: CertificateIdentity,
+    pub(crate) current: CertificateIdentity,
+    pub(crate) rejected: bool,
+}
+
+impl CertificateChange {
+    /// The `host:port` of the node.
+    pub fn remote(&self) -> &str {
+        &self.remote
+    }
+
+    /// The certificate the node presented before.
+    pub fn previous(&self) -> &CertificateIdentity {
+        &self.previous
+    }
+
+    /// The certificate the node presents now.
+    pub fn current(&self) -> &CertificateIdentity {
+        &self.current
+    }
+
+    /// Whether the connection has been failed because of the change, which is the case when
+    /// `ClusterOptions::certificate_pinning` is enabled.
+    pub fn rejected(&self) -> bool {
+        self.rejected
+    }
+}
+
+/// The parts of a certificate which identify it across renewals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateIdentity {
+    pub(crate) subject: String,
+    pub(crate) issuer: String,
+    pub(crate) spki_sha256: String,
+}
+
+impl CertificateIdentity {
+    /// The subject, in the OpenSSL one line format (i.e. `/CN=node1.example.com`).
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    /// The issuer, in the OpenSSL one line format.
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// The hex encoded SHA-256 of the DER encoded SubjectPublicKeyInfo, the same value HTTP
+    /// public key pinning uses (albeit hex instead of base64 encoded).
+    pub fn spki_sha256(&self) -> &str {
+        &self.spki_sha256
+    }
+}
@@ -0,0 +1,381 @@
+use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::options::{GetOptions, InsertOptions, ReplaceOptions};
+use crate::api::Collection;
+use crate::io::Core;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// The number of points every target gets on the hash ring by default.
+///
+/// More points spread the tenants more evenly, at the cost of a larger ring.
+pub const DEFAULT_SHARD_POINTS: u32 = 64;
+
+/// A collection tenants can be placed in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ShardTarget {
+    scope: String,
+    collection: String,
+}
+
+impl ShardTarget {
+    pub fn new<S: Into<String>, C: Into<String>>(scope: S, collection: C) -> Self {
+        Self {
+            scope: scope.into(),
+            collection: collection.into(),
+        }
+    }
+
+    pub fn scope(&self) -> &str {
+        &self.scope
+    }
+
+    pub fn collection(&self) -> &str {
+        &self.collection
+    }
+}
+
+/// Maps tenant ids onto a set of targets through consistent hashing.
+///
+/// Every target is placed on a ring of 64 bit hashes a number of times and a tenant belongs to
+/// the first target following the hash of its id. Adding or removing a target only moves the
+/// tenants between the changed target and its neighbours, roughly `1 / targets` of them, while
+/// all other tenants stay where they are.
+///
+/// The hash is stable across processes, platforms and releases, so every client computes the
+/// same placement.
+#[derive(Debug, Clone)]
+pub struct ShardRing {
+    targets: Vec<ShardTarget>,
+    points: Vec<(u64, usize)>,
+}
+
+impl ShardRing {
+    /// Builds a ring placing every target the given number of times.
+    ///
+    /// Fails with `InvalidArgument` if there are no targets, a target is listed twice or
+    /// `points` is zero.
+    pub fn new(mut targets: Vec<ShardTarget>, points: u32) -> CouchbaseResult<Self> {
+        if targets.is_empty() || points == 0 {
+            return Err(invalid("a ring needs at least one target and one point"));
+        }
+        // The placement must not depend on the order the targets were listed in
+        targets.sort();
+        if targets.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(invalid("every target may only be listed once"));
+        }
+
+        let mut ring = Vec::with_capacity(targets.len() * points as usize);
+        for (index, target) in targets.iter().enumerate() {
+            for point in 0..points {
+                let key = format!("{}.{}#{}", target.scope, target.collection, point);
+                ring.push((hash(key.as_bytes()), index));
+            }
+        }
+        ring.sort_unstable();
+        Ok(Self {
+            targets,
+            points: ring,
+        })
+    }
+
+    /// Returns the target the tenant belongs to.
+    pub fn locate(&self, tenant: &str) -> &ShardTarget {
+        let hash = hash(tenant.as_bytes());
+        let index = match self.points.binary_search_by(|(point, _)| point.cmp(&hash)) {
+            Ok(i) => i,
+            Err(i) if i == self.points.len() => 0,
+            Err(i) => i,
+        };
+        &self.targets[self.points[index].1]
+    }
+
+    /// The targets on the ring, sorted.
+    pub fn targets(&self) -> &[ShardTarget] {
+        &self.targets
+    }
+}
+
+/// 64 bit FNV-1a, followed by the MurmurHash3 finalizer so similar ids spread over the ring.
+fn hash(data: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in data {
+        h ^= *byte as u64;
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+/// The placement of the tenants, as stored in the routing document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardLayout {
+    version: u64,
+    points: u32,
+    targets: Vec<ShardTarget>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    migration: Option<Vec<ShardTarget>>,
+}
+
+impl ShardLayout {
+    /// Increases with every change of the layout.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// The number of points every target has on the ring.
+    pub fn points(&self) -> u32 {
+        self.points
+    }
+
+    /// The targets tenants are read from.
+    pub fn targets(&self) -> &[ShardTarget] {
+        &self.targets
+    }
+
+    /// The targets tenants are migrated to, if a migration is in progress.
+    pub fn migration_targets(&self) -> Option<&[ShardTarget]> {
+        self.migration.as_deref()
+    }
+}
+
+/// Where the documents of a tenant are read from and written to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TenantRoute {
+    read: ShardTarget,
+    writes: Vec<ShardTarget>,
+}
+
+impl TenantRoute {
+    /// The target to read the documents of the tenant from.
+    pub fn read(&self) -> &ShardTarget {
+        &self.read
+    }
+
+    /// The targets every write of the tenant has to go to, the read target first.
+    pub fn writes(&self) -> &[ShardTarget] {
+        &self.writes
+    }
+
+    /// Whether the tenant moves to another target in the current migration, so its writes go
+    /// to both targets and its existing documents have to be copied over.
+    pub fn is_moving(&self) -> bool {
+        self.writes.len() > 1
+    }
+}
+
+/// Places tenants in collections through consistent hashing, with a layout shared by all
+/// clients through a routing document.
+///
+/// Tenants are placed on the targets of the layout. Changing the targets happens in two
+/// steps, so no writes are lost while tenants move:
+///
+/// 1. `begin_migration` stores the new targets. From now on the tenants which move are still
+///    read from their old target, but written to both the old and the new one.
+/// 2. Once every client has picked up the migration through `refresh`, and the existing
+///    documents of the moving tenants have been copied over, `complete_migration` makes the
+///    new targets the ones read from.
+///
+/// Changes of the layout are stored with the CAS of the routing document, so concurrent
+/// changes fail with `CasMismatch` instead of overwriting each other. Refresh and retry in
+/// that case.
+///
+/// The migration is advisory only: the router tells where the documents of a tenant belong,
+/// but does not enforce it. It neither copies documents nor writes to more than one
+/// collection by itself, so writers have to go through `write_collections` while a migration
+/// is in progress. Clients which have not refreshed yet keep routing with the old layout, and
+/// nothing stops anyone from accessing the collections directly.
+///
+/// ```no_run
+/// # use couchbase::*;
+/// # async fn run(bucket: Bucket, order: serde_json::Value) -> CouchbaseResult<()> {
+/// let targets = vec![
+///     ShardTarget::new("tenants", "shard-0"),
+///     ShardTarget::new("tenants", "shard-1"),
+/// ];
+/// let router = bucket.tenant_router("tenant-routing", targets).await?;
+/// for orders in router.write_collections("acme") {
+///     orders
+///         .upsert("order::1", &order, UpsertOptions::default())
+///         .await?;
+/// }
+/// let order = router
+///     .collection("acme")
+///     .get("order::1", GetOptions::default())
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TenantRouter {
+    core: Arc<Core>,
+    bucket: String,
+    meta: Collection,
+    id: String,
+    layout: ShardLayout,
+    cas: u64,
+    ring: ShardRing,
+    migration: Option<ShardRing>,
+}
+
+impl TenantRouter {
+    pub(crate) async fn open(
+        core: Arc<Core>,
+        bucket: String,
+        id: String,
+        targets: Vec<ShardTarget>,
+    ) -> CouchbaseResult<Self> {
+        let meta = Collection::new(core.clone(), "".into(), "".into(), bucket.clone());
+        let (layout, cas) = match load(&meta, &id).await {
+            Err(CouchbaseError::DocumentNotFound { .. }) => {
+                let layout = ShardLayout {
+                    version: 1,
+                    points: DEFAULT_SHARD_POINTS,
+                    targets,
+                    migration: None,
+                };
+                // Validates the targets before they are stored
+                ShardRing::new(layout.targets.clone(), layout.points)?;
+                match meta.insert(&id, &layout, InsertOptions::default()).await {
+                    Ok(result) => (layout, result.cas()),
+                    // Another client created the layout first, use theirs
+                    Err(CouchbaseError::DocumentExists { .. }) => load(&meta, &id).await?,
+                    Err(e) => return Err(e),
+                }
+            }
+            result => result?,
+        };
+
+        let (ring, migration) = rings(&layout)?;
+        Ok(Self {
+            core,
+            bucket,
+            meta,
+            id,
+            layout,
+            cas,
+            ring,
+            migration,
+        })
+    }
+
+    /// The layout tenants are currently routed with.
+    pub fn layout(&self) -> &ShardLayout {
+        &self.layout
+    }
+
+    /// Returns where the documents of the tenant are read from and written to.
+    pub fn route(&self, tenant: &str) -> TenantRoute {
+        let read = self.ring.locate(tenant).clone();
+        let mut writes = vec![read.clone()];
+        if let Some(next) = self.migration.as_ref().map(|ring| ring.locate(tenant)) {
+            if *next != read {
+                writes.push(next.clone());
+            }
+        }
+        TenantRoute { read, writes }
+    }
+
+    /// Opens the collection to read the documents of the tenant from.
+    pub fn collection(&self, tenant: &str) -> Collection {
+        self.open_target(self.route(tenant).read())
+    }
+
+    /// Opens the collections every write of the tenant has to go to.
+    pub fn write_collections(&self, tenant: &str) -> Vec<Collection> {
+        self.route(tenant)
+            .writes()
+            .iter()
+            .map(|target| self.open_target(target))
+            .collect()
+    }
+
+    /// Reloads the layout from the routing document, to pick up changes by other clients.
+    pub async fn refresh(&mut self) -> CouchbaseResult<()> {
+        let (layout, cas) = load(&self.meta, &self.id).await?;
+        self.apply(layout, cas)
+    }
+
+    /// Starts moving tenants to the given targets.
+    ///
+    /// Fails with `InvalidArgument` if a migration is already in progress.
+    pub async fn begin_migration(&mut self, targets: Vec<ShardTarget>) -> CouchbaseResult<()> {
+        if self.layout.migration.is_some() {
+            return Err(invalid("a migration is already in progress"));
+        }
+        let mut layout = self.layout.clone();
+        layout.migration = Some(targets);
+        self.store(layout).await
+    }
+
+    /// Makes the targets of the migration the ones tenants are read from.
+    ///
+    /// Only call this once the documents of all moving tenants have been copied over.
+    pub async fn complete_migration(&mut self) -> CouchbaseResult<()> {
+        let mut layout = self.layout.clone();
+        layout.targets = match layout.migration.take() {
+            Some(targets) => targets,
+            None => return Err(invalid("no migration is in progress")),
+        };
+        self.store(layout).await
+    }
+
+    /// Stops the migration, tenants are written to their old targets only again.
+    pub async fn abort_migration(&mut self) -> CouchbaseResult<()> {
+        let mut layout = self.layout.clone();
+        if layout.migration.take().is_none() {
+            return Err(invalid("no migration is in progress"));
+        }
+        self.store(layout).await
+    }
+
+    async fn store(&mut self, mut layout: ShardLayout) -> CouchbaseResult<()> {
+        layout.version += 1;
+        rings(&layout)?;
+        let result = self
+            .meta
+            .replace(&self.id, &layout, ReplaceOptions::default().cas(self.cas))
+            .await?;
+        self.apply(layout, result.cas())
+    }
+
+    fn apply(&mut self, layout: ShardLayout, cas: u64) -> CouchbaseResult<()> {
+        let (ring, migration) = rings(&layout)?;
+        self.layout = layout;
+        self.cas = cas;
+        self.ring = ring;
+        self.migration = migration;
+        Ok(())
+    }
+
+    fn open_target(&self, target: &ShardTarget) -> Collection {
+        Collection::new(
+            self.core.clone(),
+            target.collection.clone(),
+            target.scope.clone(),
+            self.bucket.clone(),
+        )
+    }
+}
+
+async fn load(meta: &Collection, id: &str) -> CouchbaseResult<(ShardLayout, u64)> {
+    let result = meta.get(id, GetOptions::default()).await?;
+    Ok((result.content()?, result.cas()))
+}
+
+fn rings(layout: &ShardLayout) -> CouchbaseResult<(ShardRing, Option<ShardRing>)> {
+    let ring = ShardRing::new(layout.targets.clone(), layout.points)?;
+    let migration = match &layout.migration {
+        Some(targets) => Some(ShardRing::new(targets.clone(), layout.points)?),
+        None => None,
+    };
+    Ok((ring, migration))
+}
+
+fn invalid(msg: &str) -> CouchbaseError {
+    let mut ctx = ErrorContext::default();
+    ctx.insert("msg", Value::String(msg.into()));
+    CouchbaseError::InvalidArgument { ctx }
+}
@@ -0,0 +1,425 @@
+use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use serde_json::Value;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// The maximum number of components the server accepts in a sub-document path.
+const MAX_PATH_DEPTH: usize = 32;
+
+/// A single component of a sub-document path.
+#[derive(Debug, Clone)]
+pub enum PathComponent {
+    /// An object field, escaped with backticks when needed.
+    Field(String),
+    /// An array index, where `-1` refers to the last element.
+    Index(i64),
+}
+
+impl From<&str> for PathComponent {
+    fn from(field: &str) -> Self {
+        PathComponent::Field(field.into())
+    }
+}
+
+impl From<String> for PathComponent {
+    fn from(field: String) -> Self {
+        PathComponent::Field(field)
+    }
+}
+
+impl From<i32> for PathComponent {
+    fn from(index: i32) -> Self {
+        PathComponent::Index(index as i64)
+    }
+}
+
+impl From<i64> for PathComponent {
+    fn from(index: i64) -> Self {
+        PathComponent::Index(index)
+    }
+}
+
+impl From<usize> for PathComponent {
+    fn from(index: usize) -> Self {
+        PathComponent::Index(index as i64)
+    }
+}
+
+/// Builds and validates sub-document paths on the client side.
+///
+/// Field names which contain path syntax (`.`, `[`, `]` or backticks) are escaped, and the
+/// path depth is checked, so invalid paths fail before they reach the server.
+///
+/// # Examples
+///
+/// ```
+/// # use couchbase::*;
+/// # fn run() -> CouchbaseResult<()> {
+/// let path = SubdocPath::new().field("addresses").index(0).field("zip.code").build()?;
+/// assert_eq!("addresses[0].`zip.code`", path);
+/// # Ok(())
+/// # }
+/// # run().unwrap();
+/// ```
+///
+/// The `subdoc_path!` macro is a shorthand for the same:
+/// ```
+/// # use couchbase::*;
+/// # fn run() -> CouchbaseResult<()> {
+/// let path = subdoc_path!("addresses", 0, "zip.code")?;
+/// # assert_eq!("addresses[0].`zip.code`", path);
+/// # Ok(())
+/// # }
+/// # run().unwrap();
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct SubdocPath {
+    components: Vec<PathComponent>,
+}
+
+impl SubdocPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push<C: Into<PathComponent>>(mut self, component: C) -> Self {
+        self.components.push(component.into());
+        self
+    }
+
+    pub fn field<S: Into<String>>(self, name: S) -> Self {
+        self.push(PathComponent::Field(name.into()))
+    }
+
+    pub fn index(self, index: i64) -> Self {
+        self.push(PathComponent::Index(index))
+    }
+
+    /// Validates the components and renders the path string sent to the server.
+    pub fn build(&self) -> CouchbaseResult<String> {
+        if self.components.len() > MAX_PATH_DEPTH {
+            return Err(CouchbaseError::PathTooDeep {
+                ctx: self.error_context(),
+            });
+        }
+
+        let mut path = String::new();
+        for component in &self.components {
+            match component {
+                PathComponent::Field(name) => {
+                    if name.is_empty() {
+                        return Err(CouchbaseError::PathInvalid {
+                            ctx: self.error_context(),
+                        });
+                    }
+                    if !path.is_empty() {
+                        path.push('.');
+                    }
+                    if name.contains(['.', '[', ']', '`']) {
+                        path.push('`');
+                        path.push_str(&name.replace('`', "``"));
+                        path.push('`');
+                    } else {
+                        path.push_str(name);
+                    }
+                }
+                PathComponent::Index(index) => {
+                    if *index < -1 {
+                        return Err(CouchbaseError::PathInvalid {
+                            ctx: self.error_context(),
+                        });
+                    }
+                    path.push_str(&format!("[{}]", index));
+                }
+            }
+        }
+
+        if path.is_empty() {
+            return Err(CouchbaseError::PathInvalid {
+                ctx: self.error_context(),
+            });
+        }
+        Ok(path)
+    }
+
+    fn error_context(&self) -> ErrorContext {
+        let mut ctx = ErrorContext::default();
+        ctx.insert(
+            "components",
+            Value::String(format!("{:?}", self.components)),
+        );
+        ctx
+    }
+}
+
+/// Parses a sub-document path into its components, reversing the escaping done by `build`.
+///
+/// An empty path refers to the whole document.
+pub(crate) fn parse_path(path: &str) -> CouchbaseResult<Vec<PathComponent>> {
+    let invalid = || {
+        let mut ctx = ErrorContext::default();
+        ctx.insert("path", Value::String(path.into()));
+        CouchbaseError::PathInvalid { ctx }
+    };
+
+    let mut components = vec![];
+    if path.is_empty() {
+        return Ok(components);
+    }
+
+    let mut chars = path.chars().peekable();
+    // A path may start with an index into a top level array, otherwise it starts with a field
+    let mut expect_field = !path.starts_with('[');
+    while expect_field || chars.peek().is_some() {
+        if expect_field {
+            let field = parse_field(&mut chars).ok_or_else(invalid)?;
+            components.push(PathComponent::Field(field));
+            expect_field = false;
+            continue;
+        }
+        match chars.next() {
+            Some('.') => expect_field = true,
+            Some('[') => {
+                let mut index = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some(c) => index.push(c),
+                        None => return Err(invalid()),
+                    }
+                }
+                let index: i64 = index.parse().map_err(|_| invalid())?;
+                if index < -1 {
+                    return Err(invalid());
+                }
+                components.push(PathComponent::Index(index));
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    if components.len() > MAX_PATH_DEPTH {
+        let mut ctx = ErrorContext::default();
+        ctx.insert("path", Value::String(path.into()));
+        return Err(CouchbaseError::PathTooDeep { ctx });
+    }
+    Ok(components)
+}
+
+/// Reads a single, possibly backtick escaped, field name.
+fn parse_field(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut field = String::new();
+    if chars.peek() == Some(&'`') {
+        chars.next();
+        loop {
+            match chars.next() {
+                Some('`') if chars.peek() == Some(&'`') => {
+                    chars.next();
+                    field.push('`');
+                }
+                Some('`') => break,
+                Some(c) => field.push(c),
+                None => return None,
+            }
+        }
+    } else {
+        while let Some(c) = chars.peek() {
+            match c {
+                '.' | '[' => break,
+                ']' | '`' => return None,
+                _ => {
+                    field.push(*c);
+                    chars.next();
+                }
+            }
+        }
+    }
+
+    if field.is_empty() {
+        None
+    } else {
+        Some(field)
+    }
+}
+
+/// Looks up the value the path components point to inside the given document.
+pub(crate) fn resolve<'a>(document: &'a Value, components: &[PathComponent]) -> Option<&'a Value> {
+    components
+        .iter()
+        .try_fold(document, |value, component| match component {
+            PathComponent::Field(name) => value.get(name.as_str()),
+            PathComponent::Index(-1) => value.as_array().and_then(|a| a.last()),
+            PathComponent::Index(index) => value.get(*index as usize),
+        })
+}
+
+/// Inserts the value at the position the path components point to, creating the objects and
+/// arrays on the way.
+///
+/// Array indexes cannot be reproduced in a partial document, so values below an index are
+/// appended to the array instead.
+pub(crate) fn insert(document: &mut Value, components: &[PathComponent], value: Value) {
+    let (component, rest) = match components.split_first() {
+        Some(split) => split,
+        None => {
+            *document = value;
+            return;
+        }
+    };
+    let empty = match rest.first() {
+        Some(PathComponent::Index(_)) => Value::Array(vec![]),
+        _ => Value::Object(Default::default()),
+    };
+    match component {
+        PathComponent::Field(name) => {
+            if !document.is_object() {
+                *document = Value::Object(Default::default());
+            }
+            let map = document.as_object_mut().unwrap();
+            insert(map.entry(name.clone()).or_insert(empty), rest, value);
+        }
+        PathComponent::Index(_) => {
+            if !document.is_array() {
+                *document = Value::Array(vec![]);
+            }
+            let array = document.as_array_mut().unwrap();
+            array.push(empty);
+            insert(array.last_mut().unwrap(), rest, value);
+        }
+    }
+}
+
+/// Builds a validated sub-document path from fields and array indexes.
+///
+/// Expands to a `SubdocPath` and returns the result of `SubdocPath::build`.
+#[macro_export]
+macro_rules! subdoc_path {
+    ($($component:expr),+ $(,)?) => {
+        $crate::SubdocPath::new()$(.push($component))+.build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn path(components: Vec<PathComponent>) -> CouchbaseResult<String> {
+        SubdocPath { components }.build()
+    }
+
+    #[test]
+    fn escapes_fields_with_path_syntax() {
+        let built = SubdocPath::new()
+            .field("a.b")
+            .field("c`d")
+            .field("e[0]")
+            .index(1)
+            .field("plain")
+            .build()
+            .unwrap();
+        assert_eq!("`a.b`.`c``d`.`e[0]`[1].plain", built);
+
+        let parsed = parse_path(&built).unwrap();
+        assert!(matches!(&parsed[1], PathComponent::Field(f) if f == "c`d"));
+        assert!(matches!(&parsed[3], PathComponent::Index(1)));
+        assert_eq!(built, path(parsed).unwrap());
+    }
+
+    #[test]
+    fn round_trips_escaped_paths() {
+        for original in &["a", "a.b[0][1]", "[0].a", "`.`", "````", "`a]`[-1].`[b`"] {
+            let parsed = parse_path(original).unwrap();
+            assert_eq!(*original, path(parsed).unwrap());
+        }
+    }
+
+    #[test]
+    fn accepts_only_the_last_element_as_negative_index() {
+        assert_eq!("list[-1]", subdoc_path!("list", -1).unwrap());
+        assert!(matches!(
+            parse_path("list[-1]").unwrap()[1],
+            PathComponent::Index(-1)
+        ));
+        assert!(matches!(
+            subdoc_path!("list", -2),
+            Err(CouchbaseError::PathInvalid { .. })
+        ));
+        assert!(matches!(
+            parse_path("list[-2]"),
+            Err(CouchbaseError::PathInvalid { .. })
+        ));
+
+        let document = json!({"list": [1, 2, 3]});
+        let components = parse_path("list[-1]").unwrap();
+        assert_eq!(Some(&json!(3)), resolve(&document, &components));
+    }
+
+    #[test]
+    fn rejects_empty_fields() {
+        assert!(matches!(
+            subdoc_path!("a", "", "b"),
+            Err(CouchbaseError::PathInvalid { .. })
+        ));
+        assert!(matches!(
+            SubdocPath::new().build(),
+            Err(CouchbaseError::PathInvalid { .. })
+        ));
+        for invalid in &["a..b", "a.", ".a", "a.``", "a[]", "a[x]", "a[0", "a]"] {
+            assert!(
+                matches!(parse_path(invalid), Err(CouchbaseError::PathInvalid { .. })),
+                "{}",
+                invalid
+            );
+        }
+        assert!(parse_path("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn limits_the_depth_to_32_components() {
+        let components = |depth| (0..depth).map(PathComponent::Index).collect();
+        assert!(path(components(MAX_PATH_DEPTH as i64)).is_ok());
+        assert!(matches!(
+            path(components(MAX_PATH_DEPTH as i64 + 1)),
+            Err(CouchbaseError::PathTooDeep { .. })
+        ));
+
+        let deepest = vec!["a"; MAX_PATH_DEPTH].join(".");
+        assert_eq!(MAX_PATH_DEPTH, parse_path(&deepest).unwrap().len());
+        assert!(matches!(
+            parse_path(&format!("{}.a", deepest)),
+            Err(CouchbaseError::PathTooDeep { .. })
+        ));
+    }
+
+    #[test]
+    fn inserts_into_nested_objects_and_arrays() {
+        let mut document = json!({"keep": true});
+        insert(
+            &mut document,
+            &parse_path("a.b").unwrap(),
+            json!("in object"),
+        );
+        insert(
+            &mut document,
+            &parse_path("list[5].c").unwrap(),
+            json!("in array"),
+        );
+        insert(
+            &mut document,
+            &parse_path("list[0][2]").unwrap(),
+            json!("nested"),
+        );
+        assert_eq!(
+            json!({
+                "keep": true,
+                "a": {"b": "in object"},
+                "list": [{"c": "in array"}, ["nested"]],
+            }),
+            document
+        );
+
+        insert(&mut document, &[], json!("whole"));
+        assert_eq!(json!("whole"), document);
+    }
+}
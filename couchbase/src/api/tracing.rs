@@ -0,0 +1,68 @@
+use std::fmt::Debug;
+
+/// Starts a span for every operation performed through the SDK.
+///
+/// Implement this trait to feed operations into a tracing system of your choice and pass it
+/// to the cluster through `ClusterOptions::tracer`. With the `opentelemetry` feature enabled,
+/// `OpenTelemetryTracer` feeds them into the globally registered OpenTelemetry tracer.
+pub trait RequestTracer: Debug + Send + Sync {
+    /// Called when an operation is dispatched, with the name of the operation, i.e. `get` or
+    /// `query`.
+    ///
+    /// The span is given the `db.system`, `db.couchbase.service` and `db.couchbase.operation`
+    /// attributes (and `db.name` if the operation targets a bucket) right away. It is ended once
+    /// the operation completed, after setting the `error` attribute if it failed.
+    fn request_span(&self, operation: &'static str) -> Box<dyn RequestSpan>;
+}
+
+/// A span started by a `RequestTracer`.
+pub trait RequestSpan: Send {
+    fn set_attribute(&mut self, key: &'static str, value: String);
+
+    /// Called once the operation completed, successfully or not.
+    fn end(self: Box<Self>);
+}
+
+/// The default `RequestTracer`, which does not trace anything.
+#[derive(Debug, Default)]
+pub struct NoopTracer;
+
+impl RequestTracer for NoopTracer {
+    fn request_span(&self, _operation: &'static str) -> Box<dyn RequestSpan> {
+        Box::new(NoopSpan)
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct NoopSpan;
+
+impl RequestSpan for NoopSpan {
+    fn set_attribute(&mut self, _key: &'static str, _value: String) {}
+
+    fn end(self: Box<Self>) {}
+}
+
+/// A `RequestTracer` which starts its spans through the globally registered OpenTelemetry
+/// tracer provider, under the `couchbase` instrumentation name.
+#[cfg(feature = "opentelemetry")]
+#[derive(Debug, Default)]
+pub struct OpenTelemetryTracer;
+
+#[cfg(feature = "opentelemetry")]
+impl RequestTracer for OpenTelemetryTracer {
+    fn request_span(&self, operation: &'static str) -> Box<dyn RequestSpan> {
+        use opentelemetry::trace::Tracer;
+        Box::new(opentelemetry::global::tracer("couchbase").start(operation))
+    }
+}
+
+#[cfg(feature = "opentelemetry")]
+impl RequestSpan for opentelemetry::global::BoxedSpan {
+    fn set_attribute(&mut self, key: &'static str, value: String) {
+        opentelemetry::trace::Span::set_attribute(self, opentelemetry::KeyValue::new(key, value));
+    }
+
+    fn end(mut self: Box<Self>) {
+        opentelemetry::trace::Span::end(&mut *self);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use couchbase_flags::{
+    decode_common_flags, DataFormat, BINARY_COMMON_FLAGS, COMMON_FLAGS_MASK, STRING_COMMON_FLAGS,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt;
+
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+mod binary;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+
+pub(crate) use couchbase_flags::JSON_COMMON_FLAGS;
+
+/// Stores enums as flat JSON documents which carry their variant in a type field.
+///
+/// A variant like `Event::Login { user: "mike" }` is stored as
+/// `{"type": "Login", "user": "mike"}` and routed back to the right variant when decoding,
+/// without the enum needing a `#[serde(tag = "...")]` attribute. The name of the type field
+/// is configurable, so documents written by other applications can be read as well.
+///
+/// Only unit variants and variants whose content serializes to a JSON object are supported.
+///
+/// ```no_run
+/// # use couchbase::*;
+/// # use serde_derive::{Deserialize, Serialize};
+/// #[derive(Debug, Serialize, Deserialize)]
+/// enum Event {
+///     Login { user: String },
+///     Logout { user: String },
+/// }
+///
+/// # async fn run(collection: Collection) -> CouchbaseResult<()> {
+/// let transcoder = TaggedTranscoder::new("kind");
+/// let event = Event::Login { user: "mike".into() };
+/// collection
+///     .upsert_tagged("event::1", &event, &transcoder, UpsertOptions::default())
+///     .await?;
+///
+/// let result = collection.get("event::1", GetOptions::default()).await?;
+/// let event: Event = result.content_tagged(&transcoder)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TaggedTranscoder {
+    field: String,
+}
+
+impl TaggedTranscoder {
+    /// Creates a transcoder which stores the variant name in the given field.
+    pub fn new<S: Into<String>>(field: S) -> Self {
+        Self {
+            field: field.into(),
+        }
+    }
+
+    /// The name of the field holding the variant name.
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    /// Encodes the content into a flat JSON document tagged with its variant.
+    pub fn encode<T: Serialize>(&self, content: &T) -> CouchbaseResult<Vec<u8>> {
+        let value = serde_json::to_value(content).map_err(|e| CouchbaseError::EncodingFailure {
+            ctx: ErrorContext::default(),
+            source: e.into(),
+        })?;
+        let document = match value {
+            Value::String(variant) => {
+                let mut document = Map::new();
+                document.insert(self.field.clone(), Value::String(variant));
+                document
+            }
+            Value::Object(outer) if outer.len() == 1 => {
+                let (variant, inner) = outer.into_iter().next().unwrap();
+                let mut document = match inner {
+                    Value::Object(fields) => fields,
+                    _ => return Err(self.invalid("only object variants can be tagged")),
+                };
+                if document.contains_key(&self.field) {
+                    return Err(self.invalid("the variant already has a field named like the tag"));
+                }
+                document.insert(self.field.clone(), Value::String(variant));
+                document
+            }
+            _ => return Err(self.invalid("only enums can be tagged")),
+        };
+        serde_json::to_vec(&document).map_err(|e| CouchbaseError::EncodingFailure {
+            ctx: ErrorContext::default(),
+            source: e.into(),
+        })
+    }
+
+    /// Decodes a tagged JSON document into the variant named by its type field.
+    pub fn decode<T: DeserializeOwned>(&self, content: &[u8]) -> CouchbaseResult<T> {
+        let mut document: Map<String, Value> =
+            serde_json::from_slice(content).map_err(|e| CouchbaseError::DecodingFailure {
+                ctx: ErrorContext::default(),
+                source: e.into(),
+            })?;
+        let variant = match document.remove(&self.field) {
+            Some(Value::String(variant)) => variant,
+            _ => {
+                let mut ctx = ErrorContext::default();
+                ctx.insert("field", Value::String(self.field.clone()));
+                return Err(CouchbaseError::DecodingFailure {
+                    ctx,
+                    source: std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "the document has no type field",
+                    ),
+                });
+            }
+        };
+
+        // A document with only the type field may be a unit variant, or a struct variant
+        // without any fields.
+        if document.is_empty() {
+            if let Ok(decoded) = serde_json::from_value(Value::String(variant.clone())) {
+                return Ok(decoded);
+            }
+        }
+        let mut outer = Map::new();
+        outer.insert(variant, Value::Object(document));
+        serde_json::from_value(Value::Object(outer)).map_err(|e| CouchbaseError::DecodingFailure {
+            ctx: ErrorContext::default(),
+            source: e.into(),
+        })
+    }
+
+    fn invalid(&self, msg: &str) -> CouchbaseError {
+        let mut ctx = ErrorContext::default();
+        ctx.insert("msg", Value::String(msg.into()));
+        ctx.insert("field", Value::String(self.field.clone()));
+        CouchbaseError::InvalidArgument { ctx }
+    }
+}
+
+/// Turns the content of a document into the bytes and flags stored on the server, and back.
+///
+/// By default documents are stored as JSON. Set a transcoder through the `transcoder` option
+/// of the get and mutation operations to store them in another format, i.e. MessagePack:
+///
+/// ```no_run
+/// # use couchbase::*;
+/// # use serde_json::Value;
+/// # fn to_msgpack(value: &Value) -> Vec<u8> { unimplemented!() }
+/// # fn from_msgpack(content: &[u8]) -> Option<Value> { unimplemented!() }
+/// #[derive(Debug)]
+/// struct MessagePackTranscoder;
+///
+/// impl Transcoder for MessagePackTranscoder {
+///     fn encode(&self, content: Value) -> CouchbaseResult<(Vec<u8>, u32)> {
+///         Ok((to_msgpack(&content), couchbase_flags::BINARY_COMMON_FLAGS))
+///     }
+///
+///     fn decode(&self, content: &[u8], _flags: u32) -> CouchbaseResult<Value> {
+///         from_msgpack(content).ok_or_else(|| CouchbaseError::DecodingFailure {
+///             ctx: ErrorContext::default(),
+///             source: std::io::ErrorKind::InvalidData.into(),
+///         })
+///     }
+/// }
+/// ```
+///
+/// The content passes through the transcoder as a `serde_json::Value`, so transcoders can be
+/// shared between operations as `Arc<dyn Transcoder>` regardless of the type of the content.
+pub trait Transcoder: fmt::Debug + Send + Sync {
+    /// Encodes the content into the bytes and flags stored on the server.
+    fn encode(&self, content: Value) -> CouchbaseResult<(Vec<u8>, u32)>;
+
+    /// Decodes the bytes and flags of a document.
+    fn decode(&self, content: &[u8], flags: u32) -> CouchbaseResult<Value>;
+}
+
+/// Stores the content as JSON with the JSON common flags.
+///
+/// Unlike the default encoding, this sets the common flags so other SDKs recognize the
+/// document as JSON, and refuses to decode documents flagged as something else.
+#[derive(Debug, Default, Clone)]
+pub struct JsonTranscoder;
+
+impl Transcoder for JsonTranscoder {
+    fn encode(&self, content: Value) -> CouchbaseResult<(Vec<u8>, u32)> {
+        let encoded =
+            serde_json::to_vec(&content).map_err(|e| CouchbaseError::EncodingFailure {
+                ctx: ErrorContext::default(),
+                source: e.into(),
+            })?;
+        Ok((encoded, JSON_COMMON_FLAGS))
+    }
+
+    fn decode(&self, content: &[u8], flags: u32) -> CouchbaseResult<Value> {
+        verify_json_flags(flags)?;
+        serde_json::from_slice(content).map_err(|e| CouchbaseError::DecodingFailure {
+            ctx: ErrorContext::default(),
+            source: e.into(),
+        })
+    }
+}
+
+/// Stores a string which already holds JSON as it is, flagged as JSON.
+///
+/// The content has to be a string and is neither validated nor reformatted. Reading a document
+/// returns its JSON text as a string, to be decoded into a `String`.
+#[derive(Debug, Default, Clone)]
+pub struct RawJsonTranscoder;
+
+impl Transcoder for RawJsonTranscoder {
+    fn encode(&self, content: Value) -> CouchbaseResult<(Vec<u8>, u32)> {
+        match content {
+            Value::String(json) => Ok((json.into_bytes(), JSON_COMMON_FLAGS)),
+            _ => Err(unsupported_content(
+                "the raw JSON transcoder only encodes strings",
+            )),
+        }
+    }
+
+    fn decode(&self, content: &[u8], flags: u32) -> CouchbaseResult<Value> {
+        verify_json_flags(flags)?;
+        decode_string(content)
+    }
+}
+
+/// Stores bytes as they are, flagged as binary.
+///
+/// The content has to be a sequence of bytes, like a `Vec<u8>` or `&[u8]`. Reading a document
+/// returns its bytes, to be decoded into a `Vec<u8>`.
+///
+/// The bytes pass through a `serde_json::Value` array on both ways, which is slow for large
+/// documents. Those are better read with `GetResult::raw_content` or
+/// `GetReplicaResult::raw_content`, which return the stored bytes without decoding them.
+#[derive(Debug, Default, Clone)]
+pub struct RawBinaryTranscoder;
+
+impl Transcoder for RawBinaryTranscoder {
+    fn encode(&self, content: Value) -> CouchbaseResult<(Vec<u8>, u32)> {
+        let bytes = match content {
+            Value::Array(items) => items
+                .iter()
+                .map(|i| i.as_u64().filter(|b| *b <= u8::MAX as u64).map(|b| b as u8))
+                .collect::<Option<Vec<u8>>>(),
+            _ => None,
+        };
+        match bytes {
+            Some(bytes) => Ok((bytes, BINARY_COMMON_FLAGS)),
+            None => Err(unsupported_content(
+                "the raw binary transcoder only encodes bytes",
+            )),
+        }
+    }
+
+    fn decode(&self, content: &[u8], flags: u32) -> CouchbaseResult<Value> {
+        verify_flags(flags, DataFormat::Binary)?;
+        Ok(Value::Array(
+            content.iter().map(|b| Value::from(*b)).collect(),
+        ))
+    }
+}
+
+/// Stores strings as they are, flagged as strings, and everything else as JSON.
+///
+/// This matches the default transcoder of older Couchbase SDKs, which stored strings without
+/// JSON quoting. Documents are decoded according to their flags, so strings, binary and JSON
+/// documents written by those SDKs can all be read.
+#[derive(Debug, Default, Clone)]
+pub struct LegacyTranscoder;
+
+impl Transcoder for LegacyTranscoder {
+    fn encode(&self, content: Value) -> CouchbaseResult<(Vec<u8>, u32)> {
+        match content {
+            Value::String(string) => Ok((string.into_bytes(), STRING_COMMON_FLAGS)),
+            content => JsonTranscoder.encode(content),
+        }
+    }
+
+    fn decode(&self, content: &[u8], flags: u32) -> CouchbaseResult<Value> {
+        match decode_common_flags(flags).0 {
+            DataFormat::String => decode_string(content),
+            DataFormat::Binary => RawBinaryTranscoder.decode(content, flags),
+            _ => JsonTranscoder.decode(content, flags),
+        }
+    }
+}
+
+fn decode_string(content: &[u8]) -> CouchbaseResult<Value> {
+    String::from_utf8(content.to_vec())
+        .map(Value::String)
+        .map_err(|e| CouchbaseError::DecodingFailure {
+            ctx: ErrorContext::default(),
+            source: std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+        })
+}
+
+fn unsupported_content(msg: &str) -> CouchbaseError {
+    let mut ctx = ErrorContext::default();
+    ctx.insert("msg", Value::String(msg.into()));
+    CouchbaseError::EncodingFailure {
+        ctx,
+        source: std::io::ErrorKind::InvalidInput.into(),
+    }
+}
+
+/// Fails if the flags mark the document as something other than JSON.
+///
+/// Documents written without common flags (like by this SDK through `upsert`) are assumed to be
+/// JSON.
+pub(crate) fn verify_json_flags(flags: u32) -> CouchbaseResult<()> {
+    verify_flags(flags, DataFormat::Json)
+}
+
+/// Fails if the flags mark the document as something other than the given format.
+///
+/// Documents written without common flags are assumed to be in the expected format.
+pub(crate) fn verify_flags(flags: u32, format: DataFormat) -> CouchbaseResult<()> {
+    if flags & COMMON_FLAGS_MASK == 0 || decode_common_flags(flags).0 == format {
+        return Ok(());
+    }
+    let mut ctx = ErrorContext::default();
+    ctx.insert(
+        "msg",
+        Value::String(format!("the document is not {:?}", format)),
+    );
+    ctx.insert("flags", Value::from(flags));
+    Err(CouchbaseError::DecodingFailure {
+        ctx,
+        source: std::io::Error::new(std::io::ErrorKind::InvalidData, "unexpected content type"),
+    })
+}
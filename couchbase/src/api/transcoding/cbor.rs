@@ -0,0 +1,87 @@
+//! Stores documents as CBOR, see `CborTranscoder`.
+
+use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::transcoding::binary::BinaryValue;
+use crate::api::transcoding::{verify_flags, Transcoder};
+use couchbase_flags::{DataFormat, BINARY_COMMON_FLAGS};
+use serde_json::Value;
+
+/// Stores the content as CBOR, flagged as binary.
+///
+/// Since the content passes through a `serde_json::Value`, documents written elsewhere can only
+/// be read if their maps have string keys, tags are dropped and byte strings are read as arrays
+/// of bytes.
+#[derive(Debug, Default, Clone)]
+pub struct CborTranscoder;
+
+impl Transcoder for CborTranscoder {
+    fn encode(&self, content: Value) -> CouchbaseResult<(Vec<u8>, u32)> {
+        let mut encoded = Vec::new();
+        ciborium::ser::into_writer(&content, &mut encoded).map_err(|e| {
+            CouchbaseError::EncodingFailure {
+                ctx: ErrorContext::default(),
+                source: std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()),
+            }
+        })?;
+        Ok((encoded, BINARY_COMMON_FLAGS))
+    }
+
+    fn decode(&self, content: &[u8], flags: u32) -> CouchbaseResult<Value> {
+        verify_flags(flags, DataFormat::Binary)?;
+        ciborium::de::from_reader(content)
+            .map(|v: BinaryValue| v.0)
+            .map_err(|e| CouchbaseError::DecodingFailure {
+                ctx: ErrorContext::default(),
+                source: std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serializer;
+    use serde_derive::Serialize;
+
+    struct Bytes(&'static [u8]);
+
+    impl serde::Serialize for Bytes {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Document {
+        data: Bytes,
+    }
+
+    #[test]
+    fn round_trips_json() {
+        let content = serde_json::json!({"name": "40-Mile Air", "ids": [1, -2, 3.5], "open": null});
+        let (encoded, flags) = CborTranscoder.encode(content.clone()).unwrap();
+        assert_eq!(flags, BINARY_COMMON_FLAGS);
+        assert_eq!(CborTranscoder.decode(&encoded, flags).unwrap(), content);
+    }
+
+    #[test]
+    fn reads_byte_strings_as_arrays() {
+        let encoded = {
+            let mut encoded = Vec::new();
+            ciborium::ser::into_writer(
+                &Document {
+                    data: Bytes(&[1, 2, 255]),
+                },
+                &mut encoded,
+            )
+            .unwrap();
+            encoded
+        };
+        assert_eq!(
+            CborTranscoder
+                .decode(&encoded, BINARY_COMMON_FLAGS)
+                .unwrap(),
+            serde_json::json!({"data": [1, 2, 255]})
+        );
+    }
+}
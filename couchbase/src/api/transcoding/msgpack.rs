@@ -0,0 +1,103 @@
+//! Stores documents as MessagePack, see `MessagePackTranscoder`.
+
+use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::transcoding::binary::BinaryValue;
+use crate::api::transcoding::{verify_flags, Transcoder};
+use couchbase_flags::{DataFormat, BINARY_COMMON_FLAGS};
+use serde_json::Value;
+
+/// Stores the content as MessagePack, flagged as binary.
+///
+/// Maps are written with their keys, so documents can be read by other MessagePack decoders.
+/// Since the content passes through a `serde_json::Value`, documents written elsewhere can only
+/// be read if they have string keys, and binary values are read as arrays of bytes. Extension
+/// types are rejected.
+///
+/// ```no_run
+/// # use couchbase::*;
+/// # use couchbase::transcoding::msgpack::MessagePackTranscoder;
+/// # use std::sync::Arc;
+/// # async fn run(collection: Collection) -> CouchbaseResult<()> {
+/// let transcoder: Arc<dyn Transcoder> = Arc::new(MessagePackTranscoder);
+/// collection
+///     .upsert(
+///         "airline_10",
+///         serde_json::json!({"name": "40-Mile Air"}),
+///         UpsertOptions::default().transcoder(transcoder.clone()),
+///     )
+///     .await?;
+/// let result = collection
+///     .get("airline_10", GetOptions::default().transcoder(transcoder))
+///     .await?;
+/// let airline: serde_json::Value = result.content()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct MessagePackTranscoder;
+
+impl Transcoder for MessagePackTranscoder {
+    fn encode(&self, content: Value) -> CouchbaseResult<(Vec<u8>, u32)> {
+        let encoded =
+            rmp_serde::to_vec_named(&content).map_err(|e| CouchbaseError::EncodingFailure {
+                ctx: ErrorContext::default(),
+                source: std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+            })?;
+        Ok((encoded, BINARY_COMMON_FLAGS))
+    }
+
+    fn decode(&self, content: &[u8], flags: u32) -> CouchbaseResult<Value> {
+        verify_flags(flags, DataFormat::Binary)?;
+        rmp_serde::from_slice(content)
+            .map(|v: BinaryValue| v.0)
+            .map_err(|e| CouchbaseError::DecodingFailure {
+                ctx: ErrorContext::default(),
+                source: std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serializer;
+    use serde_derive::Serialize;
+
+    struct Bytes(&'static [u8]);
+
+    impl serde::Serialize for Bytes {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Document {
+        data: Bytes,
+    }
+
+    #[test]
+    fn round_trips_json() {
+        let content = serde_json::json!({"name": "40-Mile Air", "ids": [1, -2, 3.5], "open": null});
+        let (encoded, flags) = MessagePackTranscoder.encode(content.clone()).unwrap();
+        assert_eq!(flags, BINARY_COMMON_FLAGS);
+        assert_eq!(
+            MessagePackTranscoder.decode(&encoded, flags).unwrap(),
+            content
+        );
+    }
+
+    #[test]
+    fn reads_byte_strings_as_arrays() {
+        let encoded = rmp_serde::to_vec_named(&Document {
+            data: Bytes(&[1, 2, 255]),
+        })
+        .unwrap();
+        assert_eq!(
+            MessagePackTranscoder
+                .decode(&encoded, BINARY_COMMON_FLAGS)
+                .unwrap(),
+            serde_json::json!({"data": [1, 2, 255]})
+        );
+    }
+}
@@ -4,6 +4,7 @@ use crate::io::Core;
 use crate::{CouchbaseError, CouchbaseResult, ErrorContext, GenericManagementResult};
 use futures::channel::oneshot;
 use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
 use std::borrow::Borrow;
 use std::fmt::{self, Debug};
 use std::sync::Arc;
@@ -27,11 +28,33 @@ pub struct Role {
     #[serde(rename = "role")]
     name: String,
     bucket_name: Option<String>,
+    scope_name: Option<String>,
+    collection_name: Option<String>,
 }
 
 impl Role {
     pub fn new(name: String, bucket_name: Option<String>) -> Self {
-        Self { name, bucket_name }
+        Self {
+            name,
+            bucket_name,
+            scope_name: None,
+            collection_name: None,
+        }
+    }
+
+    /// Creates a role which only applies to a scope, or a collection within that scope.
+    pub fn with_scope(
+        name: String,
+        bucket_name: String,
+        scope_name: String,
+        collection_name: Option<String>,
+    ) -> Self {
+        Self {
+            name,
+            bucket_name: Some(bucket_name),
+            scope_name: Some(scope_name),
+            collection_name,
+        }
     }
 
     pub fn name(&self) -> &str {
@@ -41,6 +64,32 @@ impl Role {
     pub fn bucket(&self) -> Option<String> {
         self.bucket_name.clone()
     }
+
+    pub fn scope(&self) -> Option<String> {
+        self.scope_name.clone()
+    }
+
+    pub fn collection(&self) -> Option<String> {
+        self.collection_name.clone()
+    }
+
+    /// Renders the role the way the server expects it in forms, like `data_reader[b:s:c]`.
+    fn to_form_value(&self) -> String {
+        let mut target = match &self.bucket_name {
+            Some(bucket) => bucket.clone(),
+            None => return self.name.clone(),
+        };
+        for part in [&self.scope_name, &self.collection_name].iter() {
+            match part {
+                Some(p) => {
+                    target.push(':');
+                    target.push_str(p);
+                }
+                None => break,
+            }
+        }
+        format!("{}[{}]", self.name, target)
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -247,7 +296,7 @@ pub struct UserAndMetadata {
 
 impl UserAndMetadata {
     pub fn domain(&self) -> AuthDomain {
-        self.domain.clone()
+        self.domain
     }
 
     pub fn user(&self) -> User {
@@ -271,7 +320,7 @@ impl UserAndMetadata {
     }
 
     pub fn external_groups(&self) -> Option<&Vec<String>> {
-        self.groups.as_ref()
+        self.external_groups.as_ref()
     }
 
     fn user_roles(&self) -> Vec<Role> {
@@ -282,7 +331,12 @@ impl UserAndMetadata {
                     .iter()
                     .any(|origin| origin.origin_type.as_str() == "user")
             })
-            .map(|role| Role::new(role.role.name.clone(), role.role.bucket_name.clone()))
+            .map(|role| Role {
+                name: role.role.name.clone(),
+                bucket_name: role.role.bucket_name.clone(),
+                scope_name: role.role.scope_name.clone(),
+                collection_name: role.role.collection_name.clone(),
+            })
             .collect()
     }
 }
@@ -307,7 +361,7 @@ impl UserManager {
             None => AuthDomain::Local.to_string().to_lowercase(),
         };
 
-        self.core.send(Request::GenericManagementRequest(
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
                 path: format!("/settings/rbac/users/{}/{}", domain, username),
@@ -315,10 +369,12 @@ impl UserManager {
                 payload: None,
                 content_type: None,
                 timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
 
         match result.http_status() {
             200 => serde_json::from_slice(result.payload().unwrap()).map_err(|e| {
@@ -327,6 +383,9 @@ impl UserManager {
                     source: e.into(),
                 }
             }),
+            404 => Err(CouchbaseError::UserNotFound {
+                ctx: name_context(username),
+            }),
             _ => Err(CouchbaseError::GenericHTTP {
                 ctx: Default::default(),
                 status: result.http_status(),
@@ -347,7 +406,7 @@ impl UserManager {
             None => AuthDomain::Local.to_string().to_lowercase(),
         };
 
-        self.core.send(Request::GenericManagementRequest(
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
                 path: format!("/settings/rbac/users/{}/", domain),
@@ -355,10 +414,12 @@ impl UserManager {
                 payload: None,
                 content_type: None,
                 timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
 
         match result.http_status() {
             200 => serde_json::from_slice(result.payload().unwrap()).map_err(|e| {
@@ -378,14 +439,7 @@ impl UserManager {
     }
 
     pub async fn upsert_user(&self, user: User, options: UpsertUserOptions) -> CouchbaseResult<()> {
-        let roles: Vec<String> = user
-            .roles
-            .into_iter()
-            .map(|role| match &role.bucket_name {
-                Some(name) => format!("{}[{}]", role.name, name),
-                None => role.name,
-            })
-            .collect();
+        let roles: Vec<String> = user.roles.iter().map(Role::to_form_value).collect();
 
         // The server expects form data so we need to build that, serde expects each value to be an
         // Option.
@@ -406,11 +460,11 @@ impl UserManager {
             Some(name) => name,
             None => AuthDomain::Local.to_string().to_lowercase(),
         };
-        let user_encoded = serde_urlencoded::to_string(&user_form).unwrap();
+        let user_encoded = serde_urlencoded::to_string(user_form).unwrap();
         let content_type = String::from("application/x-www-form-urlencoded");
         let (sender, receiver) = oneshot::channel();
 
-        self.core.send(Request::GenericManagementRequest(
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
                 path: format!("/settings/rbac/users/{}/{}", domain, user.username),
@@ -418,10 +472,12 @@ impl UserManager {
                 payload: Some(user_encoded),
                 content_type: Some(content_type),
                 timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
 
         match result.http_status() {
             200 => Ok(()),
@@ -446,7 +502,7 @@ impl UserManager {
             None => AuthDomain::Local.to_string().to_lowercase(),
         };
 
-        self.core.send(Request::GenericManagementRequest(
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
                 path: format!("/settings/rbac/users/{}/{}", domain, username),
@@ -454,10 +510,56 @@ impl UserManager {
                 payload: None,
                 content_type: None,
                 timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
+            },
+        ));
+
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
+
+        match result.http_status() {
+            200 => Ok(()),
+            404 => Err(CouchbaseError::UserNotFound {
+                ctx: name_context(username),
+            }),
+            _ => Err(CouchbaseError::GenericHTTP {
+                ctx: Default::default(),
+                status: result.http_status(),
+                message: String::from_utf8(result.payload().unwrap().to_owned())
+                    .unwrap()
+                    .to_lowercase(),
+            }),
+        }
+    }
+
+    /// Changes the password of the user the cluster is connected with.
+    ///
+    /// Note that the cluster keeps using the old password for new connections, so it needs to
+    /// be reconnected with the new one.
+    pub async fn change_password(
+        &self,
+        new_password: String,
+        options: ChangePasswordOptions,
+    ) -> CouchbaseResult<()> {
+        let form = &[("password", new_password)];
+        let form_encoded = serde_urlencoded::to_string(form).unwrap();
+        let content_type = String::from("application/x-www-form-urlencoded");
+        let (sender, receiver) = oneshot::channel();
+
+        let timer = self.core.send(Request::GenericManagementRequest(
+            GenericManagementRequest {
+                sender,
+                path: String::from("/controller/changePassword"),
+                method: String::from("post"),
+                payload: Some(form_encoded),
+                content_type: Some(content_type),
+                timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
 
         match result.http_status() {
             200 => Ok(()),
@@ -476,7 +578,7 @@ impl UserManager {
         options: GetRolesOptions,
     ) -> CouchbaseResult<Vec<RoleAndDescription>> {
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::GenericManagementRequest(
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
                 path: String::from("/settings/rbac/roles"),
@@ -484,10 +586,12 @@ impl UserManager {
                 payload: None,
                 content_type: None,
                 timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
 
         match result.http_status() {
             200 => serde_json::from_slice(result.payload().unwrap()).map_err(|e| {
@@ -510,9 +614,9 @@ impl UserManager {
         &self,
         name: String,
         options: GetGroupOptions,
-    ) -> CouchbaseResult<Vec<Group>> {
+    ) -> CouchbaseResult<Group> {
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::GenericManagementRequest(
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
                 path: format!("/settings/rbac/groups/{}", name),
@@ -520,10 +624,12 @@ impl UserManager {
                 payload: None,
                 content_type: None,
                 timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
 
         match result.http_status() {
             200 => serde_json::from_slice(result.payload().unwrap()).map_err(|e| {
@@ -532,6 +638,9 @@ impl UserManager {
                     source: e.into(),
                 }
             }),
+            404 => Err(CouchbaseError::GroupNotFound {
+                ctx: name_context(name),
+            }),
             _ => Err(CouchbaseError::GenericHTTP {
                 ctx: Default::default(),
                 status: result.http_status(),
@@ -547,7 +656,7 @@ impl UserManager {
         options: GetAllGroupsOptions,
     ) -> CouchbaseResult<Vec<Group>> {
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::GenericManagementRequest(
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
                 path: String::from("/settings/rbac/groups"),
@@ -555,10 +664,12 @@ impl UserManager {
                 payload: None,
                 content_type: None,
                 timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
 
         match result.http_status() {
             200 => serde_json::from_slice(result.payload().unwrap()).map_err(|e| {
@@ -582,14 +693,7 @@ impl UserManager {
         group: Group,
         options: UpsertGroupOptions,
     ) -> CouchbaseResult<()> {
-        let roles: Vec<String> = group
-            .roles
-            .into_iter()
-            .map(|role| match &role.bucket_name {
-                Some(name) => format!("{}[{}]", role.name, name),
-                None => role.name,
-            })
-            .collect();
+        let roles: Vec<String> = group.roles.iter().map(Role::to_form_value).collect();
 
         // The server expects form data so we need to build that, serde expects each value to be an
         // Option.
@@ -599,11 +703,11 @@ impl UserManager {
             ("ldap_group_ref", group.ldap_group),
         ];
 
-        let group_encoded = serde_urlencoded::to_string(&group_form).unwrap();
+        let group_encoded = serde_urlencoded::to_string(group_form).unwrap();
         let content_type = String::from("application/x-www-form-urlencoded");
         let (sender, receiver) = oneshot::channel();
 
-        self.core.send(Request::GenericManagementRequest(
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
                 path: format!("/settings/rbac/groups/{}", group.name),
@@ -611,10 +715,12 @@ impl UserManager {
                 payload: Some(group_encoded),
                 content_type: Some(content_type),
                 timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
 
         match result.http_status() {
             200 => Ok(()),
@@ -630,7 +736,7 @@ impl UserManager {
 
     pub async fn drop_group(&self, name: String, options: DropGroupOptions) -> CouchbaseResult<()> {
         let (sender, receiver) = oneshot::channel();
-        self.core.send(Request::GenericManagementRequest(
+        let timer = self.core.send(Request::GenericManagementRequest(
             GenericManagementRequest {
                 sender,
                 path: format!("/settings/rbac/groups/{}", name),
@@ -638,13 +744,18 @@ impl UserManager {
                 payload: None,
                 content_type: None,
                 timeout: options.timeout,
+                service_type: None,
+                endpoint: None,
             },
         ));
 
-        let result: GenericManagementResult = receiver.await.unwrap().unwrap();
+        let result: GenericManagementResult = timer.finish(receiver.await.unwrap())?;
 
         match result.http_status() {
             200 => Ok(()),
+            404 => Err(CouchbaseError::GroupNotFound {
+                ctx: name_context(name),
+            }),
             _ => Err(CouchbaseError::GenericHTTP {
                 ctx: Default::default(),
                 status: result.http_status(),
@@ -655,3 +766,9 @@ impl UserManager {
         }
     }
 }
+
+fn name_context(name: String) -> ErrorContext {
+    let mut ctx = ErrorContext::default();
+    ctx.insert("name", Value::String(name));
+    ctx
+}
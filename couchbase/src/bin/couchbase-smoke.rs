@@ -0,0 +1,191 @@
+//! Runs a fixed set of checks against a cluster and prints a JSON report.
+//!
+//! Meant to gate deployments on a working cluster: the checks run one after the other, each
+//! bounded by a timeout, and the process exits with `0` if all of them passed, `1` if any failed
+//! and `2` if it was not configured correctly.
+//!
+//! The cluster is configured through environment variables, so the password does not end up in
+//! the process list:
+//!
+//! * `COUCHBASE_SMOKE_CONNECTION_STRING` - i.e. `couchbase://127.0.0.1`
+//! * `COUCHBASE_SMOKE_USERNAME`
+//! * `COUCHBASE_SMOKE_PASSWORD`
+//! * `COUCHBASE_SMOKE_BUCKET` - the bucket the key/value roundtrip writes a document to
+//! * `COUCHBASE_SMOKE_TIMEOUT` - the timeout of every check in seconds, 10 by default
+use couchbase::*;
+use futures::executor::block_on;
+use serde_json::{json, Value};
+use std::env;
+use std::process;
+use std::time::{Duration, Instant};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Config {
+    connection_string: String,
+    username: String,
+    password: String,
+    bucket: String,
+    timeout: Duration,
+}
+
+impl Config {
+    fn from_env() -> Result<Self, String> {
+        let timeout = match env::var("COUCHBASE_SMOKE_TIMEOUT") {
+            Ok(secs) => secs
+                .parse::<u64>()
+                .map(Duration::from_secs)
+                .map_err(|_| format!("COUCHBASE_SMOKE_TIMEOUT is not a number: {}", secs))?,
+            Err(_) => DEFAULT_TIMEOUT,
+        };
+        Ok(Self {
+            connection_string: required("COUCHBASE_SMOKE_CONNECTION_STRING")?,
+            username: required("COUCHBASE_SMOKE_USERNAME")?,
+            password: required("COUCHBASE_SMOKE_PASSWORD")?,
+            bucket: required("COUCHBASE_SMOKE_BUCKET")?,
+            timeout,
+        })
+    }
+}
+
+fn required(name: &str) -> Result<String, String> {
+    env::var(name).map_err(|_| format!("{} is not set", name))
+}
+
+fn main() {
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", json!({ "version": 1, "passed": false, "error": e }));
+            process::exit(2);
+        }
+    };
+
+    let cluster = Cluster::connect(
+        config.connection_string.as_str(),
+        config.username.as_str(),
+        config.password.as_str(),
+    );
+    let bucket = cluster.bucket(&config.bucket);
+    let collection = bucket.default_collection();
+    let timeout = config.timeout;
+
+    let checks = vec![
+        run("connect", || {
+            let report = block_on(
+                bucket.ping(
+                    PingOptions::default()
+                        .service_types(vec![ServiceType::KeyValue])
+                        .timeout(timeout),
+                ),
+            )?;
+            expect_ok(&report, ServiceType::KeyValue)
+        }),
+        run("kv", || {
+            let id = format!("couchbase-smoke::{}", uuid::Uuid::new_v4());
+            let content = json!({ "smoke": true });
+            block_on(
+                collection.upsert(
+                    &id,
+                    &content,
+                    UpsertOptions::default()
+                        .timeout(timeout)
+                        .expiry(Expiry::minutes(1)),
+                ),
+            )?;
+            let read: Value =
+                block_on(collection.get(&id, GetOptions::default().timeout(timeout)))?.content()?;
+            block_on(collection.remove(&id, RemoveOptions::default().timeout(timeout)))?;
+            if read == content {
+                Ok(())
+            } else {
+                Err(format!("read {} instead of the stored document", read).into())
+            }
+        }),
+        run("query", || {
+            let one: Value = block_on(
+                cluster.query_one("SELECT RAW 1", QueryOptions::default().timeout(timeout)),
+            )?;
+            if one == json!(1) {
+                Ok(())
+            } else {
+                Err(format!("SELECT RAW 1 returned {}", one).into())
+            }
+        }),
+        run("search", || {
+            let report = block_on(
+                cluster.ping(
+                    PingOptions::default()
+                        .service_types(vec![ServiceType::Search])
+                        .timeout(timeout),
+                ),
+            )?;
+            expect_ok(&report, ServiceType::Search)
+        }),
+    ];
+
+    let passed = checks.iter().all(|c| c["passed"] == json!(true));
+    println!(
+        "{}",
+        json!({
+            "version": 1,
+            "passed": passed,
+            "checks": checks,
+        })
+    );
+    process::exit(if passed { 0 } else { 1 });
+}
+
+/// Runs the check and reports its outcome and duration.
+fn run<F>(name: &str, check: F) -> Value
+where
+    F: FnOnce() -> Result<(), CheckError>,
+{
+    let start = Instant::now();
+    let result = check();
+    let mut report = json!({
+        "name": name,
+        "passed": result.is_ok(),
+        "duration_us": start.elapsed().as_micros() as u64,
+    });
+    if let Err(e) = result {
+        report["error"] = Value::String(e.0);
+    }
+    report
+}
+
+/// Fails unless the service has endpoints and all of them answered the ping.
+fn expect_ok(report: &PingResult, service: ServiceType) -> Result<(), CheckError> {
+    let endpoints = report
+        .endpoints()
+        .get(&service)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    if endpoints.is_empty() {
+        return Err(format!("no {:?} endpoints were pinged", service).into());
+    }
+    match endpoints.iter().find(|e| e.state() != PingState::OK) {
+        Some(e) => Err(format!(
+            "{} answered with {:?}: {}",
+            e.remote().unwrap_or_default(),
+            e.state(),
+            e.error().unwrap_or_default()
+        )
+        .into()),
+        None => Ok(()),
+    }
+}
+
+struct CheckError(String);
+
+impl From<CouchbaseError> for CheckError {
+    fn from(e: CouchbaseError) -> Self {
+        Self(e.to_string())
+    }
+}
+
+impl From<String> for CheckError {
+    fn from(e: String) -> Self {
+        Self(e)
+    }
+}
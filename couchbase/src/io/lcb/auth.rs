@@ -0,0 +1,183 @@
+use crate::api::authenticator::CredentialProvider;
+use crate::api::results::ServiceType;
+use crate::io::Credentials;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+use std::sync::Arc;
+
+const SERVICES: [ServiceType; 6] = [
+    ServiceType::Management,
+    ServiceType::KeyValue,
+    ServiceType::Views,
+    ServiceType::Query,
+    ServiceType::Search,
+    ServiceType::Analytics,
+];
+
+/// Holds the credentials handed out to libcouchbase through the dynamic authenticator.
+///
+/// Libcouchbase copies the returned strings right away, so they only need to live as long
+/// as the instance this cookie is associated with.
+pub struct AuthCookie {
+    default: (CString, CString),
+    services: Vec<(ServiceType, (CString, CString))>,
+    provider: Option<Arc<dyn CredentialProvider>>,
+    // The credentials last fetched from the provider, see `from_provider`
+    fetched: RefCell<Option<Fetched>>,
+}
+
+/// Credentials fetched from the provider for one endpoint.
+///
+/// Libcouchbase asks for the username and the password through separate callbacks, in no
+/// particular order. Both halves are served from a single fetch so a rotation in between
+/// can not pair the username of one credential with the password of another.
+struct Fetched {
+    endpoint: Option<String>,
+    credentials: (CString, CString),
+    username_served: bool,
+    password_served: bool,
+}
+
+#[derive(Copy, Clone)]
+enum Half {
+    Username,
+    Password,
+}
+
+impl AuthCookie {
+    pub fn new(credentials: &Credentials) -> Self {
+        let services = SERVICES
+            .iter()
+            .map(|s| {
+                let (username, password) = credentials.for_service(*s);
+                (*s, (to_cstring(username), to_cstring(password)))
+            })
+            .collect();
+        Self {
+            default: (
+                to_cstring(credentials.username()),
+                to_cstring(credentials.password()),
+            ),
+            services,
+            provider: credentials.provider().cloned(),
+            fetched: RefCell::new(None),
+        }
+    }
+
+    fn resolve(&self, host: *const c_char, port: *const c_char, half: Half) -> *const c_char {
+        if self.provider.is_some() {
+            return self.from_provider(host, port, half);
+        }
+        let credentials = self.for_port(port);
+        match half {
+            Half::Username => credentials.0.as_ptr(),
+            Half::Password => credentials.1.as_ptr(),
+        }
+    }
+
+    fn from_provider(&self, host: *const c_char, port: *const c_char, half: Half) -> *const c_char {
+        let port = to_str(port);
+        let endpoint = match (to_str(host), port) {
+            (Some(h), Some(p)) => Some(format!("{}:{}", h, p)),
+            _ => None,
+        };
+
+        let mut fetched = self.fetched.borrow_mut();
+        let reuse = match fetched.as_ref() {
+            Some(f) if f.endpoint == endpoint => match half {
+                Half::Username => !f.username_served && f.password_served,
+                Half::Password => !f.password_served && f.username_served,
+            },
+            _ => false,
+        };
+        if !reuse {
+            let provider = self.provider.as_ref().unwrap();
+            let (username, password) =
+                provider.credentials(port.and_then(service_for_port), endpoint.as_deref());
+            *fetched = Some(Fetched {
+                endpoint,
+                credentials: (to_cstring(&username), to_cstring(&password)),
+                username_served: false,
+                password_served: false,
+            });
+        }
+
+        let f = fetched.as_mut().unwrap();
+        match half {
+            Half::Username => {
+                f.username_served = true;
+                f.credentials.0.as_ptr()
+            }
+            Half::Password => {
+                f.password_served = true;
+                f.credentials.1.as_ptr()
+            }
+        }
+    }
+
+    fn for_port(&self, port: *const c_char) -> &(CString, CString) {
+        if port.is_null() {
+            return &self.default;
+        }
+        let service = match unsafe { CStr::from_ptr(port) }.to_str() {
+            Ok(p) => service_for_port(p),
+            Err(_) => None,
+        };
+        match service {
+            Some(s) => self
+                .services
+                .iter()
+                .find(|(t, _)| *t == s)
+                .map(|(_, c)| c)
+                .unwrap_or(&self.default),
+            None => &self.default,
+        }
+    }
+}
+
+fn to_str<'a>(value: *const c_char) -> Option<&'a str> {
+    if value.is_null() {
+        None
+    } else {
+        unsafe { CStr::from_ptr(value) }.to_str().ok()
+    }
+}
+
+fn to_cstring(value: &str) -> CString {
+    CString::new(value).expect("Credentials must not contain NUL bytes")
+}
+
+/// Libcouchbase only tells us which port the credentials are requested for, so the
+/// service is identified through its default (plain or TLS) port.
+fn service_for_port(port: &str) -> Option<ServiceType> {
+    match port {
+        "11210" | "11207" => Some(ServiceType::KeyValue),
+        "8091" | "18091" => Some(ServiceType::Management),
+        "8092" | "18092" => Some(ServiceType::Views),
+        "8093" | "18093" => Some(ServiceType::Query),
+        "8094" | "18094" => Some(ServiceType::Search),
+        "8095" | "18095" => Some(ServiceType::Analytics),
+        _ => None,
+    }
+}
+
+pub unsafe extern "C" fn username_callback(
+    cookie: *mut c_void,
+    host: *const c_char,
+    port: *const c_char,
+    _bucket: *const c_char,
+) -> *const c_char {
+    let cookie = &*(cookie as *const AuthCookie);
+    cookie.resolve(host, port, Half::Username)
+}
+
+pub unsafe extern "C" fn password_callback(
+    cookie: *mut c_void,
+    host: *const c_char,
+    port: *const c_char,
+    _bucket: *const c_char,
+) -> *const c_char {
+    let cookie = &*(cookie as *const AuthCookie);
+    cookie.resolve(host, port, Half::Password)
+}
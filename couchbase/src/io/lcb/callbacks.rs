@@ -1,12 +1,17 @@
-use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::error::{permission_denied, CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::events::ClusterEvent;
+use crate::api::query_transaction::TRANSACTION_ERROR_CODES;
+use crate::api::redaction::{MetaData, SystemData};
 use crate::api::results::{
-    AnalyticsResult, ExistsResult, GenericManagementResult, GetResult, LookupInResult,
-    MutateInResult, MutationResult, PingResult, PingState, QueryResult, SearchResult, SubDocField,
+    AnalyticsResult, ExistsResult, GenericManagementResult, GetReplicaResult, GetResult,
+    LookupInReplicaResult, LookupInResult, MutateInResult, MutationResult, PingResult, PingState,
+    QueryMetaData, QueryResult, SearchResult, SubDocField,
 };
 use crate::api::MutationToken;
 use crate::io::lcb::HttpCookie;
 use couchbase_sys::*;
-use log::{debug, trace};
+use log::{debug, trace, warn};
+use serde_derive::Deserialize;
 use serde_json::Value;
 use std::convert::TryInto;
 use std::ffi::CStr;
@@ -17,11 +22,25 @@ use std::str;
 use std::time::Duration;
 
 use crate::io::lcb::{
-    bucket_name_for_instance, wrapped_vsnprintf, AnalyticsCookie, QueryCookie, SearchCookie,
+    bucket_name_for_instance, wrapped_vsnprintf, AnalyticsCookie, LookupInCookie, QueryCookie,
+    SearchCookie,
 };
 
-use crate::io::lcb::instance::decrement_outstanding_requests;
-use crate::{CounterResult, EndpointPingReport, ServiceType};
+use crate::io::lcb::durability;
+use crate::io::lcb::events;
+use crate::io::lcb::health;
+use crate::io::lcb::http_metrics;
+use crate::io::lcb::instance::{
+    decrement_outstanding_requests, set_bootstrap_status, set_open_status,
+    unregister_streaming_request,
+};
+#[cfg(feature = "packet-dump")]
+use crate::io::lcb::packets;
+use crate::io::lcb::retry;
+use crate::{
+    CircuitBreakerState, CounterResult, DiagnosticsResult, EndpointDiagnostics, EndpointPingReport,
+    EndpointState, ServiceType,
+};
 use std::collections::HashMap;
 
 fn decode_and_own_str(ptr: *const c_char, len: usize) -> String {
@@ -40,6 +59,7 @@ pub unsafe extern "C" fn store_callback(
 
     let mut cookie_ptr: *mut c_void = ptr::null_mut();
     lcb_respstore_cookie(store_res, &mut cookie_ptr);
+    retry::forget(cookie_ptr);
     let sender = Box::from_raw(
         cookie_ptr as *mut futures::channel::oneshot::Sender<CouchbaseResult<MutationResult>>,
     );
@@ -48,6 +68,7 @@ pub unsafe extern "C" fn store_callback(
     lcb_respstore_error_context(store_res, &mut lcb_ctx);
 
     let status = lcb_respstore_status(store_res);
+    retry::deposit(status);
     let result = if status == lcb_STATUS_LCB_SUCCESS {
         let mut cas: u64 = 0;
         lcb_respstore_cas(store_res, &mut cas);
@@ -77,7 +98,7 @@ pub unsafe extern "C" fn store_callback(
     } else {
         Err(couchbase_error_from_lcb_status(
             status,
-            build_kv_error_context(lcb_ctx),
+            build_kv_error_context(lcb_ctx, cookie_ptr),
         ))
     };
     match sender.send(result) {
@@ -96,6 +117,7 @@ pub unsafe extern "C" fn remove_callback(
 
     let mut cookie_ptr: *mut c_void = ptr::null_mut();
     lcb_respremove_cookie(remove_res, &mut cookie_ptr);
+    retry::forget(cookie_ptr);
     let sender = Box::from_raw(
         cookie_ptr as *mut futures::channel::oneshot::Sender<CouchbaseResult<MutationResult>>,
     );
@@ -104,6 +126,7 @@ pub unsafe extern "C" fn remove_callback(
     lcb_respremove_error_context(remove_res, &mut lcb_ctx);
 
     let status = lcb_respremove_status(remove_res);
+    retry::deposit(status);
     let result = if status == lcb_STATUS_LCB_SUCCESS {
         let mut cas: u64 = 0;
         lcb_respremove_cas(remove_res, &mut cas);
@@ -133,12 +156,106 @@ pub unsafe extern "C" fn remove_callback(
     } else {
         Err(couchbase_error_from_lcb_status(
             status,
-            build_kv_error_context(lcb_ctx),
+            build_kv_error_context(lcb_ctx, cookie_ptr),
         ))
     };
-    match sender.send(result) {
-        Ok(_) => {}
-        Err(e) => trace!("Failed to send remove result because of {:?}", e),
+    send_mutation_result(
+        instance, cookie_ptr, lcb_ctx, *sender, result, "remove", true,
+    );
+}
+
+/// The results of the mutations which can wait for client verified durability.
+trait DurableResult {
+    fn cas(&self) -> u64;
+    fn mutation_token(&self) -> Option<&MutationToken>;
+}
+
+impl DurableResult for MutationResult {
+    fn cas(&self) -> u64 {
+        MutationResult::cas(self)
+    }
+
+    fn mutation_token(&self) -> Option<&MutationToken> {
+        MutationResult::mutation_token(self)
+    }
+}
+
+impl DurableResult for CounterResult {
+    fn cas(&self) -> u64 {
+        CounterResult::cas(self)
+    }
+
+    fn mutation_token(&self) -> Option<&MutationToken> {
+        CounterResult::mutation_token(self)
+    }
+}
+
+impl DurableResult for MutateInResult {
+    fn cas(&self) -> u64 {
+        MutateInResult::cas(self)
+    }
+
+    fn mutation_token(&self) -> Option<&MutationToken> {
+        MutateInResult::mutation_token(self)
+    }
+}
+
+/// Sends the result of a remove, counter or sub-document mutation, but only once it has been
+/// persisted and replicated if the operation asked for client verified durability.
+///
+/// Libcouchbase only polls for the durability of stores as part of the operation, so for these
+/// the polling is started once the mutation succeeded.
+unsafe fn send_mutation_result<T: DurableResult + 'static>(
+    instance: *mut lcb_INSTANCE,
+    cookie: *mut c_void,
+    lcb_ctx: *const lcb_KEY_VALUE_ERROR_CONTEXT,
+    sender: futures::channel::oneshot::Sender<CouchbaseResult<T>>,
+    result: CouchbaseResult<T>,
+    operation: &'static str,
+    removed: bool,
+) {
+    let result = match (durability::take(cookie), result) {
+        (Some(client_durability), Ok(result)) => {
+            let ctx = build_kv_error_context(lcb_ctx, cookie);
+            let mut key_len: usize = 0;
+            let mut key_ptr: *const c_char = ptr::null();
+            lcb_errctx_kv_key(lcb_ctx, &mut key_ptr, &mut key_len);
+            let key: &[u8] = if key_ptr.is_null() {
+                &[]
+            } else {
+                from_raw_parts(key_ptr as *const u8, key_len)
+            };
+            let token = result.mutation_token().cloned();
+            durability::endure(
+                instance,
+                client_durability,
+                key,
+                result.cas(),
+                token.as_ref(),
+                removed,
+                Box::new(move |status| {
+                    let result = if status == lcb_STATUS_LCB_SUCCESS {
+                        Ok(result)
+                    } else {
+                        Err(couchbase_error_from_lcb_status(status, ctx))
+                    };
+                    if sender.send(result).is_err() {
+                        trace!(
+                            "Failed to send {} result because the receiver is gone",
+                            operation
+                        );
+                    }
+                }),
+            );
+            return;
+        }
+        (_, result) => result,
+    };
+    if sender.send(result).is_err() {
+        trace!(
+            "Failed to send {} result because the receiver is gone",
+            operation
+        );
     }
 }
 
@@ -151,11 +268,13 @@ pub unsafe extern "C" fn get_callback(
     let get_res = res as *const lcb_RESPGET;
     let mut cookie_ptr: *mut c_void = ptr::null_mut();
     lcb_respget_cookie(get_res, &mut cookie_ptr);
+    retry::forget(cookie_ptr);
     let sender = Box::from_raw(
         cookie_ptr as *mut futures::channel::oneshot::Sender<CouchbaseResult<GetResult>>,
     );
 
     let status = lcb_respget_status(get_res);
+    retry::deposit(status);
     let result = if status == lcb_STATUS_LCB_SUCCESS {
         let mut cas: u64 = 0;
         let mut flags: u32 = 0;
@@ -171,7 +290,7 @@ pub unsafe extern "C" fn get_callback(
         lcb_respget_error_context(get_res, &mut lcb_ctx);
         Err(couchbase_error_from_lcb_status(
             status,
-            build_kv_error_context(lcb_ctx),
+            build_kv_error_context(lcb_ctx, cookie_ptr),
         ))
     };
 
@@ -181,6 +300,56 @@ pub unsafe extern "C" fn get_callback(
     }
 }
 
+/// Handles the responses of a replica read.
+///
+/// Libcouchbase invokes this once per replica, so the cookie is only released once the final
+/// response arrived. Dropping the sender at that point closes the stream of results.
+pub unsafe extern "C" fn get_replica_callback(
+    instance: *mut lcb_INSTANCE,
+    _cbtype: i32,
+    res: *const lcb_RESPBASE,
+) {
+    let get_res = res as *const lcb_RESPGETREPLICA;
+    let mut cookie_ptr: *mut c_void = ptr::null_mut();
+    lcb_respgetreplica_cookie(get_res, &mut cookie_ptr);
+    let sender = Box::from_raw(
+        cookie_ptr
+            as *mut futures::channel::mpsc::UnboundedSender<CouchbaseResult<GetReplicaResult>>,
+    );
+
+    let status = lcb_respgetreplica_status(get_res);
+    retry::deposit(status);
+    let result = if status == lcb_STATUS_LCB_SUCCESS {
+        let mut cas: u64 = 0;
+        let mut flags: u32 = 0;
+        let mut value_len: usize = 0;
+        let mut value_ptr: *const c_char = ptr::null();
+        lcb_respgetreplica_cas(get_res, &mut cas);
+        lcb_respgetreplica_flags(get_res, &mut flags);
+        lcb_respgetreplica_value(get_res, &mut value_ptr, &mut value_len);
+        let value = from_raw_parts(value_ptr as *const u8, value_len);
+        Ok(GetReplicaResult::new(value.to_vec(), cas, flags, true))
+    } else {
+        let mut lcb_ctx: *const lcb_KEY_VALUE_ERROR_CONTEXT = ptr::null();
+        lcb_respgetreplica_error_context(get_res, &mut lcb_ctx);
+        Err(couchbase_error_from_lcb_status(
+            status,
+            build_kv_error_context(lcb_ctx, cookie_ptr),
+        ))
+    };
+
+    match sender.unbounded_send(result) {
+        Ok(_) => {}
+        Err(e) => trace!("Failed to send get replica result because of {:?}", e),
+    }
+
+    if lcb_respgetreplica_is_final(get_res) != 0 {
+        decrement_outstanding_requests(instance);
+    } else {
+        Box::into_raw(sender);
+    }
+}
+
 pub unsafe extern "C" fn exists_callback(
     instance: *mut lcb_INSTANCE,
     _cbtype: i32,
@@ -190,11 +359,13 @@ pub unsafe extern "C" fn exists_callback(
     let exists_res = res as *const lcb_RESPEXISTS;
     let mut cookie_ptr: *mut c_void = ptr::null_mut();
     lcb_respexists_cookie(exists_res, &mut cookie_ptr);
+    retry::forget(cookie_ptr);
     let sender = Box::from_raw(
         cookie_ptr as *mut futures::channel::oneshot::Sender<CouchbaseResult<ExistsResult>>,
     );
 
     let status = lcb_respexists_status(exists_res);
+    retry::deposit(status);
     let result = if status == lcb_STATUS_LCB_SUCCESS {
         let found = lcb_respexists_is_found(exists_res);
         Ok(if found != 0 {
@@ -209,7 +380,7 @@ pub unsafe extern "C" fn exists_callback(
         lcb_respexists_error_context(exists_res, &mut lcb_ctx);
         Err(couchbase_error_from_lcb_status(
             status,
-            build_kv_error_context(lcb_ctx),
+            build_kv_error_context(lcb_ctx, cookie_ptr),
         ))
     };
     match sender.send(result) {
@@ -218,6 +389,94 @@ pub unsafe extern "C" fn exists_callback(
     }
 }
 
+pub unsafe extern "C" fn unlock_callback(
+    instance: *mut lcb_INSTANCE,
+    _cbtype: i32,
+    res: *const lcb_RESPBASE,
+) {
+    decrement_outstanding_requests(instance);
+    let unlock_res = res as *const lcb_RESPUNLOCK;
+    let mut cookie_ptr: *mut c_void = ptr::null_mut();
+    lcb_respunlock_cookie(unlock_res, &mut cookie_ptr);
+    retry::forget(cookie_ptr);
+    let sender =
+        Box::from_raw(cookie_ptr as *mut futures::channel::oneshot::Sender<CouchbaseResult<()>>);
+
+    let status = lcb_respunlock_status(unlock_res);
+    retry::deposit(status);
+    let result = if status == lcb_STATUS_LCB_SUCCESS {
+        Ok(())
+    } else {
+        let mut lcb_ctx: *const lcb_KEY_VALUE_ERROR_CONTEXT = ptr::null();
+        lcb_respunlock_error_context(unlock_res, &mut lcb_ctx);
+        Err(couchbase_error_from_lcb_status(
+            status,
+            build_kv_error_context(lcb_ctx, cookie_ptr),
+        ))
+    };
+    match sender.send(result) {
+        Ok(_) => {}
+        Err(e) => trace!("Failed to send unlock result because of {:?}", e),
+    }
+}
+
+pub unsafe extern "C" fn touch_callback(
+    instance: *mut lcb_INSTANCE,
+    _cbtype: i32,
+    res: *const lcb_RESPBASE,
+) {
+    decrement_outstanding_requests(instance);
+    let touch_res = res as *const lcb_RESPTOUCH;
+    let mut cookie_ptr: *mut c_void = ptr::null_mut();
+    lcb_resptouch_cookie(touch_res, &mut cookie_ptr);
+    retry::forget(cookie_ptr);
+    let sender = Box::from_raw(
+        cookie_ptr as *mut futures::channel::oneshot::Sender<CouchbaseResult<MutationResult>>,
+    );
+
+    let mut lcb_ctx: *const lcb_KEY_VALUE_ERROR_CONTEXT = ptr::null();
+    lcb_resptouch_error_context(touch_res, &mut lcb_ctx);
+
+    let status = lcb_resptouch_status(touch_res);
+    retry::deposit(status);
+    let result = if status == lcb_STATUS_LCB_SUCCESS {
+        let mut cas: u64 = 0;
+        lcb_resptouch_cas(touch_res, &mut cas);
+
+        let mut lcb_mutation_token = lcb_MUTATION_TOKEN {
+            uuid_: 0,
+            seqno_: 0,
+            vbid_: 0,
+        };
+        lcb_resptouch_mutation_token(touch_res, &mut lcb_mutation_token);
+        let mutation_token = if lcb_mutation_token.uuid_ != 0 {
+            let mut bucket_len: usize = 0;
+            let mut bucket_ptr: *const c_char = ptr::null();
+            lcb_errctx_kv_bucket(lcb_ctx, &mut bucket_ptr, &mut bucket_len);
+            let bucket = decode_and_own_str(bucket_ptr, bucket_len);
+
+            Some(MutationToken::new(
+                lcb_mutation_token.uuid_,
+                lcb_mutation_token.seqno_,
+                lcb_mutation_token.vbid_,
+                bucket,
+            ))
+        } else {
+            None
+        };
+        Ok(MutationResult::new(cas, mutation_token))
+    } else {
+        Err(couchbase_error_from_lcb_status(
+            status,
+            build_kv_error_context(lcb_ctx, cookie_ptr),
+        ))
+    };
+    match sender.send(result) {
+        Ok(_) => {}
+        Err(e) => trace!("Failed to send touch result because of {:?}", e),
+    }
+}
+
 pub unsafe extern "C" fn lookup_in_callback(
     instance: *mut lcb_INSTANCE,
     _cbtype: i32,
@@ -227,11 +486,11 @@ pub unsafe extern "C" fn lookup_in_callback(
     let subdoc_res = res as *const lcb_RESPSUBDOC;
     let mut cookie_ptr: *mut c_void = ptr::null_mut();
     lcb_respsubdoc_cookie(subdoc_res, &mut cookie_ptr);
-    let sender = Box::from_raw(
-        cookie_ptr as *mut futures::channel::oneshot::Sender<CouchbaseResult<LookupInResult>>,
-    );
+    retry::forget(cookie_ptr);
+    let cookie = Box::from_raw(cookie_ptr as *mut LookupInCookie);
 
     let status = lcb_respsubdoc_status(subdoc_res);
+    retry::deposit(status);
     let result = if status == lcb_STATUS_LCB_SUCCESS {
         let total_size = lcb_respsubdoc_result_size(subdoc_res);
         let mut fields = vec![];
@@ -254,12 +513,21 @@ pub unsafe extern "C" fn lookup_in_callback(
         lcb_respsubdoc_error_context(subdoc_res, &mut lcb_ctx);
         Err(couchbase_error_from_lcb_status(
             status,
-            build_kv_error_context(lcb_ctx),
+            build_kv_error_context(lcb_ctx, cookie_ptr),
         ))
     };
-    match sender.send(result) {
-        Ok(_) => {}
-        Err(e) => trace!("Failed to send lookup in result because of {:?}", e),
+    match *cookie {
+        LookupInCookie::Active { sender } => match sender.send(result) {
+            Ok(_) => {}
+            Err(e) => trace!("Failed to send lookup in result because of {:?}", e),
+        },
+        LookupInCookie::Replica { sender } => {
+            let result = result.map(|r| LookupInReplicaResult::new(r, true));
+            match sender.unbounded_send(result) {
+                Ok(_) => {}
+                Err(e) => trace!("Failed to send lookup in replica result because of {:?}", e),
+            }
+        }
     }
 }
 
@@ -272,11 +540,16 @@ pub unsafe extern "C" fn mutate_in_callback(
     let subdoc_res = res as *const lcb_RESPSUBDOC;
     let mut cookie_ptr: *mut c_void = ptr::null_mut();
     lcb_respsubdoc_cookie(subdoc_res, &mut cookie_ptr);
+    retry::forget(cookie_ptr);
     let sender = Box::from_raw(
         cookie_ptr as *mut futures::channel::oneshot::Sender<CouchbaseResult<MutateInResult>>,
     );
 
+    let mut lcb_ctx: *const lcb_KEY_VALUE_ERROR_CONTEXT = ptr::null();
+    lcb_respsubdoc_error_context(subdoc_res, &mut lcb_ctx);
+
     let status = lcb_respsubdoc_status(subdoc_res);
+    retry::deposit(status);
     let result = if status == lcb_STATUS_LCB_SUCCESS {
         let total_size = lcb_respsubdoc_result_size(subdoc_res);
         let mut fields = vec![];
@@ -293,19 +566,44 @@ pub unsafe extern "C" fn mutate_in_callback(
         }
         let mut cas: u64 = 0;
         lcb_respsubdoc_cas(subdoc_res, &mut cas);
-        Ok(MutateInResult::new(fields, cas))
+
+        let mut lcb_mutation_token = lcb_MUTATION_TOKEN {
+            uuid_: 0,
+            seqno_: 0,
+            vbid_: 0,
+        };
+        lcb_respsubdoc_mutation_token(subdoc_res, &mut lcb_mutation_token);
+        let mutation_token = if lcb_mutation_token.uuid_ != 0 {
+            let mut bucket_len: usize = 0;
+            let mut bucket_ptr: *const c_char = ptr::null();
+            lcb_errctx_kv_bucket(lcb_ctx, &mut bucket_ptr, &mut bucket_len);
+            let bucket = decode_and_own_str(bucket_ptr, bucket_len);
+
+            Some(MutationToken::new(
+                lcb_mutation_token.uuid_,
+                lcb_mutation_token.seqno_,
+                lcb_mutation_token.vbid_,
+                bucket,
+            ))
+        } else {
+            None
+        };
+        Ok(MutateInResult::new(fields, cas, mutation_token))
     } else {
-        let mut lcb_ctx: *const lcb_KEY_VALUE_ERROR_CONTEXT = ptr::null();
-        lcb_respsubdoc_error_context(subdoc_res, &mut lcb_ctx);
         Err(couchbase_error_from_lcb_status(
             status,
-            build_kv_error_context(lcb_ctx),
+            build_kv_error_context(lcb_ctx, cookie_ptr),
         ))
     };
-    match sender.send(result) {
-        Ok(_) => {}
-        Err(e) => trace!("Failed to send mutate in result because of {:?}", e),
-    }
+    send_mutation_result(
+        instance,
+        cookie_ptr,
+        lcb_ctx,
+        *sender,
+        result,
+        "mutate in",
+        false,
+    );
 }
 
 pub unsafe extern "C" fn counter_callback(
@@ -318,6 +616,7 @@ pub unsafe extern "C" fn counter_callback(
 
     let mut cookie_ptr: *mut c_void = ptr::null_mut();
     lcb_respcounter_cookie(counter_res, &mut cookie_ptr);
+    retry::forget(cookie_ptr);
     let sender = Box::from_raw(
         cookie_ptr as *mut futures::channel::oneshot::Sender<CouchbaseResult<CounterResult>>,
     );
@@ -326,6 +625,7 @@ pub unsafe extern "C" fn counter_callback(
     lcb_respcounter_error_context(counter_res, &mut lcb_ctx);
 
     let status = lcb_respcounter_status(counter_res);
+    retry::deposit(status);
     let result = if status == lcb_STATUS_LCB_SUCCESS {
         let mut cas: u64 = 0;
         lcb_respcounter_cas(counter_res, &mut cas);
@@ -358,18 +658,25 @@ pub unsafe extern "C" fn counter_callback(
     } else {
         Err(couchbase_error_from_lcb_status(
             status,
-            build_kv_error_context(lcb_ctx),
+            build_kv_error_context(lcb_ctx, cookie_ptr),
         ))
     };
-    match sender.send(result) {
-        Ok(_) => {}
-        Err(e) => trace!("Failed to send remove result because of {:?}", e),
-    }
+    send_mutation_result(
+        instance, cookie_ptr, lcb_ctx, *sender, result, "counter", false,
+    );
 }
 
-fn build_kv_error_context(lcb_ctx: *const lcb_KEY_VALUE_ERROR_CONTEXT) -> ErrorContext {
+fn build_kv_error_context(
+    lcb_ctx: *const lcb_KEY_VALUE_ERROR_CONTEXT,
+    cookie: *mut c_void,
+) -> ErrorContext {
     let mut ctx = ErrorContext::default();
 
+    if let Some((reason, attempts)) = retry::take_denied(cookie) {
+        ctx.insert("retry_reason", Value::String(reason.into()));
+        ctx.insert("retry_attempts", Value::from(attempts));
+    }
+
     let mut key_len: usize = 0;
     let mut key_ptr: *const c_char = ptr::null();
     unsafe { lcb_errctx_kv_key(lcb_ctx, &mut key_ptr, &mut key_len) };
@@ -430,6 +737,49 @@ fn build_kv_error_context(lcb_ctx: *const lcb_KEY_VALUE_ERROR_CONTEXT) -> ErrorC
         }
     }
 
+    // The connection id is logged by the server as well, so together with the local address
+    // it identifies the exact connection in the server logs.
+    let mut local_len: usize = 0;
+    let mut local_ptr: *const c_char = ptr::null();
+    let mut connection_id_len: usize = 0;
+    let mut connection_id_ptr: *const c_char = ptr::null();
+    unsafe {
+        lcb_errctx_kv_local_endpoint(lcb_ctx, &mut local_ptr, &mut local_len);
+        if !local_ptr.is_null() && local_len > 0 {
+            ctx.insert(
+                "local",
+                Value::String(decode_and_own_str(local_ptr, local_len)),
+            );
+        }
+        lcb_errctx_kv_connection_id(lcb_ctx, &mut connection_id_ptr, &mut connection_id_len);
+        if !connection_id_ptr.is_null() && connection_id_len > 0 {
+            let connection_id = decode_and_own_str(connection_id_ptr, connection_id_len);
+            ctx.insert("connection_id", Value::String(connection_id));
+        }
+    }
+
+    // The error reference is also logged by the server, so it identifies the failed operation
+    // when cross-referencing the server logs.
+    let mut ref_len: usize = 0;
+    let mut ref_ptr: *const c_char = ptr::null();
+    unsafe {
+        lcb_errctx_kv_ref(lcb_ctx, &mut ref_ptr, &mut ref_len);
+        if !ref_ptr.is_null() && ref_len > 0 {
+            let error_ref = decode_and_own_str(ref_ptr, ref_len);
+            ctx.insert("ref", Value::String(error_ref));
+        }
+    }
+
+    let mut context_len: usize = 0;
+    let mut context_ptr: *const c_char = ptr::null();
+    unsafe {
+        lcb_errctx_kv_context(lcb_ctx, &mut context_ptr, &mut context_len);
+        if !context_ptr.is_null() && context_len > 0 {
+            let context = decode_and_own_str(context_ptr, context_len);
+            ctx.insert("context", Value::String(context));
+        }
+    }
+
     let status = unsafe {
         let mut o = 0u16;
         lcb_errctx_kv_status_code(lcb_ctx, &mut o);
@@ -440,6 +790,19 @@ fn build_kv_error_context(lcb_ctx: *const lcb_KEY_VALUE_ERROR_CONTEXT) -> ErrorC
     ctx
 }
 
+/// The query node which served the request, which is known for successful queries as well.
+fn query_endpoint(lcb_ctx: *const lcb_QUERY_ERROR_CONTEXT) -> Option<String> {
+    let mut endpoint_len: usize = 0;
+    let mut endpoint_ptr: *const c_char = ptr::null();
+    unsafe {
+        lcb_errctx_query_endpoint(lcb_ctx, &mut endpoint_ptr, &mut endpoint_len);
+        if !endpoint_ptr.is_null() && endpoint_len > 0 {
+            return Some(decode_and_own_str(endpoint_ptr, endpoint_len));
+        }
+    }
+    None
+}
+
 fn build_query_error_context(lcb_ctx: *const lcb_QUERY_ERROR_CONTEXT) -> ErrorContext {
     let mut ctx = ErrorContext::default();
 
@@ -451,6 +814,38 @@ fn build_query_error_context(lcb_ctx: *const lcb_QUERY_ERROR_CONTEXT) -> ErrorCo
     };
     ctx.insert("statement", Value::String(statement));
 
+    let mut context_id_len: usize = 0;
+    let mut context_id_ptr: *const c_char = ptr::null();
+    unsafe {
+        lcb_errctx_query_client_context_id(lcb_ctx, &mut context_id_ptr, &mut context_id_len);
+        if !context_id_ptr.is_null() && context_id_len > 0 {
+            let context_id = decode_and_own_str(context_id_ptr, context_id_len);
+            ctx.insert("client_context_id", Value::String(context_id));
+        }
+    }
+
+    if let Some(endpoint) = query_endpoint(lcb_ctx) {
+        ctx.insert("remote", Value::String(endpoint));
+    }
+
+    let mut first_error_code: u32 = 0;
+    unsafe {
+        lcb_errctx_query_first_error_code(lcb_ctx, &mut first_error_code);
+    }
+    if first_error_code != 0 {
+        ctx.insert("first_error_code", Value::from(first_error_code));
+
+        let mut message_len: usize = 0;
+        let mut message_ptr: *const c_char = ptr::null();
+        unsafe {
+            lcb_errctx_query_first_error_message(lcb_ctx, &mut message_ptr, &mut message_len);
+            if !message_ptr.is_null() && message_len > 0 {
+                let message = decode_and_own_str(message_ptr, message_len);
+                ctx.insert("first_error_message", Value::String(message));
+            }
+        }
+    }
+
     ctx
 }
 
@@ -465,6 +860,26 @@ fn build_analytics_error_context(lcb_ctx: *const lcb_ANALYTICS_ERROR_CONTEXT) ->
     };
     ctx.insert("statement", Value::String(statement));
 
+    let mut context_id_len: usize = 0;
+    let mut context_id_ptr: *const c_char = ptr::null();
+    unsafe {
+        lcb_errctx_analytics_client_context_id(lcb_ctx, &mut context_id_ptr, &mut context_id_len);
+        if !context_id_ptr.is_null() && context_id_len > 0 {
+            let context_id = decode_and_own_str(context_id_ptr, context_id_len);
+            ctx.insert("client_context_id", Value::String(context_id));
+        }
+    }
+
+    let mut endpoint_len: usize = 0;
+    let mut endpoint_ptr: *const c_char = ptr::null();
+    unsafe {
+        lcb_errctx_analytics_endpoint(lcb_ctx, &mut endpoint_ptr, &mut endpoint_len);
+        if !endpoint_ptr.is_null() && endpoint_len > 0 {
+            let endpoint = decode_and_own_str(endpoint_ptr, endpoint_len);
+            ctx.insert("remote", Value::String(endpoint));
+        }
+    }
+
     ctx
 }
 
@@ -479,6 +894,16 @@ fn build_search_error_context(lcb_ctx: *const lcb_SEARCH_ERROR_CONTEXT) -> Error
     };
     ctx.insert("query", Value::String(query));
 
+    let mut endpoint_len: usize = 0;
+    let mut endpoint_ptr: *const c_char = ptr::null();
+    unsafe {
+        lcb_errctx_search_endpoint(lcb_ctx, &mut endpoint_ptr, &mut endpoint_len);
+        if !endpoint_ptr.is_null() && endpoint_len > 0 {
+            let endpoint = decode_and_own_str(endpoint_ptr, endpoint_len);
+            ctx.insert("remote", Value::String(endpoint));
+        }
+    }
+
     ctx
 }
 
@@ -502,15 +927,22 @@ pub unsafe extern "C" fn query_callback(
         let response = if status != 0 {
             let mut lcb_ctx: *const lcb_QUERY_ERROR_CONTEXT = ptr::null();
             lcb_respquery_error_context(res, &mut lcb_ctx);
-            Err(couchbase_error_from_lcb_status(
-                status,
-                build_query_error_context(lcb_ctx),
-            ))
+            let ctx = build_query_error_context(lcb_ctx);
+            let in_transaction = ctx
+                .get("first_error_code")
+                .and_then(Value::as_u64)
+                .map_or(false, |code| TRANSACTION_ERROR_CODES.contains(&code));
+            if in_transaction {
+                Err(CouchbaseError::QueryTransactionFailure { ctx })
+            } else {
+                Err(couchbase_error_from_lcb_status(status, ctx))
+            }
         } else {
             Ok(QueryResult::new(
                 cookie.rows_receiver.take().unwrap(),
                 cookie.meta_receiver.take().unwrap(),
-            ))
+            )
+            .with_buffer(cookie.buffer.clone()))
         };
 
         match cookie
@@ -525,18 +957,33 @@ pub unsafe extern "C" fn query_callback(
     }
 
     if lcb_respquery_is_final(res) != 0 {
+        unregister_streaming_request(instance, cookie_ptr);
+        let mut http_res: *const lcb_RESPHTTP = ptr::null();
+        lcb_respquery_http_response(res, &mut http_res);
+        http_metrics::record_body_sizes(ServiceType::Query, http_res);
         cookie.rows_sender.close_channel();
 
         if status == 0 {
+            let mut lcb_ctx: *const lcb_QUERY_ERROR_CONTEXT = ptr::null();
+            lcb_respquery_error_context(res, &mut lcb_ctx);
+            let meta: QueryMetaData = serde_json::from_slice(row).unwrap();
             match cookie
                 .meta_sender
-                .send(serde_json::from_slice(row).unwrap())
+                .send(meta.with_endpoint(query_endpoint(lcb_ctx)))
             {
                 Ok(_) => {}
                 Err(e) => trace!("Failed to send query meta data because of {:?}", e),
             }
         }
 
+        decrement_outstanding_requests(instance);
+    } else if !cookie.buffer.push(row.len()) || cookie.rows_sender.is_closed() {
+        // The rows are not consumed fast enough or not at all anymore, so stop the query
+        // instead of buffering the rest of its result. No more callbacks follow the cancel.
+        debug!("Cancelling query whose rows are not consumed");
+        lcb_query_cancel(instance, cookie.handle);
+        unregister_streaming_request(instance, cookie_ptr);
+        cookie.rows_sender.close_channel();
         decrement_outstanding_requests(instance);
     } else {
         match cookie.rows_sender.unbounded_send(row.to_vec()) {
@@ -590,6 +1037,10 @@ pub unsafe extern "C" fn analytics_callback(
     }
 
     if lcb_respanalytics_is_final(res) != 0 {
+        unregister_streaming_request(instance, cookie_ptr);
+        let mut http_res: *const lcb_RESPHTTP = ptr::null();
+        lcb_respanalytics_http_response(res, &mut http_res);
+        http_metrics::record_body_sizes(ServiceType::Analytics, http_res);
         cookie.rows_sender.close_channel();
 
         if status == 0 {
@@ -602,6 +1053,12 @@ pub unsafe extern "C" fn analytics_callback(
             }
         }
 
+        decrement_outstanding_requests(instance);
+    } else if cookie.rows_sender.is_closed() {
+        // The result or its rows have been dropped, so stop streaming rows nobody reads
+        debug!("Cancelling analytics query whose rows are not consumed");
+        lcb_analytics_cancel(instance, cookie.handle);
+        unregister_streaming_request(instance, cookie_ptr);
         decrement_outstanding_requests(instance);
     } else {
         match cookie.rows_sender.unbounded_send(row.to_vec()) {
@@ -655,6 +1112,10 @@ pub unsafe extern "C" fn search_callback(
     }
 
     if lcb_respsearch_is_final(res) != 0 {
+        unregister_streaming_request(instance, cookie_ptr);
+        let mut http_res: *const lcb_RESPHTTP = ptr::null();
+        lcb_respsearch_http_response(res, &mut http_res);
+        http_metrics::record_body_sizes(ServiceType::Search, http_res);
         cookie.rows_sender.close_channel();
 
         if status == 0 {
@@ -667,6 +1128,12 @@ pub unsafe extern "C" fn search_callback(
             }
         }
 
+        decrement_outstanding_requests(instance);
+    } else if cookie.rows_sender.is_closed() {
+        // The result or its rows have been dropped, so stop streaming rows nobody reads
+        debug!("Cancelling search query whose rows are not consumed");
+        lcb_search_cancel(instance, cookie.handle);
+        unregister_streaming_request(instance, cookie_ptr);
         decrement_outstanding_requests(instance);
     } else {
         match cookie.rows_sender.unbounded_send(row.to_vec()) {
@@ -693,7 +1160,9 @@ pub fn couchbase_error_from_lcb_status(status: lcb_STATUS, ctx: ErrorContext) ->
         },
         lcb_STATUS_LCB_ERR_INVALID_ARGUMENT => CouchbaseError::InvalidArgument { ctx },
         lcb_STATUS_LCB_ERR_CAS_MISMATCH => CouchbaseError::CasMismatch { ctx },
-        lcb_STATUS_LCB_ERR_REQUEST_CANCELED => CouchbaseError::RequestCanceled { ctx },
+        lcb_STATUS_LCB_ERR_REQUEST_CANCELED | lcb_STATUS_LCB_ERR_CIRCUIT_BREAKER_OPEN => {
+            CouchbaseError::RequestCanceled { ctx }
+        }
         lcb_STATUS_LCB_ERR_SERVICE_NOT_AVAILABLE => CouchbaseError::ServiceNotAvailable { ctx },
         lcb_STATUS_LCB_ERR_INTERNAL_SERVER_FAILURE => CouchbaseError::InternalServerFailure { ctx },
         lcb_STATUS_LCB_ERR_AUTHENTICATION_FAILURE => CouchbaseError::AuthenticationFailure { ctx },
@@ -824,15 +1293,35 @@ pub unsafe extern "C" fn logger_callback(
     };
     let decoded = CStr::from_bytes_with_nul(&target_buffer[0..range_end]).unwrap();
 
+    #[cfg(feature = "packet-dump")]
+    {
+        if packets::log_packet(decoded.to_str().unwrap()) {
+            return;
+        }
+    }
+
     log::log!(level, "{}", decoded.to_str().unwrap());
 }
 
 pub unsafe extern "C" fn open_callback(instance: *mut lcb_INSTANCE, err: lcb_STATUS) {
     debug!(
         "Libcouchbase notified of completed bucket open attempt for bucket {:?} (status: 0x{:x})",
-        bucket_name_for_instance(instance),
+        MetaData(bucket_name_for_instance(instance)),
         &err
     );
+    if err != lcb_STATUS_LCB_SUCCESS {
+        events::emit(ClusterEvent::BootstrapFailed {
+            bucket: bucket_name_for_instance(instance),
+            reason: CStr::from_ptr(lcb_strerror_short(err))
+                .to_string_lossy()
+                .into_owned(),
+        });
+    }
+    set_open_status(instance, Some(err));
+}
+
+pub unsafe extern "C" fn bootstrap_callback(instance: *mut lcb_INSTANCE, err: lcb_STATUS) {
+    set_bootstrap_status(instance, err);
 }
 
 pub unsafe extern "C" fn http_callback(
@@ -861,13 +1350,56 @@ pub unsafe extern "C" fn http_callback(
                 lcb_resphttp_body(http_res, &mut body_ptr, &mut body_len);
                 let row = from_raw_parts(body_ptr as *const u8, body_len).to_vec();
                 let payload = if row.is_empty() { None } else { Some(row) };
-                s.send(Ok(GenericManagementResult::new(status, payload)))
-                    .unwrap();
+
+                let mut endpoint = None;
+                let mut lcb_ctx: *const lcb_HTTP_ERROR_CONTEXT = ptr::null();
+                lcb_resphttp_error_context(http_res, &mut lcb_ctx);
+                let mut endpoint_len: usize = 0;
+                let mut endpoint_ptr: *const c_char = ptr::null();
+                lcb_errctx_http_endpoint(lcb_ctx, &mut endpoint_ptr, &mut endpoint_len);
+                if !endpoint_ptr.is_null() && endpoint_len > 0 {
+                    endpoint = Some(decode_and_own_str(endpoint_ptr, endpoint_len));
+                }
+
+                let result = if status == 403 {
+                    Err(permission_denied(payload.as_deref(), endpoint))
+                } else {
+                    Ok(GenericManagementResult::new(status, payload)
+                        .with_endpoint(endpoint)
+                        .with_date(http_header(http_res, "date")))
+                };
+                // The caller may have dropped the future in the meantime
+                if s.send(result).is_err() {
+                    trace!("Failed to send management result because the request was dropped");
+                }
             }
         }
     }
 }
 
+/// The value of the header of the HTTP response, looked up case insensitively.
+unsafe fn http_header(res: *const lcb_RESPHTTP, name: &str) -> Option<String> {
+    let mut headers: *const *const c_char = ptr::null();
+    lcb_resphttp_headers(res, &mut headers);
+    if headers.is_null() {
+        return None;
+    }
+    // Names and values alternate, up to a null name
+    let mut at = 0;
+    while !(*headers.add(at)).is_null() {
+        let value = *headers.add(at + 1);
+        if !value.is_null()
+            && CStr::from_ptr(*headers.add(at))
+                .to_bytes()
+                .eq_ignore_ascii_case(name.as_bytes())
+        {
+            return Some(CStr::from_ptr(value).to_string_lossy().into_owned());
+        }
+        at += 2;
+    }
+    None
+}
+
 #[cfg(not(feature = "volatile"))]
 pub unsafe extern "C" fn stats_callback(
     _instance: *mut lcb_INSTANCE,
@@ -926,15 +1458,108 @@ pub unsafe extern "C" fn stats_callback(
     }
 }
 
-pub unsafe extern "C" fn ping_callback(
+/// Logs the connections which did not answer a keepalive or health probe, libcouchbase
+/// reconnects them, and passes the results on to the health probe.
+unsafe fn keepalive_callback(instance: *mut lcb_INSTANCE, ping_res: *const lcb_RESPPING) {
+    for i in 0..lcb_respping_result_size(ping_res) {
+        let mut svc = lcb_PING_SERVICE_LCB_PING_SERVICE__MAX;
+        lcb_respping_result_service(ping_res, i, &mut svc);
+        let service_type = match svc {
+            0 => ServiceType::KeyValue,
+            1 => ServiceType::Views,
+            2 => ServiceType::Query,
+            3 => ServiceType::Search,
+            4 => ServiceType::Analytics,
+            _ => continue,
+        };
+        let status = lcb_respping_result_status(ping_res, i);
+        let mut remote_len: usize = 0;
+        let mut remote_ptr: *const c_char = ptr::null();
+        lcb_respping_result_remote(ping_res, i, &mut remote_ptr, &mut remote_len);
+        let remote = match remote_ptr.is_null() {
+            true => None,
+            false => Some(decode_and_own_str(remote_ptr, remote_len)),
+        };
+        if status != lcb_STATUS_LCB_SUCCESS && service_type == ServiceType::KeyValue {
+            warn!(
+                "KV keepalive to {} failed with {}, reconnecting",
+                SystemData(remote.as_deref().unwrap_or("unknown")),
+                CStr::from_ptr(lcb_strerror_short(status)).to_str().unwrap()
+            );
+        }
+        if let Some(remote) = remote {
+            health::record(instance, service_type, remote, status);
+        }
+    }
+}
+
+#[cfg(not(feature = "volatile"))]
+pub unsafe extern "C" fn get_cid_callback(
+    _instance: *mut lcb_INSTANCE,
+    _cbtype: i32,
+    _res: *const lcb_RESPBASE,
+) {
+}
+
+#[cfg(feature = "volatile")]
+pub unsafe extern "C" fn get_cid_callback(
     instance: *mut lcb_INSTANCE,
     _cbtype: i32,
     res: *const lcb_RESPBASE,
 ) {
     decrement_outstanding_requests(instance);
+    let cid_res = res as *const lcb_RESPGETCID;
+    let mut cookie_ptr: *mut c_void = ptr::null_mut();
+    lcb_respgetcid_cookie(cid_res, &mut cookie_ptr);
+    let sender = Box::from_raw(
+        cookie_ptr
+            as *mut futures::channel::oneshot::Sender<
+                CouchbaseResult<crate::api::results::CollectionIdResult>,
+            >,
+    );
+
+    let status = lcb_respgetcid_status(cid_res);
+    let result = if status == lcb_STATUS_LCB_SUCCESS {
+        let mut collection_id: u32 = 0;
+        lcb_respgetcid_collection_id(cid_res, &mut collection_id);
+        let mut manifest_id: u64 = 0;
+        lcb_respgetcid_manifest_id(cid_res, &mut manifest_id);
+        Ok(crate::api::results::CollectionIdResult::new(
+            collection_id,
+            manifest_id,
+        ))
+    } else {
+        let mut ctx = ErrorContext::default();
+        let mut name_len: usize = 0;
+        let mut name_ptr: *const c_char = ptr::null();
+        lcb_respgetcid_scoped_collection(cid_res, &mut name_ptr, &mut name_len);
+        if !name_ptr.is_null() {
+            ctx.insert(
+                "collection",
+                Value::String(decode_and_own_str(name_ptr, name_len)),
+            );
+        }
+        Err(couchbase_error_from_lcb_status(status, ctx))
+    };
+    match sender.send(result) {
+        Ok(_) => {}
+        Err(e) => trace!("Failed to send collection id result because of {:?}", e),
+    }
+}
+
+pub unsafe extern "C" fn ping_callback(
+    instance: *mut lcb_INSTANCE,
+    _cbtype: i32,
+    res: *const lcb_RESPBASE,
+) {
     let ping_res = res as *const lcb_RESPPING;
     let mut cookie_ptr: *mut c_void = ptr::null_mut();
     lcb_respping_cookie(ping_res, &mut cookie_ptr);
+    if cookie_ptr.is_null() {
+        keepalive_callback(instance, ping_res);
+        return;
+    }
+    decrement_outstanding_requests(instance);
     let sender = Box::from_raw(
         cookie_ptr as *mut futures::channel::oneshot::Sender<CouchbaseResult<PingResult>>,
     );
@@ -1027,7 +1652,13 @@ pub unsafe extern "C" fn ping_callback(
             ))
         }
 
-        Ok(PingResult::new(String::from(""), services))
+        let mut id_len: usize = 0;
+        let mut id_ptr: *const c_char = ptr::null();
+        lcb_respping_report_id(ping_res, &mut id_ptr, &mut id_len);
+        Ok(PingResult::new(
+            strip_instance_id(decode_and_own_str(id_ptr, id_len)),
+            services,
+        ))
     } else {
         // let lcb_error = unsafe { CStr::from_ptr(lcb_strerror_long(status)) };
         // let error: String = lcb_error.to_str().unwrap().into();
@@ -1041,3 +1672,112 @@ pub unsafe extern "C" fn ping_callback(
         Err(e) => trace!("Failed to send exists result because of {:?}", e),
     }
 }
+
+/// Libcouchbase prefixes report ids with the address of the instance, which is dropped.
+fn strip_instance_id(id: String) -> String {
+    match id.find('/') {
+        Some(i) => id[i + 1..].into(),
+        None => id,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LcbDiagEndpoint {
+    id: String,
+    remote: Option<String>,
+    local: Option<String>,
+    namespace: Option<String>,
+    last_activity_us: Option<u64>,
+    status: Option<String>,
+    circuit_breaker: Option<String>,
+    connection_id: Option<String>,
+    features: Option<Vec<String>>,
+    sasl_mechanism: Option<String>,
+}
+
+pub unsafe extern "C" fn diag_callback(
+    instance: *mut lcb_INSTANCE,
+    _cbtype: i32,
+    res: *const lcb_RESPBASE,
+) {
+    let diag_res = res as *const lcb_RESPDIAG;
+    let mut cookie_ptr: *mut c_void = ptr::null_mut();
+    lcb_respdiag_cookie(diag_res, &mut cookie_ptr);
+    let sender = Box::from_raw(
+        cookie_ptr as *mut futures::channel::oneshot::Sender<CouchbaseResult<DiagnosticsResult>>,
+    );
+
+    let mut json_len: usize = 0;
+    let mut json_ptr: *const c_char = ptr::null();
+    lcb_respdiag_value(diag_res, &mut json_ptr, &mut json_len);
+    let json = from_raw_parts(json_ptr as *const u8, json_len);
+
+    let result = match serde_json::from_slice::<serde_json::Map<String, Value>>(json) {
+        Ok(mut report) => {
+            let id = match report.remove("id") {
+                Some(Value::String(id)) => strip_instance_id(id),
+                _ => String::new(),
+            };
+            let mut services: HashMap<ServiceType, Vec<EndpointDiagnostics>> = HashMap::new();
+            for (name, endpoints) in report {
+                // The config connections are the management connections of the bucket
+                let service_type = match name.as_str() {
+                    "kv" => ServiceType::KeyValue,
+                    "mgmt" | "config" => ServiceType::Management,
+                    "view" => ServiceType::Views,
+                    "n1ql" => ServiceType::Query,
+                    "fts" => ServiceType::Search,
+                    "cbas" => ServiceType::Analytics,
+                    _ => continue,
+                };
+                let endpoints: Vec<LcbDiagEndpoint> = match serde_json::from_value(endpoints) {
+                    Ok(e) => e,
+                    Err(e) => {
+                        debug!("Ignoring malformed {} diagnostics because of {}", name, e);
+                        continue;
+                    }
+                };
+                services
+                    .entry(service_type)
+                    .or_insert_with(Vec::new)
+                    .extend(endpoints.into_iter().map(|e| EndpointDiagnostics {
+                        typ: service_type,
+                        id: e.id,
+                        local: e.local,
+                        remote: e.remote,
+                        last_activity: e.last_activity_us.map(Duration::from_micros),
+                        state: match e.status.as_deref() {
+                            Some("connected") => EndpointState::Connected,
+                            Some("connecting") => EndpointState::Connecting,
+                            _ => EndpointState::Disconnected,
+                        },
+                        namespace: e.namespace,
+                        circuit_breaker: match e.circuit_breaker.as_deref() {
+                            Some("closed") => Some(CircuitBreakerState::Closed),
+                            Some("open") => Some(CircuitBreakerState::Open),
+                            Some("half_open") => Some(CircuitBreakerState::HalfOpen),
+                            _ => None,
+                        },
+                        connection_id: e.connection_id,
+                        features: e.features.unwrap_or_default(),
+                        sasl_mechanism: e.sasl_mechanism,
+                        degraded: false,
+                    }));
+            }
+            for endpoint in services.values_mut().flatten() {
+                if let Some(remote) = &endpoint.remote {
+                    endpoint.degraded = health::is_degraded(instance, endpoint.typ, remote);
+                }
+            }
+            Ok(DiagnosticsResult::new(id, services))
+        }
+        Err(e) => Err(CouchbaseError::DecodingFailure {
+            ctx: ErrorContext::default(),
+            source: e.into(),
+        }),
+    };
+    match sender.send(result) {
+        Ok(_) => {}
+        Err(e) => trace!("Failed to send diagnostics result because of {:?}", e),
+    }
+}
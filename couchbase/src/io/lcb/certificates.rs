@@ -0,0 +1,96 @@
+use crate::api::redaction::SystemData;
+use crate::api::security::{
+    CertificateChange, CertificateIdentity, SecurityEvent, SecurityEventListener,
+};
+use couchbase_sys::*;
+use log::warn;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::sync::Arc;
+
+thread_local! {
+    // Shared by all instances on the thread of an IoCore, so the certificates seen while
+    // bootstrapping one bucket are known to all others.
+    static STATE: RefCell<CertificateWatch> = RefCell::new(CertificateWatch::default());
+}
+
+#[derive(Default)]
+struct CertificateWatch {
+    listener: Option<Arc<dyn SecurityEventListener>>,
+    pinning: bool,
+    known: HashMap<String, CertificateIdentity>,
+}
+
+pub fn set_listener(listener: Arc<dyn SecurityEventListener>) {
+    STATE.with(|s| s.borrow_mut().listener = Some(listener));
+}
+
+pub fn set_pinning(pinning: bool) {
+    STATE.with(|s| s.borrow_mut().pinning = pinning);
+}
+
+/// Remembers the certificate of every node and reports when it changes across connections.
+///
+/// With pinning enabled the first certificate seen for a node is the only one accepted
+/// afterwards, any other fails the connection.
+pub unsafe extern "C" fn certificate_callback(
+    _instance: *mut lcb_INSTANCE,
+    info: *const lcb_CERTIFICATE_INFO,
+) -> c_int {
+    let info = &*info;
+    let remote = match (to_str(info.host), to_str(info.port)) {
+        (Some(h), Some(p)) => format!("{}:{}", h, p),
+        _ => return 0,
+    };
+    let current = CertificateIdentity {
+        subject: to_str(info.subject).unwrap_or_default().into(),
+        issuer: to_str(info.issuer).unwrap_or_default().into(),
+        spki_sha256: to_str(info.spki_sha256).unwrap_or_default().into(),
+    };
+
+    STATE.with(|s| {
+        let state = &mut *s.borrow_mut();
+        let previous = match state.known.get(&remote) {
+            None => {
+                state.known.insert(remote, current);
+                return 0;
+            }
+            // A renewal keeps the issuer and key, only other changes are of interest
+            Some(p) if p.issuer == current.issuer && p.spki_sha256 == current.spki_sha256 => {
+                return 0;
+            }
+            Some(p) => p.clone(),
+        };
+
+        let rejected = state.pinning;
+        warn!(
+            "Certificate of {} changed from {:?} to {:?}{}",
+            SystemData(&remote),
+            previous,
+            current,
+            if rejected { ", rejecting it" } else { "" }
+        );
+        if !rejected {
+            state.known.insert(remote.clone(), current.clone());
+        }
+        if let Some(listener) = &state.listener {
+            listener.on_event(&SecurityEvent::CertificateChanged(CertificateChange {
+                remote,
+                previous,
+                current,
+                rejected,
+            }));
+        }
+        rejected as c_int
+    })
+}
+
+unsafe fn to_str<'a>(value: *const c_char) -> Option<&'a str> {
+    if value.is_null() {
+        None
+    } else {
+        CStr::from_ptr(value).to_str().ok()
+    }
+}
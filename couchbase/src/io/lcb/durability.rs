@@ -0,0 +1,86 @@
+use crate::api::{MutationToken, PersistTo, ReplicateTo};
+use crate::io::lcb::instance::{decrement_outstanding_requests, increment_outstanding_requests};
+use couchbase_sys::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+thread_local! {
+    // The client verified durability of pending removes, counters and sub-document mutations,
+    // by the cookie of their operation
+    static PENDING: RefCell<HashMap<usize, (PersistTo, ReplicateTo)>> = RefCell::new(HashMap::new());
+}
+
+/// Called with the outcome of polling for the durability of a mutation.
+type Completion = Box<dyn FnOnce(lcb_STATUS)>;
+
+/// Makes the operation behind the cookie wait for its client verified durability.
+///
+/// Must be called after the operation has been scheduled, so it is not left behind if
+/// scheduling failed.
+pub fn register(cookie: *mut c_void, client_durability: Option<(PersistTo, ReplicateTo)>) {
+    if let Some(client_durability) = client_durability {
+        PENDING.with(|p| p.borrow_mut().insert(cookie as usize, client_durability));
+    }
+}
+
+/// Returns the client verified durability the operation behind the cookie waits for.
+pub fn take(cookie: *mut c_void) -> Option<(PersistTo, ReplicateTo)> {
+    PENDING.with(|p| {
+        let mut pending = p.borrow_mut();
+        if pending.is_empty() {
+            return None;
+        }
+        pending.remove(&(cookie as usize))
+    })
+}
+
+/// Polls the nodes until the completed mutation of the key is persisted and replicated as
+/// requested, then calls `complete` with the outcome.
+pub unsafe fn endure(
+    instance: *mut lcb_INSTANCE,
+    (persist_to, replicate_to): (PersistTo, ReplicateTo),
+    key: &[u8],
+    cas: u64,
+    token: Option<&MutationToken>,
+    removed: bool,
+    complete: Completion,
+) {
+    let token = token.map(|t| lcb_MUTATION_TOKEN {
+        uuid_: t.partition_uuid(),
+        seqno_: t.sequence_number(),
+        vbid_: t.partition_id(),
+    });
+    let cookie = Box::into_raw(Box::new(complete));
+    let status = lcb_endure_mutation(
+        instance,
+        cookie as *mut c_void,
+        key.as_ptr() as *const c_char,
+        key.len(),
+        cas,
+        token
+            .as_ref()
+            .map_or(ptr::null(), |t| t as *const lcb_MUTATION_TOKEN),
+        persist_to.nodes(),
+        replicate_to.nodes(),
+        removed as c_int,
+    );
+    if status == lcb_STATUS_LCB_SUCCESS {
+        increment_outstanding_requests(instance);
+    } else {
+        Box::from_raw(cookie)(status);
+    }
+}
+
+pub unsafe extern "C" fn endure_callback(
+    instance: *mut lcb_INSTANCE,
+    _cbtype: i32,
+    res: *const lcb_RESPBASE,
+) {
+    decrement_outstanding_requests(instance);
+    let mut cookie_ptr: *mut c_void = ptr::null_mut();
+    lcb_respendure_cookie(res, &mut cookie_ptr);
+    let complete = Box::from_raw(cookie_ptr as *mut Completion);
+    complete(lcb_respendure_status(res));
+}
@@ -1,16 +1,31 @@
-use crate::api::{LookupInSpec, MutateInSpec};
+#[cfg(feature = "volatile")]
+use crate::api::results::PartitionMap;
+use crate::api::results::RowBuffer;
+use crate::api::{DurabilityLevel, PersistTo, ReplicateTo};
+use crate::api::{LookupInOperation, LookupInSpec, MutateInSpec};
 use crate::io::lcb::callbacks::{analytics_callback, query_callback, search_callback};
-use crate::io::lcb::{AnalyticsCookie, HttpCookie, QueryCookie, SearchCookie};
+use crate::io::lcb::durability;
+use crate::io::lcb::instance::{
+    increment_outstanding_requests, register_streaming_request, StreamingRequest,
+};
+use crate::io::lcb::retry;
+use crate::io::lcb::{AnalyticsCookie, HttpCookie, LookupInCookie, QueryCookie, SearchCookie};
 use crate::io::request::*;
-use crate::{api::options::StoreSemantics, CouchbaseResult, ErrorContext};
+use crate::{
+    api::options::StoreSemantics, CouchbaseError, CouchbaseResult, ErrorContext, MutationResult,
+    ServiceType,
+};
+use futures::channel::mpsc::UnboundedSender;
 use futures::channel::oneshot::Sender;
 use log::{debug, warn};
 use serde_json::Value;
 
 use couchbase_sys::*;
 use std::ffi::{CStr, CString};
-use std::os::raw::c_void;
+use std::os::raw::{c_char, c_int, c_void};
 use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
 use super::callbacks::couchbase_error_from_lcb_status;
@@ -52,6 +67,58 @@ fn verify<T>(
     Ok(())
 }
 
+/// Verifies the libcouchbase return status code and fails the original replica request.
+///
+/// Dropping the sender afterwards closes the stream of replica results.
+fn verify_replica(
+    status: lcb_STATUS,
+    sender: *mut UnboundedSender<CouchbaseResult<GetReplicaResult>>,
+) -> Result<(), EncodeFailure> {
+    if status != lcb_STATUS_LCB_SUCCESS {
+        if sender.is_null() {
+            warn!("Failed to notify request of encode failure because the pointer is null. This is a bug!");
+            return Ok(());
+        }
+        let sender = unsafe { Box::from_raw(sender) };
+        let mut ctx = ErrorContext::default();
+        if let Ok(msg) = unsafe { CStr::from_ptr(lcb_strerror_short(status)) }.to_str() {
+            ctx.insert("msg", Value::String(msg.to_string()));
+        }
+        let err = couchbase_error_from_lcb_status(status, ctx);
+        if let Err(_) = sender.unbounded_send(Err(err)) {
+            debug!("Failed to notify request of encode failure, because the listener has been already dropped.");
+        }
+        return Err(EncodeFailure(status));
+    }
+    Ok(())
+}
+
+/// Verifies the libcouchbase return status code and fails the lookup, either the one against
+/// the active node or the one against a single replica.
+fn verify_lookup_in(status: lcb_STATUS, cookie: *mut LookupInCookie) -> Result<(), EncodeFailure> {
+    if status != lcb_STATUS_LCB_SUCCESS {
+        if cookie.is_null() {
+            warn!("Failed to notify request of encode failure because the pointer is null. This is a bug!");
+            return Ok(());
+        }
+        let cookie = unsafe { Box::from_raw(cookie) };
+        let mut ctx = ErrorContext::default();
+        if let Ok(msg) = unsafe { CStr::from_ptr(lcb_strerror_short(status)) }.to_str() {
+            ctx.insert("msg", Value::String(msg.to_string()));
+        }
+        let err = couchbase_error_from_lcb_status(status, ctx);
+        let sent = match *cookie {
+            LookupInCookie::Active { sender } => sender.send(Err(err)).is_ok(),
+            LookupInCookie::Replica { sender } => sender.unbounded_send(Err(err)).is_ok(),
+        };
+        if !sent {
+            debug!("Failed to notify request of encode failure, because the listener has been already dropped.");
+        }
+        return Err(EncodeFailure(status));
+    }
+    Ok(())
+}
+
 fn verify_query(status: lcb_STATUS, sender: *mut QueryCookie) -> Result<(), EncodeFailure> {
     if status != lcb_STATUS_LCB_SUCCESS {
         if sender.is_null() {
@@ -184,17 +251,31 @@ pub fn encode_get(instance: *mut lcb_INSTANCE, request: GetRequest) -> Result<()
     unsafe {
         verify(lcb_cmdget_create(&mut command), cookie)?;
         verify(lcb_cmdget_key(command, id.as_ptr(), id_len), cookie)?;
-        verify(
-            lcb_cmdget_collection(
-                command,
-                scope.as_ptr(),
-                scope_len,
-                collection.as_ptr(),
-                collection_len,
-            ),
-            cookie,
-        )?;
+        match request.collection_id {
+            Some(cid) => verify(lcb_cmdget_collection_id(command, cid), cookie)?,
+            None => verify(
+                lcb_cmdget_collection(
+                    command,
+                    scope.as_ptr(),
+                    scope_len,
+                    collection.as_ptr(),
+                    collection_len,
+                ),
+                cookie,
+            )?,
+        }
 
+        let (retry_strategy, max_retries) = match &request.ty {
+            GetRequestType::Get { options } => {
+                (options.retry_strategy.clone(), options.max_retries)
+            }
+            GetRequestType::GetAndLock { options, .. } => {
+                (options.retry_strategy.clone(), options.max_retries)
+            }
+            GetRequestType::GetAndTouch { options, .. } => {
+                (options.retry_strategy.clone(), options.max_retries)
+            }
+        };
         match request.ty {
             GetRequestType::Get { options } => {
                 if let Some(timeout) = options.timeout {
@@ -206,7 +287,7 @@ pub fn encode_get(instance: *mut lcb_INSTANCE, request: GetRequest) -> Result<()
             }
             GetRequestType::GetAndLock { lock_time, options } => {
                 verify(
-                    lcb_cmdget_locktime(command, lock_time.as_micros() as u32),
+                    lcb_cmdget_locktime(command, lock_time.as_secs() as u32),
                     cookie,
                 )?;
 
@@ -218,10 +299,7 @@ pub fn encode_get(instance: *mut lcb_INSTANCE, request: GetRequest) -> Result<()
                 }
             }
             GetRequestType::GetAndTouch { expiry, options } => {
-                verify(
-                    lcb_cmdget_expiry(command, expiry.as_micros() as u32),
-                    cookie,
-                )?;
+                verify(lcb_cmdget_expiry(command, expiry.to_server()), cookie)?;
 
                 if let Some(timeout) = options.timeout {
                     verify(
@@ -233,11 +311,58 @@ pub fn encode_get(instance: *mut lcb_INSTANCE, request: GetRequest) -> Result<()
         };
 
         verify(lcb_get(instance, cookie as *mut c_void, command), cookie)?;
+        retry::register(cookie as *mut c_void, retry_strategy, max_retries);
         verify(lcb_cmdget_destroy(command), cookie)?;
     }
     Ok(())
 }
 
+/// Encodes a `GetReplicaRequest` into its libcouchbase `lcb_CMDGETREPLICA` representation.
+pub fn encode_get_replica(
+    instance: *mut lcb_INSTANCE,
+    request: GetReplicaRequest,
+) -> Result<(), EncodeFailure> {
+    let (id_len, id) = into_cstring(request.id);
+    let cookie = Box::into_raw(Box::new(request.sender));
+    let (scope_len, scope) = into_cstring(request.scope);
+    let (collection_len, collection) = into_cstring(request.collection);
+
+    let mode = match request.mode {
+        ReplicaMode::Any => lcb_REPLICA_MODE_LCB_REPLICA_MODE_ANY,
+        ReplicaMode::All => lcb_REPLICA_MODE_LCB_REPLICA_MODE_ALL,
+    };
+
+    let mut command: *mut lcb_CMDGETREPLICA = ptr::null_mut();
+    unsafe {
+        verify_replica(lcb_cmdgetreplica_create(&mut command, mode), cookie)?;
+        verify_replica(lcb_cmdgetreplica_key(command, id.as_ptr(), id_len), cookie)?;
+        verify_replica(
+            lcb_cmdgetreplica_collection(
+                command,
+                scope.as_ptr(),
+                scope_len,
+                collection.as_ptr(),
+                collection_len,
+            ),
+            cookie,
+        )?;
+
+        if let Some(timeout) = request.timeout {
+            verify_replica(
+                lcb_cmdgetreplica_timeout(command, timeout.as_micros() as u32),
+                cookie,
+            )?;
+        }
+
+        verify_replica(
+            lcb_getreplica(instance, cookie as *mut c_void, command),
+            cookie,
+        )?;
+        verify_replica(lcb_cmdgetreplica_destroy(command), cookie)?;
+    }
+    Ok(())
+}
+
 /// Encodes a `ExistsRequest` into its libcouchbase `lcb_CMDEXISTS` representation.
 pub fn encode_exists(
     instance: *mut lcb_INSTANCE,
@@ -271,12 +396,100 @@ pub fn encode_exists(
         }
 
         verify(lcb_exists(instance, cookie as *mut c_void, command), cookie)?;
+        retry::register(
+            cookie as *mut c_void,
+            request.options.retry_strategy,
+            request.options.max_retries,
+        );
         verify(lcb_cmdexists_destroy(command), cookie)?;
     }
 
     Ok(())
 }
 
+/// Encodes a `UnlockRequest` into its libcouchbase `lcb_CMDUNLOCK` representation.
+pub fn encode_unlock(
+    instance: *mut lcb_INSTANCE,
+    request: UnlockRequest,
+) -> Result<(), EncodeFailure> {
+    let (id_len, id) = into_cstring(request.id);
+    let cookie = Box::into_raw(Box::new(request.sender));
+    let (scope_len, scope) = into_cstring(request.scope);
+    let (collection_len, collection) = into_cstring(request.collection);
+
+    let mut command: *mut lcb_CMDUNLOCK = ptr::null_mut();
+    unsafe {
+        verify(lcb_cmdunlock_create(&mut command), cookie)?;
+        verify(lcb_cmdunlock_key(command, id.as_ptr(), id_len), cookie)?;
+        verify(
+            lcb_cmdunlock_collection(
+                command,
+                scope.as_ptr(),
+                scope_len,
+                collection.as_ptr(),
+                collection_len,
+            ),
+            cookie,
+        )?;
+        verify(lcb_cmdunlock_cas(command, request.cas), cookie)?;
+
+        if let Some(timeout) = request.options.timeout {
+            verify(
+                lcb_cmdunlock_timeout(command, timeout.as_micros() as u32),
+                cookie,
+            )?;
+        }
+
+        verify(lcb_unlock(instance, cookie as *mut c_void, command), cookie)?;
+        retry::register(
+            cookie as *mut c_void,
+            request.options.retry_strategy,
+            request.options.max_retries,
+        );
+        verify(lcb_cmdunlock_destroy(command), cookie)?;
+    }
+
+    Ok(())
+}
+
+/// Maps the durability level onto its libcouchbase representation.
+fn encode_durability_level(level: DurabilityLevel) -> lcb_DURABILITY_LEVEL {
+    match level {
+        DurabilityLevel::None => lcb_DURABILITY_LEVEL_LCB_DURABILITYLEVEL_NONE,
+        DurabilityLevel::Majority => lcb_DURABILITY_LEVEL_LCB_DURABILITYLEVEL_MAJORITY,
+        DurabilityLevel::MajorityAndPersistOnMaster => {
+            lcb_DURABILITY_LEVEL_LCB_DURABILITYLEVEL_MAJORITY_AND_PERSIST_TO_ACTIVE
+        }
+        DurabilityLevel::PersistToMajority => {
+            lcb_DURABILITY_LEVEL_LCB_DURABILITYLEVEL_PERSIST_TO_MAJORITY
+        }
+    }
+}
+
+/// Applies the enhanced or the client verified (observe based) durability to a store command.
+///
+/// Libcouchbase takes care of polling the nodes when client verified durability is used.
+unsafe fn encode_store_durability(
+    command: *mut lcb_CMDSTORE,
+    durability_level: Option<DurabilityLevel>,
+    client_durability: Option<(PersistTo, ReplicateTo)>,
+    cookie: *mut Sender<CouchbaseResult<MutationResult>>,
+) -> Result<(), EncodeFailure> {
+    if let Some(level) = durability_level {
+        verify(
+            lcb_cmdstore_durability(command, encode_durability_level(level)),
+            cookie,
+        )?;
+    }
+    if let Some((persist_to, replicate_to)) = client_durability {
+        verify(
+            lcb_cmdstore_durability_observe(command, persist_to.nodes(), replicate_to.nodes()),
+            cookie,
+        )?;
+    }
+    Ok(())
+}
+
 /// Encodes a `MutateRequest` into its libcouchbase `lcb_CMDSTORE` representation.
 ///
 /// This method covers insert, upsert and replace since they are very similar and
@@ -286,13 +499,31 @@ pub fn encode_mutate(
     request: MutateRequest,
 ) -> Result<(), EncodeFailure> {
     let (id_len, id) = into_cstring(request.id);
-    let (value_len, value) = into_cstring(request.content);
+    // Not a CString: the content may be binary and libcouchbase copies exactly the given length
+    let value = request.content;
     let cookie = Box::into_raw(Box::new(request.sender));
     let (scope_len, scope) = into_cstring(request.scope);
     let (collection_len, collection) = into_cstring(request.collection);
 
     let mut command: *mut lcb_CMDSTORE = ptr::null_mut();
     unsafe {
+        let (retry_strategy, max_retries) = match &request.ty {
+            MutateRequestType::Upsert { options } => {
+                (options.retry_strategy.clone(), options.max_retries)
+            }
+            MutateRequestType::Insert { options } => {
+                (options.retry_strategy.clone(), options.max_retries)
+            }
+            MutateRequestType::Replace { options } => {
+                (options.retry_strategy.clone(), options.max_retries)
+            }
+            MutateRequestType::Append { options } => {
+                (options.retry_strategy.clone(), options.max_retries)
+            }
+            MutateRequestType::Prepend { options } => {
+                (options.retry_strategy.clone(), options.max_retries)
+            }
+        };
         match request.ty {
             MutateRequestType::Upsert { options } => {
                 verify(
@@ -305,11 +536,17 @@ pub fn encode_mutate(
                         cookie,
                     )?;
                 }
+                encode_store_durability(
+                    command,
+                    options.durability_level,
+                    options.client_durability,
+                    cookie,
+                )?;
                 if let Some(expiry) = options.expiry {
-                    verify(
-                        lcb_cmdstore_expiry(command, expiry.as_secs() as u32),
-                        cookie,
-                    )?;
+                    verify(lcb_cmdstore_expiry(command, expiry.to_server()), cookie)?;
+                }
+                if options.preserve_expiry {
+                    verify(lcb_cmdstore_preserve_expiry(command, 1), cookie)?;
                 }
             }
             MutateRequestType::Insert { options } => {
@@ -323,11 +560,14 @@ pub fn encode_mutate(
                         cookie,
                     )?;
                 }
+                encode_store_durability(
+                    command,
+                    options.durability_level,
+                    options.client_durability,
+                    cookie,
+                )?;
                 if let Some(expiry) = options.expiry {
-                    verify(
-                        lcb_cmdstore_expiry(command, expiry.as_secs() as u32),
-                        cookie,
-                    )?;
+                    verify(lcb_cmdstore_expiry(command, expiry.to_server()), cookie)?;
                 }
             }
             MutateRequestType::Replace { options } => {
@@ -344,11 +584,17 @@ pub fn encode_mutate(
                         cookie,
                     )?;
                 }
+                encode_store_durability(
+                    command,
+                    options.durability_level,
+                    options.client_durability,
+                    cookie,
+                )?;
                 if let Some(expiry) = options.expiry {
-                    verify(
-                        lcb_cmdstore_expiry(command, expiry.as_secs() as u32),
-                        cookie,
-                    )?;
+                    verify(lcb_cmdstore_expiry(command, expiry.to_server()), cookie)?;
+                }
+                if options.preserve_expiry {
+                    verify(lcb_cmdstore_preserve_expiry(command, 1), cookie)?;
                 }
             }
             MutateRequestType::Append { options } => {
@@ -365,6 +611,12 @@ pub fn encode_mutate(
                         cookie,
                     )?;
                 }
+                encode_store_durability(
+                    command,
+                    options.durability_level,
+                    options.client_durability,
+                    cookie,
+                )?;
             }
             MutateRequestType::Prepend { options } => {
                 verify(
@@ -380,15 +632,60 @@ pub fn encode_mutate(
                         cookie,
                     )?;
                 }
+                encode_store_durability(
+                    command,
+                    options.durability_level,
+                    options.client_durability,
+                    cookie,
+                )?;
             }
         }
+        if let Some(flags) = request.flags {
+            verify(lcb_cmdstore_flags(command, flags), cookie)?;
+        }
         verify(lcb_cmdstore_key(command, id.as_ptr(), id_len), cookie)?;
         verify(
-            lcb_cmdstore_value(command, value.as_ptr(), value_len),
+            lcb_cmdstore_value(command, value.as_ptr() as *const c_char, value.len()),
             cookie,
         )?;
+        match request.collection_id {
+            Some(cid) => verify(lcb_cmdstore_collection_id(command, cid), cookie)?,
+            None => verify(
+                lcb_cmdstore_collection(
+                    command,
+                    scope.as_ptr(),
+                    scope_len,
+                    collection.as_ptr(),
+                    collection_len,
+                ),
+                cookie,
+            )?,
+        }
+
+        verify(lcb_store(instance, cookie as *mut c_void, command), cookie)?;
+        retry::register(cookie as *mut c_void, retry_strategy, max_retries);
+        verify(lcb_cmdstore_destroy(command), cookie)?;
+    }
+
+    Ok(())
+}
+
+/// Encodes a `TouchRequest` into its libcouchbase `lcb_CMDTOUCH` representation.
+pub fn encode_touch(
+    instance: *mut lcb_INSTANCE,
+    request: TouchRequest,
+) -> Result<(), EncodeFailure> {
+    let (id_len, id) = into_cstring(request.id);
+    let cookie = Box::into_raw(Box::new(request.sender));
+    let (scope_len, scope) = into_cstring(request.scope);
+    let (collection_len, collection) = into_cstring(request.collection);
+
+    let mut command: *mut lcb_CMDTOUCH = ptr::null_mut();
+    unsafe {
+        verify(lcb_cmdtouch_create(&mut command), cookie)?;
+        verify(lcb_cmdtouch_key(command, id.as_ptr(), id_len), cookie)?;
         verify(
-            lcb_cmdstore_collection(
+            lcb_cmdtouch_collection(
                 command,
                 scope.as_ptr(),
                 scope_len,
@@ -397,9 +694,25 @@ pub fn encode_mutate(
             ),
             cookie,
         )?;
+        verify(
+            lcb_cmdtouch_expiry(command, request.expiry.to_server()),
+            cookie,
+        )?;
 
-        verify(lcb_store(instance, cookie as *mut c_void, command), cookie)?;
-        verify(lcb_cmdstore_destroy(command), cookie)?;
+        if let Some(timeout) = request.options.timeout {
+            verify(
+                lcb_cmdtouch_timeout(command, timeout.as_micros() as u32),
+                cookie,
+            )?;
+        }
+
+        verify(lcb_touch(instance, cookie as *mut c_void, command), cookie)?;
+        retry::register(
+            cookie as *mut c_void,
+            request.options.retry_strategy,
+            request.options.max_retries,
+        );
+        verify(lcb_cmdtouch_destroy(command), cookie)?;
     }
 
     Ok(())
@@ -419,16 +732,19 @@ pub fn encode_remove(
     unsafe {
         verify(lcb_cmdremove_create(&mut command), cookie)?;
         verify(lcb_cmdremove_key(command, id.as_ptr(), id_len), cookie)?;
-        verify(
-            lcb_cmdremove_collection(
-                command,
-                scope.as_ptr(),
-                scope_len,
-                collection.as_ptr(),
-                collection_len,
-            ),
-            cookie,
-        )?;
+        match request.collection_id {
+            Some(cid) => verify(lcb_cmdremove_collection_id(command, cid), cookie)?,
+            None => verify(
+                lcb_cmdremove_collection(
+                    command,
+                    scope.as_ptr(),
+                    scope_len,
+                    collection.as_ptr(),
+                    collection_len,
+                ),
+                cookie,
+            )?,
+        }
 
         if let Some(cas) = request.options.cas {
             verify(lcb_cmdremove_cas(command, cas), cookie)?;
@@ -439,8 +755,20 @@ pub fn encode_remove(
                 cookie,
             )?;
         }
+        if let Some(level) = request.options.durability_level {
+            verify(
+                lcb_cmdremove_durability(command, encode_durability_level(level)),
+                cookie,
+            )?;
+        }
 
         verify(lcb_remove(instance, cookie as *mut c_void, command), cookie)?;
+        retry::register(
+            cookie as *mut c_void,
+            request.options.retry_strategy,
+            request.options.max_retries,
+        );
+        durability::register(cookie as *mut c_void, request.options.client_durability);
         verify(lcb_cmdremove_destroy(command), cookie)?;
     }
 
@@ -484,18 +812,30 @@ pub fn encode_counter(
                 cookie,
             )?;
         }
-        if let Some(expiry) = request.options.expiry {
+        if let Some(level) = request.options.durability_level {
             verify(
-                lcb_cmdcounter_expiry(command, expiry.as_secs() as u32),
+                lcb_cmdcounter_durability(command, encode_durability_level(level)),
                 cookie,
             )?;
         }
+        if let Some(expiry) = request.options.expiry {
+            verify(lcb_cmdcounter_expiry(command, expiry.to_server()), cookie)?;
+        }
 
         verify(lcb_cmdcounter_delta(command, request.options.delta), cookie)?;
+        if let Some(initial) = request.options.initial {
+            verify(lcb_cmdcounter_initial(command, initial), cookie)?;
+        }
         verify(
             lcb_counter(instance, cookie as *mut c_void, command),
             cookie,
         )?;
+        retry::register(
+            cookie as *mut c_void,
+            request.options.retry_strategy,
+            request.options.max_retries,
+        );
+        durability::register(cookie as *mut c_void, request.options.client_durability);
         verify(lcb_cmdcounter_destroy(command), cookie)?;
     }
 
@@ -518,9 +858,12 @@ pub fn encode_query(
         meta_receiver: Some(meta_receiver),
         rows_sender,
         rows_receiver: Some(rows_receiver),
+        buffer: Arc::new(RowBuffer::new(request.options.max_buffered_bytes)),
+        handle: ptr::null_mut(),
     }));
 
     let mut command: *mut lcb_CMDQUERY = ptr::null_mut();
+    let mut handle: *mut lcb_QUERY_HANDLE = ptr::null_mut();
     unsafe {
         verify_query(lcb_cmdquery_create(&mut command), cookie)?;
         verify_query(
@@ -532,6 +875,10 @@ pub fn encode_query(
             verify_query(lcb_cmdquery_adhoc(command, a.into()), cookie)?;
         }
 
+        if request.options.prepare_only {
+            verify_query(lcb_cmdquery_prepare_only(command, 1), cookie)?;
+        }
+
         if let Some(s) = request.scope {
             let (scope_len, scope) = into_cstring(s);
             verify_query(
@@ -541,9 +888,17 @@ pub fn encode_query(
         }
 
         verify_query(lcb_cmdquery_callback(command, Some(query_callback)), cookie)?;
+        verify_query(lcb_cmdquery_handle(command, &mut handle), cookie)?;
         verify_query(lcb_query(instance, cookie as *mut c_void, command), cookie)?;
+        // The handle is only filled in once the query is scheduled, callbacks run afterwards
+        (*cookie).handle = handle;
         verify_query(lcb_cmdquery_destroy(command), cookie)?;
     }
+    register_streaming_request(
+        instance,
+        cookie as *mut c_void,
+        StreamingRequest::Query(handle),
+    );
 
     Ok(())
 }
@@ -564,9 +919,11 @@ pub fn encode_analytics(
         meta_receiver: Some(meta_receiver),
         rows_sender,
         rows_receiver: Some(rows_receiver),
+        handle: ptr::null_mut(),
     }));
 
     let mut command: *mut lcb_CMDANALYTICS = ptr::null_mut();
+    let mut handle: *mut lcb_ANALYTICS_HANDLE = ptr::null_mut();
     unsafe {
         verify_analytics(lcb_cmdanalytics_create(&mut command), cookie)?;
         verify_analytics(
@@ -584,12 +941,22 @@ pub fn encode_analytics(
                 cookie,
             )?;
         }
+        if let Some(p) = request.options.priority {
+            verify_analytics(lcb_cmdanalytics_priority(command, p), cookie)?;
+        }
+        verify_analytics(lcb_cmdanalytics_handle(command, &mut handle), cookie)?;
         verify_analytics(
             lcb_analytics(instance, cookie as *mut c_void, command),
             cookie,
         )?;
+        (*cookie).handle = handle;
         verify_analytics(lcb_cmdanalytics_destroy(command), cookie)?;
     }
+    register_streaming_request(
+        instance,
+        cookie as *mut c_void,
+        StreamingRequest::Analytics(handle),
+    );
 
     Ok(())
 }
@@ -599,6 +966,7 @@ pub fn encode_search(
     instance: *mut lcb_INSTANCE,
     mut request: SearchRequest,
 ) -> Result<(), EncodeFailure> {
+    request.options.consistency_ctl(&request.index);
     request.options.index = Some(request.index);
     request.options.query = Some(request.query);
 
@@ -612,9 +980,11 @@ pub fn encode_search(
         meta_receiver: Some(meta_receiver),
         rows_sender,
         rows_receiver: Some(rows_receiver),
+        handle: ptr::null_mut(),
     }));
 
     let mut command: *mut lcb_CMDSEARCH = ptr::null_mut();
+    let mut handle: *mut lcb_SEARCH_HANDLE = ptr::null_mut();
     unsafe {
         verify_search(lcb_cmdsearch_create(&mut command), cookie)?;
         verify_search(
@@ -625,17 +995,45 @@ pub fn encode_search(
             lcb_cmdsearch_callback(command, Some(search_callback)),
             cookie,
         )?;
+        verify_search(lcb_cmdsearch_handle(command, &mut handle), cookie)?;
         verify_search(lcb_search(instance, cookie as *mut c_void, command), cookie)?;
+        (*cookie).handle = handle;
         verify_search(lcb_cmdsearch_destroy(command), cookie)?;
     }
+    register_streaming_request(
+        instance,
+        cookie as *mut c_void,
+        StreamingRequest::Search(handle),
+    );
 
     Ok(())
 }
 
 enum EncodedLookupSpec {
-    Get { path_len: usize, path: CString },
-    Exists { path_len: usize, path: CString },
-    Count { path_len: usize, path: CString },
+    Get {
+        path_len: usize,
+        path: CString,
+        flags: u32,
+    },
+    Exists {
+        path_len: usize,
+        path: CString,
+        flags: u32,
+    },
+    Count {
+        path_len: usize,
+        path: CString,
+        flags: u32,
+    },
+}
+
+/// Returns the subdoc spec flags for a path which may refer to an extended attribute.
+fn lookup_flags(xattr: bool) -> u32 {
+    if xattr {
+        LCB_SUBDOCSPECS_F_XATTRPATH
+    } else {
+        0
+    }
 }
 
 /// Encodes a `LookupInRequest` into its libcouchbase `lcb_CMDSUBDOC` representation.
@@ -643,26 +1041,133 @@ pub fn encode_lookup_in(
     instance: *mut lcb_INSTANCE,
     request: LookupInRequest,
 ) -> Result<(), EncodeFailure> {
-    let (id_len, id) = into_cstring(request.id);
-    let cookie = Box::into_raw(Box::new(request.sender));
-    let (scope_len, scope) = into_cstring(request.scope);
-    let (collection_len, collection) = into_cstring(request.collection);
+    let cookie = Box::into_raw(Box::new(LookupInCookie::Active {
+        sender: request.sender,
+    }));
+    let options = request.options;
+
+    with_lookup_in_command(
+        request.id,
+        request.scope,
+        request.collection,
+        request.specs,
+        options.timeout,
+        cookie,
+        |command| unsafe {
+            if let Some(access_deleted) = options.access_deleted {
+                verify_lookup_in(
+                    lcb_cmdsubdoc_access_deleted(command, if access_deleted { 1 } else { 0 }),
+                    cookie,
+                )?;
+            }
+            verify_lookup_in(lcb_subdoc(instance, cookie as *mut c_void, command), cookie)?;
+            retry::register(
+                cookie as *mut c_void,
+                options.retry_strategy,
+                options.max_retries,
+            );
+            Ok(())
+        },
+    )
+}
 
-    let lookup_specs = request
-        .specs
-        .into_iter()
-        .map(|spec| match spec {
-            LookupInSpec::Get { path } => {
-                let (path_len, path) = into_cstring(path);
-                EncodedLookupSpec::Get { path_len, path }
+/// Encodes a `LookupInReplicaRequest` into one `lcb_CMDSUBDOC` per replica, each carrying the
+/// replica read document flag.
+///
+/// Every lookup streams its result on its own, the stream ends once all of them answered. A
+/// replica which cannot be reached is reported on the stream as well, so the other replicas
+/// are still read.
+pub fn encode_lookup_in_replica(
+    instance: *mut lcb_INSTANCE,
+    request: LookupInReplicaRequest,
+) -> Result<(), EncodeFailure> {
+    let sender = request.sender;
+    let cookie = Box::into_raw(Box::new(LookupInCookie::Replica {
+        sender: sender.clone(),
+    }));
+    let replicas = unsafe { lcb_get_num_replicas(instance) };
+    if replicas < 1 {
+        verify_lookup_in(lcb_STATUS_LCB_ERR_NO_MATCHING_SERVER, cookie)?;
+    }
+
+    with_lookup_in_command(
+        request.id,
+        request.scope,
+        request.collection,
+        request.specs,
+        request.timeout,
+        cookie,
+        |command| unsafe {
+            let mut scheduled = 0;
+            for index in 0..replicas {
+                let cookie = if index == 0 {
+                    cookie
+                } else {
+                    Box::into_raw(Box::new(LookupInCookie::Replica {
+                        sender: sender.clone(),
+                    }))
+                };
+                let mut status = lcb_cmdsubdoc_replica_index(command, index);
+                if status == lcb_STATUS_LCB_SUCCESS {
+                    status = lcb_subdoc(instance, cookie as *mut c_void, command);
+                }
+                if verify_lookup_in(status, cookie).is_ok() {
+                    scheduled += 1;
+                }
             }
-            LookupInSpec::Exists { path } => {
-                let (path_len, path) = into_cstring(path);
-                EncodedLookupSpec::Exists { path_len, path }
+            match scheduled {
+                0 => Err(EncodeFailure(lcb_STATUS_LCB_ERR_NO_MATCHING_SERVER)),
+                // The request itself is counted once it has been encoded
+                n => {
+                    for _ in 1..n {
+                        increment_outstanding_requests(instance);
+                    }
+                    Ok(())
+                }
             }
-            LookupInSpec::Count { path } => {
-                let (path_len, path) = into_cstring(path);
-                EncodedLookupSpec::Count { path_len, path }
+        },
+    )
+}
+
+/// Builds the `lcb_CMDSUBDOC` of a lookup and hands it to `schedule`, failing the cookie if
+/// libcouchbase rejects any part of it.
+fn with_lookup_in_command<F>(
+    id: String,
+    scope: String,
+    collection: String,
+    specs: Vec<LookupInSpec>,
+    timeout: Option<Duration>,
+    cookie: *mut LookupInCookie,
+    schedule: F,
+) -> Result<(), EncodeFailure>
+where
+    F: FnOnce(*mut lcb_CMDSUBDOC) -> Result<(), EncodeFailure>,
+{
+    let (id_len, id) = into_cstring(id);
+    let (scope_len, scope) = into_cstring(scope);
+    let (collection_len, collection) = into_cstring(collection);
+
+    let lookup_specs = specs
+        .into_iter()
+        .map(|spec| {
+            let flags = lookup_flags(spec.xattr);
+            let (path_len, path) = into_cstring(spec.path);
+            match spec.operation {
+                LookupInOperation::Get => EncodedLookupSpec::Get {
+                    path_len,
+                    path,
+                    flags,
+                },
+                LookupInOperation::Exists => EncodedLookupSpec::Exists {
+                    path_len,
+                    path,
+                    flags,
+                },
+                LookupInOperation::Count => EncodedLookupSpec::Count {
+                    path_len,
+                    path,
+                    flags,
+                },
             }
         })
         .collect::<Vec<_>>();
@@ -670,7 +1175,7 @@ pub fn encode_lookup_in(
     let mut command: *mut lcb_CMDSUBDOC = ptr::null_mut();
     let mut specs: *mut lcb_SUBDOCSPECS = ptr::null_mut();
     unsafe {
-        verify(
+        verify_lookup_in(
             lcb_subdocspecs_create(&mut specs, lookup_specs.len()),
             cookie,
         )?;
@@ -678,21 +1183,33 @@ pub fn encode_lookup_in(
         let mut idx = 0;
         for lookup_spec in &lookup_specs {
             match lookup_spec {
-                EncodedLookupSpec::Get { path_len, path } => {
-                    verify(
-                        lcb_subdocspecs_get(specs, idx, 0, path.as_ptr(), *path_len),
+                EncodedLookupSpec::Get {
+                    path_len,
+                    path,
+                    flags,
+                } => {
+                    verify_lookup_in(
+                        lcb_subdocspecs_get(specs, idx, *flags, path.as_ptr(), *path_len),
                         cookie,
                     )?;
                 }
-                EncodedLookupSpec::Exists { path_len, path } => {
-                    verify(
-                        lcb_subdocspecs_exists(specs, idx, 0, path.as_ptr(), *path_len),
+                EncodedLookupSpec::Exists {
+                    path_len,
+                    path,
+                    flags,
+                } => {
+                    verify_lookup_in(
+                        lcb_subdocspecs_exists(specs, idx, *flags, path.as_ptr(), *path_len),
                         cookie,
                     )?;
                 }
-                EncodedLookupSpec::Count { path_len, path } => {
-                    verify(
-                        lcb_subdocspecs_get_count(specs, idx, 0, path.as_ptr(), *path_len),
+                EncodedLookupSpec::Count {
+                    path_len,
+                    path,
+                    flags,
+                } => {
+                    verify_lookup_in(
+                        lcb_subdocspecs_get_count(specs, idx, *flags, path.as_ptr(), *path_len),
                         cookie,
                     )?;
                 }
@@ -700,9 +1217,9 @@ pub fn encode_lookup_in(
             idx += 1;
         }
 
-        verify(lcb_cmdsubdoc_create(&mut command), cookie)?;
-        verify(lcb_cmdsubdoc_key(command, id.as_ptr(), id_len), cookie)?;
-        verify(
+        verify_lookup_in(lcb_cmdsubdoc_create(&mut command), cookie)?;
+        verify_lookup_in(lcb_cmdsubdoc_key(command, id.as_ptr(), id_len), cookie)?;
+        verify_lookup_in(
             lcb_cmdsubdoc_collection(
                 command,
                 scope.as_ptr(),
@@ -713,26 +1230,19 @@ pub fn encode_lookup_in(
             cookie,
         )?;
 
-        if let Some(timeout) = request.options.timeout {
-            verify(
+        if let Some(timeout) = timeout {
+            verify_lookup_in(
                 lcb_cmdsubdoc_timeout(command, timeout.as_micros() as u32),
                 cookie,
             )?;
         }
-        if let Some(access_deleted) = request.options.access_deleted {
-            verify(
-                lcb_cmdsubdoc_access_deleted(command, if access_deleted { 1 } else { 0 }),
-                cookie,
-            )?;
-        }
 
-        verify(lcb_cmdsubdoc_specs(command, specs), cookie)?;
-        verify(lcb_subdoc(instance, cookie as *mut c_void, command), cookie)?;
-        verify(lcb_subdocspecs_destroy(specs), cookie)?;
-        verify(lcb_cmdsubdoc_destroy(command), cookie)?;
+        verify_lookup_in(lcb_cmdsubdoc_specs(command, specs), cookie)?;
+        let scheduled = schedule(command);
+        lcb_subdocspecs_destroy(specs);
+        lcb_cmdsubdoc_destroy(command);
+        scheduled
     }
-
-    Ok(())
 }
 
 pub enum EncodedMutateSpec {
@@ -1071,6 +1581,12 @@ pub fn encode_mutate_in(
                 cookie,
             )?;
         }
+        if let Some(level) = request.options.durability_level {
+            verify(
+                lcb_cmdsubdoc_durability(command, encode_durability_level(level)),
+                cookie,
+            )?;
+        }
         if let Some(cas) = request.options.cas {
             verify(lcb_cmdsubdoc_cas(command, cas), cookie)?;
         }
@@ -1083,10 +1599,7 @@ pub fn encode_mutate_in(
             verify(lcb_cmdsubdoc_store_semantics(command, ss), cookie)?;
         }
         if let Some(expiry) = request.options.expiry {
-            verify(
-                lcb_cmdsubdoc_expiry(command, expiry.as_micros() as u32),
-                cookie,
-            )?;
+            verify(lcb_cmdsubdoc_expiry(command, expiry.to_server()), cookie)?;
         }
         if let Some(access_deleted) = request.options.access_deleted {
             verify(
@@ -1097,6 +1610,12 @@ pub fn encode_mutate_in(
 
         verify(lcb_cmdsubdoc_specs(command, specs), cookie)?;
         verify(lcb_subdoc(instance, cookie as *mut c_void, command), cookie)?;
+        retry::register(
+            cookie as *mut c_void,
+            request.options.retry_strategy,
+            request.options.max_retries,
+        );
+        durability::register(cookie as *mut c_void, request.options.client_durability);
         verify(lcb_subdocspecs_destroy(specs), cookie)?;
         verify(lcb_cmdsubdoc_destroy(command), cookie)?;
     }
@@ -1117,22 +1636,43 @@ pub fn encode_generic_management_request(
     let (content_type_len, content_type) =
         into_cstring(request.content_type.unwrap_or(String::from("")));
 
+    let endpoint = request
+        .endpoint
+        .map(|e| format!("{}://{}", http_scheme(instance), e));
+    let http_type = match request.service_type {
+        Some(ServiceType::Views) => lcb_HTTP_TYPE_LCB_HTTP_TYPE_VIEW,
+        Some(ServiceType::Query) => lcb_HTTP_TYPE_LCB_HTTP_TYPE_QUERY,
+        Some(ServiceType::Search) => lcb_HTTP_TYPE_LCB_HTTP_TYPE_SEARCH,
+        Some(ServiceType::Analytics) => lcb_HTTP_TYPE_LCB_HTTP_TYPE_ANALYTICS,
+        // Key/value is not served over HTTP, so the request cannot be sent anywhere
+        Some(ServiceType::KeyValue) => {
+            return verify_http(lcb_STATUS_LCB_ERR_INVALID_ARGUMENT, cookie);
+        }
+        Some(ServiceType::Management) | None => lcb_HTTP_TYPE_LCB_HTTP_TYPE_MANAGEMENT,
+    };
+
     let mut command: *mut lcb_CMDHTTP = ptr::null_mut();
     unsafe {
-        verify_http(
-            lcb_cmdhttp_create(&mut command, lcb_HTTP_TYPE_LCB_HTTP_TYPE_MANAGEMENT),
-            cookie,
-        )?;
+        verify_http(lcb_cmdhttp_create(&mut command, http_type), cookie)?;
         let method = match request.method.as_str() {
             "get" => lcb_HTTP_METHOD_LCB_HTTP_METHOD_GET,
             "put" => lcb_HTTP_METHOD_LCB_HTTP_METHOD_PUT,
             "post" => lcb_HTTP_METHOD_LCB_HTTP_METHOD_POST,
             "delete" => lcb_HTTP_METHOD_LCB_HTTP_METHOD_DELETE,
+            "patch" => lcb_HTTP_METHOD_LCB_HTTP_METHOD_PATCH,
             _ => panic!("Unknown HTTP method used"),
         };
         verify_http(lcb_cmdhttp_method(command, method), cookie)?;
         verify_http(lcb_cmdhttp_path(command, path.as_ptr(), path_len), cookie)?;
 
+        if let Some(endpoint) = endpoint {
+            let (endpoint_len, endpoint) = into_cstring(endpoint);
+            verify_http(
+                lcb_cmdhttp_host(command, endpoint.as_ptr(), endpoint_len),
+                cookie,
+            )?;
+        }
+
         if let Some(timeout) = request.timeout {
             verify_http(
                 lcb_cmdhttp_timeout(command, timeout.as_micros() as u32),
@@ -1158,6 +1698,24 @@ pub fn encode_generic_management_request(
     Ok(())
 }
 
+/// The scheme to use for requests to a specific node, depending on whether TLS is enabled.
+fn http_scheme(instance: *mut lcb_INSTANCE) -> &'static str {
+    let mut mode: i32 = 0;
+    let status = unsafe {
+        lcb_cntl(
+            instance,
+            LCB_CNTL_GET as i32,
+            LCB_CNTL_SSL_MODE as i32,
+            &mut mode as *mut i32 as *mut c_void,
+        )
+    };
+    if status == lcb_STATUS_LCB_SUCCESS && mode & lcb_SSLOPTS_LCB_SSL_ENABLED as i32 != 0 {
+        "https"
+    } else {
+        "http"
+    }
+}
+
 #[cfg(feature = "volatile")]
 pub fn encode_kv_stats(
     instance: *mut lcb_INSTANCE,
@@ -1206,6 +1764,131 @@ pub fn encode_kv_stats(
     Ok(())
 }
 
+/// Encodes a `GetCollectionIdRequest` into its libcouchbase `lcb_CMDGETCID` representation.
+#[cfg(feature = "volatile")]
+pub fn encode_get_collection_id(
+    instance: *mut lcb_INSTANCE,
+    request: GetCollectionIdRequest,
+) -> Result<(), EncodeFailure> {
+    let cookie = Box::into_raw(Box::new(request.sender));
+    let (scope_len, scope) = into_cstring(request.scope);
+    let (collection_len, collection) = into_cstring(request.collection);
+
+    let mut command: *mut lcb_CMDGETCID = ptr::null_mut();
+    unsafe {
+        verify(lcb_cmdgetcid_create(&mut command), cookie)?;
+        verify(
+            lcb_cmdgetcid_scope(command, scope.as_ptr(), scope_len),
+            cookie,
+        )?;
+        verify(
+            lcb_cmdgetcid_collection(command, collection.as_ptr(), collection_len),
+            cookie,
+        )?;
+        if let Some(timeout) = request.options.timeout {
+            verify(
+                lcb_cmdgetcid_timeout(command, timeout.as_micros() as u32),
+                cookie,
+            )?;
+        }
+
+        verify(lcb_getcid(instance, cookie as *mut c_void, command), cookie)?;
+        verify(lcb_cmdgetcid_destroy(command), cookie)?;
+    }
+
+    Ok(())
+}
+
+/// Answers a `GetPartitionMapRequest` right away from the current configuration.
+#[cfg(feature = "volatile")]
+pub fn encode_get_partition_map(
+    instance: *mut lcb_INSTANCE,
+    request: GetPartitionMapRequest,
+) -> Result<(), EncodeFailure> {
+    let result = partition_map(instance).ok_or_else(|| {
+        let mut ctx = ErrorContext::default();
+        ctx.insert("bucket", Value::String(request.bucket.clone()));
+        ctx.insert(
+            "msg",
+            Value::String("the bucket has no partition map (yet)".into()),
+        );
+        CouchbaseError::ServiceNotAvailable { ctx }
+    });
+    if request.sender.send(result).is_err() {
+        debug!("Failed to send partition map because the receiver is gone");
+    }
+    Ok(())
+}
+
+/// Builds the partition map from the current configuration of the instance.
+///
+/// Returns `None` before the instance has been bootstrapped and for buckets which are not
+/// partitioned, like memcached buckets.
+#[cfg(feature = "volatile")]
+pub fn partition_map(instance: *mut lcb_INSTANCE) -> Option<PartitionMap> {
+    let config = partitioned_config(instance)?;
+    let mut ssl_mode: c_int = 0;
+    unsafe {
+        lcb_cntl(
+            instance,
+            LCB_CNTL_GET as i32,
+            LCB_CNTL_SSL_MODE as i32,
+            &mut ssl_mode as *mut c_int as *mut c_void,
+        );
+        let mode = if ssl_mode as u32 & lcb_SSLOPTS_LCB_SSL_ENABLED != 0 {
+            lcbvb_SVCMODE_LCBVB_SVCMODE_SSL
+        } else {
+            lcbvb_SVCMODE_LCBVB_SVCMODE_PLAIN
+        };
+
+        let nodes = (0..(*config).nsrv)
+            .map(|ix| {
+                let hostport =
+                    lcbvb_get_hostport(config, ix, lcbvb_SVCTYPE_LCBVB_SVCTYPE_DATA, mode);
+                if hostport.is_null() {
+                    String::new()
+                } else {
+                    CStr::from_ptr(hostport).to_string_lossy().into_owned()
+                }
+            })
+            .collect();
+        let owners = (0..(*config).nvb)
+            .map(|vb| match lcbvb_vbmaster(config, vb as i32) {
+                ix if ix < 0 => None,
+                ix => Some(ix as usize),
+            })
+            .collect();
+        Some(PartitionMap::new((*config).revid as i64, nodes, owners))
+    }
+}
+
+/// The revision of the current configuration of the instance, which is the revision of the
+/// partition map it would build.
+#[cfg(feature = "volatile")]
+pub fn partition_revision(instance: *mut lcb_INSTANCE) -> Option<i64> {
+    partitioned_config(instance).map(|config| unsafe { (*config).revid as i64 })
+}
+
+#[cfg(feature = "volatile")]
+fn partitioned_config(instance: *mut lcb_INSTANCE) -> Option<*mut lcbvb_CONFIG> {
+    let mut config: *mut lcbvb_CONFIG = ptr::null_mut();
+    unsafe {
+        let status = lcb_cntl(
+            instance,
+            LCB_CNTL_GET as i32,
+            LCB_CNTL_VBCONFIG as i32,
+            &mut config as *mut *mut lcbvb_CONFIG as *mut c_void,
+        );
+        if status != lcb_STATUS_LCB_SUCCESS || config.is_null() {
+            return None;
+        }
+        if (*config).dtype != lcbvb_DISTMODE_LCBVB_DIST_VBUCKET {
+            return None;
+        }
+    }
+    Some(config)
+}
+
 /// Encodes a `PingRequest` into its libcouchbase `lcb_CMDPING` representation.
 pub fn encode_ping(instance: *mut lcb_INSTANCE, request: PingRequest) -> Result<(), EncodeFailure> {
     let cookie = Box::into_raw(Box::new(request.sender));
@@ -1223,10 +1906,60 @@ pub fn encode_ping(instance: *mut lcb_INSTANCE, request: PingRequest) -> Result<
             lcb_cmdping_report_id(command, c_report_id.as_ptr(), report_id_len),
             cookie,
         )?;
-        verify(lcb_cmdping_all(command), cookie)?;
+        match request.options.service_types {
+            Some(service_types) => {
+                for service_type in service_types {
+                    let status = match service_type {
+                        ServiceType::KeyValue => lcb_cmdping_kv(command, 1),
+                        ServiceType::Views => lcb_cmdping_views(command, 1),
+                        ServiceType::Query => lcb_cmdping_query(command, 1),
+                        ServiceType::Search => lcb_cmdping_search(command, 1),
+                        ServiceType::Analytics => lcb_cmdping_analytics(command, 1),
+                        ServiceType::Management => continue,
+                    };
+                    verify(status, cookie)?;
+                }
+            }
+            None => verify(lcb_cmdping_all(command), cookie)?,
+        }
+        if let Some(timeout) = request.options.timeout {
+            verify(
+                lcb_cmdping_timeout(command, timeout.as_micros() as u32),
+                cookie,
+            )?;
+        }
         verify(lcb_ping(instance, cookie as *mut c_void, command), cookie)?;
         verify(lcb_cmdping_destroy(command), cookie)?;
     }
 
     Ok(())
 }
+
+/// Encodes a `DiagnosticsRequest` into its libcouchbase `lcb_CMDDIAG` representation.
+///
+/// Libcouchbase builds the report right away, so the callback runs before this returns.
+pub fn encode_diagnostics(
+    instance: *mut lcb_INSTANCE,
+    request: DiagnosticsRequest,
+) -> Result<(), EncodeFailure> {
+    let cookie = Box::into_raw(Box::new(request.sender));
+
+    let report_id = request
+        .options
+        .report_id
+        .unwrap_or(Uuid::new_v4().to_hyphenated().to_string());
+    let (report_id_len, c_report_id) = into_cstring(report_id);
+
+    let mut command: *mut lcb_CMDDIAG = ptr::null_mut();
+    unsafe {
+        verify(lcb_cmddiag_create(&mut command), cookie)?;
+        verify(
+            lcb_cmddiag_report_id(command, c_report_id.as_ptr(), report_id_len),
+            cookie,
+        )?;
+        verify(lcb_diag(instance, cookie as *mut c_void, command), cookie)?;
+        verify(lcb_cmddiag_destroy(command), cookie)?;
+    }
+
+    Ok(())
+}
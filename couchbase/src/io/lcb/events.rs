@@ -0,0 +1,171 @@
+use crate::api::events::{ClusterEvent, ClusterEventListener};
+use crate::io::lcb::bucket_name_for_instance;
+use couchbase_sys::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// How many events are kept until the listener is set.
+const MAX_PENDING: usize = 64;
+
+thread_local! {
+    // Shared by all instances on the thread of an IoCore, like the certificate watch
+    static STATE: RefCell<EventState> = RefCell::new(EventState::default());
+}
+
+#[derive(Default)]
+struct EventState {
+    listener: Option<Arc<dyn ClusterEventListener>>,
+    // The listener is only set once the cluster instance has bootstrapped, so its events
+    // are kept until then
+    pending: VecDeque<ClusterEvent>,
+    // Counts the configurations applied by all instances, see `Core::config_generation`
+    config_generation: Option<Arc<AtomicU64>>,
+}
+
+pub fn set_config_generation(config_generation: Arc<AtomicU64>) {
+    STATE.with(|s| s.borrow_mut().config_generation = Some(config_generation));
+}
+
+fn config_updated() {
+    STATE.with(|s| {
+        if let Some(generation) = &s.borrow().config_generation {
+            generation.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+}
+
+pub fn set_listener(listener: Arc<dyn ClusterEventListener>) {
+    let pending = STATE.with(|s| {
+        let state = &mut *s.borrow_mut();
+        state.listener = Some(listener.clone());
+        std::mem::take(&mut state.pending)
+    });
+    for event in pending {
+        listener.on_event(&event);
+    }
+}
+
+pub fn emit(event: ClusterEvent) {
+    let listener = STATE.with(|s| {
+        let state = &mut *s.borrow_mut();
+        if state.listener.is_none() {
+            if state.pending.len() == MAX_PENDING {
+                state.pending.pop_front();
+            }
+            state.pending.push_back(event.clone());
+        }
+        state.listener.clone()
+    });
+    if let Some(listener) = listener {
+        listener.on_event(&event);
+    }
+}
+
+/// Translates the events libcouchbase reports for its configuration, connections and circuit
+/// breakers.
+pub unsafe extern "C" fn cluster_event_callback(
+    instance: *mut lcb_INSTANCE,
+    event: *const lcb_CLUSTER_EVENT,
+) {
+    let event = &*event;
+    let bucket = bucket_name_for_instance(instance);
+    let remote = match (to_str(event.host), to_str(event.port)) {
+        (Some(h), Some(p)) => format!("{}:{}", h, p),
+        _ => String::new(),
+    };
+    #[allow(non_upper_case_globals)]
+    let event = match event.type_ {
+        lcb_CLUSTER_EVENT_TYPE_LCB_CLUSTER_EVENT_CONFIG_UPDATED => {
+            config_updated();
+            ClusterEvent::ConfigUpdated {
+                bucket,
+                revision: event.config_revision,
+            }
+        }
+        lcb_CLUSTER_EVENT_TYPE_LCB_CLUSTER_EVENT_ENDPOINT_CONNECTED => {
+            ClusterEvent::EndpointConnected { bucket, remote }
+        }
+        lcb_CLUSTER_EVENT_TYPE_LCB_CLUSTER_EVENT_ENDPOINT_DISCONNECTED => {
+            ClusterEvent::EndpointDisconnected {
+                bucket,
+                remote,
+                reason: CStr::from_ptr(lcb_strerror_short(event.rc))
+                    .to_string_lossy()
+                    .into_owned(),
+            }
+        }
+        lcb_CLUSTER_EVENT_TYPE_LCB_CLUSTER_EVENT_CIRCUIT_OPENED => {
+            ClusterEvent::CircuitOpened { bucket, remote }
+        }
+        lcb_CLUSTER_EVENT_TYPE_LCB_CLUSTER_EVENT_CIRCUIT_CLOSED => {
+            ClusterEvent::CircuitClosed { bucket, remote }
+        }
+        _ => return,
+    };
+    emit(event);
+}
+
+unsafe fn to_str<'a>(value: *const c_char) -> Option<&'a str> {
+    if value.is_null() {
+        None
+    } else {
+        CStr::from_ptr(value).to_str().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct Recorder(Mutex<Vec<i64>>);
+
+    impl ClusterEventListener for Recorder {
+        fn on_event(&self, event: &ClusterEvent) {
+            if let ClusterEvent::ConfigUpdated { revision, .. } = event {
+                self.0.lock().unwrap().push(*revision);
+            }
+        }
+    }
+
+    fn config_update(revision: i64) -> ClusterEvent {
+        ClusterEvent::ConfigUpdated {
+            bucket: None,
+            revision,
+        }
+    }
+
+    #[test]
+    fn replays_the_pending_events_to_the_listener() {
+        emit(config_update(1));
+        emit(config_update(2));
+        let recorder = Arc::new(Recorder::default());
+        set_listener(recorder.clone());
+        assert_eq!(*recorder.0.lock().unwrap(), vec![1, 2]);
+
+        // Later events go to the listener directly
+        emit(config_update(3));
+        assert_eq!(*recorder.0.lock().unwrap(), vec![1, 2, 3]);
+        assert!(STATE.with(|s| s.borrow().pending.is_empty()));
+    }
+
+    #[test]
+    fn drops_the_oldest_events_beyond_the_limit() {
+        for revision in 0..(MAX_PENDING as i64 + 10) {
+            emit(config_update(revision));
+        }
+        assert_eq!(STATE.with(|s| s.borrow().pending.len()), MAX_PENDING);
+
+        let recorder = Arc::new(Recorder::default());
+        set_listener(recorder.clone());
+        let replayed = recorder.0.lock().unwrap();
+        assert_eq!(replayed.len(), MAX_PENDING);
+        assert_eq!(replayed.first(), Some(&10));
+        assert_eq!(replayed.last(), Some(&(MAX_PENDING as i64 + 9)));
+    }
+}
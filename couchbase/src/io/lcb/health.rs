@@ -0,0 +1,328 @@
+use crate::api::events::ClusterEvent;
+use crate::api::options::HealthProbe;
+use crate::api::redaction::SystemData;
+use crate::api::results::ServiceType;
+use crate::io::lcb::events;
+use couchbase_sys::*;
+use log::{info, warn};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CStr;
+
+thread_local! {
+    // Shared by all instances on the thread of an IoCore, so an HTTP node degraded for one
+    // bucket is also avoided by the others
+    static STATE: RefCell<HealthState> = RefCell::new(HealthState::default());
+}
+
+#[derive(Default)]
+struct HealthState {
+    probe: Option<HealthProbe>,
+    endpoints: HashMap<EndpointKey, EndpointHealth>,
+    // Quarantines which have not been applied to the instances yet
+    changes: Vec<QuarantineChange>,
+}
+
+/// Identifies an endpoint by its service and remote (`host:port`).
+///
+/// Every instance has its own key/value connections, so the health of those is tracked per
+/// instance: a healthy connection of one bucket must not hide the failures of another
+/// bucket's connection to the same node. The HTTP services are shared by all instances.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct EndpointKey {
+    service: ServiceType,
+    remote: String,
+    instance: Option<usize>,
+}
+
+impl EndpointKey {
+    fn new(instance: *mut lcb_INSTANCE, service: ServiceType, remote: String) -> Self {
+        Self {
+            service,
+            remote,
+            instance: match service {
+                ServiceType::KeyValue => Some(instance as usize),
+                _ => None,
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+struct EndpointHealth {
+    failures: u32,
+    degraded: bool,
+}
+
+/// Quarantines a node for, or releases it to, the requests of a service.
+pub struct QuarantineChange {
+    pub service: lcb_PING_SERVICE,
+    pub hostname: String,
+    pub quarantined: bool,
+}
+
+pub fn set_probe(probe: HealthProbe) {
+    STATE.with(|s| s.borrow_mut().probe = Some(probe));
+}
+
+pub fn probe() -> Option<HealthProbe> {
+    STATE.with(|s| s.borrow().probe)
+}
+
+/// Records the outcome of a background ping of the endpoint by the instance, which is ignored
+/// unless the health probe is enabled.
+pub fn record(
+    instance: *mut lcb_INSTANCE,
+    service: ServiceType,
+    remote: String,
+    status: lcb_STATUS,
+) {
+    let event = STATE.with(|s| {
+        let state = &mut *s.borrow_mut();
+        let degraded_after = state.probe?.degraded_after;
+        let key = EndpointKey::new(instance, service, remote);
+        let event = if status == lcb_STATUS_LCB_SUCCESS {
+            let health = state.endpoints.remove(&key)?;
+            if !health.degraded {
+                return None;
+            }
+            info!(
+                "{:?} endpoint {} is healthy again",
+                service,
+                SystemData(&key.remote)
+            );
+            ClusterEvent::EndpointRecovered {
+                service,
+                remote: key.remote.clone(),
+            }
+        } else {
+            let health = state.endpoints.entry(key.clone()).or_default();
+            health.failures += 1;
+            if health.degraded || health.failures < degraded_after {
+                return None;
+            }
+            health.degraded = true;
+            let reason = unsafe { CStr::from_ptr(lcb_strerror_short(status)) }
+                .to_string_lossy()
+                .into_owned();
+            warn!(
+                "{:?} endpoint {} is degraded after {} failed pings, the last with {}",
+                service,
+                SystemData(&key.remote),
+                health.failures,
+                reason
+            );
+            ClusterEvent::EndpointDegraded {
+                service,
+                remote: key.remote.clone(),
+                reason,
+            }
+        };
+        if let Some(change) = quarantine_change(&key, status != lcb_STATUS_LCB_SUCCESS) {
+            state.changes.push(change);
+        }
+        Some(event)
+    });
+    if let Some(event) = event {
+        events::emit(event);
+    }
+}
+
+/// If the background pings of the instance marked the endpoint as degraded.
+pub fn is_degraded(instance: *mut lcb_INSTANCE, service: ServiceType, remote: &str) -> bool {
+    STATE.with(|s| {
+        s.borrow()
+            .endpoints
+            .get(&EndpointKey::new(instance, service, remote.to_string()))
+            .is_some_and(|h| h.degraded)
+    })
+}
+
+/// Forgets the key/value endpoints of a destroyed instance, whose address may be reused.
+pub fn forget(instance: *mut lcb_INSTANCE) {
+    STATE.with(|s| {
+        s.borrow_mut()
+            .endpoints
+            .retain(|key, _| key.instance != Some(instance as usize))
+    })
+}
+
+/// Returns the quarantines which changed since the last call.
+pub fn take_changes() -> Vec<QuarantineChange> {
+    STATE.with(|s| std::mem::take(&mut s.borrow_mut().changes))
+}
+
+/// Returns all current quarantines, to apply them to a new instance.
+pub fn quarantined() -> Vec<QuarantineChange> {
+    STATE.with(|s| {
+        s.borrow()
+            .endpoints
+            .iter()
+            .filter(|(_, h)| h.degraded)
+            .filter_map(|(key, _)| quarantine_change(key, true))
+            .collect()
+    })
+}
+
+/// Key/value requests go to the node of their partition, so only the HTTP services can be
+/// routed around a degraded node.
+fn quarantine_change(key: &EndpointKey, quarantined: bool) -> Option<QuarantineChange> {
+    let service = match key.service {
+        ServiceType::Views => lcb_PING_SERVICE_LCB_PING_SERVICE_VIEWS,
+        ServiceType::Query => lcb_PING_SERVICE_LCB_PING_SERVICE_QUERY,
+        ServiceType::Search => lcb_PING_SERVICE_LCB_PING_SERVICE_SEARCH,
+        ServiceType::Analytics => lcb_PING_SERVICE_LCB_PING_SERVICE_ANALYTICS,
+        _ => return None,
+    };
+    let host = match key.remote.rfind(':') {
+        Some(i) => &key.remote[..i],
+        None => key.remote.as_str(),
+    };
+    Some(QuarantineChange {
+        service,
+        hostname: host.trim_start_matches('[').trim_end_matches(']').into(),
+        quarantined,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::events::ClusterEventListener;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    const FIRST: *mut lcb_INSTANCE = 8 as *mut lcb_INSTANCE;
+    const SECOND: *mut lcb_INSTANCE = 16 as *mut lcb_INSTANCE;
+    const FAILED: lcb_STATUS = lcb_STATUS_LCB_ERR_TIMEOUT;
+
+    #[derive(Debug, Default)]
+    struct Recorder(Mutex<Vec<ClusterEvent>>);
+
+    impl ClusterEventListener for Recorder {
+        fn on_event(&self, event: &ClusterEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    fn recorder() -> Arc<Recorder> {
+        set_probe(HealthProbe::new(Duration::from_secs(1)).degraded_after(2));
+        let recorder = Arc::new(Recorder::default());
+        events::set_listener(recorder.clone());
+        recorder
+    }
+
+    fn kv(instance: *mut lcb_INSTANCE, status: lcb_STATUS) {
+        record(
+            instance,
+            ServiceType::KeyValue,
+            "10.0.0.1:11210".into(),
+            status,
+        );
+    }
+
+    #[test]
+    fn ignores_pings_without_a_probe() {
+        kv(FIRST, FAILED);
+        kv(FIRST, FAILED);
+        assert!(!is_degraded(FIRST, ServiceType::KeyValue, "10.0.0.1:11210"));
+    }
+
+    #[test]
+    fn degrades_after_consecutive_failures_and_recovers() {
+        let recorder = recorder();
+        kv(FIRST, FAILED);
+        assert!(!is_degraded(FIRST, ServiceType::KeyValue, "10.0.0.1:11210"));
+        // A success in between starts the count over
+        kv(FIRST, lcb_STATUS_LCB_SUCCESS);
+        kv(FIRST, FAILED);
+        assert!(!is_degraded(FIRST, ServiceType::KeyValue, "10.0.0.1:11210"));
+        kv(FIRST, FAILED);
+        assert!(is_degraded(FIRST, ServiceType::KeyValue, "10.0.0.1:11210"));
+        // Further failures are not reported again
+        kv(FIRST, FAILED);
+        kv(FIRST, lcb_STATUS_LCB_SUCCESS);
+        assert!(!is_degraded(FIRST, ServiceType::KeyValue, "10.0.0.1:11210"));
+
+        let events = recorder.0.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            &events[0],
+            ClusterEvent::EndpointDegraded { service: ServiceType::KeyValue, remote, .. }
+                if remote == "10.0.0.1:11210"
+        ));
+        assert!(matches!(
+            &events[1],
+            ClusterEvent::EndpointRecovered { service: ServiceType::KeyValue, remote }
+                if remote == "10.0.0.1:11210"
+        ));
+        // Key/value endpoints cannot be routed around
+        assert!(take_changes().is_empty());
+    }
+
+    #[test]
+    fn tracks_key_value_endpoints_per_instance() {
+        recorder();
+        kv(FIRST, FAILED);
+        kv(SECOND, lcb_STATUS_LCB_SUCCESS);
+        kv(FIRST, FAILED);
+        assert!(is_degraded(FIRST, ServiceType::KeyValue, "10.0.0.1:11210"));
+        assert!(!is_degraded(
+            SECOND,
+            ServiceType::KeyValue,
+            "10.0.0.1:11210"
+        ));
+
+        forget(FIRST);
+        assert!(!is_degraded(FIRST, ServiceType::KeyValue, "10.0.0.1:11210"));
+    }
+
+    #[test]
+    fn quarantines_degraded_http_endpoints() {
+        recorder();
+        // The HTTP services are shared by all instances
+        record(FIRST, ServiceType::Query, "[::1]:8093".into(), FAILED);
+        record(SECOND, ServiceType::Query, "[::1]:8093".into(), FAILED);
+        assert!(is_degraded(FIRST, ServiceType::Query, "[::1]:8093"));
+
+        let changes = take_changes();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].service, lcb_PING_SERVICE_LCB_PING_SERVICE_QUERY);
+        assert_eq!(changes[0].hostname, "::1");
+        assert!(changes[0].quarantined);
+        assert!(take_changes().is_empty());
+        assert_eq!(quarantined().len(), 1);
+
+        record(
+            SECOND,
+            ServiceType::Query,
+            "[::1]:8093".into(),
+            lcb_STATUS_LCB_SUCCESS,
+        );
+        let changes = take_changes();
+        assert_eq!(changes.len(), 1);
+        assert!(!changes[0].quarantined);
+        assert!(quarantined().is_empty());
+    }
+
+    #[test]
+    fn parses_the_host_of_the_remote() {
+        let host = |service, remote: &str| {
+            quarantine_change(&EndpointKey::new(FIRST, service, remote.into()), true)
+                .map(|c| c.hostname)
+        };
+        assert_eq!(
+            host(ServiceType::Search, "10.0.0.1:8094"),
+            Some("10.0.0.1".into())
+        );
+        assert_eq!(
+            host(ServiceType::Analytics, "[fe80::1]:8095"),
+            Some("fe80::1".into())
+        );
+        assert_eq!(
+            host(ServiceType::Views, "node.example.com"),
+            Some("node.example.com".into())
+        );
+        assert_eq!(host(ServiceType::KeyValue, "10.0.0.1:11210"), None);
+    }
+}
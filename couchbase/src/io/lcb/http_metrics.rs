@@ -0,0 +1,31 @@
+use crate::api::metrics::Meter;
+use crate::api::results::ServiceType;
+use couchbase_sys::*;
+use std::cell::RefCell;
+use std::sync::Arc;
+
+thread_local! {
+    // Like the retry budget, the meter is shared by all instances running on the thread
+    // of an IoCore.
+    static METER: RefCell<Option<Arc<dyn Meter>>> = RefCell::new(None);
+}
+
+pub fn set_meter(meter: Arc<dyn Meter>) {
+    METER.with(|m| *m.borrow_mut() = Some(meter));
+}
+
+/// Reports the size of the response body once a query, search or analytics request finished.
+pub unsafe fn record_body_sizes(service: ServiceType, response: *const lcb_RESPHTTP) {
+    if response.is_null() {
+        // The request failed before any response was received
+        return;
+    }
+    METER.with(|m| {
+        if let Some(meter) = m.borrow().as_ref() {
+            let mut wire_bytes: lcb_U64 = 0;
+            let mut raw_bytes: lcb_U64 = 0;
+            lcb_resphttp_body_sizes(response, &mut wire_bytes, &mut raw_bytes);
+            meter.record_http_body_size(service, wire_bytes, raw_bytes);
+        }
+    });
+}
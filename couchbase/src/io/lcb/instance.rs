@@ -1,26 +1,76 @@
 use crate::api::error::{CouchbaseError, ErrorContext};
+use crate::api::options::{HealthProbe, KvKeepAlive};
+use crate::api::redaction::MetaData;
+#[cfg(feature = "volatile")]
+use crate::api::results::PartitionMap;
+use crate::io::lcb::auth::{password_callback, username_callback, AuthCookie};
 use crate::io::lcb::callbacks::*;
+use crate::io::lcb::certificates;
+use crate::io::lcb::durability;
 use crate::io::lcb::encode::into_cstring;
-use crate::io::lcb::{encode_request, IoRequest};
+#[cfg(feature = "volatile")]
+use crate::io::lcb::encode::{partition_map, partition_revision};
+use crate::io::lcb::events;
+use crate::io::lcb::health::{self, QuarantineChange};
+use crate::io::lcb::http_metrics;
+use crate::io::lcb::kv_metrics;
+use crate::io::lcb::retry;
+use crate::io::lcb::{encode_request, AnalyticsCookie, IoRequest, QueryCookie, SearchCookie};
 use crate::io::request::Request;
+use crate::io::Credentials;
 use couchbase_sys::*;
+#[cfg(feature = "volatile")]
+use futures::channel::mpsc::UnboundedSender;
 use log::{debug, warn};
 use serde_json::Value;
+use std::cmp;
 use std::collections::HashMap;
-use std::os::raw::c_void;
+use std::os::raw::{c_int, c_void};
 use std::ptr;
+use std::time::{Duration, Instant};
+
+/// How long requests against a bucket which failed to open are failed right away, before
+/// opening it is attempted again. The delay doubles with every failed attempt.
+const OPEN_RETRY_MIN_DELAY: Duration = Duration::from_secs(1);
+/// The longest delay between two attempts to open a bucket which keeps failing.
+const OPEN_RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+/// How long an instance waits for its outstanding requests when it is dropped. Requests still
+/// outstanding after that are abandoned.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Wraps a single `lcb_instance`.
 pub struct LcbInstance {
     // The pointer to the actual libcouchbase instance
     inner: *mut lcb_INSTANCE,
+    // Keeps the per-service credentials or the provider alive while the dynamic authenticator
+    // uses them
+    _auth_cookie: Option<Box<AuthCookie>>,
+    // When the last request or keepalive was sent, to find idle instances
+    last_activity: Instant,
+    // When the last health probe was sent
+    last_probe: Instant,
+    // The streams of `Bucket::partition_map_changes` and the revision they have last seen
+    #[cfg(feature = "volatile")]
+    partition_watchers: Vec<UnboundedSender<PartitionMap>>,
+    #[cfg(feature = "volatile")]
+    partition_revision: Option<i64>,
 }
 
 impl LcbInstance {
     pub fn new<S: Into<Vec<u8>>>(
         connection_string: S,
-        username: S,
-        password: S,
+        credentials: &Credentials,
+    ) -> Result<Self, lcb_STATUS> {
+        let instance = Self::connect(connection_string, credentials)?;
+        check_lcb_status(unsafe { lcb_wait(instance.inner, lcb_WAITFLAGS_LCB_WAIT_DEFAULT) })?;
+        Ok(instance)
+    }
+
+    /// Creates the instance and starts bootstrapping it without waiting, see
+    /// `bootstrap_status`.
+    fn connect<S: Into<Vec<u8>>>(
+        connection_string: S,
+        credentials: &Credentials,
     ) -> Result<Self, lcb_STATUS> {
         let mut inner: *mut lcb_INSTANCE = ptr::null_mut();
         let mut create_options: *mut lcb_CREATEOPTS = ptr::null_mut();
@@ -28,8 +78,9 @@ impl LcbInstance {
         let instance_cookie = Box::new(InstanceCookie::new());
 
         let (connection_string_len, connection_string) = into_cstring(connection_string);
-        let (username_len, username) = into_cstring(username);
-        let (password_len, password) = into_cstring(password);
+        let (username_len, username) = into_cstring(credentials.username());
+        let (password_len, password) = into_cstring(credentials.password());
+        let mut auth_cookie = None;
 
         unsafe {
             check_lcb_status(lcb_createopts_create(
@@ -57,15 +108,44 @@ impl LcbInstance {
             check_lcb_status(lcb_create(&mut inner, create_options))?;
             check_lcb_status(lcb_createopts_destroy(create_options))?;
 
+            // Without a bucket the instance bootstraps from the global config (GCCCP) and falls
+            // back to the bucketless HTTP stream. The static config provider is deliberately
+            // left disabled: it only lists the management service and would turn bad
+            // credentials or an unreachable cluster into a successful bootstrap.
+
+            if credentials.has_overrides() || credentials.provider().is_some() {
+                let cookie = Box::new(AuthCookie::new(credentials));
+                let auth = lcbauth_new();
+                check_lcb_status(lcbauth_set_callbacks(
+                    auth,
+                    &*cookie as *const AuthCookie as *mut c_void,
+                    Some(username_callback),
+                    Some(password_callback),
+                ))?;
+                check_lcb_status(lcbauth_set_mode(auth, lcbauth_MODE_LCBAUTH_MODE_DYNAMIC))?;
+                lcb_set_auth(inner, auth);
+                lcbauth_unref(auth);
+                auth_cookie = Some(cookie);
+            }
+
             Self::install_instance_callbacks(inner);
+            check_lcb_status(lcb_retry_strategy(inner, Some(retry::retry_strategy)))?;
 
             lcb_set_cookie(inner, Box::into_raw(instance_cookie) as *const c_void);
 
             check_lcb_status(lcb_connect(inner))?;
-            check_lcb_status(lcb_wait(inner, lcb_WAITFLAGS_LCB_WAIT_DEFAULT))?;
         }
 
-        Ok(Self { inner })
+        Ok(Self {
+            inner,
+            _auth_cookie: auth_cookie,
+            last_activity: Instant::now(),
+            last_probe: Instant::now(),
+            #[cfg(feature = "volatile")]
+            partition_watchers: vec![],
+            #[cfg(feature = "volatile")]
+            partition_revision: None,
+        })
     }
 
     /// Installs all the operation callbacks from libcouchbase.
@@ -79,6 +159,11 @@ impl LcbInstance {
             lcb_CALLBACK_TYPE_LCB_CALLBACK_GET as i32,
             Some(get_callback),
         );
+        lcb_install_callback(
+            instance,
+            lcb_CALLBACK_TYPE_LCB_CALLBACK_GETREPLICA as i32,
+            Some(get_replica_callback),
+        );
 
         lcb_install_callback(
             instance,
@@ -90,6 +175,16 @@ impl LcbInstance {
             lcb_CALLBACK_TYPE_LCB_CALLBACK_EXISTS as i32,
             Some(exists_callback),
         );
+        lcb_install_callback(
+            instance,
+            lcb_CALLBACK_TYPE_LCB_CALLBACK_UNLOCK as i32,
+            Some(unlock_callback),
+        );
+        lcb_install_callback(
+            instance,
+            lcb_CALLBACK_TYPE_LCB_CALLBACK_TOUCH as i32,
+            Some(touch_callback),
+        );
         lcb_install_callback(
             instance,
             lcb_CALLBACK_TYPE_LCB_CALLBACK_REMOVE as i32,
@@ -123,13 +218,45 @@ impl LcbInstance {
             Some(ping_callback),
         );
 
+        lcb_install_callback(
+            instance,
+            lcb_CALLBACK_TYPE_LCB_CALLBACK_DIAG as i32,
+            Some(diag_callback),
+        );
+
         lcb_install_callback(
             instance,
             lcb_CALLBACK_TYPE_LCB_CALLBACK_COUNTER as i32,
             Some(counter_callback),
         );
 
+        lcb_install_callback(
+            instance,
+            lcb_CALLBACK_TYPE_LCB_CALLBACK_ENDURE as i32,
+            Some(durability::endure_callback),
+        );
+
+        lcb_install_callback(
+            instance,
+            lcb_CALLBACK_TYPE_LCB_CALLBACK_GETCID as i32,
+            Some(get_cid_callback),
+        );
+
+        lcb_set_bootstrap_callback(instance, Some(bootstrap_callback));
         lcb_set_open_callback(instance, Some(open_callback));
+        lcb_set_certificate_callback(instance, Some(certificates::certificate_callback));
+        lcb_set_cluster_event_callback(instance, Some(events::cluster_event_callback));
+
+        if kv_metrics::enabled() {
+            lcb_set_kvdispatch_callback(instance, Some(kv_metrics::kv_dispatch_callback));
+        }
+    }
+
+    /// Observes every key/value response to feed the meter, see `kv_metrics`.
+    fn install_kv_dispatch_callback(&self) {
+        unsafe {
+            lcb_set_kvdispatch_callback(self.inner, Some(kv_metrics::kv_dispatch_callback));
+        }
     }
 
     /// Returns true if there is at least one oustanding request.
@@ -144,12 +271,7 @@ impl LcbInstance {
     }
 
     fn increment_outstanding_requests(&mut self) {
-        let mut instance_cookie = unsafe {
-            let instance_cookie_ptr: *const c_void = lcb_get_cookie(self.inner);
-            Box::from_raw(instance_cookie_ptr as *mut InstanceCookie)
-        };
-        instance_cookie.increment_outstanding();
-        Box::into_raw(instance_cookie);
+        increment_outstanding_requests(self.inner);
     }
 
     /// Makes progress on the instance without blocking.
@@ -157,12 +279,35 @@ impl LcbInstance {
         check_lcb_status(unsafe { lcb_tick_nowait(self.inner) })
     }
 
-    pub fn bind_to_bucket(&mut self, name: String) -> Result<(), lcb_STATUS> {
-        debug!("Starting bucket bind for {}", &name);
-        let (name_len, c_name) = into_cstring(name.clone());
+    /// The outcome of bootstrapping the instance, or `None` while it is still in progress.
+    fn bootstrap_status(&self) -> Option<lcb_STATUS> {
+        let instance_cookie = unsafe {
+            let instance_cookie_ptr: *const c_void = lcb_get_cookie(self.inner);
+            Box::from_raw(instance_cookie_ptr as *mut InstanceCookie)
+        };
+        let status = instance_cookie.bootstrap_status;
+        Box::into_raw(instance_cookie);
+        status
+    }
+
+    /// Starts opening the bucket without waiting, see `open_status`.
+    ///
+    /// The instance must have bootstrapped already.
+    fn start_open(&mut self, name: &str) -> Result<(), lcb_STATUS> {
+        set_open_status(self.inner, None);
+        let (name_len, c_name) = into_cstring(name);
         unsafe {
-            check_lcb_status(lcb_open(self.inner, c_name.as_ptr(), name_len))?;
+            // Libcouchbase clears the callback once it fired, i.e. for a failed bootstrap
+            lcb_set_open_callback(self.inner, Some(open_callback));
+            check_lcb_status(lcb_open(self.inner, c_name.as_ptr(), name_len))
         }
+    }
+
+    /// Opens the bucket and waits until it is ready, failing with the status libcouchbase
+    /// reported if it could not be opened (i.e. `LCB_ERR_BUCKET_NOT_FOUND`).
+    pub fn bind_to_bucket(&mut self, name: String) -> Result<(), lcb_STATUS> {
+        debug!("Starting bucket bind for {}", MetaData(&name));
+        self.start_open(&name)?;
 
         // We need a blocking wait here for now (until lcb fixes it in CCBC-1025), because
         // right now if we nowait a incoming request can race with the open and will end
@@ -172,27 +317,246 @@ impl LcbInstance {
             lcb_wait(self.inner, lcb_WAITFLAGS_LCB_WAIT_DEFAULT);
         }
 
-        debug!("Finished bucket bind for {}", &name);
-        Ok(())
+        debug!("Finished bucket bind for {}", MetaData(&name));
+        check_lcb_status(open_status(self.inner).unwrap_or(lcb_STATUS_LCB_SUCCESS))
     }
 
     pub fn handle_request(&mut self, request: Request) {
+        self.last_activity = Instant::now();
+        #[cfg(feature = "volatile")]
+        let request = match request {
+            Request::WatchPartitionMap(r) => {
+                self.watch_partition_map(r.sender);
+                return;
+            }
+            r => r,
+        };
+        // Diagnostics (and partition maps) complete during encoding, so there is nothing
+        // left to wait for
+        #[cfg(not(feature = "volatile"))]
+        let completes_inline = matches!(request, Request::Diagnostics(_));
+        #[cfg(feature = "volatile")]
+        let completes_inline = matches!(
+            request,
+            Request::Diagnostics(_) | Request::GetPartitionMap(_)
+        );
         match encode_request(self.inner, request) {
+            Ok(_) if completes_inline => {}
             Ok(_) => self.increment_outstanding_requests(),
             Err(e) => warn!("Failed to encode request because of {:?}", e),
         }
     }
+
+    /// Sends a NOOP over every key/value connection if the instance has been idle for longer
+    /// than the keepalive interval.
+    ///
+    /// Libcouchbase reconnects if the NOOP is not answered within the timeout. The ping is
+    /// sent without a cookie and not tracked as outstanding request, so it never keeps the
+    /// event loop busy.
+    fn send_keepalive(&mut self, keepalive: &KvKeepAlive) {
+        if self.last_activity.elapsed() < keepalive.interval || self.has_outstanding_requests() {
+            return;
+        }
+        self.last_activity = Instant::now();
+
+        let mut command: *mut lcb_CMDPING = ptr::null_mut();
+        unsafe {
+            let status = check_lcb_status(lcb_cmdping_create(&mut command))
+                .and_then(|_| check_lcb_status(lcb_cmdping_kv(command, 1)))
+                .and_then(|_| {
+                    check_lcb_status(lcb_cmdping_timeout(
+                        command,
+                        keepalive.timeout.as_micros() as u32,
+                    ))
+                })
+                .and_then(|_| check_lcb_status(lcb_ping(self.inner, ptr::null_mut(), command)));
+            if let Err(e) = status {
+                warn!("Failed to send kv keepalive because of {}", e);
+            }
+            lcb_cmdping_destroy(command);
+        }
+    }
+
+    /// Pings the endpoints of the instance once per probe interval, unless it is busy with
+    /// requests. Returns whether the probe was sent.
+    ///
+    /// The HTTP services are the same for every instance, so only one of them pings them. As
+    /// with the keepalive, the results are handed to `keepalive_callback`.
+    fn send_health_probe(&mut self, probe: &HealthProbe, kv: bool, http: bool) -> bool {
+        if !(kv || http)
+            || self.last_probe.elapsed() < probe.interval
+            || self.has_outstanding_requests()
+        {
+            return false;
+        }
+        self.last_probe = Instant::now();
+        self.last_activity = Instant::now();
+
+        let mut command: *mut lcb_CMDPING = ptr::null_mut();
+        unsafe {
+            let (kv, http) = (kv as c_int, http as c_int);
+            let status = check_lcb_status(lcb_cmdping_create(&mut command))
+                .and_then(|_| check_lcb_status(lcb_cmdping_kv(command, kv)))
+                .and_then(|_| check_lcb_status(lcb_cmdping_views(command, http)))
+                .and_then(|_| check_lcb_status(lcb_cmdping_query(command, http)))
+                .and_then(|_| check_lcb_status(lcb_cmdping_search(command, http)))
+                .and_then(|_| check_lcb_status(lcb_cmdping_analytics(command, http)))
+                .and_then(|_| {
+                    check_lcb_status(lcb_cmdping_timeout(
+                        command,
+                        probe.timeout.as_micros() as u32,
+                    ))
+                })
+                .and_then(|_| check_lcb_status(lcb_ping(self.inner, ptr::null_mut(), command)));
+            lcb_cmdping_destroy(command);
+            if let Err(e) = status {
+                warn!("Failed to send health probe because of {}", e);
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Keeps requests of the service away from the node while it is quarantined.
+    fn set_quarantine(&self, change: &QuarantineChange) {
+        let (_, hostname) = into_cstring(change.hostname.as_str());
+        let status = unsafe {
+            lcb_set_node_quarantine(
+                self.inner,
+                change.service,
+                hostname.as_ptr(),
+                change.quarantined as c_int,
+            )
+        };
+        if let Err(e) = check_lcb_status(status) {
+            warn!("Failed to quarantine node because of {}", e);
+        }
+    }
+
+    /// Adds a partition map watcher, which is sent the current map right away if there is one.
+    #[cfg(feature = "volatile")]
+    fn watch_partition_map(&mut self, sender: UnboundedSender<PartitionMap>) {
+        // Brings the existing watchers up to date first, so all of them have seen the same
+        // revision afterwards
+        self.notify_partition_watchers();
+        if let Some(map) = partition_map(self.inner) {
+            self.partition_revision = Some(map.revision());
+            if sender.unbounded_send(map).is_err() {
+                return;
+            }
+        }
+        self.partition_watchers.push(sender);
+    }
+
+    /// Sends the partition map to the watchers if its revision changed since the last time.
+    ///
+    /// Libcouchbase applies new configurations while ticking, so this is checked after every
+    /// tick. Only the revision is read for that, the map is only built once it changed.
+    /// Watchers whose stream has been dropped are removed.
+    #[cfg(feature = "volatile")]
+    fn notify_partition_watchers(&mut self) {
+        if self.partition_watchers.is_empty() {
+            return;
+        }
+        let revision = partition_revision(self.inner);
+        if revision.is_none() || revision == self.partition_revision {
+            return;
+        }
+        let map = match partition_map(self.inner) {
+            Some(map) => map,
+            None => return,
+        };
+        self.partition_revision = Some(map.revision());
+        self.partition_watchers
+            .retain(|w| w.unbounded_send(map.clone()).is_ok());
+    }
+
+    /// Cancels all in-flight streaming requests, failing or closing their streams.
+    ///
+    /// Libcouchbase never invokes the callback of a cancelled request, so the cookies are
+    /// reclaimed here instead.
+    fn cancel_streaming_requests(&mut self) {
+        let streams = unsafe {
+            let instance_cookie_ptr: *const c_void = lcb_get_cookie(self.inner);
+            let mut instance_cookie = Box::from_raw(instance_cookie_ptr as *mut InstanceCookie);
+            let streams = std::mem::take(&mut instance_cookie.streams);
+            Box::into_raw(instance_cookie);
+            streams
+        };
+
+        for (cookie, request) in streams {
+            debug!("Cancelling in-flight {:?} on shutdown", request);
+            unsafe {
+                match request {
+                    StreamingRequest::Query(handle) => {
+                        lcb_query_cancel(self.inner, handle);
+                        let mut cookie = Box::from_raw(cookie as *mut QueryCookie);
+                        if let Some(sender) = cookie.sender.take() {
+                            let _ = sender.send(Err(shutdown_error()));
+                        }
+                        cookie.rows_sender.close_channel();
+                    }
+                    StreamingRequest::Analytics(handle) => {
+                        lcb_analytics_cancel(self.inner, handle);
+                        let mut cookie = Box::from_raw(cookie as *mut AnalyticsCookie);
+                        if let Some(sender) = cookie.sender.take() {
+                            let _ = sender.send(Err(shutdown_error()));
+                        }
+                        cookie.rows_sender.close_channel();
+                    }
+                    StreamingRequest::Search(handle) => {
+                        lcb_search_cancel(self.inner, handle);
+                        let mut cookie = Box::from_raw(cookie as *mut SearchCookie);
+                        if let Some(sender) = cookie.sender.take() {
+                            let _ = sender.send(Err(shutdown_error()));
+                        }
+                        cookie.rows_sender.close_channel();
+                    }
+                }
+            }
+            decrement_outstanding_requests(self.inner);
+        }
+    }
 }
 
 impl Drop for LcbInstance {
     fn drop(&mut self) {
+        // Streaming requests can run until their timeout fires, so they are cancelled rather
+        // than waited for. The remaining requests complete or time out on their own, but
+        // cancelled requests are only done once their http response is drained, so the wait
+        // is bounded.
+        self.cancel_streaming_requests();
+        if self.has_outstanding_requests() {
+            let status = unsafe { lcb_wait_for(self.inner, SHUTDOWN_TIMEOUT.as_micros() as u32) };
+            if let Err(e) = check_lcb_status(status) {
+                warn!("Failed to complete outstanding requests on shutdown: {}", e);
+            }
+        }
+        health::forget(self.inner);
         unsafe {
-            lcb_wait(self.inner, lcb_WAITFLAGS_LCB_WAIT_DEFAULT);
             lcb_destroy(self.inner);
         }
     }
 }
 
+fn shutdown_error() -> CouchbaseError {
+    let mut ctx = ErrorContext::default();
+    ctx.insert(
+        "cause",
+        Value::String("The request was cancelled because the cluster is shutting down".into()),
+    );
+    CouchbaseError::RequestCanceled { ctx }
+}
+
+pub fn increment_outstanding_requests(instance: *mut lcb_INSTANCE) {
+    let mut instance_cookie = unsafe {
+        let instance_cookie_ptr: *const c_void = lcb_get_cookie(instance);
+        Box::from_raw(instance_cookie_ptr as *mut InstanceCookie)
+    };
+    instance_cookie.increment_outstanding();
+    Box::into_raw(instance_cookie);
+}
+
 pub fn decrement_outstanding_requests(instance: *mut lcb_INSTANCE) {
     let mut instance_cookie = unsafe {
         let instance_cookie_ptr: *const c_void = lcb_get_cookie(instance);
@@ -202,6 +566,67 @@ pub fn decrement_outstanding_requests(instance: *mut lcb_INSTANCE) {
     Box::into_raw(instance_cookie);
 }
 
+/// Records the outcome of bootstrapping the instance, reported through the bootstrap callback.
+pub fn set_bootstrap_status(instance: *mut lcb_INSTANCE, status: lcb_STATUS) {
+    let mut instance_cookie = unsafe {
+        let instance_cookie_ptr: *const c_void = lcb_get_cookie(instance);
+        Box::from_raw(instance_cookie_ptr as *mut InstanceCookie)
+    };
+    instance_cookie.bootstrap_status = Some(status);
+    Box::into_raw(instance_cookie);
+}
+
+/// Records the outcome of opening a bucket, reported through the open callback.
+pub fn set_open_status(instance: *mut lcb_INSTANCE, status: Option<lcb_STATUS>) {
+    let mut instance_cookie = unsafe {
+        let instance_cookie_ptr: *const c_void = lcb_get_cookie(instance);
+        Box::from_raw(instance_cookie_ptr as *mut InstanceCookie)
+    };
+    instance_cookie.open_status = status;
+    Box::into_raw(instance_cookie);
+}
+
+fn open_status(instance: *mut lcb_INSTANCE) -> Option<lcb_STATUS> {
+    let instance_cookie = unsafe {
+        let instance_cookie_ptr: *const c_void = lcb_get_cookie(instance);
+        Box::from_raw(instance_cookie_ptr as *mut InstanceCookie)
+    };
+    let status = instance_cookie.open_status;
+    Box::into_raw(instance_cookie);
+    status
+}
+
+/// Tracks a streaming request once it has been dispatched, until its final row arrived.
+pub fn register_streaming_request(
+    instance: *mut lcb_INSTANCE,
+    cookie: *mut c_void,
+    request: StreamingRequest,
+) {
+    let mut instance_cookie = unsafe {
+        let instance_cookie_ptr: *const c_void = lcb_get_cookie(instance);
+        Box::from_raw(instance_cookie_ptr as *mut InstanceCookie)
+    };
+    instance_cookie.streams.insert(cookie as usize, request);
+    Box::into_raw(instance_cookie);
+}
+
+pub fn unregister_streaming_request(instance: *mut lcb_INSTANCE, cookie: *mut c_void) {
+    let mut instance_cookie = unsafe {
+        let instance_cookie_ptr: *const c_void = lcb_get_cookie(instance);
+        Box::from_raw(instance_cookie_ptr as *mut InstanceCookie)
+    };
+    instance_cookie.streams.remove(&(cookie as usize));
+    Box::into_raw(instance_cookie);
+}
+
+/// The libcouchbase handle of an in-flight streaming http request.
+#[derive(Debug)]
+pub enum StreamingRequest {
+    Query(*mut lcb_QUERY_HANDLE),
+    Analytics(*mut lcb_ANALYTICS_HANDLE),
+    Search(*mut lcb_SEARCH_HANDLE),
+}
+
 /// A stateful cookie associated with a single instance.
 ///
 /// This cookie is available everywhere the instance is used, so it can
@@ -209,11 +634,22 @@ pub fn decrement_outstanding_requests(instance: *mut lcb_INSTANCE) {
 #[derive(Debug)]
 struct InstanceCookie {
     outstanding: usize,
+    // In-flight streaming requests, keyed by the address of their request cookie
+    streams: HashMap<usize, StreamingRequest>,
+    // The outcome of bootstrapping, once it completed
+    bootstrap_status: Option<lcb_STATUS>,
+    // The outcome of the last attempt to open a bucket, once it completed
+    open_status: Option<lcb_STATUS>,
 }
 
 impl InstanceCookie {
     pub fn new() -> Self {
-        Self { outstanding: 0 }
+        Self {
+            outstanding: 0,
+            streams: HashMap::new(),
+            bootstrap_status: None,
+            open_status: None,
+        }
     }
 
     pub fn increment_outstanding(&mut self) {
@@ -240,26 +676,144 @@ pub struct LcbInstances {
     global: Option<LcbInstance>,
     // All the instances that are already bound to a bucket
     bound: HashMap<String, LcbInstance>,
+    // The buckets which failed to open, until they are opened successfully
+    failed: HashMap<String, FailedOpen>,
+    keepalive: Option<KvKeepAlive>,
+}
+
+/// A bucket which could not be opened.
+///
+/// Requests against it fail right away with the reason, instead of each one waiting for its
+/// timeout, and opening it is only attempted again once the backoff has passed. The new attempt
+/// runs alongside the IO loop, so it never blocks the other buckets.
+struct FailedOpen {
+    status: lcb_STATUS,
+    connection_string: String,
+    credentials: Credentials,
+    attempts: u32,
+    retry_at: Instant,
+    reopen: Option<Reopen>,
+}
+
+/// An attempt to open a failed bucket again with a fresh instance.
+enum Reopen {
+    // The instance is bootstrapping
+    Connecting(LcbInstance),
+    // The instance bootstrapped and is opening the bucket
+    Opening(LcbInstance),
+}
+
+/// The delay before opening a bucket again, after it failed to open the given number of times.
+fn open_retry_delay(attempts: u32) -> Duration {
+    cmp::min(
+        OPEN_RETRY_MIN_DELAY * 2u32.saturating_pow(attempts.saturating_sub(1)),
+        OPEN_RETRY_MAX_DELAY,
+    )
 }
 
 impl LcbInstances {
     pub fn set_unbound(&mut self, instance: LcbInstance) {
+        health::quarantined()
+            .iter()
+            .for_each(|c| instance.set_quarantine(c));
         self.global = Some(instance);
     }
 
     pub fn set_bound(&mut self, bucket: String, instance: LcbInstance) {
+        health::quarantined()
+            .iter()
+            .for_each(|c| instance.set_quarantine(c));
         self.bound.insert(bucket, instance);
     }
 
-    pub fn has_unbound_instance(&self) -> bool {
-        self.global.is_some()
+    /// Binds the unbound instance to the bucket, or creates a new instance for it if there is
+    /// none, and remembers the bucket as failed if it could not be opened.
+    fn open_bucket(&mut self, name: String, connection_string: String, credentials: Credentials) {
+        let result = match self.global.take() {
+            Some(mut instance) => match instance.bind_to_bucket(name.clone()) {
+                Ok(()) => Ok(instance),
+                Err(status) => {
+                    // The failed instance is dropped, so cluster level requests get a new one
+                    drop(instance);
+                    match LcbInstance::new(connection_string.clone(), &credentials) {
+                        Ok(i) => self.set_unbound(i),
+                        Err(e) => warn!("Could not open libcouchbase instance {}", e),
+                    }
+                    Err(status)
+                }
+            },
+            None => LcbInstance::new(connection_string.clone(), &credentials)
+                .and_then(|mut i| i.bind_to_bucket(name.clone()).map(|_| i)),
+        };
+
+        match result {
+            Ok(instance) => {
+                self.failed.remove(&name);
+                self.set_bound(name, instance);
+            }
+            Err(status) => self.record_failed_open(name, status, connection_string, credentials),
+        }
     }
 
-    pub fn bind_unbound_to_bucket(&mut self, bucket: String) -> Result<(), lcb_STATUS> {
-        let mut instance = self.global.take().unwrap();
-        instance.bind_to_bucket(bucket.clone())?;
-        self.set_bound(bucket, instance);
-        Ok(())
+    /// Remembers the bucket as failed, backing off further with each failed attempt.
+    fn record_failed_open(
+        &mut self,
+        name: String,
+        status: lcb_STATUS,
+        connection_string: String,
+        credentials: Credentials,
+    ) {
+        let attempts = self.failed.get(&name).map_or(1, |f| f.attempts + 1);
+        let delay = open_retry_delay(attempts);
+        warn!(
+            "Could not open bucket {} (status: 0x{:x}), trying again in {:?}",
+            MetaData(&name),
+            status,
+            delay
+        );
+        self.failed.insert(
+            name,
+            FailedOpen {
+                status,
+                connection_string,
+                credentials,
+                attempts,
+                retry_at: Instant::now() + delay,
+                reopen: None,
+            },
+        );
+    }
+
+    /// Returns the reason the bucket failed to open, after starting to open it again if the
+    /// backoff has passed. Returns `None` if the bucket has not failed or is open now.
+    fn failed_open(&mut self, bucket: &str) -> Option<CouchbaseError> {
+        let failed = self.failed.get_mut(bucket)?;
+        if failed.reopen.is_none() && Instant::now() >= failed.retry_at {
+            debug!("Opening bucket {} again", MetaData(bucket));
+            match LcbInstance::connect(failed.connection_string.clone(), &failed.credentials) {
+                Ok(instance) => failed.reopen = Some(Reopen::Connecting(instance)),
+                Err(status) => {
+                    let connection_string = failed.connection_string.clone();
+                    let credentials = failed.credentials.clone();
+                    self.record_failed_open(bucket.into(), status, connection_string, credentials);
+                }
+            }
+        }
+
+        let failed = self.failed.get(bucket)?;
+        let mut ctx = ErrorContext::default();
+        ctx.insert("bucket", Value::String(bucket.into()));
+        ctx.insert("open_attempts", Value::from(failed.attempts));
+        ctx.insert(
+            "next_attempt_ms",
+            Value::from(
+                failed
+                    .retry_at
+                    .saturating_duration_since(Instant::now())
+                    .as_millis() as u64,
+            ),
+        );
+        Some(couchbase_error_from_lcb_status(failed.status, ctx))
     }
 
     pub fn have_outstanding_requests(&self) -> bool {
@@ -281,6 +835,10 @@ impl LcbInstances {
     pub fn handle_request(&mut self, request: IoRequest) -> Result<bool, lcb_STATUS> {
         match request {
             IoRequest::Data(r) => {
+                if let Some(e) = r.bucket().cloned().and_then(|b| self.failed_open(&b)) {
+                    r.fail(e);
+                    return Ok(false);
+                }
                 let instance = match r.bucket() {
                     Some(b) => self.bound.get_mut(b),
                     None => {
@@ -307,33 +865,128 @@ impl LcbInstances {
                     }
                 };
             }
+            IoRequest::SetRetryBudget { budget, meter } => retry::set_budget(budget, meter),
+            IoRequest::SetMaxRetries(max_retries) => retry::set_max_retries(max_retries),
+            IoRequest::SetKvKeepAlive(keepalive) => self.keepalive = Some(keepalive),
+            IoRequest::SetHealthProbe(probe) => health::set_probe(probe),
+            IoRequest::SetMeter(meter) => {
+                http_metrics::set_meter(meter.clone());
+                kv_metrics::set_meter(meter);
+                // Instances created from now on install the callback themselves
+                self.global
+                    .iter()
+                    .chain(self.bound.values())
+                    .for_each(|i| i.install_kv_dispatch_callback());
+            }
+            IoRequest::SetSlowKvSampling(percent) => kv_metrics::set_slow_sampling(percent),
+            IoRequest::SetSecurityEventListener(listener) => certificates::set_listener(listener),
+            IoRequest::SetCertificatePinning(pinning) => certificates::set_pinning(pinning),
+            IoRequest::SetClusterEventListener(listener) => events::set_listener(listener),
+            IoRequest::EmitClusterEvent(event) => events::emit(event),
             IoRequest::Shutdown => return Ok(true),
             IoRequest::OpenBucket {
                 name,
                 connection_string,
-                username,
-                password,
+                credentials,
             } => {
-                if !self.bound.contains_key(&name) {
-                    if self.has_unbound_instance() {
-                        self.bind_unbound_to_bucket(name)?
-                    } else {
-                        match LcbInstance::new(connection_string, username, password) {
-                            Ok(mut i) => {
-                                i.bind_to_bucket(name.clone())?;
-                                self.set_bound(name, i);
-                            }
-                            Err(e) => {
-                                warn!("Could not open libcouchbase bucket: {}", e);
-                            }
-                        }
-                    }
+                // A failed bucket is only opened again once its backoff has passed
+                if !self.bound.contains_key(&name) && !self.failed.contains_key(&name) {
+                    self.open_bucket(name, connection_string, credentials);
                 }
             }
         };
         Ok(false)
     }
 
+    /// Sends keepalives over the idle instances, if enabled.
+    ///
+    /// Only bucket bound instances hold key/value connections, so the unbound one is skipped.
+    pub fn send_keepalives(&mut self) {
+        if let Some(keepalive) = &self.keepalive {
+            for i in self.bound.values_mut() {
+                i.send_keepalive(keepalive);
+            }
+        }
+    }
+
+    /// Sends health probes over the idle instances, if enabled, and applies the quarantines
+    /// their results changed.
+    ///
+    /// Only bucket bound instances hold key/value connections, so the unbound one only pings
+    /// the HTTP services. Without it the first bound instance to send its probe pings them.
+    pub fn send_health_probes(&mut self) {
+        for change in health::take_changes() {
+            self.global
+                .iter()
+                .chain(self.bound.values())
+                .for_each(|i| i.set_quarantine(&change));
+        }
+
+        if let Some(probe) = health::probe() {
+            let mut http = self.global.is_none();
+            if let Some(i) = &mut self.global {
+                i.send_health_probe(&probe, false, true);
+            }
+            for i in self.bound.values_mut() {
+                if i.send_health_probe(&probe, true, http) {
+                    http = false;
+                }
+            }
+        }
+    }
+
+    /// Makes progress on the buckets which are opened again, binding the ones which opened
+    /// and backing off further on the ones which failed again.
+    #[allow(non_upper_case_globals)]
+    fn poll_reopens(&mut self) {
+        let reopening: Vec<String> = self
+            .failed
+            .iter()
+            .filter(|(_, f)| f.reopen.is_some())
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        for name in reopening {
+            let failed = match self.failed.get_mut(&name) {
+                Some(f) => f,
+                None => continue,
+            };
+            let outcome = match failed.reopen.take() {
+                Some(Reopen::Connecting(mut instance)) => {
+                    match instance.tick_nowait().map(|_| instance.bootstrap_status()) {
+                        Ok(None) => Ok(Some(Reopen::Connecting(instance))),
+                        Ok(Some(lcb_STATUS_LCB_SUCCESS)) => instance
+                            .start_open(&name)
+                            .map(|_| Some(Reopen::Opening(instance))),
+                        Ok(Some(status)) | Err(status) => Err(status),
+                    }
+                }
+                Some(Reopen::Opening(mut instance)) => {
+                    match instance.tick_nowait().map(|_| open_status(instance.inner)) {
+                        Ok(None) => Ok(Some(Reopen::Opening(instance))),
+                        Ok(Some(lcb_STATUS_LCB_SUCCESS)) => {
+                            debug!("Opened bucket {} again", MetaData(&name));
+                            self.failed.remove(&name);
+                            self.set_bound(name, instance);
+                            continue;
+                        }
+                        Ok(Some(status)) | Err(status) => Err(status),
+                    }
+                }
+                None => continue,
+            };
+
+            match outcome {
+                Ok(reopen) => failed.reopen = reopen,
+                Err(status) => {
+                    let connection_string = failed.connection_string.clone();
+                    let credentials = failed.credentials.clone();
+                    self.record_failed_open(name, status, connection_string, credentials);
+                }
+            }
+        }
+    }
+
     pub fn tick_nowait(&mut self) -> Result<(), lcb_STATUS> {
         if let Some(i) = &mut self.global {
             i.tick_nowait()?;
@@ -341,8 +994,11 @@ impl LcbInstances {
 
         for i in self.bound.values_mut() {
             i.tick_nowait()?;
+            #[cfg(feature = "volatile")]
+            i.notify_partition_watchers();
         }
 
+        self.poll_reopens();
         Ok(())
     }
 }
@@ -354,3 +1010,23 @@ fn check_lcb_status(status: lcb_STATUS) -> Result<(), lcb_STATUS> {
         _ => Err(status),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_retry_delay_doubles_up_to_the_maximum() {
+        assert_eq!(open_retry_delay(1), OPEN_RETRY_MIN_DELAY);
+        assert_eq!(open_retry_delay(2), OPEN_RETRY_MIN_DELAY * 2);
+        assert_eq!(open_retry_delay(3), OPEN_RETRY_MIN_DELAY * 4);
+        assert_eq!(open_retry_delay(10), OPEN_RETRY_MAX_DELAY);
+        assert_eq!(open_retry_delay(u32::MAX), OPEN_RETRY_MAX_DELAY);
+    }
+
+    #[test]
+    fn open_retry_delay_never_shrinks() {
+        let delays: Vec<Duration> = (1..40).map(open_retry_delay).collect();
+        assert!(delays.windows(2).all(|w| w[0] <= w[1]));
+    }
+}
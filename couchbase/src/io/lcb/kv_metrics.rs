@@ -0,0 +1,184 @@
+use crate::api::metrics::{KvPacketInfo, Meter};
+use couchbase_sys::*;
+use log::debug;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Below this many packets of an opcode nothing is sampled, the distribution is not known yet.
+const MIN_SAMPLES: u64 = 100;
+
+thread_local! {
+    // Like the http metrics, shared by all instances running on the thread of an IoCore.
+    static STATE: RefCell<Option<KvMetrics>> = RefCell::new(None);
+    static SLOW_PERCENT: Cell<f64> = Cell::new(0.0);
+}
+
+struct KvMetrics {
+    meter: Arc<dyn Meter>,
+    histograms: HashMap<u8, Histogram>,
+}
+
+/// Counts the latencies of one opcode in power of two buckets of microseconds.
+struct Histogram {
+    buckets: [u64; 65],
+    total: u64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: [0; 65],
+            total: 0,
+        }
+    }
+
+    fn record(&mut self, latency: Duration) -> usize {
+        let bucket = 64 - (latency.as_micros() as u64).leading_zeros() as usize;
+        self.buckets[bucket] += 1;
+        self.total += 1;
+        bucket
+    }
+
+    /// Whether the latencies in the bucket are among the slowest `percent` recorded so far.
+    ///
+    /// Since a bucket is either in or out, this errs on the side of sampling less.
+    fn is_slowest(&self, bucket: usize, percent: f64) -> bool {
+        if self.total < MIN_SAMPLES {
+            return false;
+        }
+        let allowed = (self.total as f64 * percent / 100.0) as u64;
+        self.buckets[bucket..].iter().sum::<u64>() <= allowed
+    }
+}
+
+pub fn set_meter(meter: Arc<dyn Meter>) {
+    STATE.with(|s| {
+        *s.borrow_mut() = Some(KvMetrics {
+            meter,
+            histograms: HashMap::new(),
+        })
+    });
+}
+
+pub fn set_slow_sampling(percent: f64) {
+    SLOW_PERCENT.with(|p| p.set(percent.clamp(0.0, 100.0)));
+}
+
+/// Whether a meter is set, only then the dispatch callback is installed.
+pub fn enabled() -> bool {
+    STATE.with(|s| s.borrow().is_some())
+}
+
+pub unsafe extern "C" fn kv_dispatch_callback(
+    _instance: *mut lcb_INSTANCE,
+    info: *const lcb_KVDISPATCH_INFO,
+) {
+    let info = &*info;
+    let latency = Duration::from_nanos(info.latency_ns);
+    let opcode_name = opcode_name(info.opcode);
+    STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        let state = match state.as_mut() {
+            Some(s) => s,
+            None => return,
+        };
+        state.meter.record_kv_latency(opcode_name, latency);
+
+        let percent = SLOW_PERCENT.with(|p| p.get());
+        let histogram = state
+            .histograms
+            .entry(info.opcode)
+            .or_insert_with(Histogram::new);
+        let bucket = histogram.record(latency);
+        if percent <= 0.0 || !histogram.is_slowest(bucket, percent) {
+            return;
+        }
+
+        let packet = KvPacketInfo {
+            opcode: info.opcode,
+            opcode_name,
+            status: if info.rc == lcb_STATUS_LCB_SUCCESS || info.response_size > 0 {
+                Some(info.status)
+            } else {
+                None
+            },
+            opaque: info.opaque,
+            partition: info.vbucket,
+            key: String::from_utf8_lossy(std::slice::from_raw_parts(
+                info.key as *const u8,
+                info.nkey,
+            ))
+            .into_owned(),
+            cas: info.cas,
+            request_bytes: info.request_size,
+            response_bytes: info.response_size,
+            latency,
+            server_duration: match info.server_duration_us {
+                0 => None,
+                us => Some(Duration::from_micros(us)),
+            },
+            remote: match (to_str(info.host), to_str(info.port)) {
+                (Some(h), Some(p)) => Some(format!("{}:{}", h, p)),
+                _ => None,
+            },
+        };
+        debug!(target: "couchbase::slow_kv", "Slow key/value packet: {:?}", packet);
+        state.meter.record_slow_kv(&packet);
+    });
+}
+
+unsafe fn to_str<'a>(value: *const c_char) -> Option<&'a str> {
+    if value.is_null() {
+        None
+    } else {
+        CStr::from_ptr(value).to_str().ok()
+    }
+}
+
+fn opcode_name(opcode: u8) -> &'static str {
+    match opcode {
+        0x00 => "get",
+        0x01 => "set",
+        0x02 => "add",
+        0x03 => "replace",
+        0x04 => "delete",
+        0x05 => "increment",
+        0x06 => "decrement",
+        0x0a => "noop",
+        0x0e => "append",
+        0x0f => "prepend",
+        0x10 => "stat",
+        0x1c => "touch",
+        0x1d => "get_and_touch",
+        0x1f => "hello",
+        0x83 => "get_replica",
+        0x89 => "select_bucket",
+        0x91 => "observe_seqno",
+        0x92 => "observe",
+        0x94 => "get_and_lock",
+        0x95 => "unlock",
+        0xb5 => "get_cluster_config",
+        0xba => "get_collections_manifest",
+        0xbb => "get_collection_id",
+        0xc5 => "subdoc_get",
+        0xc6 => "subdoc_exists",
+        0xc7 => "subdoc_dict_add",
+        0xc8 => "subdoc_dict_upsert",
+        0xc9 => "subdoc_delete",
+        0xca => "subdoc_replace",
+        0xcb => "subdoc_array_push_last",
+        0xcc => "subdoc_array_push_first",
+        0xcd => "subdoc_array_insert",
+        0xce => "subdoc_array_add_unique",
+        0xcf => "subdoc_counter",
+        0xd0 => "subdoc_multi_lookup",
+        0xd1 => "subdoc_multi_mutation",
+        0xd2 => "subdoc_get_count",
+        0xfe => "get_error_map",
+        _ => "unknown",
+    }
+}
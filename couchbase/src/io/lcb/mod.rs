@@ -1,16 +1,33 @@
+mod auth;
 mod callbacks;
+mod certificates;
+mod durability;
 mod encode;
+mod events;
+mod health;
+mod http_metrics;
 mod instance;
+mod kv_metrics;
+#[cfg(feature = "packet-dump")]
+mod packets;
+mod retry;
 
 use crate::api::error::CouchbaseResult;
+use crate::api::events::{ClusterEvent, ClusterEventListener};
+use crate::api::metrics::Meter;
+use crate::api::options::{HealthProbe, KvKeepAlive, RetryBudget};
 use crate::api::results::{
-    AnalyticsMetaData, AnalyticsResult, GenericManagementResult, QueryMetaData, QueryResult,
-    SearchMetaData, SearchResult,
+    AnalyticsMetaData, AnalyticsResult, GenericManagementResult, LookupInReplicaResult,
+    LookupInResult, QueryMetaData, QueryResult, RowBuffer, SearchMetaData, SearchResult,
+    ServiceType,
 };
+use crate::api::security::SecurityEventListener;
+use crate::api::Capability;
 
 use encode::EncodeFailure;
 
 use crate::io::request::Request;
+use crate::io::Credentials;
 use instance::{LcbInstance, LcbInstances};
 
 use couchbase_sys::*;
@@ -19,34 +36,70 @@ use crossbeam_channel::{unbounded, Receiver, Sender};
 use log::{debug, warn};
 use std::ffi::CStr;
 use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
 use std::thread::JoinHandle;
 use std::time::Duration;
 use std::{ptr, thread};
 
+/// Everything the libcouchbase backend is able to dispatch.
+const CAPABILITIES: &[Capability] = &[
+    Capability::KeyValue,
+    Capability::SubDocument,
+    Capability::ReplicaReads,
+    Capability::Query,
+    Capability::Analytics,
+    Capability::Search,
+    Capability::Management,
+    Capability::Diagnostics,
+    Capability::KvStats,
+    Capability::HttpPatch,
+    Capability::ReplicaSubDocument,
+];
+
+/// The timeouts libcouchbase applies to requests which do not set their own.
+pub const DEFAULT_TIMEOUTS: &[(ServiceType, Duration)] = &[
+    (ServiceType::KeyValue, Duration::from_millis(2500)),
+    (ServiceType::Views, Duration::from_millis(75000)),
+    (ServiceType::Query, Duration::from_millis(75000)),
+    (ServiceType::Search, Duration::from_millis(75000)),
+    (ServiceType::Analytics, Duration::from_millis(75000)),
+    (ServiceType::Management, Duration::from_millis(75000)),
+];
+
+/// The version of the linked libcouchbase library.
+pub fn backend_version() -> String {
+    unsafe { CStr::from_ptr(lcb_get_version(ptr::null_mut())) }
+        .to_string_lossy()
+        .into_owned()
+}
+
 pub struct IoCore {
     thread_handle: Option<JoinHandle<()>>,
     queue_tx: Sender<IoRequest>,
     connection_string: String,
-    username: String,
-    password: String,
+    credentials: Credentials,
 }
 
 impl IoCore {
-    pub fn new(connection_string: String, username: String, password: String) -> Self {
+    pub fn new(
+        connection_string: String,
+        credentials: Credentials,
+        config_generation: Arc<AtomicU64>,
+    ) -> Self {
         debug!("Using libcouchbase IO transport");
 
         let (queue_tx, queue_rx) = unbounded();
 
         let cstring = connection_string.clone();
-        let uname = username.clone();
-        let pwd = password.clone();
-        let thread_handle = thread::spawn(move || run_lcb_loop(queue_rx, cstring, uname, pwd));
+        let creds = credentials.clone();
+        let thread_handle =
+            thread::spawn(move || run_lcb_loop(queue_rx, cstring, creds, config_generation));
         Self {
             thread_handle: Some(thread_handle),
             queue_tx,
             connection_string,
-            username,
-            password,
+            credentials,
         }
     }
 
@@ -61,11 +114,79 @@ impl IoCore {
             .send(IoRequest::OpenBucket {
                 name,
                 connection_string: self.connection_string.clone(),
-                username: self.username.clone(),
-                password: self.password.clone(),
+                credentials: self.credentials.clone(),
             })
             .expect("Could not send open bucket request")
     }
+
+    pub fn set_retry_budget(&self, budget: RetryBudget, meter: Arc<dyn Meter>) {
+        self.queue_tx
+            .send(IoRequest::SetRetryBudget { budget, meter })
+            .expect("Could not send retry budget request")
+    }
+
+    pub fn set_max_retries(&self, max_retries: u32) {
+        self.queue_tx
+            .send(IoRequest::SetMaxRetries(max_retries))
+            .expect("Could not send max retries request")
+    }
+
+    pub fn set_kv_keepalive(&self, kv_keepalive: KvKeepAlive) {
+        self.queue_tx
+            .send(IoRequest::SetKvKeepAlive(kv_keepalive))
+            .expect("Could not send kv keepalive request")
+    }
+
+    pub fn set_health_probe(&self, health_probe: HealthProbe) {
+        self.queue_tx
+            .send(IoRequest::SetHealthProbe(health_probe))
+            .expect("Could not send health probe request")
+    }
+
+    pub fn set_meter(&self, meter: Arc<dyn Meter>) {
+        self.queue_tx
+            .send(IoRequest::SetMeter(meter))
+            .expect("Could not send meter request")
+    }
+
+    pub fn set_slow_kv_sampling(&self, percent: f64) {
+        self.queue_tx
+            .send(IoRequest::SetSlowKvSampling(percent))
+            .expect("Could not send slow kv sampling request")
+    }
+
+    pub fn set_security_event_listener(&self, listener: Arc<dyn SecurityEventListener>) {
+        self.queue_tx
+            .send(IoRequest::SetSecurityEventListener(listener))
+            .expect("Could not send security event listener request")
+    }
+
+    pub fn set_certificate_pinning(&self, pinning: bool) {
+        self.queue_tx
+            .send(IoRequest::SetCertificatePinning(pinning))
+            .expect("Could not send certificate pinning request")
+    }
+
+    pub fn set_cluster_event_listener(&self, listener: Arc<dyn ClusterEventListener>) {
+        self.queue_tx
+            .send(IoRequest::SetClusterEventListener(listener))
+            .expect("Could not send cluster event listener request")
+    }
+
+    pub fn emit_cluster_event(&self, event: ClusterEvent) {
+        self.queue_tx
+            .send(IoRequest::EmitClusterEvent(event))
+            .expect("Could not send cluster event request")
+    }
+
+    pub fn capabilities(&self) -> &'static [Capability] {
+        CAPABILITIES
+    }
+
+    #[cfg(feature = "packet-dump")]
+    pub fn set_packet_dump_sampling(&self, every: usize) {
+        packets::set_sampling(every)
+    }
 }
 
 impl Drop for IoCore {
@@ -86,18 +207,23 @@ impl Drop for IoCore {
 fn run_lcb_loop(
     queue_rx: Receiver<IoRequest>,
     connection_string: String,
-    username: String,
-    password: String,
+    credentials: Credentials,
+    config_generation: Arc<AtomicU64>,
 ) {
+    events::set_config_generation(config_generation);
     let mut instances = LcbInstances::default();
 
-    match LcbInstance::new(
-        connection_string.into_bytes(),
-        username.into_bytes(),
-        password.into_bytes(),
-    ) {
+    match LcbInstance::new(connection_string.into_bytes(), &credentials) {
         Ok(i) => instances.set_unbound(i),
-        Err(e) => warn!("Could not open libcouchbase instance {}", e),
+        Err(e) => {
+            warn!("Could not open libcouchbase instance {}", e);
+            events::emit(ClusterEvent::BootstrapFailed {
+                bucket: None,
+                reason: unsafe { CStr::from_ptr(lcb_strerror_short(e)) }
+                    .to_string_lossy()
+                    .into_owned(),
+            });
+        }
     };
 
     'running: loop {
@@ -125,6 +251,8 @@ fn run_lcb_loop(
             }
         }
 
+        instances.send_keepalives();
+        instances.send_health_probes();
         instances.tick_nowait().unwrap();
     }
 }
@@ -169,29 +297,53 @@ pub enum IoRequest {
     OpenBucket {
         name: String,
         connection_string: String,
-        username: String,
-        password: String,
+        credentials: Credentials,
     },
+    SetRetryBudget {
+        budget: RetryBudget,
+        meter: Arc<dyn Meter>,
+    },
+    SetMaxRetries(u32),
+    SetKvKeepAlive(KvKeepAlive),
+    SetHealthProbe(HealthProbe),
+    SetMeter(Arc<dyn Meter>),
+    SetSlowKvSampling(f64),
+    SetSecurityEventListener(Arc<dyn SecurityEventListener>),
+    SetCertificatePinning(bool),
+    SetClusterEventListener(Arc<dyn ClusterEventListener>),
+    EmitClusterEvent(ClusterEvent),
     Shutdown,
 }
 
 fn encode_request(instance: *mut lcb_INSTANCE, request: Request) -> Result<(), EncodeFailure> {
     match request {
         Request::Get(r) => encode::encode_get(instance, r)?,
+        Request::GetReplica(r) => encode::encode_get_replica(instance, r)?,
         Request::Query(r) => encode::encode_query(instance, r)?,
         Request::Analytics(r) => encode::encode_analytics(instance, r)?,
         Request::Search(r) => encode::encode_search(instance, r)?,
         Request::Mutate(r) => encode::encode_mutate(instance, r)?,
         Request::Exists(r) => encode::encode_exists(instance, r)?,
+        Request::Unlock(r) => encode::encode_unlock(instance, r)?,
+        Request::Touch(r) => encode::encode_touch(instance, r)?,
         Request::Remove(r) => encode::encode_remove(instance, r)?,
         Request::LookupIn(r) => encode::encode_lookup_in(instance, r)?,
+        Request::LookupInReplica(r) => encode::encode_lookup_in_replica(instance, r)?,
         Request::MutateIn(r) => encode::encode_mutate_in(instance, r)?,
         Request::GenericManagementRequest(r) => {
             encode::encode_generic_management_request(instance, r)?
         }
         #[cfg(feature = "volatile")]
         Request::KvStatsRequest(r) => encode::encode_kv_stats(instance, r)?,
+        #[cfg(feature = "volatile")]
+        Request::GetCollectionId(r) => encode::encode_get_collection_id(instance, r)?,
+        #[cfg(feature = "volatile")]
+        Request::GetPartitionMap(r) => encode::encode_get_partition_map(instance, r)?,
+        // Watches are registered on the instance itself, see `LcbInstance::handle_request`
+        #[cfg(feature = "volatile")]
+        Request::WatchPartitionMap(_) => unreachable!(),
         Request::Ping(r) => encode::encode_ping(instance, r)?,
+        Request::Diagnostics(r) => encode::encode_diagnostics(instance, r)?,
         Request::Counter(r) => encode::encode_counter(instance, r)?,
     }
 
@@ -204,6 +356,8 @@ struct QueryCookie {
     rows_receiver: Option<futures::channel::mpsc::UnboundedReceiver<Vec<u8>>>,
     meta_sender: futures::channel::oneshot::Sender<QueryMetaData>,
     meta_receiver: Option<futures::channel::oneshot::Receiver<QueryMetaData>>,
+    buffer: Arc<RowBuffer>,
+    handle: *mut lcb_QUERY_HANDLE,
 }
 
 struct AnalyticsCookie {
@@ -212,6 +366,7 @@ struct AnalyticsCookie {
     rows_receiver: Option<futures::channel::mpsc::UnboundedReceiver<Vec<u8>>>,
     meta_sender: futures::channel::oneshot::Sender<AnalyticsMetaData>,
     meta_receiver: Option<futures::channel::oneshot::Receiver<AnalyticsMetaData>>,
+    handle: *mut lcb_ANALYTICS_HANDLE,
 }
 
 struct SearchCookie {
@@ -220,6 +375,7 @@ struct SearchCookie {
     rows_receiver: Option<futures::channel::mpsc::UnboundedReceiver<Vec<u8>>>,
     meta_sender: futures::channel::oneshot::Sender<SearchMetaData>,
     meta_receiver: Option<futures::channel::oneshot::Receiver<SearchMetaData>>,
+    handle: *mut lcb_SEARCH_HANDLE,
 }
 
 /// This cookie can represent all different generic http requestes fired against lcb.
@@ -231,6 +387,19 @@ enum HttpCookie {
     },
 }
 
+/// The cookie of a sub-document lookup, which is either sent to the active node or to one of
+/// the replicas.
+///
+/// Both answer through the same libcouchbase callback, so an enum is needed to tell them apart.
+enum LookupInCookie {
+    Active {
+        sender: futures::channel::oneshot::Sender<CouchbaseResult<LookupInResult>>,
+    },
+    Replica {
+        sender: futures::channel::mpsc::UnboundedSender<CouchbaseResult<LookupInReplicaResult>>,
+    },
+}
+
 #[cfg(feature = "volatile")]
 struct KvStatsCookie {
     sender: Option<
@@ -0,0 +1,284 @@
+use log::trace;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Only every nth packet is dumped.
+static SAMPLING: AtomicUsize = AtomicUsize::new(1);
+static SEEN: AtomicUsize = AtomicUsize::new(0);
+
+const HEADER_LEN: usize = 24;
+const MAGIC_ALT_REQUEST: u8 = 0x08;
+const MAGIC_ALT_RESPONSE: u8 = 0x18;
+
+type Header = [u8; HEADER_LEN];
+
+thread_local! {
+    // The sent packets which continue in the next write, by connection
+    static SENDING: RefCell<HashMap<String, Reassembly>> = RefCell::new(HashMap::new());
+}
+
+pub fn set_sampling(every: usize) {
+    SAMPLING.store(every.max(1), Ordering::Relaxed);
+}
+
+/// Logs the packets of a libcouchbase packet dump, returning false for all other messages.
+///
+/// Libcouchbase logs the raw bytes base64 encoded, which are decoded into their headers here.
+/// Keys and values are never logged, only their lengths, so the dumps can be shared without
+/// leaking document contents. The connection prefix of the message is kept to tell the
+/// connections apart.
+///
+/// Sent packets are dumped once when they are queued and once when they are written to the
+/// socket, so only the writes are logged. A write may end in the middle of a packet, so the
+/// sent bytes are followed across writes. Received bytes are dumped again until the packet
+/// they end in is complete, so only complete packets are logged.
+pub fn log_packet(message: &str) -> bool {
+    let start = match message.find("pkt,") {
+        Some(s) => s,
+        None => return false,
+    };
+    let (connection, dump) = message.split_at(start);
+    // Queued packets are dumped again as they are written to the socket
+    if dump.starts_with("pkt,snd,") {
+        return true;
+    }
+    let direction = if dump.starts_with("pkt,snd:") {
+        "snd"
+    } else if dump.starts_with("pkt,rcv:") {
+        "rcv"
+    } else {
+        return false;
+    };
+
+    // The message may be cut off by the logger, which only loses the tail of the last packet
+    let encoded = dump.rsplit(", ").next().unwrap_or_default();
+    let truncated = encoded.len() < dumped_len(dump);
+    let encoded = &encoded[..encoded.len() - encoded.len() % 4];
+    let bytes = match base64::decode(encoded) {
+        Ok(b) => b,
+        Err(e) => {
+            trace!(target: "couchbase::packets", "{}{}: undecodable dump ({})", connection, direction, e);
+            return true;
+        }
+    };
+
+    let headers = if direction == "snd" {
+        SENDING.with(|s| {
+            let mut sending = s.borrow_mut();
+            let reassembly = sending.entry(connection.into()).or_default();
+            let headers = reassembly.feed(&bytes);
+            // Without the tail the next write cannot be followed, it is assumed to start a packet
+            if truncated || reassembly.is_idle() {
+                sending.remove(connection);
+            }
+            headers
+        })
+    } else {
+        complete_headers(&bytes, !truncated)
+    };
+
+    for header in headers {
+        let seen = SEEN.fetch_add(1, Ordering::Relaxed);
+        if seen.is_multiple_of(SAMPLING.load(Ordering::Relaxed)) {
+            trace!(target: "couchbase::packets", "{}{}: {}", connection, direction, describe(&header));
+        }
+    }
+    true
+}
+
+/// The length of the base64 encoded bytes libcouchbase dumped, before the logger cut it off.
+fn dumped_len(dump: &str) -> usize {
+    dump.split("size=")
+        .nth(1)
+        .and_then(|s| s.split(',').next())
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Follows the packets sent over a connection across the writes they are split into.
+#[derive(Debug, Default)]
+struct Reassembly {
+    // The start of a header which continues in the next write
+    partial: Vec<u8>,
+    // How much of the current packet is still to come after its header
+    remaining: usize,
+}
+
+impl Reassembly {
+    /// Returns the headers completed by the written bytes.
+    fn feed(&mut self, mut bytes: &[u8]) -> Vec<Header> {
+        let mut headers = vec![];
+        loop {
+            let skipped = self.remaining.min(bytes.len());
+            self.remaining -= skipped;
+            bytes = &bytes[skipped..];
+
+            let wanted = HEADER_LEN - self.partial.len();
+            if bytes.len() < wanted {
+                self.partial.extend_from_slice(bytes);
+                return headers;
+            }
+            self.partial.extend_from_slice(&bytes[..wanted]);
+            bytes = &bytes[wanted..];
+
+            let header: Header = self.partial[..].try_into().unwrap();
+            self.partial.clear();
+            self.remaining = body_len(&header);
+            headers.push(header);
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.partial.is_empty() && self.remaining == 0
+    }
+}
+
+/// Returns the headers of the packets received in full, from bytes which start with a packet.
+///
+/// If the bytes are cut off, the packets which reach into the missing tail are assumed to be
+/// complete, since they cannot be told apart from the ones which are not.
+fn complete_headers(bytes: &[u8], whole: bool) -> Vec<Header> {
+    let mut headers = vec![];
+    let mut offset = 0;
+    while bytes.len() >= offset + HEADER_LEN {
+        let header: Header = bytes[offset..offset + HEADER_LEN].try_into().unwrap();
+        offset += HEADER_LEN + body_len(&header);
+        if whole && offset > bytes.len() {
+            break;
+        }
+        headers.push(header);
+    }
+    headers
+}
+
+fn u16_at(header: &Header, at: usize) -> u16 {
+    u16::from_be_bytes(header[at..at + 2].try_into().unwrap())
+}
+
+fn u32_at(header: &Header, at: usize) -> u32 {
+    u32::from_be_bytes(header[at..at + 4].try_into().unwrap())
+}
+
+fn body_len(header: &Header) -> usize {
+    u32_at(header, 8) as usize
+}
+
+fn describe(header: &Header) -> String {
+    let magic = header[0];
+    // The alternative encoding carries flexible framing extras and has a single byte key length
+    let (framing_len, key_len) = match magic {
+        MAGIC_ALT_REQUEST | MAGIC_ALT_RESPONSE => (header[2] as usize, header[3] as usize),
+        _ => (0, u16_at(header, 2) as usize),
+    };
+    let extras_len = header[4] as usize;
+    let body_len = body_len(header);
+    format!(
+        "magic=0x{:02x} opcode=0x{:02x} datatype=0x{:02x} vbucket_or_status=0x{:04x} opaque=0x{:08x} cas=0x{:016x} framing_len={} extras_len={} key_len={} value_len={}",
+        magic,
+        header[1],
+        header[5],
+        u16_at(header, 6),
+        u32_at(header, 12),
+        u64::from_be_bytes(header[16..24].try_into().unwrap()),
+        framing_len,
+        extras_len,
+        key_len,
+        body_len.saturating_sub(framing_len + extras_len + key_len),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(magic: u8, framing_len: u8, key_len: u16, extras_len: u8, value_len: u32) -> Vec<u8> {
+        let mut header = vec![magic, 0x01];
+        if framing_len > 0 {
+            header.extend_from_slice(&[framing_len, key_len as u8]);
+        } else {
+            header.extend_from_slice(&key_len.to_be_bytes());
+        }
+        header.extend_from_slice(&[extras_len, 0x01]);
+        header.extend_from_slice(&0x0203u16.to_be_bytes());
+        let body_len = framing_len as u32 + extras_len as u32 + key_len as u32 + value_len;
+        header.extend_from_slice(&body_len.to_be_bytes());
+        header.extend_from_slice(&0xcafe_babeu32.to_be_bytes());
+        header.extend_from_slice(&0x1122_3344_5566_7788u64.to_be_bytes());
+        header.resize(HEADER_LEN + body_len as usize, 0xff);
+        header
+    }
+
+    fn header(packet: &[u8]) -> Header {
+        packet[..HEADER_LEN].try_into().unwrap()
+    }
+
+    #[test]
+    fn describes_classic_packets() {
+        let request = packet(0x80, 0, 300, 4, 10);
+        assert_eq!(
+            describe(&header(&request)),
+            "magic=0x80 opcode=0x01 datatype=0x01 vbucket_or_status=0x0203 opaque=0xcafebabe cas=0x1122334455667788 framing_len=0 extras_len=4 key_len=300 value_len=10"
+        );
+    }
+
+    #[test]
+    fn describes_alternative_packets() {
+        let request = packet(MAGIC_ALT_REQUEST, 3, 5, 4, 10);
+        assert_eq!(
+            describe(&header(&request)),
+            "magic=0x08 opcode=0x01 datatype=0x01 vbucket_or_status=0x0203 opaque=0xcafebabe cas=0x1122334455667788 framing_len=3 extras_len=4 key_len=5 value_len=10"
+        );
+        let response = packet(MAGIC_ALT_RESPONSE, 2, 0, 0, 7);
+        assert!(describe(&header(&response))
+            .ends_with("framing_len=2 extras_len=0 key_len=0 value_len=7"));
+    }
+
+    #[test]
+    fn follows_sent_packets_across_writes() {
+        let first = packet(0x80, 0, 3, 0, 40);
+        let second = packet(MAGIC_ALT_REQUEST, 2, 3, 0, 1);
+        let bytes: Vec<u8> = first.iter().chain(second.iter()).cloned().collect();
+
+        let mut reassembly = Reassembly::default();
+        // The first write ends within the body of the first packet
+        assert_eq!(reassembly.feed(&bytes[..30]), vec![header(&first)]);
+        // The second one ends within the header of the second packet
+        assert!(reassembly.feed(&bytes[30..first.len() + 10]).is_empty());
+        assert!(!reassembly.is_idle());
+        assert_eq!(
+            reassembly.feed(&bytes[first.len() + 10..]),
+            vec![header(&second)]
+        );
+        assert!(reassembly.is_idle());
+    }
+
+    #[test]
+    fn logs_only_complete_received_packets() {
+        let first = packet(0x81, 0, 0, 4, 10);
+        let second = packet(0x81, 0, 0, 4, 100);
+        let bytes: Vec<u8> = first.iter().chain(second.iter()).cloned().collect();
+
+        assert_eq!(
+            complete_headers(&bytes, true),
+            vec![header(&first), header(&second)]
+        );
+        // The second packet is dumped again once the rest of it arrived
+        assert_eq!(
+            complete_headers(&bytes[..first.len() + 30], true),
+            vec![header(&first)]
+        );
+        // Unless the dump was cut off, which hides whether it is complete
+        assert_eq!(
+            complete_headers(&bytes[..first.len() + 30], false),
+            vec![header(&first), header(&second)]
+        );
+    }
+
+    #[test]
+    fn reads_the_dumped_length() {
+        assert_eq!(dumped_len("pkt,rcv: size=32, gAEAAA=="), 32);
+        assert_eq!(dumped_len("pkt,rcv: garbage"), 0);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::api::events::ClusterEvent;
+use crate::api::metrics::Meter;
+use crate::api::options::RetryBudget;
+use crate::api::results::ServiceType;
+use crate::api::retry::{RetryReason, RetryRequest, RetryStrategy};
+use crate::io::lcb::events;
+use couchbase_sys::*;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::os::raw::c_void;
+use std::sync::Arc;
+use std::time::Duration;
+
+thread_local! {
+    // Every IoCore runs its instances on a dedicated thread, which makes the budget shared
+    // across all buckets of a cluster without any locking.
+    static BUDGET: RefCell<Option<TokenBucket>> = RefCell::new(None);
+    // The strategies and caps passed through the options, by the cookie of their operation
+    static OPERATIONS: RefCell<HashMap<usize, OperationRetries>> = RefCell::new(HashMap::new());
+    // The cap set through `ClusterOptions::max_retries`
+    static MAX_RETRIES: Cell<Option<u32>> = Cell::new(None);
+    // Why the retry of an operation was denied and after how many attempts, by its cookie
+    static DENIED: RefCell<HashMap<usize, (&'static str, u32)>> = RefCell::new(HashMap::new());
+}
+
+/// How the retries of a single operation are decided.
+struct OperationRetries {
+    strategy: Option<Arc<dyn RetryStrategy>>,
+    max_retries: Option<u32>,
+}
+
+/// The state of a `RetryBudget`.
+struct TokenBucket {
+    tokens: f64,
+    max_tokens: f64,
+    deposit: f64,
+    meter: Arc<dyn Meter>,
+}
+
+pub fn set_budget(budget: RetryBudget, meter: Arc<dyn Meter>) {
+    let max_tokens = budget.max_tokens as f64;
+    BUDGET.with(|b| {
+        *b.borrow_mut() = Some(TokenBucket {
+            tokens: max_tokens,
+            max_tokens,
+            deposit: budget.percent_can_retry,
+            meter,
+        })
+    });
+}
+
+pub fn set_max_retries(max_retries: u32) {
+    MAX_RETRIES.with(|m| m.set(Some(max_retries)));
+}
+
+/// Adds to the budget once a key/value operation completed successfully.
+pub fn deposit(status: lcb_STATUS) {
+    if status != lcb_STATUS_LCB_SUCCESS {
+        return;
+    }
+    BUDGET.with(|b| {
+        if let Some(bucket) = b.borrow_mut().as_mut() {
+            bucket.tokens = (bucket.tokens + bucket.deposit).min(bucket.max_tokens);
+        }
+    });
+}
+
+/// Returns why the operation behind the cookie was not retried and after how many attempts,
+/// if it failed because the budget or its cap ran out.
+pub fn take_denied(cookie: *mut c_void) -> Option<(&'static str, u32)> {
+    DENIED.with(|d| {
+        let mut denied = d.borrow_mut();
+        if denied.is_empty() {
+            return None;
+        }
+        denied.remove(&(cookie as usize))
+    })
+}
+
+/// Uses the strategy and cap for the retries of the operation behind the cookie.
+///
+/// Must be called after the operation has been scheduled, so it is not left behind if
+/// scheduling failed.
+pub fn register(
+    cookie: *mut c_void,
+    strategy: Option<Arc<dyn RetryStrategy>>,
+    max_retries: Option<u32>,
+) {
+    if strategy.is_some() || max_retries.is_some() {
+        OPERATIONS.with(|o| {
+            o.borrow_mut().insert(
+                cookie as usize,
+                OperationRetries {
+                    strategy,
+                    max_retries,
+                },
+            )
+        });
+    }
+}
+
+/// Drops the strategy of the operation behind the cookie once it completed.
+pub fn forget(cookie: *mut c_void) {
+    OPERATIONS.with(|o| {
+        let mut operations = o.borrow_mut();
+        if !operations.is_empty() {
+            operations.remove(&(cookie as usize));
+        }
+    });
+}
+
+/// Withdraws a token for the retry, or returns false if there is none left.
+fn withdraw(cookie: *mut c_void, attempts: u32) -> bool {
+    let withdrawn = BUDGET.with(|b| match b.borrow_mut().as_mut() {
+        Some(bucket) => {
+            if bucket.tokens >= 1.0 {
+                bucket.tokens -= 1.0;
+                true
+            } else {
+                bucket
+                    .meter
+                    .record_retry_budget_exhausted(ServiceType::KeyValue);
+                false
+            }
+        }
+        None => true,
+    });
+    if !withdrawn {
+        deny(cookie, "RetryBudgetExhausted", attempts);
+    }
+    withdrawn
+}
+
+fn deny(cookie: *mut c_void, reason: &'static str, attempts: u32) {
+    DENIED.with(|d| d.borrow_mut().insert(cookie as usize, (reason, attempts)));
+}
+
+/// The retry strategy installed on every instance.
+///
+/// Asks the strategy of the operation if it has one, otherwise decides like the libcouchbase
+/// best effort strategy. Either way the retry is only let through if the operation has not
+/// reached its cap and the budget allows for it. Retries which are always required (like not
+/// my vbucket) are decided by libcouchbase itself and never reach this function.
+pub unsafe extern "C" fn retry_strategy(
+    req: *mut lcb_RETRY_REQUEST,
+    reason: lcb_RETRY_REASON,
+) -> lcb_RETRY_ACTION {
+    let mut action = lcb_RETRY_ACTION {
+        should_retry: 0,
+        retry_after_ms: 0,
+    };
+    let cookie = lcb_retry_request_operation_cookie(req);
+    let attempts = lcb_retry_request_retry_attempts(req).max(0) as u32;
+    let (strategy, max_retries) = OPERATIONS.with(|o| match o.borrow().get(&(cookie as usize)) {
+        Some(operation) => (operation.strategy.clone(), operation.max_retries),
+        None => (None, None),
+    });
+    if let Some(max_retries) = max_retries.or_else(|| MAX_RETRIES.with(|m| m.get())) {
+        if attempts >= max_retries {
+            deny(cookie, "MaxRetriesReached", attempts);
+            return action;
+        }
+    }
+    match strategy {
+        Some(strategy) => {
+            let request = RetryRequest {
+                attempts,
+                idempotent: lcb_retry_request_is_idempotent(req) != 0,
+            };
+            match strategy
+                .retry_after(&request, decode_reason(reason))
+                .retry_after
+            {
+                Some(delay) => action.retry_after_ms = delay.as_millis() as u32,
+                None => return action,
+            }
+        }
+        None => {
+            if lcb_retry_request_is_idempotent(req) == 0
+                && lcb_retry_reason_allows_non_idempotent_retry(reason) == 0
+            {
+                return action;
+            }
+        }
+    }
+    if withdraw(cookie, attempts) {
+        action.should_retry = 1;
+        events::emit(ClusterEvent::RetryScheduled {
+            reason: decode_reason(reason),
+            attempts,
+            retry_after: match action.retry_after_ms {
+                0 => None,
+                ms => Some(Duration::from_millis(ms as u64)),
+            },
+        });
+    }
+    action
+}
+
+#[allow(non_upper_case_globals)]
+fn decode_reason(reason: lcb_RETRY_REASON) -> RetryReason {
+    match reason {
+        lcb_RETRY_REASON_LCB_RETRY_REASON_SOCKET_NOT_AVAILABLE => RetryReason::SocketNotAvailable,
+        lcb_RETRY_REASON_LCB_RETRY_REASON_SERVICE_NOT_AVAILABLE => RetryReason::ServiceNotAvailable,
+        lcb_RETRY_REASON_LCB_RETRY_REASON_NODE_NOT_AVAILABLE => RetryReason::NodeNotAvailable,
+        lcb_RETRY_REASON_LCB_RETRY_REASON_KV_NOT_MY_VBUCKET => RetryReason::KvNotMyVbucket,
+        lcb_RETRY_REASON_LCB_RETRY_REASON_KV_COLLECTION_OUTDATED => {
+            RetryReason::KvCollectionOutdated
+        }
+        lcb_RETRY_REASON_LCB_RETRY_REASON_KV_ERROR_MAP_RETRY_INDICATED => {
+            RetryReason::KvErrorMapRetryIndicated
+        }
+        lcb_RETRY_REASON_LCB_RETRY_REASON_KV_LOCKED => RetryReason::KvLocked,
+        lcb_RETRY_REASON_LCB_RETRY_REASON_KV_TEMPORARY_FAILURE => RetryReason::KvTemporaryFailure,
+        lcb_RETRY_REASON_LCB_RETRY_REASON_KV_SYNC_WRITE_IN_PROGRESS => {
+            RetryReason::KvSyncWriteInProgress
+        }
+        lcb_RETRY_REASON_LCB_RETRY_REASON_KV_SYNC_WRITE_RE_COMMIT_IN_PROGRESS => {
+            RetryReason::KvSyncWriteReCommitInProgress
+        }
+        lcb_RETRY_REASON_LCB_RETRY_REASON_SERVICE_RESPONSE_CODE_INDICATED => {
+            RetryReason::ServiceResponseCodeIndicated
+        }
+        lcb_RETRY_REASON_LCB_RETRY_REASON_SOCKET_CLOSED_WHILE_IN_FLIGHT => {
+            RetryReason::SocketClosedWhileInFlight
+        }
+        lcb_RETRY_REASON_LCB_RETRY_REASON_CIRCUIT_BREAKER_OPEN => RetryReason::CircuitBreakerOpen,
+        lcb_RETRY_REASON_LCB_RETRY_REASON_QUERY_PREPARED_STATEMENT_FAILURE => {
+            RetryReason::QueryPreparedStatementFailure
+        }
+        lcb_RETRY_REASON_LCB_RETRY_REASON_ANALYTICS_TEMPORARY_FAILURE => {
+            RetryReason::AnalyticsTemporaryFailure
+        }
+        lcb_RETRY_REASON_LCB_RETRY_REASON_SEARCH_TOO_MANY_REQUESTS => {
+            RetryReason::SearchTooManyRequests
+        }
+        _ => RetryReason::Unknown,
+    }
+}
@@ -1,4 +1,25 @@
-use crate::io::request::Request;
+use crate::api::authenticator::CredentialProvider;
+use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::events::{ClusterEvent, ClusterEventListener};
+use crate::api::features::ServerVersion;
+use crate::api::metrics::{Meter, NoopMeter};
+use crate::api::options::{HealthProbe, KvKeepAlive, RetryBudget};
+use crate::api::results::ServiceType;
+use crate::api::security::SecurityEventListener;
+use crate::api::tracing::{NoopTracer, RequestSpan, RequestTracer};
+use crate::api::{Capability, DurabilityLevel};
+use crate::io::request::{GenericManagementRequest, Request};
+use futures::channel::oneshot;
+use futures::future::BoxFuture;
+use futures::task::{self, ArcWake, Context};
+use futures::{Future, FutureExt};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 #[cfg(feature = "libcouchbase")]
 mod lcb;
@@ -6,24 +27,603 @@ mod lcb;
 #[cfg(feature = "libcouchbase")]
 use crate::io::lcb::IoCore;
 
+#[cfg(feature = "libcouchbase")]
+pub(crate) use crate::io::lcb::{backend_version, DEFAULT_TIMEOUTS};
+
+/// The name of the IO backend in use.
+#[cfg(feature = "libcouchbase")]
+pub(crate) const BACKEND: &str = "libcouchbase";
+
 pub mod request;
 
+/// The credentials used to authenticate against the cluster.
+///
+/// Individual services can be given their own credentials, which take precedence over the
+/// default username and password. When a provider is set, it takes precedence over both.
+#[derive(Clone, Default)]
+pub struct Credentials {
+    username: String,
+    password: String,
+    overrides: HashMap<ServiceType, (String, String)>,
+    provider: Option<Arc<dyn CredentialProvider>>,
+}
+
+/// Leaves out the passwords, so the credentials can be logged.
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let overrides: HashMap<&ServiceType, &str> = self
+            .overrides
+            .iter()
+            .map(|(service, (username, _))| (service, username.as_str()))
+            .collect();
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("overrides", &overrides)
+            .field("provider", &self.provider.is_some())
+            .finish()
+    }
+}
+
+impl Credentials {
+    pub fn new(username: String, password: String) -> Self {
+        Self {
+            username,
+            password,
+            overrides: HashMap::new(),
+            provider: None,
+        }
+    }
+
+    /// Asks the provider for the credentials every time a connection authenticates.
+    pub fn with_provider(provider: Arc<dyn CredentialProvider>) -> Self {
+        Self {
+            provider: Some(provider),
+            ..Self::default()
+        }
+    }
+
+    pub fn set_override(&mut self, service: ServiceType, username: String, password: String) {
+        self.overrides.insert(service, (username, password));
+    }
+
+    pub fn has_overrides(&self) -> bool {
+        !self.overrides.is_empty()
+    }
+
+    pub fn provider(&self) -> Option<&Arc<dyn CredentialProvider>> {
+        self.provider.as_ref()
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+
+    /// Returns the username and password to use for the given service.
+    pub fn for_service(&self, service: ServiceType) -> (&str, &str) {
+        match self.overrides.get(&service) {
+            Some((username, password)) => (username, password),
+            None => (&self.username, &self.password),
+        }
+    }
+}
+
 pub struct Core {
     io_core: IoCore,
+    meter: Arc<dyn Meter>,
+    tracer: Arc<dyn RequestTracer>,
+    /// The minimum durability level enforced by each bucket and the configuration generation it
+    /// has been read at, as far as it is known.
+    minimum_durability: Arc<Mutex<HashMap<String, (DurabilityLevel, u64)>>>,
+    /// The version of the cluster and the configuration generation it has been read at.
+    server_version: Mutex<Option<(ServerVersion, u64)>>,
+    /// Bumped by the IO layer whenever a new cluster configuration has been applied.
+    config_generation: Arc<AtomicU64>,
 }
 
 impl Core {
     pub fn new(connection_string: String, username: String, password: String) -> Self {
+        Self::with_credentials(connection_string, Credentials::new(username, password))
+    }
+
+    pub fn with_credentials(connection_string: String, credentials: Credentials) -> Self {
+        let config_generation = Arc::new(AtomicU64::new(0));
         Self {
-            io_core: IoCore::new(connection_string, username, password),
+            io_core: IoCore::new(connection_string, credentials, config_generation.clone()),
+            meter: Arc::new(NoopMeter),
+            tracer: Arc::new(NoopTracer),
+            minimum_durability: Arc::new(Mutex::new(HashMap::new())),
+            server_version: Mutex::new(None),
+            config_generation,
+        }
+    }
+
+    pub fn set_meter(&mut self, meter: Arc<dyn Meter>) {
+        self.io_core.set_meter(meter.clone());
+        self.meter = meter;
+    }
+
+    pub fn set_tracer(&mut self, tracer: Arc<dyn RequestTracer>) {
+        self.tracer = tracer;
+    }
+
+    #[cfg(feature = "read-cache")]
+    pub fn meter(&self) -> Arc<dyn Meter> {
+        self.meter.clone()
+    }
+
+    /// Dispatches the request and returns a timer which records its latency and ends its span
+    /// once finished.
+    ///
+    /// Requests the backend does not support are failed right away with `FeatureNotAvailable`,
+    /// queries whose parameters could not be encoded with `InvalidArgument`,
+    /// writes below the minimum durability level of their bucket with `DurabilityBelowMinimum`.
+    pub fn send(&self, request: Request) -> OperationTimer {
+        let service = request.service_type();
+        let operation = request.operation_name();
+        let mut span = self.tracer.request_span(operation);
+        span.set_attribute("db.system", "couchbase".into());
+        span.set_attribute("db.couchbase.service", service.report_name().into());
+        span.set_attribute("db.couchbase.operation", operation.into());
+        if let Some(bucket) = request.bucket() {
+            span.set_attribute("db.name", bucket.clone());
+        }
+        let timer = OperationTimer {
+            meter: self.meter.clone(),
+            span: Some(span),
+            service,
+            operation,
+            start: Instant::now(),
+        };
+        if let Some(Err(e)) = request.expiry().map(|e| e.validate()) {
+            request.fail(e);
+        } else if let Err(e) = request.check_parameters() {
+            request.fail(e);
+        } else if let Some(ctx) = self.check_durability(&request) {
+            request.fail(CouchbaseError::DurabilityBelowMinimum { ctx });
+        } else if let Some(ctx) = self.check_capability(request.capability()) {
+            request.fail(CouchbaseError::FeatureNotAvailable { ctx });
+        } else {
+            self.io_core.send(request);
+        }
+        timer
+    }
+
+    /// Returns the context of the `FeatureNotAvailable` error if the backend lacks the capability.
+    pub fn check_capability(&self, capability: Capability) -> Option<ErrorContext> {
+        if self.capabilities().contains(&capability) {
+            return None;
         }
+        let mut ctx = ErrorContext::default();
+        ctx.insert("capability", Value::String(format!("{:?}", capability)));
+        ctx.insert(
+            "supported",
+            Value::Array(
+                self.capabilities()
+                    .iter()
+                    .map(|c| Value::String(format!("{:?}", c)))
+                    .collect(),
+            ),
+        );
+        Some(ctx)
     }
 
-    pub fn send(&self, request: Request) {
-        self.io_core.send(request)
+    fn check_durability(&self, request: &Request) -> Option<ErrorContext> {
+        let level = request.durability_level()?;
+        let bucket = request.bucket()?;
+        let minimum = self.minimum_durability_level(bucket)?;
+        if level as u8 >= minimum as u8 {
+            return None;
+        }
+        let mut ctx = ErrorContext::default();
+        ctx.insert("bucket", Value::String(bucket.clone()));
+        ctx.insert("durability_level", Value::String(level.to_string()));
+        ctx.insert(
+            "minimum_durability_level",
+            Value::String(minimum.to_string()),
+        );
+        Some(ctx)
+    }
+
+    /// The minimum durability level of the bucket, if it has been read from its settings.
+    ///
+    /// The settings may have changed along with the cluster configuration, so a level read
+    /// before the latest configuration is read again in the background. It is not enforced
+    /// until then, which leaves the check to the server.
+    pub fn minimum_durability_level(&self, bucket: &str) -> Option<DurabilityLevel> {
+        let mut minimum_durability = self.minimum_durability.lock().unwrap();
+        let (level, generation) = *minimum_durability.get(bucket)?;
+        if generation == self.config_generation() {
+            return Some(level);
+        }
+        minimum_durability.remove(bucket);
+        drop(minimum_durability);
+        self.refresh_minimum_durability_level(bucket.into());
+        None
+    }
+
+    /// Remembers the minimum durability level read from the settings of the bucket.
+    pub fn set_minimum_durability_level(&self, bucket: String, level: DurabilityLevel) {
+        let generation = self.config_generation();
+        self.minimum_durability
+            .lock()
+            .unwrap()
+            .insert(bucket, (level, generation));
+    }
+
+    fn refresh_minimum_durability_level(&self, bucket: String) {
+        let generation = self.config_generation();
+        let (sender, receiver) = oneshot::channel();
+        self.io_core.send(Request::GenericManagementRequest(
+            GenericManagementRequest::new(
+                sender,
+                format!("/pools/default/buckets/{}", bucket),
+                String::from("get"),
+                None,
+            ),
+        ));
+        let minimum_durability = self.minimum_durability.clone();
+        spawn_detached(async move {
+            let result = match receiver.await {
+                Ok(Ok(result)) if result.http_status() == 200 => result,
+                _ => return,
+            };
+            let settings: Value = match result.payload().map(|p| serde_json::from_slice(p)) {
+                Some(Ok(settings)) => settings,
+                _ => return,
+            };
+            let alias = settings["durabilityMinLevel"].as_str().unwrap_or("none");
+            if let Ok(level) = DurabilityLevel::try_from(alias) {
+                minimum_durability
+                    .lock()
+                    .unwrap()
+                    .insert(bucket, (level, generation));
+            }
+        });
+    }
+
+    /// Counts the cluster configurations applied so far, to tell when state read from the
+    /// cluster may be outdated.
+    pub fn config_generation(&self) -> u64 {
+        self.config_generation.load(Ordering::SeqCst)
+    }
+
+    /// The version of the cluster, unless the configuration changed since it has been read.
+    ///
+    /// Nodes are added, removed and upgraded through configuration changes, so a rolling
+    /// upgrade never leaves a stale version behind.
+    pub fn server_version(&self) -> Option<ServerVersion> {
+        match *self.server_version.lock().unwrap() {
+            Some((version, generation)) if generation == self.config_generation() => Some(version),
+            _ => None,
+        }
+    }
+
+    /// Remembers the version of the cluster, read at the given configuration generation.
+    pub fn set_server_version(&self, version: ServerVersion, generation: u64) {
+        *self.server_version.lock().unwrap() = Some((version, generation));
+    }
+
+    /// The capabilities of the IO backend in use.
+    pub fn capabilities(&self) -> &'static [Capability] {
+        self.io_core.capabilities()
     }
 
     pub fn open_bucket(&self, name: String) {
         self.io_core.open_bucket(name)
     }
+
+    /// Only dumps every nth packet when packet dumps are enabled.
+    #[cfg(feature = "packet-dump")]
+    pub fn set_packet_dump_sampling(&self, every: usize) {
+        self.io_core.set_packet_dump_sampling(every)
+    }
+
+    /// Limits the retries of the IO layer, reporting denied retries to the current meter.
+    pub fn set_retry_budget(&self, retry_budget: RetryBudget) {
+        self.io_core
+            .set_retry_budget(retry_budget, self.meter.clone())
+    }
+
+    /// Caps the retries of key/value operations which do not set their own cap.
+    pub fn set_max_retries(&self, max_retries: u32) {
+        self.io_core.set_max_retries(max_retries)
+    }
+
+    /// Samples the slowest key/value packets into the current meter.
+    pub fn set_slow_kv_sampling(&self, percent: f64) {
+        self.io_core.set_slow_kv_sampling(percent)
+    }
+
+    /// Reports certificate changes (and other security events) to the listener.
+    pub fn set_security_event_listener(&self, listener: Arc<dyn SecurityEventListener>) {
+        self.io_core.set_security_event_listener(listener)
+    }
+
+    /// Reports configuration, connection, retry and circuit breaker events to the listener.
+    pub fn set_cluster_event_listener(&self, listener: Arc<dyn ClusterEventListener>) {
+        self.io_core.set_cluster_event_listener(listener)
+    }
+
+    /// Passes an event observed outside of the IO layer to the cluster event listener.
+    pub fn emit_cluster_event(&self, event: ClusterEvent) {
+        self.io_core.emit_cluster_event(event)
+    }
+
+    /// Fails connections to nodes which present a different certificate than before.
+    pub fn set_certificate_pinning(&self, pinning: bool) {
+        self.io_core.set_certificate_pinning(pinning)
+    }
+
+    /// Sends NOOPs over idle key/value connections to detect dead ones.
+    pub fn set_kv_keepalive(&self, kv_keepalive: KvKeepAlive) {
+        self.io_core.set_kv_keepalive(kv_keepalive)
+    }
+
+    /// Pings idle endpoints in the background to find and route around degraded ones.
+    pub fn set_health_probe(&self, health_probe: HealthProbe) {
+        self.io_core.set_health_probe(health_probe)
+    }
+}
+
+/// Measures an operation from dispatching it until `finish` is called with its result.
+///
+/// A timer dropped without being finished still ends its span, but records no latency.
+pub struct OperationTimer {
+    meter: Arc<dyn Meter>,
+    // Only taken by `finish` or `drop`
+    span: Option<Box<dyn RequestSpan>>,
+    service: ServiceType,
+    operation: &'static str,
+    start: Instant,
+}
+
+impl OperationTimer {
+    /// Records the latency and outcome of the operation, ends its span and passes the result
+    /// through.
+    pub fn finish<T>(mut self, result: CouchbaseResult<T>) -> CouchbaseResult<T> {
+        self.meter.record_latency(
+            self.service,
+            self.operation,
+            result.as_ref().err(),
+            self.start.elapsed(),
+        );
+        if let Some(mut span) = self.span.take() {
+            if let Err(e) = &result {
+                span.set_attribute("error", e.to_string());
+            }
+            span.end();
+        }
+        result
+    }
+
+    /// Records the latency and outcome of an operation nobody awaits, once its result arrives.
+    ///
+    /// The timer is finished by a detached task as soon as the IO thread sends the result, see
+    /// `spawn_detached`.
+    pub fn finish_detached<T: Send + 'static>(
+        self,
+        receiver: oneshot::Receiver<CouchbaseResult<T>>,
+    ) {
+        spawn_detached(async move {
+            let result = receiver.await.unwrap_or_else(|_| {
+                Err(CouchbaseError::RequestCanceled {
+                    ctx: ErrorContext::default(),
+                })
+            });
+            let _ = self.finish(result);
+        });
+    }
+}
+
+impl Drop for OperationTimer {
+    fn drop(&mut self) {
+        if let Some(span) = self.span.take() {
+            span.end();
+        }
+    }
+}
+
+/// Runs a future nobody awaits to completion.
+///
+/// The future is polled right away and then again by whoever wakes it, which is usually the IO
+/// thread sending a result, so no executor is needed.
+pub(crate) fn spawn_detached<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let task = Arc::new(DetachedTask {
+        future: Mutex::new(Some(future.boxed())),
+        notified: AtomicBool::new(true),
+    });
+    task.run();
+}
+
+/// A future nobody awaits, until it completes.
+struct DetachedTask {
+    future: Mutex<Option<BoxFuture<'static, ()>>>,
+    // Set when the future was woken and has to be polled again
+    notified: AtomicBool,
+}
+
+impl DetachedTask {
+    fn run(self: &Arc<Self>) {
+        let waker = task::waker(self.clone());
+        loop {
+            // A wake while the future is polled is picked up by the poller
+            let mut future = match self.future.try_lock() {
+                Ok(future) => future,
+                Err(_) => return,
+            };
+            while self.notified.swap(false, Ordering::SeqCst) {
+                let done = match future.as_mut() {
+                    Some(f) => f.poll_unpin(&mut Context::from_waker(&waker)).is_ready(),
+                    None => return,
+                };
+                if done {
+                    *future = None;
+                    return;
+                }
+            }
+            drop(future);
+            if !self.notified.load(Ordering::SeqCst) {
+                return;
+            }
+        }
+    }
+}
+
+impl ArcWake for DetachedTask {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.notified.store(true, Ordering::SeqCst);
+        arc_self.run();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tracing::NoopSpan;
+    use futures::task::Poll;
+    use std::time::Duration;
+
+    // Records whether each operation succeeded
+    #[derive(Debug, Default)]
+    struct RecordingMeter {
+        outcomes: Mutex<Vec<bool>>,
+    }
+
+    impl Meter for RecordingMeter {
+        fn record_latency(
+            &self,
+            _service: ServiceType,
+            _operation: &str,
+            error: Option<&CouchbaseError>,
+            _latency: Duration,
+        ) {
+            self.outcomes.lock().unwrap().push(error.is_none());
+        }
+    }
+
+    #[test]
+    fn leaves_passwords_out_of_debug_output() {
+        let mut credentials = Credentials::new("admin".into(), "hunter2".into());
+        credentials.set_override(ServiceType::Query, "analyst".into(), "swordfish".into());
+        let debug = format!("{:?}", credentials);
+        assert!(debug.contains("admin"));
+        assert!(debug.contains("analyst"));
+        assert!(!debug.contains("hunter2"));
+        assert!(!debug.contains("swordfish"));
+    }
+
+    fn timer(meter: &Arc<RecordingMeter>) -> OperationTimer {
+        OperationTimer {
+            meter: meter.clone(),
+            span: Some(Box::new(NoopSpan)),
+            service: ServiceType::KeyValue,
+            operation: "upsert",
+            start: Instant::now(),
+        }
+    }
+
+    // Shares the attributes of the span, which are taken once it ended
+    #[derive(Default)]
+    struct RecordingSpan {
+        attributes: Arc<Mutex<Vec<(&'static str, String)>>>,
+        ended: Arc<AtomicBool>,
+    }
+
+    impl RequestSpan for RecordingSpan {
+        fn set_attribute(&mut self, key: &'static str, value: String) {
+            self.attributes.lock().unwrap().push((key, value));
+        }
+
+        fn end(self: Box<Self>) {
+            self.ended.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn ends_the_span_with_the_error_of_failed_operations() {
+        let meter = Arc::new(RecordingMeter::default());
+        let span = RecordingSpan::default();
+        let (attributes, ended) = (span.attributes.clone(), span.ended.clone());
+        let mut timer = timer(&meter);
+        timer.span = Some(Box::new(span));
+
+        let result: CouchbaseResult<()> = timer.finish(Err(CouchbaseError::DocumentNotFound {
+            ctx: ErrorContext::default(),
+        }));
+        assert!(result.is_err());
+        assert!(ended.load(Ordering::SeqCst));
+        let attributes = attributes.lock().unwrap();
+        assert_eq!(attributes.len(), 1);
+        assert_eq!(attributes[0].0, "error");
+    }
+
+    #[test]
+    fn ends_the_span_of_timers_dropped_without_a_result() {
+        let meter = Arc::new(RecordingMeter::default());
+        let span = RecordingSpan::default();
+        let ended = span.ended.clone();
+        let mut timer = timer(&meter);
+        timer.span = Some(Box::new(span));
+
+        drop(timer);
+        assert!(ended.load(Ordering::SeqCst));
+        assert!(meter.outcomes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn finishes_detached_operations_once_their_result_arrives() {
+        let meter = Arc::new(RecordingMeter::default());
+        let (sender, receiver) = oneshot::channel::<CouchbaseResult<()>>();
+        timer(&meter).finish_detached(receiver);
+        assert!(meter.outcomes.lock().unwrap().is_empty());
+
+        sender.send(Ok(())).unwrap();
+        assert_eq!(*meter.outcomes.lock().unwrap(), vec![true]);
+    }
+
+    #[test]
+    fn finishes_detached_operations_which_completed_already() {
+        let meter = Arc::new(RecordingMeter::default());
+        let (sender, receiver) = oneshot::channel::<CouchbaseResult<()>>();
+        sender
+            .send(Err(CouchbaseError::DocumentNotFound {
+                ctx: ErrorContext::default(),
+            }))
+            .unwrap();
+        timer(&meter).finish_detached(receiver);
+        assert_eq!(*meter.outcomes.lock().unwrap(), vec![false]);
+    }
+
+    #[test]
+    fn runs_detached_futures_which_wake_themselves() {
+        let polls = Arc::new(Mutex::new(0));
+        let counted = polls.clone();
+        spawn_detached(futures::future::poll_fn(move |cx| {
+            let mut polls = counted.lock().unwrap();
+            *polls += 1;
+            if *polls < 3 {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            Poll::Ready(())
+        }));
+        assert_eq!(*polls.lock().unwrap(), 3);
+    }
+
+    #[test]
+    fn fails_detached_operations_whose_result_is_dropped() {
+        let meter = Arc::new(RecordingMeter::default());
+        let (sender, receiver) = oneshot::channel::<CouchbaseResult<()>>();
+        timer(&meter).finish_detached(receiver);
+        drop(sender);
+        assert_eq!(*meter.outcomes.lock().unwrap(), vec![false]);
+    }
 }
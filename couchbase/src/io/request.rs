@@ -1,25 +1,40 @@
-use crate::api::error::{CouchbaseError, CouchbaseResult};
+use crate::api::error::{CouchbaseError, CouchbaseResult, ErrorContext};
+use crate::api::expiry::Expiry;
 use crate::api::options::*;
 use crate::api::results::*;
-use crate::api::{LookupInSpec, MutateInSpec};
+use crate::api::{Capability, DurabilityLevel, LookupInSpec, MutateInSpec, PersistTo, ReplicateTo};
+use futures::channel::mpsc::UnboundedSender;
 use futures::channel::oneshot::Sender;
+use log::trace;
+use serde_json::Value;
 use std::time::Duration;
 
 #[derive(Debug)]
 pub enum Request {
     Get(GetRequest),
+    GetReplica(GetReplicaRequest),
     Mutate(MutateRequest),
     Exists(ExistsRequest),
+    Unlock(UnlockRequest),
+    Touch(TouchRequest),
     Remove(RemoveRequest),
     MutateIn(MutateInRequest),
     LookupIn(LookupInRequest),
+    LookupInReplica(LookupInReplicaRequest),
     Query(QueryRequest),
     Analytics(AnalyticsRequest),
     Search(SearchRequest),
     GenericManagementRequest(GenericManagementRequest),
     #[cfg(feature = "volatile")]
     KvStatsRequest(KvStatsRequest),
+    #[cfg(feature = "volatile")]
+    GetCollectionId(GetCollectionIdRequest),
+    #[cfg(feature = "volatile")]
+    GetPartitionMap(GetPartitionMapRequest),
+    #[cfg(feature = "volatile")]
+    WatchPartitionMap(WatchPartitionMapRequest),
     Ping(PingRequest),
+    Diagnostics(DiagnosticsRequest),
     Counter(CounterRequest),
 }
 
@@ -27,33 +42,237 @@ impl Request {
     pub fn bucket(&self) -> Option<&String> {
         match self {
             Self::Get(r) => Some(&r.bucket),
+            Self::GetReplica(r) => Some(&r.bucket),
             Self::Mutate(r) => Some(&r.bucket),
             Self::Exists(r) => Some(&r.bucket),
+            Self::Unlock(r) => Some(&r.bucket),
+            Self::Touch(r) => Some(&r.bucket),
             Self::Remove(r) => Some(&r.bucket),
             Self::MutateIn(r) => Some(&r.bucket),
             Self::LookupIn(r) => Some(&r.bucket),
+            Self::LookupInReplica(r) => Some(&r.bucket),
             Self::Counter(r) => Some(&r.bucket),
+            #[cfg(feature = "volatile")]
+            Self::GetCollectionId(r) => Some(&r.bucket),
+            #[cfg(feature = "volatile")]
+            Self::GetPartitionMap(r) => Some(&r.bucket),
+            #[cfg(feature = "volatile")]
+            Self::WatchPartitionMap(r) => Some(&r.bucket),
+            Self::Ping(r) => r.bucket.as_ref(),
+            Self::Diagnostics(r) => r.bucket.as_ref(),
             _ => None,
         }
     }
 
-    pub fn fail(self, reason: CouchbaseError) {
+    /// The service this request is dispatched to.
+    pub fn service_type(&self) -> ServiceType {
+        match self {
+            Self::Query(_) => ServiceType::Query,
+            Self::Analytics(_) => ServiceType::Analytics,
+            Self::Search(_) => ServiceType::Search,
+            Self::GenericManagementRequest(r) => r.service_type.unwrap_or(ServiceType::Management),
+            _ => ServiceType::KeyValue,
+        }
+    }
+
+    /// The capability the backend needs to support in order to dispatch the request.
+    pub fn capability(&self) -> Capability {
+        match self {
+            Self::Get(_)
+            | Self::Mutate(_)
+            | Self::Exists(_)
+            | Self::Unlock(_)
+            | Self::Touch(_)
+            | Self::Remove(_)
+            | Self::Counter(_) => Capability::KeyValue,
+            Self::GetReplica(_) => Capability::ReplicaReads,
+            Self::MutateIn(_) | Self::LookupIn(_) => Capability::SubDocument,
+            Self::LookupInReplica(_) => Capability::ReplicaSubDocument,
+            Self::Query(_) => Capability::Query,
+            Self::Analytics(_) => Capability::Analytics,
+            Self::Search(_) => Capability::Search,
+            Self::Ping(_) | Self::Diagnostics(_) => Capability::Diagnostics,
+            Self::GenericManagementRequest(r) if r.method == "patch" => Capability::HttpPatch,
+            Self::GenericManagementRequest(_) => Capability::Management,
+            #[cfg(feature = "volatile")]
+            Self::KvStatsRequest(_) => Capability::KvStats,
+            #[cfg(feature = "volatile")]
+            Self::GetCollectionId(_) => Capability::KeyValue,
+            #[cfg(feature = "volatile")]
+            Self::GetPartitionMap(_) => Capability::KeyValue,
+            #[cfg(feature = "volatile")]
+            Self::WatchPartitionMap(_) => Capability::KeyValue,
+        }
+    }
+
+    /// The durability level explicitly requested for a write, if any.
+    pub fn durability_level(&self) -> Option<DurabilityLevel> {
+        let level = match self {
+            Self::Mutate(r) => match &r.ty {
+                MutateRequestType::Insert { options } => options.durability_level,
+                MutateRequestType::Upsert { options } => options.durability_level,
+                MutateRequestType::Replace { options } => options.durability_level,
+                MutateRequestType::Append { options } => options.durability_level,
+                MutateRequestType::Prepend { options } => options.durability_level,
+            },
+            Self::Remove(r) => r.options.durability_level,
+            Self::MutateIn(r) => r.options.durability_level,
+            Self::Counter(r) => r.options.durability_level,
+            _ => None,
+        };
+        match level {
+            Some(DurabilityLevel::None) => None,
+            level => level,
+        }
+    }
+
+    /// The client verified durability the mutation has to meet, if any.
+    pub fn client_durability(&self) -> Option<(PersistTo, ReplicateTo)> {
+        match self {
+            Self::Mutate(r) => match &r.ty {
+                MutateRequestType::Insert { options } => options.client_durability,
+                MutateRequestType::Upsert { options } => options.client_durability,
+                MutateRequestType::Replace { options } => options.client_durability,
+                MutateRequestType::Append { options } => options.client_durability,
+                MutateRequestType::Prepend { options } => options.client_durability,
+            },
+            Self::Remove(r) => r.options.client_durability,
+            Self::MutateIn(r) => r.options.client_durability,
+            Self::Counter(r) => r.options.client_durability,
+            _ => None,
+        }
+    }
+
+    /// The expiry the request sets on the document, if any.
+    pub fn expiry(&self) -> Option<Expiry> {
+        match self {
+            Self::Get(r) => match &r.ty {
+                GetRequestType::GetAndTouch { expiry, .. } => Some(*expiry),
+                _ => None,
+            },
+            Self::Mutate(r) => match &r.ty {
+                MutateRequestType::Insert { options } => options.expiry,
+                MutateRequestType::Upsert { options } => options.expiry,
+                MutateRequestType::Replace { options } => options.expiry,
+                _ => None,
+            },
+            Self::Touch(r) => Some(r.expiry),
+            Self::MutateIn(r) => r.options.expiry,
+            Self::Counter(r) => r.options.expiry,
+            _ => None,
+        }
+    }
+
+    /// Fails if the parameters of a query or analytics request could not be encoded, or if a
+    /// mutation asks for both enhanced and client verified durability.
+    pub fn check_parameters(&self) -> CouchbaseResult<()> {
+        if self.durability_level().is_some() && self.client_durability().is_some() {
+            let mut ctx = ErrorContext::default();
+            ctx.insert(
+                "msg",
+                Value::String("durability and client_durability cannot be combined".into()),
+            );
+            return Err(CouchbaseError::InvalidArgument { ctx });
+        }
         match self {
-            Self::Get(r) => r.sender.send(Err(reason)).unwrap(),
-            Self::Mutate(r) => r.sender.send(Err(reason)).unwrap(),
-            Self::Exists(r) => r.sender.send(Err(reason)).unwrap(),
-            Self::Remove(r) => r.sender.send(Err(reason)).unwrap(),
-            Self::MutateIn(r) => r.sender.send(Err(reason)).unwrap(),
-            Self::LookupIn(r) => r.sender.send(Err(reason)).unwrap(),
-            Self::Query(r) => r.sender.send(Err(reason)).unwrap(),
-            Self::Analytics(r) => r.sender.send(Err(reason)).unwrap(),
-            Self::Search(r) => r.sender.send(Err(reason)).unwrap(),
-            Self::Ping(r) => r.sender.send(Err(reason)).unwrap(),
-            Self::GenericManagementRequest(r) => r.sender.send(Err(reason)).unwrap(),
+            Self::Query(r) => check_parameters(&r.options.parameters_error),
+            Self::Analytics(r) => check_parameters(&r.options.parameters_error),
+            _ => Ok(()),
+        }
+    }
+
+    /// A short, stable name for the operation, used when recording metrics.
+    pub fn operation_name(&self) -> &'static str {
+        match self {
+            Self::Get(r) => match r.ty {
+                GetRequestType::Get { .. } => "get",
+                GetRequestType::GetAndLock { .. } => "get_and_lock",
+                GetRequestType::GetAndTouch { .. } => "get_and_touch",
+            },
+            Self::GetReplica(r) => match r.mode {
+                ReplicaMode::Any => "get_any_replica",
+                ReplicaMode::All => "get_all_replicas",
+            },
+            Self::Mutate(r) => match r.ty {
+                MutateRequestType::Insert { .. } => "insert",
+                MutateRequestType::Upsert { .. } => "upsert",
+                MutateRequestType::Replace { .. } => "replace",
+                MutateRequestType::Append { .. } => "append",
+                MutateRequestType::Prepend { .. } => "prepend",
+            },
+            Self::Exists(_) => "exists",
+            Self::Unlock(_) => "unlock",
+            Self::Touch(_) => "touch",
+            Self::Remove(_) => "remove",
+            Self::MutateIn(_) => "mutate_in",
+            Self::LookupIn(_) => "lookup_in",
+            Self::LookupInReplica(r) => match r.mode {
+                ReplicaMode::Any => "lookup_in_any_replica",
+                ReplicaMode::All => "lookup_in_all_replicas",
+            },
+            Self::Query(_) => "query",
+            Self::Analytics(_) => "analytics",
+            Self::Search(_) => "search",
+            Self::Ping(_) => "ping",
+            Self::Diagnostics(_) => "diagnostics",
+            Self::GenericManagementRequest(_) => "management",
+            #[cfg(feature = "volatile")]
+            Self::KvStatsRequest(_) => "kv_stats",
+            #[cfg(feature = "volatile")]
+            Self::GetCollectionId(_) => "get_collection_id",
             #[cfg(feature = "volatile")]
-            Self::KvStatsRequest(r) => r.sender.send(Err(reason)).unwrap(),
-            Self::Counter(r) => r.sender.send(Err(reason)).unwrap(),
+            Self::GetPartitionMap(_) => "get_partition_map",
+            #[cfg(feature = "volatile")]
+            Self::WatchPartitionMap(_) => "watch_partition_map",
+            Self::Counter(r) => {
+                if r.options.delta < 0 {
+                    "decrement"
+                } else {
+                    "increment"
+                }
+            }
+        }
+    }
+
+    /// Completes the request with the given error.
+    ///
+    /// The caller may not be waiting for the result anymore, for example when a request is
+    /// fired and forgotten, so a dropped receiver is not an error.
+    pub fn fail(self, reason: CouchbaseError) {
+        let sent = match self {
+            Self::Get(r) => r.sender.send(Err(reason)).is_ok(),
+            Self::GetReplica(r) => r.sender.unbounded_send(Err(reason)).is_ok(),
+            Self::Mutate(r) => r.sender.send(Err(reason)).is_ok(),
+            Self::Exists(r) => r.sender.send(Err(reason)).is_ok(),
+            Self::Unlock(r) => r.sender.send(Err(reason)).is_ok(),
+            Self::Touch(r) => r.sender.send(Err(reason)).is_ok(),
+            Self::Remove(r) => r.sender.send(Err(reason)).is_ok(),
+            Self::MutateIn(r) => r.sender.send(Err(reason)).is_ok(),
+            Self::LookupIn(r) => r.sender.send(Err(reason)).is_ok(),
+            Self::LookupInReplica(r) => r.sender.unbounded_send(Err(reason)).is_ok(),
+            Self::Query(r) => r.sender.send(Err(reason)).is_ok(),
+            Self::Analytics(r) => r.sender.send(Err(reason)).is_ok(),
+            Self::Search(r) => r.sender.send(Err(reason)).is_ok(),
+            Self::Ping(r) => r.sender.send(Err(reason)).is_ok(),
+            Self::Diagnostics(r) => r.sender.send(Err(reason)).is_ok(),
+            Self::GenericManagementRequest(r) => r.sender.send(Err(reason)).is_ok(),
+            #[cfg(feature = "volatile")]
+            Self::KvStatsRequest(r) => r.sender.send(Err(reason)).is_ok(),
+            #[cfg(feature = "volatile")]
+            Self::GetCollectionId(r) => r.sender.send(Err(reason)).is_ok(),
+            #[cfg(feature = "volatile")]
+            Self::GetPartitionMap(r) => r.sender.send(Err(reason)).is_ok(),
+            // The changes stream has no room for an error, so it just ends
+            #[cfg(feature = "volatile")]
+            Self::WatchPartitionMap(r) => {
+                r.sender.close_channel();
+                true
+            }
+            Self::Counter(r) => r.sender.send(Err(reason)).is_ok(),
         };
+        if !sent {
+            trace!("Failed to send request failure because the receiver is gone");
+        }
     }
 }
 
@@ -65,6 +284,8 @@ pub struct GetRequest {
     pub(crate) collection: String,
     pub(crate) sender: Sender<CouchbaseResult<GetResult>>,
     pub(crate) ty: GetRequestType,
+    /// Addresses the collection by id, skipping the name resolution.
+    pub(crate) collection_id: Option<u32>,
 }
 
 #[derive(Debug)]
@@ -78,10 +299,30 @@ pub enum GetRequestType {
     },
     GetAndTouch {
         options: GetAndTouchOptions,
-        expiry: Duration,
+        expiry: Expiry,
     },
 }
 
+/// Reads a document from the replica nodes, the active node is read separately.
+#[derive(Debug)]
+pub struct GetReplicaRequest {
+    pub(crate) id: String,
+    pub(crate) bucket: String,
+    pub(crate) scope: String,
+    pub(crate) collection: String,
+    pub(crate) sender: UnboundedSender<CouchbaseResult<GetReplicaResult>>,
+    pub(crate) mode: ReplicaMode,
+    pub(crate) timeout: Option<Duration>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum ReplicaMode {
+    /// Complete with the first replica which returns the document.
+    Any,
+    /// Return the document from every replica.
+    All,
+}
+
 #[derive(Debug)]
 pub struct ExistsRequest {
     pub(crate) id: String,
@@ -92,6 +333,28 @@ pub struct ExistsRequest {
     pub(crate) options: ExistsOptions,
 }
 
+#[derive(Debug)]
+pub struct UnlockRequest {
+    pub(crate) id: String,
+    pub(crate) cas: u64,
+    pub(crate) bucket: String,
+    pub(crate) scope: String,
+    pub(crate) collection: String,
+    pub(crate) sender: Sender<CouchbaseResult<()>>,
+    pub(crate) options: UnlockOptions,
+}
+
+#[derive(Debug)]
+pub struct TouchRequest {
+    pub(crate) id: String,
+    pub(crate) expiry: Expiry,
+    pub(crate) bucket: String,
+    pub(crate) scope: String,
+    pub(crate) collection: String,
+    pub(crate) sender: Sender<CouchbaseResult<MutationResult>>,
+    pub(crate) options: TouchOptions,
+}
+
 #[derive(Debug)]
 pub struct RemoveRequest {
     pub(crate) id: String,
@@ -100,6 +363,8 @@ pub struct RemoveRequest {
     pub(crate) collection: String,
     pub(crate) sender: Sender<CouchbaseResult<MutationResult>>,
     pub(crate) options: RemoveOptions,
+    /// Addresses the collection by id, skipping the name resolution.
+    pub(crate) collection_id: Option<u32>,
 }
 
 #[derive(Debug)]
@@ -107,10 +372,13 @@ pub struct MutateRequest {
     pub(crate) id: String,
     pub(crate) bucket: String,
     pub(crate) content: Vec<u8>,
+    pub(crate) flags: Option<u32>,
     pub(crate) scope: String,
     pub(crate) collection: String,
     pub(crate) sender: Sender<CouchbaseResult<MutationResult>>,
     pub(crate) ty: MutateRequestType,
+    /// Addresses the collection by id, skipping the name resolution.
+    pub(crate) collection_id: Option<u32>,
 }
 
 #[derive(Debug)]
@@ -167,6 +435,20 @@ pub struct LookupInRequest {
     pub(crate) options: LookupInOptions,
 }
 
+/// Performs a sub-document lookup against the replica nodes, the active node is read
+/// separately.
+#[derive(Debug)]
+pub struct LookupInReplicaRequest {
+    pub(crate) id: String,
+    pub(crate) bucket: String,
+    pub(crate) scope: String,
+    pub(crate) collection: String,
+    pub(crate) sender: UnboundedSender<CouchbaseResult<LookupInReplicaResult>>,
+    pub(crate) specs: Vec<LookupInSpec>,
+    pub(crate) mode: ReplicaMode,
+    pub(crate) timeout: Option<Duration>,
+}
+
 #[derive(Debug)]
 pub struct MutateInRequest {
     pub(crate) id: String,
@@ -185,6 +467,9 @@ pub struct GenericManagementRequest {
     pub(crate) payload: Option<String>,
     pub(crate) content_type: Option<String>,
     pub(crate) timeout: Option<Duration>,
+    pub(crate) service_type: Option<ServiceType>,
+    /// Pins the request to a node (`host:port`) of the service instead of picking any.
+    pub(crate) endpoint: Option<String>,
     pub(crate) sender: Sender<CouchbaseResult<GenericManagementResult>>,
 }
 
@@ -202,6 +487,8 @@ impl GenericManagementRequest {
             payload,
             content_type: None,
             timeout: None,
+            service_type: None,
+            endpoint: None,
         }
     }
 
@@ -212,6 +499,14 @@ impl GenericManagementRequest {
     pub fn timeout(&mut self, timeout: Duration) {
         self.timeout = Some(timeout)
     }
+
+    /// Routes the request to the given service instead of the cluster manager.
+    ///
+    /// The request fails with `InvalidArgument` for `ServiceType::KeyValue`, which is not
+    /// served over HTTP.
+    pub fn service_type(&mut self, service_type: ServiceType) {
+        self.service_type = Some(service_type)
+    }
 }
 
 #[derive(Debug)]
@@ -233,8 +528,45 @@ impl KvStatsRequest {
     }
 }
 
+/// Resolves the id of a collection from its scope and collection name.
+#[derive(Debug)]
+#[cfg(feature = "volatile")]
+pub struct GetCollectionIdRequest {
+    pub(crate) bucket: String,
+    pub(crate) scope: String,
+    pub(crate) collection: String,
+    pub(crate) sender: Sender<CouchbaseResult<CollectionIdResult>>,
+    pub(crate) options: GetCollectionIdOptions,
+}
+
+/// Returns the current partition map of a bucket.
+#[derive(Debug)]
+#[cfg(feature = "volatile")]
+pub struct GetPartitionMapRequest {
+    pub(crate) bucket: String,
+    pub(crate) sender: Sender<CouchbaseResult<PartitionMap>>,
+}
+
+/// Streams the partition map of a bucket every time it changes, starting with the current one.
+#[derive(Debug)]
+#[cfg(feature = "volatile")]
+pub struct WatchPartitionMapRequest {
+    pub(crate) bucket: String,
+    pub(crate) sender: UnboundedSender<PartitionMap>,
+}
+
+/// Pings the services of the cluster, through the instance of the bucket if one is given.
 #[derive(Debug)]
 pub struct PingRequest {
     pub(crate) sender: Sender<CouchbaseResult<PingResult>>,
     pub(crate) options: PingOptions,
+    pub(crate) bucket: Option<String>,
+}
+
+/// Reports the connections of the cluster, or of the bucket if one is given.
+#[derive(Debug)]
+pub struct DiagnosticsRequest {
+    pub(crate) sender: Sender<CouchbaseResult<DiagnosticsResult>>,
+    pub(crate) options: DiagnosticsOptions,
+    pub(crate) bucket: Option<String>,
 }
@@ -3,18 +3,63 @@
 mod api;
 mod io;
 
+pub use api::audit::*;
+pub use api::authenticator::*;
 pub use api::buckets::*;
+#[cfg(feature = "read-cache")]
+pub use api::cache::*;
+pub use api::chunked::*;
 pub use api::collections::*;
+pub use api::counters::*;
+pub use api::datastructures::*;
+pub use api::deferred::*;
 pub use api::error::*;
+pub use api::events::*;
+pub use api::expiry::*;
+pub use api::features::*;
+pub use api::metrics::*;
 pub use api::options::*;
+pub use api::query_args::*;
+pub use api::query_functions::*;
+pub use api::query_indexes::*;
+pub use api::query_session::*;
+pub use api::query_transaction::*;
+pub use api::redaction::*;
 pub use api::results::*;
+pub use api::retry::*;
+pub use api::sdk_info::*;
 pub use api::search::*;
+pub use api::search_indexes::*;
+pub use api::security::*;
+pub use api::subdoc_path::*;
+pub use api::tracing::*;
+pub use api::transcoding::*;
 pub use api::users::*;
 pub use api::{
-    Bucket, Cluster, Collection, DurabilityLevel, LookupInSpec, MutateInSpec, MutationState,
-    MutationToken,
+    Bucket, Capability, Cluster, Collection, DetachedCollection, DurabilityLevel, LookupInSpec,
+    MutateInSpec, MutationState, MutationToken, MAX_LOCK_TIME,
 };
 
+/// Encoding and decoding of the common flags and datatype, also available on its own as the
+/// `couchbase-flags` crate.
+pub use couchbase_flags as flags;
+
+/// Transcoders for formats other than JSON, each enabled through the feature of the same name.
+pub mod transcoding {
+    #[cfg(feature = "cbor")]
+    pub use crate::api::transcoding::cbor;
+    #[cfg(feature = "msgpack")]
+    pub use crate::api::transcoding::msgpack;
+}
+
+#[cfg(feature = "volatile")]
+pub use api::analytics_links::*;
+#[cfg(feature = "volatile")]
+pub use api::provisioning::*;
+#[cfg(feature = "volatile")]
+pub use api::sharding::*;
+#[cfg(feature = "volatile")]
+pub use api::RawCollection;
 #[cfg(feature = "volatile")]
 pub use api::Scope;
 
@@ -0,0 +1,42 @@
+use couchbase::{cluster_event_stream, ClusterEvent, RetryReason};
+use futures::executor::block_on_stream;
+use std::time::Duration;
+
+#[test]
+fn forwards_events_into_the_stream() {
+    let (listener, receiver) = cluster_event_stream();
+    listener.on_event(&ClusterEvent::ConfigUpdated {
+        bucket: Some("travel-sample".into()),
+        revision: 42,
+    });
+    listener.on_event(&ClusterEvent::RetryScheduled {
+        reason: RetryReason::KvLocked,
+        attempts: 1,
+        retry_after: Some(Duration::from_millis(10)),
+    });
+    drop(listener);
+
+    let events: Vec<_> = block_on_stream(receiver).collect();
+    assert_eq!(events.len(), 2);
+    match &events[0] {
+        ClusterEvent::ConfigUpdated { bucket, revision } => {
+            assert_eq!(bucket.as_deref(), Some("travel-sample"));
+            assert_eq!(*revision, 42);
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+    match &events[1] {
+        ClusterEvent::RetryScheduled { reason, .. } => assert_eq!(*reason, RetryReason::KvLocked),
+        other => panic!("unexpected event {:?}", other),
+    }
+}
+
+#[test]
+fn ignores_a_dropped_stream() {
+    let (listener, receiver) = cluster_event_stream();
+    drop(receiver);
+    listener.on_event(&ClusterEvent::CircuitOpened {
+        bucket: None,
+        remote: "127.0.0.1:11210".into(),
+    });
+}
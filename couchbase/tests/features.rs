@@ -0,0 +1,132 @@
+mod util;
+
+use couchbase::{
+    CouchbaseError, CouchbaseResult, LookupInAnyReplicaOptions, LookupInSpec, QueryStringQuery,
+    SearchOptions, ServerFeature, ServerVersion, UpsertOptions, VectorQuery, VectorSearch,
+};
+use futures::executor::block_on;
+use serde_json::json;
+use std::fmt::Debug;
+
+#[test]
+fn parses_server_versions() {
+    assert_eq!(
+        Some(ServerVersion::new(7, 2, 4)),
+        ServerVersion::parse("7.2.4-7070-enterprise")
+    );
+    assert_eq!(
+        Some(ServerVersion::new(7, 6, 0)),
+        ServerVersion::parse("7.6")
+    );
+    assert_eq!(None, ServerVersion::parse("enterprise"));
+}
+
+#[test]
+fn gates_features_by_version() {
+    let matrix = [
+        (ServerVersion::new(6, 6, 0), vec![]),
+        (
+            ServerVersion::new(7, 0, 0),
+            vec![ServerFeature::Collections, ServerFeature::PreserveExpiry],
+        ),
+        (
+            ServerVersion::new(7, 2, 0),
+            vec![ServerFeature::Collections, ServerFeature::PreserveExpiry],
+        ),
+        (
+            ServerVersion::new(7, 6, 0),
+            vec![
+                ServerFeature::Collections,
+                ServerFeature::PreserveExpiry,
+                ServerFeature::ScopedSearchIndexes,
+                ServerFeature::VectorSearch,
+                ServerFeature::RangeScan,
+                ServerFeature::SubdocReplicaRead,
+            ],
+        ),
+    ];
+    let all = [
+        ServerFeature::Collections,
+        ServerFeature::PreserveExpiry,
+        ServerFeature::ScopedSearchIndexes,
+        ServerFeature::VectorSearch,
+        ServerFeature::RangeScan,
+        ServerFeature::SubdocReplicaRead,
+    ];
+
+    for (version, supported) in matrix.iter() {
+        for feature in all.iter() {
+            assert_eq!(
+                supported.contains(feature),
+                version.supports(*feature),
+                "{:?} on {}",
+                feature,
+                version
+            );
+        }
+    }
+}
+
+#[test]
+fn run_feature_tests() {
+    util::run(|cfg| {
+        let cluster = cfg.cluster();
+        let version = block_on(cluster.server_version()).expect("could not read the version");
+        let collection = cluster.bucket(cfg.bucket.as_str()).default_collection();
+
+        let upsert = collection.upsert(
+            "features::preserve_expiry",
+            json!({}),
+            UpsertOptions::default().preserve_expiry(true),
+        );
+        assert_gated(version, ServerFeature::PreserveExpiry, block_on(upsert));
+
+        let vector_search = VectorSearch::new(vec![VectorQuery::new("embedding", vec![0.1, 0.2])]);
+        let search = cluster.search_query(
+            "features",
+            QueryStringQuery::new("features".into()),
+            SearchOptions::default().vector_search(vector_search),
+        );
+        assert_gated(version, ServerFeature::VectorSearch, block_on(search));
+
+        let lookup = collection.lookup_in_any_replica(
+            "features::preserve_expiry",
+            vec![LookupInSpec::get("missing")],
+            LookupInAnyReplicaOptions::default(),
+        );
+        assert_gated(version, ServerFeature::SubdocReplicaRead, block_on(lookup));
+
+        #[cfg(feature = "volatile")]
+        {
+            let scope = cluster.bucket(cfg.bucket.as_str()).scope("_default");
+            let scoped_search = scope.search_query(
+                "features",
+                QueryStringQuery::new("features".into()),
+                SearchOptions::default(),
+            );
+            assert_gated(
+                version,
+                ServerFeature::ScopedSearchIndexes,
+                block_on(scoped_search),
+            );
+        }
+    });
+}
+
+/// Asserts that the request failed with `FeatureNotAvailable` if and only if the cluster does
+/// not support the feature. Other failures, like a missing index, are fine either way.
+fn assert_gated<T: Debug>(
+    version: ServerVersion,
+    feature: ServerFeature,
+    result: CouchbaseResult<T>,
+) {
+    let not_available = matches!(result, Err(CouchbaseError::FeatureNotAvailable { .. }));
+    assert_eq!(
+        !version.supports(feature),
+        not_available,
+        "{:?} on {}: {:?}",
+        feature,
+        version,
+        result
+    );
+}
@@ -0,0 +1,40 @@
+use couchbase::{query_args, QueryArgs};
+use serde_json::json;
+use std::collections::HashMap;
+
+#[test]
+fn builds_named_parameters() {
+    let country = String::from("France");
+    let args = query_args! { "country" => country, "$limit" => 10 };
+    assert_eq!(
+        json!({ "country": "France", "limit": 10 }),
+        serde_json::to_value(&args).unwrap()
+    );
+    // The values are only borrowed
+    assert_eq!("France", country);
+}
+
+#[test]
+fn builds_positional_parameters() {
+    let args = query_args!["France", 10, None::<u32>];
+    assert_eq!(
+        json!(["France", 10, null]),
+        serde_json::to_value(&args).unwrap()
+    );
+}
+
+#[test]
+fn fails_to_encode_invalid_parameters() {
+    let mut invalid = HashMap::new();
+    invalid.insert(vec![1u8], 1);
+    let args = query_args!["France", invalid];
+    let error = serde_json::to_value(&args).unwrap_err().to_string();
+    assert!(
+        error.starts_with("parameter $2 could not be encoded"),
+        "{}",
+        error
+    );
+
+    let mixed = QueryArgs::named().insert("country", "France").push(10);
+    assert!(serde_json::to_value(&mixed).is_err());
+}
@@ -0,0 +1,28 @@
+use couchbase::{CouchbaseError, QueryResult};
+use futures::executor::block_on_stream;
+
+#[test]
+fn reports_the_row_which_failed_to_decode() {
+    let (rows, receiver) = futures::channel::mpsc::unbounded();
+    let (_meta, meta_receiver) = futures::channel::oneshot::channel();
+    rows.unbounded_send(b"{\"id\":1}".to_vec()).unwrap();
+    rows.unbounded_send(b"{\"id\":\"two\"}".to_vec()).unwrap();
+    drop(rows);
+
+    #[derive(serde_derive::Deserialize)]
+    struct Row {
+        #[allow(dead_code)]
+        id: u32,
+    }
+    let mut result = QueryResult::new(receiver, meta_receiver);
+    let decoded: Vec<_> = block_on_stream(result.rows::<Row>()).collect();
+    assert!(decoded[0].is_ok());
+    match &decoded[1] {
+        Err(CouchbaseError::RowDecodingFailure { index, row, .. }) => {
+            assert_eq!(1, *index);
+            assert_eq!(b"{\"id\":\"two\"}".to_vec(), *row);
+        }
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("the row should not decode"),
+    }
+}
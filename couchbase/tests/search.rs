@@ -0,0 +1,127 @@
+use couchbase::{
+    BooleanQuery, DocIdQuery, GeoDistanceQuery, NumericRangeQuery, SearchMetaData, SearchQuery,
+    SearchRow, WildcardQuery,
+};
+use serde_json::json;
+
+#[test]
+fn reports_failed_partitions() {
+    let meta: SearchMetaData = serde_json::from_str(
+        r#"{
+            "status": {
+                "total": 6,
+                "failed": 1,
+                "successful": 5,
+                "errors": {"travel_fts_6ddbfb7f_aa574ad3": "context deadline exceeded"}
+            },
+            "total_hits": 12,
+            "max_score": 1.5,
+            "took": 2110583
+        }"#,
+    )
+    .unwrap();
+    assert!(meta.is_partial());
+    assert_eq!(6, meta.total_partition_count());
+    assert_eq!(5, meta.success_partition_count());
+    assert_eq!(1, meta.error_partition_count());
+    assert_eq!(
+        Some(&"context deadline exceeded".to_string()),
+        meta.errors().get("travel_fts_6ddbfb7f_aa574ad3")
+    );
+}
+
+#[test]
+fn complete_results_are_not_partial() {
+    let meta: SearchMetaData = serde_json::from_str(
+        r#"{"status": {"total": 6, "failed": 0, "successful": 6}, "total_hits": 0}"#,
+    )
+    .unwrap();
+    assert!(!meta.is_partial());
+    assert!(meta.errors().is_empty());
+}
+
+#[test]
+fn reads_facet_results() {
+    let meta: SearchMetaData = serde_json::from_str(
+        r#"{
+            "status": {"total": 1, "failed": 0, "successful": 1},
+            "facets": {
+                "types": {
+                    "field": "type",
+                    "total": 12,
+                    "missing": 1,
+                    "other": 2,
+                    "terms": [{"term": "hotel", "count": 10}],
+                    "numeric_ranges": null
+                },
+                "prices": {
+                    "field": "price",
+                    "total": 4,
+                    "missing": 0,
+                    "other": 0,
+                    "numeric_ranges": [{"name": "cheap", "max": 100, "count": 4}]
+                }
+            }
+        }"#,
+    )
+    .unwrap();
+    let types = &meta.facets()["types"];
+    assert_eq!("type", types.field());
+    assert_eq!((12, 1, 2), (types.total(), types.missing(), types.other()));
+    assert_eq!("hotel", types.terms()[0].term());
+    assert!(types.numeric_ranges().is_empty());
+    let cheap = &meta.facets()["prices"].numeric_ranges()[0];
+    assert_eq!(
+        (None, Some(100.0), 4),
+        (cheap.min(), cheap.max(), cheap.count())
+    );
+}
+
+#[test]
+fn reads_locations_and_explanation() {
+    let row: SearchRow = serde_json::from_str(
+        r#"{
+            "index": "travel_fts",
+            "id": "hotel_1",
+            "score": 0.5,
+            "locations": {"name": {"grand": [{"pos": 1, "start": 0, "end": 5, "array_positions": null}]}},
+            "explanation": {"value": 0.5, "message": "sum of:"}
+        }"#,
+    )
+    .unwrap();
+    let location = &row.locations().get("name", "grand")[0];
+    assert_eq!(
+        (1, 0, 5),
+        (location.position(), location.start(), location.end())
+    );
+    assert_eq!(None, location.array_positions());
+    assert!(row.locations().get("name", "hotel").is_empty());
+    assert_eq!(
+        Some(0.5),
+        row.explanation().and_then(|e| e["value"].as_f64())
+    );
+}
+
+#[test]
+fn builds_compound_queries() {
+    let query = BooleanQuery::new()
+        .must(NumericRangeQuery::new().min(10.0, true).field("price"))
+        .should(WildcardQuery::new("gr*d").field("name"))
+        .should(GeoDistanceQuery::new(-2.2, 53.4, "10mi").field("geo"))
+        .should_min(1)
+        .must_not(DocIdQuery::new(vec!["hotel_1"]));
+    assert_eq!(
+        json!({
+            "must": {"conjuncts": [{"min": 10.0, "inclusive_min": true, "field": "price"}]},
+            "should": {
+                "disjuncts": [
+                    {"wildcard": "gr*d", "field": "name"},
+                    {"location": [-2.2, 53.4], "distance": "10mi", "field": "geo"}
+                ],
+                "min": 1
+            },
+            "must_not": {"disjuncts": [{"ids": ["hotel_1"]}]}
+        }),
+        query.to_json()
+    );
+}
@@ -0,0 +1,48 @@
+#![cfg(feature = "volatile")]
+
+use couchbase::{ShardRing, ShardTarget, DEFAULT_SHARD_POINTS};
+
+fn targets(count: usize) -> Vec<ShardTarget> {
+    (0..count)
+        .map(|i| ShardTarget::new("tenants", format!("shard-{}", i)))
+        .collect()
+}
+
+#[test]
+fn places_tenants_independent_of_target_order() {
+    let ring = ShardRing::new(targets(4), DEFAULT_SHARD_POINTS).unwrap();
+    let mut reversed = targets(4);
+    reversed.reverse();
+    let reversed = ShardRing::new(reversed, DEFAULT_SHARD_POINTS).unwrap();
+
+    for i in 0..1000 {
+        let tenant = format!("tenant-{}", i);
+        assert_eq!(ring.locate(&tenant), reversed.locate(&tenant));
+    }
+}
+
+#[test]
+fn only_moves_tenants_to_added_target() {
+    let before = ShardRing::new(targets(4), DEFAULT_SHARD_POINTS).unwrap();
+    let after = ShardRing::new(targets(5), DEFAULT_SHARD_POINTS).unwrap();
+    let added = ShardTarget::new("tenants", "shard-4");
+
+    let mut moved = 0;
+    for i in 0..1000 {
+        let tenant = format!("tenant-{}", i);
+        if before.locate(&tenant) != after.locate(&tenant) {
+            assert_eq!(&added, after.locate(&tenant));
+            moved += 1;
+        }
+    }
+    assert!(moved > 100 && moved < 300, "moved {} tenants", moved);
+}
+
+#[test]
+fn rejects_invalid_rings() {
+    assert!(ShardRing::new(vec![], DEFAULT_SHARD_POINTS).is_err());
+    assert!(ShardRing::new(targets(2), 0).is_err());
+    let mut duplicated = targets(2);
+    duplicated.push(ShardTarget::new("tenants", "shard-0"));
+    assert!(ShardRing::new(duplicated, DEFAULT_SHARD_POINTS).is_err());
+}
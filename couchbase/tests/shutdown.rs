@@ -0,0 +1,54 @@
+mod util;
+
+use couchbase::{Cluster, QueryOptions};
+use futures::executor::block_on;
+use futures::StreamExt;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+/// Streams rows until the query times out, which is much later than any shutdown should take.
+const ENDLESS_QUERY: &str =
+    "SELECT a, b FROM ARRAY_RANGE(0, 100000) AS a UNNEST ARRAY_RANGE(0, 100000) AS b";
+
+/// Well below the 10 seconds the IO thread waits for outstanding requests when it shuts down,
+/// so waiting for a streaming request instead of cancelling it fails the tests.
+const MAX_SHUTDOWN: Duration = Duration::from_secs(3);
+
+#[test]
+fn run_shutdown_tests() {
+    util::run(|cfg| {
+        drops_clusters_with_in_flight_queries(cfg.cluster());
+        drops_clusters_with_open_row_streams(cfg.cluster());
+    });
+}
+
+fn drops_clusters_with_in_flight_queries(cluster: Cluster) {
+    {
+        // Polling once dispatches the query, its result is never awaited
+        let mut query = Box::pin(cluster.query(ENDLESS_QUERY, QueryOptions::default()));
+        let _ = block_on(async { futures::poll!(query.as_mut()) });
+    }
+    assert_shuts_down_quickly(cluster);
+}
+
+fn drops_clusters_with_open_row_streams(cluster: Cluster) {
+    let mut result = match block_on(cluster.query(ENDLESS_QUERY, QueryOptions::default())) {
+        Ok(result) => result,
+        // The mock does not run queries, so there is nothing left in flight
+        Err(_) => return assert_shuts_down_quickly(cluster),
+    };
+    assert_shuts_down_quickly(cluster);
+
+    // Only completes if the stream was closed on shutdown instead of being left open
+    let _ = block_on(result.rows::<Value>().collect::<Vec<_>>());
+}
+
+fn assert_shuts_down_quickly(cluster: Cluster) {
+    let start = Instant::now();
+    drop(cluster);
+    assert!(
+        start.elapsed() < MAX_SHUTDOWN,
+        "shutting down took {:?}",
+        start.elapsed()
+    );
+}
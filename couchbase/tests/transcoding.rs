@@ -0,0 +1,83 @@
+use couchbase::{
+    JsonTranscoder, LegacyTranscoder, RawBinaryTranscoder, RawJsonTranscoder, Transcoder,
+};
+use couchbase_flags::{BINARY_COMMON_FLAGS, JSON_COMMON_FLAGS, STRING_COMMON_FLAGS};
+use serde_json::{json, Value};
+
+#[test]
+fn json_transcoder_round_trips() {
+    let content = json!({"name": "couchbase", "tags": ["db"]});
+    let (encoded, flags) = JsonTranscoder.encode(content.clone()).unwrap();
+    assert_eq!(JSON_COMMON_FLAGS, flags);
+    assert_eq!(content, JsonTranscoder.decode(&encoded, flags).unwrap());
+    assert!(JsonTranscoder.decode(b"abc", BINARY_COMMON_FLAGS).is_err());
+}
+
+#[test]
+fn raw_transcoders_keep_content() {
+    let (encoded, flags) = RawJsonTranscoder
+        .encode(Value::String("{\"a\":1}".into()))
+        .unwrap();
+    assert_eq!((b"{\"a\":1}".to_vec(), JSON_COMMON_FLAGS), (encoded, flags));
+    assert!(RawJsonTranscoder.encode(json!({"a": 1})).is_err());
+
+    let bytes = serde_json::to_value(vec![0u8, 1, 255]).unwrap();
+    let (encoded, flags) = RawBinaryTranscoder.encode(bytes.clone()).unwrap();
+    assert_eq!(
+        (vec![0u8, 1, 255], BINARY_COMMON_FLAGS),
+        (encoded.clone(), flags)
+    );
+    assert_eq!(bytes, RawBinaryTranscoder.decode(&encoded, flags).unwrap());
+    assert!(RawBinaryTranscoder.encode(json!([256])).is_err());
+}
+
+#[test]
+fn legacy_transcoder_stores_plain_strings() {
+    let (encoded, flags) = LegacyTranscoder
+        .encode(Value::String("hello".into()))
+        .unwrap();
+    assert_eq!(
+        (b"hello".to_vec(), STRING_COMMON_FLAGS),
+        (encoded.clone(), flags)
+    );
+    assert_eq!(
+        Value::String("hello".into()),
+        LegacyTranscoder.decode(&encoded, flags).unwrap()
+    );
+
+    let (encoded, flags) = LegacyTranscoder.encode(json!({"a": 1})).unwrap();
+    assert_eq!(JSON_COMMON_FLAGS, flags);
+    assert_eq!(
+        json!({"a": 1}),
+        LegacyTranscoder.decode(&encoded, 0).unwrap()
+    );
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack_transcoder_round_trips() {
+    use couchbase::transcoding::msgpack::MessagePackTranscoder;
+
+    let content = json!({"name": "couchbase", "rating": 4.5, "tags": ["db", null]});
+    let (encoded, flags) = MessagePackTranscoder.encode(content.clone()).unwrap();
+    assert_eq!(BINARY_COMMON_FLAGS, flags);
+    assert_eq!(
+        content,
+        MessagePackTranscoder.decode(&encoded, flags).unwrap()
+    );
+    assert!(MessagePackTranscoder
+        .decode(&encoded, JSON_COMMON_FLAGS)
+        .is_err());
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_transcoder_round_trips() {
+    use couchbase::transcoding::cbor::CborTranscoder;
+
+    let content = json!({"name": "couchbase", "rating": 4.5, "tags": ["db", null]});
+    let (encoded, flags) = CborTranscoder.encode(content.clone()).unwrap();
+    assert_eq!(BINARY_COMMON_FLAGS, flags);
+    assert_eq!(content, CborTranscoder.decode(&encoded, flags).unwrap());
+    assert!(CborTranscoder.decode(&encoded, JSON_COMMON_FLAGS).is_err());
+}
@@ -1,3 +1,6 @@
+// Not every test uses every part of the harness
+#![allow(dead_code)]
+
 mod mock;
 mod standalone;
 
@@ -6,6 +9,7 @@ use standalone::StandaloneCluster;
 use std::ffi::OsStr;
 use std::panic;
 
+use couchbase::Cluster;
 use lazy_static::lazy_static;
 use std::env;
 use std::sync::Mutex;
@@ -38,15 +42,33 @@ fn teardown() {
 
 pub fn run<T>(test: T)
 where
-    T: FnOnce(TestConfig) -> () + panic::UnwindSafe,
+    T: FnOnce(TestConfig) + panic::UnwindSafe,
 {
     let config = setup();
-    let _ = panic::catch_unwind(|| test(config));
+    let result = panic::catch_unwind(|| test(config));
     teardown();
+    if let Err(e) = result {
+        panic::resume_unwind(e);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TestConfig {
+    pub connection_string: String,
+    pub username: String,
+    pub password: String,
+    pub bucket: String,
 }
 
-#[derive(Debug)]
-pub struct TestConfig {}
+impl TestConfig {
+    pub fn cluster(&self) -> Cluster {
+        Cluster::connect(
+            self.connection_string.as_str(),
+            self.username.as_str(),
+            self.password.as_str(),
+        )
+    }
+}
 
 enum ClusterUnderTest {
     Standalone(StandaloneCluster),
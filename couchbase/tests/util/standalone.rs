@@ -1,21 +1,28 @@
 use super::{ConfigAware, TestConfig};
+use std::env;
 
-pub struct StandaloneCluster {}
+/// A cluster which is already running, given through the `TEST_CONNECTION_STRING`,
+/// `TEST_USERNAME`, `TEST_PASSWORD` and `TEST_BUCKET` environment variables.
+pub struct StandaloneCluster {
+    config: TestConfig,
+}
 
 impl StandaloneCluster {
     pub fn start() -> Self {
-        todo!();
+        let var = |name: &str, default: &str| env::var(name).unwrap_or_else(|_| default.into());
+        Self {
+            config: TestConfig {
+                connection_string: var("TEST_CONNECTION_STRING", "couchbase://127.0.0.1"),
+                username: var("TEST_USERNAME", "Administrator"),
+                password: var("TEST_PASSWORD", "password"),
+                bucket: var("TEST_BUCKET", "default"),
+            },
+        }
     }
 }
 
 impl ConfigAware for StandaloneCluster {
     fn config(&self) -> TestConfig {
-        todo!();
-    }
-}
-
-impl Drop for StandaloneCluster {
-    fn drop(&mut self) {
-        todo!();
+        self.config.clone()
     }
 }